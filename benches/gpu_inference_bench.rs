@@ -207,7 +207,7 @@ fn bench_subsystem(
 ) -> Stats {
     let total_vectors = slot_count * vectors_per_slot;
     let mut backend = OrtBackend::new_with_capacity(model_bytes, total_vectors);
-    let pool = OrtBackend::make_pool();
+    let pool = OrtBackend::make_pool(FEATURE_DIM);
     let mut alloc = pool.allocator();
 
     for _ in 0..warmup_iters {
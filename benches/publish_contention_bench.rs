@@ -0,0 +1,141 @@
+//! Benchmark: several producer threads publishing into one multi-producer request ring.
+//!
+//! Mirrors the server's IO threads: each producer clones the ring's producer and the pool
+//! allocator and publishes through the shared publish gate, while one consumer drains the ring
+//! and releases the pool in ring order, as the inference thread does. Reports throughput, how
+//! evenly it is shared between producers, and how long producers wait for the gate.
+
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, build_multi_producer};
+
+use disrust::buffer_pool::{BufferPool, set_factory_pool};
+use disrust::config::GPU_DISRUPTOR_SIZE;
+use disrust::connection_id::ConnectionRef;
+use disrust::constants::FEATURE_DIM;
+use disrust::request_flow;
+use disrust::ring_types::InferenceEvent;
+
+const REQUESTS_PER_BATCH: usize = 8;
+const TARGET_DURATION: Duration = Duration::from_secs(2);
+const PRODUCER_COUNTS: [usize; 4] = [1, 2, 4, 8];
+
+/// What one producer thread did over a run.
+struct ProducerStats {
+    requests: u64,
+    /// Nanoseconds spent waiting for the publish gate, one entry per batch.
+    gate_waits_ns: Vec<u64>,
+}
+
+fn run(producers: usize) {
+    let builder = build_multi_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let producer = builder.build();
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let publish_gate = Arc::new(Mutex::new(()));
+    let stop = Arc::new(AtomicBool::new(false));
+    // The consumer outlives the producers, which may be waiting on it for ring or pool space.
+    let drained = Arc::new(AtomicBool::new(false));
+
+    let consumer = thread::spawn({
+        let drained = Arc::clone(&drained);
+        move || {
+            while !drained.load(Ordering::Relaxed) {
+                if let Ok(mut guard) = poller.poll() {
+                    for event in &mut guard {
+                        black_box(event.vector(0));
+                        event.features.release();
+                    }
+                }
+            }
+        }
+    });
+
+    let handles: Vec<_> = (0..producers)
+        .map(|id| {
+            let mut producer = producer.clone();
+            let mut allocator = pool.allocator();
+            let publish_gate = Arc::clone(&publish_gate);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let conn = ConnectionRef::new(id as u8, 0, 1);
+                let mut buf = (1u32).to_le_bytes().to_vec();
+                buf.resize(4 + FEATURE_DIM * 4, 0u8);
+                let buf = buf.repeat(REQUESTS_PER_BATCH);
+                let mut request_seq = 0u64;
+                let mut gate_waits_ns = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    let waiting = Instant::now();
+                    let publish = publish_gate.lock().unwrap();
+                    gate_waits_ns.push(waiting.elapsed().as_nanos() as u64);
+                    let result = request_flow::process_requests_from_buffer(
+                        black_box(&buf),
+                        &mut producer,
+                        &mut allocator,
+                        conn,
+                        &mut request_seq,
+                    );
+                    drop(publish);
+                    black_box(result).expect("well-formed requests");
+                }
+                ProducerStats {
+                    requests: request_seq,
+                    gate_waits_ns,
+                }
+            })
+        })
+        .collect();
+    drop(producer);
+
+    let start = Instant::now();
+    thread::sleep(TARGET_DURATION);
+    stop.store(true, Ordering::Relaxed);
+    let stats: Vec<ProducerStats> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("producer thread panicked"))
+        .collect();
+    let elapsed = start.elapsed().as_secs_f64();
+    drained.store(true, Ordering::Relaxed);
+    consumer.join().expect("consumer thread panicked");
+
+    let rates: Vec<f64> = stats.iter().map(|s| s.requests as f64 / elapsed).collect();
+    let total: f64 = rates.iter().sum();
+    let min = rates.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = rates.iter().copied().fold(0.0, f64::max);
+    // Jain's index: 1.0 when every producer gets the same share, 1/n when one gets it all.
+    let jain = total * total / (producers as f64 * rates.iter().map(|r| r * r).sum::<f64>());
+    let mut waits: Vec<u64> = stats.into_iter().flat_map(|s| s.gate_waits_ns).collect();
+    waits.sort_unstable();
+    let percentile = |p: f64| waits[((waits.len() - 1) as f64 * p) as usize];
+
+    eprintln!("{producers} producer(s) over {elapsed:.1}s:");
+    eprintln!(
+        "  {total:.0} req/s total, per producer min {min:.0} max {max:.0} req/s, \
+         jain fairness {jain:.3}"
+    );
+    eprintln!(
+        "  gate wait ns: p50 {} p99 {} p99.9 {} max {}",
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+        waits[waits.len() - 1],
+    );
+}
+
+fn main() {
+    let _ = set_factory_pool(BufferPool::new_boxed(1));
+
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    for producers in PRODUCER_COUNTS {
+        if producers >= cores {
+            eprintln!(
+                "({producers} producers and a consumer share {cores} core(s); expect scheduling noise)"
+            );
+        }
+        run(producers);
+    }
+}
@@ -0,0 +1,106 @@
+//! Benchmark: response write path (framing, iovec gathering, write completion) without io_uring.
+
+#[cfg(target_os = "linux")]
+use std::hint::black_box;
+
+#[cfg(target_os = "linux")]
+use disrust::connection_id::ConnectionRef;
+#[cfg(target_os = "linux")]
+use disrust::constants::MAX_VECTORS_PER_REQUEST;
+#[cfg(target_os = "linux")]
+use disrust::pipeline::response_queue::ResponseReady;
+#[cfg(target_os = "linux")]
+use disrust::server::WritePath;
+
+#[cfg(target_os = "linux")]
+const RESPONSES_PER_BATCH: usize = 256;
+#[cfg(target_os = "linux")]
+const TARGET_DURATION: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Deliver `batch`, then write everything it queued, the kernel taking every byte offered.
+/// Returns the bytes written.
+#[cfg(target_os = "linux")]
+fn write_batch(path: &mut WritePath, batch: &[ResponseReady]) -> usize {
+    for response in batch {
+        path.deliver(response);
+    }
+    let mut total = 0;
+    loop {
+        let iovecs = black_box(path.gather());
+        if iovecs.is_empty() {
+            return total;
+        }
+        let written: usize = iovecs.iter().map(|iov| iov.iov_len).sum();
+        path.complete(written);
+        total += written;
+    }
+}
+
+/// Number `batch` on from `next_seq`, so every response answers the next request.
+#[cfg(target_os = "linux")]
+fn renumber(batch: &mut [ResponseReady], next_seq: &mut u64) {
+    for response in batch {
+        response.request_seq = *next_seq;
+        *next_seq += 1;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run(label: &str, make_path: impl Fn(ConnectionRef) -> WritePath, vectors: usize) {
+    let conn = ConnectionRef::new(0, 0, 1);
+    let mut path = make_path(conn);
+    let results = vec![0.5f32; vectors];
+    let mut batch: Vec<ResponseReady> = (0..RESPONSES_PER_BATCH as u64)
+        .map(|seq| ResponseReady::new(conn, seq, 0, &results))
+        .collect();
+
+    // Warm up
+    let mut next_seq = 0u64;
+    for _ in 0..1_000 {
+        renumber(&mut batch, &mut next_seq);
+        write_batch(&mut path, &batch);
+    }
+
+    let start = std::time::Instant::now();
+    let mut iterations: u64 = 0;
+    let mut total_bytes: u64 = 0;
+    while start.elapsed() < TARGET_DURATION {
+        renumber(&mut batch, &mut next_seq);
+        total_bytes += write_batch(&mut path, &batch) as u64;
+        iterations += 1;
+    }
+
+    let elapsed = start.elapsed();
+    let total_responses = iterations * RESPONSES_PER_BATCH as u64;
+    eprintln!(
+        "write_path {label} ({vectors} vectors): {} responses in {:?}",
+        total_responses, elapsed
+    );
+    eprintln!(
+        "  {:.0} resp/s  {:.0} MB/s",
+        total_responses as f64 / elapsed.as_secs_f64(),
+        (total_bytes as f64 / 1_000_000.0) / elapsed.as_secs_f64(),
+    );
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    for vectors in [1, MAX_VECTORS_PER_REQUEST] {
+        run("plain", WritePath::new, vectors);
+        run(
+            "prefixed",
+            |conn| {
+                WritePath::new(conn)
+                    .with_length_prefix()
+                    .with_request_seq_echo()
+                    .with_vector_status()
+            },
+            vectors,
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("write_path_bench requires Linux");
+}
@@ -0,0 +1,96 @@
+//! Heap allocation counting for the hot-path allocation audit (`--features alloc-audit`).
+//!
+//! Install [`CountingAllocator`] as a binary's `#[global_allocator]` to count the allocations
+//! made on audited threads: a worker thread calls [`audit_this_thread`] before entering its
+//! loop, and a caller wraps a single call in [`audited`]. [`count`] totals them, so a test can
+//! take it before and after a steady-state run and assert that nothing was allocated in between.
+//! Threads that are not audited allocate as usual without being counted, so test scaffolding
+//! and logging stay out of the numbers.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // Const-initialized without a destructor, so reading it never allocates itself.
+    static AUDITED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The system allocator, counting allocations, zeroed allocations and reallocations made on
+/// audited threads. Frees are not counted: a steady state that frees has allocated too.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+fn record(size: usize) {
+    if AUDITED.with(Cell::get) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+/// Allocations counted on audited threads since the process started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AllocCount {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+impl AllocCount {
+    /// What was allocated between `earlier` and this count.
+    pub fn since(self, earlier: AllocCount) -> AllocCount {
+        AllocCount {
+            allocations: self.allocations - earlier.allocations,
+            bytes: self.bytes - earlier.bytes,
+        }
+    }
+}
+
+impl std::fmt::Display for AllocCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} allocations, {} bytes", self.allocations, self.bytes)
+    }
+}
+
+/// Count this thread's allocations from now on, or stop counting them.
+pub fn audit_this_thread(audit: bool) {
+    AUDITED.with(|audited| audited.set(audit));
+}
+
+/// Run `f` with this thread's allocations counted.
+pub fn audited<R>(f: impl FnOnce() -> R) -> R {
+    let was_audited = AUDITED.with(|audited| audited.replace(true));
+    let result = f();
+    audit_this_thread(was_audited);
+    result
+}
+
+/// Allocations counted so far, across every audited thread.
+pub fn count() -> AllocCount {
+    AllocCount {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: BYTES.load(Ordering::Relaxed),
+    }
+}
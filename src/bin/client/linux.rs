@@ -0,0 +1,1394 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::TcpStream;
+use std::os::fd::{IntoRawFd, RawFd};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser, Subcommand};
+use io_uring::{
+    opcode,
+    squeue::Entry,
+    types::{Fd, Timespec},
+};
+use slab::Slab;
+
+use disrust::affinity;
+use disrust::constants::FEATURE_DIM;
+use disrust::protocol::{
+    LENGTH_PREFIX_BYTES, OVERLOAD_FRAME_BYTES, PARSE_ERROR_FRAME_BYTES, RESPONSE_HEADER_BYTES,
+    RequestFraming, SEQ_PREFIX_BYTES, SequenceCheck, VectorStatus, decode_length_prefix,
+    decode_overload, decode_parse_error, decode_seq_prefix, decode_vector_status, is_parse_error,
+    parse_feature_dim, response_size, vector_status_size,
+};
+use disrust::timer::{TimerMetric, TimerRecorder, TimerSnapshot};
+
+const OP_READ: u64 = 1;
+const OP_WRITE: u64 = 2;
+/// The open-loop timer that wakes the event loop for the next due request.
+const OP_TIMER: u64 = 3;
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+#[derive(Parser)]
+#[command(about = "Test client for disrust inference server")]
+struct Cli {
+    /// Server port
+    #[arg(short, long, default_value_t = 9900)]
+    port: u16,
+
+    /// Pin the client event-loop thread to a specific CPU id.
+    #[arg(long)]
+    event_loop_cpu: Option<usize>,
+
+    /// Pin the client reporter thread to a specific CPU id.
+    #[arg(long)]
+    reporter_cpu: Option<usize>,
+
+    /// Expect the server's `--echo-request-seq` prefix and fail on the first lost, duplicated
+    /// or reordered response.
+    #[arg(long)]
+    expect_request_seq: bool,
+
+    /// Expect the server's `--length-prefix` framing, and skip frames this client does not know.
+    #[arg(long)]
+    expect_length_prefix: bool,
+
+    /// Expect the server's `--vector-status` trailer, and fail on any vector not scored `ok`
+    /// when verifying results.
+    #[arg(long)]
+    expect_vector_status: bool,
+
+    /// Features per request vector; must match the server's `--feature-dim`.
+    #[arg(long, default_value_t = FEATURE_DIM, value_parser = parse_feature_dim)]
+    feature_dim: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a few requests and verify results (default)
+    Smoke,
+    /// Send pipelined requests and verify all results
+    Pipeline(PipelineArgs),
+    /// Benchmark throughput with concurrent pipelined connections
+    Bench(BenchArgs),
+    /// Sustained load with per-request latency measurement
+    Sustain(SustainArgs),
+    /// Requests at a target rate regardless of how fast they are answered, with latency measured
+    /// from when each request was due
+    OpenLoop(OpenLoopArgs),
+}
+
+#[derive(Args, Clone)]
+struct PipelineArgs {
+    /// Independent client worker threads, each running the full configured shape.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Number of concurrent connections
+    #[arg(short, long, default_value_t = 1)]
+    connections: usize,
+    /// In-flight requests per connection
+    #[arg(short, long, default_value_t = 1000)]
+    window: usize,
+    /// Vectors per request
+    #[arg(short = 'v', long, default_value_t = 2)]
+    vectors: u32,
+    /// Requests per connection
+    #[arg(short, long, default_value_t = 1000)]
+    requests: usize,
+}
+
+#[derive(Args, Clone)]
+struct BenchArgs {
+    /// Independent client worker threads, each running the full configured shape.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Number of concurrent connections
+    #[arg(short, long, default_value_t = 4)]
+    connections: usize,
+    /// In-flight requests per connection
+    #[arg(short, long, default_value_t = 256)]
+    window: usize,
+    /// Vectors per request
+    #[arg(short = 'v', long, default_value_t = 1)]
+    vectors: u32,
+    /// Requests per connection
+    #[arg(short, long, default_value_t = 100_000)]
+    requests: usize,
+}
+
+#[derive(Args, Clone)]
+struct SustainArgs {
+    /// Independent client worker threads, each running the full configured shape.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Number of concurrent connections
+    #[arg(short, long, default_value_t = 4)]
+    connections: usize,
+    /// In-flight requests per connection
+    #[arg(short, long, default_value_t = 64)]
+    window: usize,
+    /// Vectors per request
+    #[arg(short = 'v', long, default_value_t = 1)]
+    vectors: u32,
+    /// Warmup duration in seconds (discarded from report)
+    #[arg(short = 'W', long, default_value_t = 3)]
+    warmup: u64,
+    /// Measurement duration in seconds
+    #[arg(short, long, default_value_t = 10)]
+    duration: u64,
+}
+
+#[derive(Args, Clone)]
+struct OpenLoopArgs {
+    /// Independent client worker threads, each running the full configured shape.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Number of concurrent connections
+    #[arg(short, long, default_value_t = 4)]
+    connections: usize,
+    /// Target requests per second, across all threads and connections
+    #[arg(long, default_value_t = 10_000.0)]
+    rate: f64,
+    /// Space each connection's requests evenly instead of as a Poisson process
+    #[arg(long)]
+    fixed_interval: bool,
+    /// Most in-flight requests per connection; requests due past it wait, and the wait counts
+    /// toward their latency
+    #[arg(short, long, default_value_t = 1024)]
+    window: usize,
+    /// Vectors per request
+    #[arg(short = 'v', long, default_value_t = 1)]
+    vectors: u32,
+    /// Warmup duration in seconds (discarded from report)
+    #[arg(short = 'W', long, default_value_t = 3)]
+    warmup: u64,
+    /// Measurement duration in seconds
+    #[arg(short, long, default_value_t = 10)]
+    duration: u64,
+}
+
+#[derive(Clone)]
+struct RequestTemplate {
+    num_vectors: u32,
+    request_bytes: Arc<[u8]>,
+    expected: Arc<[f32]>,
+}
+
+impl RequestTemplate {
+    fn new(num_vectors: u32, feature_dim: usize) -> Self {
+        let framing = RequestFraming::PLAIN.with_feature_dim(feature_dim);
+        let mut buf = Vec::with_capacity(framing.request_size(num_vectors as usize));
+        buf.extend_from_slice(&num_vectors.to_le_bytes());
+
+        let mut expected_sums = Vec::with_capacity(num_vectors as usize);
+        for v in 0..num_vectors as usize {
+            let mut sum = 0.0f32;
+            for f in 0..feature_dim {
+                let val = (v * feature_dim + f) as f32 * 0.01;
+                buf.extend_from_slice(&val.to_le_bytes());
+                sum += val;
+            }
+            expected_sums.push(sum);
+        }
+
+        Self {
+            num_vectors,
+            request_bytes: Arc::from(buf),
+            expected: Arc::from(expected_sums),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum StopMode {
+    FixedCount { requests_per_connection: u64 },
+    Duration { warmup: Duration, measure: Duration },
+}
+
+/// Open-loop request arrivals on each connection.
+#[derive(Clone, Copy)]
+struct Arrivals {
+    /// Mean time between requests on one connection.
+    interval: Duration,
+    poisson: bool,
+}
+
+/// When a connection's next open-loop request is due.
+struct ArrivalClock {
+    arrivals: Arrivals,
+    next: Instant,
+    /// xorshift64* state; never 0.
+    rng: u64,
+}
+
+impl ArrivalClock {
+    /// The first request falls at a random point of the first interval, so connections started
+    /// together do not send in lockstep.
+    fn new(arrivals: Arrivals, start: Instant, seed: u64) -> Self {
+        let mut clock = Self {
+            arrivals,
+            next: start,
+            rng: seed.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        };
+        let phase = clock.uniform();
+        clock.next += arrivals.interval.mul_f64(phase);
+        clock
+    }
+
+    /// A uniform sample from `[0, 1)`.
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// The due time of the next request, advancing the clock past it.
+    fn take(&mut self) -> Instant {
+        let due = self.next;
+        let gap = if self.arrivals.poisson {
+            self.arrivals.interval.mul_f64(-(1.0 - self.uniform()).ln())
+        } else {
+            self.arrivals.interval
+        };
+        self.next += gap;
+        due
+    }
+}
+
+#[derive(Clone)]
+struct Scenario {
+    name: &'static str,
+    threads: usize,
+    connections: usize,
+    window: usize,
+    templates: Arc<[RequestTemplate]>,
+    verify: bool,
+    collect_latency: bool,
+    stop_mode: StopMode,
+    expect_request_seq: bool,
+    expect_length_prefix: bool,
+    expect_vector_status: bool,
+    /// Open loop: requests are issued as they fall due rather than as responses free the window.
+    arrivals: Option<Arrivals>,
+}
+
+impl Scenario {
+    fn pipeline(args: PipelineArgs, feature_dim: usize) -> Self {
+        Self {
+            name: "pipeline",
+            threads: args.threads,
+            connections: args.connections,
+            window: args.window,
+            templates: Arc::from([RequestTemplate::new(args.vectors, feature_dim)]),
+            verify: true,
+            collect_latency: false,
+            stop_mode: StopMode::FixedCount {
+                requests_per_connection: args.requests as u64,
+            },
+            expect_request_seq: false,
+            expect_length_prefix: false,
+            expect_vector_status: false,
+            arrivals: None,
+        }
+    }
+
+    fn bench(args: BenchArgs, feature_dim: usize) -> Self {
+        Self {
+            name: "bench",
+            threads: args.threads,
+            connections: args.connections,
+            window: args.window,
+            templates: Arc::from([RequestTemplate::new(args.vectors, feature_dim)]),
+            verify: false,
+            collect_latency: false,
+            stop_mode: StopMode::FixedCount {
+                requests_per_connection: args.requests as u64,
+            },
+            expect_request_seq: false,
+            expect_length_prefix: false,
+            expect_vector_status: false,
+            arrivals: None,
+        }
+    }
+
+    fn sustain(args: SustainArgs, feature_dim: usize) -> Self {
+        Self {
+            name: "sustain",
+            threads: args.threads,
+            connections: args.connections,
+            window: args.window,
+            templates: Arc::from([RequestTemplate::new(args.vectors, feature_dim)]),
+            verify: false,
+            collect_latency: true,
+            stop_mode: StopMode::Duration {
+                warmup: Duration::from_secs(args.warmup),
+                measure: Duration::from_secs(args.duration),
+            },
+            expect_request_seq: false,
+            expect_length_prefix: false,
+            expect_vector_status: false,
+            arrivals: None,
+        }
+    }
+
+    fn open_loop(args: OpenLoopArgs, feature_dim: usize) -> Self {
+        assert!(args.rate > 0.0, "rate must be > 0");
+        let streams = (args.threads * args.connections) as f64;
+        Self {
+            name: "open-loop",
+            threads: args.threads,
+            connections: args.connections,
+            window: args.window,
+            templates: Arc::from([RequestTemplate::new(args.vectors, feature_dim)]),
+            verify: false,
+            collect_latency: true,
+            stop_mode: StopMode::Duration {
+                warmup: Duration::from_secs(args.warmup),
+                measure: Duration::from_secs(args.duration),
+            },
+            expect_request_seq: false,
+            expect_length_prefix: false,
+            expect_vector_status: false,
+            arrivals: Some(Arrivals {
+                interval: Duration::from_secs_f64(streams / args.rate),
+                poisson: !args.fixed_interval,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PendingRequest {
+    template_idx: usize,
+    /// When the request was sent or, in open loop, when it fell due; latency runs from here.
+    submitted_at: Instant,
+}
+
+struct Connection {
+    fd: RawFd,
+    read_buf: Box<[u8; READ_BUF_SIZE]>,
+    read_len: usize,
+    read_inflight: bool,
+    pending: VecDeque<PendingRequest>,
+    submitted_total: u64,
+    completed_total: u64,
+    /// Set from the server's Retry-After hint; no new requests are issued before it.
+    backoff_until: Option<Instant>,
+    seq_check: SequenceCheck,
+    /// In open loop, when the next request is due.
+    arrival: Option<ArrivalClock>,
+}
+
+impl Connection {
+    fn new(fd: RawFd, window: usize) -> Self {
+        Self {
+            fd,
+            read_buf: Box::new([0u8; READ_BUF_SIZE]),
+            read_len: 0,
+            read_inflight: false,
+            pending: VecDeque::with_capacity(window.max(1)),
+            submitted_total: 0,
+            completed_total: 0,
+            backoff_until: None,
+            seq_check: SequenceCheck::default(),
+            arrival: None,
+        }
+    }
+
+    fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn can_issue_more(&self, scenario: &Scenario, now: Instant, run: &RunState) -> bool {
+        if self.pending_count() >= scenario.window {
+            return false;
+        }
+        if self.backoff_until.is_some_and(|until| now < until) {
+            return false;
+        }
+        if self.arrival.as_ref().is_some_and(|clock| now < clock.next) {
+            return false;
+        }
+
+        match scenario.stop_mode {
+            StopMode::FixedCount {
+                requests_per_connection,
+            } => self.submitted_total < requests_per_connection,
+            StopMode::Duration { .. } => now < run.measure_end,
+        }
+    }
+
+    fn is_finished(&self, scenario: &Scenario, now: Instant, run: &RunState) -> bool {
+        match scenario.stop_mode {
+            StopMode::FixedCount {
+                requests_per_connection,
+            } => self.completed_total >= requests_per_connection,
+            StopMode::Duration { .. } => now >= run.measure_end && self.pending.is_empty(),
+        }
+    }
+
+    fn read_tail(&mut self) -> (*mut u8, u32) {
+        (
+            unsafe { self.read_buf.as_mut_ptr().add(self.read_len) },
+            (READ_BUF_SIZE - self.read_len) as u32,
+        )
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+struct IoUring {
+    inner: io_uring::IoUring,
+    outstanding: usize,
+}
+
+impl IoUring {
+    fn new(entries: u32) -> io::Result<Self> {
+        Ok(Self {
+            inner: io_uring::IoUring::new(entries)?,
+            outstanding: 0,
+        })
+    }
+
+    fn push(&mut self, sqe: &Entry) {
+        loop {
+            match unsafe { self.inner.submission().push(sqe) } {
+                Ok(()) => {
+                    self.outstanding += 1;
+                    return;
+                }
+                Err(_) => {
+                    self.inner.submit().expect("SQ flush failed");
+                }
+            }
+        }
+    }
+
+    fn wait(&mut self, min_complete: usize) {
+        self.inner
+            .submit_and_wait(min_complete)
+            .expect("submit_and_wait failed");
+    }
+
+    fn drain_cqes_into(&mut self, buf: &mut Vec<(u64, i32)>) {
+        for cqe in self.inner.completion() {
+            self.outstanding = self.outstanding.saturating_sub(1);
+            buf.push((cqe.user_data(), cqe.result()));
+        }
+    }
+}
+
+fn encode_user_data(op: u64, key: u32) -> u64 {
+    (op << 32) | key as u64
+}
+
+fn decode_user_data(user_data: u64) -> (u64, u32) {
+    (user_data >> 32, user_data as u32)
+}
+
+struct RunState {
+    warmup_end: Instant,
+    measure_end: Instant,
+    measurement_started: bool,
+}
+
+impl RunState {
+    fn from_plan(plan: RunPlan) -> Self {
+        Self {
+            warmup_end: plan.warmup_end,
+            measure_end: plan.measure_end,
+            measurement_started: false,
+        }
+    }
+
+    fn is_measuring(&self, scenario: &Scenario, now: Instant) -> bool {
+        match scenario.stop_mode {
+            StopMode::FixedCount { .. } => true,
+            StopMode::Duration { .. } => now >= self.warmup_end && now < self.measure_end,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SummaryStats {
+    measured_completions: u64,
+    overloaded: u64,
+}
+
+impl SummaryStats {
+    fn record_completion(&mut self, scenario: &Scenario, run: &RunState, now: Instant) {
+        if !run.is_measuring(scenario, now) {
+            return;
+        }
+
+        self.measured_completions += 1;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RunPlan {
+    start: Instant,
+    warmup_end: Instant,
+    measure_end: Instant,
+}
+
+impl RunPlan {
+    fn new(stop_mode: &StopMode) -> Self {
+        let start = Instant::now();
+        let (warmup_end, measure_end) = match stop_mode {
+            StopMode::FixedCount { .. } => (start, start),
+            StopMode::Duration { warmup, measure } => {
+                let warmup_end = start + *warmup;
+                (warmup_end, warmup_end + *measure)
+            }
+        };
+        Self {
+            start,
+            warmup_end,
+            measure_end,
+        }
+    }
+}
+
+enum WorkerReport {
+    Interval {
+        interval_idx: u64,
+        end: Instant,
+        completions: u64,
+        snapshot: Option<TimerSnapshot>,
+    },
+    Finished {
+        end: Instant,
+        measured_completions: u64,
+    },
+}
+
+fn print_interval(snapshot: Option<TimerSnapshot>, completions: u64, start: Instant, end: Instant) {
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+    let elapsed = end.duration_since(start);
+    let qps = completions as f64 / elapsed.as_secs_f64();
+    eprintln!(
+        "{:>10.0}  {:>8.1}us  {:>8.1}us  {:>8.1}us  {:>8.1}us  {:>8}",
+        qps,
+        snapshot.p50_us(),
+        snapshot.p95_us(),
+        snapshot.p99_us(),
+        snapshot.p999_us(),
+        snapshot.count(),
+    );
+}
+
+fn print_summary(
+    snapshot: Option<TimerSnapshot>,
+    measurement_start: Option<Instant>,
+    end: Instant,
+    measured_completions: u64,
+) {
+    let Some(start) = measurement_start else {
+        eprintln!("no samples collected");
+        return;
+    };
+    let Some(snapshot) = snapshot else {
+        eprintln!("no samples collected");
+        return;
+    };
+
+    let elapsed = end.duration_since(start);
+
+    eprintln!();
+    eprintln!(
+        "summary ({:.1}s, {} requests)",
+        elapsed.as_secs_f64(),
+        measured_completions
+    );
+    eprintln!(
+        "  qps     {:.0}",
+        measured_completions as f64 / elapsed.as_secs_f64()
+    );
+    eprintln!("  p50     {:.1}us", snapshot.p50_us());
+    eprintln!("  p95     {:.1}us", snapshot.p95_us());
+    eprintln!("  p99     {:.1}us", snapshot.p99_us());
+    eprintln!("  p99.9   {:.1}us", snapshot.p999_us());
+    eprintln!("  p99.99  {:.1}us", snapshot.p9999_us());
+    eprintln!("  max     {:.1}us", snapshot.max_us());
+}
+
+fn aggregate_snapshot(snapshots: Vec<Option<TimerSnapshot>>) -> Option<TimerSnapshot> {
+    let mut total_hist = hdrhistogram::Histogram::new_with_bounds(1, 60_000_000_000, 3)
+        .expect("failed to create aggregate client histogram");
+    let mut saw_data = false;
+    for snapshot in snapshots.into_iter().flatten() {
+        snapshot.merge_into(&mut total_hist);
+        saw_data = true;
+    }
+    saw_data.then(|| TimerSnapshot::from_histogram(total_hist))
+}
+
+fn report_worker_intervals(
+    rx: mpsc::Receiver<WorkerReport>,
+    worker_count: usize,
+    measurement_start: Instant,
+) -> (Option<TimerSnapshot>, Instant, u64) {
+    let mut total_hist = hdrhistogram::Histogram::new_with_bounds(1, 60_000_000_000, 3)
+        .expect("failed to create cumulative client histogram");
+    let mut interval_start = measurement_start;
+    let mut next_interval_idx = 0u64;
+    let mut pending = std::collections::BTreeMap::<u64, Vec<WorkerReport>>::new();
+    let mut finished = 0usize;
+    let mut measured_completions = 0u64;
+    let mut end = measurement_start;
+
+    while finished < worker_count {
+        let report = rx
+            .recv()
+            .expect("worker report channel closed unexpectedly");
+        let key = match report {
+            WorkerReport::Interval { interval_idx, .. } => interval_idx,
+            WorkerReport::Finished {
+                end: worker_end,
+                measured_completions: worker_completions,
+                ..
+            } => {
+                finished += 1;
+                measured_completions += worker_completions;
+                end = end.max(worker_end);
+                continue;
+            }
+        };
+        pending.entry(key).or_default().push(report);
+
+        while let Some(reports) = pending.remove(&next_interval_idx) {
+            if reports.len() < worker_count {
+                pending.insert(next_interval_idx, reports);
+                break;
+            }
+
+            let mut completions = 0u64;
+            let mut interval_end = interval_start;
+            let mut snapshots = Vec::with_capacity(worker_count);
+            for report in reports {
+                if let WorkerReport::Interval {
+                    interval_idx: _,
+                    end: worker_end,
+                    completions: worker_completions,
+                    snapshot,
+                } = report
+                {
+                    completions += worker_completions;
+                    interval_end = interval_end.max(worker_end);
+                    snapshots.push(snapshot);
+                }
+            }
+            let snapshot = aggregate_snapshot(snapshots);
+            if let Some(ref snap) = snapshot {
+                snap.merge_into(&mut total_hist);
+            }
+            print_interval(snapshot, completions, interval_start, interval_end);
+            interval_start = interval_end;
+            next_interval_idx += 1;
+        }
+    }
+
+    let final_snapshot = if total_hist.is_empty() {
+        None
+    } else {
+        Some(TimerSnapshot::from_histogram(total_hist))
+    };
+    (final_snapshot, end, measured_completions)
+}
+
+fn create_connection(addr: &str) -> io::Result<RawFd> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    Ok(stream.into_raw_fd())
+}
+
+fn submit_read(ring: &mut IoUring, conn: &mut Connection, key: u32) {
+    if conn.read_inflight || conn.read_len == READ_BUF_SIZE || conn.pending.is_empty() {
+        return;
+    }
+
+    let (ptr, len) = conn.read_tail();
+    let sqe = opcode::Read::new(Fd(conn.fd), ptr, len)
+        .build()
+        .user_data(encode_user_data(OP_READ, key));
+    ring.push(&sqe);
+    conn.read_inflight = true;
+}
+
+fn submit_writes(
+    ring: &mut IoUring,
+    conn: &mut Connection,
+    key: u32,
+    scenario: &Scenario,
+    run: &RunState,
+    now: Instant,
+) {
+    while conn.can_issue_more(scenario, now, run) {
+        let template_idx = (conn.submitted_total as usize) % scenario.templates.len();
+        let template = &scenario.templates[template_idx];
+        let sqe = opcode::Write::new(
+            Fd(conn.fd),
+            template.request_bytes.as_ptr(),
+            template.request_bytes.len() as u32,
+        )
+        .build()
+        .user_data(encode_user_data(OP_WRITE, key));
+        ring.push(&sqe);
+        let submitted_at = conn.arrival.as_mut().map_or(now, ArrivalClock::take);
+        conn.pending.push_back(PendingRequest {
+            template_idx,
+            submitted_at,
+        });
+        conn.submitted_total += 1;
+    }
+
+    submit_read(ring, conn, key);
+}
+
+fn verify_response(frame: &[u8], template: &RequestTemplate) {
+    let got_vectors = frame[0] as u32;
+    assert_eq!(
+        got_vectors, template.num_vectors,
+        "response num_vectors={} does not match expected={} (protocol error or data corruption)",
+        got_vectors, template.num_vectors
+    );
+
+    let body = &frame[RESPONSE_HEADER_BYTES..];
+    assert_eq!(body.len(), template.expected.len() * 4);
+
+    for (i, expected) in template.expected.iter().enumerate() {
+        let offset = i * 4;
+        let got = f32::from_le_bytes([
+            body[offset],
+            body[offset + 1],
+            body[offset + 2],
+            body[offset + 3],
+        ]);
+        let diff = (got - expected).abs();
+        assert!(
+            diff < 0.1,
+            "vector {}: result {} != expected {}",
+            i,
+            got,
+            expected
+        );
+    }
+}
+
+fn process_read_buffer(
+    conn: &mut Connection,
+    scenario: &Scenario,
+    stats: &mut SummaryStats,
+    run: &RunState,
+    now: Instant,
+    interval_latency_recorder: &mut Option<TimerRecorder>,
+) {
+    let mut consumed = 0usize;
+    let length_prefix = if scenario.expect_length_prefix {
+        LENGTH_PREFIX_BYTES
+    } else {
+        0
+    };
+    let prefix = length_prefix
+        + if scenario.expect_request_seq {
+            SEQ_PREFIX_BYTES
+        } else {
+            0
+        };
+
+    while let Some(pending) = conn.pending.front().copied() {
+        if conn.read_len - consumed < prefix + 1 {
+            break;
+        }
+        let seq_start = consumed + length_prefix;
+        // With length prefixes, wait for the whole frame so unknown kinds can be skipped.
+        let framed_end = (length_prefix > 0)
+            .then(|| seq_start + decode_length_prefix(&conn.read_buf[consumed..]));
+        if framed_end.is_some_and(|end| end > conn.read_len) {
+            break;
+        }
+        let available = &conn.read_buf[consumed + prefix..framed_end.unwrap_or(conn.read_len)];
+        if is_parse_error(available) {
+            // The server rejected a request as malformed and is closing the connection.
+            let Some(error) = decode_parse_error(available) else {
+                if available.len() < PARSE_ERROR_FRAME_BYTES {
+                    break;
+                }
+                panic!("conn fd {}: server sent an unknown parse error", conn.fd);
+            };
+            panic!(
+                "conn fd {} after {} responses: server rejected request: {error}",
+                conn.fd, conn.completed_total
+            );
+        }
+        if available.first() == Some(&0) {
+            // Overload frame: the request was rejected unrun. Back off for the server's hint and
+            // re-issue it afterwards.
+            let Some((_, retry_after_ms)) = decode_overload(available) else {
+                match framed_end {
+                    // A frame kind this client does not know; it answers no request.
+                    Some(end) => {
+                        consumed = end;
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+            check_request_seq(conn, scenario, seq_start);
+            conn.pending.pop_front();
+            conn.submitted_total -= 1;
+            conn.backoff_until = Some(now + Duration::from_millis(retry_after_ms as u64));
+            stats.overloaded += 1;
+            consumed += prefix + OVERLOAD_FRAME_BYTES;
+            continue;
+        }
+
+        let template = &scenario.templates[pending.template_idx];
+        let response_len = response_size(template.num_vectors as usize);
+        let expected_len = response_len
+            + if scenario.expect_vector_status {
+                vector_status_size(template.num_vectors as usize)
+            } else {
+                0
+            };
+        if conn.read_len - consumed < prefix + expected_len {
+            break;
+        }
+        if let Some(end) = framed_end {
+            assert_eq!(
+                end - consumed,
+                prefix + expected_len,
+                "conn fd {}: length prefix does not match a {}-vector response",
+                conn.fd,
+                template.num_vectors
+            );
+        }
+
+        check_request_seq(conn, scenario, seq_start);
+        let frame = &conn.read_buf[consumed + prefix..consumed + prefix + expected_len];
+        if scenario.verify {
+            verify_response(&frame[..response_len], template);
+            if scenario.expect_vector_status {
+                let statuses = decode_vector_status(&frame[response_len..]);
+                assert!(
+                    statuses.iter().all(|&s| s == Some(VectorStatus::Ok)),
+                    "conn fd {}: vector status {statuses:?}",
+                    conn.fd
+                );
+            }
+        }
+
+        conn.pending.pop_front();
+        conn.completed_total += 1;
+        stats.record_completion(scenario, run, now);
+        if scenario.collect_latency && run.is_measuring(scenario, now) {
+            let latency = now.duration_since(pending.submitted_at);
+            if let Some(recorder) = interval_latency_recorder.as_mut() {
+                recorder.record_duration(latency);
+            }
+        }
+        consumed += prefix + expected_len;
+    }
+
+    if consumed > 0 {
+        conn.read_buf.copy_within(consumed..conn.read_len, 0);
+        conn.read_len -= consumed;
+    }
+}
+
+/// Verify the `request_seq` prefix at `seq_start`, if the scenario expects one.
+fn check_request_seq(conn: &mut Connection, scenario: &Scenario, seq_start: usize) {
+    if !scenario.expect_request_seq {
+        return;
+    }
+    let request_seq = decode_seq_prefix(&conn.read_buf[seq_start..]);
+    if let Err(e) = conn.seq_check.check(request_seq) {
+        panic!(
+            "conn fd {} after {} responses: {e}",
+            conn.fd, conn.completed_total
+        );
+    }
+}
+
+fn handle_write_cqe(result: i32, key: u32, scenario: &Scenario) {
+    let template = &scenario.templates[0];
+    assert!(
+        result >= 0,
+        "write failed for conn {}: {}",
+        key,
+        io::Error::from_raw_os_error(-result)
+    );
+    assert_eq!(
+        result as usize,
+        template.request_bytes.len(),
+        "partial write for conn {}: wrote {} of {} bytes",
+        key,
+        result,
+        template.request_bytes.len()
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_read_cqe(
+    result: i32,
+    conn: &mut Connection,
+    scenario: &Scenario,
+    stats: &mut SummaryStats,
+    run: &RunState,
+    now: Instant,
+    interval_latency_recorder: &mut Option<TimerRecorder>,
+) {
+    conn.read_inflight = false;
+    if result == 0 {
+        panic!(
+            "connection closed with {} responses still pending",
+            conn.pending_count()
+        );
+    }
+    assert!(
+        result > 0,
+        "read failed: {}",
+        io::Error::from_raw_os_error(-result)
+    );
+
+    conn.read_len += result as usize;
+    process_read_buffer(conn, scenario, stats, run, now, interval_latency_recorder);
+}
+
+/// The earliest open-loop request due before the run ends on a connection that could send it.
+fn next_arrival(
+    conns: &Slab<Connection>,
+    scenario: &Scenario,
+    run: &RunState,
+    now: Instant,
+) -> Option<Instant> {
+    conns
+        .iter()
+        .filter(|(_, conn)| conn.pending_count() < scenario.window)
+        .filter_map(|(_, conn)| {
+            let due = conn.arrival.as_ref()?.next;
+            Some(conn.backoff_until.map_or(due, |until| due.max(until)))
+        })
+        .filter(|&due| due > now && due < run.measure_end)
+        .min()
+}
+
+fn run_worker(
+    worker_id: usize,
+    addr: String,
+    scenario: Scenario,
+    run_plan: RunPlan,
+    event_loop_cpu: Option<usize>,
+    start_barrier: Arc<Barrier>,
+    report_tx: Option<Sender<WorkerReport>>,
+) -> WorkerOutcome {
+    assert!(scenario.connections > 0, "connections must be > 0");
+    assert!(scenario.window > 0, "window must be > 0");
+
+    if let Some(cpu) = event_loop_cpu {
+        let thread_name = format!("client-worker-{worker_id}");
+        affinity::pin_current_thread(cpu, &thread_name).unwrap_or_else(|e| panic!("{e}"));
+    }
+
+    start_barrier.wait();
+    let sq_entries = (scenario.connections * scenario.window * 2).clamp(256, 16384) as u32;
+    let mut ring = IoUring::new(sq_entries).expect("io_uring creation failed");
+    let mut conns: Slab<Connection> = Slab::with_capacity(scenario.connections);
+    let mut cqe_buf: Vec<(u64, i32)> = Vec::with_capacity(sq_entries as usize);
+    let mut stats = SummaryStats::default();
+    let mut run = RunState::from_plan(run_plan);
+    let latency_interval_timer = scenario.collect_latency.then(TimerMetric::new);
+    let mut latency_interval_recorder = latency_interval_timer.as_ref().map(TimerMetric::recorder);
+    let mut next_interval_idx = 0u64;
+    let mut last_interval_start = run.warmup_end;
+
+    for _ in 0..scenario.connections {
+        let fd = create_connection(&addr).expect("failed to connect");
+        let entry = conns.vacant_entry();
+        let mut conn = Connection::new(fd, scenario.window);
+        conn.arrival = scenario.arrivals.map(|arrivals| {
+            let seed = ((worker_id as u64) << 32) | entry.key() as u64;
+            ArrivalClock::new(arrivals, run_plan.start, seed)
+        });
+        entry.insert(conn);
+    }
+    // Open loop: the armed timeout, so the loop wakes when the next request falls due. The
+    // timespec is read when the SQE is submitted, so it lives as long as the loop.
+    let mut timer_armed = false;
+    let mut timer: Timespec;
+
+    loop {
+        let now = Instant::now();
+        if scenario.collect_latency && !run.measurement_started && now >= run.warmup_end {
+            run.measurement_started = true;
+            last_interval_start = now;
+        }
+        for (key, conn) in &mut conns {
+            submit_writes(&mut ring, conn, key as u32, &scenario, &run, now);
+        }
+
+        if conns
+            .iter()
+            .all(|(_, conn)| conn.is_finished(&scenario, now, &run))
+            && ring.outstanding == 0
+        {
+            break;
+        }
+
+        if !timer_armed && let Some(due) = next_arrival(&conns, &scenario, &run, now) {
+            timer = Timespec::from(due.saturating_duration_since(now));
+            let sqe = opcode::Timeout::new(&timer)
+                .build()
+                .user_data(encode_user_data(OP_TIMER, 0));
+            ring.push(&sqe);
+            timer_armed = true;
+        }
+
+        if ring.outstanding == 0 {
+            continue;
+        }
+
+        ring.wait(1);
+        cqe_buf.clear();
+        ring.drain_cqes_into(&mut cqe_buf);
+
+        let now = Instant::now();
+        for &(user_data, result) in &cqe_buf {
+            let (op, key) = decode_user_data(user_data);
+            if op == OP_TIMER {
+                timer_armed = false;
+                continue;
+            }
+            let conn = conns
+                .get_mut(key as usize)
+                .unwrap_or_else(|| panic!("missing conn for key {}", key));
+            match op {
+                OP_WRITE => handle_write_cqe(result, key, &scenario),
+                OP_READ => handle_read_cqe(
+                    result,
+                    conn,
+                    &scenario,
+                    &mut stats,
+                    &run,
+                    now,
+                    &mut latency_interval_recorder,
+                ),
+                _ => panic!("unknown op {}", op),
+            }
+        }
+
+        if scenario.collect_latency
+            && run.measurement_started
+            && let Some(tx) = report_tx.as_ref()
+        {
+            let now = Instant::now();
+            while now.duration_since(last_interval_start) >= Duration::from_secs(1) {
+                let interval_end = last_interval_start + Duration::from_secs(1);
+                let snapshot = latency_interval_timer
+                    .as_ref()
+                    .and_then(TimerMetric::snapshot_and_reset);
+                let completions = snapshot.as_ref().map_or(0, TimerSnapshot::count);
+                tx.send(WorkerReport::Interval {
+                    interval_idx: next_interval_idx,
+                    end: interval_end,
+                    completions,
+                    snapshot,
+                })
+                .expect("failed to send worker interval report");
+                last_interval_start = interval_end;
+                next_interval_idx += 1;
+            }
+        }
+    }
+
+    let end = Instant::now();
+    drop(latency_interval_recorder);
+
+    if scenario.collect_latency
+        && run.measurement_started
+        && let Some(tx) = report_tx.as_ref()
+    {
+        let snapshot = latency_interval_timer
+            .as_ref()
+            .and_then(TimerMetric::snapshot_and_reset);
+        let completions = snapshot.as_ref().map_or(0, TimerSnapshot::count);
+        tx.send(WorkerReport::Interval {
+            interval_idx: next_interval_idx,
+            end,
+            completions,
+            snapshot,
+        })
+        .expect("failed to send final worker interval report");
+        tx.send(WorkerReport::Finished {
+            end,
+            measured_completions: stats.measured_completions,
+        })
+        .expect("failed to send worker finish report");
+    }
+
+    let total_completed = conns.iter().map(|(_, conn)| conn.completed_total).sum();
+    WorkerOutcome {
+        end,
+        measured_completions: stats.measured_completions,
+        total_completed,
+        overloaded: stats.overloaded,
+    }
+}
+
+struct WorkerOutcome {
+    end: Instant,
+    measured_completions: u64,
+    total_completed: u64,
+    overloaded: u64,
+}
+
+fn print_overloaded(outcomes: &[WorkerOutcome]) {
+    let overloaded: u64 = outcomes.iter().map(|outcome| outcome.overloaded).sum();
+    if overloaded > 0 {
+        eprintln!("  overloaded {overloaded} (re-issued after the server's retry-after hint)");
+    }
+}
+
+fn run_scenario(
+    addr: &str,
+    scenario: Scenario,
+    event_loop_cpu: Option<usize>,
+    reporter_cpu: Option<usize>,
+) {
+    assert!(scenario.threads > 0, "threads must be > 0");
+    let run_plan = RunPlan::new(&scenario.stop_mode);
+    let start_barrier = Arc::new(Barrier::new(scenario.threads));
+
+    if matches!(scenario.stop_mode, StopMode::Duration { warmup, .. } if warmup > Duration::ZERO) {
+        eprintln!(
+            "{}: {} thread(s) x {} connections, window={}, {} vector(s)/req, warmup={}s, duration={}s -> {}",
+            scenario.name,
+            scenario.threads,
+            scenario.connections,
+            scenario.window,
+            scenario.templates[0].num_vectors,
+            run_plan.warmup_end.duration_since(run_plan.start).as_secs(),
+            run_plan
+                .measure_end
+                .duration_since(run_plan.warmup_end)
+                .as_secs(),
+            addr
+        );
+        if let Some(arrivals) = scenario.arrivals {
+            let streams = (scenario.threads * scenario.connections) as f64;
+            eprintln!(
+                "{}: {:.0} req/s target, {} arrivals, latency measured from when each request was due",
+                scenario.name,
+                streams / arrivals.interval.as_secs_f64(),
+                if arrivals.poisson { "poisson" } else { "fixed" }
+            );
+        }
+        eprintln!(
+            "{:>10}  {:>9}  {:>9}  {:>9}  {:>9}  {:>8}",
+            "qps", "p50", "p95", "p99", "p99.9", "n"
+        );
+    } else {
+        eprintln!(
+            "{}: {} thread(s) x {} connections, window={}, {} vector(s)/req -> {}",
+            scenario.name,
+            scenario.threads,
+            scenario.connections,
+            scenario.window,
+            scenario.templates[0].num_vectors,
+            addr
+        );
+    }
+
+    let report_rx = if scenario.collect_latency {
+        let (tx, rx) = mpsc::channel();
+        Some((tx, rx))
+    } else {
+        None
+    };
+
+    let handles = (0..scenario.threads)
+        .map(|worker_id| {
+            let worker_addr = addr.to_string();
+            let worker_scenario = scenario.clone();
+            let worker_plan = run_plan;
+            let worker_cpu = event_loop_cpu.map(|base| base + worker_id);
+            let worker_barrier = Arc::clone(&start_barrier);
+            let report_tx = report_rx.as_ref().map(|(tx, _)| tx.clone());
+            thread::Builder::new()
+                .name(format!("client-worker-{worker_id}"))
+                .spawn(move || {
+                    run_worker(
+                        worker_id,
+                        worker_addr,
+                        worker_scenario,
+                        worker_plan,
+                        worker_cpu,
+                        worker_barrier,
+                        report_tx,
+                    )
+                })
+                .expect("failed to spawn client worker")
+        })
+        .collect::<Vec<_>>();
+
+    match scenario.stop_mode {
+        StopMode::FixedCount {
+            requests_per_connection,
+        } => {
+            let outcomes = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("worker thread panicked"))
+                .collect::<Vec<_>>();
+            let total =
+                scenario.threads as u64 * scenario.connections as u64 * requests_per_connection;
+            let end = outcomes
+                .iter()
+                .map(|outcome| outcome.end)
+                .max()
+                .unwrap_or(run_plan.start);
+            let elapsed = end.duration_since(run_plan.start);
+            let qps = total as f64 / elapsed.as_secs_f64();
+            eprintln!(
+                "{}: {} requests in {:.2}s = {:.0} QPS",
+                scenario.name,
+                total,
+                elapsed.as_secs_f64(),
+                qps
+            );
+            print_overloaded(&outcomes);
+        }
+        StopMode::Duration { .. } => {
+            let (_, rx) = report_rx.expect("latency reporting channel missing");
+            let reporter = thread::Builder::new()
+                .name("client-reporter".into())
+                .spawn(move || {
+                    if let Some(cpu) = reporter_cpu {
+                        affinity::pin_current_thread(cpu, "client-reporter")
+                            .unwrap_or_else(|e| panic!("{e}"));
+                    }
+                    report_worker_intervals(rx, scenario.threads, run_plan.warmup_end)
+                })
+                .expect("failed to spawn client reporter");
+            let (snapshot, end, measured_completions) =
+                reporter.join().expect("reporter thread panicked");
+            let outcomes = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("worker thread panicked"))
+                .collect::<Vec<_>>();
+            for outcome in &outcomes {
+                debug_assert!(outcome.total_completed >= outcome.measured_completions);
+            }
+            print_summary(
+                snapshot,
+                Some(run_plan.warmup_end),
+                end,
+                measured_completions,
+            );
+            print_overloaded(&outcomes);
+        }
+    }
+}
+
+fn smoke_test(
+    addr: &str,
+    expect_request_seq: bool,
+    expect_length_prefix: bool,
+    expect_vector_status: bool,
+    feature_dim: usize,
+) {
+    eprintln!("smoke test: connecting to {}", addr);
+
+    run_scenario(
+        addr,
+        Scenario {
+            name: "smoke-1",
+            threads: 1,
+            connections: 1,
+            window: 1,
+            templates: Arc::from([RequestTemplate::new(1, feature_dim)]),
+            verify: true,
+            collect_latency: false,
+            stop_mode: StopMode::FixedCount {
+                requests_per_connection: 1,
+            },
+            expect_request_seq,
+            expect_length_prefix,
+            expect_vector_status,
+            arrivals: None,
+        },
+        None,
+        None,
+    );
+    eprintln!("  1 vector: OK");
+
+    run_scenario(
+        addr,
+        Scenario {
+            name: "smoke-4",
+            threads: 1,
+            connections: 1,
+            window: 1,
+            templates: Arc::from([RequestTemplate::new(4, feature_dim)]),
+            verify: true,
+            collect_latency: false,
+            stop_mode: StopMode::FixedCount {
+                requests_per_connection: 1,
+            },
+            expect_request_seq,
+            expect_length_prefix,
+            expect_vector_status,
+            arrivals: None,
+        },
+        None,
+        None,
+    );
+    eprintln!("  4 vectors: OK");
+    eprintln!("smoke test: PASSED");
+}
+
+pub fn main() {
+    let cli = Cli::parse();
+    let addr = format!("127.0.0.1:{}", cli.port);
+
+    let scenario = match cli.command.unwrap_or(Command::Smoke) {
+        Command::Smoke => {
+            return smoke_test(
+                &addr,
+                cli.expect_request_seq,
+                cli.expect_length_prefix,
+                cli.expect_vector_status,
+                cli.feature_dim,
+            );
+        }
+        Command::Pipeline(args) => Scenario::pipeline(args, cli.feature_dim),
+        Command::Bench(args) => Scenario::bench(args, cli.feature_dim),
+        Command::Sustain(args) => Scenario::sustain(args, cli.feature_dim),
+        Command::OpenLoop(args) => Scenario::open_loop(args, cli.feature_dim),
+    };
+    run_scenario(
+        &addr,
+        Scenario {
+            expect_request_seq: cli.expect_request_seq,
+            expect_length_prefix: cli.expect_length_prefix,
+            expect_vector_status: cli.expect_vector_status,
+            ..scenario
+        },
+        cli.event_loop_cpu,
+        cli.reporter_cpu,
+    );
+}
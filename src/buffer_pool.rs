@@ -4,7 +4,13 @@ use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
+use crate::cache_line::CachePadded;
+use crate::clock;
 use crate::metrics;
+
+/// Huge page size a huge-page pool is rounded up to, 2 MiB on x86_64 and aarch64.
+const HUGE_PAGE_BYTES: usize = 2 << 20;
+
 /// Error returned when buffer pool allocation fails.
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -52,7 +58,7 @@ impl PoolSlice {
         unsafe { std::slice::from_raw_parts(self.data, self.len) }
     }
 
-    /// Get the vector at index `i` (assumes FEATURE_DIM stride).
+    /// Get the vector at index `i`, `feature_dim` floats each.
     pub fn vector(&self, i: usize, feature_dim: usize) -> &[f32] {
         let start = i * feature_dim;
         let end = start.saturating_add(feature_dim);
@@ -151,14 +157,97 @@ impl PoolSliceMut {
 pub struct BufferPool {
     data: *const f32,
     _backing: Option<Box<[UnsafeCell<f32>]>>,
+    _mapping: Option<HugePageMapping>,
+    pages: PoolPages,
     capacity: usize,
-    write_cursor: AtomicUsize,
-    read_cursor: AtomicUsize,
+    write_cursor: CachePadded<AtomicUsize>,
+    read_cursor: CachePadded<AtomicUsize>,
 }
 
 unsafe impl Send for BufferPool {}
 unsafe impl Sync for BufferPool {}
 
+/// Where a pool's allocator and releases have got to, in f32 values since the pool was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolCursors {
+    pub read: usize,
+    pub write: usize,
+    pub capacity: usize,
+}
+
+impl PoolCursors {
+    /// Values allocated and not yet released.
+    pub fn in_use(self) -> usize {
+        self.write.wrapping_sub(self.read)
+    }
+}
+
+/// What pages back a pool's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolPages {
+    /// Ordinary pages, or memory the pool was handed and did not allocate.
+    Regular,
+    /// Reserved huge pages from `mmap(MAP_HUGETLB)`. Linux only.
+    HugeTlb,
+    /// Transparent huge pages requested with `madvise(MADV_HUGEPAGE)`, which the kernel grants
+    /// as it can. Linux only.
+    Transparent,
+}
+
+impl PoolPages {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PoolPages::Regular => "regular",
+            PoolPages::HugeTlb => "hugetlb",
+            PoolPages::Transparent => "transparent",
+        }
+    }
+}
+
+/// An anonymous mapping rounded up to whole huge pages, unmapped on drop.
+struct HugePageMapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl HugePageMapping {
+    /// Map at least `bytes` bytes: reserved huge pages if enough are free, else ordinary pages
+    /// with transparent huge pages requested. Off Linux the mapping is always ordinary pages.
+    /// `None` only if every mapping fails.
+    fn new(bytes: usize) -> Option<(Self, PoolPages)> {
+        let len = bytes.max(1).next_multiple_of(HUGE_PAGE_BYTES);
+        let map = |flags| unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+                -1,
+                0,
+            );
+            (ptr != libc::MAP_FAILED).then_some(Self { ptr, len })
+        };
+        // A private MAP_HUGETLB mapping reserves its pages up front, so it fails here rather
+        // than faulting later when the system has too few.
+        #[cfg(target_os = "linux")]
+        if let Some(mapping) = map(libc::MAP_HUGETLB) {
+            return Some((mapping, PoolPages::HugeTlb));
+        }
+        let mapping = map(0)?;
+        #[cfg(target_os = "linux")]
+        if unsafe { libc::madvise(mapping.ptr, len, libc::MADV_HUGEPAGE) } == 0 {
+            return Some((mapping, PoolPages::Transparent));
+        }
+        Some((mapping, PoolPages::Regular))
+    }
+}
+
+impl Drop for HugePageMapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct PoolAllocator {
     pool: &'static BufferPool,
@@ -167,7 +256,15 @@ pub struct PoolAllocator {
 impl PoolAllocator {
     /// Allocate space for `len` f32 values from the underlying pool.
     pub fn alloc(&mut self, len: usize) -> Result<PoolSliceMut, AllocError> {
-        self.pool.alloc_inner(len)
+        if !cfg!(feature = "metrics") {
+            return self.pool.alloc_inner(len);
+        }
+        let start = clock::ticks();
+        let result = self.pool.alloc_inner(len);
+        if result.is_ok() {
+            metrics::record_pool_alloc_ticks(clock::ticks().wrapping_sub(start));
+        }
+        result
     }
 }
 
@@ -191,9 +288,34 @@ impl BufferPool {
         Box::new(Self {
             data: ptr,
             _backing: Some(data),
+            _mapping: None,
+            pages: PoolPages::Regular,
             capacity,
-            write_cursor: AtomicUsize::new(0),
-            read_cursor: AtomicUsize::new(0),
+            write_cursor: CachePadded::new(AtomicUsize::new(0)),
+            read_cursor: CachePadded::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Like [`Self::new_boxed`], but backed by huge pages to cut TLB misses across a large pool:
+    /// reserved huge pages (`MAP_HUGETLB`) when enough are free, else transparent huge pages
+    /// (`MADV_HUGEPAGE`), else ordinary pages. [`Self::pages`] reports which it got.
+    pub fn new_boxed_huge(capacity: usize) -> Box<Self> {
+        let Some((mapping, pages)) = HugePageMapping::new(capacity * size_of::<f32>()) else {
+            return Self::new_boxed(capacity);
+        };
+        let ptr = mapping.ptr as *mut f32;
+        // Anonymous mappings are zeroed; touch every page to fault it in upfront.
+        for i in (0..capacity).step_by(1024) {
+            unsafe { ptr.add(i).write(0.0f32) };
+        }
+        Box::new(Self {
+            data: ptr,
+            _backing: None,
+            _mapping: Some(mapping),
+            pages,
+            capacity,
+            write_cursor: CachePadded::new(AtomicUsize::new(0)),
+            read_cursor: CachePadded::new(AtomicUsize::new(0)),
         })
     }
 
@@ -210,9 +332,11 @@ impl BufferPool {
         Box::new(Self {
             data: ptr as *const f32,
             _backing: None,
+            _mapping: None,
+            pages: PoolPages::Regular,
             capacity,
-            write_cursor: AtomicUsize::new(0),
-            read_cursor: AtomicUsize::new(0),
+            write_cursor: CachePadded::new(AtomicUsize::new(0)),
+            read_cursor: CachePadded::new(AtomicUsize::new(0)),
         })
     }
 
@@ -222,6 +346,16 @@ impl BufferPool {
         Box::leak(Self::new_boxed(capacity))
     }
 
+    /// [`Self::leak_new`] over huge pages, as [`Self::new_boxed_huge`].
+    pub fn leak_new_huge(capacity: usize) -> &'static Self {
+        Box::leak(Self::new_boxed_huge(capacity))
+    }
+
+    /// What pages back this pool.
+    pub fn pages(&self) -> PoolPages {
+        self.pages
+    }
+
     /// Create an exclusive allocator capability for this pool.
     pub fn allocator(&'static self) -> PoolAllocator {
         PoolAllocator { pool: self }
@@ -289,6 +423,15 @@ impl BufferPool {
         })
     }
 
+    /// The pool's cursors right now, for diagnostics such as a fatal-error state dump.
+    pub fn cursors(&self) -> PoolCursors {
+        PoolCursors {
+            read: self.read_cursor.load(Ordering::Acquire),
+            write: self.write_cursor.load(Ordering::Acquire),
+            capacity: self.capacity,
+        }
+    }
+
     /// Get current pool utilization for debugging.
     #[allow(dead_code)]
     pub fn utilization(&self) -> (usize, usize) {
@@ -332,7 +475,13 @@ mod tests {
     where
         F: FnOnce(&'static BufferPool, &mut PoolAllocator),
     {
-        let boxed = BufferPool::new_boxed(capacity);
+        with_boxed_pool(BufferPool::new_boxed(capacity), f);
+    }
+
+    fn with_boxed_pool<F>(boxed: Box<BufferPool>, f: F)
+    where
+        F: FnOnce(&'static BufferPool, &mut PoolAllocator),
+    {
         let ptr = Box::into_raw(boxed);
         struct PoolGuard {
             ptr: *mut BufferPool,
@@ -388,6 +537,22 @@ mod tests {
         });
     }
 
+    #[test]
+    fn huge_page_pool_falls_back_and_allocates() {
+        init_factory_pool();
+        // Whatever pages the system grants, the pool behaves like a heap-backed one.
+        let capacity = 3 * 1024 * 1024 / 4;
+        with_boxed_pool(BufferPool::new_boxed_huge(capacity), |pool, alloc| {
+            assert_eq!(pool.utilization(), (0, capacity));
+            let mut m = alloc.alloc(capacity - 1).expect("alloc failed");
+            assert!(m.as_mut_slice().iter().all(|&x| x == 0.0));
+            m.as_mut_slice().fill(7.0);
+            let s = m.freeze();
+            assert!(s.as_slice().iter().all(|&x| x == 7.0));
+        });
+        assert_eq!(BufferPool::new_boxed(16).pages(), PoolPages::Regular);
+    }
+
     #[test]
     fn wraparound_overwrites_in_use_region() {
         with_pool(100, |_pool, alloc| {
@@ -0,0 +1,189 @@
+//! Host ↔ wire conversion for little-endian `f32` payloads.
+//!
+//! The wire is little-endian (see `wire_layout`). On little-endian hosts wire bytes are the
+//! native representation, so [`write_f32s_le`] and [`read_f32s_le`] compile to a single
+//! memcpy. On big-endian hosts they fall back to [`portable`], which swaps per element.
+//!
+//! [`portable`] is always compiled so tests on little-endian CI machines exercise the big-endian
+//! code path and check it against the memcpy path byte for byte.
+//!
+//! Half-precision payloads are widened to little-endian `f32` bytes by [`widen_f16s_le`], one
+//! element at a time on any host.
+//!
+//! Fixed-point scores are written by [`write_fixed_point_u16s_le`], which scales and saturates
+//! eight scores at a time in a fixed-width block the compiler turns into vector instructions.
+
+/// Write `src` into `dst` as little-endian `f32`s. `dst.len()` must equal `src.len() * 4`.
+#[inline]
+pub fn write_f32s_le(src: &[f32], dst: &mut [u8]) {
+    #[cfg(target_endian = "little")]
+    dst.copy_from_slice(bytemuck::cast_slice(src));
+    #[cfg(not(target_endian = "little"))]
+    portable::write_f32s_le(src, dst);
+}
+
+/// Read little-endian `f32`s from `src` into `dst`. `src.len()` must equal `dst.len() * 4`.
+#[inline]
+pub fn read_f32s_le(src: &[u8], dst: &mut [f32]) {
+    #[cfg(target_endian = "little")]
+    bytemuck::cast_slice_mut::<f32, u8>(dst).copy_from_slice(src);
+    #[cfg(not(target_endian = "little"))]
+    portable::read_f32s_le(src, dst);
+}
+
+/// The `f32` an IEEE half-precision value with `bits` stands for. Every half value, NaN
+/// payloads aside, has an exact `f32`.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as u32;
+    match exponent {
+        // Zero and subnormals: mantissa × 2^-24.
+        0 => sign * mantissa as f32 * f32::from_bits(0x3380_0000),
+        0x1f => f32::from_bits((bits as u32 & 0x8000) << 16 | 0x7f80_0000 | mantissa << 13),
+        _ => f32::from_bits(
+            (bits as u32 & 0x8000) << 16 | (exponent as u32 + 127 - 15) << 23 | mantissa << 13,
+        ),
+    }
+}
+
+/// Widen little-endian half-precision values in `src` to little-endian `f32`s in `dst`.
+/// `dst.len()` must equal `src.len() * 2`.
+pub fn widen_f16s_le(src: &[u8], dst: &mut [u8]) {
+    assert_eq!(dst.len(), src.len() * 2);
+    for (bytes, out) in src.chunks_exact(2).zip(dst.chunks_exact_mut(4)) {
+        let value = f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]]));
+        out.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// `value * scale` rounded to the nearest `u16`, saturating at 0 and `u16::MAX`; NaN is 0.
+#[inline]
+pub fn to_fixed_point_u16(value: f32, scale: f32) -> u16 {
+    // `max` and `min` rather than a saturating cast alone, so the block below vectorizes; `max`
+    // also turns NaN into 0. The value is non-negative by then, so adding 0.5 and truncating
+    // rounds it.
+    (value * scale + 0.5).max(0.0).min(u16::MAX as f32) as u16
+}
+
+/// Write `src` scaled by `scale` into `dst` as little-endian fixed-point `u16`s, each as
+/// [`to_fixed_point_u16`] converts it. `dst.len()` must equal `src.len() * 2`.
+pub fn write_fixed_point_u16s_le(src: &[f32], scale: f32, dst: &mut [u8]) {
+    const LANES: usize = 8;
+    assert_eq!(dst.len(), src.len() * 2);
+    let mut blocks = src.chunks_exact(LANES);
+    let mut out = dst.chunks_exact_mut(LANES * 2);
+    for (block, out) in (&mut blocks).zip(&mut out) {
+        let mut fixed = [0u16; LANES];
+        for (fixed, &value) in fixed.iter_mut().zip(block) {
+            *fixed = to_fixed_point_u16(value, scale);
+        }
+        for (fixed, out) in fixed.iter().zip(out.chunks_exact_mut(2)) {
+            out.copy_from_slice(&fixed.to_le_bytes());
+        }
+    }
+    let tail = out.into_remainder();
+    for (&value, out) in blocks.remainder().iter().zip(tail.chunks_exact_mut(2)) {
+        out.copy_from_slice(&to_fixed_point_u16(value, scale).to_le_bytes());
+    }
+}
+
+/// Per-element conversion that is correct on any host byte order.
+pub mod portable {
+    pub fn write_f32s_le(src: &[f32], dst: &mut [u8]) {
+        assert_eq!(dst.len(), src.len() * 4);
+        for (value, out) in src.iter().zip(dst.chunks_exact_mut(4)) {
+            out.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub fn read_f32s_le(src: &[u8], dst: &mut [f32]) {
+        assert_eq!(src.len(), dst.len() * 4);
+        for (bytes, value) in src.chunks_exact(4).zip(dst.iter_mut()) {
+            *value = f32::from_le_bytes(bytes.try_into().unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        f16_to_f32, portable, read_f32s_le, to_fixed_point_u16, widen_f16s_le, write_f32s_le,
+        write_fixed_point_u16s_le,
+    };
+
+    const VALUES: [f32; 5] = [0.0, -1.5, 3.25e7, f32::MIN_POSITIVE, f32::INFINITY];
+
+    #[test]
+    fn host_and_portable_paths_agree() {
+        let mut host = [0u8; 20];
+        let mut swapped = [0u8; 20];
+        write_f32s_le(&VALUES, &mut host);
+        portable::write_f32s_le(&VALUES, &mut swapped);
+        assert_eq!(host, swapped);
+        assert_eq!(&host[4..8], &[0x00, 0x00, 0xc0, 0xbf]);
+
+        let mut from_host = [0f32; 5];
+        let mut from_portable = [0f32; 5];
+        read_f32s_le(&host, &mut from_host);
+        portable::read_f32s_le(&host, &mut from_portable);
+        assert_eq!(from_host, VALUES);
+        assert_eq!(from_portable, VALUES);
+    }
+
+    #[test]
+    fn half_precision_values_widen_exactly() {
+        for (bits, value) in [
+            (0x0000, 0.0),
+            (0x3c00, 1.0),
+            (0xc000, -2.0),
+            (0x3555, 0.333_251_95),
+            (0x7bff, 65504.0),
+            (0x0400, 6.103_515_6e-5),
+            (0x0001, 1.0 / 16_777_216.0),
+            (0x83ff, -1023.0 / 16_777_216.0),
+            (0x7c00, f32::INFINITY),
+            (0xfc00, f32::NEG_INFINITY),
+        ] {
+            assert_eq!(f16_to_f32(bits), value, "{bits:#06x}");
+        }
+        assert!(f16_to_f32(0x7e00).is_nan());
+        assert!(f16_to_f32(0x8000).is_sign_negative());
+
+        let mut wide = [0u8; 8];
+        widen_f16s_le(&[0x00, 0x3c, 0x00, 0xc0], &mut wide);
+        assert_eq!(wide[..4], 1.0f32.to_le_bytes());
+        assert_eq!(wide[4..], (-2.0f32).to_le_bytes());
+    }
+
+    #[test]
+    fn fixed_point_scores_round_and_saturate() {
+        for (value, fixed) in [
+            (0.0, 0),
+            (0.5, 5000),
+            (0.123_44, 1234),
+            (0.123_46, 1235),
+            (1.0, 10_000),
+            (6.5535, u16::MAX),
+            (7.0, u16::MAX),
+            (-0.2, 0),
+            (f32::INFINITY, u16::MAX),
+            (f32::NEG_INFINITY, 0),
+            (f32::NAN, 0),
+        ] {
+            assert_eq!(to_fixed_point_u16(value, 10_000.0), fixed, "{value}");
+        }
+
+        // Whole blocks and a tail, each value landing where its index says.
+        for len in [0, 1, 7, 8, 9, 16, 21] {
+            let scores: Vec<f32> = (0..len).map(|i| i as f32 / 100.0).collect();
+            let mut bytes = vec![0u8; len * 2];
+            write_fixed_point_u16s_le(&scores, 100.0, &mut bytes);
+            let fixed: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            assert_eq!(fixed, (0..len as u16).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Target cache-line size and padding helpers.
+//!
+//! Ring slots and cross-thread cursors are padded to a full line so a producer and a consumer
+//! never write the same line. The line size differs by target, and `repr(align)` only accepts a
+//! literal, so [`cache_line_aligned!`] stamps out the matching `cfg_attr` pair and
+//! [`CachePadded`] wraps single values. Layout checks use [`is_padded`] rather than a fixed size,
+//! so adding a field to a slot grows it by whole lines instead of breaking the build.
+
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+
+/// Destructive-interference size on this target.
+///
+/// Apple aarch64 cores use 128-byte lines; Graviton/Neoverse and x86_64 use 64.
+#[cfg(all(target_arch = "aarch64", target_vendor = "apple"))]
+pub const CACHE_LINE_BYTES: usize = 128;
+#[cfg(not(all(target_arch = "aarch64", target_vendor = "apple")))]
+pub const CACHE_LINE_BYTES: usize = 64;
+
+/// Declare a `repr(C)` struct aligned to [`CACHE_LINE_BYTES`].
+macro_rules! cache_line_aligned {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident $($body:tt)*) => {
+        $(#[$meta])*
+        #[cfg_attr(
+            all(target_arch = "aarch64", target_vendor = "apple"),
+            repr(C, align(128))
+        )]
+        #[cfg_attr(
+            not(all(target_arch = "aarch64", target_vendor = "apple")),
+            repr(C, align(64))
+        )]
+        $vis struct $name $($body)*
+    };
+}
+pub(crate) use cache_line_aligned;
+
+/// `true` if `T` starts on a line boundary and occupies whole lines.
+pub const fn is_padded<T>() -> bool {
+    align_of::<T>() >= CACHE_LINE_BYTES && size_of::<T>().is_multiple_of(CACHE_LINE_BYTES)
+}
+
+cache_line_aligned! {
+    /// A value alone on its own cache line(s).
+    #[derive(Debug, Default)]
+    pub struct CachePadded<T> {
+        value: T,
+    }
+}
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+const _: () = assert!(is_padded::<CachePadded<u8>>());
+const _: () = assert!(is_padded::<CachePadded<[u8; CACHE_LINE_BYTES + 1]>>());
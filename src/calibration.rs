@@ -0,0 +1,203 @@
+//! Post-inference calibration of model scores.
+//!
+//! Models are retrained rarely but recalibrated often, so the calibration lives in its own file
+//! and is applied by the inference thread to every result before it is encoded. The file uses
+//! the config file's format, one `key = value` per line with `#` comments. Platt scaling maps a
+//! score `s` to `1 / (1 + exp(a * s + b))`:
+//!
+//! ```text
+//! method = platt
+//! a = -1.7
+//! b = 0.2
+//! ```
+//!
+//! An isotonic lookup interpolates linearly between `point = <score>, <calibrated>` lines, with
+//! scores strictly increasing and calibrated values non-decreasing, and clamps scores outside
+//! the first and last points to their values:
+//!
+//! ```text
+//! method = isotonic
+//! point = 0.0, 0.02
+//! point = 0.5, 0.30
+//! point = 1.0, 0.95
+//! ```
+//!
+//! NaN scores stay NaN, so `--vector-status` still reports them as `model_error`.
+
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Calibration {
+    Platt {
+        a: f32,
+        b: f32,
+    },
+    Isotonic {
+        scores: Vec<f32>,
+        calibrated: Vec<f32>,
+    },
+}
+
+impl Calibration {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (mut method, mut a, mut b) = (None, None, None);
+        let mut points: Vec<(f32, f32)> = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_err = |message: String| format!("line {}: {message}", line_no + 1);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| line_err("expected `key = value`".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            let parse_value = |value: &str| {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| line_err(format!("invalid value '{value}'")))
+            };
+            match key {
+                "method" => method = Some(value.to_string()),
+                "a" => a = Some(parse_value(value)?),
+                "b" => b = Some(parse_value(value)?),
+                "point" => {
+                    let (score, calibrated) = value.split_once(',').ok_or_else(|| {
+                        line_err("expected `point = <score>, <calibrated>`".into())
+                    })?;
+                    points.push((parse_value(score.trim())?, parse_value(calibrated.trim())?));
+                }
+                other => return Err(line_err(format!("unknown key '{other}'"))),
+            }
+        }
+        match method.as_deref() {
+            Some("platt") => {
+                if !points.is_empty() {
+                    return Err("platt takes `a` and `b`, not `point`".to_string());
+                }
+                let (Some(a), Some(b)) = (a, b) else {
+                    return Err("platt needs both `a` and `b`".to_string());
+                };
+                Ok(Calibration::Platt { a, b })
+            }
+            Some("isotonic") => {
+                if a.is_some() || b.is_some() {
+                    return Err("isotonic takes `point`, not `a` or `b`".to_string());
+                }
+                if points.len() < 2 {
+                    return Err("isotonic needs at least two points".to_string());
+                }
+                for pair in points.windows(2) {
+                    let ((s0, c0), (s1, c1)) = (pair[0], pair[1]);
+                    if s1 <= s0 {
+                        return Err(format!("point scores must increase: {s1} after {s0}"));
+                    }
+                    if c1 < c0 {
+                        return Err(format!(
+                            "calibrated values must not decrease: {c1} after {c0}"
+                        ));
+                    }
+                }
+                let (scores, calibrated) = points.into_iter().unzip();
+                Ok(Calibration::Isotonic { scores, calibrated })
+            }
+            Some(other) => Err(format!("unknown method '{other}'")),
+            None => Err("missing key 'method'".to_string()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn apply(&self, score: f32) -> f32 {
+        match self {
+            Calibration::Platt { a, b } => 1.0 / (1.0 + (a * score + b).exp()),
+            Calibration::Isotonic { scores, calibrated } => {
+                if score.is_nan() {
+                    return score;
+                }
+                // First point above `score`; the ends clamp.
+                let upper = scores.partition_point(|&s| s <= score);
+                if upper == 0 {
+                    return calibrated[0];
+                }
+                if upper == scores.len() {
+                    return calibrated[upper - 1];
+                }
+                let (s0, s1) = (scores[upper - 1], scores[upper]);
+                let (c0, c1) = (calibrated[upper - 1], calibrated[upper]);
+                c0 + (c1 - c0) * (score - s0) / (s1 - s0)
+            }
+        }
+    }
+
+    /// Calibrate `scores` in place.
+    pub fn apply_all(&self, scores: &mut [f32]) {
+        for score in scores {
+            *score = self.apply(*score);
+        }
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Calibration::Platt { a, b } => write!(f, "platt a={a} b={b}"),
+            Calibration::Isotonic { scores, .. } => write!(f, "isotonic points={}", scores.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Calibration;
+
+    #[test]
+    fn parse_needs_a_known_method_and_its_parameters() {
+        assert_eq!(
+            Calibration::parse("method = platt # fitted\na = -2\nb = 0.5\n").unwrap(),
+            Calibration::Platt { a: -2.0, b: 0.5 }
+        );
+        assert_eq!(
+            Calibration::parse("method = isotonic\npoint = 0, 0.1\npoint = 1, 0.9\n").unwrap(),
+            Calibration::Isotonic {
+                scores: vec![0.0, 1.0],
+                calibrated: vec![0.1, 0.9]
+            }
+        );
+
+        assert!(Calibration::parse("a = 1\nb = 1\n").is_err());
+        assert!(Calibration::parse("method = beta\n").is_err());
+        assert!(Calibration::parse("method = platt\na = 1\n").is_err());
+        assert!(Calibration::parse("method = platt\na = 1\nb = NaN\n").is_err());
+        assert!(Calibration::parse("method = isotonic\npoint = 0, 0\n").is_err());
+        assert!(Calibration::parse("method = isotonic\npoint = 1, 0\npoint = 0, 1\n").is_err());
+        assert!(Calibration::parse("method = isotonic\npoint = 0, 1\npoint = 1, 0\n").is_err());
+        assert!(
+            Calibration::parse("method = isotonic\npoint = 0, 0\npoint = 1, 1\na = 1\n").is_err()
+        );
+    }
+
+    #[test]
+    fn apply_maps_scores_through_the_calibration() {
+        let platt = Calibration::Platt { a: -1.0, b: 0.0 };
+        assert_eq!(platt.apply(0.0), 0.5);
+        assert!(platt.apply(10.0) > 0.99);
+        assert!(platt.apply(f32::NAN).is_nan());
+
+        let isotonic = Calibration::parse(
+            "method = isotonic\npoint = 0, 0.25\npoint = 1, 0.5\npoint = 3, 1\n",
+        )
+        .unwrap();
+        let mut scores = [-5.0, 0.0, 0.5, 1.0, 2.0, 3.0, 7.0, f32::NAN];
+        isotonic.apply_all(&mut scores);
+        assert_eq!(&scores[..7], &[0.25, 0.25, 0.375, 0.5, 0.75, 1.0, 1.0]);
+        assert!(scores[7].is_nan());
+    }
+}
@@ -0,0 +1,281 @@
+//! Canary check run against a freshly loaded model before it serves traffic.
+//!
+//! The canary file holds one `input = ...` line per vector, each `--feature-dim` comma- or
+//! whitespace-separated values, plus the `min` and `max` every output must fall within:
+//!
+//! ```text
+//! input = 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5
+//! min = 0.0
+//! max = 1.0
+//! ```
+//!
+//! The inputs go through the backend as one batch, the same way the inference thread submits
+//! requests, so a model that loads but scores garbage (wrong export, swapped weights, NaNs) is
+//! caught before any IO thread accepts a connection.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::buffer_pool::PoolAllocator;
+use crate::pipeline::InferenceBackend;
+use crate::pipeline::session::BatchPoll;
+
+/// How long the canary batch may take before the model is considered broken.
+const CANARY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canary {
+    /// Vectors of the same width, at least one.
+    inputs: Vec<Vec<f32>>,
+    min: f32,
+    max: f32,
+}
+
+impl Canary {
+    /// Parse a canary of `feature_dim`-wide input vectors.
+    pub fn parse(text: &str, feature_dim: usize) -> Result<Self, String> {
+        let (mut inputs, mut min, mut max) = (Vec::new(), None, None);
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", line_no + 1))?;
+            let parse_value = |value: &str| {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("line {}: invalid value '{value}'", line_no + 1))
+            };
+            match key.trim() {
+                "input" => {
+                    let values = value
+                        .split(|c: char| c.is_whitespace() || c == ',')
+                        .filter(|value| !value.is_empty())
+                        .map(parse_value)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if values.len() != feature_dim {
+                        return Err(format!(
+                            "line {}: expected {feature_dim} input values, found {}",
+                            line_no + 1,
+                            values.len()
+                        ));
+                    }
+                    inputs.push(values);
+                }
+                "min" => min = Some(parse_value(value.trim())?),
+                "max" => max = Some(parse_value(value.trim())?),
+                other => return Err(format!("line {}: unknown key '{other}'", line_no + 1)),
+            }
+        }
+        let (Some(min), Some(max)) = (min, max) else {
+            return Err("expected both `min` and `max`".to_string());
+        };
+        if inputs.is_empty() {
+            return Err("expected at least one `input`".to_string());
+        }
+        if min > max {
+            return Err(format!("min {min} is above max {max}"));
+        }
+        Ok(Self { inputs, min, max })
+    }
+
+    pub fn load(path: &Path, feature_dim: usize) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        Self::parse(&text, feature_dim).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Features across all the canary inputs.
+    pub fn input_len(&self) -> usize {
+        self.inputs.len() * self.inputs[0].len()
+    }
+
+    /// Score the canary inputs on `backend` and check every output is within `min..=max`.
+    ///
+    /// Must run before the backend is handed to the inference thread: it takes a session and
+    /// returns it once the batch completes.
+    pub fn run<B: InferenceBackend>(
+        &self,
+        backend: &mut B,
+        allocator: &mut PoolAllocator,
+    ) -> CanaryOutcome {
+        match self.score(backend, allocator) {
+            Ok(outputs) => self.check(outputs),
+            Err(reason) => CanaryOutcome::Failed { reason },
+        }
+    }
+
+    fn score<B: InferenceBackend>(
+        &self,
+        backend: &mut B,
+        allocator: &mut PoolAllocator,
+    ) -> Result<Vec<f32>, String> {
+        if !backend.try_acquire() {
+            return Err("no free session".to_string());
+        }
+        let feature_dim = self.inputs[0].len();
+        let mut input = allocator
+            .alloc(self.inputs.len() * feature_dim)
+            .map_err(|e| format!("input allocation failed: {e:?}"))?;
+        for (dst, vector) in input
+            .as_mut_slice()
+            .chunks_exact_mut(feature_dim)
+            .zip(&self.inputs)
+        {
+            dst.copy_from_slice(vector);
+        }
+        let input = input.freeze();
+        let batch = backend.submit_batch(input.as_slice().as_ptr(), self.inputs.len());
+        let deadline = Instant::now() + CANARY_TIMEOUT;
+        let result = loop {
+            match batch.completion.poll() {
+                BatchPoll::Ready => {
+                    // SAFETY: the backend wrote `output_len` outputs before marking the batch
+                    // ready, and the buffer stays valid until `batch` is dropped below.
+                    let outputs =
+                        unsafe { std::slice::from_raw_parts(batch.output_ptr, batch.output_len) };
+                    break Ok(outputs[..self.inputs.len().min(outputs.len())].to_vec());
+                }
+                BatchPoll::Failed => break Err("backend failed the batch".to_string()),
+                BatchPoll::Pending if Instant::now() >= deadline => {
+                    // The backend may still write into the session buffer, so keep it.
+                    std::mem::forget(batch);
+                    std::mem::forget(input);
+                    return Err(format!("no result within {CANARY_TIMEOUT:?}"));
+                }
+                BatchPoll::Pending => std::thread::sleep(Duration::from_millis(1)),
+            }
+        };
+        let session_available = Arc::clone(&batch.session_available);
+        drop(batch);
+        drop(input);
+        session_available.store(true, Ordering::Release);
+        result
+    }
+
+    fn check(&self, outputs: Vec<f32>) -> CanaryOutcome {
+        if outputs.len() != self.inputs.len() {
+            return CanaryOutcome::Failed {
+                reason: format!("{} outputs for {} inputs", outputs.len(), self.inputs.len()),
+            };
+        }
+        let range = self.min..=self.max;
+        match outputs.iter().position(|output| !range.contains(output)) {
+            Some(i) => CanaryOutcome::Failed {
+                reason: format!(
+                    "output {i} is {}, outside {}..={}",
+                    outputs[i], self.min, self.max
+                ),
+            },
+            None => CanaryOutcome::Passed { outputs },
+        }
+    }
+}
+
+/// Result of the canary run, reported by the admin `canary` and `health` commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanaryOutcome {
+    Passed { outputs: Vec<f32> },
+    Failed { reason: String },
+}
+
+impl CanaryOutcome {
+    pub fn passed(&self) -> bool {
+        matches!(self, CanaryOutcome::Passed { .. })
+    }
+}
+
+impl fmt::Display for CanaryOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanaryOutcome::Passed { outputs } => {
+                write!(f, "passed outputs=")?;
+                for (i, output) in outputs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{output}")?;
+                }
+                Ok(())
+            }
+            CanaryOutcome::Failed { reason } => write!(f, "failed: {reason}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canary, CanaryOutcome};
+    use crate::buffer_pool::BufferPool;
+    use crate::constants::FEATURE_DIM;
+    use crate::engine::tests::SumBackend;
+    use crate::pipeline::InferenceBackend;
+
+    fn input_line(value: f32) -> String {
+        let values = vec![value.to_string(); FEATURE_DIM];
+        format!("input = {}\n", values.join(", "))
+    }
+
+    #[test]
+    fn parse_needs_full_vectors_and_a_range() {
+        let text = format!(
+            "{}{}min = 0\nmax = 20 # sums\n",
+            input_line(0.5),
+            input_line(1.0)
+        );
+        let canary = Canary::parse(&text, FEATURE_DIM).unwrap();
+        assert_eq!(canary.inputs.len(), 2);
+        assert_eq!((canary.min, canary.max), (0.0, 20.0));
+
+        assert!(Canary::parse("input = 1, 2\nmin = 0\nmax = 1\n", FEATURE_DIM).is_err());
+        assert!(Canary::parse("input = 1, 2\nmin = 0\nmax = 1\n", 2).is_ok());
+        assert!(Canary::parse(&format!("{}min = 0\n", input_line(1.0)), FEATURE_DIM).is_err());
+        assert!(Canary::parse("min = 0\nmax = 1\n", FEATURE_DIM).is_err());
+        assert!(
+            Canary::parse(
+                &format!("{}min = 2\nmax = 1\n", input_line(1.0)),
+                FEATURE_DIM
+            )
+            .is_err()
+        );
+        assert!(
+            Canary::parse(
+                &format!("{}min = NaN\nmax = 1\n", input_line(1.0)),
+                FEATURE_DIM
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn run_checks_every_output_and_returns_the_session() {
+        let mut backend = SumBackend::new();
+        let mut allocator = BufferPool::leak_new(4 * FEATURE_DIM).allocator();
+        let inputs = format!("{}{}", input_line(0.5), input_line(1.0));
+
+        let canary = Canary::parse(&format!("{inputs}min = 0\nmax = 20\n"), FEATURE_DIM).unwrap();
+        let outcome = canary.run(&mut backend, &mut allocator);
+        assert_eq!(
+            outcome,
+            CanaryOutcome::Passed {
+                outputs: vec![FEATURE_DIM as f32 * 0.5, FEATURE_DIM as f32]
+            }
+        );
+        assert!(backend.is_available(), "session returned after the canary");
+
+        let canary = Canary::parse(&format!("{inputs}min = 0\nmax = 10\n"), FEATURE_DIM).unwrap();
+        let outcome = canary.run(&mut backend, &mut allocator);
+        assert!(!outcome.passed());
+        assert_eq!(
+            outcome.to_string(),
+            format!("failed: output 1 is {}, outside 0..=10", FEATURE_DIM)
+        );
+    }
+}
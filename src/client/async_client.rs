@@ -0,0 +1,288 @@
+//! Tokio client: a pool of connections that many tasks share.
+//!
+//! Each connection runs two tasks. The writer takes encoded requests off a channel and writes
+//! them, coalescing those already waiting into one write; the reader reads the responses and
+//! hands each to the task awaiting it. A connection answers its requests in order, so the writer
+//! queues each request's reply to the reader before writing it, and no more than
+//! [`DEFAULT_PIPELINE_DEPTH`] requests are unanswered on a connection; callers wait for room
+//! beyond that. A connection that fails answers every request waiting on it with
+//! [`ClientError::Closed`] or the error itself, and is skipped from then on.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    ClientError, DEFAULT_PIPELINE_DEPTH, decode_frame, encode_request, frame_len, num_vectors,
+};
+use crate::constants::MAX_FEATURE_DIM;
+use crate::protocol::{RESPONSE_HEADER_BYTES, RequestFraming};
+
+type Reply = oneshot::Sender<Result<Vec<f32>, ClientError>>;
+
+/// An encoded request on its way to a connection's writer.
+struct Request {
+    bytes: Vec<u8>,
+    num_vectors: usize,
+    reply: Reply,
+}
+
+/// A written request on its way to a connection's reader.
+struct Awaiting {
+    num_vectors: usize,
+    reply: Reply,
+}
+
+/// Connections to a server, shared by every clone.
+#[derive(Clone)]
+pub struct AsyncClient {
+    connections: Arc<[mpsc::Sender<Request>]>,
+    next: Arc<AtomicUsize>,
+    framing: RequestFraming,
+}
+
+impl AsyncClient {
+    /// Open `connections` connections to the server at `addr`, for vectors of
+    /// [`FEATURE_DIM`](crate::constants::FEATURE_DIM) features. Must be called within a Tokio
+    /// runtime, which runs the connections' tasks.
+    pub async fn connect(
+        addr: impl ToSocketAddrs,
+        connections: usize,
+    ) -> Result<Self, ClientError> {
+        assert!(connections > 0, "connections must be > 0");
+        let addrs: Vec<_> = tokio::net::lookup_host(addr).await?.collect();
+        let mut senders = Vec::with_capacity(connections);
+        for _ in 0..connections {
+            let stream = TcpStream::connect(&addrs[..]).await?;
+            stream.set_nodelay(true)?;
+            senders.push(spawn_connection(stream));
+        }
+        Ok(Self {
+            connections: senders.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+            framing: RequestFraming::PLAIN,
+        })
+    }
+
+    /// Features per vector, which must match the server's `--feature-dim`.
+    pub fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+        assert!(
+            (1..=MAX_FEATURE_DIM).contains(&feature_dim),
+            "feature_dim must be in 1..={MAX_FEATURE_DIM}"
+        );
+        self.framing = self.framing.with_feature_dim(feature_dim);
+        self
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.framing.feature_dim
+    }
+
+    /// Connections that have not failed.
+    pub fn live_connections(&self) -> usize {
+        self.connections.iter().filter(|c| !c.is_closed()).count()
+    }
+
+    /// Score `features`, one or more vectors back to back, returning a score per vector. Sent on
+    /// the next live connection in turn, pipelined behind other tasks' requests.
+    pub async fn infer(&self, features: &[f32]) -> Result<Vec<f32>, ClientError> {
+        let num_vectors = num_vectors(self.framing, features)?;
+        let mut bytes = Vec::with_capacity(self.framing.request_size(num_vectors));
+        encode_request(self.framing, features, num_vectors, &mut bytes);
+        let (reply, outcome) = oneshot::channel();
+        let request = Request {
+            bytes,
+            num_vectors,
+            reply,
+        };
+        self.next_connection()?
+            .send(request)
+            .await
+            .map_err(|_| ClientError::Closed)?;
+        outcome.await.unwrap_or(Err(ClientError::Closed))
+    }
+
+    fn next_connection(&self) -> Result<&mpsc::Sender<Request>, ClientError> {
+        let count = self.connections.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..count)
+            .map(|i| &self.connections[(start + i) % count])
+            .find(|connection| !connection.is_closed())
+            .ok_or(ClientError::Closed)
+    }
+}
+
+/// Start the writer and reader tasks of a connection, returning where to send its requests.
+fn spawn_connection(stream: TcpStream) -> mpsc::Sender<Request> {
+    let (reader, writer) = stream.into_split();
+    let (requests, requests_rx) = mpsc::channel(DEFAULT_PIPELINE_DEPTH);
+    let (awaiting, awaiting_rx) = mpsc::channel(DEFAULT_PIPELINE_DEPTH);
+    tokio::spawn(write_requests(writer, requests_rx, awaiting));
+    tokio::spawn(read_responses(BufReader::new(reader), awaiting_rx));
+    requests
+}
+
+/// Write requests as they arrive until the client is dropped or the connection fails.
+async fn write_requests(
+    mut writer: OwnedWriteHalf,
+    mut requests: mpsc::Receiver<Request>,
+    awaiting: mpsc::Sender<Awaiting>,
+) {
+    let mut buf = Vec::new();
+    // A request taken off the channel while the pipeline was full, written first next time.
+    let mut held = None;
+    loop {
+        let first = match held.take() {
+            Some(request) => request,
+            None => match requests.recv().await {
+                Some(request) => request,
+                None => return,
+            },
+        };
+        // Everything before this request is written, so the reader frees room as it is answered.
+        let Ok(permit) = awaiting.reserve().await else {
+            return;
+        };
+        buf.clear();
+        queue_request(first, permit, &mut buf);
+        while let Ok(request) = requests.try_recv() {
+            match awaiting.try_reserve() {
+                Ok(permit) => queue_request(request, permit, &mut buf),
+                Err(_) => {
+                    held = Some(request);
+                    break;
+                }
+            }
+        }
+        if writer.write_all(&buf).await.is_err() {
+            // The reader fails every request already queued to it once its read fails too.
+            return;
+        }
+    }
+}
+
+fn queue_request(request: Request, permit: mpsc::Permit<'_, Awaiting>, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&request.bytes);
+    permit.send(Awaiting {
+        num_vectors: request.num_vectors,
+        reply: request.reply,
+    });
+}
+
+/// Answer written requests in order until the writer stops or the connection fails.
+async fn read_responses(
+    mut reader: BufReader<OwnedReadHalf>,
+    mut awaiting: mpsc::Receiver<Awaiting>,
+) {
+    let mut frame = Vec::new();
+    while let Some(request) = awaiting.recv().await {
+        let outcome = read_frame(&mut reader, &mut frame, request.num_vectors).await;
+        let failed = matches!(
+            outcome,
+            Err(ClientError::Io(_) | ClientError::Closed | ClientError::UnexpectedResponse(_))
+        );
+        let _ = request.reply.send(outcome);
+        if failed {
+            // Dropping `awaiting` answers the requests behind this one, and stops the writer.
+            return;
+        }
+    }
+}
+
+async fn read_frame(
+    reader: &mut BufReader<OwnedReadHalf>,
+    frame: &mut Vec<u8>,
+    num_vectors: usize,
+) -> Result<Vec<f32>, ClientError> {
+    frame.clear();
+    let mut len = RESPONSE_HEADER_BYTES;
+    while frame.len() < len {
+        let start = frame.len();
+        frame.resize(len, 0);
+        reader.read_exact(&mut frame[start..]).await?;
+        len = frame_len(frame);
+    }
+    decode_frame(frame, num_vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use super::AsyncClient;
+    use crate::client::ClientError;
+    use crate::client::tests::{serve_connections, vectors};
+    use crate::protocol::OverloadReason;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn tasks_share_a_pool_of_pipelined_connections() {
+        let per_connection = 100;
+        let (addr, server) = serve_connections(2, per_connection);
+        block_on(async move {
+            let client = AsyncClient::connect(addr, 2).await.unwrap();
+            let tasks: Vec<_> = (0..2 * per_connection)
+                .map(|i| {
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let first = if i % 7 == 3 { -1.0 } else { i as f32 };
+                        (i, client.infer(&vectors(&[first; 3])).await)
+                    })
+                })
+                .collect();
+            for task in tasks {
+                match task.await.unwrap() {
+                    (i, Ok(scores)) => assert_eq!(scores, vec![i as f32; 3]),
+                    (
+                        i,
+                        Err(ClientError::Overloaded {
+                            reason,
+                            retry_after,
+                        }),
+                    ) => {
+                        assert_eq!(i % 7, 3);
+                        assert_eq!(reason, Some(OverloadReason::RingFull));
+                        assert_eq!(retry_after.as_millis(), 25);
+                    }
+                    (i, Err(e)) => panic!("request {i}: {e}"),
+                }
+            }
+            assert_eq!(client.live_connections(), 2);
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn a_closed_connection_fails_its_requests_and_is_skipped() {
+        let (addr, server) = serve_connections(1, 1);
+        block_on(async move {
+            let client = AsyncClient::connect(addr, 1).await.unwrap();
+            assert_eq!(client.infer(&vectors(&[2.0])).await.unwrap(), vec![2.0]);
+            assert!(matches!(
+                client.infer(&vectors(&[2.0])).await,
+                Err(ClientError::Closed)
+            ));
+            assert!(matches!(
+                client.infer(&vectors(&[2.0])).await,
+                Err(ClientError::Closed)
+            ));
+            assert_eq!(client.live_connections(), 0);
+            assert!(matches!(
+                client.infer(&[1.0]).await,
+                Err(ClientError::FeatureCount { len: 1, .. })
+            ));
+        });
+        server.join().unwrap();
+    }
+}
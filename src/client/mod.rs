@@ -0,0 +1,483 @@
+//! Clients for the wire protocol.
+//!
+//! [`Client`] is a blocking client; with `--features tokio-client`, [`AsyncClient`] serves Tokio
+//! tasks from a pool of connections. Both frame requests and responses with the codec here.
+//!
+//! [`Client`] holds one TCP connection to a server and frames requests and responses with
+//! [`protocol`], so applications need not. [`Client::infer`] sends one request and waits for its
+//! scores; [`Client::infer_batch`] pipelines many, keeping up to a pipeline depth of them in
+//! flight, and relies on the protocol's one-response-per-request ordering to match responses to
+//! requests.
+//!
+//! Requests use plain framing: no `--request-ids`, sequence or length prefixes, vector status
+//! trailers or half-precision features, so the server must run without those options.
+
+#[cfg(feature = "tokio-client")]
+mod async_client;
+
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::byte_order;
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::protocol::{
+    self, OVERLOAD_FRAME_BYTES, OverloadReason, PARSE_ERROR_FRAME_BYTES, ParseError,
+    RESPONSE_HEADER_BYTES, RequestFraming,
+};
+use crate::wire_layout;
+
+#[cfg(feature = "tokio-client")]
+pub use async_client::AsyncClient;
+
+/// Requests [`Client::infer_batch`] keeps in flight by default, and [`AsyncClient`] on each of
+/// its connections.
+pub const DEFAULT_PIPELINE_DEPTH: usize = 64;
+
+/// Why a request got no scores.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Connecting, reading or writing failed; the connection is unusable.
+    Io(io::Error),
+    /// The server closed the connection before answering every request sent on it.
+    Closed,
+    /// The feature count is not 1..=`MAX_VECTORS_PER_REQUEST` whole vectors of the client's
+    /// `feature_dim`. Nothing was sent.
+    FeatureCount { len: usize, feature_dim: usize },
+    /// The server rejected the request unrun; send it again after `retry_after`. `reason` is
+    /// `None` for a reason this build does not know.
+    Overloaded {
+        reason: Option<OverloadReason>,
+        retry_after: Duration,
+    },
+    /// The server could not parse the request, say because of a feature it does not accept.
+    /// Whether the connection stays open depends on the server's `--malformed-requests`.
+    Rejected(ParseError),
+    /// The server answered with a frame that does not fit the request, most likely because it
+    /// runs with framing options this client does not speak.
+    UnexpectedResponse(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "connection failed: {e}"),
+            ClientError::Closed => write!(f, "server closed the connection"),
+            ClientError::FeatureCount { len, feature_dim } => write!(
+                f,
+                "{len} features is not 1..={MAX_VECTORS_PER_REQUEST} vectors of {feature_dim}"
+            ),
+            ClientError::Overloaded {
+                reason,
+                retry_after,
+            } => write!(
+                f,
+                "server overloaded ({}), retry after {retry_after:?}",
+                reason.map_or("unknown", OverloadReason::as_str)
+            ),
+            ClientError::Rejected(e) => write!(f, "server rejected the request: {e}"),
+            ClientError::UnexpectedResponse(what) => write!(f, "unexpected response: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            ClientError::Rejected(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        if matches!(
+            e.kind(),
+            io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::BrokenPipe
+        ) {
+            ClientError::Closed
+        } else {
+            ClientError::Io(e)
+        }
+    }
+}
+
+impl ClientError {
+    /// Whether the connection is still usable after this error, so later requests may be sent
+    /// on it.
+    pub fn is_request_error(&self) -> bool {
+        matches!(
+            self,
+            ClientError::FeatureCount { .. } | ClientError::Overloaded { .. }
+        )
+    }
+}
+
+/// One connection to a server.
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    framing: RequestFraming,
+    pipeline_depth: usize,
+    request_buf: Vec<u8>,
+    response_buf: Vec<u8>,
+}
+
+impl Client {
+    /// Connect to the server at `addr`, for vectors of
+    /// [`FEATURE_DIM`](crate::constants::FEATURE_DIM) features.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, ClientError> {
+        let writer = TcpStream::connect(addr)?;
+        writer.set_nodelay(true)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self {
+            reader,
+            writer,
+            framing: RequestFraming::PLAIN,
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            request_buf: Vec::new(),
+            response_buf: Vec::new(),
+        })
+    }
+
+    /// Features per vector, which must match the server's `--feature-dim`.
+    pub fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+        assert!(
+            (1..=MAX_FEATURE_DIM).contains(&feature_dim),
+            "feature_dim must be in 1..={MAX_FEATURE_DIM}"
+        );
+        self.framing = self.framing.with_feature_dim(feature_dim);
+        self
+    }
+
+    /// Requests [`Self::infer_batch`] sends before it waits for the first of their responses.
+    pub fn with_pipeline_depth(mut self, pipeline_depth: usize) -> Self {
+        assert!(pipeline_depth > 0, "pipeline_depth must be > 0");
+        self.pipeline_depth = pipeline_depth;
+        self
+    }
+
+    /// Fail reads and writes that block for longer than `timeout`, or never with `None`.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Result<Self, ClientError> {
+        self.writer.set_read_timeout(timeout)?;
+        self.writer.set_write_timeout(timeout)?;
+        Ok(self)
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.framing.feature_dim
+    }
+
+    /// Score `features`, one or more vectors back to back, returning a score per vector.
+    pub fn infer(&mut self, features: &[f32]) -> Result<Vec<f32>, ClientError> {
+        let num_vectors = num_vectors(self.framing, features)?;
+        self.request_buf.clear();
+        encode_request(self.framing, features, num_vectors, &mut self.request_buf);
+        self.writer.write_all(&self.request_buf)?;
+        self.read_response(num_vectors)
+    }
+
+    /// Score each of `requests` as [`Self::infer`] would, pipelined on the connection. Each
+    /// request's outcome is returned in order; overloaded requests fail on their own and leave
+    /// the rest to be answered. Fails as a whole, with nothing sent, if any request has the
+    /// wrong feature count, and on any error that leaves the connection unusable.
+    pub fn infer_batch(
+        &mut self,
+        requests: &[&[f32]],
+    ) -> Result<Vec<Result<Vec<f32>, ClientError>>, ClientError> {
+        let num_vectors = requests
+            .iter()
+            .map(|features| num_vectors(self.framing, features))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut outcomes = Vec::with_capacity(requests.len());
+        let mut sent = 0;
+        while outcomes.len() < requests.len() {
+            self.request_buf.clear();
+            while sent < requests.len() && sent - outcomes.len() < self.pipeline_depth {
+                encode_request(
+                    self.framing,
+                    requests[sent],
+                    num_vectors[sent],
+                    &mut self.request_buf,
+                );
+                sent += 1;
+            }
+            if !self.request_buf.is_empty() {
+                self.writer.write_all(&self.request_buf)?;
+            }
+            match self.read_response(num_vectors[outcomes.len()]) {
+                Err(e) if !e.is_request_error() => return Err(e),
+                outcome => outcomes.push(outcome),
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Read the next frame off the connection, the answer to a request of `num_vectors`.
+    fn read_response(&mut self, num_vectors: usize) -> Result<Vec<f32>, ClientError> {
+        self.response_buf.clear();
+        let mut len = RESPONSE_HEADER_BYTES;
+        while self.response_buf.len() < len {
+            let start = self.response_buf.len();
+            self.response_buf.resize(len, 0);
+            self.reader.read_exact(&mut self.response_buf[start..])?;
+            len = frame_len(&self.response_buf);
+        }
+        decode_frame(&self.response_buf, num_vectors)
+    }
+}
+
+/// Vectors in `features`, if they are 1..=`MAX_VECTORS_PER_REQUEST` whole vectors framed as
+/// `framing`.
+fn num_vectors(framing: RequestFraming, features: &[f32]) -> Result<usize, ClientError> {
+    let feature_dim = framing.feature_dim;
+    let num_vectors = features.len() / feature_dim;
+    if !features.len().is_multiple_of(feature_dim)
+        || !(1..=MAX_VECTORS_PER_REQUEST).contains(&num_vectors)
+    {
+        return Err(ClientError::FeatureCount {
+            len: features.len(),
+            feature_dim,
+        });
+    }
+    Ok(num_vectors)
+}
+
+/// Append a request of `num_vectors` vectors carrying `features` to `buf`.
+fn encode_request(
+    framing: RequestFraming,
+    features: &[f32],
+    num_vectors: usize,
+    buf: &mut Vec<u8>,
+) {
+    let start = buf.len();
+    buf.resize(start + framing.request_size(num_vectors), 0);
+    let frame = &mut buf[start..];
+    wire_layout::REQUEST_NUM_VECTORS.write_u32(frame, num_vectors as u32);
+    byte_order::write_f32s_le(features, &mut frame[framing.header_bytes()..]);
+}
+
+/// Bytes of the response frame starting with `head`, or, until its first two bytes tell it
+/// from an overload or parse error frame, of those two. A frame is read by reading on to this
+/// length until the frame is as long as it says.
+fn frame_len(head: &[u8]) -> usize {
+    // A response's vector count, or the zero marker of an overload or parse error frame, then
+    // the overload reason or parse error kind.
+    let answered = wire_layout::RESPONSE_NUM_VECTORS.read_u8(head) as usize;
+    if answered > 0 {
+        protocol::response_size(answered)
+    } else if head.len() < 2 {
+        2
+    } else if protocol::is_parse_error(head) {
+        PARSE_ERROR_FRAME_BYTES
+    } else {
+        OVERLOAD_FRAME_BYTES
+    }
+}
+
+/// The scores in `frame`, a whole frame answering a request of `num_vectors`, or why it has none.
+fn decode_frame(frame: &[u8], num_vectors: usize) -> Result<Vec<f32>, ClientError> {
+    let answered = wire_layout::RESPONSE_NUM_VECTORS.read_u8(frame) as usize;
+    if answered > 0 {
+        if answered != num_vectors {
+            return Err(ClientError::UnexpectedResponse(format!(
+                "{answered} scores for a request of {num_vectors} vectors"
+            )));
+        }
+        return Ok(protocol::decode_response(frame));
+    }
+    if protocol::is_parse_error(frame) {
+        return Err(protocol::decode_parse_error(frame).map_or_else(
+            || ClientError::UnexpectedResponse("parse error for an unknown field".into()),
+            ClientError::Rejected,
+        ));
+    }
+    let (reason, retry_after_ms) = protocol::decode_overload(frame)
+        .expect("a zero marker without the parse error kind is an overload frame");
+    Err(ClientError::Overloaded {
+        reason,
+        retry_after: Duration::from_millis(retry_after_ms as u64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+
+    use super::{Client, ClientError};
+    use crate::constants::FEATURE_DIM;
+    use crate::protocol::{
+        self, OVERLOAD_FRAME_BYTES, OverloadReason, PARSE_ERROR_FRAME_BYTES, ParseError,
+        ParseResult, RequestField, RequestFraming,
+    };
+
+    /// A server for one connection that scores each vector with its first feature, answers a
+    /// request whose first feature is negative with an overload, one whose first feature is NaN
+    /// with a parse error, and hangs up after `requests` requests.
+    fn serve(requests: usize) -> (SocketAddr, JoinHandle<()>) {
+        serve_connections(1, requests)
+    }
+
+    /// [`serve`] for `connections` connections, each answered on a thread of its own.
+    pub(super) fn serve_connections(
+        connections: usize,
+        requests: usize,
+    ) -> (SocketAddr, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let answering: Vec<_> = (0..connections)
+                .map(|_| {
+                    let (stream, _) = listener.accept().unwrap();
+                    thread::spawn(move || answer(stream, requests))
+                })
+                .collect();
+            for connection in answering {
+                connection.join().unwrap();
+            }
+        });
+        (addr, server)
+    }
+
+    fn answer(mut stream: TcpStream, requests: usize) {
+        let framing = RequestFraming::PLAIN;
+        let mut buf = Vec::new();
+        let mut answered = 0;
+        while answered < requests {
+            let ParseResult::Complete {
+                num_vectors,
+                bytes_consumed,
+                ..
+            } = protocol::try_parse_request(&buf, framing)
+            else {
+                let mut chunk = [0u8; 4096];
+                let n = stream.read(&mut chunk).unwrap();
+                assert!(n > 0, "client hung up early");
+                buf.extend_from_slice(&chunk[..n]);
+                continue;
+            };
+            let mut features = vec![0f32; num_vectors as usize * FEATURE_DIM];
+            protocol::copy_features(&buf[framing.header_bytes()..bytes_consumed], &mut features);
+            buf.drain(..bytes_consumed);
+            let frame = if features[0].is_nan() {
+                let mut frame = vec![0u8; PARSE_ERROR_FRAME_BYTES];
+                let error = ParseError {
+                    field: RequestField::Features,
+                    value: features[0].to_bits(),
+                    offset: 4,
+                };
+                protocol::encode_parse_error(&error, &mut frame);
+                frame
+            } else if features[0] < 0.0 {
+                let mut frame = vec![0u8; OVERLOAD_FRAME_BYTES];
+                protocol::encode_overload(OverloadReason::RingFull, 25, &mut frame);
+                frame
+            } else {
+                let scores: Vec<f32> = features.chunks(FEATURE_DIM).map(|v| v[0]).collect();
+                let mut frame = vec![0u8; protocol::response_size(scores.len())];
+                protocol::encode_response(&scores, &mut frame);
+                frame
+            };
+            stream.write_all(&frame).unwrap();
+            answered += 1;
+        }
+    }
+
+    pub(super) fn vectors(firsts: &[f32]) -> Vec<f32> {
+        firsts
+            .iter()
+            .flat_map(|&first| {
+                let mut vector = vec![1.0; FEATURE_DIM];
+                vector[0] = first;
+                vector
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scores_each_vector_of_a_request() {
+        let (addr, server) = serve(2);
+        let mut client = Client::connect(addr).unwrap();
+        assert_eq!(client.infer(&vectors(&[0.5])).unwrap(), vec![0.5]);
+        assert_eq!(
+            client.infer(&vectors(&[0.25, 0.75, 2.0])).unwrap(),
+            vec![0.25, 0.75, 2.0]
+        );
+        server.join().unwrap();
+        assert!(matches!(
+            client.infer(&vectors(&[0.5])),
+            Err(ClientError::Closed)
+        ));
+    }
+
+    #[test]
+    fn rejects_a_partial_vector_without_sending_it() {
+        let (addr, server) = serve(1);
+        let mut client = Client::connect(addr).unwrap();
+        let err = client.infer(&[1.0; FEATURE_DIM + 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::FeatureCount {
+                len,
+                feature_dim: FEATURE_DIM
+            } if len == FEATURE_DIM + 1
+        ));
+        assert!(err.is_request_error());
+        assert!(client.infer(&[]).is_err());
+        assert_eq!(client.infer(&vectors(&[3.0])).unwrap(), vec![3.0]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn pipelines_a_batch_and_answers_each_request_in_order() {
+        let count = 200;
+        let (addr, server) = serve(count);
+        let mut client = Client::connect(addr).unwrap().with_pipeline_depth(16);
+        let requests: Vec<Vec<f32>> = (0..count)
+            .map(|i| {
+                let first = if i % 7 == 3 { -1.0 } else { i as f32 };
+                vectors(&[first; 2])
+            })
+            .collect();
+        let refs: Vec<&[f32]> = requests.iter().map(Vec::as_slice).collect();
+
+        let outcomes = client.infer_batch(&refs).unwrap();
+        assert_eq!(outcomes.len(), count);
+        for (i, outcome) in outcomes.into_iter().enumerate() {
+            match outcome {
+                Ok(scores) => assert_eq!(scores, vec![i as f32; 2]),
+                Err(ClientError::Overloaded {
+                    reason,
+                    retry_after,
+                }) => {
+                    assert_eq!(i % 7, 3);
+                    assert_eq!(reason, Some(OverloadReason::RingFull));
+                    assert_eq!(retry_after.as_millis(), 25);
+                }
+                Err(e) => panic!("request {i}: {e}"),
+            }
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn a_parse_error_fails_the_batch() {
+        let (addr, server) = serve(2);
+        let mut client = Client::connect(addr).unwrap();
+        let requests = [vectors(&[1.0]), vectors(&[f32::NAN])];
+        let refs: Vec<&[f32]> = requests.iter().map(Vec::as_slice).collect();
+        match client.infer_batch(&refs) {
+            Err(ClientError::Rejected(error)) => assert_eq!(error.field, RequestField::Features),
+            other => panic!("{other:?}"),
+        }
+        server.join().unwrap();
+    }
+}
@@ -13,3 +13,40 @@ pub fn monotonic_now_ns() -> u64 {
 pub fn elapsed_since_ns(start_ns: u64) -> Duration {
     Duration::from_nanos(monotonic_now_ns().saturating_sub(start_ns))
 }
+
+/// Raw cycle counter for timing sections too short for the monotonic clock: `rdtsc` on x86_64,
+/// monotonic nanoseconds elsewhere. Convert differences with [`ticks_to_ns`].
+#[inline]
+pub fn ticks() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        unsafe { core::arch::x86_64::_rdtsc() }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        monotonic_now_ns()
+    }
+}
+
+fn ns_per_tick() -> f64 {
+    static NS_PER_TICK: OnceLock<f64> = OnceLock::new();
+    *NS_PER_TICK.get_or_init(|| {
+        if !cfg!(target_arch = "x86_64") {
+            return 1.0;
+        }
+        let (start_ticks, start) = (ticks(), Instant::now());
+        std::thread::sleep(Duration::from_millis(10));
+        let elapsed_ticks = ticks().saturating_sub(start_ticks).max(1);
+        start.elapsed().as_nanos() as f64 / elapsed_ticks as f64
+    })
+}
+
+/// Measure the tick rate against the monotonic clock (~10 ms). Call at startup so the first
+/// conversion on a data-plane thread does not pay for it.
+pub fn calibrate_ticks() {
+    ns_per_tick();
+}
+
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    (ticks as f64 * ns_per_tick()) as u64
+}
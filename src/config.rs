@@ -3,7 +3,8 @@
 //! Hardcoded values that are not necessarily shared protocol constants.
 //! Protocol constants (e.g. `FEATURE_DIM`, `MAX_VECTORS_PER_REQUEST`) live in `constants`.
 
-use crate::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::protocol::{BYTES_PER_F32, REQUEST_HEADER_BYTES, REQUEST_ID_BYTES};
 use std::mem::size_of;
 
 /// Packed connection identity reserves 4 bits for ingress shard id.
@@ -12,15 +13,42 @@ pub const MAX_IO_THREADS: usize = 16;
 /// Per-connection read buffer size (bytes).
 pub const READ_BUF_SIZE: usize = 65536;
 
+/// Smallest read `--adaptive-reads` submits, for connections that send a little at a time.
+pub const MIN_READ_SIZE: usize = 4096;
+
+/// Bytes per provided buffer with `--read-buffer-ring`: what one read selecting a buffer can
+/// return.
+pub const PROVIDED_READ_BUF_SIZE: usize = 16384;
+
+/// Connection read buffers each IO thread keeps for reuse with `--read-buffer-ring`.
+pub const SPARE_READ_BUFS: usize = 64;
+
 /// Max concurrent connections per IO thread. Must fit in u16 (conn_id).
 pub const SLAB_CAPACITY: usize = 4096;
 
-/// Size each buffer pool to handle all in-flight requests at max size.
-/// CRITICAL: Pool must be >= request ring capacity * max request size to prevent
-/// wraparound from overwriting unread data. Worst-case sizing (conservative).
-/// See PERFORMANCE.md for right-sizing opportunities.
-pub const BUFFER_POOL_CAPACITY: usize =
-    GPU_REQUEST_RING_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+/// Response queue slots per IO thread: one in-flight response per connection, twice over.
+pub const RESPONSE_QUEUE_SIZE: usize = SLAB_CAPACITY * 2;
+
+/// Requests each IO thread can park while the request ring is full, before the connections
+/// behind them stop reading.
+pub const REQUEST_OVERFLOW_CAPACITY: usize = 64;
+
+/// Longest malformed request `--malformed-requests skip` reads past to keep its connection open.
+/// A longer declared request more likely means the stream lost its framing, so the connection
+/// is closed.
+pub const MAX_SKIPPED_REQUEST_BYTES: usize = 1 << 20;
+
+/// Control events the inference thread can have queued before senders see the channel full.
+pub const CONTROL_CHANNEL_CAPACITY: usize = 1024;
+
+/// Sampled requests queued for the sample writer before further samples are dropped.
+pub const SAMPLE_QUEUE_CAPACITY: usize = 4096;
+
+/// Replay sessions held with `--replay-window`, across all IO threads.
+pub const REPLAY_MAX_SESSIONS: usize = 4096;
+
+/// Fraction of requests sampled once `--sample-sink` is set, unless `--sample-rate` says otherwise.
+pub const DEFAULT_SAMPLE_RATE: f64 = 0.001;
 
 // ---------------------------------------------------------------------------
 // ONNX/CUDA server pipeline
@@ -51,13 +79,19 @@ pub const GPU_REQUEST_RING_SIZE: usize = 4096;
 /// Request ring buffer size for the ONNX pipeline.
 pub const GPU_DISRUPTOR_SIZE: usize = GPU_REQUEST_RING_SIZE;
 
-/// Pinned host buffer pool capacity in f32 units (pass to `BufferPool::from_raw_ptr`).
-/// Byte count for `cuMemAllocHost` = `GPU_BUFFER_POOL_CAPACITY * size_of::<f32>()`.
-pub const GPU_BUFFER_POOL_CAPACITY: usize =
-    GPU_REQUEST_RING_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+/// Pinned host buffer pool capacity in f32 units (pass to `BufferPool::from_raw_ptr`), sized to
+/// handle all in-flight requests at max size with `feature_dim` features per vector.
+/// CRITICAL: Pool must be >= request ring capacity * max request size to prevent
+/// wraparound from overwriting unread data. Worst-case sizing (conservative).
+/// See PERFORMANCE.md for right-sizing opportunities.
+pub const fn gpu_buffer_pool_capacity(feature_dim: usize) -> usize {
+    GPU_REQUEST_RING_SIZE * MAX_VECTORS_PER_REQUEST * feature_dim
+}
 
-/// Byte size of the pinned host buffer pool allocation.
-pub const GPU_BUFFER_POOL_BYTES: usize = GPU_BUFFER_POOL_CAPACITY * size_of::<f32>();
+/// Byte size of the pinned host buffer pool allocation (`cuMemAllocHost`).
+pub const fn gpu_buffer_pool_bytes(feature_dim: usize) -> usize {
+    gpu_buffer_pool_capacity(feature_dim) * size_of::<f32>()
+}
 
 /// Batch queue capacity: +1 absorbs one wrap-induced extra batch per cycle (prevents deadlock).
 pub const BATCH_QUEUE_CAPACITY: usize = SESSION_POOL_SIZE + 1;
@@ -81,6 +115,13 @@ const _: () = assert!(
     "SLAB_CAPACITY must fit in u16 (conn_id)"
 );
 const _: () = assert!(
-    BUFFER_POOL_CAPACITY >= GPU_REQUEST_RING_SIZE * FEATURE_DIM,
-    "buffer pool capacity is too small for disruptor size"
+    PROVIDED_READ_BUF_SIZE <= READ_BUF_SIZE,
+    "a provided read buffer must fit an empty connection read buffer"
+);
+const _: () = assert!(
+    REQUEST_ID_BYTES
+        + REQUEST_HEADER_BYTES
+        + MAX_VECTORS_PER_REQUEST * MAX_FEATURE_DIM * BYTES_PER_F32
+        <= READ_BUF_SIZE,
+    "the largest request must fit a connection's read buffer"
 );
@@ -49,12 +49,101 @@ impl ConnectionRef {
     }
 }
 
+/// Per-connection state in a fixed array indexed by `conn_id`.
+///
+/// Storage is sized once, so connection churn cannot grow it: an entry for a newer generation of
+/// a `conn_id` replaces the older one, and lookups with a stale [`ConnectionRef`] miss. Use this
+/// rather than a map keyed by connection for per-connection state that outlives one event.
+pub struct ConnSlots<T> {
+    slots: Box<[Option<(ConnectionRef, T)>]>,
+    len: usize,
+}
+
+impl<T> ConnSlots<T> {
+    /// Slots for `conn_id`s below `capacity`.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity <= SLAB_CAPACITY,
+            "capacity must be <= SLAB_CAPACITY"
+        );
+        Self {
+            slots: (0..capacity).map(|_| None).collect(),
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Store `value` for `conn`, dropping any entry left by an earlier generation of its
+    /// `conn_id`. Returns the value `conn` itself held before.
+    pub fn insert(&mut self, conn: ConnectionRef, value: T) -> Option<T> {
+        let slot = &mut self.slots[conn.conn_id as usize];
+        match slot.replace((conn, value)) {
+            Some((previous, value)) if previous == conn => Some(value),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    pub fn contains(&self, conn: ConnectionRef) -> bool {
+        self.get(conn).is_some()
+    }
+
+    pub fn get(&self, conn: ConnectionRef) -> Option<&T> {
+        match self.slots.get(conn.conn_id as usize)? {
+            Some((held, value)) if *held == conn => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, conn: ConnectionRef) -> Option<&mut T> {
+        match self.slots.get_mut(conn.conn_id as usize)? {
+            Some((held, value)) if *held == conn => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, conn: ConnectionRef) -> Option<T> {
+        let slot = self.slots.get_mut(conn.conn_id as usize)?;
+        if slot.as_ref().is_some_and(|(held, _)| *held == conn) {
+            self.len -= 1;
+            slot.take().map(|(_, value)| value)
+        } else {
+            None
+        }
+    }
+
+    /// Remove every entry, in `conn_id` order.
+    pub fn drain(&mut self) -> impl Iterator<Item = (ConnectionRef, T)> + '_ {
+        let slots = if self.len == 0 {
+            &mut self.slots[..0]
+        } else {
+            &mut self.slots[..]
+        };
+        self.len = 0;
+        slots.iter_mut().filter_map(Option::take)
+    }
+}
+
 const _: () = assert!(MAX_IO_THREADS <= (1 << SHARD_BITS) as usize);
 const _: () = assert!(SLAB_CAPACITY <= u16::MAX as usize);
 
 #[cfg(test)]
 mod tests {
-    use super::ConnectionRef;
+    use super::{ConnSlots, ConnectionRef};
 
     #[test]
     fn round_trips_packed_identity() {
@@ -64,4 +153,31 @@ mod tests {
         assert_eq!(conn.generation(), 0x456);
         assert_eq!(ConnectionRef::from_u32(conn.as_u32()), conn);
     }
+
+    #[test]
+    fn conn_slots_stay_bounded_under_conn_id_churn() {
+        let mut slots = ConnSlots::new(4);
+        for generation in 1..=1000u16 {
+            for conn_id in 0..4 {
+                let conn = ConnectionRef::new(1, conn_id, generation + 1);
+                assert_eq!(slots.insert(conn, generation), None);
+            }
+        }
+        assert_eq!(slots.len(), 4, "newer generations replace older entries");
+        assert_eq!(slots.capacity(), 4);
+
+        let stale = ConnectionRef::new(1, 2, 1);
+        let live = ConnectionRef::new(1, 2, 1001);
+        assert_eq!(slots.get(stale), None);
+        assert_eq!(slots.remove(stale), None);
+        assert_eq!(slots.get(live), Some(&1000));
+        assert_eq!(slots.insert(live, 7), Some(1000));
+        assert_eq!(slots.remove(live), Some(7));
+        assert!(!slots.contains(live));
+
+        let drained: Vec<u16> = slots.drain().map(|(conn, _)| conn.conn_id).collect();
+        assert_eq!(drained, [0, 1, 3]);
+        assert!(slots.is_empty());
+        assert_eq!(slots.drain().count(), 0);
+    }
 }
@@ -1,4 +1,8 @@
+/// Features per vector unless the server or client is given `--feature-dim`.
 pub const FEATURE_DIM: usize = 16;
+/// Largest `--feature-dim`. Fixed-size per-feature state is sized for it, and the largest request
+/// at this width still fits a connection's read buffer.
+pub const MAX_FEATURE_DIM: usize = 255;
 pub const MAX_VECTORS_PER_REQUEST: usize = 64;
 
 const _: () = assert!(
@@ -0,0 +1,325 @@
+//! Embedding lookup for categorical ids sent in place of features.
+//!
+//! With `--embeddings FILE --embedding-ids N`, the last `N` values of every request vector are
+//! little-endian `u32` ids rather than `f32` features. While copying a request into the pool,
+//! the IO thread keeps the vector's dense features and appends each id's row of the table, so
+//! the model sees `feature_dim - N + N * dim` features and clients never send the embeddings
+//! themselves. An id past the end of the table gets a row of zeros and is counted. Ids still go
+//! through the non-finite check as `f32` bits, so ids from `0x7f80_0000` up read as NaN or
+//! infinite there.
+//!
+//! The table file is mapped read-only rather than read, so a large table costs page cache, not
+//! heap, and is shared with any other process mapping it. It is a 16-byte header, the magic
+//! `DREMBED1` then `rows` and `dim` as little-endian `u32`s, followed by `rows * dim`
+//! little-endian `f32`s, row by row. A config reload maps the file again and swaps the new table
+//! in; each IO thread picks it up before its next parse, and batches already in the pool keep
+//! the rows they were built with.
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::byte_order;
+use crate::metrics;
+use crate::protocol::BYTES_PER_F32;
+
+const MAGIC: &[u8; 8] = b"DREMBED1";
+const HEADER_BYTES: usize = 16;
+
+/// A mapped embedding table.
+#[derive(Debug)]
+pub struct EmbeddingTable {
+    /// The whole mapped file, header included.
+    map: *const u8,
+    map_len: usize,
+    rows: usize,
+    dim: usize,
+}
+
+// SAFETY: the mapping is read-only and lives until drop.
+unsafe impl Send for EmbeddingTable {}
+unsafe impl Sync for EmbeddingTable {}
+
+impl EmbeddingTable {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let err = |message: String| format!("{}: {message}", path.display());
+        let file = File::open(path).map_err(|e| err(e.to_string()))?;
+        let map_len = file.metadata().map_err(|e| err(e.to_string()))?.len() as usize;
+        if map_len < HEADER_BYTES {
+            return Err(err(format!("{map_len} bytes is too short for the header")));
+        }
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                map_len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(err(format!("mmap: {}", std::io::Error::last_os_error())));
+        }
+        // From here on, drop unmaps the file on every error path.
+        let mut table = Self {
+            map: map as *const u8,
+            map_len,
+            rows: 0,
+            dim: 0,
+        };
+        let header = table.bytes(0, HEADER_BYTES);
+        if &header[..8] != MAGIC {
+            return Err(err("not an embedding table (bad magic)".to_string()));
+        }
+        let rows = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let dim = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        if dim == 0 {
+            return Err(err("dim must be > 0".to_string()));
+        }
+        let expected = rows
+            .checked_mul(dim * BYTES_PER_F32)
+            .and_then(|bytes| bytes.checked_add(HEADER_BYTES));
+        if expected != Some(map_len) {
+            return Err(err(format!(
+                "{rows} rows of {dim} need {} bytes, file has {map_len}",
+                rows as u128 * (dim * BYTES_PER_F32) as u128 + HEADER_BYTES as u128
+            )));
+        }
+        table.rows = rows;
+        table.dim = dim;
+        Ok(table)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Values per row.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.map_len);
+        unsafe { std::slice::from_raw_parts(self.map.add(offset), len) }
+    }
+
+    /// Row `id` as little-endian `f32` bytes, or `None` past the end of the table.
+    fn row_bytes(&self, id: u32) -> Option<&[u8]> {
+        let id = id as usize;
+        let row_bytes = self.dim * BYTES_PER_F32;
+        (id < self.rows).then(|| self.bytes(HEADER_BYTES + id * row_bytes, row_bytes))
+    }
+}
+
+impl Drop for EmbeddingTable {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map as *mut libc::c_void, self.map_len) };
+    }
+}
+
+/// The current table, shared by every IO thread and replaced on reload.
+#[derive(Debug)]
+pub struct EmbeddingStore {
+    id_count: usize,
+    dim: usize,
+    generation: AtomicU64,
+    table: Mutex<Arc<EmbeddingTable>>,
+}
+
+impl EmbeddingStore {
+    /// Look up the last `id_count` values of each request vector in `table`.
+    pub fn new(table: EmbeddingTable, id_count: usize) -> Self {
+        assert!(id_count > 0, "id_count must be > 0");
+        Self {
+            id_count,
+            dim: table.dim,
+            generation: AtomicU64::new(0),
+            table: Mutex::new(Arc::new(table)),
+        }
+    }
+
+    pub fn id_count(&self) -> usize {
+        self.id_count
+    }
+
+    /// Features the model sees per vector of `wire_dim` values on the wire.
+    pub fn model_dim(&self, wire_dim: usize) -> usize {
+        wire_dim - self.id_count + self.id_count * self.dim
+    }
+
+    pub fn table(&self) -> Arc<EmbeddingTable> {
+        Arc::clone(&self.table.lock().expect("poisoned embedding table lock"))
+    }
+
+    /// Swap in `table`, which must have the same `dim` so the model's input width holds.
+    pub fn replace(&self, table: EmbeddingTable) -> Result<(), String> {
+        if table.dim != self.dim {
+            return Err(format!(
+                "embedding dim {} differs from the running {}; restart to change it",
+                table.dim, self.dim
+            ));
+        }
+        *self.table.lock().expect("poisoned embedding table lock") = Arc::new(table);
+        self.generation.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn reader(self: &Arc<Self>) -> EmbeddingReader {
+        EmbeddingReader {
+            generation: self.generation.load(Ordering::Acquire),
+            table: self.table(),
+            store: Arc::clone(self),
+        }
+    }
+}
+
+/// One IO thread's view of an [`EmbeddingStore`], refreshed only when the table is replaced.
+#[derive(Debug)]
+pub struct EmbeddingReader {
+    store: Arc<EmbeddingStore>,
+    generation: u64,
+    table: Arc<EmbeddingTable>,
+}
+
+impl EmbeddingReader {
+    /// The current table, picking up a replacement since the last call.
+    pub fn current(&mut self) -> EmbeddingLookup<'_> {
+        let generation = self.store.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.table = self.store.table();
+            self.generation = generation;
+        }
+        EmbeddingLookup {
+            table: &self.table,
+            id_count: self.store.id_count,
+        }
+    }
+}
+
+/// Expands request vectors whose last `id_count` values are ids.
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddingLookup<'a> {
+    table: &'a EmbeddingTable,
+    id_count: usize,
+}
+
+impl<'a> EmbeddingLookup<'a> {
+    pub fn new(table: &'a EmbeddingTable, id_count: usize) -> Self {
+        Self { table, id_count }
+    }
+
+    /// Features per vector after expansion, for vectors of `wire_dim` values.
+    pub fn model_dim(&self, wire_dim: usize) -> usize {
+        wire_dim - self.id_count + self.id_count * self.table.dim
+    }
+
+    /// Expand `wire`, whole vectors of `wire_dim` little-endian values, into `out`, whole
+    /// vectors of [`Self::model_dim`] features.
+    pub fn expand(&self, wire: &[u8], wire_dim: usize, out: &mut [f32]) {
+        let dense = wire_dim - self.id_count;
+        let vector_bytes = wire_dim * BYTES_PER_F32;
+        let model_dim = self.model_dim(wire_dim);
+        assert_eq!(wire.len() / vector_bytes, out.len() / model_dim);
+        for (vector, out) in wire
+            .chunks_exact(vector_bytes)
+            .zip(out.chunks_exact_mut(model_dim))
+        {
+            let (dense_bytes, ids) = vector.split_at(dense * BYTES_PER_F32);
+            let (dense_out, rows_out) = out.split_at_mut(dense);
+            byte_order::read_f32s_le(dense_bytes, dense_out);
+            for (id, row_out) in ids
+                .chunks_exact(4)
+                .zip(rows_out.chunks_exact_mut(self.table.dim))
+            {
+                let id = u32::from_le_bytes(id.try_into().unwrap());
+                match self.table.row_bytes(id) {
+                    Some(row) => byte_order::read_f32s_le(row, row_out),
+                    None => {
+                        metrics::inc_embedding_misses();
+                        row_out.fill(0.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use super::{EmbeddingStore, EmbeddingTable, MAGIC};
+
+    /// Write a table of `rows` to a temporary file named after `name`.
+    fn write_table(name: &str, rows: &[Vec<f32>]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("disrust-embeddings-{name}-{}", std::process::id()));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((rows.len() as u32).to_le_bytes());
+        bytes.extend((rows[0].len() as u32).to_le_bytes());
+        for value in rows.iter().flatten() {
+            bytes.extend(value.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn wire(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn ids_expand_to_their_rows_after_the_dense_features() {
+        let path = write_table("expand", &[vec![0.5, 1.5], vec![2.5, 3.5]]);
+        let store = Arc::new(EmbeddingStore::new(EmbeddingTable::load(&path).unwrap(), 2));
+        assert_eq!(store.model_dim(3), 5);
+        let mut reader = store.reader();
+
+        // Two vectors of one dense feature and two ids; id 7 is past the table.
+        let request = wire(&[1.0f32.to_bits(), 1, 0, 2.0f32.to_bits(), 7, 1]);
+        let mut out = vec![f32::NAN; 10];
+        reader.current().expand(&request, 3, &mut out);
+        assert_eq!(
+            out,
+            [1.0, 2.5, 3.5, 0.5, 1.5, 2.0, 0.0, 0.0, 2.5, 3.5],
+            "dense, then each id's row"
+        );
+
+        let replacement = write_table("expand-next", &[vec![9.0, 9.0]]);
+        store
+            .replace(EmbeddingTable::load(&replacement).unwrap())
+            .unwrap();
+        reader.current().expand(&request[..12], 3, &mut out[..5]);
+        assert_eq!(&out[..5], &[1.0, 0.0, 0.0, 9.0, 9.0]);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(replacement).unwrap();
+    }
+
+    #[test]
+    fn malformed_tables_are_refused() {
+        let path = write_table("short", &[vec![1.0, 2.0]]);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(EmbeddingTable::load(&path).is_err(), "truncated");
+        std::fs::write(&path, b"not a table at all").unwrap();
+        assert!(EmbeddingTable::load(&path).is_err(), "bad magic");
+        assert!(EmbeddingTable::load(Path::new("/nonexistent/table")).is_err());
+
+        let wide = write_table("wide", &[vec![1.0, 2.0, 3.0]]);
+        let narrow = write_table("narrow", &[vec![1.0]]);
+        let store = EmbeddingStore::new(EmbeddingTable::load(&wide).unwrap(), 1);
+        let narrow_table = EmbeddingTable::load(&narrow).unwrap();
+        assert!(store.replace(narrow_table).is_err(), "dim change");
+        for path in [path, wide, narrow] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
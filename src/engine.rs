@@ -0,0 +1,846 @@
+//! In-process inference for embedders.
+//!
+//! [`EngineBuilder`] wires up the server's request ring, [`InferenceConsumer`] and response queue
+//! without any sockets. [`Engine::infer`] publishes a request straight into the ring and resolves
+//! once the inference thread has answered it: a dispatch thread pops the response queue and
+//! wakes the task waiting on that request, so application code never polls the pipeline itself.
+//!
+//! Every request is published on one logical connection and matched to its response by
+//! `request_seq`, so concurrent callers may complete in any order.
+//!
+//! Offline jobs can skip the executor: [`Engine::submit_batch`] claims ring slots for many
+//! requests at once and returns a [`PendingBatch`] to poll or block on.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::Duration;
+
+use disruptor::{BusySpin, MultiProducer, Producer, SingleConsumerBarrier, build_multi_producer};
+
+use crate::buffer_pool::{BufferPool, PoolAllocator, set_factory_pool};
+use crate::calibration::Calibration;
+use crate::clock::monotonic_now_ns;
+use crate::config::{
+    DEFAULT_BATCH_COALESCE_US, GPU_DISRUPTOR_SIZE, MAX_SESSION_BATCH_SIZE, RESPONSE_QUEUE_SIZE,
+};
+use crate::connection_id::ConnectionRef;
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::metrics;
+use crate::notify::CondvarNotifier;
+use crate::pipeline::InferenceBackend;
+use crate::pipeline::connection_registry::ConnectionRegistry;
+use crate::pipeline::inference::InferenceConsumer;
+use crate::pipeline::response_queue::{ResponseQueue, ResponseRouter};
+use crate::request_flow;
+use crate::ring_types::InferenceEvent;
+
+/// How long the dispatch thread sleeps between checks for shutdown when no responses arrive.
+const DISPATCH_IDLE_WAIT: Duration = Duration::from_millis(10);
+
+/// Why a request got no results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineError {
+    /// The feature count is not 1..=`MAX_VECTORS_PER_REQUEST` whole vectors of the backend's
+    /// `feature_dim`.
+    FeatureCount { len: usize, feature_dim: usize },
+    /// The inference thread panicked; the engine answers nothing more.
+    Poisoned,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EngineError::FeatureCount { len, feature_dim } => write!(
+                f,
+                "{len} features is not 1..={MAX_VECTORS_PER_REQUEST} vectors of {feature_dim}"
+            ),
+            EngineError::Poisoned => write!(f, "inference thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Builds an [`Engine`] around an inference backend.
+pub struct EngineBuilder<B: InferenceBackend> {
+    backend: B,
+    pool: Option<&'static BufferPool>,
+    ring_size: usize,
+    max_batch_slots: usize,
+    batch_coalesce: Duration,
+    calibration: Option<Arc<Calibration>>,
+}
+
+impl<B: InferenceBackend + 'static> EngineBuilder<B> {
+    /// `backend` must have been constructed after `B::init()`.
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            pool: None,
+            ring_size: GPU_DISRUPTOR_SIZE,
+            max_batch_slots: MAX_SESSION_BATCH_SIZE,
+            batch_coalesce: Duration::from_micros(DEFAULT_BATCH_COALESCE_US),
+            calibration: None,
+        }
+    }
+
+    /// Copy request features into `pool` instead of a new pool from `B::make_pool`.
+    pub fn with_pool(mut self, pool: &'static BufferPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Request ring slots; must be a power of two.
+    pub fn with_ring_size(mut self, ring_size: usize) -> Self {
+        self.ring_size = ring_size;
+        self
+    }
+
+    /// Runtime cap on ring slots per backend submission.
+    pub fn with_max_batch_slots(mut self, max_batch_slots: usize) -> Self {
+        self.max_batch_slots = max_batch_slots;
+        self
+    }
+
+    /// Coalescing window for a partial batch once a session is available.
+    pub fn with_batch_coalesce(mut self, batch_coalesce: Duration) -> Self {
+        self.batch_coalesce = batch_coalesce;
+        self
+    }
+
+    /// Apply `calibration` to every result.
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(Arc::new(calibration));
+        self
+    }
+
+    /// Start the inference and dispatch threads.
+    pub fn build(self) -> Engine {
+        assert!(
+            self.ring_size.is_power_of_two(),
+            "ring_size must be a power of two"
+        );
+        assert!(
+            (1..=MAX_SESSION_BATCH_SIZE).contains(&self.max_batch_slots),
+            "max_batch_slots must be in 1..={MAX_SESSION_BATCH_SIZE}"
+        );
+        let feature_dim = self.backend.feature_dim();
+        assert!(
+            (1..=MAX_FEATURE_DIM).contains(&feature_dim),
+            "feature_dim must be in 1..={MAX_FEATURE_DIM}"
+        );
+        set_factory_pool(BufferPool::new_boxed(1));
+        let pool = self.pool.unwrap_or_else(|| B::make_pool(feature_dim));
+
+        let builder = build_multi_producer(self.ring_size, InferenceEvent::factory, BusySpin);
+        let (submission_poller, builder) = builder.event_poller();
+        let (completion_poller, builder) = builder.and_then().event_poller();
+        let producer = builder.build();
+
+        let notifier = Arc::new(CondvarNotifier::new());
+        let response_queue = Arc::new(ResponseQueue::with_notifier(
+            RESPONSE_QUEUE_SIZE,
+            Arc::clone(&notifier) as _,
+        ));
+        let registry = Arc::new(ConnectionRegistry::new(1, 1));
+        // No socket behind the engine's connection: fd -1 is never closed on retirement.
+        let conn = registry.open(0, 0, -1);
+        let mut consumer = InferenceConsumer::new(
+            submission_poller,
+            completion_poller,
+            self.backend,
+            Arc::new(ResponseRouter::from(vec![Arc::clone(&response_queue)])),
+            registry,
+            self.max_batch_slots,
+            self.batch_coalesce,
+        );
+        if let Some(calibration) = self.calibration {
+            consumer = consumer.with_calibration(calibration);
+        }
+
+        let waiters = Arc::new(Waiters::default());
+        let inference = Worker::spawn("engine-inference", |stop| consumer.run_until(stop));
+        let dispatch = Worker::spawn("engine-dispatch", {
+            let waiters = Arc::clone(&waiters);
+            move |stop| dispatch_responses(&response_queue, &notifier, &waiters, &stop)
+        });
+
+        Engine {
+            submitter: Mutex::new(Submitter {
+                producer,
+                allocator: pool.allocator(),
+            }),
+            conn,
+            feature_dim,
+            publish_chunk: self.ring_size.min(self.max_batch_slots),
+            next_seq: AtomicU64::new(0),
+            waiters,
+            workers: [inference, dispatch],
+        }
+    }
+}
+
+/// The publish side of the ring. The pool allocator is not thread safe, so allocation and
+/// publish happen together under one lock, like the server's publish gate.
+struct Submitter {
+    producer: MultiProducer<InferenceEvent, SingleConsumerBarrier>,
+    allocator: PoolAllocator,
+}
+
+/// An in-process inference pipeline. Dropping it stops its threads.
+pub struct Engine {
+    submitter: Mutex<Submitter>,
+    conn: ConnectionRef,
+    feature_dim: usize,
+    /// Most ring slots [`Engine::submit_batch`] claims at once.
+    publish_chunk: usize,
+    next_seq: AtomicU64,
+    waiters: Arc<Waiters>,
+    /// Stopped in order: the inference thread may be waiting for the dispatch thread to make
+    /// room in the response queue.
+    workers: [Worker; 2],
+}
+
+impl Engine {
+    /// Features per vector, as the backend takes them.
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    /// Score `features`, one or more vectors of the backend's `feature_dim` values, returning one
+    /// result per vector.
+    ///
+    /// While the request ring is full the returned future yields to the executor and retries
+    /// on its next poll. Dropping the future after the request is published discards its
+    /// response. Fails with [`EngineError::Poisoned`] once the inference thread has panicked.
+    pub async fn infer(&self, features: &[f32]) -> Result<Vec<f32>, EngineError> {
+        let num_vectors = vector_count(features, self.feature_dim)?;
+        let request_seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        // Register before publishing so the dispatch thread always finds the waiter.
+        let response = Response {
+            waiters: &self.waiters,
+            request_seq: self.waiters.register(request_seq),
+        };
+        std::future::poll_fn(|cx| self.try_publish(request_seq, num_vectors, features, cx)).await;
+        response.await
+    }
+
+    fn try_publish(
+        &self,
+        request_seq: u64,
+        num_vectors: u8,
+        features: &[f32],
+        cx: &mut Context<'_>,
+    ) -> Poll<()> {
+        // Nothing would consume the request; the waiter already holds the error.
+        if self.waiters.is_poisoned() {
+            return Poll::Ready(());
+        }
+        let mut submitter = self.submitter.lock().unwrap();
+        let Submitter {
+            producer,
+            allocator,
+        } = &mut *submitter;
+        match request_flow::publish(
+            producer,
+            allocator,
+            self.conn,
+            request_seq,
+            monotonic_now_ns(),
+            num_vectors,
+            self.feature_dim,
+            None,
+            |dst| dst.copy_from_slice(features),
+        ) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Publish every request in `requests` and return a handle to their results, in order.
+    ///
+    /// Requests are published in claims of up to one backend batch, each waiting for that many
+    /// free ring slots, so the call blocks other submitters until the last request is in the
+    /// ring. Nothing is published if any request has an invalid feature count.
+    pub fn submit_batch(&self, requests: &[&[f32]]) -> Result<PendingBatch<'_>, EngineError> {
+        let num_vectors = requests
+            .iter()
+            .map(|features| vector_count(features, self.feature_dim))
+            .collect::<Result<Vec<_>, _>>()?;
+        let first_seq = self
+            .next_seq
+            .fetch_add(requests.len() as u64, Ordering::Relaxed);
+        for offset in 0..requests.len() as u64 {
+            self.waiters.register(first_seq + offset);
+        }
+        let batch = PendingBatch {
+            waiters: &self.waiters,
+            first_seq,
+            len: requests.len(),
+            results: Vec::with_capacity(requests.len()),
+        };
+
+        let mut submitter = self.submitter.lock().unwrap();
+        let Submitter {
+            producer,
+            allocator,
+        } = &mut *submitter;
+        let mut request_seq = first_seq;
+        let submitted_at_ns = monotonic_now_ns();
+        for (chunk, chunk_vectors) in requests
+            .chunks(self.publish_chunk)
+            .zip(num_vectors.chunks(self.publish_chunk))
+        {
+            loop {
+                // Poisoned waiters already hold the error, and nothing would free ring slots.
+                if self.waiters.is_poisoned() {
+                    return Ok(batch);
+                }
+                let claimed = producer.try_batch_publish(chunk.len(), |slots| {
+                    for ((slot, features), &num_vectors) in slots.zip(chunk).zip(chunk_vectors) {
+                        request_flow::fill_event(
+                            slot,
+                            allocator,
+                            self.conn,
+                            request_seq,
+                            submitted_at_ns,
+                            num_vectors,
+                            self.feature_dim,
+                            None,
+                            |dst| dst.copy_from_slice(features),
+                        );
+                        request_seq += 1;
+                    }
+                });
+                if claimed.is_ok() {
+                    break;
+                }
+                std::hint::spin_loop();
+            }
+            for _ in chunk {
+                metrics::inc_requests_published();
+                metrics::inc_req_occ();
+            }
+        }
+        Ok(batch)
+    }
+
+    /// [`Self::submit_batch`] and wait for every result.
+    pub fn infer_batch(&self, requests: &[&[f32]]) -> Result<Vec<Vec<f32>>, EngineError> {
+        self.submit_batch(requests)?.wait()
+    }
+}
+
+/// Results of [`Engine::submit_batch`], collected in request order as they arrive.
+///
+/// Dropping it before every result arrives discards the rest.
+pub struct PendingBatch<'a> {
+    waiters: &'a Waiters,
+    first_seq: u64,
+    len: usize,
+    results: Vec<Vec<f32>>,
+}
+
+impl PendingBatch<'_> {
+    /// Every result if all have arrived, without blocking.
+    pub fn try_wait(&mut self) -> Option<Result<Vec<Vec<f32>>, EngineError>> {
+        match self.collect(None) {
+            Poll::Ready(result) => Some(result.map(|()| self.take())),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Block until every result has arrived.
+    pub fn wait(mut self) -> Result<Vec<Vec<f32>>, EngineError> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        loop {
+            match self.collect(Some(&waker)) {
+                Poll::Ready(result) => return result.map(|()| self.take()),
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    /// Move arrived results into `self.results` in order, until all have arrived or one
+    /// failed. Otherwise `waker` is woken when the next missing result arrives.
+    fn collect(&mut self, waker: Option<&Waker>) -> Poll<Result<(), EngineError>> {
+        let mut pending = self.waiters.pending.lock().unwrap();
+        while self.results.len() < self.len {
+            let request_seq = self.first_seq + self.results.len() as u64;
+            let waiter = pending
+                .get_mut(&request_seq)
+                .expect("waiter removed while its batch is alive");
+            match waiter.results.take() {
+                Some(Ok(results)) => {
+                    pending.remove(&request_seq);
+                    self.results.push(results);
+                }
+                Some(Err(err)) => {
+                    waiter.results = Some(Err(err));
+                    return Poll::Ready(Err(err));
+                }
+                None => {
+                    waiter.waker = waker.cloned();
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl PendingBatch<'_> {
+    fn take(&mut self) -> Vec<Vec<f32>> {
+        // Mark the batch consumed so drop has nothing left to discard.
+        self.first_seq += self.len as u64;
+        self.len = 0;
+        std::mem::take(&mut self.results)
+    }
+}
+
+impl Drop for PendingBatch<'_> {
+    fn drop(&mut self) {
+        let mut pending = self.waiters.pending.lock().unwrap();
+        for offset in self.results.len()..self.len {
+            pending.remove(&(self.first_seq + offset as u64));
+        }
+    }
+}
+
+/// Wakes a thread parked in [`PendingBatch::wait`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            worker.stop();
+        }
+    }
+}
+
+/// An engine thread that runs until its stop flag is set.
+struct Worker {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn(name: &str, run: impl FnOnce(Arc<AtomicBool>) + Send + 'static) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new()
+            .name(name.into())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || run(stop)
+            })
+            .unwrap_or_else(|e| panic!("failed to spawn {name}: {e}"));
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn vector_count(features: &[f32], feature_dim: usize) -> Result<u8, EngineError> {
+    let num_vectors = features.len() / feature_dim;
+    if !features.len().is_multiple_of(feature_dim)
+        || !(1..=MAX_VECTORS_PER_REQUEST).contains(&num_vectors)
+    {
+        return Err(EngineError::FeatureCount {
+            len: features.len(),
+            feature_dim,
+        });
+    }
+    Ok(num_vectors as u8)
+}
+
+#[derive(Default)]
+struct Waiter {
+    results: Option<Result<Vec<f32>, EngineError>>,
+    waker: Option<Waker>,
+}
+
+/// Requests published but not yet handed back to their caller, by `request_seq`.
+#[derive(Default)]
+struct Waiters {
+    pending: Mutex<HashMap<u64, Waiter>>,
+    /// Set before the pending map is failed, so a waiter registered later starts failed.
+    poisoned: AtomicBool,
+}
+
+impl Waiters {
+    fn register(&self, request_seq: u64) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        let waiter = Waiter {
+            results: self.is_poisoned().then_some(Err(EngineError::Poisoned)),
+            waker: None,
+        };
+        pending.insert(request_seq, waiter);
+        request_seq
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Fail every waiting request, and every one registered later, with `Poisoned`.
+    fn fail_all(&self) {
+        self.poisoned.store(true, Ordering::Release);
+        let wakers: Vec<Waker> = {
+            let mut pending = self.pending.lock().unwrap();
+            pending
+                .values_mut()
+                .filter_map(|waiter| {
+                    waiter.results.get_or_insert(Err(EngineError::Poisoned));
+                    waiter.waker.take()
+                })
+                .collect()
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn complete(&self, request_seq: u64, results: Vec<f32>) {
+        let waker = {
+            let mut pending = self.pending.lock().unwrap();
+            // A missing waiter means its future was dropped; the response is discarded.
+            let Some(waiter) = pending.get_mut(&request_seq) else {
+                return;
+            };
+            waiter.results = Some(Ok(results));
+            waiter.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves to the results for `request_seq` once the dispatch thread delivers them.
+struct Response<'a> {
+    waiters: &'a Waiters,
+    request_seq: u64,
+}
+
+impl Future for Response<'_> {
+    type Output = Result<Vec<f32>, EngineError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut pending = self.waiters.pending.lock().unwrap();
+        let waiter = pending
+            .get_mut(&self.request_seq)
+            .expect("waiter removed while its future is alive");
+        match waiter.results.take() {
+            Some(results) => Poll::Ready(results),
+            None => {
+                waiter.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Response<'_> {
+    fn drop(&mut self) {
+        self.waiters
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&self.request_seq);
+    }
+}
+
+fn dispatch_responses(
+    queue: &ResponseQueue,
+    notifier: &CondvarNotifier,
+    waiters: &Waiters,
+    stop: &AtomicBool,
+) {
+    let drain = || {
+        while let Some((request_seq, results)) =
+            queue.pop_with(|response| (response.request_seq, response.results().to_vec()))
+        {
+            waiters.complete(request_seq, results);
+        }
+    };
+    while !stop.load(Ordering::Relaxed) {
+        drain();
+        if queue.is_poisoned() {
+            // Deliver whatever was queued before the inference thread died, then fail the rest.
+            drain();
+            waiters.fail_all();
+            return;
+        }
+        notifier.wait_timeout(DISPATCH_IDLE_WAIT);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::future::Future;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{EngineBuilder, EngineError, ThreadWaker};
+    use crate::buffer_pool::BufferPool;
+    use crate::calibration::Calibration;
+    use crate::config::MAX_BATCH_VECTORS;
+    use crate::constants::FEATURE_DIM;
+    use crate::pipeline::InferenceBackend;
+    use crate::pipeline::session::{BatchCompletion, InFlightBatch};
+
+    /// Single-session backend that sums each vector synchronously.
+    pub(crate) struct SumBackend {
+        output: Vec<f32>,
+        feature_dim: usize,
+        available: Arc<AtomicBool>,
+    }
+
+    impl SumBackend {
+        pub(crate) fn new() -> Self {
+            Self {
+                output: vec![0.0; MAX_BATCH_VECTORS],
+                feature_dim: FEATURE_DIM,
+                available: Arc::new(AtomicBool::new(true)),
+            }
+        }
+
+        pub(crate) fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+            self.feature_dim = feature_dim;
+            self
+        }
+    }
+
+    impl InferenceBackend for SumBackend {
+        type Resources = ();
+
+        fn make_pool(_: usize) -> &'static BufferPool {
+            static POOL: OnceLock<&'static BufferPool> = OnceLock::new();
+            POOL.get_or_init(|| BufferPool::leak_new(1 << 16))
+        }
+
+        fn feature_dim(&self) -> usize {
+            self.feature_dim
+        }
+
+        fn try_acquire(&mut self) -> bool {
+            self.available
+                .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        }
+
+        fn is_available(&self) -> bool {
+            self.available.load(Ordering::Acquire)
+        }
+
+        fn submit_batch(&mut self, input: *const f32, num_vectors: usize) -> InFlightBatch<()> {
+            let dim = self.feature_dim;
+            let input = unsafe { std::slice::from_raw_parts(input, num_vectors * dim) };
+            for (out, vector) in self.output.iter_mut().zip(input.chunks_exact(dim)) {
+                *out = vector.iter().sum();
+            }
+            let completion = Arc::new(BatchCompletion::new());
+            completion.mark_ready();
+            InFlightBatch::new(
+                completion,
+                self.output.as_ptr(),
+                num_vectors,
+                Arc::clone(&self.available),
+                (),
+            )
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park_timeout(Duration::from_millis(100));
+        }
+    }
+
+    /// Backend whose every submission panics the inference thread.
+    struct PanicBackend;
+
+    impl InferenceBackend for PanicBackend {
+        type Resources = ();
+
+        fn make_pool(feature_dim: usize) -> &'static BufferPool {
+            SumBackend::make_pool(feature_dim)
+        }
+
+        fn try_acquire(&mut self) -> bool {
+            true
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn submit_batch(&mut self, _: *const f32, _: usize) -> InFlightBatch<()> {
+            panic!("backend failed");
+        }
+    }
+
+    #[test]
+    fn infer_resolves_each_caller_with_its_own_results() {
+        let engine = Arc::new(
+            EngineBuilder::new(SumBackend::new())
+                .with_ring_size(64)
+                .with_batch_coalesce(Duration::ZERO)
+                .build(),
+        );
+        let callers: Vec<_> = (0..4)
+            .map(|caller| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let value = (caller * 100 + i) as f32;
+                        let mut features = vec![value; 2 * FEATURE_DIM];
+                        features[FEATURE_DIM..].fill(1.0);
+                        let results = block_on(engine.infer(&features)).unwrap();
+                        assert_eq!(
+                            results,
+                            vec![value * FEATURE_DIM as f32, FEATURE_DIM as f32]
+                        );
+                    }
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn infer_rejects_partial_vectors() {
+        let engine = EngineBuilder::new(SumBackend::new())
+            .with_ring_size(64)
+            .build();
+        assert_eq!(
+            block_on(engine.infer(&[1.0; FEATURE_DIM + 1])),
+            Err(EngineError::FeatureCount {
+                len: FEATURE_DIM + 1,
+                feature_dim: FEATURE_DIM
+            })
+        );
+        assert!(block_on(engine.infer(&[])).is_err());
+    }
+
+    #[test]
+    fn engine_takes_vectors_of_the_backend_feature_dim() {
+        let engine = EngineBuilder::new(SumBackend::new().with_feature_dim(3))
+            .with_ring_size(64)
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        assert_eq!(
+            block_on(engine.infer(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0])),
+            Ok(vec![6.0, 15.0])
+        );
+        assert_eq!(
+            block_on(engine.infer(&[1.0; FEATURE_DIM])),
+            Err(EngineError::FeatureCount {
+                len: FEATURE_DIM,
+                feature_dim: 3
+            })
+        );
+    }
+
+    #[test]
+    fn calibration_applies_to_every_result() {
+        // Sums of FEATURE_DIM ones and twos map to 0.5 and 1.0.
+        let calibration = Calibration::Isotonic {
+            scores: vec![0.0, 2.0 * FEATURE_DIM as f32],
+            calibrated: vec![0.0, 1.0],
+        };
+        let engine = EngineBuilder::new(SumBackend::new())
+            .with_ring_size(64)
+            .with_batch_coalesce(Duration::ZERO)
+            .with_calibration(calibration)
+            .build();
+        let mut features = vec![1.0; 2 * FEATURE_DIM];
+        features[FEATURE_DIM..].fill(2.0);
+        assert_eq!(block_on(engine.infer(&features)), Ok(vec![0.5, 1.0]));
+    }
+
+    #[test]
+    fn submit_batch_returns_results_in_request_order() {
+        let engine = EngineBuilder::new(SumBackend::new())
+            .with_ring_size(64)
+            .with_max_batch_slots(16)
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        let features: Vec<Vec<f32>> = (0..300)
+            .map(|i| vec![i as f32; (i % 3 + 1) * FEATURE_DIM])
+            .collect();
+        let requests: Vec<&[f32]> = features.iter().map(Vec::as_slice).collect();
+        let expected: Vec<Vec<f32>> = (0..300)
+            .map(|i| vec![(i * FEATURE_DIM) as f32; i % 3 + 1])
+            .collect();
+
+        assert_eq!(engine.infer_batch(&requests).unwrap(), expected);
+
+        let mut pending = engine.submit_batch(&requests[..10]).unwrap();
+        let results = loop {
+            if let Some(results) = pending.try_wait() {
+                break results.unwrap();
+            }
+            thread::yield_now();
+        };
+        assert_eq!(results, expected[..10]);
+
+        let invalid: [&[f32]; 2] = [&[1.0; FEATURE_DIM], &[1.0; 3]];
+        assert!(engine.submit_batch(&invalid).is_err());
+    }
+
+    #[test]
+    fn inference_panic_fails_waiting_and_later_requests() {
+        let engine = EngineBuilder::new(PanicBackend)
+            .with_ring_size(64)
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        let features = [1.0; FEATURE_DIM];
+        assert_eq!(
+            block_on(engine.infer(&features)),
+            Err(EngineError::Poisoned)
+        );
+        assert_eq!(
+            block_on(engine.infer(&features)),
+            Err(EngineError::Poisoned)
+        );
+        assert_eq!(
+            engine.infer_batch(&[&features, &features]),
+            Err(EngineError::Poisoned)
+        );
+    }
+}
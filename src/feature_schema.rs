@@ -0,0 +1,279 @@
+//! Feature schema checked against every request vector at the serving edge.
+//!
+//! The schema file uses the config file's format, one `key = value` per line with `#` comments.
+//! `dim` is required and must match `--feature-dim`; each `feature.<index>` line names a feature
+//! and constrains it to a closed range or a set of categorical codes:
+//!
+//! ```text
+//! dim = 16
+//! feature.0 = amount range 0 100000
+//! feature.3 = country categorical 0, 1, 2, 3
+//! ```
+//!
+//! Features without a line are unconstrained. Violations are counted per feature so upstream
+//! pipeline drift shows up in the metrics report; they do not change how a request is scored,
+//! but with `--vector-status` the vector is reported `invalid_input`.
+//! NaN and infinity are left to `--non-finite-features`, except that NaN is never a valid code.
+
+use std::path::Path;
+
+use crate::constants::MAX_FEATURE_DIM;
+use crate::protocol::{self, BYTES_PER_F32};
+
+/// Words of a [`FeatureMask`].
+pub const FEATURE_MASK_WORDS: usize = MAX_FEATURE_DIM.div_ceil(u64::BITS as usize);
+
+/// A set of features: bit `i % 64` of word `i / 64` for feature `i`.
+pub type FeatureMask = [u64; FEATURE_MASK_WORDS];
+
+/// Valid values of one feature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureRule {
+    Range { min: f32, max: f32 },
+    Categorical(Vec<f32>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureSchema {
+    /// Name and rule of each constrained feature, by index.
+    features: Vec<(usize, String, FeatureRule)>,
+    /// Per-feature bounds for the range pass, one per feature of the vectors checked;
+    /// unconstrained and categorical features get `-inf..=inf`.
+    min: Vec<f32>,
+    max: Vec<f32>,
+}
+
+impl FeatureSchema {
+    /// Parse a schema for vectors of `feature_dim` features.
+    pub fn parse(text: &str, feature_dim: usize) -> Result<Self, String> {
+        let mut dim = None;
+        let mut features: Vec<(usize, String, FeatureRule)> = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_err = |message: String| format!("line {}: {message}", line_no + 1);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| line_err("expected `key = value`".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key == "dim" {
+                dim = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| line_err(format!("invalid dim '{value}'")))?,
+                );
+                continue;
+            }
+            let index = key
+                .strip_prefix("feature.")
+                .ok_or_else(|| line_err(format!("unknown key '{key}'")))?
+                .parse::<usize>()
+                .ok()
+                .filter(|&index| index < feature_dim)
+                .ok_or_else(|| {
+                    line_err(format!(
+                        "feature index in '{key}' is not below {feature_dim}"
+                    ))
+                })?;
+            if features.iter().any(|(i, _, _)| *i == index) {
+                return Err(line_err(format!("feature {index} listed twice")));
+            }
+            let (name, rule) = parse_feature(value).map_err(line_err)?;
+            features.push((index, name, rule));
+        }
+        match dim {
+            Some(dim) if dim == feature_dim => {}
+            Some(dim) => {
+                return Err(format!(
+                    "dim {dim} does not match feature dim {feature_dim}"
+                ));
+            }
+            None => return Err("missing key 'dim'".to_string()),
+        }
+        features.sort_by_key(|(index, _, _)| *index);
+
+        let mut min = vec![f32::NEG_INFINITY; feature_dim];
+        let mut max = vec![f32::INFINITY; feature_dim];
+        for (index, _, rule) in &features {
+            if let FeatureRule::Range { min: lo, max: hi } = *rule {
+                (min[*index], max[*index]) = (lo, hi);
+            }
+        }
+        Ok(Self { features, min, max })
+    }
+
+    pub fn load(path: &Path, feature_dim: usize) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        Self::parse(&text, feature_dim).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Features per vector the schema describes.
+    pub fn feature_dim(&self) -> usize {
+        self.min.len()
+    }
+
+    /// Index and name of each constrained feature, in index order.
+    pub fn feature_names(&self) -> Vec<(usize, String)> {
+        self.features
+            .iter()
+            .map(|(index, name, _)| (*index, name.clone()))
+            .collect()
+    }
+
+    /// The features of `vector`, [`Self::feature_dim`] wide, that break the schema.
+    pub fn violations(&self, vector: &[f32]) -> FeatureMask {
+        // Branch-free so the range pass vectorizes; NaN compares false and passes it.
+        let mut mask = [0u64; FEATURE_MASK_WORDS];
+        for (i, ((value, min), max)) in vector.iter().zip(&self.min).zip(&self.max).enumerate() {
+            let outside = (value < min) | (value > max);
+            mask[i / 64] |= (outside as u64) << (i % 64);
+        }
+        for (index, _, rule) in &self.features {
+            if let FeatureRule::Categorical(codes) = rule
+                && !codes.contains(&vector[*index])
+            {
+                mask[index / 64] |= 1 << (index % 64);
+            }
+        }
+        mask
+    }
+
+    /// Check every vector in wire-format `feature_bytes`, counting each violated feature in the
+    /// metrics. Returns the mask of vectors with any violation; bit `i` for vector `i`.
+    pub fn check_vectors(&self, feature_bytes: &[u8]) -> u64 {
+        let feature_dim = self.feature_dim();
+        let mut vectors = 0u64;
+        for (i, bytes) in feature_bytes
+            .chunks_exact(feature_dim * BYTES_PER_F32)
+            .enumerate()
+        {
+            let mut vector = [0f32; MAX_FEATURE_DIM];
+            let vector = &mut vector[..feature_dim];
+            protocol::copy_features(bytes, vector);
+            let features = self.violations(vector);
+            if features.iter().any(|&word| word != 0) {
+                crate::metrics::record_schema_violations(&features);
+                vectors |= 1 << i;
+            }
+        }
+        vectors
+    }
+}
+
+/// Parse `<name> range <min> <max>` or `<name> categorical <code>...`.
+fn parse_feature(value: &str) -> Result<(String, FeatureRule), String> {
+    let mut words = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty());
+    let (Some(name), Some(kind)) = (words.next(), words.next()) else {
+        return Err(format!(
+            "expected `<name> range|categorical ...`, got '{value}'"
+        ));
+    };
+    let numbers = words
+        .map(|word| {
+            word.parse::<f32>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .ok_or_else(|| format!("invalid value '{word}'"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let rule = match (kind, numbers.as_slice()) {
+        ("range", &[min, max]) if min <= max => FeatureRule::Range { min, max },
+        ("range", &[min, max]) => return Err(format!("min {min} is above max {max}")),
+        ("range", _) => return Err(format!("{name}: range takes a min and a max")),
+        ("categorical", []) => return Err(format!("{name}: categorical needs at least one code")),
+        ("categorical", codes) => FeatureRule::Categorical(codes.to_vec()),
+        (other, _) => return Err(format!("{name}: unknown rule '{other}'")),
+    };
+    Ok((name.to_string(), rule))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FEATURE_MASK_WORDS, FeatureMask, FeatureRule, FeatureSchema};
+    use crate::constants::FEATURE_DIM;
+
+    fn schema() -> FeatureSchema {
+        FeatureSchema::parse(
+            &format!(
+                "# inputs\ndim = {FEATURE_DIM}\nfeature.3 = country categorical 0, 1, 2\nfeature.0 = amount range 0 100\n"
+            ),
+            FEATURE_DIM,
+        )
+        .unwrap()
+    }
+
+    fn mask(low_bits: u64) -> FeatureMask {
+        let mut mask = [0; FEATURE_MASK_WORDS];
+        mask[0] = low_bits;
+        mask
+    }
+
+    #[test]
+    fn parse_needs_matching_dim_and_valid_rules() {
+        let schema = schema();
+        assert_eq!(
+            schema.feature_names(),
+            vec![(0, "amount".to_string()), (3, "country".to_string())]
+        );
+        assert_eq!(
+            schema.features[1].2,
+            FeatureRule::Categorical(vec![0.0, 1.0, 2.0])
+        );
+
+        let parse = |text: &str| FeatureSchema::parse(text, FEATURE_DIM);
+        let dim = format!("dim = {FEATURE_DIM}\n");
+        assert!(parse("feature.0 = a range 0 1\n").is_err());
+        assert!(parse(&format!("dim = {}\n", FEATURE_DIM + 1)).is_err());
+        assert!(parse(&format!("{dim}feature.0 = a range 1 0\n")).is_err());
+        assert!(parse(&format!("{dim}feature.0 = a range 1\n")).is_err());
+        assert!(parse(&format!("{dim}feature.0 = a categorical\n")).is_err());
+        assert!(parse(&format!("{dim}feature.0 = a bucket 1 2\n")).is_err());
+        assert!(parse(&format!("{dim}feature.{FEATURE_DIM} = a range 0 1\n")).is_err());
+        assert!(
+            parse(&format!(
+                "{dim}feature.0 = a range 0 1\nfeature.0 = b range 0 1\n"
+            ))
+            .is_err()
+        );
+
+        let narrow = FeatureSchema::parse("dim = 20\nfeature.19 = late range 0 1\n", 20).unwrap();
+        assert_eq!(narrow.feature_dim(), 20);
+        assert!(FeatureSchema::parse("dim = 20\nfeature.20 = past range 0 1\n", 20).is_err());
+        assert!(parse("dim = 20\n").is_err());
+    }
+
+    #[test]
+    fn violations_flag_out_of_range_and_unknown_codes() {
+        let schema = schema();
+        let mut vector = [1.0f32; FEATURE_DIM];
+        assert_eq!(schema.violations(&vector), mask(0));
+
+        vector[0] = 100.5;
+        vector[3] = 1.5;
+        vector[5] = -1e9;
+        assert_eq!(schema.violations(&vector), mask(0b1001));
+
+        vector[0] = f32::NAN;
+        vector[3] = f32::NAN;
+        assert_eq!(
+            schema.violations(&vector),
+            mask(0b1000),
+            "NaN is only an unknown code"
+        );
+
+        let good = [1.0f32; FEATURE_DIM];
+        let mut bad = good;
+        bad[0] = -1.0;
+        let bytes: Vec<u8> = [good, bad, good]
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        assert_eq!(schema.check_vectors(&bytes), 0b010);
+    }
+}
@@ -1,22 +1,40 @@
 //! Library crate for disrust: ONNX inference server support, request parsing, buffer pool, and shared types.
 //!
-//! The `disrust` binary is the only io_uring server entrypoint. The library intentionally exposes
-//! the protocol, request path, and pipeline pieces so they can be tested without starting the
-//! full network server.
+//! The `disrust` binary starts the io_uring server from flags, and [`server::ServerBuilder`]
+//! starts the same server from an application or a test. The library also exposes the protocol,
+//! request path, and pipeline pieces so they can be tested without starting the full network
+//! server.
 
 pub mod affinity;
+#[cfg(feature = "alloc-audit")]
+pub mod alloc_audit;
 pub mod buffer_pool;
+pub mod byte_order;
+pub mod cache_line;
+pub mod calibration;
+pub mod canary;
+pub mod client;
 pub mod clock;
 pub mod config;
 pub mod connection_id;
 pub mod constants;
 #[cfg(feature = "cuda")]
 pub mod cuda;
+pub mod embedding;
+pub mod engine;
+pub mod feature_schema;
+pub mod memory_plan;
 pub mod metrics;
+pub mod model_artifact;
+pub mod notify;
 pub mod pipeline;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
 pub mod protocol;
 pub mod request_flow;
 pub mod ring_types;
+pub mod score;
 pub mod server;
 pub mod timer;
 pub mod verify;
+pub mod wire_layout;
@@ -9,14 +9,26 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Command {
-    Serve(disrust::server::ServeArgs),
+    Serve(Box<disrust::server::ServeArgs>),
     Verify(disrust::verify::VerifyArgs),
+    /// Score a file of requests through the serving pipeline, without a network
+    Score(disrust::score::ScoreArgs),
+    /// Print the byte-level wire format spec
+    WireSpec,
 }
 
 fn main() {
     let cli = Cli::parse();
     match cli.command {
-        Command::Serve(args) => disrust::server::run(args),
+        #[cfg(target_os = "linux")]
+        Command::Serve(args) => disrust::server::run(*args),
+        #[cfg(not(target_os = "linux"))]
+        Command::Serve(_) => {
+            eprintln!("disrust: serve needs io_uring and runs only on Linux");
+            std::process::exit(1);
+        }
         Command::Verify(args) => disrust::verify::run(args),
+        Command::Score(args) => disrust::score::run(args),
+        Command::WireSpec => print!("{}", disrust::wire_layout::render_spec()),
     }
 }
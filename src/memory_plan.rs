@@ -0,0 +1,236 @@
+//! Startup memory budget and allocation plan.
+//!
+//! Every large allocation the server makes is sized from compile-time constants plus a handful
+//! of runtime knobs (IO thread count, connections per thread). This module computes the full
+//! plan up front so the server can print it and refuse to start — or shrink the per-thread
+//! connection cap — before the OOM killer gets a say.
+
+use std::fmt;
+use std::mem::size_of;
+
+use crate::config::{
+    GPU_DISRUPTOR_SIZE, MAX_BATCH_VECTORS, READ_BUF_SIZE, REQUEST_OVERFLOW_CAPACITY,
+    RESPONSE_QUEUE_SIZE, SESSION_POOL_SIZE, gpu_buffer_pool_bytes,
+};
+use crate::constants::MAX_VECTORS_PER_REQUEST;
+use crate::pipeline::response_queue::ResponseReady;
+use crate::ring_types::InferenceEvent;
+
+/// One line of the allocation plan: `count` allocations of `unit_bytes` each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlanEntry {
+    pub name: &'static str,
+    pub count: usize,
+    pub unit_bytes: usize,
+}
+
+impl PlanEntry {
+    pub fn total_bytes(&self) -> usize {
+        self.count.saturating_mul(self.unit_bytes)
+    }
+}
+
+/// Worst-case resident memory for one server configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationPlan {
+    pub io_threads: usize,
+    pub max_connections: usize,
+    pub feature_dim: usize,
+    pub entries: Vec<PlanEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetError {
+    /// The plan exceeds the budget and shrinking was not requested.
+    OverBudget { plan: AllocationPlan, budget: usize },
+    /// Even with a single connection per IO thread the fixed allocations exceed the budget.
+    CannotShrink { plan: AllocationPlan, budget: usize },
+}
+
+impl BudgetError {
+    /// The plan that failed the budget check.
+    pub fn plan(&self) -> &AllocationPlan {
+        match self {
+            BudgetError::OverBudget { plan, .. } | BudgetError::CannotShrink { plan, .. } => plan,
+        }
+    }
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetError::OverBudget { plan, budget } => write!(
+                f,
+                "allocation plan needs {} but memory budget is {}",
+                format_bytes(plan.total_bytes()),
+                format_bytes(*budget)
+            ),
+            BudgetError::CannotShrink { plan, budget } => write!(
+                f,
+                "fixed allocations alone need {} with 1 connection per IO thread, budget is {}",
+                format_bytes(plan.total_bytes()),
+                format_bytes(*budget)
+            ),
+        }
+    }
+}
+
+impl AllocationPlan {
+    /// Build the plan for `io_threads` ingress threads each accepting up to `max_connections`,
+    /// serving vectors of `feature_dim` features.
+    pub fn for_server(io_threads: usize, max_connections: usize, feature_dim: usize) -> Self {
+        let entries = vec![
+            PlanEntry {
+                name: "buffer pool",
+                count: 1,
+                unit_bytes: gpu_buffer_pool_bytes(feature_dim),
+            },
+            PlanEntry {
+                name: "request ring",
+                count: GPU_DISRUPTOR_SIZE,
+                unit_bytes: size_of::<InferenceEvent>(),
+            },
+            PlanEntry {
+                name: "session outputs",
+                count: SESSION_POOL_SIZE,
+                unit_bytes: MAX_BATCH_VECTORS * size_of::<f32>(),
+            },
+            PlanEntry {
+                name: "response queues",
+                count: io_threads * RESPONSE_QUEUE_SIZE,
+                unit_bytes: size_of::<ResponseReady>(),
+            },
+            PlanEntry {
+                name: "overflow queues",
+                count: io_threads * REQUEST_OVERFLOW_CAPACITY,
+                unit_bytes: MAX_VECTORS_PER_REQUEST * feature_dim * size_of::<f32>(),
+            },
+            PlanEntry {
+                name: "read buffers",
+                count: io_threads * max_connections,
+                unit_bytes: READ_BUF_SIZE,
+            },
+        ];
+        Self {
+            io_threads,
+            max_connections,
+            feature_dim,
+            entries,
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .fold(0usize, |acc, entry| acc.saturating_add(entry.total_bytes()))
+    }
+
+    /// Check the plan against `budget` bytes.
+    ///
+    /// With `shrink`, an over-budget plan lowers the per-thread connection cap (read buffers are
+    /// the only allocation that scales with it) to the largest value that fits.
+    pub fn fit_to_budget(self, budget: usize, shrink: bool) -> Result<Self, BudgetError> {
+        if self.total_bytes() <= budget {
+            return Ok(self);
+        }
+        if !shrink {
+            return Err(BudgetError::OverBudget { plan: self, budget });
+        }
+
+        let minimal = Self::for_server(self.io_threads, 1, self.feature_dim);
+        if minimal.total_bytes() > budget {
+            return Err(BudgetError::CannotShrink {
+                plan: minimal,
+                budget,
+            });
+        }
+        let per_connection = self.io_threads * READ_BUF_SIZE;
+        let fixed = minimal.total_bytes() - per_connection;
+        let max_connections = ((budget - fixed) / per_connection).min(self.max_connections);
+        Ok(Self::for_server(
+            self.io_threads,
+            max_connections,
+            self.feature_dim,
+        ))
+    }
+}
+
+impl fmt::Display for AllocationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "allocation plan (io_threads={}, max_connections={}, feature_dim={}):",
+            self.io_threads, self.max_connections, self.feature_dim
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "  {:<16} {:>9} x {:>9} = {:>10}",
+                entry.name,
+                entry.count,
+                entry.unit_bytes,
+                format_bytes(entry.total_bytes())
+            )?;
+        }
+        write!(
+            f,
+            "  {:<16} {:>34}",
+            "total",
+            format_bytes(self.total_bytes())
+        )
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    format!("{:.1} MiB", bytes as f64 / MIB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllocationPlan, BudgetError};
+    use crate::config::{READ_BUF_SIZE, SLAB_CAPACITY};
+    use crate::constants::FEATURE_DIM;
+
+    #[test]
+    fn total_is_sum_of_entries() {
+        let plan = AllocationPlan::for_server(2, 16, FEATURE_DIM);
+        let sum: usize = plan.entries.iter().map(|e| e.count * e.unit_bytes).sum();
+        assert_eq!(plan.total_bytes(), sum);
+    }
+
+    #[test]
+    fn plan_within_budget_is_unchanged() {
+        let plan = AllocationPlan::for_server(1, 8, FEATURE_DIM);
+        let budget = plan.total_bytes();
+        assert_eq!(plan.clone().fit_to_budget(budget, false), Ok(plan));
+    }
+
+    #[test]
+    fn over_budget_is_refused_without_shrink() {
+        let plan = AllocationPlan::for_server(1, SLAB_CAPACITY, FEATURE_DIM);
+        let budget = plan.total_bytes() - 1;
+        assert!(matches!(
+            plan.fit_to_budget(budget, false),
+            Err(BudgetError::OverBudget { .. })
+        ));
+    }
+
+    #[test]
+    fn shrink_lowers_connection_cap_to_fit() {
+        let plan = AllocationPlan::for_server(2, SLAB_CAPACITY, FEATURE_DIM);
+        let budget = AllocationPlan::for_server(2, 100, FEATURE_DIM).total_bytes() + READ_BUF_SIZE;
+        let shrunk = plan.fit_to_budget(budget, true).expect("shrink should fit");
+        assert_eq!(shrunk.max_connections, 100);
+        assert!(shrunk.total_bytes() <= budget);
+    }
+
+    #[test]
+    fn shrink_fails_when_fixed_allocations_exceed_budget() {
+        let plan = AllocationPlan::for_server(1, SLAB_CAPACITY, FEATURE_DIM);
+        assert!(matches!(
+            plan.fit_to_budget(1024, true),
+            Err(BudgetError::CannotShrink { .. })
+        ));
+    }
+}
@@ -1,11 +1,19 @@
 #[cfg(feature = "metrics")]
 mod imp {
     use std::cell::RefCell;
-    use std::sync::OnceLock;
+    use std::collections::HashMap;
     use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock};
     use std::time::Duration;
 
-    use crate::affinity;
+    use crate::constants::MAX_FEATURE_DIM;
+    use crate::feature_schema::FeatureMask;
+    use crate::pipeline::drift::{DriftMonitor, DriftStats};
+    use crate::pipeline::inline::Placement;
+    use crate::pipeline::response_queue::{QueueOccupancy, ResponseRouter};
+    use crate::protocol::OverloadReason;
+    use crate::request_flow::NonFinitePolicy;
+    use crate::server::control::{FRAMES_PER_READ_BOUNDS, IoThreadSet, ReadFrameCounts};
     use crate::timer::{TimerMetric, TimerRecorder, TimerSnapshot};
 
     // Stall / backpressure (cumulative counts)
@@ -19,6 +27,44 @@ mod imp {
     static WRITE_PARTIAL: AtomicU64 = AtomicU64::new(0);
     static WRITE_EAGAIN: AtomicU64 = AtomicU64::new(0);
     static WRITE_FATAL: AtomicU64 = AtomicU64::new(0);
+    // Overload rejections, per reason (cumulative)
+    static OVERLOAD_RING_FULL: AtomicU64 = AtomicU64::new(0);
+    static OVERLOAD_LARGE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+    static OVERLOAD_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+    static OVERLOAD_CIRCUIT_OPEN: AtomicU64 = AtomicU64::new(0);
+    // Times the circuit breaker opened (cumulative)
+    static CIRCUIT_OPENED: AtomicU64 = AtomicU64::new(0);
+    // Parses stopped at a large request waiting for admission (cumulative)
+    static LARGE_REQUESTS_HELD: AtomicU64 = AtomicU64::new(0);
+    // Slow consumers: reads paused on write backlog, connections evicted (cumulative)
+    static WRITE_BACKLOG_PAUSED: AtomicU64 = AtomicU64::new(0);
+    static SLOW_CONSUMER_EVICTED: AtomicU64 = AtomicU64::new(0);
+    // Connections closed after `--idle-timeout-secs` without traffic (cumulative)
+    static IDLE_CONNECTIONS_CLOSED: AtomicU64 = AtomicU64::new(0);
+    // Scores that reached their IO thread past `--response-ttl-us` or `--request-timeout-us` and
+    // were replaced by a timeout frame (cumulative)
+    static RESPONSES_STALE: AtomicU64 = AtomicU64::new(0);
+    // Request ids past the end of the embedding table, expanded as zeros (cumulative)
+    static EMBEDDING_MISSES: AtomicU64 = AtomicU64::new(0);
+    // Kept frames resent to clients resuming a replay session (cumulative)
+    static RESPONSES_REPLAYED: AtomicU64 = AtomicU64::new(0);
+    // Inline fast path placement decisions, per outcome (cumulative)
+    static PLACEMENT_INLINE: AtomicU64 = AtomicU64::new(0);
+    static PLACEMENT_OFFLOAD_SIZE: AtomicU64 = AtomicU64::new(0);
+    static PLACEMENT_OFFLOAD_BUSY: AtomicU64 = AtomicU64::new(0);
+    // Requests carrying NaN or infinite features, per policy outcome (cumulative)
+    static NON_FINITE_PASSED: AtomicU64 = AtomicU64::new(0);
+    static NON_FINITE_CLAMPED: AtomicU64 = AtomicU64::new(0);
+    static NON_FINITE_REJECTED: AtomicU64 = AtomicU64::new(0);
+    // Feature schema violations, per feature (cumulative)
+    static SCHEMA_VIOLATIONS: [AtomicU64; MAX_FEATURE_DIM] =
+        [const { AtomicU64::new(0) }; MAX_FEATURE_DIM];
+    // Requests parked in an IO thread's overflow queue, and parses stopped by a full one (cumulative)
+    static REQUESTS_PARKED: AtomicU64 = AtomicU64::new(0);
+    static REQUEST_OVERFLOW_FULL: AtomicU64 = AtomicU64::new(0);
+    // Requests sampled to the sample sink, and samples dropped behind a slow writer (cumulative)
+    static SAMPLES_TAKEN: AtomicU64 = AtomicU64::new(0);
+    static SAMPLES_DROPPED: AtomicU64 = AtomicU64::new(0);
     // Throughput (cumulative)
     static REQUESTS_PUBLISHED: AtomicU64 = AtomicU64::new(0);
     static BATCHES_SUBMITTED: AtomicU64 = AtomicU64::new(0);
@@ -30,10 +76,13 @@ mod imp {
     static BATCH_STOP_BACKLOG_EMPTY: AtomicU64 = AtomicU64::new(0);
     static BATCH_STOP_NON_CONTIG: AtomicU64 = AtomicU64::new(0);
     static RESPONSES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+    static RESPONSES_DROPPED: AtomicU64 = AtomicU64::new(0);
     static READ_SUBMITS: AtomicU64 = AtomicU64::new(0);
     static READ_CQES: AtomicU64 = AtomicU64::new(0);
     static READ_BYTES: AtomicU64 = AtomicU64::new(0);
     static READ_NEGATIVE: AtomicU64 = AtomicU64::new(0);
+    static READ_NOBUFS: AtomicU64 = AtomicU64::new(0);
+    static READ_RETRIED: AtomicU64 = AtomicU64::new(0);
     static BYTES_CONSUMED: AtomicU64 = AtomicU64::new(0);
     static WRITE_SQES: AtomicU64 = AtomicU64::new(0);
     static WRITE_CQES: AtomicU64 = AtomicU64::new(0);
@@ -48,12 +97,39 @@ mod imp {
     static IO_CQE_NS: AtomicU64 = AtomicU64::new(0);
     static IO_WAIT_LOOPS: AtomicU64 = AtomicU64::new(0);
     static IO_WAIT_NS: AtomicU64 = AtomicU64::new(0);
+    // IO loop wakes, and which completion kinds each wake carried (cumulative)
+    static IO_WAKES: AtomicU64 = AtomicU64::new(0);
+    static IO_WAKE_ACCEPT: AtomicU64 = AtomicU64::new(0);
+    static IO_WAKE_READ: AtomicU64 = AtomicU64::new(0);
+    static IO_WAKE_WRITE: AtomicU64 = AtomicU64::new(0);
+    static IO_WAKE_NOTIFY: AtomicU64 = AtomicU64::new(0);
+    static IO_WAKE_CONTROL: AtomicU64 = AtomicU64::new(0);
+    // CQEs handled from each IO thread's data ring and accept ring (cumulative)
+    static IO_DATA_RING_CQES: AtomicU64 = AtomicU64::new(0);
+    static IO_ACCEPT_RING_CQES: AtomicU64 = AtomicU64::new(0);
+    // CQEs the kernel dropped from a full completion queue, and ones with no known op (cumulative)
+    static IO_CQES_DROPPED: AtomicU64 = AtomicU64::new(0);
+    static IO_UNKNOWN_CQES: AtomicU64 = AtomicU64::new(0);
+    static MODEL_VERSION: Mutex<Option<Arc<str>>> = Mutex::new(None);
+    // Models swapped in by a reload, and reloads that failed to load or pass the canary
+    // (cumulative)
+    static MODEL_RELOADS: AtomicU64 = AtomicU64::new(0);
+    static MODEL_RELOAD_FAILED: AtomicU64 = AtomicU64::new(0);
+    static SCHEMA_FEATURES: OnceLock<Vec<(usize, String)>> = OnceLock::new();
     static BATCH_TOTAL_NS: OnceLock<TimerMetric> = OnceLock::new();
     static BATCH_WAIT_NS: OnceLock<TimerMetric> = OnceLock::new();
     static BACKLOG_AGE_NS: OnceLock<TimerMetric> = OnceLock::new();
     static PUBLISH_TO_SUBMIT_NS: OnceLock<TimerMetric> = OnceLock::new();
     static PUBLISH_TO_WRITE_SUBMIT_NS: OnceLock<TimerMetric> = OnceLock::new();
     static WRITE_DRAIN_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static IO_ITERATION_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static IO_SQES_PER_SUBMIT: OnceLock<TimerMetric> = OnceLock::new();
+    static POOL_ALLOC_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static POOL_EXHAUSTED_WAIT_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static RECEIVE_TO_PUBLISH_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static RESPONSE_QUEUE_WAIT_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static WRITE_SUBMIT_TO_WRITTEN_NS: OnceLock<TimerMetric> = OnceLock::new();
+    static RECEIVE_TO_WRITTEN_NS: OnceLock<TimerMetric> = OnceLock::new();
     thread_local! {
         static BATCH_TOTAL_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
         static BATCH_WAIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
@@ -61,15 +137,49 @@ mod imp {
         static PUBLISH_TO_SUBMIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
         static PUBLISH_TO_WRITE_SUBMIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
         static WRITE_DRAIN_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static IO_ITERATION_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static IO_SQES_PER_SUBMIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static POOL_ALLOC_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static POOL_EXHAUSTED_WAIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static RECEIVE_TO_PUBLISH_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static RESPONSE_QUEUE_WAIT_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static WRITE_SUBMIT_TO_WRITTEN_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
+        static RECEIVE_TO_WRITTEN_RECORDER: RefCell<Option<TimerRecorder>> = const { RefCell::new(None) };
     }
     // Gauges
     static POOL_MAX_IN_USE: AtomicUsize = AtomicUsize::new(0);
     static REQ_OCC: AtomicUsize = AtomicUsize::new(0);
     static REQ_MAX_OCC: AtomicUsize = AtomicUsize::new(0);
+    static SLOTS_QUARANTINED: AtomicUsize = AtomicUsize::new(0);
 
     #[derive(Clone, Copy)]
     pub struct MetricsSnapshot {
         pub req_ring_full: u64,
+        pub overload_ring_full: u64,
+        pub overload_large_requests: u64,
+        pub overload_timeout: u64,
+        pub overload_circuit_open: u64,
+        pub circuit_opened: u64,
+        pub large_requests_held: u64,
+        pub model_reloads: u64,
+        pub model_reload_failed: u64,
+        pub write_backlog_paused: u64,
+        pub slow_consumer_evicted: u64,
+        pub idle_connections_closed: u64,
+        pub responses_stale: u64,
+        pub embedding_misses: u64,
+        pub responses_replayed: u64,
+        pub placement_inline: u64,
+        pub placement_offload_size: u64,
+        pub placement_offload_busy: u64,
+        pub non_finite_passed: u64,
+        pub non_finite_clamped: u64,
+        pub non_finite_rejected: u64,
+        pub schema_violations: [u64; MAX_FEATURE_DIM],
+        pub requests_parked: u64,
+        pub request_overflow_full: u64,
+        pub samples_taken: u64,
+        pub samples_dropped: u64,
         pub pool_exhausted: u64,
         pub pool_too_large: u64,
         pub requests_published: u64,
@@ -82,10 +192,14 @@ mod imp {
         pub batch_stop_backlog_empty: u64,
         pub batch_stop_non_contig: u64,
         pub responses_written: u64,
+        /// Responses dropped because their response queue stayed full.
+        pub responses_dropped: u64,
         pub read_submits: u64,
         pub read_cqes: u64,
         pub read_bytes: u64,
         pub read_negative: u64,
+        pub read_nobufs: u64,
+        pub read_retried: u64,
         pub bytes_consumed: u64,
         pub write_sqes: u64,
         pub write_cqes: u64,
@@ -100,6 +214,18 @@ mod imp {
         pub io_cqe_ns: u64,
         pub io_wait_loops: u64,
         pub io_wait_ns: u64,
+        pub io_wakes: u64,
+        pub io_wake_accept: u64,
+        pub io_wake_read: u64,
+        pub io_wake_write: u64,
+        pub io_wake_notify: u64,
+        pub io_wake_control: u64,
+        pub io_data_ring_cqes: u64,
+        pub io_accept_ring_cqes: u64,
+        /// Completions the kernel dropped because an IO thread's completion queue was full.
+        pub io_cqes_dropped: u64,
+        /// Completions whose user data named no operation the IO thread submits.
+        pub io_unknown_cqes: u64,
         pub session_waits: u64,
         pub completion_queue_empty_waits: u64,
         pub completion_poll_stalls: u64,
@@ -110,12 +236,111 @@ mod imp {
         pub pool_max_in_use: usize,
         pub req_occ: usize,
         pub req_max_occ: usize,
+        pub slots_quarantined: usize,
     }
 
     pub fn inc_req_ring_full() {
         REQ_RING_FULL.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn inc_overload_rejected(reason: OverloadReason) {
+        match reason {
+            OverloadReason::RingFull => OVERLOAD_RING_FULL.fetch_add(1, Ordering::Relaxed),
+            OverloadReason::LargeRequests => {
+                OVERLOAD_LARGE_REQUESTS.fetch_add(1, Ordering::Relaxed)
+            }
+            OverloadReason::Timeout => OVERLOAD_TIMEOUT.fetch_add(1, Ordering::Relaxed),
+            OverloadReason::CircuitOpen => OVERLOAD_CIRCUIT_OPEN.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn inc_circuit_opened() {
+        CIRCUIT_OPENED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_model_reloads() {
+        MODEL_RELOADS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_model_reload_failed() {
+        MODEL_RELOAD_FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_large_requests_held() {
+        LARGE_REQUESTS_HELD.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_write_backlog_paused() {
+        WRITE_BACKLOG_PAUSED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_slow_consumer_evicted() {
+        SLOW_CONSUMER_EVICTED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_idle_connections_closed() {
+        IDLE_CONNECTIONS_CLOSED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_responses_stale() {
+        RESPONSES_STALE.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_embedding_misses() {
+        EMBEDDING_MISSES.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_responses_replayed(frames: u64) {
+        RESPONSES_REPLAYED.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub fn record_placement(placement: Placement) {
+        let counter = match placement {
+            Placement::Inline => &PLACEMENT_INLINE,
+            Placement::OffloadSize => &PLACEMENT_OFFLOAD_SIZE,
+            Placement::OffloadBusy => &PLACEMENT_OFFLOAD_BUSY,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a request with NaN or infinite features, by what `policy` did with it.
+    pub fn record_non_finite(policy: NonFinitePolicy) {
+        let counter = match policy {
+            NonFinitePolicy::PassThrough => &NON_FINITE_PASSED,
+            NonFinitePolicy::Clamp => &NON_FINITE_CLAMPED,
+            NonFinitePolicy::Reject => &NON_FINITE_REJECTED,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count each feature set in `features`, a mask of schema violations in one vector.
+    pub fn record_schema_violations(features: &FeatureMask) {
+        for (word_index, &word) in features.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                let feature = word_index * 64 + word.trailing_zeros() as usize;
+                SCHEMA_VIOLATIONS[feature].fetch_add(1, Ordering::Relaxed);
+                word &= word - 1;
+            }
+        }
+    }
+
+    pub fn inc_requests_parked() {
+        REQUESTS_PARKED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_request_overflow_full() {
+        REQUEST_OVERFLOW_FULL.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_samples_taken() {
+        SAMPLES_TAKEN.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_samples_dropped() {
+        SAMPLES_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn inc_pool_exhausted() {
         POOL_EXHAUSTED.fetch_add(1, Ordering::Relaxed);
     }
@@ -180,6 +405,15 @@ mod imp {
         }
     }
 
+    /// Connection slots freed but held back from reuse, across IO threads.
+    pub fn add_slots_quarantined(count: usize) {
+        SLOTS_QUARANTINED.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn sub_slots_quarantined(count: usize) {
+        SLOTS_QUARANTINED.fetch_sub(count, Ordering::Relaxed);
+    }
+
     pub fn dec_req_occ() {
         let mut prev = REQ_OCC.load(Ordering::Relaxed);
         loop {
@@ -231,6 +465,10 @@ mod imp {
         RESPONSES_WRITTEN.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn inc_responses_dropped() {
+        RESPONSES_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn inc_read_submits() {
         READ_SUBMITS.fetch_add(1, Ordering::Relaxed);
     }
@@ -247,6 +485,16 @@ mod imp {
         READ_NEGATIVE.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// A read found every provided buffer taken and was retried into a connection buffer.
+    pub fn inc_read_nobufs() {
+        READ_NOBUFS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A read failed with a transient error and was submitted again.
+    pub fn inc_read_retried() {
+        READ_RETRIED.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn add_bytes_consumed(count: u64) {
         BYTES_CONSUMED.fetch_add(count, Ordering::Relaxed);
     }
@@ -288,6 +536,42 @@ mod imp {
         IO_WAIT_NS.fetch_add(ns, Ordering::Relaxed);
     }
 
+    /// Count one IO loop wake; `reasons` is a mask of [`super::wake`] bits.
+    pub fn record_io_wake(reasons: u8) {
+        IO_WAKES.fetch_add(1, Ordering::Relaxed);
+        for (bit, counter) in [
+            (super::wake::ACCEPT, &IO_WAKE_ACCEPT),
+            (super::wake::READ, &IO_WAKE_READ),
+            (super::wake::WRITE, &IO_WAKE_WRITE),
+            (super::wake::NOTIFY, &IO_WAKE_NOTIFY),
+            (super::wake::CONTROL, &IO_WAKE_CONTROL),
+        ] {
+            if reasons & bit != 0 {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Count CQEs handled from an IO thread's data ring and accept ring.
+    pub fn record_io_ring_cqes(data: u64, accept: u64) {
+        if data > 0 {
+            IO_DATA_RING_CQES.fetch_add(data, Ordering::Relaxed);
+        }
+        if accept > 0 {
+            IO_ACCEPT_RING_CQES.fetch_add(accept, Ordering::Relaxed);
+        }
+    }
+
+    /// Count completions the kernel dropped from a full completion queue.
+    pub fn add_io_cqes_dropped(count: u64) {
+        IO_CQES_DROPPED.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Count a completion whose user data named no known operation.
+    pub fn inc_io_unknown_cqes() {
+        IO_UNKNOWN_CQES.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn batch_total_timer() -> &'static TimerMetric {
         BATCH_TOTAL_NS.get_or_init(TimerMetric::new)
     }
@@ -312,6 +596,38 @@ mod imp {
         WRITE_DRAIN_NS.get_or_init(TimerMetric::new)
     }
 
+    fn io_iteration_timer() -> &'static TimerMetric {
+        IO_ITERATION_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn io_sqes_per_submit_hist() -> &'static TimerMetric {
+        IO_SQES_PER_SUBMIT.get_or_init(TimerMetric::new)
+    }
+
+    fn pool_alloc_timer() -> &'static TimerMetric {
+        POOL_ALLOC_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn pool_exhausted_wait_timer() -> &'static TimerMetric {
+        POOL_EXHAUSTED_WAIT_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn receive_to_publish_timer() -> &'static TimerMetric {
+        RECEIVE_TO_PUBLISH_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn response_queue_wait_timer() -> &'static TimerMetric {
+        RESPONSE_QUEUE_WAIT_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn write_submit_to_written_timer() -> &'static TimerMetric {
+        WRITE_SUBMIT_TO_WRITTEN_NS.get_or_init(TimerMetric::new)
+    }
+
+    fn receive_to_written_timer() -> &'static TimerMetric {
+        RECEIVE_TO_WRITTEN_NS.get_or_init(TimerMetric::new)
+    }
+
     pub fn record_batch_total(duration: Duration) {
         BATCH_TOTAL_RECORDER.with(|slot| {
             let mut slot = slot.borrow_mut();
@@ -352,6 +668,42 @@ mod imp {
         });
     }
 
+    /// Time from the read that completed a request to its publish to the request ring.
+    pub fn record_receive_to_publish(duration: Duration) {
+        RECEIVE_TO_PUBLISH_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| receive_to_publish_timer().recorder());
+            recorder.record_duration(duration);
+        });
+    }
+
+    /// Time a response waited in its IO thread's response queue.
+    pub fn record_response_queue_wait(duration: Duration) {
+        RESPONSE_QUEUE_WAIT_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| response_queue_wait_timer().recorder());
+            recorder.record_duration(duration);
+        });
+    }
+
+    /// Time from submitting the write carrying a frame to the write completing.
+    pub fn record_write_submit_to_written(duration: Duration) {
+        WRITE_SUBMIT_TO_WRITTEN_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| write_submit_to_written_timer().recorder());
+            recorder.record_duration(duration);
+        });
+    }
+
+    /// Time from the read that completed a request to the write of its answer completing.
+    pub fn record_receive_to_written(duration: Duration) {
+        RECEIVE_TO_WRITTEN_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| receive_to_written_timer().recorder());
+            recorder.record_duration(duration);
+        });
+    }
+
     pub fn record_write_drain(duration: Duration) {
         WRITE_DRAIN_RECORDER.with(|slot| {
             let mut slot = slot.borrow_mut();
@@ -360,6 +712,57 @@ mod imp {
         });
     }
 
+    /// Busy time of one IO loop iteration, excluding time blocked waiting for completions.
+    pub fn record_io_iteration(duration: Duration) {
+        IO_ITERATION_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| io_iteration_timer().recorder());
+            recorder.record_duration(duration);
+        });
+    }
+
+    /// SQEs handed to the kernel by one submit.
+    pub fn record_io_sqes_submitted(sqes: u64) {
+        IO_SQES_PER_SUBMIT_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| io_sqes_per_submit_hist().recorder());
+            recorder.record_nanos(sqes);
+        });
+    }
+
+    /// One successful `BufferPool` allocation, in `clock::ticks`.
+    pub fn record_pool_alloc_ticks(ticks: u64) {
+        POOL_ALLOC_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| pool_alloc_timer().recorder());
+            recorder.record_nanos(crate::clock::ticks_to_ns(ticks));
+        });
+    }
+
+    /// Time spent retrying an exhausted pool until the allocation succeeded, in `clock::ticks`.
+    pub fn record_pool_exhausted_wait_ticks(ticks: u64) {
+        POOL_EXHAUSTED_WAIT_RECORDER.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let recorder = slot.get_or_insert_with(|| pool_exhausted_wait_timer().recorder());
+            recorder.record_nanos(crate::clock::ticks_to_ns(ticks));
+        });
+    }
+
+    /// Label reports with the served model's version, replacing it when a reload swaps in
+    /// another model.
+    pub fn set_model_version(version: &str) {
+        *MODEL_VERSION.lock().unwrap() = Some(Arc::from(version));
+    }
+
+    pub fn model_version() -> Option<Arc<str>> {
+        MODEL_VERSION.lock().unwrap().clone()
+    }
+
+    /// Report schema violations for these features, by index and name.
+    pub fn set_schema_features(features: Vec<(usize, String)>) {
+        let _ = SCHEMA_FEATURES.set(features);
+    }
+
     pub fn idle_timers() {
         // No-op. Timer snapshots use bounded refresh timeouts instead of dropping recorders
         // on transient idle phases, which was perturbing the completion hot path.
@@ -368,6 +771,33 @@ mod imp {
     pub fn snapshot() -> MetricsSnapshot {
         MetricsSnapshot {
             req_ring_full: REQ_RING_FULL.load(Ordering::Relaxed),
+            overload_ring_full: OVERLOAD_RING_FULL.load(Ordering::Relaxed),
+            overload_large_requests: OVERLOAD_LARGE_REQUESTS.load(Ordering::Relaxed),
+            overload_timeout: OVERLOAD_TIMEOUT.load(Ordering::Relaxed),
+            overload_circuit_open: OVERLOAD_CIRCUIT_OPEN.load(Ordering::Relaxed),
+            circuit_opened: CIRCUIT_OPENED.load(Ordering::Relaxed),
+            large_requests_held: LARGE_REQUESTS_HELD.load(Ordering::Relaxed),
+            model_reloads: MODEL_RELOADS.load(Ordering::Relaxed),
+            model_reload_failed: MODEL_RELOAD_FAILED.load(Ordering::Relaxed),
+            write_backlog_paused: WRITE_BACKLOG_PAUSED.load(Ordering::Relaxed),
+            slow_consumer_evicted: SLOW_CONSUMER_EVICTED.load(Ordering::Relaxed),
+            idle_connections_closed: IDLE_CONNECTIONS_CLOSED.load(Ordering::Relaxed),
+            responses_stale: RESPONSES_STALE.load(Ordering::Relaxed),
+            embedding_misses: EMBEDDING_MISSES.load(Ordering::Relaxed),
+            responses_replayed: RESPONSES_REPLAYED.load(Ordering::Relaxed),
+            placement_inline: PLACEMENT_INLINE.load(Ordering::Relaxed),
+            placement_offload_size: PLACEMENT_OFFLOAD_SIZE.load(Ordering::Relaxed),
+            placement_offload_busy: PLACEMENT_OFFLOAD_BUSY.load(Ordering::Relaxed),
+            non_finite_passed: NON_FINITE_PASSED.load(Ordering::Relaxed),
+            non_finite_clamped: NON_FINITE_CLAMPED.load(Ordering::Relaxed),
+            non_finite_rejected: NON_FINITE_REJECTED.load(Ordering::Relaxed),
+            schema_violations: std::array::from_fn(|i| {
+                SCHEMA_VIOLATIONS[i].load(Ordering::Relaxed)
+            }),
+            requests_parked: REQUESTS_PARKED.load(Ordering::Relaxed),
+            request_overflow_full: REQUEST_OVERFLOW_FULL.load(Ordering::Relaxed),
+            samples_taken: SAMPLES_TAKEN.load(Ordering::Relaxed),
+            samples_dropped: SAMPLES_DROPPED.load(Ordering::Relaxed),
             pool_exhausted: POOL_EXHAUSTED.load(Ordering::Relaxed),
             pool_too_large: POOL_TOO_LARGE.load(Ordering::Relaxed),
             requests_published: REQUESTS_PUBLISHED.load(Ordering::Relaxed),
@@ -380,10 +810,13 @@ mod imp {
             batch_stop_backlog_empty: BATCH_STOP_BACKLOG_EMPTY.load(Ordering::Relaxed),
             batch_stop_non_contig: BATCH_STOP_NON_CONTIG.load(Ordering::Relaxed),
             responses_written: RESPONSES_WRITTEN.load(Ordering::Relaxed),
+            responses_dropped: RESPONSES_DROPPED.load(Ordering::Relaxed),
             read_submits: READ_SUBMITS.load(Ordering::Relaxed),
             read_cqes: READ_CQES.load(Ordering::Relaxed),
             read_bytes: READ_BYTES.load(Ordering::Relaxed),
             read_negative: READ_NEGATIVE.load(Ordering::Relaxed),
+            read_nobufs: READ_NOBUFS.load(Ordering::Relaxed),
+            read_retried: READ_RETRIED.load(Ordering::Relaxed),
             bytes_consumed: BYTES_CONSUMED.load(Ordering::Relaxed),
             write_sqes: WRITE_SQES.load(Ordering::Relaxed),
             write_cqes: WRITE_CQES.load(Ordering::Relaxed),
@@ -398,6 +831,16 @@ mod imp {
             io_cqe_ns: IO_CQE_NS.load(Ordering::Relaxed),
             io_wait_loops: IO_WAIT_LOOPS.load(Ordering::Relaxed),
             io_wait_ns: IO_WAIT_NS.load(Ordering::Relaxed),
+            io_wakes: IO_WAKES.load(Ordering::Relaxed),
+            io_wake_accept: IO_WAKE_ACCEPT.load(Ordering::Relaxed),
+            io_wake_read: IO_WAKE_READ.load(Ordering::Relaxed),
+            io_wake_write: IO_WAKE_WRITE.load(Ordering::Relaxed),
+            io_wake_notify: IO_WAKE_NOTIFY.load(Ordering::Relaxed),
+            io_wake_control: IO_WAKE_CONTROL.load(Ordering::Relaxed),
+            io_data_ring_cqes: IO_DATA_RING_CQES.load(Ordering::Relaxed),
+            io_accept_ring_cqes: IO_ACCEPT_RING_CQES.load(Ordering::Relaxed),
+            io_cqes_dropped: IO_CQES_DROPPED.load(Ordering::Relaxed),
+            io_unknown_cqes: IO_UNKNOWN_CQES.load(Ordering::Relaxed),
             session_waits: SESSION_WAITS.load(Ordering::Relaxed),
             completion_queue_empty_waits: COMPLETION_QUEUE_EMPTY_WAITS.load(Ordering::Relaxed),
             completion_poll_stalls: COMPLETION_POLL_STALLS.load(Ordering::Relaxed),
@@ -408,180 +851,584 @@ mod imp {
             pool_max_in_use: POOL_MAX_IN_USE.load(Ordering::Relaxed),
             req_occ: REQ_OCC.load(Ordering::Relaxed),
             req_max_occ: REQ_MAX_OCC.load(Ordering::Relaxed),
+            slots_quarantined: SLOTS_QUARANTINED.load(Ordering::Relaxed),
         }
     }
 
-    pub fn spawn_reporter(interval_secs: u64, metrics_cpu: Option<usize>) {
-        assert!(interval_secs > 0, "metrics interval must be > 0");
-        std::thread::Builder::new()
-            .name("metrics".into())
-            .spawn(move || {
-                if let Some(cpu) = metrics_cpu {
-                    affinity::pin_current_thread(cpu, "metrics")
-                        .unwrap_or_else(|e| panic!("{e}"));
-                }
-                let mut last_snap = snapshot();
-                loop {
-                    std::thread::sleep(Duration::from_secs(interval_secs));
-                    let snap = snapshot();
-                    let req_full_d = snap.req_ring_full.saturating_sub(last_snap.req_ring_full);
-                    let pool_exh_d = snap.pool_exhausted.saturating_sub(last_snap.pool_exhausted);
-                    let pool_tl_d = snap.pool_too_large.saturating_sub(last_snap.pool_too_large);
-                    let req_pub_d = snap
-                        .requests_published
-                        .saturating_sub(last_snap.requests_published);
-                    let batches_submitted_d = snap
-                        .batches_submitted
-                        .saturating_sub(last_snap.batches_submitted);
-                    let vectors_submitted_d = snap
-                        .vectors_submitted
-                        .saturating_sub(last_snap.vectors_submitted);
-                    let batches_completed_d = snap
-                        .batches_completed
-                        .saturating_sub(last_snap.batches_completed);
-                    let slots_submitted_d = snap
-                        .slots_submitted
-                        .saturating_sub(last_snap.slots_submitted);
-                    let backlog_slots_at_build_d = snap
-                        .backlog_slots_at_build
-                        .saturating_sub(last_snap.backlog_slots_at_build);
-                    let batch_stop_cap_d =
-                        snap.batch_stop_cap.saturating_sub(last_snap.batch_stop_cap);
-                    let batch_stop_backlog_empty_d = snap
-                        .batch_stop_backlog_empty
-                        .saturating_sub(last_snap.batch_stop_backlog_empty);
-                    let batch_stop_non_contig_d = snap
-                        .batch_stop_non_contig
-                        .saturating_sub(last_snap.batch_stop_non_contig);
-                    let responses_written_d = snap
-                        .responses_written
-                        .saturating_sub(last_snap.responses_written);
-                    let read_submits_d = snap.read_submits.saturating_sub(last_snap.read_submits);
-                    let read_cqes_d = snap.read_cqes.saturating_sub(last_snap.read_cqes);
-                    let read_bytes_d = snap.read_bytes.saturating_sub(last_snap.read_bytes);
-                    let read_negative_d =
-                        snap.read_negative.saturating_sub(last_snap.read_negative);
-                    let bytes_consumed_d =
-                        snap.bytes_consumed.saturating_sub(last_snap.bytes_consumed);
-                    let write_sqes_d = snap.write_sqes.saturating_sub(last_snap.write_sqes);
-                    let write_cqes_d = snap.write_cqes.saturating_sub(last_snap.write_cqes);
-                    let write_negative_d =
-                        snap.write_negative.saturating_sub(last_snap.write_negative);
-                    let io_response_drain_loops_d = snap
-                        .io_response_drain_loops
-                        .saturating_sub(last_snap.io_response_drain_loops);
-                    let io_response_drain_ns_d = snap
-                        .io_response_drain_ns
-                        .saturating_sub(last_snap.io_response_drain_ns);
-                    let io_write_submit_loops_d = snap
-                        .io_write_submit_loops
-                        .saturating_sub(last_snap.io_write_submit_loops);
-                    let io_write_submit_ns_d = snap
-                        .io_write_submit_ns
-                        .saturating_sub(last_snap.io_write_submit_ns);
-                    let io_parse_loops_d =
-                        snap.io_parse_loops.saturating_sub(last_snap.io_parse_loops);
-                    let io_parse_ns_d =
-                        snap.io_parse_ns.saturating_sub(last_snap.io_parse_ns);
-                    let io_cqe_loops_d =
-                        snap.io_cqe_loops.saturating_sub(last_snap.io_cqe_loops);
-                    let io_cqe_ns_d = snap.io_cqe_ns.saturating_sub(last_snap.io_cqe_ns);
-                    let io_wait_loops_d =
-                        snap.io_wait_loops.saturating_sub(last_snap.io_wait_loops);
-                    let io_wait_ns_d =
-                        snap.io_wait_ns.saturating_sub(last_snap.io_wait_ns);
-                    let session_waits_d = snap
-                        .session_waits
-                        .saturating_sub(last_snap.session_waits);
-                    let completion_queue_empty_waits_d = snap
-                        .completion_queue_empty_waits
-                        .saturating_sub(last_snap.completion_queue_empty_waits);
-                    let completion_poll_stalls_d = snap
-                        .completion_poll_stalls
-                        .saturating_sub(last_snap.completion_poll_stalls);
-                    let write_drain_waits_d = snap
-                        .write_drain_waits
-                        .saturating_sub(last_snap.write_drain_waits);
-                    let write_partial_d =
-                        snap.write_partial.saturating_sub(last_snap.write_partial);
-                    let write_eagain_d =
-                        snap.write_eagain.saturating_sub(last_snap.write_eagain);
-                    let write_fatal_d = snap.write_fatal.saturating_sub(last_snap.write_fatal);
-                    let batch_total = batch_total_timer().snapshot_and_reset();
-                    let batch_wait = batch_wait_timer().snapshot_and_reset();
-                    let backlog_age = backlog_age_timer().snapshot_and_reset();
-                    let publish_to_submit = publish_to_submit_timer().snapshot_and_reset();
-                    let publish_to_write_submit =
-                        publish_to_write_submit_timer().snapshot_and_reset();
-                    let write_drain = write_drain_timer().snapshot_and_reset();
-                    println!("--- metrics {}s ---", interval_secs);
-                    println!(
-                        "  throughput:  req_pub={} batches_sub={} batches_cmp={} slots={} backlog={} vectors={} responses={}",
-                        req_pub_d, batches_submitted_d, batches_completed_d,
-                        slots_submitted_d, backlog_slots_at_build_d,
-                        vectors_submitted_d, responses_written_d,
-                    );
-                    println!(
-                        "  batch_build: stop_cap={} stop_empty={} stop_noncontig={}",
-                        batch_stop_cap_d, batch_stop_backlog_empty_d, batch_stop_non_contig_d,
-                    );
-                    println!(
-                        "  reads:       submits={} cqes={} bytes={} neg={} consumed={}",
-                        read_submits_d, read_cqes_d, read_bytes_d, read_negative_d, bytes_consumed_d,
-                    );
-                    println!(
-                        "  writes:      sqes={} cqes={} neg={} partial={} eagain={} fatal={} drain_waits={}",
-                        write_sqes_d, write_cqes_d, write_negative_d,
-                        write_partial_d, write_eagain_d, write_fatal_d, write_drain_waits_d,
-                    );
-                    let io_total_ns_d = io_response_drain_ns_d
-                        .saturating_add(io_write_submit_ns_d)
-                        .saturating_add(io_parse_ns_d)
-                        .saturating_add(io_cqe_ns_d)
-                        .saturating_add(io_wait_ns_d);
-                    println!(
-                        "  io_loop:     resp={} ({}) write={} ({}) parse={} ({}) cqe={} ({}) wait={} ({})",
-                        io_response_drain_loops_d,
-                        format_phase_share(
-                            io_response_drain_ns_d,
-                            io_response_drain_loops_d,
-                            io_total_ns_d,
-                        ),
-                        io_write_submit_loops_d,
-                        format_phase_share(
-                            io_write_submit_ns_d,
-                            io_write_submit_loops_d,
-                            io_total_ns_d,
-                        ),
-                        io_parse_loops_d,
-                        format_phase_share(io_parse_ns_d, io_parse_loops_d, io_total_ns_d),
-                        io_cqe_loops_d,
-                        format_phase_share(io_cqe_ns_d, io_cqe_loops_d, io_total_ns_d),
-                        io_wait_loops_d,
-                        format_phase_share(io_wait_ns_d, io_wait_loops_d, io_total_ns_d),
-                    );
-                    println!(
-                        "  stalls:      ring_full={} pool_exh={} pool_too_large={} session_waits={} cq_empty_waits={} poll_stalls={}",
-                        req_full_d, pool_exh_d, pool_tl_d,
-                        session_waits_d, completion_queue_empty_waits_d, completion_poll_stalls_d,
-                    );
-                    println!(
-                        "  gauges:      req_occ={} req_max={} pool_max={}",
-                        snap.req_occ, snap.req_max_occ, snap.pool_max_in_use,
-                    );
+    /// Prints interval deltas of every counter and the timer histograms. Driven by the
+    /// control-plane thread; see `server::control_plane`.
+    pub struct Reporter {
+        last_snap: MetricsSnapshot,
+        response_queues: Option<Arc<ResponseRouter>>,
+        last_response_queues: HashMap<u8, QueueOccupancy>,
+        io_threads: Option<Arc<IoThreadSet>>,
+        last_read_frames: HashMap<u8, ReadFrameCounts>,
+        drift: Option<(Arc<DriftMonitor>, DriftStats)>,
+    }
+
+    impl Reporter {
+        pub fn new() -> Self {
+            Self {
+                last_snap: snapshot(),
+                response_queues: None,
+                last_response_queues: HashMap::new(),
+                io_threads: None,
+                last_read_frames: HashMap::new(),
+                drift: None,
+            }
+        }
+
+        /// Also report occupancy of each IO thread's response queue.
+        pub fn with_response_queues(mut self, router: Arc<ResponseRouter>) -> Self {
+            self.response_queues = Some(router);
+            self
+        }
+
+        /// Also report frames per socket read for each IO thread.
+        pub fn with_io_threads(mut self, threads: Arc<IoThreadSet>) -> Self {
+            self.io_threads = Some(threads);
+            self
+        }
+
+        /// Also report how far each feature's mean moved this interval.
+        pub fn with_drift_monitor(mut self, monitor: Arc<DriftMonitor>) -> Self {
+            let last = monitor.snapshot();
+            self.drift = Some((monitor, last));
+            self
+        }
+
+        /// Print the deltas since the previous report; `interval_secs` labels the block.
+        pub fn report(&mut self, interval_secs: u64) {
+            let snap = snapshot();
+            let req_full_d = snap
+                .req_ring_full
+                .saturating_sub(self.last_snap.req_ring_full);
+            let overload_ring_full_d = snap
+                .overload_ring_full
+                .saturating_sub(self.last_snap.overload_ring_full);
+            let overload_large_requests_d = snap
+                .overload_large_requests
+                .saturating_sub(self.last_snap.overload_large_requests);
+            let overload_timeout_d = snap
+                .overload_timeout
+                .saturating_sub(self.last_snap.overload_timeout);
+            let overload_circuit_open_d = snap
+                .overload_circuit_open
+                .saturating_sub(self.last_snap.overload_circuit_open);
+            let circuit_opened_d = snap
+                .circuit_opened
+                .saturating_sub(self.last_snap.circuit_opened);
+            let large_requests_held_d = snap
+                .large_requests_held
+                .saturating_sub(self.last_snap.large_requests_held);
+            let model_reloads_d = snap
+                .model_reloads
+                .saturating_sub(self.last_snap.model_reloads);
+            let model_reload_failed_d = snap
+                .model_reload_failed
+                .saturating_sub(self.last_snap.model_reload_failed);
+            let write_backlog_paused_d = snap
+                .write_backlog_paused
+                .saturating_sub(self.last_snap.write_backlog_paused);
+            let slow_consumer_evicted_d = snap
+                .slow_consumer_evicted
+                .saturating_sub(self.last_snap.slow_consumer_evicted);
+            let idle_connections_closed_d = snap
+                .idle_connections_closed
+                .saturating_sub(self.last_snap.idle_connections_closed);
+            let responses_stale_d = snap
+                .responses_stale
+                .saturating_sub(self.last_snap.responses_stale);
+            let embedding_misses_d = snap
+                .embedding_misses
+                .saturating_sub(self.last_snap.embedding_misses);
+            let responses_replayed_d = snap
+                .responses_replayed
+                .saturating_sub(self.last_snap.responses_replayed);
+            let placement_inline_d = snap
+                .placement_inline
+                .saturating_sub(self.last_snap.placement_inline);
+            let placement_offload_size_d = snap
+                .placement_offload_size
+                .saturating_sub(self.last_snap.placement_offload_size);
+            let placement_offload_busy_d = snap
+                .placement_offload_busy
+                .saturating_sub(self.last_snap.placement_offload_busy);
+            let non_finite_passed_d = snap
+                .non_finite_passed
+                .saturating_sub(self.last_snap.non_finite_passed);
+            let non_finite_clamped_d = snap
+                .non_finite_clamped
+                .saturating_sub(self.last_snap.non_finite_clamped);
+            let non_finite_rejected_d = snap
+                .non_finite_rejected
+                .saturating_sub(self.last_snap.non_finite_rejected);
+            let requests_parked_d = snap
+                .requests_parked
+                .saturating_sub(self.last_snap.requests_parked);
+            let request_overflow_full_d = snap
+                .request_overflow_full
+                .saturating_sub(self.last_snap.request_overflow_full);
+            let samples_taken_d = snap
+                .samples_taken
+                .saturating_sub(self.last_snap.samples_taken);
+            let samples_dropped_d = snap
+                .samples_dropped
+                .saturating_sub(self.last_snap.samples_dropped);
+            let pool_exh_d = snap
+                .pool_exhausted
+                .saturating_sub(self.last_snap.pool_exhausted);
+            let pool_tl_d = snap
+                .pool_too_large
+                .saturating_sub(self.last_snap.pool_too_large);
+            let req_pub_d = snap
+                .requests_published
+                .saturating_sub(self.last_snap.requests_published);
+            let batches_submitted_d = snap
+                .batches_submitted
+                .saturating_sub(self.last_snap.batches_submitted);
+            let vectors_submitted_d = snap
+                .vectors_submitted
+                .saturating_sub(self.last_snap.vectors_submitted);
+            let batches_completed_d = snap
+                .batches_completed
+                .saturating_sub(self.last_snap.batches_completed);
+            let slots_submitted_d = snap
+                .slots_submitted
+                .saturating_sub(self.last_snap.slots_submitted);
+            let backlog_slots_at_build_d = snap
+                .backlog_slots_at_build
+                .saturating_sub(self.last_snap.backlog_slots_at_build);
+            let batch_stop_cap_d = snap
+                .batch_stop_cap
+                .saturating_sub(self.last_snap.batch_stop_cap);
+            let batch_stop_backlog_empty_d = snap
+                .batch_stop_backlog_empty
+                .saturating_sub(self.last_snap.batch_stop_backlog_empty);
+            let batch_stop_non_contig_d = snap
+                .batch_stop_non_contig
+                .saturating_sub(self.last_snap.batch_stop_non_contig);
+            let responses_written_d = snap
+                .responses_written
+                .saturating_sub(self.last_snap.responses_written);
+            let responses_dropped_d = snap
+                .responses_dropped
+                .saturating_sub(self.last_snap.responses_dropped);
+            let read_submits_d = snap
+                .read_submits
+                .saturating_sub(self.last_snap.read_submits);
+            let read_cqes_d = snap.read_cqes.saturating_sub(self.last_snap.read_cqes);
+            let read_bytes_d = snap.read_bytes.saturating_sub(self.last_snap.read_bytes);
+            let read_negative_d = snap
+                .read_negative
+                .saturating_sub(self.last_snap.read_negative);
+            let read_nobufs_d = snap.read_nobufs.saturating_sub(self.last_snap.read_nobufs);
+            let read_retried_d = snap
+                .read_retried
+                .saturating_sub(self.last_snap.read_retried);
+            let bytes_consumed_d = snap
+                .bytes_consumed
+                .saturating_sub(self.last_snap.bytes_consumed);
+            let write_sqes_d = snap.write_sqes.saturating_sub(self.last_snap.write_sqes);
+            let write_cqes_d = snap.write_cqes.saturating_sub(self.last_snap.write_cqes);
+            let write_negative_d = snap
+                .write_negative
+                .saturating_sub(self.last_snap.write_negative);
+            let io_response_drain_loops_d = snap
+                .io_response_drain_loops
+                .saturating_sub(self.last_snap.io_response_drain_loops);
+            let io_response_drain_ns_d = snap
+                .io_response_drain_ns
+                .saturating_sub(self.last_snap.io_response_drain_ns);
+            let io_write_submit_loops_d = snap
+                .io_write_submit_loops
+                .saturating_sub(self.last_snap.io_write_submit_loops);
+            let io_write_submit_ns_d = snap
+                .io_write_submit_ns
+                .saturating_sub(self.last_snap.io_write_submit_ns);
+            let io_parse_loops_d = snap
+                .io_parse_loops
+                .saturating_sub(self.last_snap.io_parse_loops);
+            let io_parse_ns_d = snap.io_parse_ns.saturating_sub(self.last_snap.io_parse_ns);
+            let io_cqe_loops_d = snap
+                .io_cqe_loops
+                .saturating_sub(self.last_snap.io_cqe_loops);
+            let io_cqe_ns_d = snap.io_cqe_ns.saturating_sub(self.last_snap.io_cqe_ns);
+            let io_wait_loops_d = snap
+                .io_wait_loops
+                .saturating_sub(self.last_snap.io_wait_loops);
+            let io_wait_ns_d = snap.io_wait_ns.saturating_sub(self.last_snap.io_wait_ns);
+            let session_waits_d = snap
+                .session_waits
+                .saturating_sub(self.last_snap.session_waits);
+            let completion_queue_empty_waits_d = snap
+                .completion_queue_empty_waits
+                .saturating_sub(self.last_snap.completion_queue_empty_waits);
+            let completion_poll_stalls_d = snap
+                .completion_poll_stalls
+                .saturating_sub(self.last_snap.completion_poll_stalls);
+            let write_drain_waits_d = snap
+                .write_drain_waits
+                .saturating_sub(self.last_snap.write_drain_waits);
+            let write_partial_d = snap
+                .write_partial
+                .saturating_sub(self.last_snap.write_partial);
+            let write_eagain_d = snap
+                .write_eagain
+                .saturating_sub(self.last_snap.write_eagain);
+            let write_fatal_d = snap.write_fatal.saturating_sub(self.last_snap.write_fatal);
+            let batch_total = batch_total_timer().snapshot_and_reset();
+            let batch_wait = batch_wait_timer().snapshot_and_reset();
+            let backlog_age = backlog_age_timer().snapshot_and_reset();
+            let publish_to_submit = publish_to_submit_timer().snapshot_and_reset();
+            let publish_to_write_submit = publish_to_write_submit_timer().snapshot_and_reset();
+            let write_drain = write_drain_timer().snapshot_and_reset();
+            let io_iteration = io_iteration_timer().snapshot_and_reset();
+            let io_sqes_per_submit = io_sqes_per_submit_hist().snapshot_and_reset();
+            let pool_alloc = pool_alloc_timer().snapshot_and_reset();
+            let pool_exhausted_wait = pool_exhausted_wait_timer().snapshot_and_reset();
+            let receive_to_publish = receive_to_publish_timer().snapshot_and_reset();
+            let response_queue_wait = response_queue_wait_timer().snapshot_and_reset();
+            let write_submit_to_written = write_submit_to_written_timer().snapshot_and_reset();
+            let receive_to_written = receive_to_written_timer().snapshot_and_reset();
+            #[cfg(feature = "prometheus")]
+            crate::prometheus::record_interval(&[
+                ("batch_total", batch_total.as_ref()),
+                ("batch_wait", batch_wait.as_ref()),
+                ("backlog_age", backlog_age.as_ref()),
+                ("publish_to_submit", publish_to_submit.as_ref()),
+                ("publish_to_write_submit", publish_to_write_submit.as_ref()),
+                ("write_drain", write_drain.as_ref()),
+                ("io_iteration", io_iteration.as_ref()),
+                ("pool_alloc", pool_alloc.as_ref()),
+                ("pool_exhausted_wait", pool_exhausted_wait.as_ref()),
+                ("receive_to_publish", receive_to_publish.as_ref()),
+                ("response_queue_wait", response_queue_wait.as_ref()),
+                ("write_submit_to_written", write_submit_to_written.as_ref()),
+                ("receive_to_written", receive_to_written.as_ref()),
+            ]);
+            let io_wakes_d = snap.io_wakes.saturating_sub(self.last_snap.io_wakes);
+            let io_wake_accept_d = snap
+                .io_wake_accept
+                .saturating_sub(self.last_snap.io_wake_accept);
+            let io_wake_read_d = snap
+                .io_wake_read
+                .saturating_sub(self.last_snap.io_wake_read);
+            let io_wake_write_d = snap
+                .io_wake_write
+                .saturating_sub(self.last_snap.io_wake_write);
+            let io_wake_notify_d = snap
+                .io_wake_notify
+                .saturating_sub(self.last_snap.io_wake_notify);
+            let io_wake_control_d = snap
+                .io_wake_control
+                .saturating_sub(self.last_snap.io_wake_control);
+            let io_data_ring_cqes_d = snap
+                .io_data_ring_cqes
+                .saturating_sub(self.last_snap.io_data_ring_cqes);
+            let io_accept_ring_cqes_d = snap
+                .io_accept_ring_cqes
+                .saturating_sub(self.last_snap.io_accept_ring_cqes);
+            let io_cqes_dropped_d = snap
+                .io_cqes_dropped
+                .saturating_sub(self.last_snap.io_cqes_dropped);
+            let io_unknown_cqes_d = snap
+                .io_unknown_cqes
+                .saturating_sub(self.last_snap.io_unknown_cqes);
+            println!("--- metrics {}s ---", interval_secs);
+            if let Some(version) = model_version() {
+                if snap.model_reloads + snap.model_reload_failed > 0 {
                     println!(
-                        "  timers:      {} {} {} {} {} {}",
-                        format_timer("backlog_age_us", backlog_age.as_ref()),
-                        format_timer("publish_to_submit_us", publish_to_submit.as_ref()),
-                        format_timer("publish_to_write_submit_us", publish_to_write_submit.as_ref()),
-                        format_timer("batch_total_us", batch_total.as_ref()),
-                        format_timer("batch_wait_us", batch_wait.as_ref()),
-                        format_timer("write_drain_us", write_drain.as_ref()),
+                        "  model:       version={version} reloads={} failed={}",
+                        model_reloads_d, model_reload_failed_d,
                     );
-                    last_snap = snap;
+                } else {
+                    println!("  model:       version={version}");
                 }
+            }
+            println!(
+                "  throughput:  req_pub={} batches_sub={} batches_cmp={} slots={} backlog={} vectors={} responses={} dropped={}",
+                req_pub_d,
+                batches_submitted_d,
+                batches_completed_d,
+                slots_submitted_d,
+                backlog_slots_at_build_d,
+                vectors_submitted_d,
+                responses_written_d,
+                responses_dropped_d,
+            );
+            println!(
+                "  batch_build: stop_cap={} stop_empty={} stop_noncontig={}",
+                batch_stop_cap_d, batch_stop_backlog_empty_d, batch_stop_non_contig_d,
+            );
+            println!(
+                "  reads:       submits={} cqes={} bytes={} neg={} nobufs={} retried={} consumed={}",
+                read_submits_d,
+                read_cqes_d,
+                read_bytes_d,
+                read_negative_d,
+                read_nobufs_d,
+                read_retried_d,
+                bytes_consumed_d,
+            );
+            println!(
+                "  writes:      sqes={} cqes={} neg={} partial={} eagain={} fatal={} drain_waits={}",
+                write_sqes_d,
+                write_cqes_d,
+                write_negative_d,
+                write_partial_d,
+                write_eagain_d,
+                write_fatal_d,
+                write_drain_waits_d,
+            );
+            let io_total_ns_d = io_response_drain_ns_d
+                .saturating_add(io_write_submit_ns_d)
+                .saturating_add(io_parse_ns_d)
+                .saturating_add(io_cqe_ns_d)
+                .saturating_add(io_wait_ns_d);
+            println!(
+                "  io_loop:     resp={} ({}) write={} ({}) parse={} ({}) cqe={} ({}) wait={} ({})",
+                io_response_drain_loops_d,
+                format_phase_share(
+                    io_response_drain_ns_d,
+                    io_response_drain_loops_d,
+                    io_total_ns_d,
+                ),
+                io_write_submit_loops_d,
+                format_phase_share(io_write_submit_ns_d, io_write_submit_loops_d, io_total_ns_d,),
+                io_parse_loops_d,
+                format_phase_share(io_parse_ns_d, io_parse_loops_d, io_total_ns_d),
+                io_cqe_loops_d,
+                format_phase_share(io_cqe_ns_d, io_cqe_loops_d, io_total_ns_d),
+                io_wait_loops_d,
+                format_phase_share(io_wait_ns_d, io_wait_loops_d, io_total_ns_d),
+            );
+            println!(
+                "  io_wakes:    n={} accept={} read={} write={} notify={} control={} {} {}",
+                io_wakes_d,
+                io_wake_accept_d,
+                io_wake_read_d,
+                io_wake_write_d,
+                io_wake_notify_d,
+                io_wake_control_d,
+                format_timer("iteration_us", io_iteration.as_ref()),
+                format_count_hist("sqes_per_submit", io_sqes_per_submit.as_ref()),
+            );
+            let ring_cqes_d = (io_data_ring_cqes_d + io_accept_ring_cqes_d).max(1);
+            println!(
+                "  io_rings:    data_cqes={} accept_cqes={} accept_share={:.1}% dropped={} unknown={}",
+                io_data_ring_cqes_d,
+                io_accept_ring_cqes_d,
+                io_accept_ring_cqes_d as f64 * 100.0 / ring_cqes_d as f64,
+                io_cqes_dropped_d,
+                io_unknown_cqes_d,
+            );
+            println!(
+                "  stalls:      ring_full={} pool_exh={} pool_too_large={} session_waits={} cq_empty_waits={} poll_stalls={}",
+                req_full_d,
+                pool_exh_d,
+                pool_tl_d,
+                session_waits_d,
+                completion_queue_empty_waits_d,
+                completion_poll_stalls_d,
+            );
+            println!(
+                "  pool:        {} {}",
+                format_count_hist("alloc_ns", pool_alloc.as_ref()),
+                format_timer("exhausted_wait_us", pool_exhausted_wait.as_ref()),
+            );
+            println!(
+                "  overload:    ring_full={} large_requests={} timeout={} circuit_open={}",
+                overload_ring_full_d,
+                overload_large_requests_d,
+                overload_timeout_d,
+                overload_circuit_open_d,
+            );
+            if snap.circuit_opened > 0 {
+                println!("  circuit:     opened={}", circuit_opened_d);
+            }
+            if snap.large_requests_held + snap.overload_large_requests > 0 {
+                println!("  large_req:   held={}", large_requests_held_d);
+            }
+            println!(
+                "  overflow:    parked={} full={}",
+                requests_parked_d, request_overflow_full_d,
+            );
+            println!(
+                "  slow_conn:   paused={} evicted={}",
+                write_backlog_paused_d, slow_consumer_evicted_d,
+            );
+            if snap.idle_connections_closed > 0 {
+                println!("  idle_conn:   closed={}", idle_connections_closed_d);
+            }
+            if snap.responses_stale > 0 {
+                println!("  stale_resp:  dropped={}", responses_stale_d);
+            }
+            if snap.embedding_misses > 0 {
+                println!("  embedding:   misses={}", embedding_misses_d);
+            }
+            if snap.responses_replayed > 0 {
+                println!("  replay:      resent={}", responses_replayed_d);
+            }
+            println!(
+                "  placement:   inline={} offload_size={} offload_busy={}",
+                placement_inline_d, placement_offload_size_d, placement_offload_busy_d,
+            );
+            println!(
+                "  non_finite:  passed={} clamped={} rejected={}",
+                non_finite_passed_d, non_finite_clamped_d, non_finite_rejected_d,
+            );
+            if snap.samples_taken + snap.samples_dropped > 0 {
+                println!(
+                    "  sampling:    taken={} dropped={}",
+                    samples_taken_d, samples_dropped_d,
+                );
+            }
+            if let Some(features) = SCHEMA_FEATURES.get() {
+                let counts: Vec<String> = features
+                    .iter()
+                    .map(|(index, name)| {
+                        let count = snap.schema_violations[*index]
+                            .saturating_sub(self.last_snap.schema_violations[*index]);
+                        format!("{name}={count}")
+                    })
+                    .collect();
+                println!("  schema:      {}", counts.join(" "));
+            }
+            if let Some((monitor, last)) = &mut self.drift {
+                let now = monitor.snapshot();
+                println!("  drift:       {}", format_drift(&now, last));
+                *last = now;
+            }
+            println!(
+                "  gauges:      req_occ={} req_max={} pool_max={} quarantined={}",
+                snap.req_occ, snap.req_max_occ, snap.pool_max_in_use, snap.slots_quarantined,
+            );
+            println!(
+                "  timers:      {} {} {} {} {} {}",
+                format_timer("backlog_age_us", backlog_age.as_ref()),
+                format_timer("publish_to_submit_us", publish_to_submit.as_ref()),
+                format_timer(
+                    "publish_to_write_submit_us",
+                    publish_to_write_submit.as_ref()
+                ),
+                format_timer("batch_total_us", batch_total.as_ref()),
+                format_timer("batch_wait_us", batch_wait.as_ref()),
+                format_timer("write_drain_us", write_drain.as_ref()),
+            );
+            println!(
+                "  stages:      {} {} {} {}",
+                format_timer("receive_to_publish_us", receive_to_publish.as_ref()),
+                format_timer("response_queue_us", response_queue_wait.as_ref()),
+                format_timer("write_us", write_submit_to_written.as_ref()),
+                format_timer("receive_to_written_us", receive_to_written.as_ref()),
+            );
+            self.report_response_queues();
+            self.report_read_frames();
+            self.last_snap = snap;
+        }
+
+        /// One line per IO thread: interval and lifetime high-water marks against capacity, and
+        /// how long the producer spent blocked on a full queue this interval.
+        fn report_response_queues(&mut self) {
+            let Some(router) = &self.response_queues else {
+                return;
+            };
+            for (shard_id, queue) in router.registered() {
+                let now = queue.take_occupancy();
+                let last = self
+                    .last_response_queues
+                    .insert(shard_id, now)
+                    .unwrap_or_default();
+                println!(
+                    "  resp_queue:  io-{} cap={} len={} hwm={} hwm_all={} full_waits={} full_ms={:.1}",
+                    shard_id,
+                    now.capacity,
+                    now.len,
+                    now.interval_max_occupancy,
+                    now.max_occupancy,
+                    now.full_waits.saturating_sub(last.full_waits),
+                    now.full_ns.saturating_sub(last.full_ns) as f64 / 1_000_000.0,
+                );
+            }
+        }
+
+        /// One line per IO thread: mean frames per read, the share of reads ending mid-frame, and
+        /// reads per frames-per-read bucket this interval.
+        fn report_read_frames(&mut self) {
+            let Some(threads) = &self.io_threads else {
+                return;
+            };
+            for (thread_id, now) in threads.read_frames() {
+                let last = self
+                    .last_read_frames
+                    .insert(thread_id, now)
+                    .unwrap_or_default();
+                let d = now.saturating_sub(last);
+                let per_read = |count: u64| {
+                    if d.reads == 0 {
+                        0.0
+                    } else {
+                        count as f64 / d.reads as f64
+                    }
+                };
+                let mut lower = 0;
+                let buckets: Vec<String> = d
+                    .buckets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, reads)| {
+                        let label = match FRAMES_PER_READ_BOUNDS.get(i) {
+                            Some(&bound) if bound == lower => bound.to_string(),
+                            Some(&bound) => format!("{lower}-{bound}"),
+                            None => format!("{lower}+"),
+                        };
+                        lower = FRAMES_PER_READ_BOUNDS.get(i).map_or(lower, |b| b + 1);
+                        format!("{label}:{reads}")
+                    })
+                    .collect();
+                println!(
+                    "  read_frames: io-{} reads={} frames/read={:.2} partial={:.1}% [{}]",
+                    thread_id,
+                    d.reads,
+                    per_read(d.frames),
+                    per_read(d.partial) * 100.0,
+                    buckets.join(" "),
+                );
+            }
+        }
+    }
+
+    impl Default for Reporter {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Vectors this interval, then the features whose mean moved furthest from the baseline
+    /// before it, in baseline standard deviations.
+    fn format_drift(now: &DriftStats, baseline: &DriftStats) -> String {
+        const SHOWN: usize = 4;
+        let window = now.moments.since(&baseline.moments);
+        let mut shifts: Vec<(usize, f64)> = (0..now.feature_dim())
+            .filter_map(|i| window.shift(&baseline.moments, i).map(|shift| (i, shift)))
+            .collect();
+        if shifts.is_empty() {
+            return format!("vectors={} (no baseline yet)", window.count);
+        }
+        shifts.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+        let names = SCHEMA_FEATURES.get();
+        let top: Vec<String> = shifts
+            .iter()
+            .take(SHOWN)
+            .map(|&(i, shift)| {
+                let name = names
+                    .and_then(|names| names.iter().find(|(index, _)| *index == i))
+                    .map_or_else(|| format!("f{i}"), |(_, name)| name.clone());
+                format!("{name}={shift:+.2}sd")
             })
-            .expect("failed to spawn metrics reporter");
+            .collect();
+        format!("vectors={} shift: {}", window.count, top.join(" "))
     }
 
     fn format_timer(label: &str, snapshot: Option<&TimerSnapshot>) -> String {
@@ -600,6 +1447,21 @@ mod imp {
         }
     }
 
+    fn format_count_hist(label: &str, snapshot: Option<&TimerSnapshot>) -> String {
+        match snapshot {
+            Some(snapshot) => format!(
+                "{}[n={} p50={} p99={} p99.9={} max={}]",
+                label,
+                snapshot.count(),
+                snapshot.value_at_percentile(50.0),
+                snapshot.value_at_percentile(99.0),
+                snapshot.value_at_percentile(99.9),
+                snapshot.max(),
+            ),
+            None => format!("{}[n=0]", label),
+        }
+    }
+
     fn format_phase_share(phase_ns: u64, phase_loops: u64, total_ns: u64) -> String {
         if total_ns == 0 {
             return "0.0% avg=0.0us".to_string();
@@ -620,6 +1482,31 @@ mod imp {
     #[derive(Clone, Copy)]
     pub struct MetricsSnapshot {
         pub req_ring_full: u64,
+        pub overload_ring_full: u64,
+        pub overload_large_requests: u64,
+        pub overload_timeout: u64,
+        pub overload_circuit_open: u64,
+        pub circuit_opened: u64,
+        pub large_requests_held: u64,
+        pub model_reloads: u64,
+        pub model_reload_failed: u64,
+        pub write_backlog_paused: u64,
+        pub slow_consumer_evicted: u64,
+        pub idle_connections_closed: u64,
+        pub responses_stale: u64,
+        pub embedding_misses: u64,
+        pub responses_replayed: u64,
+        pub placement_inline: u64,
+        pub placement_offload_size: u64,
+        pub placement_offload_busy: u64,
+        pub non_finite_passed: u64,
+        pub non_finite_clamped: u64,
+        pub non_finite_rejected: u64,
+        pub schema_violations: [u64; crate::constants::MAX_FEATURE_DIM],
+        pub requests_parked: u64,
+        pub request_overflow_full: u64,
+        pub samples_taken: u64,
+        pub samples_dropped: u64,
         pub pool_exhausted: u64,
         pub pool_too_large: u64,
         pub requests_published: u64,
@@ -632,10 +1519,14 @@ mod imp {
         pub batch_stop_backlog_empty: u64,
         pub batch_stop_non_contig: u64,
         pub responses_written: u64,
+        /// Responses dropped because their response queue stayed full.
+        pub responses_dropped: u64,
         pub read_submits: u64,
         pub read_cqes: u64,
         pub read_bytes: u64,
         pub read_negative: u64,
+        pub read_nobufs: u64,
+        pub read_retried: u64,
         pub bytes_consumed: u64,
         pub write_sqes: u64,
         pub write_cqes: u64,
@@ -650,6 +1541,16 @@ mod imp {
         pub io_cqe_ns: u64,
         pub io_wait_loops: u64,
         pub io_wait_ns: u64,
+        pub io_wakes: u64,
+        pub io_wake_accept: u64,
+        pub io_wake_read: u64,
+        pub io_wake_write: u64,
+        pub io_wake_notify: u64,
+        pub io_wake_control: u64,
+        pub io_data_ring_cqes: u64,
+        pub io_accept_ring_cqes: u64,
+        pub io_cqes_dropped: u64,
+        pub io_unknown_cqes: u64,
         pub session_waits: u64,
         pub completion_queue_empty_waits: u64,
         pub completion_poll_stalls: u64,
@@ -660,9 +1561,27 @@ mod imp {
         pub pool_max_in_use: usize,
         pub req_occ: usize,
         pub req_max_occ: usize,
+        pub slots_quarantined: usize,
     }
 
     pub fn inc_req_ring_full() {}
+    pub fn inc_overload_rejected(_reason: crate::protocol::OverloadReason) {}
+    pub fn inc_circuit_opened() {}
+    pub fn inc_large_requests_held() {}
+    pub fn inc_model_reloads() {}
+    pub fn inc_model_reload_failed() {}
+    pub fn inc_write_backlog_paused() {}
+    pub fn inc_slow_consumer_evicted() {}
+    pub fn inc_idle_connections_closed() {}
+    pub fn inc_responses_stale() {}
+    pub fn inc_embedding_misses() {}
+    pub fn add_responses_replayed(_: u64) {}
+    pub fn record_placement(_: crate::pipeline::inline::Placement) {}
+    pub fn record_non_finite(_: crate::request_flow::NonFinitePolicy) {}
+    pub fn inc_requests_parked() {}
+    pub fn inc_request_overflow_full() {}
+    pub fn inc_samples_taken() {}
+    pub fn inc_samples_dropped() {}
     pub fn inc_pool_exhausted() {}
     pub fn inc_pool_too_large() {}
     pub fn inc_session_waits() {}
@@ -675,6 +1594,8 @@ mod imp {
     pub fn update_pool_in_use(_: usize) {}
     pub fn inc_req_occ() {}
     pub fn dec_req_occ() {}
+    pub fn add_slots_quarantined(_: usize) {}
+    pub fn sub_slots_quarantined(_: usize) {}
     pub fn inc_requests_published() {}
     pub fn inc_batches_submitted() {}
     pub fn add_vectors_submitted(_: u64) {}
@@ -685,10 +1606,13 @@ mod imp {
     pub fn inc_batch_stop_backlog_empty() {}
     pub fn inc_batch_stop_non_contig() {}
     pub fn inc_responses_written() {}
+    pub fn inc_responses_dropped() {}
     pub fn inc_read_submits() {}
     pub fn inc_read_cqes() {}
     pub fn add_read_bytes(_: u64) {}
     pub fn inc_read_negative() {}
+    pub fn inc_read_nobufs() {}
+    pub fn inc_read_retried() {}
     pub fn add_bytes_consumed(_: u64) {}
     pub fn inc_write_sqes() {}
     pub fn inc_write_cqes() {}
@@ -698,16 +1622,56 @@ mod imp {
     pub fn add_io_parse(_: u64) {}
     pub fn add_io_cqe(_: u64) {}
     pub fn add_io_wait(_: u64) {}
+    pub fn record_io_wake(_: u8) {}
+    pub fn record_io_ring_cqes(_: u64, _: u64) {}
+    pub fn add_io_cqes_dropped(_: u64) {}
+    pub fn inc_io_unknown_cqes() {}
+    pub fn record_io_iteration(_: std::time::Duration) {}
+    pub fn record_io_sqes_submitted(_: u64) {}
+    pub fn record_pool_alloc_ticks(_: u64) {}
+    pub fn record_pool_exhausted_wait_ticks(_: u64) {}
+    pub fn set_model_version(_: &str) {}
+    pub fn set_schema_features(_: Vec<(usize, String)>) {}
+    pub fn record_schema_violations(_: &crate::feature_schema::FeatureMask) {}
     pub fn record_batch_total(_: std::time::Duration) {}
     pub fn record_batch_wait(_: std::time::Duration) {}
     pub fn record_backlog_age(_: std::time::Duration) {}
     pub fn record_publish_to_submit(_: std::time::Duration) {}
     pub fn record_publish_to_write_submit(_: std::time::Duration) {}
     pub fn record_write_drain(_: std::time::Duration) {}
+    pub fn record_receive_to_publish(_: std::time::Duration) {}
+    pub fn record_response_queue_wait(_: std::time::Duration) {}
+    pub fn record_write_submit_to_written(_: std::time::Duration) {}
+    pub fn record_receive_to_written(_: std::time::Duration) {}
     pub fn idle_timers() {}
     pub fn snapshot() -> MetricsSnapshot {
         MetricsSnapshot {
             req_ring_full: 0,
+            overload_ring_full: 0,
+            overload_large_requests: 0,
+            overload_timeout: 0,
+            overload_circuit_open: 0,
+            circuit_opened: 0,
+            large_requests_held: 0,
+            model_reloads: 0,
+            model_reload_failed: 0,
+            write_backlog_paused: 0,
+            slow_consumer_evicted: 0,
+            idle_connections_closed: 0,
+            responses_stale: 0,
+            embedding_misses: 0,
+            responses_replayed: 0,
+            placement_inline: 0,
+            placement_offload_size: 0,
+            placement_offload_busy: 0,
+            non_finite_passed: 0,
+            non_finite_clamped: 0,
+            non_finite_rejected: 0,
+            schema_violations: [0; crate::constants::MAX_FEATURE_DIM],
+            requests_parked: 0,
+            request_overflow_full: 0,
+            samples_taken: 0,
+            samples_dropped: 0,
             pool_exhausted: 0,
             pool_too_large: 0,
             requests_published: 0,
@@ -720,10 +1684,13 @@ mod imp {
             batch_stop_backlog_empty: 0,
             batch_stop_non_contig: 0,
             responses_written: 0,
+            responses_dropped: 0,
             read_submits: 0,
             read_cqes: 0,
             read_bytes: 0,
             read_negative: 0,
+            read_nobufs: 0,
+            read_retried: 0,
             bytes_consumed: 0,
             write_sqes: 0,
             write_cqes: 0,
@@ -738,6 +1705,16 @@ mod imp {
             io_cqe_ns: 0,
             io_wait_loops: 0,
             io_wait_ns: 0,
+            io_wakes: 0,
+            io_wake_accept: 0,
+            io_wake_read: 0,
+            io_wake_write: 0,
+            io_wake_notify: 0,
+            io_wake_control: 0,
+            io_data_ring_cqes: 0,
+            io_accept_ring_cqes: 0,
+            io_cqes_dropped: 0,
+            io_unknown_cqes: 0,
             session_waits: 0,
             completion_queue_empty_waits: 0,
             completion_poll_stalls: 0,
@@ -748,9 +1725,49 @@ mod imp {
             pool_max_in_use: 0,
             req_occ: 0,
             req_max_occ: 0,
+            slots_quarantined: 0,
         }
     }
-    pub fn spawn_reporter(_: u64, _: Option<usize>) {}
+    #[derive(Default)]
+    pub struct Reporter;
+
+    impl Reporter {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn with_response_queues(
+            self,
+            _: std::sync::Arc<crate::pipeline::response_queue::ResponseRouter>,
+        ) -> Self {
+            self
+        }
+
+        pub fn with_io_threads(
+            self,
+            _: std::sync::Arc<crate::server::control::IoThreadSet>,
+        ) -> Self {
+            self
+        }
+
+        pub fn with_drift_monitor(
+            self,
+            _: std::sync::Arc<crate::pipeline::drift::DriftMonitor>,
+        ) -> Self {
+            self
+        }
+
+        pub fn report(&mut self, _: u64) {}
+    }
 }
 
 pub use imp::*;
+
+/// Completion kinds that woke an IO loop iteration, OR-ed together for `record_io_wake`.
+pub mod wake {
+    pub const ACCEPT: u8 = 1 << 0;
+    pub const READ: u8 = 1 << 1;
+    pub const WRITE: u8 = 1 << 2;
+    pub const NOTIFY: u8 = 1 << 3;
+    pub const CONTROL: u8 = 1 << 4;
+}
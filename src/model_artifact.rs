@@ -0,0 +1,304 @@
+//! Versioned model artifacts: a model file plus a manifest checked before the backend loads it.
+//!
+//! The manifest uses the config file's format, one `key = value` per line with `#` comments,
+//! and needs every key:
+//!
+//! ```text
+//! version = fraud-2024-06-01
+//! sha256 = 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! dtype = f32
+//! input_dim = 16
+//! ```
+//!
+//! `dtype` and `input_dim` describe the model's input tensor, so a model exported for another
+//! feature layout is refused at startup instead of failing or mis-scoring its first batch.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// Input element types; only `f32`, what requests carry, can be served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtype {
+    F32,
+}
+
+impl Dtype {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dtype::F32 => "f32",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelManifest {
+    pub version: String,
+    pub sha256: [u8; 32],
+    pub dtype: Dtype,
+    pub input_dim: usize,
+}
+
+impl ModelManifest {
+    pub fn parse(text: &str) -> Result<Self, ArtifactError> {
+        let (mut version, mut sha256, mut dtype, mut input_dim) = (None, None, None, None);
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let manifest_err = |message: String| {
+                ArtifactError::Manifest(format!("line {}: {message}", line_no + 1))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| manifest_err("expected `key = value`".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "version" if !value.is_empty() && !value.contains(char::is_whitespace) => {
+                    version = Some(value.to_string())
+                }
+                "version" => return Err(manifest_err(format!("invalid version '{value}'"))),
+                "sha256" => {
+                    sha256 = Some(
+                        parse_sha256(value)
+                            .ok_or_else(|| manifest_err(format!("invalid sha256 '{value}'")))?,
+                    )
+                }
+                "dtype" => match value {
+                    "f32" | "float32" => dtype = Some(Dtype::F32),
+                    other => return Err(ArtifactError::Dtype(other.to_string())),
+                },
+                "input_dim" => {
+                    input_dim = Some(
+                        value
+                            .parse()
+                            .map_err(|_| manifest_err(format!("invalid input_dim '{value}'")))?,
+                    )
+                }
+                _ => return Err(manifest_err(format!("unknown key '{key}'"))),
+            }
+        }
+        let missing = |key: &str| ArtifactError::Manifest(format!("missing key '{key}'"));
+        Ok(Self {
+            version: version.ok_or_else(|| missing("version"))?,
+            sha256: sha256.ok_or_else(|| missing("sha256"))?,
+            dtype: dtype.ok_or_else(|| missing("dtype"))?,
+            input_dim: input_dim.ok_or_else(|| missing("input_dim"))?,
+        })
+    }
+}
+
+impl fmt::Display for ModelManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "version {} sha256 {} dtype {} input_dim {}",
+            self.version,
+            sha256_hex(&self.sha256),
+            self.dtype.as_str(),
+            self.input_dim
+        )
+    }
+}
+
+/// Model bytes ready for a backend, with the manifest they were checked against, if any.
+pub struct ModelArtifact {
+    pub bytes: Vec<u8>,
+    pub manifest: Option<ModelManifest>,
+}
+
+impl ModelArtifact {
+    /// Read `model` and, when given, check it against the manifest at `manifest` for a server
+    /// taking `feature_dim` features per vector.
+    pub fn load(
+        model: &Path,
+        manifest: Option<&Path>,
+        feature_dim: usize,
+    ) -> Result<Self, ArtifactError> {
+        let read_err = |path: &Path| {
+            let path = path.to_path_buf();
+            move |error| ArtifactError::Read { path, error }
+        };
+        let bytes = std::fs::read(model).map_err(read_err(model))?;
+        match manifest {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(read_err(path))?;
+                Self::verify(ModelManifest::parse(&text)?, bytes, feature_dim)
+            }
+            None => Ok(Self {
+                bytes,
+                manifest: None,
+            }),
+        }
+    }
+
+    /// Accept `bytes` only if they match `manifest` and the manifest takes vectors of
+    /// `feature_dim` features.
+    pub fn verify(
+        manifest: ModelManifest,
+        bytes: Vec<u8>,
+        feature_dim: usize,
+    ) -> Result<Self, ArtifactError> {
+        if manifest.input_dim != feature_dim {
+            return Err(ArtifactError::InputDim {
+                manifest: manifest.input_dim,
+                feature_dim,
+            });
+        }
+        let actual: [u8; 32] = Sha256::digest(&bytes).into();
+        if actual != manifest.sha256 {
+            return Err(ArtifactError::Checksum {
+                expected: manifest.sha256,
+                actual,
+            });
+        }
+        Ok(Self {
+            bytes,
+            manifest: Some(manifest),
+        })
+    }
+
+    /// The manifest's version, or `unversioned` for a model loaded without one.
+    pub fn version(&self) -> &str {
+        self.manifest
+            .as_ref()
+            .map_or("unversioned", |manifest| manifest.version.as_str())
+    }
+}
+
+/// Why a model artifact was refused.
+#[derive(Debug)]
+pub enum ArtifactError {
+    Read {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Manifest(String),
+    /// The manifest names an input dtype requests cannot feed.
+    Dtype(String),
+    /// The model expects a different number of features per vector than `--feature-dim`.
+    InputDim {
+        manifest: usize,
+        feature_dim: usize,
+    },
+    Checksum {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactError::Read { path, error } => write!(f, "read {}: {error}", path.display()),
+            ArtifactError::Manifest(message) => write!(f, "manifest: {message}"),
+            ArtifactError::Dtype(dtype) => {
+                write!(f, "manifest dtype '{dtype}' is not supported, expected f32")
+            }
+            ArtifactError::InputDim {
+                manifest,
+                feature_dim,
+            } => write!(
+                f,
+                "manifest input_dim {manifest} does not match feature dim {feature_dim}"
+            ),
+            ArtifactError::Checksum { expected, actual } => write!(
+                f,
+                "model sha256 {} does not match manifest {}",
+                sha256_hex(actual),
+                sha256_hex(expected)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn sha256_hex(digest: &[u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::{ArtifactError, Dtype, ModelArtifact, ModelManifest, sha256_hex};
+    use crate::constants::FEATURE_DIM;
+
+    fn manifest_for(bytes: &[u8], input_dim: usize) -> String {
+        let digest: [u8; 32] = Sha256::digest(bytes).into();
+        format!(
+            "# exported 2024-06-01\nversion = fraud-7\nsha256 = {}\ndtype = f32\ninput_dim = {input_dim}\n",
+            sha256_hex(&digest)
+        )
+    }
+
+    #[test]
+    fn manifest_needs_every_key_with_a_valid_value() {
+        let manifest = ModelManifest::parse(&manifest_for(b"model", FEATURE_DIM)).unwrap();
+        assert_eq!(manifest.version, "fraud-7");
+        assert_eq!(manifest.dtype, Dtype::F32);
+        assert_eq!(manifest.input_dim, FEATURE_DIM);
+        assert_eq!(
+            manifest.to_string(),
+            format!(
+                "version fraud-7 sha256 {} dtype f32 input_dim {FEATURE_DIM}",
+                sha256_hex(&manifest.sha256)
+            )
+        );
+
+        let text = manifest_for(b"model", FEATURE_DIM);
+        let without_dtype: String = text
+            .lines()
+            .filter(|line| !line.starts_with("dtype"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert!(matches!(
+            ModelManifest::parse(&without_dtype),
+            Err(ArtifactError::Manifest(message)) if message.contains("dtype")
+        ));
+        assert!(matches!(
+            ModelManifest::parse(&text.replace("dtype = f32", "dtype = f16")),
+            Err(ArtifactError::Dtype(dtype)) if dtype == "f16"
+        ));
+        assert!(ModelManifest::parse(&text.replace("sha256 = ", "sha256 = zz")).is_err());
+        assert!(ModelManifest::parse(&format!("{text}layers = 3\n")).is_err());
+    }
+
+    #[test]
+    fn verify_refuses_wrong_input_dim_and_checksum() {
+        let bytes = b"onnx model bytes".to_vec();
+        let manifest = ModelManifest::parse(&manifest_for(&bytes, FEATURE_DIM)).unwrap();
+        let artifact = ModelArtifact::verify(manifest.clone(), bytes.clone(), FEATURE_DIM).unwrap();
+        assert_eq!(artifact.version(), "fraud-7");
+        assert_eq!(artifact.bytes, bytes);
+
+        let wide = ModelManifest::parse(&manifest_for(&bytes, FEATURE_DIM * 2)).unwrap();
+        assert!(matches!(
+            ModelArtifact::verify(wide.clone(), bytes.clone(), FEATURE_DIM),
+            Err(ArtifactError::InputDim { manifest, .. }) if manifest == FEATURE_DIM * 2
+        ));
+        assert!(ModelArtifact::verify(wide, bytes.clone(), FEATURE_DIM * 2).is_ok());
+
+        let mut tampered = bytes;
+        tampered[0] ^= 1;
+        assert!(matches!(
+            ModelArtifact::verify(manifest, tampered, FEATURE_DIM),
+            Err(ArtifactError::Checksum { .. })
+        ));
+    }
+}
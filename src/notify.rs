@@ -0,0 +1,386 @@
+//! Cross-thread wakeups for channel consumers.
+//!
+//! A channel is built with a [`Notifier`] matching how its consumer waits. IO threads poll
+//! a [`NotifyFd`]: an eventfd on Linux and, on other Unix targets, which only build the library
+//! for development, a non-blocking pipe. Either way the pollable side is a single fd that becomes
+//! readable after [`NotifyFd::signal`] and stays readable until [`drain_fd`]; every signal is an
+//! 8-byte `1u64`, so readers can treat both the same. Consumers that block a thread instead, such
+//! as tests, use a [`CondvarNotifier`].
+//!
+//! On Linux 5.18 and later an io_uring consumer can mostly skip the fd: a [`MsgRingNotifier`]
+//! posts each wakeup straight into the consumer's ring as a completion with
+//! `IORING_OP_MSG_RING`, which saves the producer's eventfd write and the consumer's poll rearm
+//! and drain. Its fd only fires for a wakeup that could not be posted.
+
+use std::io;
+use std::os::fd::RawFd;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How a channel's producer wakes its consumer.
+pub trait Notifier: Send + Sync {
+    /// Wake the consumer. Wakeups sent before it next waits may coalesce.
+    fn notify(&self);
+
+    /// An fd that becomes readable after [`Self::notify`], for consumers that poll one.
+    fn poll_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Wake the consumer from now on by posting a completion carrying `user_data` on the
+    /// io_uring whose fd is `ring_fd`, or stop posting when `None`. Returns whether this notifier
+    /// posts to rings at all; those that do not leave their consumer on [`Self::poll_fd`].
+    #[cfg(target_os = "linux")]
+    fn post_to_ring(&self, target: Option<(RawFd, u64)>) -> bool {
+        let _ = target;
+        false
+    }
+}
+
+pub struct NotifyFd {
+    read_fd: RawFd,
+    /// Same as `read_fd` for an eventfd.
+    write_fd: RawFd,
+}
+
+impl NotifyFd {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            read_fd: fd,
+            write_fd: fd,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> io::Result<Self> {
+        let mut fds = [0 as RawFd; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let notify = Self {
+            read_fd: fds[0],
+            write_fd: fds[1],
+        };
+        for fd in fds {
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0
+                || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+                || unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(notify)
+    }
+
+    /// The fd to poll for readability.
+    pub fn fd(&self) -> RawFd {
+        self.read_fd
+    }
+
+    /// Wake the poller. Signals sent before it drains coalesce into one wakeup.
+    pub fn signal(&self) {
+        let one = 1u64;
+        let rc = unsafe {
+            libc::write(
+                self.write_fd,
+                (&one as *const u64).cast::<libc::c_void>(),
+                std::mem::size_of::<u64>(),
+            )
+        };
+        // EAGAIN means the counter or pipe is already full, so the poller is awake anyway.
+        if rc >= 0 {
+            assert_eq!(
+                rc as usize,
+                std::mem::size_of::<u64>(),
+                "short notify write"
+            );
+        }
+    }
+}
+
+impl Drop for NotifyFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.read_fd) };
+        if self.write_fd != self.read_fd {
+            unsafe { libc::close(self.write_fd) };
+        }
+    }
+}
+
+impl Notifier for NotifyFd {
+    fn notify(&self) {
+        self.signal();
+    }
+
+    fn poll_fd(&self) -> Option<RawFd> {
+        Some(self.fd())
+    }
+}
+
+/// Wakeups for a consumer that blocks in [`Self::wait_timeout`].
+#[derive(Default)]
+pub struct CondvarNotifier {
+    pending: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl CondvarNotifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait up to `timeout` for a wakeup and consume it. Returns `false` on timeout.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let pending = self.pending.lock().unwrap();
+        let (mut pending, _) = self
+            .cond
+            .wait_timeout_while(pending, timeout, |pending| !*pending)
+            .unwrap();
+        std::mem::replace(&mut *pending, false)
+    }
+}
+
+impl Notifier for CondvarNotifier {
+    fn notify(&self) {
+        *self.pending.lock().unwrap() = true;
+        self.cond.notify_one();
+    }
+}
+
+/// Whether the running kernel can post completions to another ring: `IORING_OP_MSG_RING` came in
+/// Linux 5.18.
+#[cfg(target_os = "linux")]
+pub fn msg_ring_supported() -> bool {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } < 0 {
+        return false;
+    }
+    let release = unsafe { std::ffi::CStr::from_ptr(uts.release.as_ptr()) };
+    kernel_version(&release.to_string_lossy()).is_some_and(|version| version >= (5, 18))
+}
+
+/// The major and minor version in a kernel release string such as `6.1.0-18-amd64`.
+#[cfg(target_os = "linux")]
+fn kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Wakeups posted as completions on the consumer's io_uring; see [`Notifier::post_to_ring`].
+///
+/// A producer needs a ring of its own to submit `IORING_OP_MSG_RING`, so each notifier holds a
+/// small one. Until a consumer attaches its ring, wakeups go nowhere: a consumer drains its
+/// channel once after attaching, and the lock both sides take orders that drain after any
+/// push whose wakeup was skipped. The lock also keeps a detaching consumer from closing its
+/// ring while a wakeup to it is being submitted; only the producer takes it otherwise, so it
+/// is uncontended.
+///
+/// A wakeup that cannot be submitted after a few tries signals [`Notifier::poll_fd`] instead,
+/// which the consumer keeps polled.
+#[cfg(target_os = "linux")]
+pub struct MsgRingNotifier {
+    sender: Mutex<MsgRingSender>,
+    fallback: NotifyFd,
+}
+
+#[cfg(target_os = "linux")]
+struct MsgRingSender {
+    ring: io_uring::IoUring,
+    target: Option<(RawFd, u64)>,
+}
+
+#[cfg(target_os = "linux")]
+impl MsgRingNotifier {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            sender: Mutex::new(MsgRingSender {
+                ring: io_uring::IoUring::new(2)?,
+                target: None,
+            }),
+            fallback: NotifyFd::new()?,
+        })
+    }
+}
+
+/// Submits tried for one wakeup before it falls back to the eventfd.
+#[cfg(target_os = "linux")]
+const MSG_RING_SUBMIT_ATTEMPTS: usize = 3;
+
+#[cfg(target_os = "linux")]
+impl MsgRingSender {
+    /// Submit a wakeup for the target without waiting for it. Returns `false` if it could not
+    /// be submitted.
+    fn post(&mut self, ring_fd: RawFd, user_data: u64) -> bool {
+        // A message that was sent leaves no completion here. One that failed, which happens
+        // only when the consumer's completion queue is full and it is awake anyway, does, and
+        // is reaped before the next so the sender's queue never fills.
+        self.ring.completion().for_each(drop);
+        let sqe =
+            io_uring::opcode::MsgRingData::new(io_uring::types::Fd(ring_fd), 0, user_data, None)
+                .build()
+                .flags(io_uring::squeue::Flags::SKIP_SUCCESS);
+        // Submits that failed may have left earlier wakeups to the same ring queued; if they
+        // fill the queue, sending them wakes the consumer just as well.
+        let _ = unsafe { self.ring.submission().push(&sqe) };
+        self.flush()
+    }
+
+    /// Submit whatever is queued, retrying interrupted and busy submits.
+    fn flush(&mut self) -> bool {
+        for _ in 0..MSG_RING_SUBMIT_ATTEMPTS {
+            match self.ring.submit() {
+                Ok(_) => return true,
+                Err(e)
+                    if matches!(
+                        e.raw_os_error(),
+                        Some(libc::EINTR | libc::EAGAIN | libc::EBUSY)
+                    ) =>
+                {
+                    self.ring.completion().for_each(drop);
+                }
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Notifier for MsgRingNotifier {
+    fn notify(&self) {
+        let mut sender = self.sender.lock().unwrap();
+        let Some((ring_fd, user_data)) = sender.target else {
+            return;
+        };
+        if !sender.post(ring_fd, user_data) {
+            self.fallback.signal();
+        }
+    }
+
+    fn poll_fd(&self) -> Option<RawFd> {
+        Some(self.fallback.fd())
+    }
+
+    fn post_to_ring(&self, target: Option<(RawFd, u64)>) -> bool {
+        let mut sender = self.sender.lock().unwrap();
+        // Send any wakeup a failed submit left queued while its ring is still open.
+        if !sender.ring.submission().is_empty() {
+            sender.flush();
+        }
+        sender.target = target;
+        true
+    }
+}
+
+/// Consume every pending signal on the readable side of a [`NotifyFd`].
+pub fn drain_fd(fd: RawFd) {
+    loop {
+        let mut value = 0u64;
+        let rc = unsafe {
+            libc::read(
+                fd,
+                (&mut value as *mut u64).cast::<libc::c_void>(),
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if rc < 0 {
+            let err = io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or_default();
+            if err == libc::EAGAIN {
+                break;
+            }
+            panic!("notify read failed: {err}");
+        }
+        if rc == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    #[cfg(target_os = "linux")]
+    use std::os::fd::AsRawFd;
+
+    use super::{CondvarNotifier, Notifier, NotifyFd, drain_fd};
+    #[cfg(target_os = "linux")]
+    use super::{MsgRingNotifier, kernel_version, msg_ring_supported};
+
+    fn read_one(fd: i32) -> isize {
+        let mut value = 0u64;
+        unsafe {
+            libc::read(
+                fd,
+                (&mut value as *mut u64).cast::<libc::c_void>(),
+                std::mem::size_of::<u64>(),
+            )
+        }
+    }
+
+    #[test]
+    fn signal_makes_fd_readable_until_drained() {
+        let notify = NotifyFd::new().expect("notify fd");
+        assert!(read_one(notify.fd()) < 0, "nothing pending yet");
+        notify.signal();
+        notify.signal();
+        drain_fd(notify.fd());
+        assert!(read_one(notify.fd()) < 0, "drain consumes every signal");
+    }
+
+    #[test]
+    fn condvar_wakeups_coalesce_until_consumed() {
+        let notifier = CondvarNotifier::new();
+        assert_eq!(notifier.poll_fd(), None);
+        assert!(!notifier.wait_timeout(Duration::ZERO));
+        notifier.notify();
+        notifier.notify();
+        assert!(notifier.wait_timeout(Duration::ZERO));
+        assert!(!notifier.wait_timeout(Duration::ZERO));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn kernel_versions_parse_from_release_strings() {
+        assert_eq!(kernel_version("6.1.0-18-amd64"), Some((6, 1)));
+        assert_eq!(kernel_version("5.18.19"), Some((5, 18)));
+        assert_eq!(kernel_version("4.19"), Some((4, 19)));
+        assert_eq!(kernel_version("6"), None);
+        assert!(kernel_version("5.15.0-generic").unwrap() < (5, 18));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn msg_ring_posts_wakeups_to_the_attached_ring_only() {
+        if !msg_ring_supported() {
+            return;
+        }
+        let notifier = MsgRingNotifier::new().expect("sender ring");
+        let mut consumer = io_uring::IoUring::new(4).expect("consumer ring");
+        notifier.notify();
+        assert_eq!(consumer.completion().len(), 0, "nothing attached yet");
+
+        assert!(notifier.post_to_ring(Some((consumer.as_raw_fd(), 42))));
+        notifier.notify();
+        notifier.notify();
+        let posted: Vec<u64> = consumer.completion().map(|cqe| cqe.user_data()).collect();
+        assert_eq!(posted, [42, 42]);
+
+        notifier.post_to_ring(None);
+        notifier.notify();
+        assert_eq!(consumer.completion().len(), 0, "detached");
+        let fallback = notifier.poll_fd().expect("fallback fd");
+        assert!(read_one(fallback) < 0, "every wakeup was posted");
+        assert!(!CondvarNotifier::new().post_to_ring(None));
+    }
+}
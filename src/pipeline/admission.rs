@@ -0,0 +1,116 @@
+//! Admission control for large requests.
+//!
+//! The inference thread batches the request ring strictly in order, so a burst of huge requests
+//! fills the ring and every batch ahead of the small requests behind it. With
+//! `--large-requests vectors:limit`, requests of more than `vectors` vectors are admitted to the
+//! ring only while fewer than `limit` of them are published and unanswered, across all IO
+//! threads. A large request that is not admitted waits in its connection's read buffer, which
+//! stops only that connection, until the inference thread answers an admitted one; with
+//! `--overload-retry-after-ms` it is answered with a `large_requests` overload frame instead.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cache_line::CachePadded;
+use crate::constants::MAX_VECTORS_PER_REQUEST;
+
+/// Requests above a size threshold admitted to the request ring, and the most that may be.
+#[derive(Debug)]
+pub struct LargeRequestAdmission {
+    /// Requests of more than this many vectors are large.
+    threshold: usize,
+    limit: u64,
+    in_flight: CachePadded<AtomicU64>,
+}
+
+impl LargeRequestAdmission {
+    /// At most `limit` requests of more than `threshold` vectors in the ring at once.
+    pub fn new(threshold: usize, limit: u64) -> Self {
+        assert!(limit > 0, "large request limit must be > 0");
+        Self {
+            threshold,
+            limit,
+            in_flight: CachePadded::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Parse `vectors:limit`, with `vectors` in 1..[`MAX_VECTORS_PER_REQUEST`] and `limit` > 0.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (threshold, limit) = text
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("'{text}': expected `vectors:limit`"))?;
+        let threshold = threshold
+            .trim()
+            .parse()
+            .ok()
+            .filter(|n| (1..MAX_VECTORS_PER_REQUEST).contains(n))
+            .ok_or_else(|| format!("'{text}': vectors must be in 1..{MAX_VECTORS_PER_REQUEST}"))?;
+        let limit = limit
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("'{text}': limit must be > 0"))?;
+        Ok(Self::new(threshold, limit))
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn is_large(&self, num_vectors: usize) -> bool {
+        num_vectors > self.threshold
+    }
+
+    /// Admit a request of `num_vectors` vectors. Small requests are always admitted; a large one
+    /// is counted in flight until [`Self::complete`].
+    pub fn try_admit(&self, num_vectors: usize) -> bool {
+        !self.is_large(num_vectors)
+            || self
+                .in_flight
+                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| {
+                    (n < self.limit).then_some(n + 1)
+                })
+                .is_ok()
+    }
+
+    /// Release an admitted request of `num_vectors` vectors, once answered or given up on.
+    pub fn complete(&self, num_vectors: usize) {
+        if self.is_large(num_vectors) {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Large requests admitted and not yet completed.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LargeRequestAdmission;
+
+    #[test]
+    fn admits_large_requests_up_to_the_limit() {
+        let admission = LargeRequestAdmission::parse("16:2").unwrap();
+        assert!((0..10).all(|_| admission.try_admit(16)), "small requests");
+        assert!(admission.try_admit(17));
+        assert!(admission.try_admit(64));
+        assert!(!admission.try_admit(17));
+        assert_eq!(admission.in_flight(), 2);
+        admission.complete(16);
+        assert_eq!(admission.in_flight(), 2, "small requests are not counted");
+        admission.complete(64);
+        assert!(admission.try_admit(40));
+
+        assert!(LargeRequestAdmission::parse("16").is_err());
+        assert!(LargeRequestAdmission::parse("0:4").is_err());
+        assert!(LargeRequestAdmission::parse("64:4").is_err());
+        assert!(LargeRequestAdmission::parse("16:0").is_err());
+    }
+}
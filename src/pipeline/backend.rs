@@ -0,0 +1,101 @@
+//! The backend `disrust serve --backend` picks for `--model`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::buffer_pool::BufferPool;
+use crate::pipeline::gbdt::GbdtBackend;
+use crate::pipeline::session::{InFlightBatch, InferenceBackend, OrtBackend, OrtBatchResources};
+
+/// Which backend reads the model file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    /// An ONNX model run by ONNX Runtime.
+    #[default]
+    Ort,
+    /// An XGBoost text dump evaluated natively; see [`crate::pipeline::gbdt`].
+    Gbdt,
+}
+
+impl BackendKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendKind::Ort => "ort",
+            BackendKind::Gbdt => "gbdt",
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "ort" => Ok(BackendKind::Ort),
+            "gbdt" => Ok(BackendKind::Gbdt),
+            other => Err(format!("unknown backend '{other}', expected ort or gbdt")),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Either backend, chosen at startup, behind one `InferenceConsumer` type.
+pub enum ModelBackend {
+    Ort(OrtBackend),
+    Gbdt(GbdtBackend),
+}
+
+pub enum ModelBatchResources {
+    Ort(OrtBatchResources),
+    Gbdt,
+}
+
+impl InferenceBackend for ModelBackend {
+    type Resources = ModelBatchResources;
+
+    /// ORT's pool, pinned host memory under CUDA, which the tree backend reads just as well.
+    fn make_pool(feature_dim: usize) -> &'static BufferPool {
+        OrtBackend::make_pool(feature_dim)
+    }
+
+    fn feature_dim(&self) -> usize {
+        match self {
+            ModelBackend::Ort(backend) => backend.feature_dim(),
+            ModelBackend::Gbdt(backend) => backend.feature_dim(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        match self {
+            ModelBackend::Ort(backend) => backend.try_acquire(),
+            ModelBackend::Gbdt(backend) => backend.try_acquire(),
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        match self {
+            ModelBackend::Ort(backend) => backend.is_available(),
+            ModelBackend::Gbdt(backend) => backend.is_available(),
+        }
+    }
+
+    fn submit_batch(
+        &mut self,
+        input_host_ptr: *const f32,
+        num_vectors: usize,
+    ) -> InFlightBatch<ModelBatchResources> {
+        match self {
+            ModelBackend::Ort(backend) => backend
+                .submit_batch(input_host_ptr, num_vectors)
+                .map_resources(ModelBatchResources::Ort),
+            ModelBackend::Gbdt(backend) => backend
+                .submit_batch(input_host_ptr, num_vectors)
+                .map_resources(|()| ModelBatchResources::Gbdt),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! A circuit breaker in front of the backend.
+//!
+//! A wedged or badly degraded model runtime does not fail requests, it sits on them: every
+//! request published after it stalls waits out `--request-timeout-us` in the ring, holding its
+//! slot and pool space, until both are full and every connection waits behind them. With
+//! `--circuit-breaker percent:window:open_ms`, the inference thread counts the outcome of every
+//! request, and once at least `percent` of a `window` of them timed out, the circuit opens: the
+//! IO threads answer new requests with a `circuit_open` overload frame, without publishing
+//! them, for `open_ms`. Then it is half-open: [`HALF_OPEN_PROBES`] requests are let through,
+//! and the circuit closes again once that many are answered in time, or opens for another
+//! `open_ms` on the first that is not.
+//!
+//! A request counts as timed out if it waited out the timeout for a batch, or ran in a batch
+//! that took longer than the timeout from submit to ready.
+
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::cache_line::CachePadded;
+use crate::clock::monotonic_now_ns;
+
+/// Requests let through a half-open circuit before it closes again.
+pub const HALF_OPEN_PROBES: u64 = 8;
+
+const CLOSED: u8 = 0;
+const OPEN: u8 = 1;
+const HALF_OPEN: u8 = 2;
+
+/// Whether requests are let through to the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// How a [`CircuitBreaker`] let a request through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admitted {
+    /// The circuit was closed.
+    Closed,
+    /// As one of a half-open circuit's probes.
+    Probe,
+}
+
+/// Error budget over the backend's recent requests, shared by the inference thread, which
+/// records outcomes, and the IO threads, which admit requests through it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Timed-out share of a window, in percent, that opens the circuit.
+    percent: u64,
+    window: u64,
+    open_for_ns: u64,
+    state: CachePadded<AtomicU8>,
+    /// When the circuit last opened or went half-open.
+    changed_at_ns: AtomicU64,
+    /// Probes a half-open circuit still lets through.
+    probes_left: AtomicU64,
+    // Outcomes counted since the window or the half-open circuit began. Only the inference
+    // thread writes these.
+    outcomes: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl CircuitBreaker {
+    /// Open for `open_for` once at least `percent` of `window` requests timed out.
+    pub fn new(percent: u64, window: u64, open_for: Duration) -> Self {
+        assert!(
+            (1..=100).contains(&percent),
+            "circuit breaker percent must be in 1..=100"
+        );
+        assert!(window > 0, "circuit breaker window must be > 0");
+        assert!(
+            open_for > Duration::ZERO,
+            "circuit breaker open time must be > 0"
+        );
+        Self {
+            percent,
+            window,
+            open_for_ns: open_for.as_nanos().min(u64::MAX as u128) as u64,
+            state: CachePadded::new(AtomicU8::new(CLOSED)),
+            changed_at_ns: AtomicU64::new(0),
+            probes_left: AtomicU64::new(0),
+            outcomes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Parse `percent:window:open_ms`, with `percent` in 1..=100 and the others > 0.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.trim().split(':');
+        let (Some(percent), Some(window), Some(open_ms), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("'{text}': expected `percent:window:open_ms`"));
+        };
+        let percent = percent
+            .trim()
+            .parse()
+            .ok()
+            .filter(|n| (1..=100).contains(n))
+            .ok_or_else(|| format!("'{text}': percent must be in 1..=100"))?;
+        let window = window
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("'{text}': window must be > 0"))?;
+        let open_ms = open_ms
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("'{text}': open_ms must be > 0"))?;
+        Ok(Self::new(percent, window, Duration::from_millis(open_ms)))
+    }
+
+    pub fn percent(&self) -> u64 {
+        self.percent
+    }
+
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    pub fn open_for(&self) -> Duration {
+        Duration::from_nanos(self.open_for_ns)
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.state.load(Ordering::Acquire) {
+            CLOSED => CircuitState::Closed,
+            OPEN => CircuitState::Open,
+            _ => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether to let a request through, and how. An open circuit goes half-open once it has
+    /// been open for the open time; a half-open one lets through what is left of its probes,
+    /// and another round of them if it is still undecided after another open time.
+    pub fn try_admit(&self) -> Option<Admitted> {
+        self.try_admit_at(monotonic_now_ns())
+    }
+
+    fn try_admit_at(&self, now_ns: u64) -> Option<Admitted> {
+        let state = self.state.load(Ordering::Acquire);
+        if state == CLOSED {
+            return Some(Admitted::Closed);
+        }
+        if now_ns.saturating_sub(self.changed_at_ns.load(Ordering::Acquire)) >= self.open_for_ns
+            && self
+                .state
+                .compare_exchange(state, HALF_OPEN, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            self.probes_left.store(HALF_OPEN_PROBES, Ordering::Release);
+            self.changed_at_ns.store(now_ns, Ordering::Release);
+        }
+        let probe = self.state.load(Ordering::Acquire) == HALF_OPEN
+            && self
+                .probes_left
+                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok();
+        probe.then_some(Admitted::Probe)
+    }
+
+    /// Undo `admitted` for a request that is not published after all. A probe is handed back
+    /// while the circuit is still half-open: it will never be answered, and the circuit would
+    /// otherwise wait out another open time for it.
+    pub fn give_back(&self, admitted: Admitted) {
+        if admitted == Admitted::Probe && self.state.load(Ordering::Acquire) == HALF_OPEN {
+            let _ = self
+                .probes_left
+                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| {
+                    (n < HALF_OPEN_PROBES).then_some(n + 1)
+                });
+        }
+    }
+
+    /// Milliseconds until the circuit lets more requests through, for the overload frames it
+    /// answers with.
+    pub fn retry_after_ms(&self) -> u16 {
+        let open_ns = monotonic_now_ns().saturating_sub(self.changed_at_ns.load(Ordering::Acquire));
+        let left = Duration::from_nanos(self.open_for_ns.saturating_sub(open_ns));
+        left.as_millis().clamp(1, u16::MAX as u128) as u16
+    }
+
+    /// Count `answered` requests answered in time and `timed_out` ones that were not. Called
+    /// only from the inference thread. Returns `true` if this opened the circuit.
+    pub fn record(&self, answered: u64, timed_out: u64) -> bool {
+        self.record_at(answered, timed_out, monotonic_now_ns())
+    }
+
+    fn record_at(&self, answered: u64, timed_out: u64, now_ns: u64) -> bool {
+        let state = self.state.load(Ordering::Acquire);
+        if state == OPEN {
+            // Requests admitted before the circuit opened still finish; they say nothing new.
+            return false;
+        }
+        if state == HALF_OPEN {
+            if timed_out > 0 {
+                return self.open(HALF_OPEN, now_ns);
+            }
+            if self.outcomes.fetch_add(answered, Ordering::Relaxed) + answered >= HALF_OPEN_PROBES
+                && self
+                    .state
+                    .compare_exchange(HALF_OPEN, CLOSED, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+            {
+                self.reset_window();
+            }
+            return false;
+        }
+        let outcomes = self
+            .outcomes
+            .fetch_add(answered + timed_out, Ordering::Relaxed)
+            + answered
+            + timed_out;
+        let failures = self.failures.fetch_add(timed_out, Ordering::Relaxed) + timed_out;
+        if outcomes < self.window {
+            return false;
+        }
+        if failures * 100 >= outcomes * self.percent {
+            return self.open(CLOSED, now_ns);
+        }
+        self.reset_window();
+        false
+    }
+
+    fn open(&self, from: u8, now_ns: u64) -> bool {
+        self.changed_at_ns.store(now_ns, Ordering::Release);
+        let opened = self
+            .state
+            .compare_exchange(from, OPEN, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        self.reset_window();
+        opened
+    }
+
+    fn reset_window(&self) {
+        self.outcomes.store(0, Ordering::Relaxed);
+        self.failures.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Admitted, CircuitBreaker, CircuitState, HALF_OPEN_PROBES};
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn parses_percent_window_and_open_time() {
+        let breaker = CircuitBreaker::parse("50:100:250").unwrap();
+        assert_eq!(breaker.percent(), 50);
+        assert_eq!(breaker.window(), 100);
+        assert_eq!(breaker.open_for(), Duration::from_millis(250));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        for bad in [
+            "",
+            "50:100",
+            "50:100:250:1",
+            "0:100:250",
+            "101:100:250",
+            "50:0:250",
+            "50:100:0",
+        ] {
+            assert!(CircuitBreaker::parse(bad).is_err(), "{bad:?} parsed");
+        }
+    }
+
+    #[test]
+    fn opens_once_a_window_spends_its_error_budget() {
+        let breaker = CircuitBreaker::new(50, 10, Duration::from_millis(100));
+        assert!(!breaker.record_at(6, 3, 0));
+        // A full window at 30% timed out stays closed and starts over.
+        assert!(!breaker.record_at(1, 0, 0));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        assert!(!breaker.record_at(5, 0, MS));
+        assert!(breaker.record_at(0, 5, MS));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_admit_at(2 * MS).is_none());
+        // Requests that were already in flight do not reopen or close it.
+        assert!(!breaker.record_at(0, 10, 3 * MS));
+        assert!(!breaker.record_at(10, 0, 3 * MS));
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn a_half_open_circuit_lets_probes_through_and_closes_once_they_are_answered() {
+        let breaker = CircuitBreaker::new(50, 2, Duration::from_millis(100));
+        assert!(breaker.record_at(0, 2, 0));
+        assert!(breaker.try_admit_at(99 * MS).is_none());
+
+        for _ in 0..HALF_OPEN_PROBES {
+            assert_eq!(breaker.try_admit_at(100 * MS), Some(Admitted::Probe));
+        }
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(
+            breaker.try_admit_at(101 * MS).is_none(),
+            "only the probes get through"
+        );
+
+        assert!(!breaker.record_at(HALF_OPEN_PROBES - 1, 0, 102 * MS));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.record_at(1, 0, 102 * MS));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.try_admit_at(102 * MS), Some(Admitted::Closed));
+        // The window starts over once closed.
+        assert!(!breaker.record_at(1, 0, 103 * MS));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_probe_that_times_out_opens_the_circuit_again() {
+        let breaker = CircuitBreaker::new(100, 1, Duration::from_millis(100));
+        assert!(breaker.record_at(0, 1, 0));
+        assert!(breaker.try_admit_at(100 * MS).is_some());
+        assert!(breaker.record_at(0, 1, 150 * MS));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_admit_at(200 * MS).is_none());
+        assert!(breaker.try_admit_at(250 * MS).is_some());
+    }
+
+    #[test]
+    fn a_half_open_circuit_still_undecided_after_the_open_time_lets_more_probes_through() {
+        let breaker = CircuitBreaker::new(100, 1, Duration::from_millis(100));
+        assert!(breaker.record_at(0, 1, 0));
+        for _ in 0..HALF_OPEN_PROBES {
+            assert!(breaker.try_admit_at(100 * MS).is_some());
+        }
+        // The probes were never answered, say because the ring was full.
+        assert!(breaker.try_admit_at(199 * MS).is_none());
+        assert!(breaker.try_admit_at(200 * MS).is_some());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn probes_handed_back_let_other_requests_through() {
+        let breaker = CircuitBreaker::new(100, 1, Duration::from_millis(100));
+        assert!(breaker.record_at(0, 1, 0));
+        for _ in 0..HALF_OPEN_PROBES {
+            assert_eq!(breaker.try_admit_at(100 * MS), Some(Admitted::Probe));
+        }
+        assert!(breaker.try_admit_at(101 * MS).is_none());
+
+        breaker.give_back(Admitted::Probe);
+        breaker.give_back(Admitted::Closed);
+        assert_eq!(breaker.try_admit_at(101 * MS), Some(Admitted::Probe));
+        assert!(
+            breaker.try_admit_at(101 * MS).is_none(),
+            "only the probe handed back"
+        );
+
+        // Never more than a round of probes, and nothing once the circuit has closed.
+        for _ in 0..=HALF_OPEN_PROBES {
+            breaker.give_back(Admitted::Probe);
+        }
+        assert!(!breaker.record_at(HALF_OPEN_PROBES, 0, 102 * MS));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.give_back(Admitted::Probe);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,105 @@
+//! Feature-major (structure-of-arrays) batch layout.
+//!
+//! The request pool is row-major: each request's vectors are copied in whole, in publish order,
+//! and a batch is whatever contiguous run of requests the inference thread takes next, so the
+//! batch a vector lands in is not known when it is copied. Models that evaluate one feature
+//! across many vectors at a time, such as linear or tree models, want each feature's values
+//! contiguous instead. Such a backend owns a [`FeatureColumns`] and transposes every batch into
+//! it on submission, once the batch is known; backends that read rows keep using the pool
+//! directly.
+
+/// Vectors transposed per block; a block's rows stay in cache while its columns are written.
+const TRANSPOSE_BLOCK_VECTORS: usize = 64;
+
+/// A batch transposed to feature-major order: all vectors' feature 0, then feature 1, and so on.
+#[derive(Debug, Clone)]
+pub struct FeatureColumns {
+    feature_dim: usize,
+    num_vectors: usize,
+    values: Vec<f32>,
+}
+
+impl FeatureColumns {
+    /// Columns for batches of up to `max_vectors` vectors of `feature_dim` features, allocated
+    /// once.
+    pub fn new(feature_dim: usize, max_vectors: usize) -> Self {
+        assert!(feature_dim > 0, "feature_dim must be > 0");
+        Self {
+            feature_dim,
+            num_vectors: 0,
+            values: vec![0.0; feature_dim * max_vectors],
+        }
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    pub fn max_vectors(&self) -> usize {
+        self.values.len() / self.feature_dim
+    }
+
+    /// Vectors in the current batch.
+    pub fn num_vectors(&self) -> usize {
+        self.num_vectors
+    }
+
+    /// Replace the batch with `rows`, a row-major `[num_vectors × feature_dim]` array.
+    pub fn fill(&mut self, rows: &[f32]) {
+        assert_eq!(
+            rows.len() % self.feature_dim,
+            0,
+            "rows must hold whole vectors"
+        );
+        let num_vectors = rows.len() / self.feature_dim;
+        assert!(
+            num_vectors <= self.max_vectors(),
+            "batch of {num_vectors} vectors exceeds {}",
+            self.max_vectors()
+        );
+        self.num_vectors = num_vectors;
+        for block_start in (0..num_vectors).step_by(TRANSPOSE_BLOCK_VECTORS) {
+            let block_end = (block_start + TRANSPOSE_BLOCK_VECTORS).min(num_vectors);
+            for feature in 0..self.feature_dim {
+                let column = &mut self.values[feature * num_vectors..][..num_vectors];
+                for vector in block_start..block_end {
+                    column[vector] = rows[vector * self.feature_dim + feature];
+                }
+            }
+        }
+    }
+
+    /// Every vector's value of `feature`, in batch order.
+    pub fn column(&self, feature: usize) -> &[f32] {
+        assert!(feature < self.feature_dim, "feature {feature} out of range");
+        &self.values[feature * self.num_vectors..][..self.num_vectors]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeatureColumns;
+
+    #[test]
+    fn fill_transposes_rows_into_columns() {
+        let mut columns = FeatureColumns::new(3, 200);
+        let rows: Vec<f32> = (0..150 * 3).map(|i| i as f32).collect();
+        columns.fill(&rows);
+        assert_eq!(columns.num_vectors(), 150);
+        for feature in 0..3 {
+            let column = columns.column(feature);
+            assert_eq!(column.len(), 150);
+            assert!(
+                column
+                    .iter()
+                    .enumerate()
+                    .all(|(vector, &value)| value == (vector * 3 + feature) as f32),
+                "feature {feature}"
+            );
+        }
+
+        columns.fill(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(columns.column(0), &[1.0, 4.0]);
+        assert_eq!(columns.column(2), &[3.0, 6.0]);
+    }
+}
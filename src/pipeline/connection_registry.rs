@@ -83,6 +83,15 @@ impl ConnectionRegistry {
         conn.shard_id() as usize * self.shard_capacity + conn.conn_id as usize
     }
 
+    /// `conn`'s slot, or `None` for a reference outside this registry, such as one with a
+    /// shard id past the IO threads it was sized for.
+    fn slot(&self, conn: ConnectionRef) -> Option<&Slot> {
+        if conn.conn_id as usize >= self.shard_capacity {
+            return None;
+        }
+        self.slots.get(self.slot_index(conn))
+    }
+
     fn slot_index_parts(&self, shard_id: u8, conn_id: u16) -> usize {
         shard_id as usize * self.shard_capacity + conn_id as usize
     }
@@ -124,9 +133,25 @@ impl ConnectionRegistry {
     }
 
     pub fn is_open(&self, conn: ConnectionRef) -> bool {
-        let state = self.slots[self.slot_index(conn)].state.lock().unwrap();
+        let Some(slot) = self.slot(conn) else {
+            return false;
+        };
+        let state = slot.state.lock().unwrap();
         state.matches(conn) && !state.retired
     }
+
+    /// Shut down `conn`'s socket from any thread, so its IO thread sees the read side close and
+    /// winds it down. Does nothing once `conn` is retired; the fd is only closed on retirement,
+    /// so it cannot belong to a newer connection here, nor for a `conn` outside this registry.
+    pub fn shutdown(&self, conn: ConnectionRef) {
+        let Some(slot) = self.slot(conn) else {
+            return;
+        };
+        let state = slot.state.lock().unwrap();
+        if state.matches(conn) && state.fd >= 0 {
+            unsafe { libc::shutdown(state.fd, libc::SHUT_RDWR) };
+        }
+    }
 }
 
 pub fn encode_user_data(conn: ConnectionRef) -> u64 {
@@ -181,4 +206,16 @@ mod tests {
         assert!(registry.is_open(conn0));
         assert!(registry.is_open(conn1));
     }
+
+    #[test]
+    fn references_outside_the_registry_are_never_open() {
+        let registry = ConnectionRegistry::new(2, 2);
+        let past_shards = ConnectionRef::new(5, 0, 1);
+        let past_capacity = ConnectionRef::new(0, 3, 1);
+
+        assert!(!registry.is_open(past_shards));
+        assert!(!registry.is_open(past_capacity));
+        registry.shutdown(past_shards);
+        registry.shutdown(past_capacity);
+    }
 }
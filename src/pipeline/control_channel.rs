@@ -0,0 +1,140 @@
+//! Low-rate control path into the inference thread.
+//!
+//! The request ring carries only inference events. Everything else the inference thread needs to
+//! hear about, from IO threads or the control plane, is a [`ControlEvent`] on one bounded side
+//! channel that the thread drains between batches. New features add a variant here and a match
+//! arm in `InferenceConsumer::apply_control` rather than a channel of their own.
+//!
+//! Control events are not ordered against the request ring: an event may be applied before
+//! requests published ahead of it have been drained.
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::Duration;
+
+use crate::calibration::Calibration;
+use crate::config::SLAB_CAPACITY;
+use crate::connection_id::{ConnSlots, ConnectionRef};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlEvent {
+    /// Drop responses to `conn`'s requests before `request_seq` instead of queuing them; the
+    /// sender answers those requests itself. Inference still runs for requests already in the
+    /// ring.
+    Cancel {
+        conn: ConnectionRef,
+        request_seq: u64,
+    },
+    /// `conn` is gone; forget any state kept for it.
+    ConnectionClosed(ConnectionRef),
+    /// Replace the partial-batch coalescing window.
+    SetBatchCoalesce(Duration),
+    /// Replace the calibration applied to results, or stop calibrating with `None`.
+    SetCalibration(Option<Arc<Calibration>>),
+    /// Replace the fraction of requests sampled to the sample sink.
+    SetSampleRate(f64),
+    /// Return from `run` once the ring and every in-flight batch are empty. Sent only after
+    /// every IO thread has stopped publishing.
+    Shutdown,
+}
+
+/// Creates a channel that holds up to `capacity` undelivered events.
+pub fn control_channel(capacity: usize) -> (ControlSender, ControlReceiver) {
+    let (tx, rx) = mpsc::sync_channel(capacity);
+    (ControlSender(tx), ControlReceiver(rx))
+}
+
+#[derive(Clone)]
+pub struct ControlSender(SyncSender<ControlEvent>);
+
+impl ControlSender {
+    /// Queue `event` without blocking. Returns `false` if the channel is full or the inference
+    /// thread is gone.
+    pub fn send(&self, event: ControlEvent) -> bool {
+        match self.0.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+pub struct ControlReceiver(Receiver<ControlEvent>);
+
+impl ControlReceiver {
+    /// The next queued event, or `None` if none is queued or every sender is gone.
+    pub fn try_recv(&self) -> Option<ControlEvent> {
+        self.0.try_recv().ok()
+    }
+}
+
+/// Cancellation points per connection, in one slot array per IO thread so connection churn
+/// cannot grow them.
+#[derive(Default)]
+pub struct Cancellations {
+    shards: Vec<Option<ConnSlots<u64>>>,
+}
+
+impl Cancellations {
+    /// Cancel `conn`'s requests before `request_seq`; an earlier point never moves it back.
+    pub fn cancel(&mut self, conn: ConnectionRef, request_seq: u64) {
+        let shard = conn.shard_id() as usize;
+        if self.shards.len() <= shard {
+            self.shards.resize_with(shard + 1, || None);
+        }
+        let slots = self.shards[shard].get_or_insert_with(|| ConnSlots::new(SLAB_CAPACITY));
+        match slots.get_mut(conn) {
+            Some(before) => *before = (*before).max(request_seq),
+            None => {
+                slots.insert(conn, request_seq);
+            }
+        }
+    }
+
+    pub fn forget(&mut self, conn: ConnectionRef) {
+        if let Some(Some(slots)) = self.shards.get_mut(conn.shard_id() as usize) {
+            slots.remove(conn);
+        }
+    }
+
+    pub fn is_cancelled(&self, conn: ConnectionRef, request_seq: u64) -> bool {
+        match self.shards.get(conn.shard_id() as usize) {
+            Some(Some(slots)) => slots.get(conn).is_some_and(|&before| request_seq < before),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Cancellations, ControlEvent, control_channel};
+    use crate::connection_id::ConnectionRef;
+
+    #[test]
+    fn full_channel_refuses_events_until_drained() {
+        let (tx, rx) = control_channel(1);
+        let event = ControlEvent::SetBatchCoalesce(Duration::from_micros(50));
+        assert!(tx.send(event.clone()));
+        assert!(!tx.send(event.clone()), "capacity 1");
+        assert_eq!(rx.try_recv(), Some(event.clone()));
+        assert_eq!(rx.try_recv(), None);
+        drop(rx);
+        assert!(!tx.send(event), "receiver gone");
+    }
+
+    #[test]
+    fn cancellation_covers_earlier_requests_of_one_generation() {
+        let mut cancellations = Cancellations::default();
+        let conn = ConnectionRef::new(3, 9, 1);
+        cancellations.cancel(conn, 5);
+        cancellations.cancel(conn, 2);
+        assert!(cancellations.is_cancelled(conn, 4));
+        assert!(!cancellations.is_cancelled(conn, 5));
+        assert!(!cancellations.is_cancelled(ConnectionRef::new(3, 9, 2), 0));
+        assert!(!cancellations.is_cancelled(ConnectionRef::new(1, 9, 1), 0));
+
+        cancellations.forget(conn);
+        assert!(!cancellations.is_cancelled(conn, 0));
+    }
+}
@@ -0,0 +1,91 @@
+//! Deadline-aware batch coalescing.
+//!
+//! The coalescing window holds a partial batch open for more requests, measured from when the
+//! inference thread first saw the backlog. A request that already waited in the ring while the
+//! backend was busy can spend its whole latency budget there before the window even starts.
+//! With `--request-deadline-us`, every request must be answered within that long of being
+//! published, and the window closes early once the oldest waiting request has no slack left:
+//! its age plus the time a batch is expected to take reaches the deadline.
+//!
+//! Every request shares one deadline, so the oldest request is also the one closest to it and
+//! the ring's FIFO order is already earliest-deadline-first; only the waiting needs to change.
+
+use std::time::Duration;
+
+/// Weight of each new batch time in the running estimate, as a right shift (1/8).
+const SERVICE_ESTIMATE_SHIFT: u32 = 3;
+
+/// A per-request deadline and a running estimate of how long a batch takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestDeadline {
+    deadline_ns: u64,
+    /// Exponentially weighted submit-to-ready time of recent batches; `0` until one completes.
+    service_estimate_ns: u64,
+}
+
+impl RequestDeadline {
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            deadline_ns: deadline.as_nanos().min(u64::MAX as u128) as u64,
+            service_estimate_ns: 0,
+        }
+    }
+
+    pub fn deadline(&self) -> Duration {
+        Duration::from_nanos(self.deadline_ns)
+    }
+
+    /// Expected submit-to-ready time of the next batch.
+    pub fn service_estimate(&self) -> Duration {
+        Duration::from_nanos(self.service_estimate_ns)
+    }
+
+    /// Fold a finished batch's submit-to-ready time into the estimate.
+    pub fn record_service(&mut self, elapsed: Duration) {
+        let elapsed_ns = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.service_estimate_ns = if self.service_estimate_ns == 0 {
+            elapsed_ns
+        } else {
+            let keep =
+                self.service_estimate_ns - (self.service_estimate_ns >> SERVICE_ESTIMATE_SHIFT);
+            keep + (elapsed_ns >> SERVICE_ESTIMATE_SHIFT)
+        };
+    }
+
+    /// Whether a request published at `published_at_ns` must be submitted now, at `now_ns`, to
+    /// be answered by its deadline.
+    pub fn out_of_slack(&self, published_at_ns: u64, now_ns: u64) -> bool {
+        let age_ns = now_ns.saturating_sub(published_at_ns);
+        age_ns.saturating_add(self.service_estimate_ns) >= self.deadline_ns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RequestDeadline;
+
+    #[test]
+    fn slack_shrinks_by_age_and_expected_batch_time() {
+        let mut deadline = RequestDeadline::new(Duration::from_micros(500));
+        assert!(!deadline.out_of_slack(1_000, 400_000));
+        assert!(deadline.out_of_slack(1_000, 501_000));
+
+        deadline.record_service(Duration::from_micros(200));
+        assert_eq!(deadline.service_estimate(), Duration::from_micros(200));
+        assert!(!deadline.out_of_slack(1_000, 200_000));
+        assert!(
+            deadline.out_of_slack(1_000, 301_000),
+            "200us age + 200us batch"
+        );
+
+        deadline.record_service(Duration::from_micros(1_000));
+        assert_eq!(deadline.service_estimate(), Duration::from_micros(300));
+        assert!(deadline.out_of_slack(0, 200_000));
+        assert!(
+            !deadline.out_of_slack(5_000, 0),
+            "a clock behind the publisher counts as no age"
+        );
+    }
+}
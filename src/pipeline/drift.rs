@@ -0,0 +1,294 @@
+//! Streaming per-feature statistics for spotting input drift at serving time.
+//!
+//! The inference thread folds every submitted batch into running count, mean, variance, min
+//! and max per feature, and publishes them to a [`DriftMonitor`] the control plane reads. The
+//! moments merge without loss (Chan et al.), so a metrics report can split the lifetime statistics
+//! into the interval since the previous report and the baseline before it, and report how far
+//! each feature's recent mean has moved in baseline standard deviations. Vectors with a NaN or
+//! infinite feature are left out; `--non-finite-features` already counts them.
+//!
+//! Per-feature arrays are sized for [`MAX_FEATURE_DIM`]; only the first `feature_dim` entries
+//! are used.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+use crate::constants::MAX_FEATURE_DIM;
+
+/// Count, mean and sum of squared deviations per feature over a set of vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureMoments {
+    pub feature_dim: usize,
+    pub count: u64,
+    pub mean: [f64; MAX_FEATURE_DIM],
+    pub m2: [f64; MAX_FEATURE_DIM],
+}
+
+impl FeatureMoments {
+    /// No vectors of `feature_dim` features yet.
+    pub fn new(feature_dim: usize) -> Self {
+        assert!(feature_dim <= MAX_FEATURE_DIM);
+        Self {
+            feature_dim,
+            count: 0,
+            mean: [0.0; MAX_FEATURE_DIM],
+            m2: [0.0; MAX_FEATURE_DIM],
+        }
+    }
+
+    /// Moments of the finite vectors in `features`, `feature_dim` values each.
+    pub fn of_vectors(features: &[f32], feature_dim: usize) -> Self {
+        let finite = || {
+            features
+                .chunks_exact(feature_dim)
+                .filter(|vector| vector.iter().all(|value| value.is_finite()))
+        };
+        let mut moments = Self::new(feature_dim);
+        for vector in finite() {
+            moments.count += 1;
+            for (sum, &value) in moments.mean.iter_mut().zip(vector) {
+                *sum += f64::from(value);
+            }
+        }
+        if moments.count == 0 {
+            return moments;
+        }
+        for mean in &mut moments.mean {
+            *mean /= moments.count as f64;
+        }
+        for vector in finite() {
+            for ((m2, mean), &value) in moments.m2.iter_mut().zip(&moments.mean).zip(vector) {
+                let deviation = f64::from(value) - mean;
+                *m2 += deviation * deviation;
+            }
+        }
+        moments
+    }
+
+    /// Fold `other` into these moments.
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let (n_a, n_b) = (self.count as f64, other.count as f64);
+        for i in 0..self.feature_dim {
+            let delta = other.mean[i] - self.mean[i];
+            self.mean[i] += delta * n_b / count as f64;
+            self.m2[i] += other.m2[i] + delta * delta * n_a * n_b / count as f64;
+        }
+        self.count = count;
+    }
+
+    /// Moments of the vectors added after `earlier`, which must be a prefix of these.
+    pub fn since(&self, earlier: &Self) -> Self {
+        let count = self.count.saturating_sub(earlier.count);
+        if count == 0 {
+            return Self::new(self.feature_dim);
+        }
+        let (n, n_e, n_w) = (self.count as f64, earlier.count as f64, count as f64);
+        let mut window = Self {
+            count,
+            ..Self::new(self.feature_dim)
+        };
+        for i in 0..self.feature_dim {
+            let mean = (n * self.mean[i] - n_e * earlier.mean[i]) / n_w;
+            let delta = mean - earlier.mean[i];
+            window.mean[i] = mean;
+            window.m2[i] = (self.m2[i] - earlier.m2[i] - delta * delta * n_e * n_w / n).max(0.0);
+        }
+        window
+    }
+
+    /// Sample standard deviation of feature `i`; 0 below two vectors.
+    pub fn std_dev(&self, i: usize) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2[i] / (self.count - 1) as f64).sqrt()
+    }
+
+    /// How far feature `i`'s mean here is from `baseline`'s, in `baseline` standard deviations.
+    /// `None` while the baseline has no spread to measure against.
+    pub fn shift(&self, baseline: &Self, i: usize) -> Option<f64> {
+        let std_dev = baseline.std_dev(i);
+        (self.count > 0 && std_dev > 0.0).then(|| (self.mean[i] - baseline.mean[i]) / std_dev)
+    }
+}
+
+/// Lifetime moments plus the range each feature has taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftStats {
+    pub moments: FeatureMoments,
+    pub min: [f32; MAX_FEATURE_DIM],
+    pub max: [f32; MAX_FEATURE_DIM],
+}
+
+impl DriftStats {
+    pub fn new(feature_dim: usize) -> Self {
+        Self {
+            moments: FeatureMoments::new(feature_dim),
+            min: [f32::INFINITY; MAX_FEATURE_DIM],
+            max: [f32::NEG_INFINITY; MAX_FEATURE_DIM],
+        }
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.moments.feature_dim
+    }
+
+    /// Add the finite vectors of `features`, `feature_dim` values each.
+    pub fn record(&mut self, features: &[f32]) {
+        let feature_dim = self.feature_dim();
+        let batch = FeatureMoments::of_vectors(features, feature_dim);
+        if batch.count == 0 {
+            return;
+        }
+        self.moments.merge(&batch);
+        for vector in features
+            .chunks_exact(feature_dim)
+            .filter(|vector| vector.iter().all(|value| value.is_finite()))
+        {
+            for ((min, max), &value) in self.min.iter_mut().zip(&mut self.max).zip(vector) {
+                *min = min.min(value);
+                *max = max.max(value);
+            }
+        }
+    }
+}
+
+/// [`DriftStats`] published by the inference thread for the control plane.
+///
+/// A sequence lock over atomics: the single writer never waits, and readers retry if they
+/// overlap a publish, so a snapshot never mixes two batches.
+pub struct DriftMonitor {
+    feature_dim: usize,
+    seq: AtomicU64,
+    count: AtomicU64,
+    mean: [AtomicU64; MAX_FEATURE_DIM],
+    m2: [AtomicU64; MAX_FEATURE_DIM],
+    min: [AtomicU32; MAX_FEATURE_DIM],
+    max: [AtomicU32; MAX_FEATURE_DIM],
+}
+
+impl DriftMonitor {
+    /// Statistics of vectors of `feature_dim` features.
+    pub fn new(feature_dim: usize) -> Self {
+        let stats = DriftStats::new(feature_dim);
+        Self {
+            feature_dim,
+            seq: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+            mean: std::array::from_fn(|_| AtomicU64::new(0f64.to_bits())),
+            m2: std::array::from_fn(|_| AtomicU64::new(0f64.to_bits())),
+            min: std::array::from_fn(|i| AtomicU32::new(stats.min[i].to_bits())),
+            max: std::array::from_fn(|i| AtomicU32::new(stats.max[i].to_bits())),
+        }
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    /// Replace the published statistics. Only one thread may publish.
+    pub fn publish(&self, stats: &DriftStats) {
+        debug_assert_eq!(stats.feature_dim(), self.feature_dim);
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.count.store(stats.moments.count, Ordering::Relaxed);
+        for i in 0..self.feature_dim {
+            self.mean[i].store(stats.moments.mean[i].to_bits(), Ordering::Relaxed);
+            self.m2[i].store(stats.moments.m2[i].to_bits(), Ordering::Relaxed);
+            self.min[i].store(stats.min[i].to_bits(), Ordering::Relaxed);
+            self.max[i].store(stats.max[i].to_bits(), Ordering::Relaxed);
+        }
+        self.seq.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn snapshot(&self) -> DriftStats {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let mut stats = DriftStats::new(self.feature_dim);
+            stats.moments.count = self.count.load(Ordering::Relaxed);
+            for i in 0..self.feature_dim {
+                stats.moments.mean[i] = f64::from_bits(self.mean[i].load(Ordering::Relaxed));
+                stats.moments.m2[i] = f64::from_bits(self.m2[i].load(Ordering::Relaxed));
+                stats.min[i] = f32::from_bits(self.min[i].load(Ordering::Relaxed));
+                stats.max[i] = f32::from_bits(self.max[i].load(Ordering::Relaxed));
+            }
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return stats;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DriftMonitor, DriftStats, FeatureMoments};
+    use crate::constants::FEATURE_DIM;
+
+    fn vectors(values: &[f32]) -> Vec<f32> {
+        values
+            .iter()
+            .flat_map(|&value| [value; FEATURE_DIM])
+            .collect()
+    }
+
+    #[test]
+    fn moments_merge_and_split_exactly() {
+        let of_vectors = |values: &[f32]| FeatureMoments::of_vectors(&vectors(values), FEATURE_DIM);
+        let all = of_vectors(&[1.0, 2.0, 3.0, 4.0, 10.0]);
+        assert_eq!(all.count, 5);
+        assert_eq!(all.mean[0], 4.0);
+        assert!((all.std_dev(0) - 12.5f64.sqrt()).abs() < 1e-12);
+
+        let mut merged = of_vectors(&[1.0, 2.0]);
+        let earlier = merged;
+        merged.merge(&of_vectors(&[3.0, 4.0, 10.0]));
+        assert_eq!(merged.count, all.count);
+        assert!((merged.mean[0] - all.mean[0]).abs() < 1e-12);
+        assert!((merged.m2[0] - all.m2[0]).abs() < 1e-9);
+
+        let window = merged.since(&earlier);
+        let expected = of_vectors(&[3.0, 4.0, 10.0]);
+        assert_eq!(window.count, 3);
+        assert!((window.mean[0] - expected.mean[0]).abs() < 1e-12);
+        assert!((window.m2[0] - expected.m2[0]).abs() < 1e-9);
+
+        let shift = window.shift(&earlier, 0).unwrap();
+        assert!((shift - (17.0 / 3.0 - 1.5) / earlier.std_dev(0)).abs() < 1e-9);
+        assert_eq!(window.shift(&FeatureMoments::new(FEATURE_DIM), 0), None);
+    }
+
+    #[test]
+    fn non_finite_vectors_are_left_out() {
+        let mut features = vectors(&[1.0, 3.0, 5.0]);
+        features[FEATURE_DIM + 2] = f32::NAN;
+        let mut stats = DriftStats::new(FEATURE_DIM);
+        stats.record(&features);
+        assert_eq!(stats.moments.count, 2);
+        assert_eq!(stats.moments.mean[0], 3.0);
+        assert_eq!((stats.min[0], stats.max[0]), (1.0, 5.0));
+    }
+
+    #[test]
+    fn monitor_publishes_whole_snapshots() {
+        let monitor = DriftMonitor::new(FEATURE_DIM);
+        assert_eq!(monitor.snapshot(), DriftStats::new(FEATURE_DIM));
+        let mut stats = DriftStats::new(FEATURE_DIM);
+        stats.record(&vectors(&[2.0, 4.0]));
+        monitor.publish(&stats);
+        assert_eq!(monitor.snapshot(), stats);
+
+        let mut narrow = DriftStats::new(2);
+        narrow.record(&[1.0, 10.0, 3.0, 20.0]);
+        assert_eq!(narrow.moments.count, 2);
+        assert_eq!(narrow.moments.mean[..2], [2.0, 15.0]);
+        assert_eq!((narrow.min[1], narrow.max[1]), (10.0, 20.0));
+    }
+}
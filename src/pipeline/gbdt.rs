@@ -0,0 +1,419 @@
+//! Native gradient-boosted tree backend.
+//!
+//! Most models scoring feature vectors at this latency are tree ensembles, which need no ONNX
+//! runtime: the backend walks every tree on the inference thread during `submit_batch`, so each
+//! batch is ready as soon as it is submitted. Models are read from XGBoost's text dump
+//! (`Booster.dump_model(path)`, with or without stats, of a model trained without feature
+//! names), after optional `key = value` lines:
+//!
+//! ```text
+//! objective = logistic   # or identity, the default
+//! base_margin = -0.4     # added to every tree sum; XGBoost's logit(base_score) for logistic
+//! booster[0]:
+//! 0:[f3<0.5] yes=1,no=2,missing=1
+//!     1:leaf=0.12
+//!     2:leaf=-0.3
+//! booster[1]:
+//! 0:leaf=0.05
+//! ```
+//!
+//! A vector goes to `yes` when its feature is below the threshold, to `missing` when it is NaN,
+//! and to `no` otherwise. Each batch is transposed into [`FeatureColumns`] and the trees are
+//! walked a level at a time across every vector, so the vectors' reads at each level come from
+//! the few columns that level splits on.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::buffer_pool::BufferPool;
+use crate::config::{MAX_BATCH_VECTORS, gpu_buffer_pool_capacity};
+use crate::pipeline::columns::FeatureColumns;
+use crate::pipeline::session::{BatchCompletion, InFlightBatch, InferenceBackend};
+
+/// `Node::feature` of a leaf, whose `threshold` holds its value.
+const LEAF: u32 = u32::MAX;
+
+/// How the sum of the trees becomes a score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Objective {
+    /// The sum itself, for regression.
+    #[default]
+    Identity,
+    /// `1 / (1 + exp(-sum))`, for binary classification.
+    Logistic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Node {
+    feature: u32,
+    threshold: f32,
+    yes: u32,
+    no: u32,
+    missing: u32,
+}
+
+impl Node {
+    fn leaf(value: f32) -> Self {
+        Self {
+            feature: LEAF,
+            threshold: value,
+            yes: 0,
+            no: 0,
+            missing: 0,
+        }
+    }
+}
+
+/// A parsed tree ensemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GbdtModel {
+    feature_dim: usize,
+    objective: Objective,
+    base_margin: f32,
+    /// Every tree's nodes, children by index into this array.
+    nodes: Vec<Node>,
+    roots: Vec<u32>,
+    /// Most splits on any root-to-leaf path.
+    depth: usize,
+}
+
+impl GbdtModel {
+    /// Parse a model whose splits must all use features below `feature_dim`.
+    pub fn parse(text: &str, feature_dim: usize) -> Result<Self, String> {
+        let mut objective = Objective::Identity;
+        let mut base_margin = 0.0;
+        // Each tree's nodes by their ids in the dump, children still in those ids.
+        let mut trees: Vec<Vec<Option<Node>>> = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_err = |message: String| format!("line {}: {message}", line_no + 1);
+            if line.starts_with("booster[") {
+                trees.push(Vec::new());
+                continue;
+            }
+            let Some(tree) = trees.last_mut() else {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| line_err("expected `key = value` or `booster[0]:`".into()))?;
+                match (key.trim(), value.trim()) {
+                    ("objective", "identity") => objective = Objective::Identity,
+                    ("objective", "logistic") => objective = Objective::Logistic,
+                    ("objective", other) => {
+                        return Err(line_err(format!(
+                            "unknown objective '{other}', expected identity or logistic"
+                        )));
+                    }
+                    ("base_margin", value) => {
+                        base_margin = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|value| value.is_finite())
+                            .ok_or_else(|| line_err(format!("invalid base_margin '{value}'")))?;
+                    }
+                    (other, _) => return Err(line_err(format!("unknown key '{other}'"))),
+                }
+                continue;
+            };
+            let (id, node) = parse_node(line, feature_dim).map_err(line_err)?;
+            if tree.len() <= id {
+                tree.resize(id + 1, None);
+            }
+            if tree[id].replace(node).is_some() {
+                return Err(line_err(format!("node {id} defined twice")));
+            }
+        }
+        if trees.is_empty() {
+            return Err("no trees; expected XGBoost's text dump (`booster[0]:` ...)".to_string());
+        }
+
+        let mut nodes = Vec::new();
+        let mut roots = Vec::with_capacity(trees.len());
+        let mut depth = 0;
+        for (tree_no, tree) in trees.iter().enumerate() {
+            let tree_err = |message: String| format!("booster[{tree_no}]: {message}");
+            let offset = nodes.len() as u32;
+            let mut depths = vec![0usize; tree.len()];
+            for (id, node) in tree.iter().enumerate() {
+                let Some(node) = *node else {
+                    return Err(tree_err(format!("node {id} missing")));
+                };
+                if node.feature == LEAF {
+                    nodes.push(node);
+                    continue;
+                }
+                // XGBoost numbers children after their parent, which also rules out cycles.
+                for child in [node.yes, node.no, node.missing] {
+                    let child = child as usize;
+                    if child <= id || child >= tree.len() {
+                        return Err(tree_err(format!("node {id} has invalid child {child}")));
+                    }
+                    depths[child] = depths[child].max(depths[id] + 1);
+                }
+                depth = depth.max(depths[id] + 1);
+                nodes.push(Node {
+                    yes: offset + node.yes,
+                    no: offset + node.no,
+                    missing: offset + node.missing,
+                    ..node
+                });
+            }
+            roots.push(offset);
+        }
+        Ok(Self {
+            feature_dim,
+            objective,
+            base_margin,
+            nodes,
+            roots,
+            depth,
+        })
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    pub fn objective(&self) -> Objective {
+        self.objective
+    }
+
+    pub fn num_trees(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Score every vector in `columns` into `scores`, walking each tree with one node cursor
+    /// per vector in `cursors`.
+    fn predict(&self, columns: &FeatureColumns, cursors: &mut [u32], scores: &mut [f32]) {
+        let num_vectors = columns.num_vectors();
+        let (cursors, scores) = (&mut cursors[..num_vectors], &mut scores[..num_vectors]);
+        scores.fill(self.base_margin);
+        for &root in &self.roots {
+            cursors.fill(root);
+            for _ in 0..self.depth {
+                for (vector, cursor) in cursors.iter_mut().enumerate() {
+                    let node = &self.nodes[*cursor as usize];
+                    if node.feature == LEAF {
+                        continue;
+                    }
+                    let value = columns.column(node.feature as usize)[vector];
+                    *cursor = if value.is_nan() {
+                        node.missing
+                    } else if value < node.threshold {
+                        node.yes
+                    } else {
+                        node.no
+                    };
+                }
+            }
+            for (score, &cursor) in scores.iter_mut().zip(cursors.iter()) {
+                *score += self.nodes[cursor as usize].threshold;
+            }
+        }
+        if self.objective == Objective::Logistic {
+            for score in scores {
+                *score = 1.0 / (1.0 + (-*score).exp());
+            }
+        }
+    }
+}
+
+/// Parse one dump line: `<id>:leaf=<value>[,...]` or
+/// `<id>:[f<feature><<threshold>] yes=<id>,no=<id>,missing=<id>[,...]`.
+fn parse_node(line: &str, feature_dim: usize) -> Result<(usize, Node), String> {
+    let (id, rest) = line
+        .split_once(':')
+        .ok_or_else(|| format!("expected a node, got '{line}'"))?;
+    let id = id
+        .parse::<usize>()
+        .map_err(|_| format!("invalid node id '{id}'"))?;
+    let parse_f32 = |text: &str| {
+        text.parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| format!("node {id}: invalid value '{text}'"))
+    };
+    if let Some(leaf) = rest.strip_prefix("leaf=") {
+        let value = leaf.split(',').next().unwrap_or_default();
+        return Ok((id, Node::leaf(parse_f32(value)?)));
+    }
+    let (split, children) = rest
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .ok_or_else(|| format!("node {id}: expected `leaf=` or `[f<feature><<threshold>]`"))?;
+    let (feature, threshold) = split
+        .strip_prefix('f')
+        .and_then(|split| split.split_once('<'))
+        .ok_or_else(|| {
+            format!("node {id}: unsupported split '{split}'; dump a model without feature names")
+        })?;
+    let feature = feature
+        .parse::<usize>()
+        .ok()
+        .filter(|&feature| feature < feature_dim)
+        .ok_or_else(|| {
+            format!("node {id}: feature must be in 0..{feature_dim}, got '{feature}'")
+        })?;
+    let (mut yes, mut no, mut missing) = (None, None, None);
+    for item in children.trim().split(',') {
+        let (key, value) = item.split_once('=').unwrap_or((item, ""));
+        let child = || {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("node {id}: invalid child '{value}'"))
+        };
+        match key {
+            "yes" => yes = Some(child()?),
+            "no" => no = Some(child()?),
+            "missing" => missing = Some(child()?),
+            // `gain` and `cover` from a dump with stats.
+            _ => {}
+        }
+    }
+    let (Some(yes), Some(no), Some(missing)) = (yes, no, missing) else {
+        return Err(format!("node {id}: needs yes, no and missing"));
+    };
+    Ok((
+        id,
+        Node {
+            feature: feature as u32,
+            threshold: parse_f32(threshold)?,
+            yes,
+            no,
+            missing,
+        },
+    ))
+}
+
+/// Scores batches with a [`GbdtModel`] on the inference thread. One batch at a time, complete
+/// when `submit_batch` returns.
+pub struct GbdtBackend {
+    model: GbdtModel,
+    columns: FeatureColumns,
+    cursors: Vec<u32>,
+    output: Vec<f32>,
+    available: Arc<AtomicBool>,
+    /// Shared by every batch: each is ready before it is returned.
+    completion: Arc<BatchCompletion>,
+}
+
+impl GbdtBackend {
+    pub fn new(model: GbdtModel) -> Self {
+        Self {
+            columns: FeatureColumns::new(model.feature_dim, MAX_BATCH_VECTORS),
+            model,
+            cursors: vec![0; MAX_BATCH_VECTORS],
+            output: vec![0.0; MAX_BATCH_VECTORS],
+            available: Arc::new(AtomicBool::new(true)),
+            completion: {
+                let completion = BatchCompletion::new();
+                completion.mark_ready();
+                Arc::new(completion)
+            },
+        }
+    }
+
+    pub fn model(&self) -> &GbdtModel {
+        &self.model
+    }
+}
+
+impl InferenceBackend for GbdtBackend {
+    type Resources = ();
+
+    fn make_pool(feature_dim: usize) -> &'static BufferPool {
+        BufferPool::leak_new(gpu_buffer_pool_capacity(feature_dim))
+    }
+
+    fn feature_dim(&self) -> usize {
+        self.model.feature_dim
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        self.available
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    fn is_available(&self) -> bool {
+        self.available.load(Ordering::Acquire)
+    }
+
+    // The trait's contract: `input_host_ptr` holds `num_vectors` vectors of the pool.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn submit_batch(
+        &mut self,
+        input_host_ptr: *const f32,
+        num_vectors: usize,
+    ) -> InFlightBatch<()> {
+        let rows = unsafe {
+            std::slice::from_raw_parts(input_host_ptr, num_vectors * self.model.feature_dim)
+        };
+        self.columns.fill(rows);
+        self.model
+            .predict(&self.columns, &mut self.cursors, &mut self.output);
+        InFlightBatch::new(
+            Arc::clone(&self.completion),
+            self.output.as_ptr(),
+            num_vectors,
+            Arc::clone(&self.available),
+            (),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GbdtBackend, GbdtModel, Objective};
+    use crate::pipeline::session::{BatchPoll, InferenceBackend};
+
+    const MODEL: &str = "\
+objective = identity
+base_margin = 0.5
+booster[0]:
+0:[f0<1] yes=1,no=2,missing=2
+\t1:leaf=1
+\t2:[f1<10] yes=3,no=4,missing=3,gain=2.5,cover=8
+\t\t3:leaf=2
+\t\t4:leaf=3
+booster[1]:
+0:leaf=-0.25,cover=8
+";
+
+    #[test]
+    fn trees_sum_over_the_branch_each_vector_takes() {
+        let model = GbdtModel::parse(MODEL, 2).unwrap();
+        assert_eq!(model.num_trees(), 2);
+        let mut backend = GbdtBackend::new(model);
+        // Leaves 1, 3 (f1 < 10), 4, and 3 again for NaN, which takes `missing` at both splits.
+        let rows = [0.0, 50.0, 5.0, 5.0, 5.0, 10.0, f32::NAN, f32::NAN];
+        assert!(backend.try_acquire());
+        assert!(!backend.is_available());
+        let batch = backend.submit_batch(rows.as_ptr(), 4);
+        assert!(matches!(batch.completion.poll(), BatchPoll::Ready));
+        let scores = unsafe { std::slice::from_raw_parts(batch.output_ptr, batch.output_len) };
+        assert_eq!(scores, &[1.25, 2.25, 3.25, 2.25]);
+    }
+
+    #[test]
+    fn logistic_objective_and_invalid_models() {
+        let logistic = MODEL.replace("identity", "logistic");
+        let model = GbdtModel::parse(&logistic, 2).unwrap();
+        assert_eq!(model.objective(), Objective::Logistic);
+        let mut backend = GbdtBackend::new(model);
+        assert!(backend.try_acquire());
+        let batch = backend.submit_batch([0.0f32, 0.0].as_ptr(), 1);
+        let score = unsafe { *batch.output_ptr };
+        assert!((score - 1.0 / (1.0 + (-1.25f32).exp())).abs() < 1e-6);
+
+        assert!(GbdtModel::parse(MODEL, 1).is_err(), "f1 beyond feature_dim");
+        assert!(GbdtModel::parse("objective = identity\n", 2).is_err());
+        assert!(GbdtModel::parse("booster[0]:\n0:[amount<1] yes=1,no=2,missing=1\n", 2).is_err());
+        assert!(
+            GbdtModel::parse("booster[0]:\n0:[f0<1] yes=1,no=2,missing=1\n1:leaf=1\n", 2).is_err()
+        );
+        assert!(GbdtModel::parse("booster[0]:\n0:[f0<1] yes=0,no=0,missing=0\n", 2).is_err());
+    }
+}
@@ -1,22 +1,36 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
 use std::thread;
 use std::time::{Duration, Instant};
 
 use disruptor::{EventGuard, EventPoller, MultiProducerBarrier, Polling, SingleConsumerBarrier};
 
 use crate::buffer_pool::PoolSlice;
-use crate::clock::elapsed_since_ns;
+use crate::calibration::Calibration;
+use crate::clock::{elapsed_since_ns, monotonic_now_ns};
 use crate::config::MAX_SESSION_BATCH_SIZE;
+use crate::constants::MAX_VECTORS_PER_REQUEST;
 use crate::metrics;
+use crate::pipeline::admission::LargeRequestAdmission;
+use crate::pipeline::circuit_breaker::CircuitBreaker;
 use crate::pipeline::connection_registry::ConnectionRegistry;
-use crate::pipeline::response_queue::{ResponseQueue, ResponseReady};
+use crate::pipeline::control_channel::{Cancellations, ControlEvent, ControlReceiver};
+use crate::pipeline::deadline::RequestDeadline;
+use crate::pipeline::drift::{DriftMonitor, DriftStats};
+use crate::pipeline::inline::RingOccupancy;
+use crate::pipeline::response_queue::{ResponseReady, ResponseRouter};
+use crate::pipeline::sampling::{RequestSampler, Sample};
 use crate::pipeline::session::{BatchPoll, InFlightBatch, InferenceBackend};
 use crate::ring_types::InferenceEvent;
 
 const MAX_COMPLETIONS_PER_PASS: usize = 8;
 const MAX_SUBMISSIONS_PER_PASS: usize = 8;
+const MAX_CONTROL_EVENTS_PER_PASS: usize = 16;
+/// Longest a response waits for room in a full response queue before it is dropped, so an IO
+/// thread that stops draining cannot stall the inference thread.
+const RESPONSE_PUSH_TIMEOUT: Duration = Duration::from_secs(1);
 
 struct BatchEntry<R: Send> {
     slot_count: usize,
@@ -39,23 +53,58 @@ enum BatchStopReason {
 
 struct InflightBatchEntry<R: Send> {
     entry: BatchEntry<R>,
+    /// When the batch was submitted, kept only with a request deadline or a circuit breaker.
+    submitted_at_ns: u64,
     #[cfg(feature = "metrics")]
     wait_started_at: Option<Instant>,
 }
 
+/// Ring entries taken off the backlog, in ring order: a batch the backend runs, or requests
+/// that timed out waiting for one.
+enum Inflight<R: Send> {
+    Batch(InflightBatchEntry<R>),
+    /// Answered once every batch ahead of them completes, since ring slots and pool space are
+    /// released in order.
+    TimedOut(Vec<PendingSlot>),
+}
+
+/// A freshly loaded backend to serve from once the batches on the current one complete.
+pub struct ModelSwap<B> {
+    pub backend: B,
+    /// Reported in metrics once the backend is serving.
+    pub version: String,
+}
+
 pub struct InferenceConsumer<B: InferenceBackend> {
     submission_poller: EventPoller<InferenceEvent, MultiProducerBarrier>,
     completion_poller: EventPoller<InferenceEvent, SingleConsumerBarrier>,
     backend: B,
     backlog: VecDeque<PendingSlot>,
-    inflight: VecDeque<InflightBatchEntry<B::Resources>>,
-    response_queues: Vec<Arc<ResponseQueue>>,
+    inflight: VecDeque<Inflight<B::Resources>>,
+    response_queues: Arc<ResponseRouter>,
     registry: Arc<ConnectionRegistry>,
     max_batch_slots: usize,
     batch_coalesce_timeout: Duration,
     backlog_started_at: Option<Instant>,
     coalesce_check_spins: u32,
     timers_idle: bool,
+    ring_occupancy: Option<Arc<RingOccupancy>>,
+    admission: Option<Arc<LargeRequestAdmission>>,
+    request_deadline: Option<RequestDeadline>,
+    request_timeout: Option<Duration>,
+    circuit: Option<Arc<CircuitBreaker>>,
+    model_swaps: Option<Receiver<ModelSwap<B>>>,
+    /// A swap waiting for the batches on the current backend, which no more are submitted to.
+    pending_swap: Option<ModelSwap<B>>,
+    /// Emptied input slice lists of completed batches, reused so building a batch does not
+    /// allocate.
+    spare_input_slices: Vec<Vec<PoolSlice>>,
+    control: Option<ControlReceiver>,
+    cancellations: Cancellations,
+    calibration: Option<Arc<Calibration>>,
+    drift: Option<(Arc<DriftMonitor>, DriftStats)>,
+    sampler: Option<RequestSampler>,
+    shutting_down: bool,
 }
 
 unsafe impl<B: InferenceBackend> Send for InferenceConsumer<B> {}
@@ -65,7 +114,7 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
         submission_poller: EventPoller<InferenceEvent, MultiProducerBarrier>,
         completion_poller: EventPoller<InferenceEvent, SingleConsumerBarrier>,
         backend: B,
-        response_queues: Vec<Arc<ResponseQueue>>,
+        response_queues: Arc<ResponseRouter>,
         registry: Arc<ConnectionRegistry>,
         max_batch_slots: usize,
         batch_coalesce_timeout: Duration,
@@ -83,9 +132,91 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
             backlog_started_at: None,
             coalesce_check_spins: 0,
             timers_idle: false,
+            ring_occupancy: None,
+            admission: None,
+            request_deadline: None,
+            request_timeout: None,
+            circuit: None,
+            model_swaps: None,
+            pending_swap: None,
+            spare_input_slices: Vec::new(),
+            control: None,
+            cancellations: Cancellations::default(),
+            calibration: None,
+            drift: None,
+            sampler: None,
+            shutting_down: false,
         }
     }
 
+    /// Count answered requests in `occupancy`, which the IO threads' inline fast path reads.
+    pub fn with_ring_occupancy(mut self, occupancy: Arc<RingOccupancy>) -> Self {
+        self.ring_occupancy = Some(occupancy);
+        self
+    }
+
+    /// Release each answered large request from `admission`, which the IO threads admit
+    /// requests through.
+    pub fn with_large_request_admission(mut self, admission: Arc<LargeRequestAdmission>) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Close the coalescing window early once the oldest waiting request could not otherwise be
+    /// answered within `deadline` of being published.
+    pub fn with_request_deadline(mut self, deadline: Duration) -> Self {
+        self.request_deadline = Some(RequestDeadline::new(deadline));
+        self
+    }
+
+    /// Answer requests still waiting for a batch `timeout` after they were published with a
+    /// timeout frame instead of running them.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Count every answered request in `circuit`'s error budget, as timed out if it waited out
+    /// the request timeout for a batch or ran in a batch that took longer than that. Needs
+    /// [`Self::with_request_timeout`].
+    pub fn with_circuit_breaker(mut self, circuit: Arc<CircuitBreaker>) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
+
+    /// Serve from each backend received on `swaps` instead of the current one. Batches already
+    /// on the current backend complete first; requests wait in the ring meanwhile.
+    pub fn with_model_swaps(mut self, swaps: Receiver<ModelSwap<B>>) -> Self {
+        self.model_swaps = Some(swaps);
+        self
+    }
+
+    /// Apply [`ControlEvent`]s from `control` between batches.
+    pub fn with_control(mut self, control: ControlReceiver) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Apply `calibration` to every result before it is encoded.
+    pub fn with_calibration(mut self, calibration: Arc<Calibration>) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Fold the features of every submitted batch into per-feature statistics published to
+    /// `monitor`.
+    pub fn with_drift_monitor(mut self, monitor: Arc<DriftMonitor>) -> Self {
+        let stats = DriftStats::new(monitor.feature_dim());
+        self.drift = Some((monitor, stats));
+        self
+    }
+
+    /// Hand a sampled fraction of answered requests, with their scores, to `sampler`'s writer.
+    pub fn with_sampler(mut self, sampler: RequestSampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
     pub fn run(self) {
         self.run_inner(None);
     }
@@ -95,12 +226,14 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
     }
 
     fn run_inner(mut self, stop: Option<Arc<AtomicBool>>) {
+        let _poison = PoisonOnPanic(Arc::clone(&self.response_queues));
         let mut idle_loops = 0u32;
         loop {
             if stop_requested(stop.as_ref()) {
                 return;
             }
-            let mut progressed = false;
+            let mut progressed = self.drain_control();
+            progressed |= self.try_swap_model();
 
             let backlog_was_empty = self.backlog.is_empty();
             match drain_visible_events(
@@ -142,6 +275,9 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
             }
 
             if !progressed {
+                if self.shutting_down && self.backlog.is_empty() && self.inflight.is_empty() {
+                    return;
+                }
                 if !self.timers_idle {
                     metrics::idle_timers();
                     self.timers_idle = true;
@@ -157,7 +293,73 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
         }
     }
 
+    /// Apply queued control events, a bounded number per pass. Returns `true` if any were.
+    fn drain_control(&mut self) -> bool {
+        let mut applied = false;
+        for _ in 0..MAX_CONTROL_EVENTS_PER_PASS {
+            let Some(event) = self.control.as_ref().and_then(ControlReceiver::try_recv) else {
+                break;
+            };
+            self.apply_control(event);
+            applied = true;
+        }
+        applied
+    }
+
+    fn apply_control(&mut self, event: ControlEvent) {
+        match event {
+            ControlEvent::Cancel { conn, request_seq } => {
+                self.cancellations.cancel(conn, request_seq)
+            }
+            ControlEvent::ConnectionClosed(conn) => self.cancellations.forget(conn),
+            ControlEvent::SetBatchCoalesce(timeout) => self.batch_coalesce_timeout = timeout,
+            ControlEvent::SetCalibration(calibration) => self.calibration = calibration,
+            ControlEvent::SetSampleRate(rate) => {
+                if let Some(sampler) = &mut self.sampler {
+                    sampler.set_rate(rate);
+                }
+            }
+            ControlEvent::Shutdown => self.shutting_down = true,
+        }
+    }
+
+    /// Take a backend off the swap channel, and put the pending one in place of the current
+    /// backend once no batch is running on it. Returns `true` if a backend was swapped in.
+    fn try_swap_model(&mut self) -> bool {
+        if self.pending_swap.is_none() {
+            self.pending_swap = self
+                .model_swaps
+                .as_ref()
+                .and_then(|swaps| swaps.try_recv().ok());
+        }
+        if self.pending_swap.is_none()
+            || self
+                .inflight
+                .iter()
+                .any(|inflight| matches!(inflight, Inflight::Batch(_)))
+        {
+            return false;
+        }
+        let swap = self.pending_swap.take().expect("pending swap just checked");
+        // The old backend is dropped here, with nothing left running on it.
+        self.backend = swap.backend;
+        if let Some(deadline) = &mut self.request_deadline {
+            *deadline = RequestDeadline::new(deadline.deadline());
+        }
+        metrics::set_model_version(&swap.version);
+        metrics::inc_model_reloads();
+        eprintln!("inference: serving model version {}", swap.version);
+        true
+    }
+
     fn try_submit_next(&mut self) -> bool {
+        if self.expire_timed_out() {
+            return true;
+        }
+        if self.pending_swap.is_some() {
+            // Let the batches on the outgoing backend drain.
+            return false;
+        }
         if self.backlog.is_empty() {
             self.backlog_started_at = None;
             self.coalesce_check_spins = 0;
@@ -168,14 +370,22 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
             if !self.backend.is_available() {
                 return false;
             }
-            if self.batch_coalesce_timeout > Duration::ZERO && self.coalesce_check_spins < 64 {
+            let out_of_slack = self.request_deadline.is_some_and(|deadline| {
+                let oldest = self.backlog.front().expect("backlog is not empty");
+                deadline.out_of_slack(oldest.published_at_ns, monotonic_now_ns())
+            });
+            if !out_of_slack
+                && self.batch_coalesce_timeout > Duration::ZERO
+                && self.coalesce_check_spins < 64
+            {
                 self.coalesce_check_spins += 1;
                 return false;
             }
             self.coalesce_check_spins = 0;
-            if self
-                .backlog_started_at
-                .is_some_and(|started| started.elapsed() < self.batch_coalesce_timeout)
+            if !out_of_slack
+                && self
+                    .backlog_started_at
+                    .is_some_and(|started| started.elapsed() < self.batch_coalesce_timeout)
             {
                 return false;
             }
@@ -191,25 +401,89 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
         if let Some(started) = self.backlog_started_at {
             metrics::record_backlog_age(started.elapsed());
         }
-        let batch_entry =
-            build_batch_entry(&mut self.backend, &mut self.backlog, self.max_batch_slots);
+        let input_slices = self
+            .spare_input_slices
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.max_batch_slots));
+        let batch_entry = build_batch_entry(
+            &mut self.backend,
+            &mut self.backlog,
+            self.max_batch_slots,
+            input_slices,
+        );
         metrics::inc_batches_submitted();
         metrics::add_vectors_submitted(batch_entry.batch.output_len as u64);
+        if let Some((monitor, stats)) = &mut self.drift {
+            // The backend only reads the input, so the batch can be sampled while it runs.
+            for slice in &batch_entry.batch.input_slices {
+                stats.record(slice.as_slice());
+            }
+            monitor.publish(stats);
+        }
         if self.backlog.is_empty() {
             self.backlog_started_at = None;
         }
         self.coalesce_check_spins = 0;
-        self.inflight.push_back(InflightBatchEntry {
+        self.inflight.push_back(Inflight::Batch(InflightBatchEntry {
             entry: batch_entry,
+            submitted_at_ns: if self.request_deadline.is_some() || self.circuit.is_some() {
+                monotonic_now_ns()
+            } else {
+                0
+            },
             #[cfg(feature = "metrics")]
             wait_started_at: None,
-        });
+        }));
+        true
+    }
+
+    /// Take the requests at the front of the backlog that have waited out the request timeout
+    /// off it, to be answered with timeouts. Returns `true` if there were any.
+    fn expire_timed_out(&mut self) -> bool {
+        let Some(timeout) = self.request_timeout else {
+            return false;
+        };
+        let now_ns = monotonic_now_ns();
+        let expired = self
+            .backlog
+            .iter()
+            .take_while(|slot| {
+                Duration::from_nanos(now_ns.saturating_sub(slot.published_at_ns)) >= timeout
+            })
+            .count();
+        if expired == 0 {
+            return false;
+        }
+        let timed_out = self.backlog.drain(..expired).collect();
+        self.inflight.push_back(Inflight::TimedOut(timed_out));
+        // Counted now rather than once answered, which waits on any batch still running.
+        self.record_outcomes(0, expired as u64);
         true
     }
 
+    fn record_outcomes(&self, answered: u64, timed_out: u64) {
+        if let Some(circuit) = &self.circuit
+            && circuit.record(answered, timed_out)
+        {
+            metrics::inc_circuit_opened();
+            eprintln!(
+                "inference: backend timed out too many requests, opening the circuit for {:?}",
+                circuit.open_for()
+            );
+        }
+    }
+
     fn try_complete_front(&mut self) -> Result<bool, Polling> {
-        let Some(front) = self.inflight.front_mut() else {
-            return Ok(false);
+        let front = match self.inflight.front_mut() {
+            None => return Ok(false),
+            Some(Inflight::TimedOut(_)) => {
+                let Some(Inflight::TimedOut(slots)) = self.inflight.pop_front() else {
+                    unreachable!("front just checked");
+                };
+                self.answer_timed_out(slots)?;
+                return Ok(true);
+            }
+            Some(Inflight::Batch(front)) => front,
         };
 
         match front.entry.batch.completion.poll() {
@@ -219,7 +493,21 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
                 Ok(false)
             }
             BatchPoll::Ready => {
-                let inflight = self.inflight.pop_front().expect("front just checked");
+                let inflight = self.pop_front_batch();
+                if let Some(deadline) = &mut self.request_deadline {
+                    deadline.record_service(elapsed_since_ns(inflight.submitted_at_ns));
+                }
+                if self.circuit.is_some() {
+                    let slots = inflight.entry.slot_count as u64;
+                    let timeout = self
+                        .request_timeout
+                        .expect("circuit breaker needs a timeout");
+                    if elapsed_since_ns(inflight.submitted_at_ns) >= timeout {
+                        self.record_outcomes(0, slots);
+                    } else {
+                        self.record_outcomes(slots, 0);
+                    }
+                }
                 #[cfg(feature = "metrics")]
                 metrics::record_batch_wait(
                     inflight
@@ -229,24 +517,29 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
                 );
                 #[cfg(not(feature = "metrics"))]
                 metrics::record_batch_wait(Duration::ZERO);
-                let response_queues = self.response_queues.clone();
+                let response_queues = Arc::clone(&self.response_queues);
                 let registry = Arc::clone(&self.registry);
-                let max_batch_slots = self.max_batch_slots;
+                debug_assert!(inflight.entry.slot_count <= self.max_batch_slots);
                 let mut guard = wait_for_completion_guard(
                     &mut self.completion_poller,
                     inflight.entry.slot_count,
                 )?;
-                process_batch(
+                let input_slices = process_batch(
                     &mut guard,
                     inflight.entry,
                     &response_queues,
                     &registry,
-                    max_batch_slots,
+                    &self.cancellations,
+                    self.calibration.as_deref(),
+                    self.sampler.as_mut(),
+                    self.ring_occupancy.as_deref(),
+                    self.admission.as_deref(),
                 );
+                self.spare_input_slices.push(input_slices);
                 Ok(true)
             }
             BatchPoll::Failed => {
-                let inflight = self.inflight.pop_front().expect("front just checked");
+                let inflight = self.pop_front_batch();
                 #[cfg(feature = "metrics")]
                 metrics::record_batch_wait(
                     inflight
@@ -261,6 +554,49 @@ impl<B: InferenceBackend> InferenceConsumer<B> {
             }
         }
     }
+
+    fn pop_front_batch(&mut self) -> InflightBatchEntry<B::Resources> {
+        match self.inflight.pop_front() {
+            Some(Inflight::Batch(batch)) => batch,
+            _ => unreachable!("front just checked"),
+        }
+    }
+
+    /// Answer requests that timed out in the backlog with timeouts, then release their
+    /// features.
+    fn answer_timed_out(&mut self, slots: Vec<PendingSlot>) -> Result<(), Polling> {
+        let mut guard = wait_for_completion_guard(&mut self.completion_poller, slots.len())?;
+        for event in &mut guard {
+            queue_response(
+                &self.response_queues,
+                &self.registry,
+                &self.cancellations,
+                event,
+                |slot| slot.fill_timed_out(event.conn, event.request_seq, event.published_at_ns),
+            );
+            if let Some(admission) = &self.admission {
+                admission.complete(event.num_vectors as usize);
+            }
+            metrics::inc_responses_written();
+            metrics::dec_req_occ();
+        }
+        if let Some(occupancy) = &self.ring_occupancy {
+            occupancy.add_completed(slots.len() as u64);
+        }
+        Ok(())
+    }
+}
+
+/// Poisons every response queue if the inference thread unwinds, so their consumers fail
+/// connections instead of waiting for responses that will never come.
+struct PoisonOnPanic(Arc<ResponseRouter>);
+
+impl Drop for PoisonOnPanic {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.poison_all();
+        }
+    }
 }
 
 fn idle_wait(idle_loops: &mut u32) {
@@ -313,13 +649,17 @@ fn take_event_features(event: &InferenceEvent) -> PoolSlice {
     }
 }
 
+/// Take the next batch off the front of `backlog` and submit it, collecting its features into
+/// `input_slices`, which must be empty.
 fn build_batch_entry<B: InferenceBackend>(
     backend: &mut B,
     backlog: &mut VecDeque<PendingSlot>,
     max_batch_slots: usize,
+    mut input_slices: Vec<PoolSlice>,
 ) -> BatchEntry<B::Resources> {
     debug_assert!(max_batch_slots > 0);
     debug_assert!(max_batch_slots <= MAX_SESSION_BATCH_SIZE);
+    debug_assert!(input_slices.is_empty());
     let first = backlog
         .front()
         .expect("build_batch_entry requires non-empty backlog");
@@ -327,7 +667,6 @@ fn build_batch_entry<B: InferenceBackend>(
 
     let mut slot_count = 0usize;
     let mut num_vectors = 0usize;
-    let mut input_slices = Vec::new();
     let backlog_slots_at_build = backlog.len() as u64;
     let mut stop_reason = None;
 
@@ -400,36 +739,100 @@ fn wait_for_completion_guard<'a>(
     }
 }
 
+/// Queue the response `fill` writes for `event` on its IO thread's response queue, unless its
+/// connection closed or cancelled the request.
+fn queue_response(
+    response_queues: &ResponseRouter,
+    registry: &ConnectionRegistry,
+    cancellations: &Cancellations,
+    event: &InferenceEvent,
+    fill: impl FnOnce(&mut ResponseReady),
+) {
+    let conn = event.conn;
+    match response_queues.get(conn.shard_id()) {
+        None => {
+            // No IO thread ever registered this shard, so nothing can deliver the response.
+            // Close the connection, if it is one, rather than leave its client waiting.
+            metrics::inc_responses_dropped();
+            registry.shutdown(conn);
+        }
+        Some(response_queue)
+            if registry.is_open(conn) && !cancellations.is_cancelled(conn, event.request_seq) =>
+        {
+            // Encode straight into the queue slot the IO thread will read.
+            let pushed = response_queue.try_push_with(RESPONSE_PUSH_TIMEOUT, |slot| {
+                fill(slot);
+                slot.received_at_ns = event.received_at_ns;
+            });
+            if pushed.is_err() {
+                // The connection can never be answered in order now; close it rather than
+                // leave its client waiting.
+                metrics::inc_responses_dropped();
+                registry.shutdown(conn);
+            }
+        }
+        Some(_) => {}
+    }
+}
+
+/// Answer every request in `entry` and release its features and session. Returns its input slice
+/// list, emptied, for the next batch to reuse.
+#[allow(clippy::too_many_arguments)]
 fn process_batch<R: Send>(
     guard: &mut EventGuard<'_, InferenceEvent, SingleConsumerBarrier>,
-    entry: BatchEntry<R>,
-    response_queues: &[Arc<ResponseQueue>],
+    mut entry: BatchEntry<R>,
+    response_queues: &ResponseRouter,
     registry: &Arc<ConnectionRegistry>,
-    max_batch_slots: usize,
-) {
+    cancellations: &Cancellations,
+    calibration: Option<&Calibration>,
+    mut sampler: Option<&mut RequestSampler>,
+    ring_occupancy: Option<&RingOccupancy>,
+    admission: Option<&LargeRequestAdmission>,
+) -> Vec<PoolSlice> {
     let mut guard_ref = &mut *guard;
     let output =
         unsafe { std::slice::from_raw_parts(entry.batch.output_ptr, entry.batch.output_len) };
     let mut output_offset = 0usize;
+    let mut calibrated = [0f32; MAX_VECTORS_PER_REQUEST];
 
-    debug_assert!(entry.slot_count <= max_batch_slots);
-    for _ in 0..entry.slot_count {
+    for slot in 0..entry.slot_count {
         let event = guard_ref
             .next()
             .expect("guard exhausted before queued batch slot_count");
         let num_vecs = event.num_vectors as usize;
 
-        let response = &output[output_offset..output_offset + num_vecs];
-        let conn = event.conn;
-        if registry.is_open(conn) {
-            response_queues[conn.shard_id() as usize].push(ResponseReady::encode(
-                conn,
+        let scores = &output[output_offset..output_offset + num_vecs];
+        let mut response = scores;
+        if let Some(calibration) = calibration {
+            // The session owns `output`, so calibrate a copy.
+            let calibrated = &mut calibrated[..num_vecs];
+            calibrated.copy_from_slice(response);
+            calibration.apply_all(calibrated);
+            response = calibrated;
+        }
+        if let Some(sampler) = sampler.as_deref_mut()
+            && sampler.pick()
+        {
+            sampler.offer(Sample {
+                conn: event.conn,
+                request_seq: event.request_seq,
+                features: entry.batch.input_slices[slot].as_slice().to_vec(),
+                scores: scores.to_vec(),
+                calibrated: calibration.is_some().then(|| response.to_vec()),
+            });
+        }
+        queue_response(response_queues, registry, cancellations, event, |slot| {
+            slot.fill(
+                event.conn,
                 event.request_seq,
                 event.published_at_ns,
                 response,
-            ));
-        }
+            )
+        });
 
+        if let Some(admission) = admission {
+            admission.complete(num_vecs);
+        }
         output_offset += num_vecs;
         metrics::inc_responses_written();
         metrics::dec_req_occ();
@@ -439,11 +842,17 @@ fn process_batch<R: Send>(
         output_offset, entry.batch.output_len,
         "slot_count/num_vectors mismatch between ring events and batch output"
     );
+    if let Some(occupancy) = ring_occupancy {
+        occupancy.add_completed(entry.slot_count as u64);
+    }
 
     let session_available = Arc::clone(&entry.batch.session_available);
     #[cfg(feature = "metrics")]
     metrics::record_batch_total(entry.submitted_at.elapsed());
+    let mut input_slices = std::mem::take(&mut entry.batch.input_slices);
+    input_slices.clear();
     drop(entry);
     session_available.store(true, Ordering::Release);
     metrics::inc_batches_completed();
+    input_slices
 }
@@ -0,0 +1,303 @@
+//! Inline scoring of small requests on the IO thread.
+//!
+//! For a model cheap enough to evaluate in a few nanoseconds, the hop through the request ring,
+//! the inference thread, the response queue and its eventfd dominates the latency of a small
+//! request. With the fast path enabled an IO thread scores such a request itself and answers it
+//! straight away. A [`PlacementPolicy`] decides which requests qualify, by size and by how many
+//! requests are already waiting in the ring: under load requests still go through the ring so
+//! the backend keeps batching.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cache_line::CachePadded;
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::protocol::{self, BYTES_PER_F32};
+
+/// A model evaluated on the IO thread. It must give the same result as the backend would.
+pub trait InlineScorer: Send + Sync {
+    /// Score one vector of the server's `--feature-dim` features.
+    fn score(&self, features: &[f32]) -> f32;
+}
+
+/// `weights · features + bias`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearModel {
+    weights: Vec<f32>,
+    bias: f32,
+}
+
+impl LinearModel {
+    /// A model of vectors as wide as `weights`.
+    pub fn new(weights: Vec<f32>, bias: f32) -> Self {
+        Self { weights, bias }
+    }
+
+    /// Parse `feature_dim` weights followed by the bias, separated by whitespace or commas.
+    pub fn parse(text: &str, feature_dim: usize) -> Result<Self, String> {
+        let values = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("invalid value '{value}'"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() != feature_dim + 1 {
+            return Err(format!(
+                "expected {} weights and a bias, found {} values",
+                feature_dim,
+                values.len()
+            ));
+        }
+        let bias = values[feature_dim];
+        Ok(Self::new(values[..feature_dim].to_vec(), bias))
+    }
+
+    pub fn load(path: &Path, feature_dim: usize) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        Self::parse(&text, feature_dim).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl InlineScorer for LinearModel {
+    fn score(&self, features: &[f32]) -> f32 {
+        self.weights
+            .iter()
+            .zip(features)
+            .fold(self.bias, |acc, (w, x)| acc + w * x)
+    }
+}
+
+/// Requests published to the request ring and not yet answered, across all IO threads.
+#[derive(Debug, Default)]
+pub struct RingOccupancy {
+    published: CachePadded<AtomicU64>,
+    completed: CachePadded<AtomicU64>,
+}
+
+impl RingOccupancy {
+    /// Count a request before its slot is published.
+    pub fn add_published(&self, requests: u64) {
+        self.published.fetch_add(requests, Ordering::Relaxed);
+    }
+
+    pub fn add_completed(&self, requests: u64) {
+        self.completed.fetch_add(requests, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> u64 {
+        let completed = self.completed.load(Ordering::Relaxed);
+        self.published
+            .load(Ordering::Relaxed)
+            .saturating_sub(completed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Where a request is processed, and for offloads, why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Scored on the IO thread.
+    Inline,
+    /// Published to the ring: no rule admits a request this large.
+    OffloadSize,
+    /// Published to the ring: too many requests already waiting for one this large.
+    OffloadBusy,
+}
+
+/// Rules admitting requests to the inline path, written `vectors:occupancy[,...]`.
+///
+/// A rule `n:m` scores requests of up to `n` vectors inline while at most `m` requests wait in
+/// the ring. A request uses the first rule, by ascending `n`, that covers its size, so `1:8,4:0`
+/// scores one-vector requests inline until eight are queued, requests of two to four vectors only
+/// while the ring is empty, and offloads anything larger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacementPolicy {
+    /// `(max_vectors, max_ring_occupancy)`, ascending by `max_vectors`.
+    rules: Vec<(u8, u64)>,
+}
+
+impl PlacementPolicy {
+    /// One-vector requests inline while the ring is empty.
+    pub const DEFAULT: &str = "1:0";
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = text
+            .split(',')
+            .map(|rule| {
+                let (vectors, occupancy) = rule
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| format!("rule '{rule}': expected `vectors:occupancy`"))?;
+                let vectors: u8 = vectors
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&n| (1..=MAX_VECTORS_PER_REQUEST).contains(&(n as usize)))
+                    .ok_or_else(|| {
+                        format!("rule '{rule}': vectors must be in 1..={MAX_VECTORS_PER_REQUEST}")
+                    })?;
+                let occupancy = occupancy
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("rule '{rule}': invalid occupancy"))?;
+                Ok((vectors, occupancy))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        rules.sort_by_key(|&(vectors, _)| vectors);
+        if rules.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err("two rules for the same request size".to_string());
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn place(&self, num_vectors: u8, ring_occupancy: u64) -> Placement {
+        match self
+            .rules
+            .iter()
+            .find(|&&(max_vectors, _)| num_vectors <= max_vectors)
+        {
+            None => Placement::OffloadSize,
+            Some(&(_, max_occupancy)) if ring_occupancy > max_occupancy => Placement::OffloadBusy,
+            Some(_) => Placement::Inline,
+        }
+    }
+}
+
+impl Default for PlacementPolicy {
+    fn default() -> Self {
+        Self::parse(Self::DEFAULT).expect("default placement policy")
+    }
+}
+
+/// A model's scorer and placement policy, shared by the IO threads.
+#[derive(Clone)]
+pub struct InlineFastPath {
+    scorer: Arc<dyn InlineScorer>,
+    policy: PlacementPolicy,
+    occupancy: Arc<RingOccupancy>,
+}
+
+impl InlineFastPath {
+    /// `occupancy` must also be given to the inference consumer.
+    pub fn new(
+        scorer: Arc<dyn InlineScorer>,
+        policy: PlacementPolicy,
+        occupancy: Arc<RingOccupancy>,
+    ) -> Self {
+        Self {
+            scorer,
+            policy,
+            occupancy,
+        }
+    }
+
+    pub fn occupancy(&self) -> &RingOccupancy {
+        &self.occupancy
+    }
+
+    /// Place the request and, if it goes inline, score each of its vectors into `scores`. The
+    /// vectors are as wide as `feature_bytes` divides evenly into `num_vectors`.
+    pub fn place_and_score(
+        &self,
+        num_vectors: u8,
+        feature_bytes: &[u8],
+        scores: &mut [f32; MAX_VECTORS_PER_REQUEST],
+    ) -> Placement {
+        let placement = self.policy.place(num_vectors, self.occupancy.len());
+        if placement == Placement::Inline {
+            let feature_dim = feature_bytes.len() / BYTES_PER_F32 / num_vectors as usize;
+            let mut vector = [0.0; MAX_FEATURE_DIM];
+            let vector = &mut vector[..feature_dim];
+            for (score, bytes) in scores
+                .iter_mut()
+                .zip(feature_bytes.chunks_exact(feature_dim * BYTES_PER_F32))
+                .take(num_vectors as usize)
+            {
+                protocol::copy_features(bytes, vector);
+                *score = self.scorer.score(vector);
+            }
+        }
+        placement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        InlineFastPath, InlineScorer, LinearModel, Placement, PlacementPolicy, RingOccupancy,
+    };
+    use crate::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+
+    #[test]
+    fn linear_model_parses_weights_then_bias() {
+        let text = format!("{}, 0.5\n", vec!["2"; FEATURE_DIM].join(" "));
+        let model = LinearModel::parse(&text, FEATURE_DIM).unwrap();
+        assert_eq!(
+            model.score(&[1.0; FEATURE_DIM]),
+            2.0 * FEATURE_DIM as f32 + 0.5
+        );
+        assert!(LinearModel::parse("1 2 3", FEATURE_DIM).is_err());
+        assert!(LinearModel::parse(&text.replace("0.5", "x"), FEATURE_DIM).is_err());
+        assert_eq!(
+            LinearModel::parse("1 2 3", 2).unwrap().score(&[1.0, 1.0]),
+            6.0
+        );
+    }
+
+    #[test]
+    fn policy_places_by_size_then_occupancy() {
+        let policy = PlacementPolicy::parse("4:0, 1:8").unwrap();
+        assert_eq!(policy.place(1, 8), Placement::Inline);
+        assert_eq!(policy.place(1, 9), Placement::OffloadBusy);
+        assert_eq!(policy.place(3, 0), Placement::Inline);
+        assert_eq!(policy.place(3, 1), Placement::OffloadBusy);
+        assert_eq!(policy.place(5, 0), Placement::OffloadSize);
+        assert!(PlacementPolicy::parse("1:0,1:4").is_err());
+        assert!(PlacementPolicy::parse("0:1").is_err());
+        assert!(PlacementPolicy::parse("1").is_err());
+        assert_eq!(
+            PlacementPolicy::default().place(2, 0),
+            Placement::OffloadSize
+        );
+    }
+
+    #[test]
+    fn scores_each_vector_of_inline_requests() {
+        let occupancy = Arc::new(RingOccupancy::default());
+        let fast_path = InlineFastPath::new(
+            Arc::new(LinearModel::new(vec![1.0; FEATURE_DIM], 0.0)),
+            PlacementPolicy::parse("2:1").unwrap(),
+            Arc::clone(&occupancy),
+        );
+        let features: Vec<u8> = (0..2 * FEATURE_DIM)
+            .flat_map(|i| ((i / FEATURE_DIM) as f32 + 1.0).to_le_bytes())
+            .collect();
+        let mut scores = [0.0; MAX_VECTORS_PER_REQUEST];
+        assert_eq!(
+            fast_path.place_and_score(2, &features, &mut scores),
+            Placement::Inline
+        );
+        assert_eq!(scores[..2], [FEATURE_DIM as f32, 2.0 * FEATURE_DIM as f32]);
+
+        occupancy.add_published(2);
+        assert_eq!(
+            fast_path.place_and_score(1, &features, &mut scores),
+            Placement::OffloadBusy
+        );
+        occupancy.add_completed(1);
+        assert_eq!(
+            fast_path.place_and_score(1, &features, &mut scores),
+            Placement::Inline
+        );
+    }
+}
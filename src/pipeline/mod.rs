@@ -1,6 +1,17 @@
+pub mod admission;
+pub mod backend;
+pub mod circuit_breaker;
+pub mod columns;
 pub mod connection_registry;
+pub mod control_channel;
+pub mod deadline;
+pub mod drift;
+pub mod gbdt;
 pub mod inference;
+pub mod inline;
 pub mod response_queue;
+pub mod sampling;
 pub mod session;
 
-pub use session::{InferenceBackend, OrtBackend};
+pub use backend::{BackendKind, ModelBackend};
+pub use session::{InferenceBackend, Numerics, OrtBackend};
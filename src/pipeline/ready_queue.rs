@@ -4,12 +4,13 @@ use std::cell::UnsafeCell;
 use std::mem::MaybeUninit;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::cache_line::CachePadded;
 pub use crate::connection_id::ConnectionRef;
 
 pub struct ReadyQueue {
     capacity: usize,
-    head: AtomicUsize,
-    tail: AtomicUsize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
     slots: Box<[UnsafeCell<MaybeUninit<ConnectionRef>>]>,
 }
 
@@ -24,8 +25,8 @@ impl ReadyQueue {
             .into_boxed_slice();
         Self {
             capacity,
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
             slots,
         }
     }
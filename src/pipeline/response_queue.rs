@@ -1,153 +1,599 @@
 use std::cell::UnsafeCell;
-use std::mem::MaybeUninit;
 use std::os::fd::RawFd;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use crate::config::WRITE_BUF_SIZE;
+use crate::cache_line::CachePadded;
+use crate::clock::monotonic_now_ns;
 use crate::connection_id::ConnectionRef;
-use crate::protocol;
+use crate::constants::MAX_VECTORS_PER_REQUEST;
+#[cfg(target_os = "linux")]
+use crate::notify::MsgRingNotifier;
+use crate::notify::{Notifier, NotifyFd};
 
+/// A request's scores on their way to its IO thread, which serializes them in the format of
+/// the connection they answer.
 #[derive(Clone, Copy)]
 pub struct ResponseReady {
     pub conn: ConnectionRef,
     pub request_seq: u64,
+    /// When the read that completed the request finished; `published_at_ns` unless set after
+    /// `fill`.
+    pub received_at_ns: u64,
     pub published_at_ns: u64,
-    pub len: usize,
-    pub data: [u8; WRITE_BUF_SIZE],
+    /// When the inference thread queued the response.
+    pub processed_at_ns: u64,
+    pub num_results: usize,
+    /// The request ran out of its timeout before it was run; it is answered with a timeout
+    /// frame and carries no results.
+    pub timed_out: bool,
+    results: [f32; MAX_VECTORS_PER_REQUEST],
 }
 
 impl ResponseReady {
-    pub fn encode(
+    fn empty() -> Self {
+        Self {
+            conn: ConnectionRef::new(0, 0, 1),
+            request_seq: 0,
+            received_at_ns: 0,
+            published_at_ns: 0,
+            processed_at_ns: 0,
+            num_results: 0,
+            timed_out: false,
+            results: [0.0; MAX_VECTORS_PER_REQUEST],
+        }
+    }
+
+    pub fn new(
         conn: ConnectionRef,
         request_seq: u64,
         published_at_ns: u64,
         results: &[f32],
     ) -> Self {
-        let len = protocol::response_size(results.len());
-        debug_assert!(len <= WRITE_BUF_SIZE);
-        let mut data = [0u8; WRITE_BUF_SIZE];
-        protocol::encode_response(results, &mut data[..len]);
-        Self {
-            conn,
-            request_seq,
-            published_at_ns,
-            len,
-            data,
-        }
+        let mut entry = Self::empty();
+        entry.fill(conn, request_seq, published_at_ns, results);
+        entry
+    }
+
+    /// Overwrite this entry with `results`, touching only the values the response uses.
+    pub fn fill(
+        &mut self,
+        conn: ConnectionRef,
+        request_seq: u64,
+        published_at_ns: u64,
+        results: &[f32],
+    ) {
+        self.results[..results.len()].copy_from_slice(results);
+        self.conn = conn;
+        self.request_seq = request_seq;
+        self.received_at_ns = published_at_ns;
+        self.published_at_ns = published_at_ns;
+        self.processed_at_ns = monotonic_now_ns();
+        self.num_results = results.len();
+        self.timed_out = false;
+    }
+
+    /// Overwrite this entry with a timeout for a request that was never run.
+    pub fn fill_timed_out(&mut self, conn: ConnectionRef, request_seq: u64, published_at_ns: u64) {
+        self.fill(conn, request_seq, published_at_ns, &[]);
+        self.timed_out = true;
     }
+
+    pub fn results(&self) -> &[f32] {
+        &self.results[..self.num_results]
+    }
+}
+
+/// Occupancy counters written by the producer, recorded only with the `metrics` feature.
+#[derive(Default)]
+struct OccupancyStats {
+    max_occupancy: AtomicUsize,
+    interval_max_occupancy: AtomicUsize,
+    full_waits: AtomicU64,
+    full_ns: AtomicU64,
+}
+
+/// Point-in-time view of a queue's occupancy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueOccupancy {
+    pub capacity: usize,
+    pub len: usize,
+    /// Highest occupancy since the queue was created.
+    pub max_occupancy: usize,
+    /// Highest occupancy since the previous `take_occupancy`.
+    pub interval_max_occupancy: usize,
+    /// Pushes that found the queue full, and total time they spent waiting (cumulative).
+    pub full_waits: u64,
+    pub full_ns: u64,
+}
+
+/// Why [`ResponseQueue::try_push_with`] gave up on a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushError {
+    /// The queue was full and its consumer had stopped draining it.
+    Detached,
+    /// The queue stayed full for the whole timeout.
+    TimedOut,
 }
 
 pub struct ResponseQueue {
     capacity: usize,
-    head: AtomicUsize,
-    tail: AtomicUsize,
-    notify_fd: RawFd,
-    slots: Box<[UnsafeCell<MaybeUninit<ResponseReady>>]>,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    notifier: Arc<dyn Notifier>,
+    /// Set when the producer died; no more responses will arrive.
+    poisoned: AtomicBool,
+    /// Consumers attached through [`ResponseQueue::attach_consumer`].
+    consumers: AtomicUsize,
+    /// Set when the last attached consumer detached; nothing will free a slot.
+    detached: AtomicBool,
+    slots: Box<[UnsafeCell<ResponseReady>]>,
+    stats: CachePadded<OccupancyStats>,
 }
 
 unsafe impl Send for ResponseQueue {}
 unsafe impl Sync for ResponseQueue {}
 
 impl ResponseQueue {
+    /// A queue whose consumer polls [`Self::notify_fd`], as IO threads do.
     pub fn new(capacity: usize) -> Self {
-        let notify_fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
-        assert!(notify_fd >= 0, "eventfd creation failed");
+        let notify = NotifyFd::new().expect("notify fd creation failed");
+        Self::with_notifier(capacity, Arc::new(notify))
+    }
+
+    /// A queue that wakes its consumer through `notifier` when a push finds it empty.
+    pub fn with_notifier(capacity: usize, notifier: Arc<dyn Notifier>) -> Self {
         let slots = (0..capacity)
-            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .map(|_| UnsafeCell::new(ResponseReady::empty()))
             .collect::<Vec<_>>()
             .into_boxed_slice();
         Self {
             capacity,
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
-            notify_fd,
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            notifier,
+            poisoned: AtomicBool::new(false),
+            consumers: AtomicUsize::new(0),
+            detached: AtomicBool::new(false),
             slots,
+            stats: CachePadded::new(OccupancyStats::default()),
         }
     }
 
     pub fn push(&self, entry: ResponseReady) {
+        self.push_with(|slot| *slot = entry);
+    }
+
+    /// Write the next response in place with `fill`, waiting while the queue is full.
+    ///
+    /// Slots are reused without being cleared, so `fill` must set every field the consumer reads.
+    pub fn push_with(&self, fill: impl FnOnce(&mut ResponseReady)) {
+        self.push_until(None, fill)
+            .expect("an unbounded push waits for space");
+    }
+
+    /// Like [`Self::push_with`], but give up, without calling `fill`, once the queue has been
+    /// full for `timeout` or as soon as it is full with no consumer attached.
+    pub fn try_push_with(
+        &self,
+        timeout: Duration,
+        fill: impl FnOnce(&mut ResponseReady),
+    ) -> Result<(), PushError> {
+        self.push_until(Some(timeout), fill)
+    }
+
+    fn push_until(
+        &self,
+        timeout: Option<Duration>,
+        fill: impl FnOnce(&mut ResponseReady),
+    ) -> Result<(), PushError> {
+        let mut fill = Some(fill);
+        let mut full_since_ns = None;
         loop {
             let tail = self.tail.load(Ordering::Relaxed);
             let head = self.head.load(Ordering::Acquire);
             if tail.wrapping_sub(head) < self.capacity {
                 let was_empty = tail == head;
                 let idx = tail % self.capacity;
-                unsafe { (*self.slots[idx].get()).write(entry) };
+                if let Some(fill) = fill.take() {
+                    fill(unsafe { &mut *self.slots[idx].get() });
+                }
                 self.tail.store(tail.wrapping_add(1), Ordering::Release);
+                if cfg!(feature = "metrics") {
+                    self.record_push(tail.wrapping_sub(head) + 1, full_since_ns);
+                }
                 if was_empty {
-                    let one = 1u64;
-                    let rc = unsafe {
-                        libc::write(
-                            self.notify_fd,
-                            (&one as *const u64).cast::<libc::c_void>(),
-                            std::mem::size_of::<u64>(),
-                        )
-                    };
-                    if rc >= 0 {
-                        assert_eq!(
-                            rc as usize,
-                            std::mem::size_of::<u64>(),
-                            "short eventfd write"
-                        );
-                    }
+                    self.notifier.notify();
+                }
+                return Ok(());
+            }
+            if let Some(timeout) = timeout {
+                if self.detached.load(Ordering::Acquire) {
+                    return Err(PushError::Detached);
+                }
+                let since_ns = *full_since_ns.get_or_insert_with(monotonic_now_ns);
+                if monotonic_now_ns().saturating_sub(since_ns) >= timeout.as_nanos() as u64 {
+                    return Err(PushError::TimedOut);
                 }
-                return;
+            } else if cfg!(feature = "metrics") && full_since_ns.is_none() {
+                full_since_ns = Some(monotonic_now_ns());
             }
             std::hint::spin_loop();
         }
     }
 
-    pub fn notify_fd(&self) -> RawFd {
-        self.notify_fd
+    /// Single producer, so plain load/store suffices for the maxima; a concurrent
+    /// `take_occupancy` reset can lose at most one sample.
+    fn record_push(&self, occupancy: usize, full_since_ns: Option<u64>) {
+        let stats = &*self.stats;
+        if occupancy > stats.max_occupancy.load(Ordering::Relaxed) {
+            stats.max_occupancy.store(occupancy, Ordering::Relaxed);
+        }
+        if occupancy > stats.interval_max_occupancy.load(Ordering::Relaxed) {
+            stats
+                .interval_max_occupancy
+                .store(occupancy, Ordering::Relaxed);
+        }
+        if let Some(start_ns) = full_since_ns {
+            stats.full_waits.fetch_add(1, Ordering::Relaxed);
+            stats.full_ns.fetch_add(
+                monotonic_now_ns().saturating_sub(start_ns),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Current occupancy counters, starting a new interval for `interval_max_occupancy`.
+    pub fn take_occupancy(&self) -> QueueOccupancy {
+        let stats = &*self.stats;
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        QueueOccupancy {
+            capacity: self.capacity,
+            len: tail.wrapping_sub(head),
+            max_occupancy: stats.max_occupancy.load(Ordering::Relaxed),
+            interval_max_occupancy: stats.interval_max_occupancy.swap(0, Ordering::Relaxed),
+            full_waits: stats.full_waits.load(Ordering::Relaxed),
+            full_ns: stats.full_ns.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Tell the consumer that no more responses will arrive, because the inference thread died.
+    /// Responses already queued can still be popped.
+    pub fn poison(&self) {
+        self.poisoned.store(true, Ordering::Release);
+        self.notifier.notify();
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Register a consumer draining the queue until the returned guard drops. Once the last
+    /// guard drops, bounded pushes onto a full queue fail at once instead of waiting out their
+    /// timeout, until another consumer attaches.
+    pub fn attach_consumer(self: &Arc<Self>) -> ConsumerGuard {
+        self.consumers.fetch_add(1, Ordering::AcqRel);
+        self.detached.store(false, Ordering::Release);
+        ConsumerGuard {
+            queue: Arc::clone(self),
+        }
+    }
+
+    /// The fd to poll for wakeups, if the queue's notifier has one.
+    pub fn notify_fd(&self) -> Option<RawFd> {
+        self.notifier.poll_fd()
+    }
+
+    /// Have wakeups arrive as completions carrying `user_data` on the io_uring `ring_fd` until
+    /// the returned guard drops, if the queue's notifier posts to rings. The consumer must drain
+    /// the queue once after this, for responses pushed before it.
+    #[cfg(target_os = "linux")]
+    pub fn post_wakeups_to(
+        self: &Arc<Self>,
+        ring_fd: RawFd,
+        user_data: u64,
+    ) -> Option<RingWakeups> {
+        self.notifier
+            .post_to_ring(Some((ring_fd, user_data)))
+            .then(|| RingWakeups {
+                queue: Arc::clone(self),
+            })
     }
 
     pub fn pop(&self) -> Option<ResponseReady> {
+        self.pop_with(|entry| *entry)
+    }
+
+    /// Pass the oldest response to `read` in place, then release its slot.
+    pub fn pop_with<R>(&self, read: impl FnOnce(&ResponseReady) -> R) -> Option<R> {
         let head = self.head.load(Ordering::Relaxed);
         let tail = self.tail.load(Ordering::Acquire);
         if head == tail {
             return None;
         }
         let idx = head % self.capacity;
-        let entry = unsafe { (*self.slots[idx].get()).assume_init_read() };
+        let result = read(unsafe { &*self.slots[idx].get() });
         self.head.store(head.wrapping_add(1), Ordering::Release);
-        Some(entry)
+        Some(result)
     }
 }
 
-impl Drop for ResponseQueue {
+/// A consumer attached to a [`ResponseQueue`]; detaches on drop, including by unwinding.
+pub struct ConsumerGuard {
+    queue: Arc<ResponseQueue>,
+}
+
+impl Drop for ConsumerGuard {
     fn drop(&mut self) {
-        while self.pop().is_some() {}
-        if self.notify_fd >= 0 {
-            unsafe {
-                libc::close(self.notify_fd);
+        if self.queue.consumers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.queue.detached.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Wakeups posted to a consumer's ring; stops posting on drop, before the ring closes.
+#[cfg(target_os = "linux")]
+pub struct RingWakeups {
+    queue: Arc<ResponseQueue>,
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for RingWakeups {
+    fn drop(&mut self) {
+        self.queue.notifier.post_to_ring(None);
+    }
+}
+
+/// Per-shard response queues, indexed by `ConnectionRef::shard_id`.
+///
+/// Slots are registered once and never removed, so IO threads can be added at runtime without
+/// coordinating with the inference consumer. A slot whose IO thread was removed keeps its queue
+/// for the next thread started on that shard; stale responses are dropped by generation.
+pub struct ResponseRouter {
+    shards: Box<[OnceLock<Arc<ResponseQueue>>]>,
+    poisoned: AtomicBool,
+    /// Whether queues wake their IO thread with `MSG_RING` rather than an eventfd.
+    msg_ring_wakeups: bool,
+}
+
+impl ResponseRouter {
+    pub fn new(max_shards: usize) -> Self {
+        Self {
+            shards: (0..max_shards).map(|_| OnceLock::new()).collect(),
+            poisoned: AtomicBool::new(false),
+            msg_ring_wakeups: false,
+        }
+    }
+
+    /// Create queues whose wakeups are posted straight to their io_uring IO thread's ring, whose
+    /// eventfd then fires only for a wakeup that could not be posted; see [`MsgRingNotifier`].
+    /// Only for kernels that pass
+    /// [`msg_ring_supported`](crate::notify::msg_ring_supported) and IO threads on io_uring.
+    #[cfg(target_os = "linux")]
+    pub fn with_msg_ring_wakeups(mut self) -> Self {
+        self.msg_ring_wakeups = true;
+        self
+    }
+
+    /// Return the queue for `shard_id`, creating it with `capacity` slots on first use.
+    /// Queues created after [`Self::poison_all`] start poisoned.
+    pub fn get_or_register(&self, shard_id: u8, capacity: usize) -> Arc<ResponseQueue> {
+        let queue = Arc::clone(
+            self.shards[shard_id as usize].get_or_init(|| Arc::new(self.new_queue(capacity))),
+        );
+        if self.poisoned.load(Ordering::Acquire) {
+            queue.poison();
+        }
+        queue
+    }
+
+    /// Poison every queue, current and future, so each consumer learns the inference thread
+    /// is gone instead of waiting for responses forever.
+    pub fn poison_all(&self) {
+        self.poisoned.store(true, Ordering::Release);
+        for (_, queue) in self.registered() {
+            queue.poison();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn new_queue(&self, capacity: usize) -> ResponseQueue {
+        if self.msg_ring_wakeups {
+            match MsgRingNotifier::new() {
+                Ok(notifier) => return ResponseQueue::with_notifier(capacity, Arc::new(notifier)),
+                Err(e) => eprintln!(
+                    "disrust: cannot create a MSG_RING sender ({e}), waking IO threads through an eventfd"
+                ),
             }
         }
+        ResponseQueue::new(capacity)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new_queue(&self, capacity: usize) -> ResponseQueue {
+        ResponseQueue::new(capacity)
+    }
+
+    pub fn get(&self, shard_id: u8) -> Option<&Arc<ResponseQueue>> {
+        self.shards.get(shard_id as usize)?.get()
+    }
+
+    /// Every registered queue with its shard id, in shard order.
+    pub fn registered(&self) -> impl Iterator<Item = (u8, &Arc<ResponseQueue>)> {
+        self.shards
+            .iter()
+            .enumerate()
+            .filter_map(|(shard_id, slot)| Some((shard_id as u8, slot.get()?)))
+    }
+}
+
+impl From<Vec<Arc<ResponseQueue>>> for ResponseRouter {
+    fn from(queues: Vec<Arc<ResponseQueue>>) -> Self {
+        Self {
+            shards: queues.into_iter().map(OnceLock::from).collect(),
+            poisoned: AtomicBool::new(false),
+            msg_ring_wakeups: false,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ResponseQueue, ResponseReady};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{PushError, ResponseQueue, ResponseReady, ResponseRouter};
     use crate::connection_id::ConnectionRef;
+    use crate::notify::CondvarNotifier;
 
     #[test]
     fn preserves_fifo_order() {
         let queue = ResponseQueue::new(4);
         let conn0 = ConnectionRef::new(0, 1, 11);
         let conn1 = ConnectionRef::new(1, 2, 22);
-        queue.push(ResponseReady::encode(conn0, 7, 100, &[1.0f32, 2.0, 3.0]));
-        queue.push(ResponseReady::encode(conn1, 8, 101, &[4.0f32, 5.0]));
+        queue.push(ResponseReady::new(conn0, 7, 100, &[1.0f32, 2.0, 3.0]));
+        queue.push(ResponseReady::new(conn1, 8, 101, &[4.0f32, 5.0]));
 
         let first = queue.pop().expect("first response");
         assert_eq!(first.conn, conn0);
         assert_eq!(first.request_seq, 7);
-        assert_eq!(first.data[0], 3); // num_vectors header
+        assert_eq!(first.results(), [1.0, 2.0, 3.0]);
 
         let second = queue.pop().expect("second response");
         assert_eq!(second.conn, conn1);
         assert_eq!(second.request_seq, 8);
-        assert_eq!(second.data[0], 2); // num_vectors header
+        assert_eq!(second.results(), [4.0, 5.0]);
 
         assert!(queue.pop().is_none());
     }
+
+    #[test]
+    fn fills_and_reads_slots_in_place() {
+        let queue = ResponseQueue::new(1);
+        let conn = ConnectionRef::new(0, 1, 11);
+        queue.push(ResponseReady::new(conn, 0, 0, &[1.0f32, 2.0, 3.0]));
+        assert!(queue.pop().is_some());
+
+        // The reused slot still holds the longer response; only its `num_results` are read.
+        queue.push_with(|slot| slot.fill(conn, 1, 5, &[4.0f32]));
+        let (seq, results) = queue
+            .pop_with(|entry| (entry.request_seq, entry.results().to_vec()))
+            .expect("response");
+        assert_eq!(seq, 1);
+        assert_eq!(results, [4.0]);
+        assert!(queue.pop_with(|_| ()).is_none());
+    }
+
+    #[test]
+    fn wakes_consumer_through_its_notifier_when_empty() {
+        let notifier = Arc::new(CondvarNotifier::new());
+        let queue = Arc::new(ResponseQueue::with_notifier(4, notifier.clone()));
+        assert_eq!(queue.notify_fd(), None);
+        let conn = ConnectionRef::new(0, 1, 11);
+
+        let producer = {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || queue.push(ResponseReady::new(conn, 0, 0, &[1.0f32])))
+        };
+        assert!(notifier.wait_timeout(Duration::from_secs(5)));
+        producer.join().unwrap();
+
+        // A push onto a non-empty queue does not wake the consumer again.
+        queue.push(ResponseReady::new(conn, 1, 0, &[1.0f32]));
+        assert!(!notifier.wait_timeout(Duration::ZERO));
+        assert_eq!(queue.pop().map(|entry| entry.request_seq), Some(0));
+        assert_eq!(queue.pop().map(|entry| entry.request_seq), Some(1));
+    }
+
+    #[test]
+    fn bounded_pushes_give_up_on_a_full_queue() {
+        let queue = Arc::new(ResponseQueue::new(1));
+        let conn = ConnectionRef::new(0, 1, 11);
+        let consumer = queue.attach_consumer();
+        let push = |seq| {
+            queue.try_push_with(Duration::from_millis(20), |slot| {
+                slot.fill(conn, seq, 0, &[1.0f32])
+            })
+        };
+        assert_eq!(push(0), Ok(()));
+        assert_eq!(push(1), Err(PushError::TimedOut));
+
+        drop(consumer);
+        let start = std::time::Instant::now();
+        assert_eq!(push(1), Err(PushError::Detached));
+        assert!(
+            start.elapsed() < Duration::from_millis(20),
+            "no wait once detached"
+        );
+        assert_eq!(queue.pop().map(|entry| entry.request_seq), Some(0));
+        assert_eq!(push(1), Ok(()), "space is still taken while detached");
+    }
+
+    #[test]
+    fn router_registers_shards_once() {
+        let router = ResponseRouter::new(4);
+        assert!(router.get(2).is_none());
+        let queue = router.get_or_register(2, 4);
+        assert!(std::sync::Arc::ptr_eq(
+            &queue,
+            &router.get_or_register(2, 8)
+        ));
+        assert!(std::sync::Arc::ptr_eq(&queue, router.get(2).unwrap()));
+        assert!(router.get(1).is_none());
+        assert!(router.get(9).is_none());
+        assert_eq!(
+            router
+                .registered()
+                .map(|(shard_id, _)| shard_id)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn poisoning_wakes_consumers_and_covers_later_shards() {
+        let notifier = Arc::new(CondvarNotifier::new());
+        let queue = Arc::new(ResponseQueue::with_notifier(4, notifier.clone()));
+        let conn = ConnectionRef::new(0, 1, 11);
+        queue.push(ResponseReady::new(conn, 0, 0, &[1.0f32]));
+        assert!(notifier.wait_timeout(Duration::ZERO));
+
+        let router = ResponseRouter::from(vec![Arc::clone(&queue)]);
+        router.poison_all();
+        assert!(queue.is_poisoned());
+        assert!(
+            notifier.wait_timeout(Duration::ZERO),
+            "poison wakes the consumer"
+        );
+        assert_eq!(queue.pop().map(|entry| entry.request_seq), Some(0));
+
+        let router = ResponseRouter::new(2);
+        router.poison_all();
+        assert!(router.get_or_register(1, 4).is_poisoned());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn tracks_lifetime_and_interval_high_water_marks() {
+        let queue = ResponseQueue::new(4);
+        let conn = ConnectionRef::new(0, 1, 11);
+        for seq in 0..3 {
+            queue.push(ResponseReady::new(conn, seq, 0, &[1.0f32]));
+        }
+        while queue.pop().is_some() {}
+        queue.push(ResponseReady::new(conn, 3, 0, &[1.0f32]));
+
+        let first = queue.take_occupancy();
+        assert_eq!(first.capacity, 4);
+        assert_eq!(first.len, 1);
+        assert_eq!(first.max_occupancy, 3);
+        assert_eq!(first.interval_max_occupancy, 3);
+        assert_eq!(first.full_waits, 0);
+
+        let second = queue.take_occupancy();
+        assert_eq!(second.max_occupancy, 3);
+        assert_eq!(second.interval_max_occupancy, 0);
+    }
 }
@@ -0,0 +1,218 @@
+//! Sampled request logging for debugging model scores in production.
+//!
+//! With `--sample-sink`, the inference thread picks a `--sample-rate` fraction of the requests it
+//! answers and hands their features and scores to a writer thread, which appends one line per
+//! request to the sink: a file, or a Unix socket given as `unix:<path>`. Vectors are separated
+//! by `;`, and `calibrated` appears only while a calibration is applied:
+//!
+//! ```text
+//! conn=0:12:1 seq=40 features=0.5,1,2;3,4,5 scores=0.71,0.12 calibrated=0.66,0.2
+//! ```
+//!
+//! Samples reach the writer through a bounded queue. When the writer falls behind, samples are
+//! dropped and counted rather than slowing the inference thread. Requests scored inline on an IO
+//! thread are not sampled.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+use crate::clock;
+use crate::connection_id::ConnectionRef;
+use crate::metrics;
+
+/// One sampled request, as the inference thread answered it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub conn: ConnectionRef,
+    pub request_seq: u64,
+    pub features: Vec<f32>,
+    pub scores: Vec<f32>,
+    pub calibrated: Option<Vec<f32>>,
+}
+
+impl Sample {
+    /// Append this sample's line, without the newline, to `line`.
+    pub fn format(&self, line: &mut String) {
+        let join = |line: &mut String, values: &[f32], separator| {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    line.push(separator);
+                }
+                let _ = write!(line, "{value}");
+            }
+        };
+        let _ = write!(
+            line,
+            "conn={}:{}:{} seq={} features=",
+            self.conn.shard_id(),
+            self.conn.conn_id,
+            self.conn.generation(),
+            self.request_seq
+        );
+        let feature_dim = self.features.len() / self.scores.len().max(1);
+        for (i, vector) in self.features.chunks(feature_dim.max(1)).enumerate() {
+            if i > 0 {
+                line.push(';');
+            }
+            join(line, vector, ',');
+        }
+        line.push_str(" scores=");
+        join(line, &self.scores, ',');
+        if let Some(calibrated) = &self.calibrated {
+            line.push_str(" calibrated=");
+            join(line, calibrated, ',');
+        }
+    }
+}
+
+/// Parse a sampling rate, a fraction of requests in `0..=1`.
+pub fn parse_sample_rate(text: &str) -> Result<f64, String> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| format!("expected a fraction in 0..=1, got '{text}'"))
+}
+
+/// Open the sink named by `spec`: `unix:<path>` connects to a Unix socket, anything else is a
+/// file path appended to.
+pub fn open_sink(spec: &str) -> io::Result<Box<dyn Write + Send>> {
+    match spec.strip_prefix("unix:") {
+        Some(path) => Ok(Box::new(BufWriter::new(UnixStream::connect(path)?))),
+        None => {
+            let file = OpenOptions::new().create(true).append(true).open(spec)?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+    }
+}
+
+/// Creates a sampler and the receiving end of its queue, which holds up to `capacity` samples.
+pub fn sample_channel(rate: f64, capacity: usize) -> (RequestSampler, Receiver<Sample>) {
+    let (tx, rx) = mpsc::sync_channel(capacity);
+    let seed = clock::monotonic_now_ns() | 1;
+    let mut sampler = RequestSampler {
+        threshold: 0,
+        state: seed,
+        tx,
+    };
+    sampler.set_rate(rate);
+    (sampler, rx)
+}
+
+/// Picks requests to sample on the inference thread and queues them for the writer.
+pub struct RequestSampler {
+    /// Sample when the next random `u64` is below this; `u64::MAX` samples everything.
+    threshold: u64,
+    /// xorshift64 state, never zero.
+    state: u64,
+    tx: SyncSender<Sample>,
+}
+
+impl RequestSampler {
+    pub fn set_rate(&mut self, rate: f64) {
+        debug_assert!((0.0..=1.0).contains(&rate));
+        self.threshold = if rate >= 1.0 {
+            u64::MAX
+        } else {
+            (rate * u64::MAX as f64) as u64
+        };
+    }
+
+    /// Whether to sample the next request.
+    pub fn pick(&mut self) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.threshold == u64::MAX || self.state < self.threshold
+    }
+
+    /// Queue `sample` for the writer, or drop it if the writer is behind or gone.
+    pub fn offer(&mut self, sample: Sample) {
+        match self.tx.try_send(sample) {
+            Ok(()) => metrics::inc_samples_taken(),
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                metrics::inc_samples_dropped()
+            }
+        }
+    }
+}
+
+/// Write every queued sample to `sink`, one line each, until the sampler is dropped. Flushes
+/// whenever the queue runs empty.
+pub fn write_samples(samples: Receiver<Sample>, mut sink: impl Write) -> io::Result<()> {
+    let mut line = String::new();
+    while let Ok(mut sample) = samples.recv() {
+        loop {
+            line.clear();
+            sample.format(&mut line);
+            line.push('\n');
+            sink.write_all(line.as_bytes())?;
+            match samples.try_recv() {
+                Ok(next) => sample = next,
+                Err(_) => break,
+            }
+        }
+        sink.flush()?;
+    }
+    sink.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sample, parse_sample_rate, sample_channel, write_samples};
+    use crate::connection_id::ConnectionRef;
+
+    fn sample(request_seq: u64) -> Sample {
+        Sample {
+            conn: ConnectionRef::new(0, 12, 1),
+            request_seq,
+            features: vec![0.5, 1.0, 2.0, 3.0, 4.0, 5.0],
+            scores: vec![0.75, 0.125],
+            calibrated: None,
+        }
+    }
+
+    #[test]
+    fn samples_are_written_one_line_each() {
+        let mut line = String::new();
+        let calibrated = Sample {
+            calibrated: Some(vec![0.5, 0.25]),
+            ..sample(40)
+        };
+        calibrated.format(&mut line);
+        assert_eq!(
+            line,
+            "conn=0:12:1 seq=40 features=0.5,1,2;3,4,5 scores=0.75,0.125 calibrated=0.5,0.25"
+        );
+
+        let (mut sampler, rx) = sample_channel(1.0, 1);
+        assert!(sampler.pick());
+        sampler.offer(sample(1));
+        sampler.offer(sample(2));
+        drop(sampler);
+        let mut out = Vec::new();
+        write_samples(rx, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 1, "the full queue dropped the second");
+        assert!(out.starts_with("conn=0:12:1 seq=1 "));
+    }
+
+    #[test]
+    fn rate_sets_the_sampled_fraction() {
+        let (mut sampler, _rx) = sample_channel(0.0, 1);
+        assert!((0..1000).all(|_| !sampler.pick()));
+        sampler.set_rate(0.25);
+        let picked = (0..100_000).filter(|_| sampler.pick()).count();
+        assert!((23_000..27_000).contains(&picked), "picked {picked}");
+
+        assert_eq!(parse_sample_rate("0.01"), Ok(0.01));
+        assert!(parse_sample_rate("1.5").is_err());
+        assert!(parse_sample_rate("-0.1").is_err());
+        assert!(parse_sample_rate("often").is_err());
+    }
+}
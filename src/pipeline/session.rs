@@ -10,19 +10,19 @@ use std::sync::{
 use ort::{
     AsPointer, api,
     memory::{AllocationDevice, AllocatorType, MemoryInfo, MemoryType},
-    session::Session,
+    session::{Session, builder::GraphOptimizationLevel},
     sys,
 };
 
 #[cfg(feature = "cuda")]
-use ort::execution_providers::CUDAExecutionProvider;
+use ort::execution_providers::{CUDAExecutionProvider, cuda::ConvAlgorithmSearch};
 
 use crate::buffer_pool::{BufferPool, PoolSlice};
 #[cfg(feature = "cuda")]
-use crate::config::GPU_BUFFER_POOL_BYTES;
-use crate::config::GPU_BUFFER_POOL_CAPACITY;
+use crate::config::gpu_buffer_pool_bytes;
+use crate::config::gpu_buffer_pool_capacity;
 use crate::config::{MAX_BATCH_VECTORS, ORT_INTRA_THREADS};
-use crate::constants::FEATURE_DIM as FDIM;
+use crate::constants::FEATURE_DIM;
 
 #[cfg(feature = "cuda")]
 use crate::cuda::memory::{alloc_pinned, free_pinned};
@@ -52,23 +52,31 @@ pub trait InferenceBackend: Send {
     {
     }
 
-    /// Allocate and leak the server's input feature pool. Called once at
-    /// startup before sessions are constructed. The backend decides the memory
-    /// type: pinned host memory for zero-copy GPU DMA, or regular heap.
+    /// Allocate and leak the server's input feature pool, sized for vectors of
+    /// `feature_dim` features. Called once at startup before sessions are
+    /// constructed. The backend decides the memory type: pinned host memory
+    /// for zero-copy GPU DMA, or regular heap.
     ///
     /// The returned reference is `'static` because the pool lives for the
     /// process lifetime.
-    fn make_pool() -> &'static BufferPool
+    fn make_pool(feature_dim: usize) -> &'static BufferPool
     where
         Self: Sized;
 
+    /// Features per vector the model takes.
+    fn feature_dim(&self) -> usize {
+        FEATURE_DIM
+    }
+
     fn try_acquire(&mut self) -> bool;
     fn is_available(&self) -> bool;
 
     /// Submit a batch for inference. `input_host_ptr` points to a contiguous
-    /// row-major `[num_vectors × FEATURE_DIM]` f32 array in the pool returned
+    /// row-major `[num_vectors × feature_dim]` f32 array in the pool returned
     /// by `make_pool`. Returns an `InFlightBatch` whose `completion` will be
-    /// signaled when `output_ptr` is safe to read.
+    /// signaled when `output_ptr` is safe to read. Backends that evaluate
+    /// feature-major transpose the batch into their own
+    /// [`FeatureColumns`](crate::pipeline::columns::FeatureColumns).
     fn submit_batch(
         &mut self,
         input_host_ptr: *const f32,
@@ -76,6 +84,18 @@ pub trait InferenceBackend: Send {
     ) -> InFlightBatch<Self::Resources>;
 }
 
+/// How a backend may trade reproducibility for speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Numerics {
+    /// Fastest kernels; results for the same input may differ in the last bits between runs.
+    #[default]
+    Fast,
+    /// Bit-identical results for the same input across runs: deterministic kernels, no
+    /// reassociating graph rewrites or reduced-precision math. The pipeline pairs this with one
+    /// request per batch, so results never depend on which requests shared a batch.
+    Deterministic,
+}
+
 // ---------------------------------------------------------------------------
 // BatchCompletion
 // ---------------------------------------------------------------------------
@@ -199,6 +219,18 @@ impl<R: Send> InFlightBatch<R> {
             _resources: resources,
         }
     }
+
+    /// The same batch holding `f(resources)`, for backends that wrap another backend's.
+    pub(crate) fn map_resources<S: Send>(self, f: impl FnOnce(R) -> S) -> InFlightBatch<S> {
+        InFlightBatch {
+            completion: self.completion,
+            output_ptr: self.output_ptr,
+            output_len: self.output_len,
+            session_available: self.session_available,
+            input_slices: self.input_slices,
+            _resources: f(self._resources),
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -303,6 +335,7 @@ pub struct OrtSession {
     output_value_ptrs: [*mut sys::OrtValue; 1],
     output_ptr: *mut f32,
     output_capacity: usize,
+    feature_dim: usize,
     available: Arc<AtomicBool>,
 }
 
@@ -317,6 +350,10 @@ impl OrtSession {
 
     /// Construct a session with a caller-chosen maximum output vector capacity.
     pub fn with_output_capacity(model_bytes: &[u8], output_capacity: usize) -> Self {
+        Self::with_numerics(model_bytes, output_capacity, Numerics::Fast)
+    }
+
+    pub fn with_numerics(model_bytes: &[u8], output_capacity: usize, numerics: Numerics) -> Self {
         assert!(output_capacity > 0, "output_capacity must be > 0");
         #[allow(unused_mut)]
         let mut builder = Session::builder()
@@ -330,9 +367,34 @@ impl OrtSession {
                 std::process::abort()
             });
 
+        if numerics == Numerics::Deterministic {
+            // Level 1 keeps only rewrites that do not change results, such as constant folding
+            // and redundant node elimination; later levels fuse and reassociate arithmetic.
+            builder = builder
+                .with_deterministic_compute(true)
+                .unwrap_or_else(|e| {
+                    eprintln!("with_deterministic_compute failed: {e}");
+                    std::process::abort()
+                })
+                .with_optimization_level(GraphOptimizationLevel::Level1)
+                .unwrap_or_else(|e| {
+                    eprintln!("with_optimization_level failed: {e}");
+                    std::process::abort()
+                });
+        }
+
+        #[cfg(feature = "cuda")]
+        let cuda = match numerics {
+            Numerics::Fast => CUDAExecutionProvider::default(),
+            // Benchmarked cuDNN algorithm choice and TF32 matmuls both vary results.
+            Numerics::Deterministic => CUDAExecutionProvider::default()
+                .with_tf32(false)
+                .with_conv_algorithm_search(ConvAlgorithmSearch::Default),
+        };
+
         #[cfg(feature = "cuda")]
         let mut builder = builder
-            .with_execution_providers([CUDAExecutionProvider::default().build()])
+            .with_execution_providers([cuda.build()])
             .unwrap_or_else(|e| {
                 eprintln!("with_execution_providers failed: {e}");
                 std::process::abort()
@@ -438,6 +500,7 @@ impl OrtSession {
             output_value_ptrs: [std::ptr::null_mut()],
             output_ptr,
             output_capacity,
+            feature_dim: FEATURE_DIM,
             available: Arc::new(AtomicBool::new(true)),
         }
     }
@@ -471,12 +534,12 @@ impl OrtSession {
             "submit_batch requires an acquired session"
         );
 
-        let input_shape = [num_vectors as i64, FDIM as i64];
+        let input_shape = [num_vectors as i64, self.feature_dim as i64];
         let output_shape = [num_vectors as i64];
         let input_value = create_tensor_from_external(
             &self.input_memory_info,
             input_host_ptr as *mut c_void,
-            num_vectors * FDIM,
+            num_vectors * self.feature_dim,
             &input_shape,
         );
         let output_value = create_tensor_from_external(
@@ -567,9 +630,13 @@ unsafe impl Send for OrtBackend {}
 
 impl OrtBackend {
     pub fn new(model_bytes: &[u8], pool_size: usize) -> Self {
+        Self::with_numerics(model_bytes, pool_size, Numerics::Fast)
+    }
+
+    pub fn with_numerics(model_bytes: &[u8], pool_size: usize, numerics: Numerics) -> Self {
         assert!(pool_size > 0, "pool_size must be > 0");
         let sessions = (0..pool_size)
-            .map(|_| OrtSession::new(model_bytes))
+            .map(|_| OrtSession::with_numerics(model_bytes, MAX_BATCH_VECTORS, numerics))
             .collect();
         Self {
             sessions,
@@ -593,6 +660,14 @@ impl OrtBackend {
         }
     }
 
+    /// Feed the model vectors of `feature_dim` features instead of `FEATURE_DIM`.
+    pub fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+        for session in &mut self.sessions {
+            session.feature_dim = feature_dim;
+        }
+        self
+    }
+
     /// Name of the model's output tensor. Delegates to the first slot.
     pub fn output_name(&self) -> &str {
         self.sessions[0].output_name()
@@ -613,30 +688,35 @@ impl InferenceBackend for OrtBackend {
         }
     }
 
-    fn make_pool() -> &'static BufferPool {
+    fn make_pool(feature_dim: usize) -> &'static BufferPool {
+        let capacity = gpu_buffer_pool_capacity(feature_dim);
         let ptr: *mut f32 = {
             #[cfg(feature = "cuda")]
             {
                 unsafe {
                     let mut raw: *mut std::ffi::c_void = std::ptr::null_mut();
-                    let status =
-                        cudarc::driver::sys::cuMemAllocHost_v2(&mut raw, GPU_BUFFER_POOL_BYTES);
+                    let bytes = gpu_buffer_pool_bytes(feature_dim);
+                    let status = cudarc::driver::sys::cuMemAllocHost_v2(&mut raw, bytes);
                     if status != cudarc::driver::sys::CUresult::CUDA_SUCCESS {
                         eprintln!("OrtBackend::make_pool: cuMemAllocHost_v2 failed: {status:?}");
                         std::process::abort();
                     }
-                    std::ptr::write_bytes(raw as *mut u8, 0u8, GPU_BUFFER_POOL_BYTES);
+                    std::ptr::write_bytes(raw as *mut u8, 0u8, bytes);
                     raw as *mut f32
                 }
             }
 
             #[cfg(not(feature = "cuda"))]
             {
-                Box::leak(vec![0f32; GPU_BUFFER_POOL_CAPACITY].into_boxed_slice()).as_mut_ptr()
+                Box::leak(vec![0f32; capacity].into_boxed_slice()).as_mut_ptr()
             }
         };
 
-        Box::leak(unsafe { BufferPool::from_raw_ptr(ptr, GPU_BUFFER_POOL_CAPACITY) })
+        Box::leak(unsafe { BufferPool::from_raw_ptr(ptr, capacity) })
+    }
+
+    fn feature_dim(&self) -> usize {
+        self.sessions[0].feature_dim
     }
 
     fn is_available(&self) -> bool {
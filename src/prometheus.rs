@@ -0,0 +1,467 @@
+//! Prometheus exporter for the `metrics` counters and timers.
+//!
+//! `serve --prometheus-port` answers `GET /metrics` with every cumulative counter in
+//! [`MetricsSnapshot`], read at scrape time, and the latency timers as histograms. The timers
+//! are reset by each metrics report, so the exporter folds every reported interval into its own
+//! cumulative histograms: their counts lag the counters by up to one `--metrics-interval-secs`.
+//!
+//! The listener is polled by the control-plane thread, like the admin socket, so a scrape never
+//! runs on a data-plane thread.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+
+use crate::metrics::{self, MetricsSnapshot};
+use crate::timer::TimerSnapshot;
+
+/// Longest a scraper may take to send its request.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest request head read before answering.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Histogram bucket upper bounds, in nanoseconds.
+const BUCKET_BOUNDS_NS: [u64; 19] = [
+    1_000,
+    2_500,
+    5_000,
+    10_000,
+    25_000,
+    50_000,
+    100_000,
+    250_000,
+    500_000,
+    1_000_000,
+    2_500_000,
+    5_000_000,
+    10_000_000,
+    25_000_000,
+    50_000_000,
+    100_000_000,
+    250_000_000,
+    500_000_000,
+    1_000_000_000,
+];
+
+/// Values of one metric, each with its label set (empty for none).
+type LabeledValues<'a> = &'a [(&'a str, u64)];
+
+/// Cumulative latency histograms by metric name, in report order.
+static LATENCY: Mutex<Vec<(&'static str, Histogram<u64>)>> = Mutex::new(Vec::new());
+
+/// Fold one report interval's timer snapshots into the exported histograms. `timers` names each
+/// timer as its Prometheus metric, without the `disrust_` prefix and `_seconds` suffix.
+pub fn record_interval(timers: &[(&'static str, Option<&TimerSnapshot>)]) {
+    let mut latency = LATENCY.lock().unwrap();
+    for &(name, snapshot) in timers {
+        let index = match latency.iter().position(|(known, _)| *known == name) {
+            Some(index) => index,
+            None => {
+                let hist = Histogram::new_with_bounds(1, 60_000_000_000, 3)
+                    .expect("failed to create hdrhistogram");
+                latency.push((name, hist));
+                latency.len() - 1
+            }
+        };
+        if let Some(snapshot) = snapshot {
+            snapshot.merge_into(&mut latency[index].1);
+        }
+    }
+}
+
+/// Render every counter and histogram in the Prometheus text format.
+pub fn render(snap: &MetricsSnapshot, out: &mut String) {
+    let counters: [(&str, &str, LabeledValues); 26] = [
+        (
+            "requests_published",
+            "Requests published into the request ring.",
+            &[("", snap.requests_published)],
+        ),
+        (
+            "responses_written",
+            "Responses queued for IO threads by the inference thread.",
+            &[("", snap.responses_written)],
+        ),
+        (
+            "responses_dropped",
+            "Responses dropped because their IO thread's response queue stayed full.",
+            &[("", snap.responses_dropped)],
+        ),
+        (
+            "batches",
+            "Backend batches, by stage.",
+            &[
+                ("stage=\"submitted\"", snap.batches_submitted),
+                ("stage=\"completed\"", snap.batches_completed),
+            ],
+        ),
+        (
+            "vectors_submitted",
+            "Vectors submitted to the backend.",
+            &[("", snap.vectors_submitted)],
+        ),
+        (
+            "request_ring_full",
+            "Publishes that found the request ring full.",
+            &[("", snap.req_ring_full)],
+        ),
+        (
+            "overload_rejected",
+            "Requests answered with an overload frame, by reason.",
+            &[
+                ("reason=\"ring_full\"", snap.overload_ring_full),
+                ("reason=\"large_requests\"", snap.overload_large_requests),
+                ("reason=\"timeout\"", snap.overload_timeout),
+                ("reason=\"circuit_open\"", snap.overload_circuit_open),
+            ],
+        ),
+        (
+            "model_reloads",
+            "Model reloads, by whether the new model was swapped in or failed to load.",
+            &[
+                ("result=\"swapped\"", snap.model_reloads),
+                ("result=\"failed\"", snap.model_reload_failed),
+            ],
+        ),
+        (
+            "circuit_opened",
+            "Times the circuit breaker opened on the backend's timeouts.",
+            &[("", snap.circuit_opened)],
+        ),
+        (
+            "large_requests_held",
+            "Parses stopped at a large request waiting for admission to the ring.",
+            &[("", snap.large_requests_held)],
+        ),
+        (
+            "pool_failures",
+            "Buffer pool allocations that failed, by reason.",
+            &[
+                ("reason=\"exhausted\"", snap.pool_exhausted),
+                ("reason=\"too_large\"", snap.pool_too_large),
+            ],
+        ),
+        (
+            "session_waits",
+            "Full batches held back waiting for a backend session.",
+            &[("", snap.session_waits)],
+        ),
+        (
+            "placement",
+            "Inline fast path placement decisions, by outcome.",
+            &[
+                ("outcome=\"inline\"", snap.placement_inline),
+                ("outcome=\"offload_size\"", snap.placement_offload_size),
+                ("outcome=\"offload_busy\"", snap.placement_offload_busy),
+            ],
+        ),
+        (
+            "non_finite_requests",
+            "Requests with NaN or infinite features, by what the policy did.",
+            &[
+                ("action=\"passed\"", snap.non_finite_passed),
+                ("action=\"clamped\"", snap.non_finite_clamped),
+                ("action=\"rejected\"", snap.non_finite_rejected),
+            ],
+        ),
+        (
+            "slow_connections",
+            "Connections whose write backlog paused reads or evicted them.",
+            &[
+                ("action=\"paused\"", snap.write_backlog_paused),
+                ("action=\"evicted\"", snap.slow_consumer_evicted),
+            ],
+        ),
+        (
+            "idle_connections_closed",
+            "Connections closed after --idle-timeout-secs without traffic.",
+            &[("", snap.idle_connections_closed)],
+        ),
+        (
+            "responses_stale",
+            "Scores that reached their IO thread too late and were replaced by a timeout frame.",
+            &[("", snap.responses_stale)],
+        ),
+        (
+            "embedding_misses",
+            "Request ids past the end of the embedding table, expanded as zeros.",
+            &[("", snap.embedding_misses)],
+        ),
+        (
+            "responses_replayed",
+            "Kept frames resent to clients resuming a replay session.",
+            &[("", snap.responses_replayed)],
+        ),
+        (
+            "request_overflow",
+            "Requests parked while the ring was full, and parses stopped by a full overflow queue.",
+            &[
+                ("event=\"parked\"", snap.requests_parked),
+                ("event=\"full\"", snap.request_overflow_full),
+            ],
+        ),
+        (
+            "samples",
+            "Requests sampled to the sample sink, and samples dropped behind a slow writer.",
+            &[
+                ("outcome=\"taken\"", snap.samples_taken),
+                ("outcome=\"dropped\"", snap.samples_dropped),
+            ],
+        ),
+        (
+            "read_bytes",
+            "Bytes read from client sockets.",
+            &[("", snap.read_bytes)],
+        ),
+        (
+            "write_errors",
+            "Socket writes that failed, by kind.",
+            &[
+                ("kind=\"eagain\"", snap.write_eagain),
+                ("kind=\"fatal\"", snap.write_fatal),
+            ],
+        ),
+        (
+            "io_wakes",
+            "IO thread event loop wakes.",
+            &[("", snap.io_wakes)],
+        ),
+        (
+            "io_ring_cqes",
+            "IO thread completions handled, by ring.",
+            &[
+                ("ring=\"data\"", snap.io_data_ring_cqes),
+                ("ring=\"accept\"", snap.io_accept_ring_cqes),
+            ],
+        ),
+        (
+            "io_cqe_faults",
+            "IO thread completions the kernel dropped from a full completion queue, and ones \
+             naming no known operation.",
+            &[
+                ("kind=\"dropped\"", snap.io_cqes_dropped),
+                ("kind=\"unknown_op\"", snap.io_unknown_cqes),
+            ],
+        ),
+    ];
+    for (name, help, samples) in counters {
+        let _ = writeln!(out, "# HELP disrust_{name}_total {help}");
+        let _ = writeln!(out, "# TYPE disrust_{name}_total counter");
+        for (labels, value) in samples {
+            write_sample(out, &format!("disrust_{name}_total"), labels, *value as f64);
+        }
+    }
+
+    let gauges = [
+        (
+            "request_ring_occupancy",
+            "Requests in the request ring.",
+            snap.req_occ,
+        ),
+        (
+            "request_ring_max_occupancy",
+            "Most requests ever in the request ring at once.",
+            snap.req_max_occ,
+        ),
+        (
+            "pool_max_in_use",
+            "Most buffer pool floats ever in use at once.",
+            snap.pool_max_in_use,
+        ),
+        (
+            "connection_slots_quarantined",
+            "Freed connection slots not yet reusable.",
+            snap.slots_quarantined,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP disrust_{name} {help}");
+        let _ = writeln!(out, "# TYPE disrust_{name} gauge");
+        write_sample(out, &format!("disrust_{name}"), "", value as f64);
+    }
+    if let Some(version) = metrics::model_version() {
+        let _ = writeln!(out, "# HELP disrust_model_info The model being served.");
+        let _ = writeln!(out, "# TYPE disrust_model_info gauge");
+        let labels = format!("version=\"{}\"", escape_label(&version));
+        write_sample(out, "disrust_model_info", &labels, 1.0);
+    }
+
+    for (name, hist) in LATENCY.lock().unwrap().iter() {
+        render_histogram(out, name, hist);
+    }
+}
+
+fn render_histogram(out: &mut String, name: &str, hist: &Histogram<u64>) {
+    let metric = format!("disrust_{name}_seconds");
+    let _ = writeln!(out, "# TYPE {metric} histogram");
+    let mut buckets = [0u64; BUCKET_BOUNDS_NS.len()];
+    let mut sum_ns = 0u128;
+    for value in hist.iter_recorded() {
+        let ns = hist.highest_equivalent(value.value_iterated_to());
+        let count = value.count_at_value();
+        sum_ns += u128::from(ns) * u128::from(count);
+        let first = BUCKET_BOUNDS_NS.partition_point(|&bound| bound < ns);
+        for bucket in &mut buckets[first..] {
+            *bucket += count;
+        }
+    }
+    let bucket_metric = format!("{metric}_bucket");
+    for (bound, count) in BUCKET_BOUNDS_NS.iter().zip(buckets) {
+        let labels = format!("le=\"{}\"", *bound as f64 / 1e9);
+        write_sample(out, &bucket_metric, &labels, count as f64);
+    }
+    write_sample(out, &bucket_metric, "le=\"+Inf\"", hist.len() as f64);
+    write_sample(out, &format!("{metric}_sum"), "", sum_ns as f64 / 1e9);
+    write_sample(out, &format!("{metric}_count"), "", hist.len() as f64);
+}
+
+fn write_sample(out: &mut String, metric: &str, labels: &str, value: f64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{metric} {value}");
+    } else {
+        let _ = writeln!(out, "{metric}{{{labels}}} {value}");
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Listener for Prometheus scrapes.
+pub struct Exporter {
+    listener: TcpListener,
+}
+
+impl Exporter {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answer every scrape waiting to be accepted, without blocking for new ones.
+    pub fn serve_pending(&self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = serve_scrape(stream) {
+                        eprintln!("disrust: prometheus scrape failed: {e}");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    eprintln!("disrust: prometheus accept failed: {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Answer one HTTP request, then close the connection.
+fn serve_scrape(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() >= MAX_REQUEST_BYTES {
+            return respond(&mut stream, "431 Request Header Fields Too Large", "");
+        }
+        match stream.read(&mut buf)? {
+            0 => return Ok(()),
+            n => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    let request_line = head.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            let mut body = String::new();
+            render(&metrics::snapshot(), &mut body);
+            respond(&mut stream, "200 OK", &body)
+        }
+        (Some(b"GET"), _) => respond(&mut stream, "404 Not Found", ""),
+        _ => respond(&mut stream, "405 Method Not Allowed", ""),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::{Exporter, record_interval, render};
+    use crate::metrics;
+    use crate::timer::TimerMetric;
+
+    #[test]
+    fn histograms_accumulate_across_intervals() {
+        let timer = TimerMetric::new();
+        let mut recorder = timer.recorder();
+        recorder.record_nanos(3_000);
+        recorder.record_nanos(40_000);
+        drop(recorder);
+        record_interval(&[("test_interval", timer.snapshot_and_reset().as_ref())]);
+        let mut recorder = timer.recorder();
+        recorder.record_nanos(2_000_000);
+        drop(recorder);
+        record_interval(&[("test_interval", timer.snapshot_and_reset().as_ref())]);
+
+        let mut out = String::new();
+        render(&metrics::snapshot(), &mut out);
+        for line in [
+            "# TYPE disrust_requests_published_total counter",
+            "disrust_batches_total{stage=\"completed\"} ",
+            "# TYPE disrust_test_interval_seconds histogram",
+            "disrust_test_interval_seconds_bucket{le=\"0.000001\"} 0",
+            "disrust_test_interval_seconds_bucket{le=\"0.000005\"} 1",
+            "disrust_test_interval_seconds_bucket{le=\"0.00005\"} 2",
+            "disrust_test_interval_seconds_bucket{le=\"0.0025\"} 3",
+            "disrust_test_interval_seconds_bucket{le=\"+Inf\"} 3",
+            "disrust_test_interval_seconds_count 3",
+        ] {
+            assert!(out.contains(line), "missing {line:?} in\n{out}");
+        }
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let exporter = Exporter::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let scrape = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            exporter.serve_pending();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = scrape("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("\r\n\r\n# HELP disrust_requests_published_total "));
+        assert!(scrape("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
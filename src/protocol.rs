@@ -7,55 +7,362 @@
 //! exactly N responses in the same order. Any server-side code path that silently
 //! drops or reorders a response is a protocol violation.
 
-use crate::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use std::any::Any;
 
-/// Wire format sizes — single source of truth for both sides of the protocol.
+use crate::byte_order;
+use crate::constants::{FEATURE_DIM, MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::wire_layout::{self, Scalar};
+
+/// Wire format sizes, derived from the declarative layouts in [`wire_layout`].
 ///
-/// Request:  `[u32 num_vectors LE][f32 × num_vectors × FEATURE_DIM LE]`
+/// Request:  `[u32 num_vectors LE][f32 × num_vectors × feature_dim LE]`, where `feature_dim` is
+/// the server's `--feature-dim` ([`FEATURE_DIM`] by default)
 /// Response: `[u8 num_vectors][f32 × num_vectors LE]`
-pub const REQUEST_HEADER_BYTES: usize = 4; // u32 num_vectors
-pub const RESPONSE_HEADER_BYTES: usize = 1; // u8 num_vectors
-pub const BYTES_PER_F32: usize = 4;
+/// Fixed-point response: `[u8 num_vectors][u16 × num_vectors LE]`, answering a request with
+/// [`FIXED_POINT_REQUEST_FLAG`] set, only when the server runs with `--fixed-point-scale`
+/// Overload: `[u8 0][u8 reason][u16 retry_after_ms LE]`
+/// Parse error: `[u8 0][u8 255][u16 field LE][u32 value LE][u32 offset LE]`
+/// Seq prefix: `[u32 request_seq LE]` before each response or overload frame, only when the
+/// server runs with `--echo-request-seq`
+/// Length prefix: `[u32 frame_len LE]` before the seq prefix and frame, counting both, only when
+/// the server runs with `--length-prefix`
+/// Vector status: `[u8 status × num_vectors]` after each response frame, only when the server
+/// runs with `--vector-status`
+/// Request id prefix: `[u64 request_id LE]` before each request, echoed after the seq prefix of
+/// the frame answering it, only when the server runs with `--request-ids`
+///
+/// A request always carries at least one vector, so a zero vector count in the response header
+/// unambiguously marks an overload frame: the server rejected that request without running it
+/// and suggests waiting `retry_after_ms` before sending more, or, with
+/// [`OverloadReason::Timeout`], gave up on answering it within `--request-timeout-us`. A parse
+/// error frame shares that
+/// marker, with [`PARSE_ERROR_KIND`] in place of the reason. It is the last frame before the
+/// server closes the connection, unless the server runs with `--malformed-requests skip` and
+/// the request's `num_vectors` still frames it, or it rejected the request's features.
+pub const REQUEST_HEADER_BYTES: usize = wire_layout::REQUEST.header_bytes();
+pub const RESPONSE_HEADER_BYTES: usize = wire_layout::RESPONSE.header_bytes();
+pub const BYTES_PER_F32: usize = Scalar::F32Le.width();
+pub const OVERLOAD_FRAME_BYTES: usize = wire_layout::OVERLOAD.header_bytes();
+pub const PARSE_ERROR_FRAME_BYTES: usize = wire_layout::PARSE_ERROR.header_bytes();
+/// Reason byte of a parse error frame; never an [`OverloadReason`].
+pub const PARSE_ERROR_KIND: u8 = 0xFF;
+/// Bytes of the optional `request_seq` prefix on server-to-client frames.
+pub const SEQ_PREFIX_BYTES: usize = wire_layout::SEQ_PREFIX.header_bytes();
+/// Bytes of the optional length prefix on server-to-client frames.
+pub const LENGTH_PREFIX_BYTES: usize = wire_layout::LENGTH_PREFIX.header_bytes();
+/// Bytes of the optional `request_id` prefix, in both directions.
+pub const REQUEST_ID_BYTES: usize = wire_layout::REQUEST_ID.header_bytes();
+/// Bytes of the hello opening every connection with `--replay-window`.
+pub const REPLAY_HELLO_BYTES: usize = wire_layout::REPLAY_HELLO.header_bytes();
+/// Bytes of the server's answer to a replay hello.
+pub const REPLAY_REPLY_BYTES: usize = wire_layout::REPLAY_REPLY.header_bytes();
+
+/// Bit of a request's `num_vectors` marking its features as half-precision.
+pub const HALF_REQUEST_FLAG: u32 = 1 << 31;
+/// Bytes of one half-precision feature.
+pub const BYTES_PER_F16: usize = Scalar::F16Le.width();
+/// Bit of a request's `num_vectors` asking for its scores as a [`FixedPointResponse`]; a
+/// `num_vectors` parse error unless the connection's framing allows it.
+pub const FIXED_POINT_REQUEST_FLAG: u32 = 1 << 30;
+/// Bytes of one fixed-point score.
+pub const BYTES_PER_FIXED_POINT: usize = Scalar::U16Le.width();
+/// Default `--fixed-point-scale`: scores in `[0, 1]` are sent in basis points.
+pub const BASIS_POINTS: f32 = 10_000.0;
+
+/// A request header's `num_vectors` field split into the vector count and whether the features
+/// are half-precision.
+pub const fn split_num_vectors(field: u32) -> (u32, bool) {
+    (field & !HALF_REQUEST_FLAG, field & HALF_REQUEST_FLAG != 0)
+}
+
+/// Bytes of the vector status trailer after a response carrying `num_vectors` results.
+pub const fn vector_status_size(num_vectors: usize) -> usize {
+    wire_layout::VECTOR_STATUS.size(num_vectors)
+}
 
-/// Total byte length of a request carrying `num_vectors` vectors.
+/// Total byte length of a request carrying `num_vectors` vectors of [`FEATURE_DIM`] features.
 pub const fn request_size(num_vectors: usize) -> usize {
-    REQUEST_HEADER_BYTES + num_vectors * FEATURE_DIM * BYTES_PER_F32
+    wire_layout::REQUEST.size(num_vectors)
 }
 
 /// Total byte length of a response carrying `num_vectors` results.
 pub const fn response_size(num_vectors: usize) -> usize {
-    RESPONSE_HEADER_BYTES + num_vectors * BYTES_PER_F32
+    wire_layout::RESPONSE.size(num_vectors)
+}
+
+/// Total byte length of a fixed-point response carrying `num_vectors` results.
+pub const fn fixed_point_response_size(num_vectors: usize) -> usize {
+    wire_layout::FIXED_POINT_RESPONSE.size(num_vectors)
+}
+
+/// Parse a `--feature-dim`: 1..=[`MAX_FEATURE_DIM`] features per vector.
+pub fn parse_feature_dim(text: &str) -> Result<usize, String> {
+    text.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|dim| (1..=MAX_FEATURE_DIM).contains(dim))
+        .ok_or_else(|| format!("feature dim '{text}' is not in 1..={MAX_FEATURE_DIM}"))
+}
+
+/// Parse a `--fixed-point-scale`: a positive, finite factor scores are multiplied by.
+pub fn parse_fixed_point_scale(text: &str) -> Result<f32, String> {
+    text.trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|scale| scale.is_finite() && *scale > 0.0)
+        .ok_or_else(|| format!("fixed-point scale '{text}' is not a positive number"))
+}
+
+/// Parse a `--max-vectors`: 1..=[`MAX_VECTORS_PER_REQUEST`] vectors per request.
+pub fn parse_max_vectors(text: &str) -> Result<usize, String> {
+    text.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=MAX_VECTORS_PER_REQUEST).contains(n))
+        .ok_or_else(|| format!("max vectors '{text}' is not in 1..={MAX_VECTORS_PER_REQUEST}"))
+}
+
+/// How requests are framed on a connection, and the limits they are parsed against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestFraming {
+    /// Each request preceded by a client-chosen `request_id`, with `--request-ids`.
+    pub request_ids: bool,
+    /// Features per vector, 1..=[`MAX_FEATURE_DIM`].
+    pub feature_dim: usize,
+    /// Most vectors a request may carry, 1..=[`MAX_VECTORS_PER_REQUEST`]; larger requests are
+    /// malformed.
+    pub max_vectors: usize,
+    /// Requests may set [`FIXED_POINT_REQUEST_FLAG`], with `--fixed-point-scale`.
+    pub fixed_point_scores: bool,
+}
+
+impl Default for RequestFraming {
+    fn default() -> Self {
+        Self::PLAIN
+    }
+}
+
+impl RequestFraming {
+    /// Requests of [`FEATURE_DIM`]-wide vectors back to back.
+    pub const PLAIN: Self = Self {
+        request_ids: false,
+        feature_dim: FEATURE_DIM,
+        max_vectors: MAX_VECTORS_PER_REQUEST,
+        fixed_point_scores: false,
+    };
+    /// Like [`Self::PLAIN`], each request preceded by its `request_id`.
+    pub const REQUEST_ID: Self = Self::PLAIN.with_request_ids();
+
+    pub const fn with_request_ids(self) -> Self {
+        Self {
+            request_ids: true,
+            ..self
+        }
+    }
+
+    pub const fn with_feature_dim(self, feature_dim: usize) -> Self {
+        Self {
+            feature_dim,
+            ..self
+        }
+    }
+
+    pub const fn with_max_vectors(self, max_vectors: usize) -> Self {
+        Self {
+            max_vectors,
+            ..self
+        }
+    }
+
+    pub const fn with_fixed_point_scores(self) -> Self {
+        Self {
+            fixed_point_scores: true,
+            ..self
+        }
+    }
+
+    /// A request header's `num_vectors` field split into the vector count and whether the
+    /// features are half-precision, with [`FIXED_POINT_REQUEST_FLAG`] cleared when this framing
+    /// allows it. A request setting the flag where it is not allowed counts too many vectors.
+    pub const fn split_num_vectors(self, field: u32) -> (u32, bool) {
+        let field = if self.fixed_point_scores {
+            field & !FIXED_POINT_REQUEST_FLAG
+        } else {
+            field
+        };
+        split_num_vectors(field)
+    }
+
+    /// Bytes before a request's feature data.
+    pub const fn header_bytes(self) -> usize {
+        if self.request_ids {
+            REQUEST_ID_BYTES + REQUEST_HEADER_BYTES
+        } else {
+            REQUEST_HEADER_BYTES
+        }
+    }
+
+    /// Bytes of one vector's features.
+    pub const fn vector_bytes(self) -> usize {
+        self.feature_dim * BYTES_PER_F32
+    }
+
+    /// Bytes of one vector's features, in half precision when `half`.
+    pub const fn vector_bytes_as(self, half: bool) -> usize {
+        if half {
+            self.feature_dim * BYTES_PER_F16
+        } else {
+            self.vector_bytes()
+        }
+    }
+
+    /// Total byte length of a framed request carrying `num_vectors` vectors.
+    pub const fn request_size(self, num_vectors: usize) -> usize {
+        self.header_bytes() + num_vectors * self.vector_bytes()
+    }
+
+    /// Total byte length of a framed request carrying `num_vectors` vectors, of half-precision
+    /// features when `half`.
+    pub const fn request_size_as(self, num_vectors: usize, half: bool) -> usize {
+        self.header_bytes() + num_vectors * self.vector_bytes_as(half)
+    }
 }
 
 /// Result of attempting to parse a request from a byte buffer.
 #[allow(dead_code)]
 pub enum ParseResult {
-    /// Successfully parsed a request. Contains (num_vectors, total bytes consumed).
+    /// Successfully parsed a request: its vector count, total bytes consumed including any
+    /// `request_id` prefix, and the `request_id` when the framing has one.
     Complete {
         num_vectors: u8,
         bytes_consumed: usize,
+        request_id: Option<u64>,
     },
     /// Need more data. Contains minimum bytes still needed.
     Incomplete(usize),
     /// Protocol error (e.g., num_vectors > MAX or == 0).
-    Error(&'static str),
+    Error(ParseError),
+}
+
+/// A request header field the parser can reject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum RequestField {
+    NumVectors = 1,
+    /// A feature value was NaN or infinite; the error's `value` holds its bits.
+    Features = 2,
+}
+
+impl RequestField {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            1 => Some(RequestField::NumVectors),
+            2 => Some(RequestField::Features),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestField::NumVectors => wire_layout::REQUEST_NUM_VECTORS.name,
+            RequestField::Features => wire_layout::REQUEST_FEATURES.name,
+        }
+    }
+}
+
+/// Why a request failed to parse: which field, the value read for it and where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub field: RequestField,
+    pub value: u32,
+    /// Byte offset of `field`, from the start of the buffer the parser was given until
+    /// [`Self::at`] rebases it.
+    pub offset: u64,
+}
+
+impl ParseError {
+    /// The same error with `offset` counted from `base` bytes earlier.
+    pub fn at(self, base: u64) -> Self {
+        Self {
+            offset: base + self.offset,
+            ..self
+        }
+    }
+
+    /// Bytes of the malformed request framed as `framing`, when its header still frames it and
+    /// they number at most `max_bytes`: an out-of-range `num_vectors` still says how many
+    /// vectors follow, so a reader can skip them and resume at the next request.
+    pub fn skip_len(&self, framing: RequestFraming, max_bytes: usize) -> Option<usize> {
+        if self.field != RequestField::NumVectors {
+            return None;
+        }
+        let (num_vectors, half) = framing.split_num_vectors(self.value);
+        (num_vectors as usize)
+            .checked_mul(framing.vector_bytes_as(half))
+            .and_then(|bytes| bytes.checked_add(framing.header_bytes()))
+            .filter(|&len| len <= max_bytes)
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.field.as_str();
+        match self.field {
+            RequestField::NumVectors => write!(
+                f,
+                "{name} = {} at byte {}: expected 1..={MAX_VECTORS_PER_REQUEST} or the port's --max-vectors",
+                self.value, self.offset
+            ),
+            RequestField::Features => write!(
+                f,
+                "{name} = {} at byte {}: expected a finite value",
+                f32::from_bits(self.value),
+                self.offset
+            ),
+        }
+    }
 }
 
-/// Try to parse a request from the buffer. Returns how many bytes were consumed
-/// and the number of vectors. Feature data starts at offset 4 in the buffer.
-pub fn try_parse_request(buf: &[u8]) -> ParseResult {
-    if buf.len() < REQUEST_HEADER_BYTES {
-        return ParseResult::Incomplete(REQUEST_HEADER_BYTES - buf.len());
+impl std::error::Error for ParseError {}
+
+/// Try to parse a request framed as `framing` from the buffer. Returns how many bytes were
+/// consumed and the number of vectors. Feature data starts at `framing.header_bytes()` in the
+/// buffer.
+pub fn try_parse_request(buf: &[u8], framing: RequestFraming) -> ParseResult {
+    debug_assert!(
+        (1..=MAX_FEATURE_DIM).contains(&framing.feature_dim),
+        "feature dim {} was not validated",
+        framing.feature_dim
+    );
+    debug_assert!(
+        (1..=MAX_VECTORS_PER_REQUEST).contains(&framing.max_vectors),
+        "max vectors {} was not validated",
+        framing.max_vectors
+    );
+    let header_bytes = framing.header_bytes();
+    if buf.len() < header_bytes {
+        return ParseResult::Incomplete(header_bytes - buf.len());
     }
+    let (request_id, header) = if framing.request_ids {
+        (Some(decode_request_id(buf)), &buf[REQUEST_ID_BYTES..])
+    } else {
+        (None, buf)
+    };
+    let header_offset = header_bytes - REQUEST_HEADER_BYTES;
 
-    let num_vectors_u32 = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let field = wire_layout::REQUEST_NUM_VECTORS.read_u32(header);
+    let (num_vectors_u32, half) = framing.split_num_vectors(field);
 
-    if num_vectors_u32 == 0 || num_vectors_u32 as usize > MAX_VECTORS_PER_REQUEST {
-        return ParseResult::Error("num_vectors out of range");
+    if num_vectors_u32 == 0 || num_vectors_u32 as usize > framing.max_vectors {
+        return ParseResult::Error(ParseError {
+            field: RequestField::NumVectors,
+            value: field,
+            offset: (header_offset + wire_layout::REQUEST_NUM_VECTORS.offset) as u64,
+        });
     }
 
     let num_vectors = num_vectors_u32 as u8;
-    let total_size = request_size(num_vectors as usize);
+    let total_size = framing.request_size_as(num_vectors as usize, half);
 
     if buf.len() < total_size {
         return ParseResult::Incomplete(total_size - buf.len());
@@ -64,21 +371,686 @@ pub fn try_parse_request(buf: &[u8]) -> ParseResult {
     ParseResult::Complete {
         num_vectors,
         bytes_consumed: total_size,
+        request_id,
+    }
+}
+
+/// How a connection's request stream is split into requests. Whatever the format, the request
+/// pipeline sees each request as its vector count, its length in the stream, its `request_id`
+/// under `--request-ids`, and its features as little-endian `f32`s.
+///
+/// A decoder that needs to carry something across reads keeps it in the [`DecoderState`] it
+/// opens for each connection. The same bytes may be parsed more than once, so parsing only
+/// reads the state; it changes once the pipeline has consumed whole requests.
+pub trait RequestDecoder: Sync {
+    /// Name for logs.
+    fn name(&self) -> &'static str;
+
+    /// State for a newly accepted connection, or `None` if the decoder keeps none.
+    fn open(&self) -> Option<Box<dyn DecoderState>> {
+        None
+    }
+
+    /// Parse the request at the start of `buf`, under the limits and options of `framing`.
+    fn parse(
+        &self,
+        state: Option<&dyn DecoderState>,
+        buf: &[u8],
+        framing: RequestFraming,
+    ) -> ParseResult;
+
+    /// The features of `frame`, a request [`Self::parse`] found complete, as
+    /// `num_vectors * framing.feature_dim` little-endian `f32`s: `frame`'s own bytes when they
+    /// already are, else converted into `scratch`. The connection keeps `scratch` across
+    /// requests, so converting allocates only until it has grown to the largest request.
+    fn features<'a>(
+        &self,
+        state: Option<&dyn DecoderState>,
+        frame: &'a [u8],
+        framing: RequestFraming,
+        scratch: &'a mut Vec<u8>,
+    ) -> &'a [u8];
+
+    /// Offset in `frame`, a complete request, of feature `index`, counted across its vectors,
+    /// so an error about the value points into the stream.
+    fn feature_offset(&self, frame: &[u8], framing: RequestFraming, index: usize) -> usize;
+
+    /// Bytes of the malformed request `error` reports when they still frame it and number at
+    /// most `max_bytes`, so a reader can skip it and resume at the next request.
+    fn skip_len(
+        &self,
+        error: &ParseError,
+        framing: RequestFraming,
+        max_bytes: usize,
+    ) -> Option<usize>;
+
+    /// The `request_id` at the start of `buf`, read from a request's header alone so a
+    /// malformed request still has one. Called under `--request-ids` only, once the header is
+    /// whole.
+    fn request_id(&self, buf: &[u8]) -> u64;
+
+    /// Whether `frame`, a request [`Self::parse`] found complete, asks for its scores as a
+    /// [`FixedPointResponse`]. Called only when `framing.fixed_point_scores`; a format with no
+    /// way to ask keeps the default.
+    fn wants_fixed_point(&self, _frame: &[u8], _framing: RequestFraming) -> bool {
+        false
+    }
+
+    /// Whether requests are framed as in the native format, so debug builds can re-walk what a
+    /// parse consumed with an independent native parser.
+    fn native_framing(&self) -> bool {
+        false
+    }
+}
+
+/// The native request format: an optional `request_id`, `num_vectors` as a little-endian
+/// `u32`, then the features as little-endian `f32`s, vector by vector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeRequest;
+
+/// The decoder connections use unless told otherwise.
+pub static NATIVE_REQUEST: NativeRequest = NativeRequest;
+
+impl RequestDecoder for NativeRequest {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn parse(
+        &self,
+        _state: Option<&dyn DecoderState>,
+        buf: &[u8],
+        framing: RequestFraming,
+    ) -> ParseResult {
+        try_parse_request(buf, framing)
+    }
+
+    fn features<'a>(
+        &self,
+        _state: Option<&dyn DecoderState>,
+        frame: &'a [u8],
+        framing: RequestFraming,
+        scratch: &'a mut Vec<u8>,
+    ) -> &'a [u8] {
+        let features = &frame[framing.header_bytes()..];
+        if !is_half_request(frame, framing) {
+            return features;
+        }
+        scratch.resize(features.len() * 2, 0);
+        byte_order::widen_f16s_le(features, scratch);
+        scratch
+    }
+
+    fn feature_offset(&self, frame: &[u8], framing: RequestFraming, index: usize) -> usize {
+        let width = if is_half_request(frame, framing) {
+            BYTES_PER_F16
+        } else {
+            BYTES_PER_F32
+        };
+        framing.header_bytes() + index * width
+    }
+
+    fn skip_len(
+        &self,
+        error: &ParseError,
+        framing: RequestFraming,
+        max_bytes: usize,
+    ) -> Option<usize> {
+        error.skip_len(framing, max_bytes)
+    }
+
+    fn request_id(&self, buf: &[u8]) -> u64 {
+        decode_request_id(buf)
+    }
+
+    fn wants_fixed_point(&self, frame: &[u8], framing: RequestFraming) -> bool {
+        let header = &frame[framing.header_bytes() - REQUEST_HEADER_BYTES..];
+        wire_layout::REQUEST_NUM_VECTORS.read_u32(header) & FIXED_POINT_REQUEST_FLAG != 0
+    }
+
+    fn native_framing(&self) -> bool {
+        true
+    }
+}
+
+/// Whether the native request `frame` carries half-precision features.
+fn is_half_request(frame: &[u8], framing: RequestFraming) -> bool {
+    let header = &frame[framing.header_bytes() - REQUEST_HEADER_BYTES..];
+    split_num_vectors(wire_layout::REQUEST_NUM_VECTORS.read_u32(header)).1
+}
+
+/// What a [`RequestDecoder`] keeps for one connection, such as a decompressor or a jumbo frame
+/// being reassembled. The connection owns it from accept until it is reaped.
+pub trait DecoderState: Any + Send {
+    /// The pipeline consumed `bytes`, whole requests and skipped malformed ones, from the front
+    /// of the stream; the next parse starts after them.
+    fn on_consumed(&mut self, _bytes: &[u8]) {}
+
+    /// The connection was reaped; nothing more is parsed with this state.
+    fn on_close(&mut self) {}
+}
+
+impl dyn DecoderState {
+    /// The state as the concrete type its decoder opened.
+    pub fn downcast_ref<T: DecoderState>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+}
+
+/// A connection's decoder and the state it opened for the connection.
+pub struct ConnectionDecoder {
+    decoder: &'static dyn RequestDecoder,
+    state: Option<Box<dyn DecoderState>>,
+    /// Features the decoder converted; see [`RequestDecoder::features`].
+    scratch: Vec<u8>,
+}
+
+impl ConnectionDecoder {
+    pub fn new(decoder: &'static dyn RequestDecoder) -> Self {
+        Self {
+            decoder,
+            state: decoder.open(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn native() -> Self {
+        Self::new(&NATIVE_REQUEST)
+    }
+
+    /// The state the decoder keeps for this connection, if any.
+    pub fn state(&self) -> Option<&dyn DecoderState> {
+        self.state.as_deref()
+    }
+
+    pub fn parse(&self, buf: &[u8], framing: RequestFraming) -> ParseResult {
+        self.decoder.parse(self.state.as_deref(), buf, framing)
+    }
+
+    pub fn features<'a>(&'a mut self, frame: &'a [u8], framing: RequestFraming) -> &'a [u8] {
+        self.decoder
+            .features(self.state.as_deref(), frame, framing, &mut self.scratch)
+    }
+
+    pub fn feature_offset(&self, frame: &[u8], framing: RequestFraming, index: usize) -> usize {
+        self.decoder.feature_offset(frame, framing, index)
+    }
+
+    pub fn skip_len(
+        &self,
+        error: &ParseError,
+        framing: RequestFraming,
+        max_bytes: usize,
+    ) -> Option<usize> {
+        self.decoder.skip_len(error, framing, max_bytes)
+    }
+
+    pub fn request_id(&self, buf: &[u8]) -> u64 {
+        self.decoder.request_id(buf)
+    }
+
+    pub fn wants_fixed_point(&self, frame: &[u8], framing: RequestFraming) -> bool {
+        self.decoder.wants_fixed_point(frame, framing)
+    }
+
+    pub fn native_framing(&self) -> bool {
+        self.decoder.native_framing()
+    }
+
+    /// Tell the state `bytes` were consumed; see [`DecoderState::on_consumed`].
+    pub fn consumed(&mut self, bytes: &[u8]) {
+        if let Some(state) = &mut self.state
+            && !bytes.is_empty()
+        {
+            state.on_consumed(bytes);
+        }
+    }
+
+    /// Tell the state its connection is gone and drop it.
+    pub fn close(&mut self) {
+        if let Some(mut state) = self.state.take() {
+            state.on_close();
+        }
+    }
+}
+
+/// Outcome of one vector of a request, sent after its response with `--vector-status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum VectorStatus {
+    Ok = 0,
+    /// The vector carried NaN or infinite features, or broke the `--feature-schema`, and was
+    /// scored anyway.
+    InvalidInput = 1,
+    /// The model returned NaN or infinity for the vector.
+    ModelError = 2,
+}
+
+impl VectorStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(VectorStatus::Ok),
+            1 => Some(VectorStatus::InvalidInput),
+            2 => Some(VectorStatus::ModelError),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VectorStatus::Ok => "ok",
+            VectorStatus::InvalidInput => "invalid_input",
+            VectorStatus::ModelError => "model_error",
+        }
+    }
+}
+
+/// Why the server rejected a request instead of running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OverloadReason {
+    /// The request ring had no free slot.
+    RingFull = 1,
+    /// The request was over the `--large-requests` size threshold and as many large requests
+    /// as it allows were already in flight.
+    LargeRequests = 2,
+    /// The request was not answered within `--request-timeout-us` of being published, so its
+    /// scores, if it was ever run, are not sent.
+    Timeout = 3,
+    /// The backend timed out too many recent requests, so `--circuit-breaker` is failing new ones
+    /// fast until it recovers.
+    CircuitOpen = 4,
+}
+
+impl OverloadReason {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(OverloadReason::RingFull),
+            2 => Some(OverloadReason::LargeRequests),
+            3 => Some(OverloadReason::Timeout),
+            4 => Some(OverloadReason::CircuitOpen),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OverloadReason::RingFull => "ring_full",
+            OverloadReason::LargeRequests => "large_requests",
+            OverloadReason::Timeout => "timeout",
+            OverloadReason::CircuitOpen => "circuit_open",
+        }
+    }
+}
+
+/// Encode an overload frame into `dst`. Caller must ensure `dst.len() == OVERLOAD_FRAME_BYTES`.
+pub fn encode_overload(reason: OverloadReason, retry_after_ms: u16, dst: &mut [u8]) {
+    wire_layout::OVERLOAD_MARKER.write_u8(dst, 0);
+    wire_layout::OVERLOAD_REASON.write_u8(dst, reason as u8);
+    wire_layout::OVERLOAD_RETRY_AFTER_MS.write_u16(dst, retry_after_ms);
+}
+
+/// Decode `(reason, retry_after_ms)` if `frame` starts with an overload frame.
+///
+/// Returns `None` for a result or parse error frame. An unknown reason byte still decodes as an
+/// overload so older clients keep honoring the backoff when the server adds reasons.
+pub fn decode_overload(frame: &[u8]) -> Option<(Option<OverloadReason>, u16)> {
+    if frame.len() < OVERLOAD_FRAME_BYTES
+        || wire_layout::OVERLOAD_MARKER.read_u8(frame) != 0
+        || wire_layout::OVERLOAD_REASON.read_u8(frame) == PARSE_ERROR_KIND
+    {
+        return None;
+    }
+    Some((
+        OverloadReason::from_u8(wire_layout::OVERLOAD_REASON.read_u8(frame)),
+        wire_layout::OVERLOAD_RETRY_AFTER_MS.read_u16(frame),
+    ))
+}
+
+/// Encode a parse error frame into `dst`, with the low 32 bits of `error.offset`. Caller must
+/// ensure `dst.len() == PARSE_ERROR_FRAME_BYTES`.
+pub fn encode_parse_error(error: &ParseError, dst: &mut [u8]) {
+    wire_layout::PARSE_ERROR_MARKER.write_u8(dst, 0);
+    wire_layout::PARSE_ERROR_KIND.write_u8(dst, PARSE_ERROR_KIND);
+    wire_layout::PARSE_ERROR_FIELD.write_u16(dst, error.field as u16);
+    wire_layout::PARSE_ERROR_VALUE.write_u32(dst, error.value);
+    wire_layout::PARSE_ERROR_OFFSET.write_u32(dst, error.offset as u32);
+}
+
+/// Whether `frame` starts with a parse error frame, once its first two bytes have arrived.
+pub fn is_parse_error(frame: &[u8]) -> bool {
+    frame.len() >= 2
+        && wire_layout::PARSE_ERROR_MARKER.read_u8(frame) == 0
+        && wire_layout::PARSE_ERROR_KIND.read_u8(frame) == PARSE_ERROR_KIND
+}
+
+/// Decode a complete parse error frame. Returns `None` for other frames, and for a field this
+/// build does not know.
+pub fn decode_parse_error(frame: &[u8]) -> Option<ParseError> {
+    if frame.len() < PARSE_ERROR_FRAME_BYTES || !is_parse_error(frame) {
+        return None;
+    }
+    Some(ParseError {
+        field: RequestField::from_u16(wire_layout::PARSE_ERROR_FIELD.read_u16(frame))?,
+        value: wire_layout::PARSE_ERROR_VALUE.read_u32(frame),
+        offset: wire_layout::PARSE_ERROR_OFFSET.read_u32(frame) as u64,
+    })
+}
+
+/// Encode the length prefix for `frame_len` bytes that follow it into `dst[..LENGTH_PREFIX_BYTES]`.
+pub fn encode_length_prefix(frame_len: usize, dst: &mut [u8]) {
+    wire_layout::LENGTH_PREFIX_FRAME_LEN.write_u32(dst, frame_len as u32);
+}
+
+pub fn decode_length_prefix(frame: &[u8]) -> usize {
+    wire_layout::LENGTH_PREFIX_FRAME_LEN.read_u32(frame) as usize
+}
+
+/// Encode the `request_seq` prefix into `dst[..SEQ_PREFIX_BYTES]`; only the low 32 bits are
+/// sent.
+pub fn encode_seq_prefix(request_seq: u64, dst: &mut [u8]) {
+    wire_layout::SEQ_PREFIX_REQUEST_SEQ.write_u32(dst, request_seq as u32);
+}
+
+pub fn decode_seq_prefix(frame: &[u8]) -> u32 {
+    wire_layout::SEQ_PREFIX_REQUEST_SEQ.read_u32(frame)
+}
+
+/// Encode the `request_id` prefix into `dst[..REQUEST_ID_BYTES]`.
+pub fn encode_request_id(request_id: u64, dst: &mut [u8]) {
+    wire_layout::REQUEST_ID_REQUEST_ID.write_u64(dst, request_id);
+}
+
+pub fn decode_request_id(frame: &[u8]) -> u64 {
+    wire_layout::REQUEST_ID_REQUEST_ID.read_u64(frame)
+}
+
+/// How the server answered a replay hello.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ReplayStatus {
+    /// The kept frames from `resume_seq` follow.
+    Resumed = 0,
+    /// The session's previous connection is still open or owed responses; retry shortly.
+    Busy = 1,
+    /// The session or `resume_seq` is no longer held, or never was; start a new session.
+    Gone = 2,
+}
+
+impl ReplayStatus {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(ReplayStatus::Resumed),
+            1 => Some(ReplayStatus::Busy),
+            2 => Some(ReplayStatus::Gone),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a replay hello into `dst[..REPLAY_HELLO_BYTES]`.
+pub fn encode_replay_hello(session_token: u64, resume_seq: u64, dst: &mut [u8]) {
+    wire_layout::REPLAY_HELLO_SESSION_TOKEN.write_u64(dst, session_token);
+    wire_layout::REPLAY_HELLO_RESUME_SEQ.write_u64(dst, resume_seq);
+}
+
+/// Decode `(session_token, resume_seq)` from a complete replay hello.
+pub fn decode_replay_hello(frame: &[u8]) -> (u64, u64) {
+    (
+        wire_layout::REPLAY_HELLO_SESSION_TOKEN.read_u64(frame),
+        wire_layout::REPLAY_HELLO_RESUME_SEQ.read_u64(frame),
+    )
+}
+
+/// Encode a replay reply into `dst[..REPLAY_REPLY_BYTES]`.
+pub fn encode_replay_reply(
+    status: ReplayStatus,
+    session_token: u64,
+    next_request_seq: u64,
+    dst: &mut [u8],
+) {
+    wire_layout::REPLAY_REPLY_STATUS.write_u32(dst, status as u32);
+    wire_layout::REPLAY_REPLY_SESSION_TOKEN.write_u64(dst, session_token);
+    wire_layout::REPLAY_REPLY_NEXT_REQUEST_SEQ.write_u64(dst, next_request_seq);
+}
+
+/// Decode `(status, session_token, next_request_seq)` from a complete replay reply; `None` for a
+/// status this build does not know.
+pub fn decode_replay_reply(frame: &[u8]) -> Option<(ReplayStatus, u64, u64)> {
+    Some((
+        ReplayStatus::from_u32(wire_layout::REPLAY_REPLY_STATUS.read_u32(frame))?,
+        wire_layout::REPLAY_REPLY_SESSION_TOKEN.read_u64(frame),
+        wire_layout::REPLAY_REPLY_NEXT_REQUEST_SEQ.read_u64(frame),
+    ))
+}
+
+/// An echoed `request_seq` that does not follow the previous frame on the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceError {
+    /// At or before a sequence already answered: a duplicate or reordered response.
+    Repeated { expected: u32, got: u32 },
+    /// Past the expected sequence: responses in between were lost.
+    Skipped { expected: u32, got: u32 },
+}
+
+impl std::fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SequenceError::Repeated { expected, got } => {
+                write!(f, "expected request_seq {expected}, got repeated {got}")
+            }
+            SequenceError::Skipped { expected, got } => write!(
+                f,
+                "expected request_seq {expected}, got {got}: {} response(s) missing",
+                got.wrapping_sub(expected)
+            ),
+        }
+    }
+}
+
+/// Client-side check that echoed `request_seq`s on one connection count up by one from 0.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequenceCheck {
+    next: u32,
+}
+
+impl SequenceCheck {
+    pub fn check(&mut self, request_seq: u32) -> Result<(), SequenceError> {
+        let expected = self.next;
+        // Compare in wrapping distance so the check survives 2^32 requests on one connection.
+        let ahead = request_seq.wrapping_sub(expected);
+        if ahead == 0 {
+            self.next = expected.wrapping_add(1);
+            Ok(())
+        } else if ahead > u32::MAX / 2 {
+            Err(SequenceError::Repeated {
+                expected,
+                got: request_seq,
+            })
+        } else {
+            Err(SequenceError::Skipped {
+                expected,
+                got: request_seq,
+            })
+        }
+    }
+}
+
+/// How a connection's scores are written back to its client. Overload, parse error and replay
+/// frames are the same whichever serializer a connection uses, as are the frame prefixes and
+/// the vector status trailer around each response.
+pub trait ResponseSerializer: Sync {
+    /// Name for logs.
+    fn name(&self) -> &'static str;
+
+    /// Bytes of the response to a request of `num_results` vectors; at most `WRITE_BUF_SIZE`
+    /// for up to `MAX_VECTORS_PER_REQUEST` results.
+    fn response_size(&self, num_results: usize) -> usize;
+
+    /// Encode `results` into `dst`, which is `response_size(results.len())` bytes.
+    fn encode(&self, results: &[f32], dst: &mut [u8]);
+
+    /// Decode a response written by [`Self::encode`].
+    fn decode(&self, frame: &[u8]) -> Vec<f32>;
+}
+
+/// The native response format: `num_vectors` as a `u8`, then one little-endian `f32` per
+/// vector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeResponse;
+
+/// The serializer connections use unless told otherwise.
+pub static NATIVE_RESPONSE: NativeResponse = NativeResponse;
+
+impl ResponseSerializer for NativeResponse {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn response_size(&self, num_results: usize) -> usize {
+        response_size(num_results)
+    }
+
+    fn encode(&self, results: &[f32], dst: &mut [u8]) {
+        encode_response(results, dst);
+    }
+
+    fn decode(&self, frame: &[u8]) -> Vec<f32> {
+        decode_response(frame)
+    }
+}
+
+/// Scores as unsigned 16-bit fixed point, for clients that would rather not handle floats:
+/// `num_vectors` as a `u8`, then each score times `scale`, rounded and saturated to
+/// `0..=u16::MAX`, as a little-endian `u16`. NaN scores are sent as 0; with `--vector-status`
+/// their status still says `model_error`. The default [`BASIS_POINTS`] scale sends scores in
+/// `[0, 1]` as basis points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedPointResponse {
+    pub scale: f32,
+}
+
+impl Default for FixedPointResponse {
+    fn default() -> Self {
+        Self {
+            scale: BASIS_POINTS,
+        }
+    }
+}
+
+impl ResponseSerializer for FixedPointResponse {
+    fn name(&self) -> &'static str {
+        "fixed-point"
+    }
+
+    fn response_size(&self, num_results: usize) -> usize {
+        fixed_point_response_size(num_results)
+    }
+
+    fn encode(&self, results: &[f32], dst: &mut [u8]) {
+        wire_layout::FIXED_POINT_RESPONSE_NUM_VECTORS.write_u8(dst, results.len() as u8);
+        byte_order::write_fixed_point_u16s_le(
+            results,
+            self.scale,
+            &mut dst[wire_layout::FIXED_POINT_RESPONSE_RESULTS.offset..],
+        );
+    }
+
+    /// The scores as sent, divided back by `scale`.
+    fn decode(&self, frame: &[u8]) -> Vec<f32> {
+        let num_vectors = wire_layout::FIXED_POINT_RESPONSE_NUM_VECTORS.read_u8(frame) as usize;
+        frame[wire_layout::FIXED_POINT_RESPONSE_RESULTS.offset
+            ..fixed_point_response_size(num_vectors)]
+            .chunks_exact(BYTES_PER_FIXED_POINT)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / self.scale)
+            .collect()
     }
 }
 
 /// Encode a response into `dst`. Caller must ensure `dst.len() == response_size(results.len())`.
 pub fn encode_response(results: &[f32], dst: &mut [u8]) {
-    dst[0] = results.len() as u8;
-    dst[1..].copy_from_slice(bytemuck::cast_slice(results));
+    wire_layout::RESPONSE_NUM_VECTORS.write_u8(dst, results.len() as u8);
+    byte_order::write_f32s_le(results, &mut dst[wire_layout::RESPONSE_RESULTS.offset..]);
+}
+
+/// Decode the results of a response frame written by [`encode_response`].
+pub fn decode_response(frame: &[u8]) -> Vec<f32> {
+    let num_vectors = wire_layout::RESPONSE_NUM_VECTORS.read_u8(frame) as usize;
+    let mut results = vec![0f32; num_vectors];
+    byte_order::read_f32s_le(
+        &frame[wire_layout::RESPONSE_RESULTS.offset..response_size(num_vectors)],
+        &mut results,
+    );
+    results
+}
+
+/// Encode the status of each of `results` into `dst`: `InvalidInput` for the vectors set in the
+/// `invalid_vectors` mask, `ModelError` for NaN or infinite results, `Ok` otherwise. Caller must
+/// ensure `dst.len() == vector_status_size(results.len())`.
+pub fn encode_vector_status(results: &[f32], invalid_vectors: u64, dst: &mut [u8]) {
+    for (i, (status, &result)) in dst.iter_mut().zip(results).enumerate() {
+        *status = if invalid_vectors & (1 << i) != 0 {
+            VectorStatus::InvalidInput
+        } else if !result.is_finite() {
+            VectorStatus::ModelError
+        } else {
+            VectorStatus::Ok
+        } as u8;
+    }
+}
+
+/// Decode a vector status trailer; `None` for a status this build does not know.
+pub fn decode_vector_status(trailer: &[u8]) -> Vec<Option<VectorStatus>> {
+    trailer
+        .iter()
+        .map(|&status| VectorStatus::from_u8(status))
+        .collect()
+}
+
+const _: () = assert!(
+    MAX_VECTORS_PER_REQUEST <= u64::BITS as usize,
+    "vector masks are u64"
+);
+
+/// Mask of the vectors of `feature_dim` features in wire-format `feature_bytes` that carry a NaN
+/// or infinite value; bit `i` for vector `i`.
+pub fn non_finite_vectors(feature_bytes: &[u8], feature_dim: usize) -> u64 {
+    feature_bytes
+        .chunks_exact(feature_dim * BYTES_PER_F32)
+        .enumerate()
+        .filter(|(_, vector)| first_non_finite(vector).is_some())
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+/// Index and bits of the first NaN or infinite value in wire-format `feature_bytes`.
+pub fn first_non_finite(feature_bytes: &[u8]) -> Option<(usize, u32)> {
+    feature_bytes
+        .chunks_exact(BYTES_PER_F32)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .enumerate()
+        .find(|&(_, bits)| !f32::from_bits(bits).is_finite())
+}
+
+/// Replace NaN features in wire-format `feature_bytes` with 0 and infinities with the largest
+/// finite value of the same sign.
+pub fn clamp_non_finite(feature_bytes: &mut [u8]) {
+    for bytes in feature_bytes.chunks_exact_mut(BYTES_PER_F32) {
+        let value = f32::from_le_bytes(bytes.try_into().unwrap());
+        if !value.is_finite() {
+            let clamped = if value.is_nan() {
+                0.0
+            } else {
+                value.signum() * f32::MAX
+            };
+            bytes.copy_from_slice(&clamped.to_le_bytes());
+        }
+    }
 }
 
-/// Copy feature data from a raw byte buffer (starting after the 4-byte header)
-/// into the pre-allocated f32 slice in the disruptor event.
+/// Fill `dst` with feature data from a raw byte buffer (starting after the request header),
+/// such as the pre-allocated f32 slice in the disruptor event.
 ///
-/// Casts the destination (pool-allocated, f32-aligned) to bytes and copies
-/// directly — valid on little-endian platforms where f32 wire bytes are native.
-pub fn copy_features(src: &[u8], dst: &mut [f32], num_vectors: u8) {
-    let count = num_vectors as usize * FEATURE_DIM;
-    bytemuck::cast_slice_mut::<f32, u8>(&mut dst[..count]).copy_from_slice(&src[..count * 4]);
+/// A plain memcpy on little-endian hosts where f32 wire bytes are native; see [`byte_order`].
+pub fn copy_features(src: &[u8], dst: &mut [f32]) {
+    byte_order::read_f32s_le(&src[..dst.len() * BYTES_PER_F32], dst);
 }
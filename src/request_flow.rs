@@ -2,19 +2,38 @@
 //!
 //! Extracted so integration tests and benchmarks can drive the flow without io_uring.
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
 use disruptor::{Producer, RingBufferFull};
 
 use crate::buffer_pool::{AllocError, PoolAllocator};
-use crate::clock::monotonic_now_ns;
-use crate::connection_id::ConnectionRef;
-use crate::constants::FEATURE_DIM;
-use crate::protocol;
+use crate::clock::{self, monotonic_now_ns};
+use crate::config::{MAX_SKIPPED_REQUEST_BYTES, SLAB_CAPACITY};
+use crate::connection_id::{ConnSlots, ConnectionRef};
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::embedding::EmbeddingLookup;
+use crate::pipeline::admission::LargeRequestAdmission;
+use crate::pipeline::circuit_breaker::CircuitBreaker;
+use crate::pipeline::inline::{InlineFastPath, Placement, RingOccupancy};
+use crate::protocol::{
+    self, ConnectionDecoder, OverloadReason, ParseError, RequestField, RequestFraming,
+};
 use crate::ring_types::InferenceEvent;
 
+/// Feature bytes of the largest request at any `--feature-dim`, the size of the copy a clamped
+/// request is scored from.
+const MAX_REQUEST_FEATURE_BYTES: usize =
+    MAX_VECTORS_PER_REQUEST * MAX_FEATURE_DIM * protocol::BYTES_PER_F32;
+
 /// Error from processing request bytes.
 #[derive(Debug)]
 pub enum ProcessRequestError {
-    Parse(#[allow(dead_code)] &'static str),
+    /// `error.offset` is counted from the start of the buffer. The `consumed` bytes before the
+    /// malformed frame were processed and their `request_seq`s taken, like an `Ok` outcome's.
+    Parse { error: ParseError, consumed: usize },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -24,6 +43,249 @@ pub struct ProcessRequestOutcome {
     /// `true` if parsing stopped because more socket bytes are required to finish the
     /// next request, so the caller should re-arm a read when space is available.
     pub needs_read: bool,
+    /// `true` if parsing stopped at a complete request that neither the ring nor the overflow
+    /// queue could take.
+    pub ring_full: bool,
+    /// `true` if parsing stopped at a complete large request the admission limit held back.
+    pub large_request_held: bool,
+    /// Bytes of a skipped malformed request still to arrive after `buf`; the caller must discard
+    /// them from the stream before parsing again.
+    pub skip: usize,
+}
+
+/// What to do with a complete request when the request ring has no free slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingFullPolicy {
+    /// Stop consuming and leave the request buffered until a slot frees up.
+    Wait,
+    /// Consume the request and report it through `on_reject` so the caller can answer it with an
+    /// overload frame.
+    Reject,
+}
+
+/// What to do with a request carrying a NaN or infinite feature value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Score the request as sent.
+    #[default]
+    PassThrough,
+    /// Replace NaN with 0 and infinities with the largest finite value of the same sign.
+    Clamp,
+    /// Consume the request and report it through `on_invalid` so the caller can answer it with
+    /// a parse error frame.
+    Reject,
+}
+
+impl NonFinitePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NonFinitePolicy::PassThrough => "pass",
+            NonFinitePolicy::Clamp => "clamp",
+            NonFinitePolicy::Reject => "reject",
+        }
+    }
+}
+
+impl FromStr for NonFinitePolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "pass" => Ok(NonFinitePolicy::PassThrough),
+            "clamp" => Ok(NonFinitePolicy::Clamp),
+            "reject" => Ok(NonFinitePolicy::Reject),
+            other => Err(format!(
+                "unknown policy '{other}', expected pass, clamp or reject"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NonFinitePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What to do with a request whose `num_vectors` is out of range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MalformedPolicy {
+    /// Stop parsing and return the error, so the caller answers it and closes the connection.
+    #[default]
+    Close,
+    /// Report the request through `on_invalid` and skip the vectors its header declared, up to
+    /// [`MAX_SKIPPED_REQUEST_BYTES`]; longer requests are handled as under `Close`.
+    Skip,
+}
+
+impl MalformedPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MalformedPolicy::Close => "close",
+            MalformedPolicy::Skip => "skip",
+        }
+    }
+}
+
+impl FromStr for MalformedPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "close" => Ok(MalformedPolicy::Close),
+            "skip" => Ok(MalformedPolicy::Skip),
+            other => Err(format!("unknown policy '{other}', expected close or skip")),
+        }
+    }
+}
+
+impl fmt::Display for MalformedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A request that found the ring full, with its features copied out of the read buffer.
+struct ParkedRequest {
+    conn: ConnectionRef,
+    request_seq: u64,
+    /// When the read that completed the request finished.
+    received_at_ns: u64,
+    num_vectors: u8,
+    features: Vec<f32>,
+}
+
+/// Per-IO-thread FIFO of parsed requests that found the request ring full.
+///
+/// Parking a request frees its connection's read buffer and lets the IO thread go on serving
+/// its other connections instead of spinning on the ring. Parked requests are published in
+/// order by [`Self::drain`]; a connection with a request parked parks its later requests too, so
+/// each connection's requests still reach the ring in order. When the queue is full, connections
+/// that could not park are recorded as blocked and stop reading until [`Self::take_unblocked`]
+/// hands them back.
+pub struct RequestOverflow {
+    capacity: usize,
+    parked: VecDeque<ParkedRequest>,
+    /// Feature buffers of published requests, kept for reuse.
+    spare: Vec<Vec<f32>>,
+    /// Indexed by `conn_id`, so connection churn while the queue stays full cannot grow it.
+    blocked: ConnSlots<()>,
+}
+
+impl RequestOverflow {
+    /// A queue holding at most `capacity` requests; `0` disables parking.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            parked: VecDeque::with_capacity(capacity),
+            spare: Vec::new(),
+            blocked: ConnSlots::new(SLAB_CAPACITY),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.parked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parked.is_empty()
+    }
+
+    fn holds(&self, conn: ConnectionRef) -> bool {
+        self.parked.iter().any(|parked| parked.conn == conn)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn park(
+        &mut self,
+        conn: ConnectionRef,
+        request_seq: u64,
+        received_at_ns: u64,
+        num_vectors: u8,
+        feature_bytes: &[u8],
+        feature_dim: usize,
+        embeddings: Option<EmbeddingLookup<'_>>,
+    ) -> bool {
+        if self.parked.len() >= self.capacity {
+            return false;
+        }
+        let mut features = self.spare.pop().unwrap_or_default();
+        match embeddings {
+            Some(embeddings) => {
+                features.resize(
+                    num_vectors as usize * embeddings.model_dim(feature_dim),
+                    0.0,
+                );
+                embeddings.expand(feature_bytes, feature_dim, &mut features);
+            }
+            None => {
+                features.resize(feature_bytes.len() / protocol::BYTES_PER_F32, 0.0);
+                protocol::copy_features(feature_bytes, &mut features);
+            }
+        }
+        self.parked.push_back(ParkedRequest {
+            conn,
+            request_seq,
+            received_at_ns,
+            num_vectors,
+            features,
+        });
+        crate::metrics::inc_requests_parked();
+        true
+    }
+
+    /// Record that `conn` stopped parsing because the queue was full.
+    pub fn block(&mut self, conn: ConnectionRef) {
+        self.blocked.insert(conn, ());
+    }
+
+    /// Whether `conn` has stopped parsing until the queue has room.
+    pub fn is_blocked(&self, conn: ConnectionRef) -> bool {
+        self.blocked.get(conn).is_some()
+    }
+
+    /// Publish parked requests in order until the ring fills up. Returns the number published.
+    pub fn drain(
+        &mut self,
+        producer: &mut impl Producer<InferenceEvent>,
+        allocator: &mut PoolAllocator,
+        occupancy: Option<&RingOccupancy>,
+    ) -> usize {
+        let mut published = 0;
+        while let Some(parked) = self.parked.front() {
+            let result = publish(
+                producer,
+                allocator,
+                parked.conn,
+                parked.request_seq,
+                parked.received_at_ns,
+                parked.num_vectors,
+                parked.features.len() / parked.num_vectors as usize,
+                occupancy,
+                |features| features.copy_from_slice(&parked.features),
+            );
+            if result.is_err() {
+                break;
+            }
+            let parked = self.parked.pop_front().expect("front was present");
+            self.spare.push(parked.features);
+            published += 1;
+        }
+        published
+    }
+
+    /// Connections blocked by a full queue, once there is room again.
+    pub fn take_unblocked(&mut self) -> Vec<ConnectionRef> {
+        if self.parked.len() < self.capacity {
+            self.blocked.drain().map(|(conn, ())| conn).collect()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 /// Process all complete requests in `buf`, publishing each to the request ring.
@@ -35,73 +297,673 @@ pub struct ProcessRequestOutcome {
 ///
 /// Pool exhaustion spins inside the closure until the batch processor releases
 /// slices on the other thread. `AllocError::TooLarge` cannot occur in practice
-/// because `num_vectors * FEATURE_DIM` is bounded far below pool capacity.
+/// because `num_vectors * feature_dim` is bounded far below pool capacity.
 ///
 /// Returns `Err` only on a parse error; caller should close the connection.
+///
+/// Either way the bytes reported consumed are at most `buf.len()` and cover whole frames only,
+/// each processed exactly once, so the caller can drop them from the front of its buffer and
+/// resume at the next frame. Debug builds re-check this against an independent parse of `buf`.
 pub fn process_requests_from_buffer(
     buf: &[u8],
     producer: &mut impl Producer<InferenceEvent>,
     allocator: &mut PoolAllocator,
     conn: ConnectionRef,
     request_seq: &mut u64,
+) -> Result<ProcessRequestOutcome, ProcessRequestError> {
+    process_requests_with_policy(
+        buf,
+        producer,
+        allocator,
+        conn,
+        request_seq,
+        RingFullPolicy::Wait,
+        |_, _| {},
+    )
+}
+
+/// Like [`process_requests_from_buffer`], but with an explicit [`RingFullPolicy`].
+///
+/// Under `Reject`, a request that finds the ring full still consumes its bytes and its
+/// `request_seq`, and `on_reject(request_seq, reason)` is called so the caller can answer it in
+/// order. Rejected requests are not counted in `num_published`.
+pub fn process_requests_with_policy(
+    buf: &[u8],
+    producer: &mut impl Producer<InferenceEvent>,
+    allocator: &mut PoolAllocator,
+    conn: ConnectionRef,
+    request_seq: &mut u64,
+    ring_full: RingFullPolicy,
+    on_reject: impl FnMut(u64, OverloadReason),
+) -> Result<ProcessRequestOutcome, ProcessRequestError> {
+    process_requests_with_inline(
+        buf,
+        producer,
+        allocator,
+        conn,
+        request_seq,
+        monotonic_now_ns(),
+        ring_full,
+        NonFinitePolicy::PassThrough,
+        MalformedPolicy::Close,
+        RequestFraming::PLAIN,
+        &mut ConnectionDecoder::native(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        on_reject,
+        |_, _| {},
+        |_, _| {},
+    )
+}
+
+/// Like [`process_requests_with_policy`], but requests that `inline` places on the IO thread are
+/// answered through `on_inline(request_seq, scores)` instead of being published. They consume their bytes
+/// and `request_seq` and are not counted in `num_published`. Published requests are counted in
+/// the fast path's ring occupancy.
+///
+/// With an `overflow` queue, a request that finds the ring full under `Wait` is parked there
+/// instead of stopping the parse, and is not counted in `num_published`. Once a connection has a
+/// request parked, its later requests are parked behind it under either policy.
+///
+/// Requests with a NaN or infinite feature are handled by `non_finite` before placement. Under
+/// `Reject` they consume their bytes and `request_seq` and `on_invalid(request_seq, error)` is
+/// called with the first offending value, its offset counted from the start of the buffer; the
+/// parse goes on with the next request.
+///
+/// With an `admission` limit, a large request it does not admit stops the parse under `Wait`,
+/// and under `Reject` is answered through `on_reject` with [`OverloadReason::LargeRequests`].
+/// An admitted request that is neither published nor parked is released again.
+///
+/// While `circuit` is open, every request the inline fast path does not score is answered
+/// through `on_reject` with [`OverloadReason::CircuitOpen`] under either policy. A half-open
+/// circuit's probe taken by a request that is then held, parked or rejected is handed back.
+///
+/// Under [`MalformedPolicy::Skip`], a request whose `num_vectors` is out of range but still
+/// frames it is reported the same way, with the error's offset counted from the start of the
+/// buffer, and the parse goes on after it. When its vectors run past the end of `buf`, the
+/// outcome consumes all of `buf` and reports the bytes still to discard in `skip`.
+///
+/// Published requests carry `received_at_ns`, when the read that completed `buf` finished.
+///
+/// Requests are parsed by `decoder` as `framing`, and its state is told what was consumed; the
+/// caller reads back any `request_id`s from the consumed bytes. With `embeddings`, the ids at
+/// the end of each published or parked vector are expanded to their rows on the way into the
+/// pool, so the pool holds vectors of the model's width.
+#[allow(clippy::too_many_arguments)]
+pub fn process_requests_with_inline(
+    buf: &[u8],
+    producer: &mut impl Producer<InferenceEvent>,
+    allocator: &mut PoolAllocator,
+    conn: ConnectionRef,
+    request_seq: &mut u64,
+    received_at_ns: u64,
+    ring_full: RingFullPolicy,
+    non_finite: NonFinitePolicy,
+    malformed: MalformedPolicy,
+    framing: RequestFraming,
+    decoder: &mut ConnectionDecoder,
+    inline: Option<&InlineFastPath>,
+    overflow: Option<&mut RequestOverflow>,
+    admission: Option<&LargeRequestAdmission>,
+    circuit: Option<&CircuitBreaker>,
+    embeddings: Option<EmbeddingLookup<'_>>,
+    on_reject: impl FnMut(u64, OverloadReason),
+    on_inline: impl FnMut(u64, &[f32]),
+    on_invalid: impl FnMut(u64, ParseError),
+) -> Result<ProcessRequestOutcome, ProcessRequestError> {
+    let seq_start = *request_seq;
+    let result = process_frames(
+        buf,
+        producer,
+        allocator,
+        conn,
+        request_seq,
+        received_at_ns,
+        ring_full,
+        non_finite,
+        malformed,
+        framing,
+        decoder,
+        inline,
+        overflow,
+        admission,
+        circuit,
+        embeddings,
+        on_reject,
+        on_inline,
+        on_invalid,
+    );
+    if cfg!(debug_assertions) && decoder.native_framing() {
+        check_consumption(buf, framing, malformed, *request_seq - seq_start, &result);
+    }
+    let consumed = match &result {
+        Ok(outcome) => outcome.consumed,
+        Err(ProcessRequestError::Parse { consumed, .. }) => *consumed,
+    };
+    decoder.consumed(&buf[..consumed]);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_frames(
+    buf: &[u8],
+    producer: &mut impl Producer<InferenceEvent>,
+    allocator: &mut PoolAllocator,
+    conn: ConnectionRef,
+    request_seq: &mut u64,
+    received_at_ns: u64,
+    ring_full: RingFullPolicy,
+    non_finite: NonFinitePolicy,
+    malformed: MalformedPolicy,
+    framing: RequestFraming,
+    decoder: &mut ConnectionDecoder,
+    inline: Option<&InlineFastPath>,
+    mut overflow: Option<&mut RequestOverflow>,
+    admission: Option<&LargeRequestAdmission>,
+    circuit: Option<&CircuitBreaker>,
+    embeddings: Option<EmbeddingLookup<'_>>,
+    mut on_reject: impl FnMut(u64, OverloadReason),
+    mut on_inline: impl FnMut(u64, &[f32]),
+    mut on_invalid: impl FnMut(u64, ParseError),
 ) -> Result<ProcessRequestOutcome, ProcessRequestError> {
     let mut consumed = 0;
     let mut num_published = 0;
     let mut needs_read = false;
+    let mut stopped_full = false;
+    let mut held_large = false;
+    let mut skip = 0;
+    let mut parking = overflow
+        .as_ref()
+        .is_some_and(|overflow| overflow.holds(conn));
 
     while consumed < buf.len() {
         let slice = &buf[consumed..];
-        match protocol::try_parse_request(slice) {
+        match decoder.parse(slice, framing) {
             protocol::ParseResult::Complete {
                 num_vectors,
                 bytes_consumed,
+                ..
             } => {
-                let feature_bytes = &slice[protocol::REQUEST_HEADER_BYTES..bytes_consumed];
+                let mut feature_bytes = decoder.features(&slice[..bytes_consumed], framing);
                 let seq = *request_seq;
-                let feature_count = num_vectors as usize * FEATURE_DIM;
-
-                match producer.try_publish(|slot| {
-                    // Alloc inside the closure: only runs when a ring slot is available,
-                    // so RingBufferFull never leaves a live PoolSlice outside the ring.
-                    // Spin on Exhausted — the batch processor releases on the other thread.
-                    let mut pool_slice = loop {
-                        match allocator.alloc(feature_count) {
-                            Ok(s) => break s,
-                            Err(AllocError::Exhausted { .. }) => std::hint::spin_loop(),
-                            Err(AllocError::TooLarge { .. }) => unreachable!(
-                                "feature_count {feature_count} cannot exceed pool capacity"
-                            ),
+
+                let mut clamped;
+                if let Some((index, bits)) = protocol::first_non_finite(feature_bytes) {
+                    crate::metrics::record_non_finite(non_finite);
+                    match non_finite {
+                        NonFinitePolicy::PassThrough => {}
+                        NonFinitePolicy::Clamp => {
+                            clamped = [0u8; MAX_REQUEST_FEATURE_BYTES];
+                            let clamped = &mut clamped[..feature_bytes.len()];
+                            clamped.copy_from_slice(feature_bytes);
+                            protocol::clamp_non_finite(clamped);
+                            feature_bytes = clamped;
+                        }
+                        NonFinitePolicy::Reject => {
+                            let offset = consumed
+                                + decoder.feature_offset(&slice[..bytes_consumed], framing, index);
+                            on_invalid(
+                                seq,
+                                ParseError {
+                                    field: RequestField::Features,
+                                    value: bits,
+                                    offset: offset as u64,
+                                },
+                            );
+                            *request_seq += 1;
+                            consumed += bytes_consumed;
+                            continue;
                         }
-                    };
-                    protocol::copy_features(feature_bytes, pool_slice.as_mut_slice(), num_vectors);
-                    slot.conn = conn;
-                    slot.request_seq = seq;
-                    slot.num_vectors = num_vectors;
-                    slot.published_at_ns = monotonic_now_ns();
-                    slot.features = pool_slice.freeze();
-                }) {
-                    Ok(_) => {}
-                    Err(RingBufferFull) => {
+                    }
+                }
+
+                if let Some(inline) = inline {
+                    let mut scores = [0.0; MAX_VECTORS_PER_REQUEST];
+                    let placement = inline.place_and_score(num_vectors, feature_bytes, &mut scores);
+                    crate::metrics::record_placement(placement);
+                    if placement == Placement::Inline {
+                        on_inline(seq, &scores[..num_vectors as usize]);
+                        *request_seq += 1;
+                        consumed += bytes_consumed;
+                        continue;
+                    }
+                }
+
+                let passed = match circuit.map(CircuitBreaker::try_admit) {
+                    Some(None) => {
+                        crate::metrics::inc_overload_rejected(OverloadReason::CircuitOpen);
+                        on_reject(seq, OverloadReason::CircuitOpen);
+                        *request_seq += 1;
+                        consumed += bytes_consumed;
+                        continue;
+                    }
+                    Some(passed) => passed,
+                    None => None,
+                };
+                // A half-open circuit's probe only learns something once published; one taken
+                // by a request that is held, parked or rejected here goes back.
+                let unpass = || {
+                    if let (Some(circuit), Some(passed)) = (circuit, passed) {
+                        circuit.give_back(passed);
+                    }
+                };
+
+                if let Some(admission) = admission
+                    && !admission.try_admit(num_vectors as usize)
+                {
+                    unpass();
+                    if ring_full == RingFullPolicy::Wait {
+                        crate::metrics::inc_large_requests_held();
+                        held_large = true;
+                        break;
+                    }
+                    crate::metrics::inc_overload_rejected(OverloadReason::LargeRequests);
+                    on_reject(seq, OverloadReason::LargeRequests);
+                    *request_seq += 1;
+                    consumed += bytes_consumed;
+                    continue;
+                }
+                let release = || {
+                    if let Some(admission) = admission {
+                        admission.complete(num_vectors as usize);
+                    }
+                };
+
+                let published = !parking
+                    && publish(
+                        producer,
+                        allocator,
+                        conn,
+                        seq,
+                        received_at_ns,
+                        num_vectors,
+                        embeddings.map_or(framing.feature_dim, |embeddings| {
+                            embeddings.model_dim(framing.feature_dim)
+                        }),
+                        inline.map(InlineFastPath::occupancy),
+                        |features| match embeddings {
+                            Some(embeddings) => {
+                                embeddings.expand(feature_bytes, framing.feature_dim, features)
+                            }
+                            None => protocol::copy_features(feature_bytes, features),
+                        },
+                    )
+                    .is_ok();
+                if !published {
+                    if !parking {
                         crate::metrics::inc_req_ring_full();
+                    }
+                    unpass();
+                    if (parking || ring_full == RingFullPolicy::Wait)
+                        && let Some(overflow) = overflow.as_deref_mut()
+                        && overflow.park(
+                            conn,
+                            seq,
+                            received_at_ns,
+                            num_vectors,
+                            feature_bytes,
+                            framing.feature_dim,
+                            embeddings,
+                        )
+                    {
+                        parking = true;
+                        *request_seq += 1;
+                        consumed += bytes_consumed;
+                        continue;
+                    }
+                    release();
+                    if ring_full == RingFullPolicy::Wait {
+                        if overflow.is_some() {
+                            crate::metrics::inc_request_overflow_full();
+                        }
+                        stopped_full = true;
                         break;
                     }
+                    crate::metrics::inc_overload_rejected(OverloadReason::RingFull);
+                    on_reject(seq, OverloadReason::RingFull);
+                    *request_seq += 1;
+                    consumed += bytes_consumed;
+                    continue;
                 }
                 *request_seq += 1;
                 num_published += 1;
-                crate::metrics::inc_requests_published();
-                crate::metrics::inc_req_occ();
                 consumed += bytes_consumed;
             }
             protocol::ParseResult::Incomplete(_) => {
                 needs_read = true;
                 break;
             }
-            protocol::ParseResult::Error(e) => return Err(ProcessRequestError::Parse(e)),
+            protocol::ParseResult::Error(e) => {
+                let error = e.at(consumed as u64);
+                let skip_len = decoder.skip_len(&error, framing, MAX_SKIPPED_REQUEST_BYTES);
+                match skip_len {
+                    Some(len) if malformed == MalformedPolicy::Skip => {
+                        on_invalid(*request_seq, error);
+                        *request_seq += 1;
+                        let available = buf.len() - consumed;
+                        consumed += len.min(available);
+                        skip = len.saturating_sub(available);
+                    }
+                    _ => return Err(ProcessRequestError::Parse { error, consumed }),
+                }
+            }
         }
     }
     Ok(ProcessRequestOutcome {
         consumed,
         num_published,
         needs_read,
+        ring_full: stopped_full,
+        large_request_held: held_large,
+        skip,
     })
 }
+
+/// Shadow parser for debug builds of natively framed requests: walk `buf` frame by frame from its headers alone, without
+/// [`protocol::try_parse_request`], and panic unless the pass that produced `result` took
+/// `frames` whole frames, consumed exactly their bytes, and stopped where it had to: at the end
+/// of `buf`, at an incomplete frame, at the malformed frame it reported, or at a complete frame
+/// left behind for a full ring. Under [`MalformedPolicy::Skip`], malformed frames short enough to
+/// skip count as frames, and the last may run past `buf` by the outcome's `skip`.
+fn check_consumption(
+    buf: &[u8],
+    framing: RequestFraming,
+    malformed: MalformedPolicy,
+    frames: u64,
+    result: &Result<ProcessRequestOutcome, ProcessRequestError>,
+) {
+    let (consumed, skip) = match result {
+        Ok(outcome) => (outcome.consumed, outcome.skip),
+        Err(ProcessRequestError::Parse { consumed, .. }) => (*consumed, 0),
+    };
+    assert!(
+        consumed <= buf.len(),
+        "consumed {consumed} of a {}-byte buffer",
+        buf.len()
+    );
+
+    let num_vectors_at = framing.header_bytes() - protocol::REQUEST_HEADER_BYTES;
+    // Vector count and whether the features are half-precision.
+    let header = |pos: usize| {
+        buf.get(pos + num_vectors_at..pos + framing.header_bytes())
+            .map(|bytes| {
+                let (n, half) =
+                    framing.split_num_vectors(u32::from_le_bytes(bytes.try_into().unwrap()));
+                (n as usize, half)
+            })
+    };
+    let skipped_len = |(n, half): (usize, bool)| {
+        let len = framing.header_bytes() + n * framing.vector_bytes_as(half);
+        (malformed == MalformedPolicy::Skip && len <= MAX_SKIPPED_REQUEST_BYTES).then_some(len)
+    };
+    let frame_len = |(n, half): (usize, bool)| {
+        if (1..=framing.max_vectors).contains(&n) {
+            Some(framing.request_size_as(n, half))
+        } else {
+            skipped_len((n, half))
+        }
+    };
+    let mut pos = 0;
+    let mut walked = 0;
+    while pos < consumed {
+        let end = header(pos).and_then(frame_len).map(|len| pos + len);
+        match end {
+            Some(end) if end <= consumed => pos = end,
+            Some(end) if skip > 0 && end == consumed + skip => pos = consumed,
+            _ => panic!("consumed {consumed} ends inside or past the frame at {pos}"),
+        }
+        walked += 1;
+    }
+    assert_eq!(
+        walked, frames,
+        "consumed {consumed} bytes of {walked} frames"
+    );
+    if skip > 0 {
+        assert_eq!(
+            consumed,
+            buf.len(),
+            "left bytes behind a frame still being skipped"
+        );
+        return;
+    }
+
+    let next = header(consumed);
+    let next_valid = next.filter(|(n, _)| (1..=framing.max_vectors).contains(n));
+    let next_complete = next_valid
+        .is_some_and(|(n, half)| consumed + framing.request_size_as(n, half) <= buf.len());
+    match result {
+        Ok(outcome) if outcome.ring_full || outcome.large_request_held => assert!(
+            next_complete,
+            "stopped for a full ring or admission at {consumed} without a complete frame there"
+        ),
+        Ok(outcome) if outcome.needs_read => assert!(
+            consumed < buf.len() && !next_complete && (next.is_none() || next_valid.is_some()),
+            "stopped for more bytes at {consumed} without an incomplete frame there"
+        ),
+        Ok(_) => assert_eq!(consumed, buf.len(), "stopped early without a reason"),
+        Err(ProcessRequestError::Parse { error, .. }) => {
+            assert!(
+                next.is_some() && next_valid.is_none() && next.and_then(skipped_len).is_none(),
+                "reported a parse error at {consumed} over a well-formed header"
+            );
+            assert!(error.offset >= consumed as u64 && error.offset < buf.len() as u64);
+        }
+    }
+}
+
+/// Publish one request of `num_vectors` vectors of `feature_dim` features to the ring, filling
+/// its pool slice with `fill`.
+///
+/// Pool allocation happens inside the `try_publish` closure, which only runs when a ring slot
+/// is available, so `RingBufferFull` never leaves a live `PoolSlice` outside the ring.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn publish(
+    producer: &mut impl Producer<InferenceEvent>,
+    allocator: &mut PoolAllocator,
+    conn: ConnectionRef,
+    request_seq: u64,
+    received_at_ns: u64,
+    num_vectors: u8,
+    feature_dim: usize,
+    occupancy: Option<&RingOccupancy>,
+    fill: impl FnOnce(&mut [f32]),
+) -> Result<(), RingBufferFull> {
+    producer.try_publish(|slot| {
+        fill_event(
+            slot,
+            allocator,
+            conn,
+            request_seq,
+            received_at_ns,
+            num_vectors,
+            feature_dim,
+            occupancy,
+            fill,
+        )
+    })?;
+    crate::metrics::inc_requests_published();
+    crate::metrics::inc_req_occ();
+    Ok(())
+}
+
+/// Fill a claimed ring slot with one request. Callers count it as published once the slot is.
+///
+/// Pool exhaustion spins until the batch processor releases slices on the other thread.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fill_event(
+    slot: &mut InferenceEvent,
+    allocator: &mut PoolAllocator,
+    conn: ConnectionRef,
+    request_seq: u64,
+    received_at_ns: u64,
+    num_vectors: u8,
+    feature_dim: usize,
+    occupancy: Option<&RingOccupancy>,
+    fill: impl FnOnce(&mut [f32]),
+) {
+    let feature_count = num_vectors as usize * feature_dim;
+    let mut exhausted_at = None;
+    let mut pool_slice = loop {
+        match allocator.alloc(feature_count) {
+            Ok(s) => break s,
+            Err(AllocError::Exhausted { .. }) => {
+                exhausted_at.get_or_insert_with(clock::ticks);
+                std::hint::spin_loop()
+            }
+            Err(AllocError::TooLarge { .. }) => {
+                unreachable!("feature_count {feature_count} cannot exceed pool capacity")
+            }
+        }
+    };
+    if let Some(start) = exhausted_at {
+        crate::metrics::record_pool_exhausted_wait_ticks(clock::ticks().wrapping_sub(start));
+    }
+    fill(pool_slice.as_mut_slice());
+    slot.conn = conn;
+    slot.request_seq = request_seq;
+    slot.num_vectors = num_vectors;
+    slot.received_at_ns = received_at_ns;
+    slot.published_at_ns = monotonic_now_ns();
+    slot.features = pool_slice.freeze();
+    crate::metrics::record_receive_to_publish(Duration::from_nanos(
+        slot.published_at_ns.saturating_sub(received_at_ns),
+    ));
+    if let Some(occupancy) = occupancy {
+        occupancy.add_published(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MalformedPolicy, ProcessRequestOutcome, check_consumption};
+    use crate::protocol::{self, RequestFraming};
+
+    fn frames(counts: &[u32]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for &n in counts {
+            let start = buf.len();
+            buf.resize(start + protocol::request_size(n as usize), 0);
+            buf[start..start + 4].copy_from_slice(&n.to_le_bytes());
+        }
+        buf
+    }
+
+    fn outcome(consumed: usize, needs_read: bool, ring_full: bool) -> ProcessRequestOutcome {
+        ProcessRequestOutcome {
+            consumed,
+            num_published: 0,
+            needs_read,
+            ring_full,
+            large_request_held: false,
+            skip: 0,
+        }
+    }
+
+    #[test]
+    fn shadow_parser_accepts_whole_frame_consumption() {
+        let buf = frames(&[1, 3, 2]);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            3,
+            &Ok(outcome(buf.len(), false, false)),
+        );
+        let first_two = protocol::request_size(1) + protocol::request_size(3);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            2,
+            &Ok(outcome(first_two, false, true)),
+        );
+        check_consumption(
+            &buf[..first_two + 6],
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            2,
+            &Ok(outcome(first_two, true, false)),
+        );
+
+        let mut with_ids = Vec::new();
+        for frame in [
+            &buf[..protocol::request_size(1)],
+            &buf[protocol::request_size(1)..first_two],
+        ] {
+            with_ids.extend_from_slice(&7u64.to_le_bytes());
+            with_ids.extend_from_slice(frame);
+        }
+        check_consumption(
+            &with_ids,
+            RequestFraming::REQUEST_ID,
+            MalformedPolicy::Close,
+            2,
+            &Ok(outcome(with_ids.len(), false, false)),
+        );
+    }
+
+    #[test]
+    fn shadow_parser_walks_skipped_frames() {
+        let buf = frames(&[1, 0, 70, 2]);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Skip,
+            4,
+            &Ok(outcome(buf.len(), false, false)),
+        );
+        let partial = protocol::request_size(1) + protocol::request_size(0) + 100;
+        check_consumption(
+            &buf[..partial],
+            RequestFraming::PLAIN,
+            MalformedPolicy::Skip,
+            3,
+            &Ok(ProcessRequestOutcome {
+                skip: protocol::request_size(70) - 100,
+                ..outcome(partial, false, false)
+            }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ends inside or past the frame")]
+    fn shadow_parser_rejects_consumption_mid_frame() {
+        let buf = frames(&[1, 1]);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            1,
+            &Ok(outcome(protocol::request_size(1) + 4, true, false)),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "bytes of 2 frames")]
+    fn shadow_parser_rejects_frames_processed_twice() {
+        let buf = frames(&[1, 1]);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            3,
+            &Ok(outcome(buf.len(), false, false)),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "stopped early without a reason")]
+    fn shadow_parser_rejects_unexplained_stop() {
+        let buf = frames(&[1, 1]);
+        check_consumption(
+            &buf,
+            RequestFraming::PLAIN,
+            MalformedPolicy::Close,
+            1,
+            &Ok(outcome(protocol::request_size(1), false, false)),
+        );
+    }
+}
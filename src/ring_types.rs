@@ -1,22 +1,26 @@
 use crate::buffer_pool::PoolSlice;
+use crate::cache_line::{self, cache_line_aligned};
 use crate::connection_id::ConnectionRef;
-use crate::constants::FEATURE_DIM;
-use std::mem::size_of;
 
-/// Entry in the disruptor ring buffer. Pre-allocated per slot via factory.
-/// IO threads fill these in the publish closure; the ONNX submission/completion
-/// consumers read them.
-///
-/// Invariants:
-/// - `conn`: logical connection identity `(shard, conn_id, generation)` packed into 32 bits.
-/// - `num_vectors`: 1..=MAX_VECTORS_PER_REQUEST (u8).
-#[repr(C, align(64))]
-pub struct InferenceEvent {
-    pub conn: ConnectionRef,
-    pub request_seq: u64,
-    pub num_vectors: u8,
-    pub published_at_ns: u64,
-    pub features: PoolSlice,
+cache_line_aligned! {
+    /// Entry in the disruptor ring buffer. Pre-allocated per slot via factory.
+    /// IO threads fill these in the publish closure; the ONNX submission/completion
+    /// consumers read them.
+    ///
+    /// Invariants:
+    /// - `conn`: logical connection identity `(shard, conn_id, generation)` packed into 32 bits.
+    /// - `num_vectors`: 1..=MAX_VECTORS_PER_REQUEST (u8).
+    ///
+    /// Occupies whole cache lines, so adjacent slots never false-share.
+    pub struct InferenceEvent {
+        pub conn: ConnectionRef,
+        pub request_seq: u64,
+        pub num_vectors: u8,
+        /// When the read that completed the request finished.
+        pub received_at_ns: u64,
+        pub published_at_ns: u64,
+        pub features: PoolSlice,
+    }
 }
 
 impl InferenceEvent {
@@ -26,6 +30,7 @@ impl InferenceEvent {
             conn: ConnectionRef::new(0, 0, 1),
             request_seq: 0,
             num_vectors: 0,
+            received_at_ns: 0,
             published_at_ns: 0,
             features: PoolSlice::empty(),
         }
@@ -33,7 +38,8 @@ impl InferenceEvent {
 
     /// Get the feature slice for vector `i`.
     pub fn vector(&self, i: usize) -> &[f32] {
-        self.features.vector(i, FEATURE_DIM)
+        let feature_dim = self.features.as_slice().len() / self.num_vectors as usize;
+        self.features.vector(i, feature_dim)
     }
 
     pub fn io_thread_id(&self) -> u8 {
@@ -50,6 +56,6 @@ impl InferenceEvent {
 }
 
 const _: () = assert!(
-    size_of::<InferenceEvent>() == 64,
-    "InferenceEvent must be exactly 64 bytes"
+    cache_line::is_padded::<InferenceEvent>(),
+    "InferenceEvent must occupy whole cache lines"
 );
@@ -0,0 +1,404 @@
+//! Offline scoring: stream a file of requests through the serving pipeline, without a network.
+//!
+//! The input is a concatenation of wire-format requests and the output the matching
+//! wire-format responses, one per request in the same order, so a dataset scored here can be
+//! compared byte for byte against what the server sends. Requests run through an [`Engine`],
+//! with the server's buffer pool, batching and backend.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+
+use crate::calibration::Calibration;
+use crate::config::{DEFAULT_BATCH_COALESCE_US, MAX_SESSION_BATCH_SIZE, SESSION_POOL_SIZE};
+use crate::constants::FEATURE_DIM;
+use crate::engine::{Engine, EngineBuilder, EngineError};
+use crate::model_artifact::ModelArtifact;
+use crate::pipeline::{InferenceBackend, Numerics, OrtBackend};
+use crate::protocol::{self, ParseResult, REQUEST_HEADER_BYTES, RequestFraming};
+
+/// Requests parsed from the input and submitted to the engine together.
+const SCORE_CHUNK_REQUESTS: usize = 4096;
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Args, Clone)]
+pub struct ScoreArgs {
+    /// Path to the ONNX model file.
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Manifest (`version`, `sha256`, `dtype`, `input_dim`) to check --model against before
+    /// loading it.
+    #[arg(long)]
+    pub model_manifest: Option<PathBuf>,
+
+    /// Features per request vector, as `serve --feature-dim` takes them.
+    #[arg(long, default_value_t = FEATURE_DIM, value_parser = protocol::parse_feature_dim)]
+    pub feature_dim: usize,
+
+    /// File of wire-format requests: `[u32 num_vectors LE][f32 × num_vectors × feature_dim LE]`.
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// Where to write one wire-format response per request, in request order.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Runtime cap on ring slots per backend submission.
+    #[arg(long, default_value_t = MAX_SESSION_BATCH_SIZE)]
+    pub max_batch_slots: usize,
+
+    /// Coalescing window for a partial batch once a session is available, in microseconds.
+    #[arg(long, default_value_t = DEFAULT_BATCH_COALESCE_US)]
+    pub batch_coalesce_us: u64,
+
+    /// Return bit-identical results for identical requests across runs: deterministic backend
+    /// kernels and one request per batch, at some cost in throughput.
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Calibration (`method = platt|isotonic` and its parameters) applied to every result, as
+    /// `serve --calibration` does.
+    #[arg(long)]
+    pub calibration: Option<PathBuf>,
+
+    /// Follow every response with a status byte per vector, as `serve --vector-status` does.
+    #[arg(long)]
+    pub vector_status: bool,
+}
+
+/// Totals for one scored stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreSummary {
+    pub requests: u64,
+    pub vectors: u64,
+}
+
+pub fn run(args: ScoreArgs) {
+    if args.max_batch_slots == 0 || args.max_batch_slots > MAX_SESSION_BATCH_SIZE {
+        eprintln!("disrust: --max-batch-slots must be in 1..={MAX_SESSION_BATCH_SIZE}");
+        std::process::exit(1);
+    }
+    let model = ModelArtifact::load(
+        args.model.as_ref(),
+        args.model_manifest.as_deref(),
+        args.feature_dim,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("disrust: model {}: {e}", args.model);
+        std::process::exit(1);
+    });
+    eprintln!("disrust: model {} {}", args.model, model.version());
+    let input = File::open(&args.input).unwrap_or_else(|e| {
+        eprintln!("disrust: open {}: {e}", args.input.display());
+        std::process::exit(1);
+    });
+    let output = File::create(&args.output).unwrap_or_else(|e| {
+        eprintln!("disrust: create {}: {e}", args.output.display());
+        std::process::exit(1);
+    });
+
+    OrtBackend::init();
+    let (numerics, max_batch_slots) = if args.deterministic {
+        (Numerics::Deterministic, 1)
+    } else {
+        (Numerics::Fast, args.max_batch_slots)
+    };
+    let backend = OrtBackend::with_numerics(&model.bytes, SESSION_POOL_SIZE, numerics)
+        .with_feature_dim(args.feature_dim);
+    let mut builder = EngineBuilder::new(backend)
+        .with_max_batch_slots(max_batch_slots)
+        .with_batch_coalesce(Duration::from_micros(args.batch_coalesce_us));
+    if let Some(path) = &args.calibration {
+        let calibration = Calibration::load(path).unwrap_or_else(|e| {
+            eprintln!("disrust: --calibration: {e}");
+            std::process::exit(1);
+        });
+        eprintln!("disrust: calibration {} ({calibration})", path.display());
+        builder = builder.with_calibration(calibration);
+    }
+    let engine = builder.build();
+
+    let started = Instant::now();
+    let summary = score_stream(
+        &engine,
+        input,
+        BufWriter::new(output),
+        SCORE_CHUNK_REQUESTS,
+        args.vector_status,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("disrust: score {}: {e}", args.input.display());
+        std::process::exit(1);
+    });
+    let secs = started.elapsed().as_secs_f64();
+    eprintln!(
+        "disrust: scored {} requests ({} vectors) in {secs:.3}s, {:.0} req/s",
+        summary.requests,
+        summary.vectors,
+        summary.requests as f64 / secs.max(f64::EPSILON),
+    );
+}
+
+/// Score every request in `input`, vectors of the engine's feature dim, writing responses to
+/// `output`, each followed by its vector status when `vector_status` is set.
+///
+/// Requests are submitted `chunk_requests` at a time, and each chunk is submitted before the
+/// previous one's results are written, so parsing overlaps inference. A malformed or truncated
+/// request fails the stream with [`io::ErrorKind::InvalidData`] after the responses before it
+/// are written.
+pub fn score_stream(
+    engine: &Engine,
+    mut input: impl Read,
+    mut output: impl Write,
+    chunk_requests: usize,
+    vector_status: bool,
+) -> io::Result<ScoreSummary> {
+    assert!(chunk_requests > 0, "chunk_requests must be > 0");
+    let framing = RequestFraming::PLAIN.with_feature_dim(engine.feature_dim());
+    let mut summary = ScoreSummary::default();
+    let mut unparsed = Vec::new();
+    let mut read_buf = vec![0u8; READ_CHUNK_BYTES];
+    let mut parsed_bytes = 0u64;
+    let mut features = Vec::new();
+    let mut bounds: Vec<Range<usize>> = Vec::new();
+    // Vectors with non-finite features, per request in `bounds`.
+    let mut invalid_vectors = Vec::new();
+    let mut in_flight = None;
+    let mut eof = false;
+
+    let failure = loop {
+        let mut pos = 0;
+        let mut parse_error = None;
+        while bounds.len() < chunk_requests {
+            match protocol::try_parse_request(&unparsed[pos..], framing) {
+                ParseResult::Complete {
+                    num_vectors,
+                    bytes_consumed,
+                    ..
+                } => {
+                    let start = features.len();
+                    let feature_bytes = &unparsed[pos + REQUEST_HEADER_BYTES..pos + bytes_consumed];
+                    features.resize(start + num_vectors as usize * framing.feature_dim, 0.0);
+                    protocol::copy_features(feature_bytes, &mut features[start..]);
+                    if vector_status {
+                        invalid_vectors.push(protocol::non_finite_vectors(
+                            feature_bytes,
+                            framing.feature_dim,
+                        ));
+                    }
+                    bounds.push(start..features.len());
+                    pos += bytes_consumed;
+                }
+                ParseResult::Incomplete(_) => break,
+                ParseResult::Error(e) => {
+                    let e = e.at(parsed_bytes + pos as u64);
+                    parse_error = Some(invalid_data(format!("malformed request: {e}")));
+                    break;
+                }
+            }
+        }
+        unparsed.drain(..pos);
+        parsed_bytes += pos as u64;
+
+        if !bounds.is_empty() && (bounds.len() == chunk_requests || eof || parse_error.is_some()) {
+            let requests: Vec<&[f32]> = bounds.iter().map(|r| &features[r.clone()]).collect();
+            let pending = engine
+                .submit_batch(&requests)
+                .expect("parsed requests have valid feature counts");
+            features.clear();
+            bounds.clear();
+            let chunk = (pending, std::mem::take(&mut invalid_vectors));
+            if let Some((previous, invalid_vectors)) = in_flight.replace(chunk) {
+                write_responses(
+                    previous.wait(),
+                    vector_status.then_some(invalid_vectors.as_slice()),
+                    &mut output,
+                    &mut summary,
+                )?;
+            }
+            if parse_error.is_none() {
+                continue;
+            }
+        }
+        if let Some(err) = parse_error {
+            break Some(err);
+        }
+        if eof {
+            break (!unparsed.is_empty())
+                .then(|| invalid_data(format!("truncated request at byte {parsed_bytes}")));
+        }
+        match input.read(&mut read_buf)? {
+            0 => eof = true,
+            n => unparsed.extend_from_slice(&read_buf[..n]),
+        }
+    };
+
+    if let Some((pending, invalid_vectors)) = in_flight {
+        write_responses(
+            pending.wait(),
+            vector_status.then_some(invalid_vectors.as_slice()),
+            &mut output,
+            &mut summary,
+        )?;
+    }
+    output.flush()?;
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(summary),
+    }
+}
+
+/// Write one response per result, followed by its vector status when `invalid_vectors` holds
+/// each request's mask.
+fn write_responses(
+    results: Result<Vec<Vec<f32>>, EngineError>,
+    invalid_vectors: Option<&[u64]>,
+    output: &mut impl Write,
+    summary: &mut ScoreSummary,
+) -> io::Result<()> {
+    let results = results.map_err(io::Error::other)?;
+    let mut frame = Vec::new();
+    for (i, result) in results.into_iter().enumerate() {
+        let len = protocol::response_size(result.len());
+        frame.resize(len, 0);
+        protocol::encode_response(&result, &mut frame);
+        if let Some(invalid_vectors) = invalid_vectors {
+            frame.resize(len + protocol::vector_status_size(result.len()), 0);
+            protocol::encode_vector_status(&result, invalid_vectors[i], &mut frame[len..]);
+        }
+        output.write_all(&frame)?;
+        summary.requests += 1;
+        summary.vectors += result.len() as u64;
+    }
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use super::{ScoreSummary, score_stream};
+    use crate::constants::FEATURE_DIM;
+    use crate::engine::EngineBuilder;
+    use crate::engine::tests::SumBackend;
+    use crate::protocol::{self, VectorStatus};
+
+    fn request_bytes(num_vectors: u32, value: f32) -> Vec<u8> {
+        request_bytes_of_dim(num_vectors, value, FEATURE_DIM)
+    }
+
+    fn request_bytes_of_dim(num_vectors: u32, value: f32, feature_dim: usize) -> Vec<u8> {
+        let mut bytes = num_vectors.to_le_bytes().to_vec();
+        for _ in 0..num_vectors as usize * feature_dim {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn scores_requests_in_file_order_across_chunks() {
+        let engine = EngineBuilder::new(SumBackend::new())
+            .with_ring_size(64)
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        let input: Vec<u8> = (0..25)
+            .flat_map(|i| request_bytes(i % 3 + 1, i as f32))
+            .collect();
+        let mut expected = Vec::new();
+        for i in 0..25u32 {
+            let results = vec![(i as usize * FEATURE_DIM) as f32; i as usize % 3 + 1];
+            let mut frame = vec![0u8; protocol::response_size(results.len())];
+            protocol::encode_response(&results, &mut frame);
+            expected.extend_from_slice(&frame);
+        }
+
+        let mut output = Vec::new();
+        let summary = score_stream(&engine, input.as_slice(), &mut output, 4, false).unwrap();
+        assert_eq!(
+            summary,
+            ScoreSummary {
+                requests: 25,
+                vectors: (0..25).map(|i| i % 3 + 1).sum()
+            }
+        );
+        assert_eq!(output, expected);
+
+        let mut truncated = request_bytes(1, 1.0);
+        truncated.extend_from_slice(&request_bytes(2, 2.0)[..10]);
+        let mut output = Vec::new();
+        let err = score_stream(&engine, truncated.as_slice(), &mut output, 4, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            output.len(),
+            protocol::response_size(1),
+            "earlier responses kept"
+        );
+    }
+
+    #[test]
+    fn reads_requests_of_the_engine_feature_dim() {
+        let engine = EngineBuilder::new(SumBackend::new().with_feature_dim(3))
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        let mut input = request_bytes_of_dim(2, 1.0, 3);
+        input.extend_from_slice(&request_bytes_of_dim(1, 2.0, 3));
+
+        let mut output = Vec::new();
+        let summary = score_stream(&engine, input.as_slice(), &mut output, 4, false).unwrap();
+        assert_eq!(
+            summary,
+            ScoreSummary {
+                requests: 2,
+                vectors: 3
+            }
+        );
+        let mut expected = vec![0u8; protocol::response_size(2) + protocol::response_size(1)];
+        let (first, second) = expected.split_at_mut(protocol::response_size(2));
+        protocol::encode_response(&[3.0, 3.0], first);
+        protocol::encode_response(&[6.0], second);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn vector_status_follows_each_response() {
+        let engine = EngineBuilder::new(SumBackend::new())
+            .with_batch_coalesce(Duration::ZERO)
+            .build();
+        let mut input = request_bytes(1, 1.0);
+        // Two vectors: NaN features, then finite features whose sum overflows to infinity.
+        input.extend_from_slice(&2u32.to_le_bytes());
+        for _ in 0..FEATURE_DIM {
+            input.extend_from_slice(&f32::NAN.to_le_bytes());
+        }
+        for _ in 0..FEATURE_DIM {
+            input.extend_from_slice(&f32::MAX.to_le_bytes());
+        }
+
+        let mut output = Vec::new();
+        score_stream(&engine, input.as_slice(), &mut output, 4, true).unwrap();
+        let first = protocol::response_size(1);
+        assert_eq!(
+            protocol::decode_vector_status(&output[first..first + 1]),
+            vec![Some(VectorStatus::Ok)]
+        );
+        let second = first + 1 + protocol::response_size(2);
+        assert_eq!(output.len(), second + 2);
+        assert_eq!(
+            protocol::decode_vector_status(&output[second..]),
+            vec![
+                Some(VectorStatus::InvalidInput),
+                Some(VectorStatus::ModelError)
+            ]
+        );
+    }
+}
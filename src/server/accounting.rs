@@ -0,0 +1,149 @@
+//! Exactly-once response accounting for debug builds.
+//!
+//! Each connection counts requests published, responses that reached it from the response queue
+//! (or were generated locally, like overload frames), and frames written or dropped. Responses
+//! for one connection arrive in request order, so a lost response shows up as a gap in sequence
+//! numbers and a repeated one as a duplicate. When the connection is reaped the counts are
+//! checked and the outcome is added to its IO thread's `IoThreadControl`, which the admin
+//! `accounting` command reports.
+//!
+//! A client may close with requests still in flight; those responses are counted as abandoned,
+//! not as violations.
+
+/// Accounting runs only in debug builds; release builds skip the bookkeeping and the check.
+pub const ENABLED: bool = cfg!(debug_assertions);
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct ResponseAccounting {
+    /// Responses queued for writing, including locally generated overload frames.
+    enqueued: u64,
+    /// Responses that arrived after the connection stopped writing.
+    discarded: u64,
+    written: u64,
+    /// Queued frames thrown away when the connection was aborted.
+    dropped: u64,
+    /// Frames answering no request of this connection: a replay reply and the frames it resent.
+    unnumbered: u64,
+    duplicates: u64,
+    gaps: u64,
+}
+
+/// Outcome of checking one connection at close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCheck {
+    Clean,
+    /// Closed with this many published requests still unanswered.
+    Abandoned(u64),
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+impl ResponseAccounting {
+    /// A response for `request_seq` was queued while `expected_seq` was next in order.
+    pub(crate) fn on_response(&mut self, request_seq: u64, expected_seq: u64) {
+        if !ENABLED {
+            return;
+        }
+        self.enqueued += 1;
+        if request_seq < expected_seq {
+            self.duplicates += 1;
+        } else if request_seq > expected_seq {
+            self.gaps += 1;
+        }
+    }
+
+    /// A locally generated frame was queued; its order is enforced by the deferred queue.
+    pub(crate) fn on_local_frame(&mut self) {
+        if ENABLED {
+            self.enqueued += 1;
+        }
+    }
+
+    /// A frame answering none of this connection's requests was queued.
+    pub(crate) fn on_unnumbered_frame(&mut self) {
+        if ENABLED {
+            self.unnumbered += 1;
+        }
+    }
+
+    pub(crate) fn on_discarded(&mut self) {
+        if ENABLED {
+            self.discarded += 1;
+        }
+    }
+
+    pub(crate) fn on_written(&mut self, frames: u64) {
+        if ENABLED {
+            self.written += frames;
+        }
+    }
+
+    pub(crate) fn on_dropped(&mut self, frames: u64) {
+        if ENABLED {
+            self.dropped += frames;
+        }
+    }
+
+    /// Check the counts of a connection with nothing left queued, after `published` requests.
+    pub(crate) fn check(&self, published: u64) -> Result<CloseCheck, String> {
+        let answered = self.enqueued + self.discarded;
+        if self.duplicates > 0 || self.gaps > 0 {
+            return Err(format!(
+                "{} duplicate and {} out-of-order responses",
+                self.duplicates, self.gaps
+            ));
+        }
+        if answered > published {
+            return Err(format!(
+                "{answered} responses for {published} published requests"
+            ));
+        }
+        if self.written + self.dropped != self.enqueued + self.unnumbered {
+            return Err(format!(
+                "{} responses and {} other frames enqueued but {} written and {} dropped",
+                self.enqueued, self.unnumbered, self.written, self.dropped
+            ));
+        }
+        Ok(match published - answered {
+            0 => CloseCheck::Clean,
+            abandoned => CloseCheck::Abandoned(abandoned),
+        })
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::{CloseCheck, ResponseAccounting};
+
+    #[test]
+    fn balanced_connection_checks_clean() {
+        let mut accounting = ResponseAccounting::default();
+        accounting.on_response(0, 0);
+        accounting.on_local_frame();
+        accounting.on_response(2, 2);
+        accounting.on_written(3);
+        assert_eq!(accounting.check(3), Ok(CloseCheck::Clean));
+        assert_eq!(accounting.check(5), Ok(CloseCheck::Abandoned(2)));
+    }
+
+    #[test]
+    fn lost_duplicate_and_unwritten_responses_are_violations() {
+        let mut lost = ResponseAccounting::default();
+        lost.on_response(0, 0);
+        lost.on_response(2, 1);
+        lost.on_written(2);
+        assert!(lost.check(3).is_err());
+
+        let mut duplicated = ResponseAccounting::default();
+        duplicated.on_response(0, 0);
+        duplicated.on_response(0, 1);
+        duplicated.on_written(2);
+        assert!(duplicated.check(2).is_err());
+
+        let mut unwritten = ResponseAccounting::default();
+        unwritten.on_response(0, 0);
+        unwritten.on_response(1, 1);
+        unwritten.on_written(1);
+        assert!(unwritten.check(2).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+//! Line-oriented admin socket, served from the control-plane thread.
+//!
+//! Listens on a Unix domain socket and accepts one command per line:
+//!
+//! - `health` — `ok healthy running=<n>` while at least one IO thread is accepting connections
+//!   and the model passed its canary, if one was configured
+//! - `canary` — the canary outcome: `ok canary passed outputs=<...>` or `err canary failed: ...`
+//! - `status` — one line per IO thread: `io-<id> <state> connections=<n>`
+//! - `connections` — one line per open connection: `io-<id> conn=<conn_id> read_size=<bytes>
+//!   bytes_per_read=<bytes> buffered=<bytes> requests=<n> in_flight=<n>`, where `read_size` is
+//!   what its next socket read asks for (see `--adaptive-reads`) and `in_flight` counts requests
+//!   still waiting for their scores; `io-<id> no answer` for a thread too busy to list its
+//!   connections in time
+//! - `drain <id>` — stop accepts on one IO thread and let its connections finish; poll `status`
+//!   until it reports `drained`
+//! - `add` — start a new IO thread in the lowest free shard slot
+//! - `remove <id>` — drain one IO thread, then stop it and free its shard slot
+//! - `drift` — with `--drift-stats`, one line per feature: `f<i> count=<n> mean=<m> std=<s>
+//!   min=<min> max=<max>` over every vector scored so far
+//! - `reload-model` — load `--model` again on a side thread and swap it in once it passes
+//!   `--canary`, if given; the reply only confirms the load started, the log and the metrics
+//!   `model` line report how it went
+//! - `accounting` — debug builds only: per IO thread, closed connections whose responses
+//!   balanced (`clean`), closed with responses in flight (`abandoned`), or lost or repeated a
+//!   response (`violations`)
+//!
+//! Every command answers with one or more lines; errors start with `err `.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::canary::CanaryOutcome;
+use crate::pipeline::drift::DriftMonitor;
+use crate::server::accounting;
+use crate::server::control::{IoThreadSet, IoThreadState};
+use crate::server::model_reload::ModelReloader;
+
+/// How long `connections` waits for each IO thread to list its connections.
+const LISTING_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long one admin client may sit idle before it is disconnected, so a stuck client cannot
+/// hold up the rest of the control plane.
+const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminCommand {
+    Health,
+    Canary,
+    Status,
+    Connections,
+    Drain(usize),
+    Add,
+    Remove(usize),
+    Accounting,
+    Drift,
+    ReloadModel,
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("health"), None) => AdminCommand::Health,
+            (Some("canary"), None) => AdminCommand::Canary,
+            (Some("status"), None) => AdminCommand::Status,
+            (Some("connections"), None) => AdminCommand::Connections,
+            (Some("add"), None) => AdminCommand::Add,
+            (Some("accounting"), None) => AdminCommand::Accounting,
+            (Some("drift"), None) => AdminCommand::Drift,
+            (Some("reload-model"), None) => AdminCommand::ReloadModel,
+            (Some("drain"), Some(id)) => AdminCommand::Drain(parse_thread_id(id)?),
+            (Some("remove"), Some(id)) => AdminCommand::Remove(parse_thread_id(id)?),
+            (Some(command @ ("drain" | "remove")), None) => {
+                return Err(format!("usage: {command} <io-thread-id>"));
+            }
+            (Some(other), _) => return Err(format!("unknown command '{other}'")),
+            (None, _) => return Err("empty command".to_string()),
+        };
+        if words.next().is_some() {
+            return Err("unexpected trailing arguments".to_string());
+        }
+        Ok(command)
+    }
+}
+
+fn parse_thread_id(id: &str) -> Result<usize, String> {
+    id.parse()
+        .map_err(|_| format!("invalid io thread id '{id}'"))
+}
+
+/// Apply `command` against the IO threads and write the reply to `out`. `canary` is the model's
+/// canary outcome, if one was run, `drift` the input statistics, if collected, and `model` what
+/// reloads the model, if it can be.
+pub fn execute(
+    command: AdminCommand,
+    threads: &IoThreadSet,
+    canary: Option<&CanaryOutcome>,
+    drift: Option<&DriftMonitor>,
+    model: Option<&ModelReloader>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let result = match command {
+        AdminCommand::Health => {
+            let running = threads
+                .status()
+                .iter()
+                .filter(|(_, state, _)| *state == IoThreadState::Running)
+                .count();
+            if let Some(outcome @ CanaryOutcome::Failed { .. }) = canary {
+                Err(format!("unhealthy: canary {outcome}"))
+            } else if running > 0 {
+                Ok(format!("healthy running={running}"))
+            } else {
+                Err("unhealthy: no io thread is accepting connections".to_string())
+            }
+        }
+        AdminCommand::Canary => match canary {
+            Some(outcome @ CanaryOutcome::Passed { .. }) => Ok(format!("canary {outcome}")),
+            Some(outcome) => Err(format!("canary {outcome}")),
+            None => Err("no canary configured".to_string()),
+        },
+        AdminCommand::Status => {
+            for (thread_id, state, connections) in threads.status() {
+                writeln!(
+                    out,
+                    "io-{thread_id} {} connections={connections}",
+                    state.as_str()
+                )?;
+            }
+            return Ok(());
+        }
+        AdminCommand::Connections => {
+            for (thread_id, listing) in threads.connections(LISTING_TIMEOUT) {
+                let Some(connections) = listing else {
+                    writeln!(out, "io-{thread_id} no answer")?;
+                    continue;
+                };
+                for conn in connections {
+                    writeln!(
+                        out,
+                        "io-{thread_id} conn={} read_size={} bytes_per_read={} buffered={} requests={} in_flight={}",
+                        conn.conn_id,
+                        conn.read_size,
+                        conn.bytes_per_read,
+                        conn.buffered,
+                        conn.requests,
+                        conn.in_flight
+                    )?;
+                }
+            }
+            return Ok(());
+        }
+        AdminCommand::Accounting if !accounting::ENABLED => {
+            Err("response accounting needs a debug build".to_string())
+        }
+        AdminCommand::Accounting => {
+            for (thread_id, counts) in threads.accounting() {
+                writeln!(
+                    out,
+                    "io-{thread_id} clean={} abandoned={} violations={}",
+                    counts.clean, counts.abandoned, counts.violations
+                )?;
+            }
+            return Ok(());
+        }
+        AdminCommand::Drift => match drift {
+            Some(monitor) => {
+                let stats = monitor.snapshot();
+                let moments = &stats.moments;
+                for i in 0..stats.feature_dim() {
+                    let (min, max) = match moments.count {
+                        0 => (0.0, 0.0),
+                        _ => (stats.min[i], stats.max[i]),
+                    };
+                    writeln!(
+                        out,
+                        "f{i} count={} mean={} std={} min={min} max={max}",
+                        moments.count,
+                        moments.mean[i],
+                        moments.std_dev(i),
+                    )?;
+                }
+                return Ok(());
+            }
+            None => Err("drift statistics are off; start with --drift-stats".to_string()),
+        },
+        AdminCommand::Drain(thread_id) => threads
+            .drain(thread_id)
+            .map(|()| format!("io-{thread_id} draining")),
+        AdminCommand::Add => threads
+            .add()
+            .map(|thread_id| format!("io-{thread_id} running")),
+        AdminCommand::Remove(thread_id) => threads
+            .remove(thread_id)
+            .map(|()| format!("io-{thread_id} removing")),
+        AdminCommand::ReloadModel => match model {
+            Some(reloader) => reloader
+                .start()
+                .map(|()| format!("reloading {}", reloader.model().display())),
+            None => Err("model reload is unavailable".to_string()),
+        },
+    };
+    match result {
+        Ok(reply) => writeln!(out, "ok {reply}"),
+        Err(e) => writeln!(out, "err {e}"),
+    }
+}
+
+/// Bind the admin socket at `path`, replacing a stale socket file left by a previous run.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
+
+/// Answer commands from one client until it disconnects or idles past `CLIENT_IDLE_TIMEOUT`.
+pub(crate) fn serve_client(
+    stream: UnixStream,
+    threads: &IoThreadSet,
+    canary: Option<&CanaryOutcome>,
+    drift: Option<&DriftMonitor>,
+    model: Option<&ModelReloader>,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match AdminCommand::parse(&line) {
+            Ok(command) => execute(command, threads, canary, drift, model, &mut out)?,
+            Err(e) => writeln!(out, "err {e}")?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{AdminCommand, execute};
+    use crate::canary::CanaryOutcome;
+    use crate::constants::FEATURE_DIM;
+    use crate::pipeline::drift::{DriftMonitor, DriftStats};
+    use crate::server::control::{ConnectionInfo, IoThreadSet};
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(AdminCommand::parse("health"), Ok(AdminCommand::Health));
+        assert_eq!(AdminCommand::parse("status"), Ok(AdminCommand::Status));
+        assert_eq!(
+            AdminCommand::parse("connections"),
+            Ok(AdminCommand::Connections)
+        );
+        assert_eq!(AdminCommand::parse("canary"), Ok(AdminCommand::Canary));
+        assert_eq!(AdminCommand::parse(" drain 2 "), Ok(AdminCommand::Drain(2)));
+        assert_eq!(AdminCommand::parse("add"), Ok(AdminCommand::Add));
+        assert_eq!(
+            AdminCommand::parse("accounting"),
+            Ok(AdminCommand::Accounting)
+        );
+        assert_eq!(AdminCommand::parse("remove 3"), Ok(AdminCommand::Remove(3)));
+        assert_eq!(AdminCommand::parse("drift"), Ok(AdminCommand::Drift));
+        assert_eq!(
+            AdminCommand::parse("reload-model"),
+            Ok(AdminCommand::ReloadModel)
+        );
+        assert!(AdminCommand::parse("drain").is_err());
+        assert!(AdminCommand::parse("remove").is_err());
+        assert!(AdminCommand::parse("drain x").is_err());
+        assert!(AdminCommand::parse("drain 1 2").is_err());
+        assert!(AdminCommand::parse("reload").is_err());
+    }
+
+    #[test]
+    fn drain_targets_one_thread() {
+        let threads = IoThreadSet::new(2, Box::new(|_, _| Ok(())));
+        let mut out = Vec::new();
+        execute(AdminCommand::Add, &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Add, &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Drain(1), &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Drain(1), &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Drain(5), &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Status, &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Health, &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Drain(0), &threads, None, None, None, &mut out).unwrap();
+        execute(AdminCommand::Health, &threads, None, None, None, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ok io-0 running\n\
+             ok io-1 running\n\
+             ok io-1 draining\n\
+             err io-1 already draining\n\
+             err no io thread 5\n\
+             io-0 running connections=0\n\
+             io-1 draining connections=0\n\
+             ok healthy running=1\n\
+             ok io-0 draining\n\
+             err unhealthy: no io thread is accepting connections\n"
+        );
+    }
+
+    #[test]
+    fn connections_lists_what_each_io_thread_answers() {
+        // Thread 0 answers every listing request; thread 1 never does.
+        let threads = IoThreadSet::new(
+            2,
+            Box::new(|thread_id, control| {
+                if thread_id == 0 {
+                    thread::spawn(move || {
+                        while !control.take_listing_request() {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        control.publish_connections(vec![ConnectionInfo {
+                            conn_id: 3,
+                            read_size: 4096,
+                            bytes_per_read: 120,
+                            buffered: 7,
+                            requests: 12,
+                            in_flight: 2,
+                        }]);
+                    });
+                }
+                Ok(())
+            }),
+        );
+        threads.add().unwrap();
+        threads.add().unwrap();
+        let mut out = Vec::new();
+        execute(
+            AdminCommand::Connections,
+            &threads,
+            None,
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "io-0 conn=3 read_size=4096 bytes_per_read=120 buffered=7 requests=12 in_flight=2\n\
+             io-1 no answer\n"
+        );
+    }
+
+    #[test]
+    fn failed_canary_keeps_the_server_unhealthy() {
+        let threads = IoThreadSet::new(1, Box::new(|_, _| Ok(())));
+        let passed = CanaryOutcome::Passed {
+            outputs: vec![0.25, 0.5],
+        };
+        let failed = CanaryOutcome::Failed {
+            reason: "output 0 is NaN, outside 0..=1".to_string(),
+        };
+        let mut out = Vec::new();
+        execute(AdminCommand::Canary, &threads, None, None, None, &mut out).unwrap();
+        execute(
+            AdminCommand::Add,
+            &threads,
+            Some(&passed),
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        execute(
+            AdminCommand::Canary,
+            &threads,
+            Some(&passed),
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        execute(
+            AdminCommand::Health,
+            &threads,
+            Some(&passed),
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        execute(
+            AdminCommand::Canary,
+            &threads,
+            Some(&failed),
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        execute(
+            AdminCommand::Health,
+            &threads,
+            Some(&failed),
+            None,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "err no canary configured\n\
+             ok io-0 running\n\
+             ok canary passed outputs=0.25,0.5\n\
+             ok healthy running=1\n\
+             err canary failed: output 0 is NaN, outside 0..=1\n\
+             err unhealthy: canary failed: output 0 is NaN, outside 0..=1\n"
+        );
+    }
+
+    #[test]
+    fn drift_reports_statistics_per_feature() {
+        let threads = IoThreadSet::new(1, Box::new(|_, _| Ok(())));
+        let monitor = DriftMonitor::new(FEATURE_DIM);
+        let mut stats = DriftStats::new(FEATURE_DIM);
+        let features: Vec<f32> = [1.0, 3.0]
+            .iter()
+            .flat_map(|&value| [value; FEATURE_DIM])
+            .collect();
+        stats.record(&features);
+        monitor.publish(&stats);
+
+        let mut out = Vec::new();
+        execute(AdminCommand::Drift, &threads, None, None, None, &mut out).unwrap();
+        execute(
+            AdminCommand::Drift,
+            &threads,
+            None,
+            Some(&monitor),
+            None,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next(),
+            Some("err drift statistics are off; start with --drift-stats")
+        );
+        assert_eq!(
+            lines.next(),
+            Some(format!("f0 count=2 mean=2 std={} min=1 max=3", 2f64.sqrt()).as_str())
+        );
+        assert_eq!(lines.count(), FEATURE_DIM - 1);
+    }
+}
@@ -0,0 +1,141 @@
+//! Provided buffer rings for socket reads.
+//!
+//! With `--read-buffer-ring N`, each IO thread registers `N` buffers of
+//! [`PROVIDED_READ_BUF_SIZE`] bytes with its data ring (`IORING_REGISTER_PBUF_RING`). A
+//! connection with nothing buffered reads with buffer selection, so it holds no read buffer while
+//! it waits and the kernel picks one of the shared buffers only once bytes arrive. The IO thread
+//! copies those bytes into a connection read buffer taken from a small per-thread spare list and
+//! hands the provided buffer straight back. A connection keeps its read buffer only while it
+//! holds the start of a frame, reading the rest directly into it; once everything it read is
+//! parsed, the buffer goes back to the spare list.
+//!
+//! A read that finds every provided buffer taken fails with `ENOBUFS` without consuming
+//! anything; it is retried into a connection buffer of its own.
+
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use io_uring::Submitter;
+use io_uring::types::BufRingEntry;
+
+use crate::config::{PROVIDED_READ_BUF_SIZE, READ_BUF_SIZE, SPARE_READ_BUFS};
+
+/// Buffer group id the IO thread's reads select from.
+const READ_BUF_GROUP: u16 = 0;
+
+/// One IO thread's provided read buffers and spare connection read buffers.
+pub(crate) struct ReadBufferRing {
+    /// `entries` ring slots, shared with the kernel.
+    ring: *mut BufRingEntry,
+    ring_bytes: usize,
+    entries: u16,
+    /// Our copy of the ring tail; the kernel reads the published one.
+    tail: u16,
+    buffers: Box<[u8]>,
+    /// Connection read buffers of `READ_BUF_SIZE`, kept for reuse.
+    spare: Vec<Box<[u8]>>,
+}
+
+impl ReadBufferRing {
+    /// Register `entries` buffers, a power of two up to 32768, with the ring `submitter` belongs
+    /// to.
+    pub(crate) fn register(submitter: &Submitter<'_>, entries: u16) -> io::Result<Self> {
+        assert!(
+            entries.is_power_of_two() && entries <= 1 << 15,
+            "read buffer ring entries must be a power of two up to 32768"
+        );
+        let ring_bytes = entries as usize * size_of::<BufRingEntry>();
+        // The ring must be page aligned, which an anonymous mapping is.
+        let ring = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                ring_bytes,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ring == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buffers = Self {
+            ring: ring.cast(),
+            ring_bytes,
+            entries,
+            tail: 0,
+            buffers: vec![0u8; entries as usize * PROVIDED_READ_BUF_SIZE].into_boxed_slice(),
+            spare: Vec::new(),
+        };
+        // SAFETY: the ring stays mapped until drop, after the io_uring it is registered with
+        // has gone.
+        unsafe {
+            submitter.register_buf_ring_with_flags(ring as u64, entries, READ_BUF_GROUP, 0)?;
+        }
+        for bid in 0..entries {
+            buffers.push(bid);
+        }
+        buffers.publish();
+        Ok(buffers)
+    }
+
+    pub(crate) fn group(&self) -> u16 {
+        READ_BUF_GROUP
+    }
+
+    /// Bytes a read selecting a buffer may return.
+    pub(crate) fn buf_size(&self) -> u32 {
+        PROVIDED_READ_BUF_SIZE as u32
+    }
+
+    /// The first `len` bytes of buffer `bid`.
+    pub(crate) fn buffer(&self, bid: u16, len: usize) -> &[u8] {
+        let start = bid as usize * PROVIDED_READ_BUF_SIZE;
+        &self.buffers[start..start + len.min(PROVIDED_READ_BUF_SIZE)]
+    }
+
+    /// Give buffer `bid` back to the kernel once its bytes are copied out.
+    pub(crate) fn recycle(&mut self, bid: u16) {
+        self.push(bid);
+        self.publish();
+    }
+
+    fn push(&mut self, bid: u16) {
+        let slot = (self.tail & (self.entries - 1)) as usize;
+        let start = bid as usize * PROVIDED_READ_BUF_SIZE;
+        // SAFETY: `slot < entries`; the kernel only reads slots before the published tail.
+        let entry = unsafe { &mut *self.ring.add(slot) };
+        entry.set_addr(self.buffers[start..].as_ptr() as u64);
+        entry.set_len(PROVIDED_READ_BUF_SIZE as u32);
+        entry.set_bid(bid);
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    fn publish(&self) {
+        // SAFETY: the tail shares the first entry's reserved field; the kernel reads it with
+        // acquire ordering.
+        let tail = unsafe { &*(BufRingEntry::tail(self.ring) as *const AtomicU16) };
+        tail.store(self.tail, Ordering::Release);
+    }
+
+    /// A connection read buffer, reused if one is spare.
+    pub(crate) fn take_read_buf(&mut self) -> Box<[u8]> {
+        self.spare
+            .pop()
+            .unwrap_or_else(|| vec![0u8; READ_BUF_SIZE].into_boxed_slice())
+    }
+
+    /// Keep a connection's read buffer for reuse, up to `SPARE_READ_BUFS` of them.
+    pub(crate) fn put_read_buf(&mut self, buf: Box<[u8]>) {
+        if !buf.is_empty() && self.spare.len() < SPARE_READ_BUFS {
+            self.spare.push(buf);
+        }
+    }
+}
+
+impl Drop for ReadBufferRing {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ring.cast(), self.ring_bytes) };
+    }
+}
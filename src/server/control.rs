@@ -0,0 +1,538 @@
+//! Out-of-band control of a running IO thread.
+//!
+//! Each `IngressThread` owns one `IoThreadControl`. Other threads (the admin socket) flip the
+//! requested state and kick the eventfd; the IO thread polls that eventfd on its ring, applies
+//! the request on its own loop, and publishes the resulting state back.
+//!
+//! `IoThreadSet` tracks the controls of every shard slot so IO threads can be added and removed
+//! at runtime. A removed thread drains, exits, and leaves its slot free for a later `add`.
+
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::notify::NotifyFd;
+use crate::server::accounting::CloseCheck;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IoThreadState {
+    /// Accepting new connections.
+    Running = 0,
+    /// Accepts stopped; waiting for existing connections to close.
+    Draining = 1,
+    /// Accepts stopped and no connections remain.
+    Drained = 2,
+    /// Removed: the thread has exited and its shard slot can be reused.
+    Stopped = 3,
+}
+
+impl IoThreadState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => IoThreadState::Running,
+            1 => IoThreadState::Draining,
+            2 => IoThreadState::Drained,
+            _ => IoThreadState::Stopped,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IoThreadState::Running => "running",
+            IoThreadState::Draining => "draining",
+            IoThreadState::Drained => "drained",
+            IoThreadState::Stopped => "stopped",
+        }
+    }
+}
+
+/// Connections checked by response accounting on one IO thread, by outcome (cumulative).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccountingCounts {
+    pub clean: u64,
+    /// Closed by the client with responses still in flight.
+    pub abandoned: u64,
+    pub violations: u64,
+}
+
+/// Upper bounds of the frames-per-read buckets; reads past the last bound share one more bucket.
+pub const FRAMES_PER_READ_BOUNDS: [u64; 6] = [0, 1, 2, 4, 8, 16];
+
+const FRAMES_PER_READ_BUCKETS: usize = FRAMES_PER_READ_BOUNDS.len() + 1;
+
+/// Complete frames parsed from each socket read on one IO thread (cumulative).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadFrameCounts {
+    pub reads: u64,
+    pub frames: u64,
+    /// Reads whose bytes ended partway through a frame.
+    pub partial: u64,
+    /// Reads per frames-per-read bucket, by [`FRAMES_PER_READ_BOUNDS`].
+    pub buckets: [u64; FRAMES_PER_READ_BUCKETS],
+}
+
+impl ReadFrameCounts {
+    pub fn saturating_sub(self, earlier: Self) -> Self {
+        Self {
+            reads: self.reads.saturating_sub(earlier.reads),
+            frames: self.frames.saturating_sub(earlier.frames),
+            partial: self.partial.saturating_sub(earlier.partial),
+            buckets: std::array::from_fn(|i| self.buckets[i].saturating_sub(earlier.buckets[i])),
+        }
+    }
+}
+
+/// One connection as the admin `connections` command lists it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub conn_id: u16,
+    /// Bytes the connection's next read asks for.
+    pub read_size: usize,
+    /// Recent bytes per read.
+    pub bytes_per_read: usize,
+    /// Request bytes read but not yet parsed.
+    pub buffered: usize,
+    /// Requests read so far.
+    pub requests: u64,
+    /// Requests read whose response has not reached the connection yet.
+    pub in_flight: u64,
+}
+
+pub struct IoThreadControl {
+    state: AtomicU8,
+    remove: AtomicBool,
+    shutdown: AtomicBool,
+    connections: AtomicUsize,
+    accounting_clean: AtomicU64,
+    accounting_abandoned: AtomicU64,
+    accounting_violations: AtomicU64,
+    read_frames: AtomicU64,
+    partial_reads: AtomicU64,
+    frames_per_read: [AtomicU64; FRAMES_PER_READ_BUCKETS],
+    listing_requested: AtomicBool,
+    /// The IO thread's answer to the last listing request.
+    listing: Mutex<Option<Vec<ConnectionInfo>>>,
+    notify: NotifyFd,
+}
+
+// The IO-thread half is driven only by the Linux ingress.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+impl IoThreadControl {
+    pub fn new() -> Self {
+        let notify = NotifyFd::new().expect("notify fd creation failed");
+        Self {
+            state: AtomicU8::new(IoThreadState::Running as u8),
+            remove: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+            connections: AtomicUsize::new(0),
+            accounting_clean: AtomicU64::new(0),
+            accounting_abandoned: AtomicU64::new(0),
+            accounting_violations: AtomicU64::new(0),
+            read_frames: AtomicU64::new(0),
+            partial_reads: AtomicU64::new(0),
+            frames_per_read: std::array::from_fn(|_| AtomicU64::new(0)),
+            listing_requested: AtomicBool::new(false),
+            listing: Mutex::new(None),
+            notify,
+        }
+    }
+
+    pub fn state(&self) -> IoThreadState {
+        IoThreadState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    /// Number of live connections as last published by the IO thread.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn accounting(&self) -> AccountingCounts {
+        AccountingCounts {
+            clean: self.accounting_clean.load(Ordering::Relaxed),
+            abandoned: self.accounting_abandoned.load(Ordering::Relaxed),
+            violations: self.accounting_violations.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn read_frames(&self) -> ReadFrameCounts {
+        let buckets = std::array::from_fn(|i| self.frames_per_read[i].load(Ordering::Relaxed));
+        ReadFrameCounts {
+            reads: buckets.iter().sum(),
+            frames: self.read_frames.load(Ordering::Relaxed),
+            partial: self.partial_reads.load(Ordering::Relaxed),
+            buckets,
+        }
+    }
+
+    /// Ask the IO thread to stop accepting and drain. Returns `false` if it was already
+    /// draining or drained.
+    pub fn request_drain(&self) -> bool {
+        let requested = self
+            .state
+            .compare_exchange(
+                IoThreadState::Running as u8,
+                IoThreadState::Draining as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok();
+        if requested {
+            self.wake();
+        }
+        requested
+    }
+
+    /// Ask the IO thread to drain and then exit. Returns `false` if removal was already
+    /// requested.
+    pub fn request_remove(&self) -> bool {
+        if self.remove.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        if !self.request_drain() {
+            self.wake();
+        }
+        true
+    }
+
+    pub fn remove_requested(&self) -> bool {
+        self.remove.load(Ordering::Acquire)
+    }
+
+    /// Ask the IO thread to stop reading, close each connection once everything it sent has
+    /// been answered and written, and then exit. Returns `false` if shutdown was already
+    /// requested.
+    pub fn request_shutdown(&self) -> bool {
+        if self.shutdown.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        if !self.request_remove() {
+            self.wake();
+        }
+        true
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    /// Ask the IO thread to list its connections; collect the answer with
+    /// [`Self::take_connections`].
+    pub fn request_connections(&self) {
+        *self.listing.lock().unwrap() = None;
+        self.listing_requested.store(true, Ordering::Release);
+        self.wake();
+    }
+
+    /// The IO thread's connection listing, once it has answered.
+    pub fn take_connections(&self) -> Option<Vec<ConnectionInfo>> {
+        self.listing.lock().unwrap().take()
+    }
+
+    pub fn notify_fd(&self) -> RawFd {
+        self.notify.fd()
+    }
+
+    pub(crate) fn mark_drained(&self) {
+        self.state
+            .store(IoThreadState::Drained as u8, Ordering::Release);
+    }
+
+    pub(crate) fn mark_stopped(&self) {
+        self.state
+            .store(IoThreadState::Stopped as u8, Ordering::Release);
+    }
+
+    pub(crate) fn set_connections(&self, connections: usize) {
+        self.connections.store(connections, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_close_check(&self, check: &Result<CloseCheck, String>) {
+        let counter = match check {
+            Ok(CloseCheck::Clean) => &self.accounting_clean,
+            Ok(CloseCheck::Abandoned(_)) => &self.accounting_abandoned,
+            Err(_) => &self.accounting_violations,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether a connection listing was requested since the last call.
+    pub(crate) fn take_listing_request(&self) -> bool {
+        self.listing_requested.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn publish_connections(&self, connections: Vec<ConnectionInfo>) {
+        *self.listing.lock().unwrap() = Some(connections);
+    }
+
+    /// One socket read yielded `frames` complete frames and, if `partial`, the start of another.
+    pub(crate) fn record_read_frames(&self, frames: u64, partial: bool) {
+        let bucket = FRAMES_PER_READ_BOUNDS
+            .iter()
+            .position(|&bound| frames <= bound)
+            .unwrap_or(FRAMES_PER_READ_BOUNDS.len());
+        self.frames_per_read[bucket].fetch_add(1, Ordering::Relaxed);
+        self.read_frames.fetch_add(frames, Ordering::Relaxed);
+        if partial {
+            self.partial_reads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn wake(&self) {
+        self.notify.signal();
+    }
+}
+
+impl Default for IoThreadControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Starts the IO thread for shard `thread_id`, driven by `control`.
+pub type SpawnIoThread = Box<dyn Fn(u8, Arc<IoThreadControl>) -> Result<(), String> + Send + Sync>;
+
+/// Shard slots for the running IO threads.
+pub struct IoThreadSet {
+    slots: Mutex<Vec<Option<Arc<IoThreadControl>>>>,
+    spawn: SpawnIoThread,
+}
+
+impl IoThreadSet {
+    pub fn new(max_threads: usize, spawn: SpawnIoThread) -> Self {
+        Self {
+            slots: Mutex::new(vec![None; max_threads]),
+            spawn,
+        }
+    }
+
+    /// Start an IO thread in the lowest free shard slot and return its id.
+    pub fn add(&self) -> Result<u8, String> {
+        let mut slots = self.slots.lock().unwrap();
+        let thread_id = slots
+            .iter()
+            .position(|slot| {
+                slot.as_ref()
+                    .is_none_or(|control| control.state() == IoThreadState::Stopped)
+            })
+            .ok_or_else(|| format!("all {} io thread slots in use", slots.len()))?;
+        let control = Arc::new(IoThreadControl::new());
+        (self.spawn)(thread_id as u8, Arc::clone(&control))?;
+        slots[thread_id] = Some(control);
+        Ok(thread_id as u8)
+    }
+
+    pub fn drain(&self, thread_id: usize) -> Result<(), String> {
+        let control = self.get(thread_id)?;
+        if control.request_drain() {
+            Ok(())
+        } else {
+            Err(format!(
+                "io-{thread_id} already {}",
+                control.state().as_str()
+            ))
+        }
+    }
+
+    pub fn remove(&self, thread_id: usize) -> Result<(), String> {
+        let control = self.get(thread_id)?;
+        if control.state() != IoThreadState::Stopped && control.request_remove() {
+            Ok(())
+        } else {
+            Err(format!("io-{thread_id} already being removed"))
+        }
+    }
+
+    /// Shut down every IO thread that has not stopped; see [`IoThreadControl::request_shutdown`].
+    pub fn shutdown_all(&self) {
+        for control in self.slots.lock().unwrap().iter().flatten() {
+            if control.state() != IoThreadState::Stopped {
+                control.request_shutdown();
+            }
+        }
+    }
+
+    /// `(thread_id, state, connections)` for every occupied slot.
+    pub fn status(&self) -> Vec<(u8, IoThreadState, usize)> {
+        self.slots
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(thread_id, slot)| {
+                slot.as_ref()
+                    .map(|control| (thread_id as u8, control.state(), control.connections()))
+            })
+            .collect()
+    }
+
+    /// `(thread_id, accounting)` for every occupied slot.
+    pub fn accounting(&self) -> Vec<(u8, AccountingCounts)> {
+        self.slots
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(thread_id, slot)| {
+                slot.as_ref()
+                    .map(|control| (thread_id as u8, control.accounting()))
+            })
+            .collect()
+    }
+
+    /// `(thread_id, read frame counts)` for every occupied slot.
+    pub fn read_frames(&self) -> Vec<(u8, ReadFrameCounts)> {
+        self.slots
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(thread_id, slot)| {
+                slot.as_ref()
+                    .map(|control| (thread_id as u8, control.read_frames()))
+            })
+            .collect()
+    }
+
+    /// `(thread_id, connections)` for every IO thread that has not stopped, or `None` for one
+    /// that did not answer within `timeout`.
+    pub fn connections(&self, timeout: Duration) -> Vec<(u8, Option<Vec<ConnectionInfo>>)> {
+        let controls: Vec<(u8, Arc<IoThreadControl>)> = self
+            .slots
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(thread_id, slot)| Some((thread_id as u8, Arc::clone(slot.as_ref()?))))
+            .filter(|(_, control)| control.state() != IoThreadState::Stopped)
+            .collect();
+        for (_, control) in &controls {
+            control.request_connections();
+        }
+        let deadline = Instant::now() + timeout;
+        controls
+            .into_iter()
+            .map(|(thread_id, control)| {
+                loop {
+                    if let Some(connections) = control.take_connections() {
+                        break (thread_id, Some(connections));
+                    }
+                    if Instant::now() >= deadline {
+                        break (thread_id, None);
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+            .collect()
+    }
+
+    fn get(&self, thread_id: usize) -> Result<Arc<IoThreadControl>, String> {
+        self.slots
+            .lock()
+            .unwrap()
+            .get(thread_id)
+            .cloned()
+            .flatten()
+            .ok_or_else(|| format!("no io thread {thread_id}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{IoThreadControl, IoThreadSet, IoThreadState};
+
+    #[test]
+    fn drain_is_requested_once() {
+        let control = IoThreadControl::new();
+        assert_eq!(control.state(), IoThreadState::Running);
+        assert!(control.request_drain());
+        assert_eq!(control.state(), IoThreadState::Draining);
+        assert!(!control.request_drain());
+        control.mark_drained();
+        assert_eq!(control.state(), IoThreadState::Drained);
+        assert!(!control.request_drain());
+    }
+
+    #[test]
+    fn drain_request_wakes_notify_fd() {
+        let control = IoThreadControl::new();
+        control.request_drain();
+        let mut value = 0u64;
+        let rc = unsafe {
+            libc::read(
+                control.notify_fd(),
+                (&mut value as *mut u64).cast::<libc::c_void>(),
+                std::mem::size_of::<u64>(),
+            )
+        };
+        assert_eq!(rc as usize, std::mem::size_of::<u64>());
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn remove_drains_then_frees_slot_once_stopped() {
+        let set = IoThreadSet::new(2, Box::new(|_, _| Ok(())));
+        assert_eq!(set.add(), Ok(0));
+        assert_eq!(set.add(), Ok(1));
+        assert!(set.add().is_err());
+
+        set.remove(0).unwrap();
+        assert!(set.remove(0).is_err());
+        assert_eq!(set.status()[0].1, IoThreadState::Draining);
+        assert!(
+            set.add().is_err(),
+            "slot is reusable only after the thread stops"
+        );
+
+        let stopped = set.slots.lock().unwrap()[0].clone().unwrap();
+        stopped.mark_stopped();
+        assert_eq!(set.add(), Ok(0));
+        assert_eq!(set.status()[0].1, IoThreadState::Running);
+        assert!(!Arc::ptr_eq(
+            &stopped,
+            set.slots.lock().unwrap()[0].as_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn shutdown_removes_every_running_thread() {
+        let set = IoThreadSet::new(3, Box::new(|_, _| Ok(())));
+        set.add().unwrap();
+        set.add().unwrap();
+        set.drain(1).unwrap();
+
+        set.shutdown_all();
+        let slots = set.slots.lock().unwrap();
+        for control in slots.iter().flatten() {
+            assert!(control.shutdown_requested());
+            assert!(control.remove_requested());
+            assert_eq!(control.state(), IoThreadState::Draining);
+            assert!(!control.request_shutdown(), "requested once");
+        }
+    }
+
+    #[test]
+    fn read_frames_are_bucketed_per_read() {
+        let control = IoThreadControl::new();
+        control.record_read_frames(0, true);
+        control.record_read_frames(1, false);
+        control.record_read_frames(3, true);
+        control.record_read_frames(40, false);
+        let counts = control.read_frames();
+        assert_eq!(counts.reads, 4);
+        assert_eq!(counts.frames, 44);
+        assert_eq!(counts.partial, 2);
+        assert_eq!(counts.buckets, [1, 1, 0, 1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn failed_spawn_leaves_slot_free() {
+        let set = IoThreadSet::new(1, Box::new(|_, _| Err("bind failed".to_string())));
+        assert_eq!(set.add(), Err("bind failed".to_string()));
+        assert!(set.status().is_empty());
+    }
+}
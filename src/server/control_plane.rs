@@ -0,0 +1,248 @@
+//! Control-plane thread.
+//!
+//! One thread owns everything that observes or steers the server without serving requests:
+//! periodic metrics reports, the admin socket and its health check, Prometheus scrapes, config
+//! reloads on SIGHUP, and starting model reloads, which load on a thread of their own. It reads data-plane state
+//! only through atomics (`metrics`, `IoThreadControl`, `DriftMonitor`) and steers IO threads only through their
+//! control eventfds, so adding observability here never puts work or locks on a data-plane
+//! thread.
+
+use std::io;
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::affinity;
+use crate::canary::CanaryOutcome;
+use crate::metrics;
+use crate::pipeline::drift::DriftMonitor;
+use crate::pipeline::response_queue::ResponseRouter;
+#[cfg(feature = "prometheus")]
+use crate::prometheus::Exporter;
+use crate::server::admin;
+use crate::server::control::IoThreadSet;
+use crate::server::model_reload::ModelReloader;
+use crate::server::reload::{self, ConfigReloader, SoftLimits};
+
+/// Longest the thread sleeps between checks for admin clients and due reports.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct ControlPlane {
+    threads: Arc<IoThreadSet>,
+    metrics_interval_secs: u64,
+    admin: Option<UnixListener>,
+    cpu: Option<usize>,
+    reload: Option<(ConfigReloader, Arc<SoftLimits>)>,
+    response_queues: Option<Arc<ResponseRouter>>,
+    canary: Option<CanaryOutcome>,
+    drift: Option<Arc<DriftMonitor>>,
+    model_reload: Option<ModelReloader>,
+    #[cfg(feature = "prometheus")]
+    prometheus: Option<Exporter>,
+}
+
+impl ControlPlane {
+    pub fn new(threads: Arc<IoThreadSet>, metrics_interval_secs: u64) -> Self {
+        assert!(metrics_interval_secs > 0, "metrics interval must be > 0");
+        Self {
+            threads,
+            metrics_interval_secs,
+            admin: None,
+            cpu: None,
+            reload: None,
+            response_queues: None,
+            canary: None,
+            drift: None,
+            model_reload: None,
+            #[cfg(feature = "prometheus")]
+            prometheus: None,
+        }
+    }
+
+    /// Serve admin commands from `listener`; see [`admin`] for the protocol.
+    pub fn with_admin_socket(mut self, listener: UnixListener) -> Self {
+        self.admin = Some(listener);
+        self
+    }
+
+    /// Pin the control-plane thread to `cpu`.
+    pub fn with_cpu(mut self, cpu: usize) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    /// Include per-IO-thread response queue occupancy in metrics reports.
+    pub fn with_response_queues(mut self, router: Arc<ResponseRouter>) -> Self {
+        self.response_queues = Some(router);
+        self
+    }
+
+    /// Report the model's canary outcome on the admin socket, and fail `health` if it failed.
+    pub fn with_canary(mut self, outcome: CanaryOutcome) -> Self {
+        self.canary = Some(outcome);
+        self
+    }
+
+    /// Report input drift statistics in metrics reports and on the admin `drift` command.
+    pub fn with_drift_monitor(mut self, monitor: Arc<DriftMonitor>) -> Self {
+        self.drift = Some(monitor);
+        self
+    }
+
+    /// Reload the model with `reloader` on the admin `reload-model` command.
+    pub fn with_model_reload(mut self, reloader: ModelReloader) -> Self {
+        self.model_reload = Some(reloader);
+        self
+    }
+
+    /// Answer Prometheus scrapes on `exporter`'s listener.
+    #[cfg(feature = "prometheus")]
+    pub fn with_prometheus(mut self, exporter: Exporter) -> Self {
+        self.prometheus = Some(exporter);
+        self
+    }
+
+    /// Re-read the config file on SIGHUP and store its soft limits into `limits`, which the IO
+    /// threads read on every use.
+    pub fn with_config_reload(mut self, reloader: ConfigReloader, limits: Arc<SoftLimits>) -> Self {
+        self.reload = Some((reloader, limits));
+        self
+    }
+
+    pub fn spawn(self) -> io::Result<JoinHandle<()>> {
+        self.spawn_with_stop(None)
+    }
+
+    /// Like [`Self::spawn`], but the thread returns once `stop` is set.
+    pub fn spawn_until(self, stop: Arc<AtomicBool>) -> io::Result<JoinHandle<()>> {
+        self.spawn_with_stop(Some(stop))
+    }
+
+    fn spawn_with_stop(self, stop: Option<Arc<AtomicBool>>) -> io::Result<JoinHandle<()>> {
+        if let Some(listener) = &self.admin {
+            listener.set_nonblocking(true)?;
+        }
+        if self.reload.is_some() {
+            reload::install_sighup_handler();
+        }
+        thread::Builder::new()
+            .name("control".into())
+            .spawn(move || self.run(stop))
+    }
+
+    fn run(mut self, stop: Option<Arc<AtomicBool>>) {
+        if let Some(cpu) = self.cpu {
+            affinity::pin_current_thread(cpu, "control").unwrap_or_else(|e| panic!("{e}"));
+        }
+        let mut reporter = metrics::Reporter::new().with_io_threads(Arc::clone(&self.threads));
+        if let Some(router) = self.response_queues.take() {
+            reporter = reporter.with_response_queues(router);
+        }
+        if let Some(monitor) = &self.drift {
+            reporter = reporter.with_drift_monitor(Arc::clone(monitor));
+        }
+        let mut next_report = Instant::now() + Duration::from_secs(self.metrics_interval_secs);
+        while !stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+        {
+            if let Some(listener) = &self.admin {
+                self.accept_admin(listener);
+            }
+            #[cfg(feature = "prometheus")]
+            if let Some(exporter) = &self.prometheus {
+                exporter.serve_pending();
+            }
+            if reload::take_sighup() && self.reload_config() {
+                next_report = Instant::now() + Duration::from_secs(self.metrics_interval_secs);
+            }
+            let now = Instant::now();
+            if now >= next_report {
+                reporter.report(self.metrics_interval_secs);
+                next_report += Duration::from_secs(self.metrics_interval_secs);
+            }
+            thread::sleep(POLL_INTERVAL.min(next_report.saturating_duration_since(now)));
+        }
+    }
+
+    /// Returns `true` if the metrics interval changed.
+    fn reload_config(&mut self) -> bool {
+        let Some((reloader, limits)) = &self.reload else {
+            return false;
+        };
+        match reloader.reload(limits) {
+            Ok(report) => {
+                eprintln!("disrust: config reloaded: {}", report.applied.join(" "));
+                if !report.restart_required.is_empty() {
+                    eprintln!(
+                        "disrust: config changes need a restart: {}",
+                        report.restart_required.join(", ")
+                    );
+                }
+            }
+            Err(e) => {
+                eprintln!("disrust: config reload failed, keeping current limits: {e}");
+                return false;
+            }
+        }
+        let interval = limits.metrics_interval_secs();
+        let changed = interval != self.metrics_interval_secs;
+        self.metrics_interval_secs = interval;
+        changed
+    }
+
+    fn accept_admin(&self, listener: &UnixListener) {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = admin::serve_client(
+                        stream,
+                        &self.threads,
+                        self.canary.as_ref(),
+                        self.drift.as_deref(),
+                        self.model_reload.as_ref(),
+                    ) {
+                        eprintln!("disrust: admin client error: {e}");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    eprintln!("disrust: admin accept failed: {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+
+    use super::ControlPlane;
+    use crate::server::admin;
+    use crate::server::control::IoThreadSet;
+
+    #[test]
+    fn serves_admin_commands_from_control_thread() {
+        let path =
+            std::env::temp_dir().join(format!("disrust-control-{}.sock", std::process::id()));
+        let threads = Arc::new(IoThreadSet::new(1, Box::new(|_, _| Ok(()))));
+        threads.add().unwrap();
+        ControlPlane::new(Arc::clone(&threads), 3600)
+            .with_admin_socket(admin::bind(&path).unwrap())
+            .spawn()
+            .unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"health\n").unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ok healthy running=1\n");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,94 @@
+//! Registered buffers for socket reads and writes.
+//!
+//! With `--fixed-buffers`, each IO thread registers a sparse buffer table with its data ring
+//! (`IORING_REGISTER_BUFFERS2`), two entries per connection slot, and reads and writes with
+//! `ReadFixed` and `WriteFixed`, so the kernel pins each buffer's pages once rather than on every
+//! operation. Entry `2 * key` is the read buffer of the connection in slot `key`, registered
+//! when it submits its first read. Entry `2 * key + 1` is the slot's write buffer: a write copies
+//! the frames it gathers into it, a few hundred bytes per response, in exchange for one pinned
+//! buffer instead of an iovec per frame. A write buffer is kept for the slot's next connection.
+//! A closed connection's read buffer stays registered, its pages held by the kernel, until the
+//! next connection in the slot replaces it.
+//!
+//! Registered pages count against `RLIMIT_MEMLOCK`. Once a registration fails, connections
+//! opened after it read and write unregistered.
+
+use std::io;
+
+use io_uring::Submitter;
+
+/// One IO thread's registered read and write buffers, by connection slot.
+pub(crate) struct FixedBuffers {
+    /// Each slot's write buffer once a connection in it has registered.
+    write_bufs: Box<[Option<Box<[u8]>>]>,
+    write_buf_size: usize,
+    disabled: bool,
+}
+
+impl FixedBuffers {
+    /// Register an empty table for `slots` connection slots, with write buffers of
+    /// `write_buf_size` bytes, with the ring `submitter` belongs to. Needs Linux 5.19.
+    pub(crate) fn register(
+        submitter: &Submitter<'_>,
+        slots: usize,
+        write_buf_size: usize,
+    ) -> io::Result<Self> {
+        submitter.register_buffers_sparse((slots * 2) as u32)?;
+        Ok(Self {
+            write_bufs: (0..slots).map(|_| None).collect(),
+            write_buf_size,
+            disabled: false,
+        })
+    }
+
+    /// Register `read_buf` and the slot's write buffer for the connection in slot `key`. The
+    /// caller keeps `read_buf` alive, unmoved, while reads use it. After an error, no further
+    /// connection is registered.
+    pub(crate) fn attach(
+        &mut self,
+        submitter: &Submitter<'_>,
+        key: u16,
+        read_buf: &mut [u8],
+    ) -> io::Result<()> {
+        if self.disabled {
+            return Err(io::Error::other("an earlier registration failed"));
+        }
+        let size = self.write_buf_size;
+        let write_buf =
+            self.write_bufs[key as usize].get_or_insert_with(|| vec![0u8; size].into_boxed_slice());
+        let iovecs = [
+            libc::iovec {
+                iov_base: read_buf.as_mut_ptr().cast(),
+                iov_len: read_buf.len(),
+            },
+            libc::iovec {
+                iov_base: write_buf.as_mut_ptr().cast(),
+                iov_len: write_buf.len(),
+            },
+        ];
+        // SAFETY: write buffers live as long as the table; the caller keeps `read_buf` alive.
+        let registered = unsafe {
+            submitter.register_buffers_update(Self::read_index(key) as u32, &iovecs, None)
+        };
+        if registered.is_err() {
+            self.disabled = true;
+        }
+        registered
+    }
+
+    /// Whether connections are still registered.
+    pub(crate) fn enabled(&self) -> bool {
+        !self.disabled
+    }
+
+    /// The table entry of slot `key`'s read buffer.
+    pub(crate) fn read_index(key: u16) -> u16 {
+        key * 2
+    }
+
+    /// Slot `key`'s write buffer, once attached, and its table entry.
+    pub(crate) fn write_buf(&mut self, key: u16) -> Option<(&mut [u8], u16)> {
+        let buf = self.write_bufs[key as usize].as_deref_mut()?;
+        Some((buf, key * 2 + 1))
+    }
+}
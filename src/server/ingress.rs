@@ -2,29 +2,79 @@
 
 use std::collections::VecDeque;
 use std::io;
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use disruptor::Producer;
-use io_uring::{opcode, squeue::Entry, types::Fd};
-use slab::Slab;
+use io_uring::types::{Fd, Timespec};
+use io_uring::{cqueue, opcode, squeue, squeue::Entry};
 
 use crate::buffer_pool::PoolAllocator;
 use crate::clock::{elapsed_since_ns, monotonic_now_ns};
-use crate::config::{READ_BUF_SIZE, SLAB_CAPACITY, WRITE_BUF_SIZE};
+use crate::config::{
+    MAX_SKIPPED_REQUEST_BYTES, MIN_READ_SIZE, READ_BUF_SIZE, REQUEST_OVERFLOW_CAPACITY,
+    SLAB_CAPACITY, WRITE_BUF_SIZE,
+};
 use crate::connection_id::ConnectionRef;
+use crate::constants::{FEATURE_DIM, MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::embedding::{EmbeddingLookup, EmbeddingReader, EmbeddingStore};
+use crate::feature_schema::FeatureSchema;
 use crate::metrics;
+use crate::notify;
+use crate::pipeline::admission::LargeRequestAdmission;
+use crate::pipeline::circuit_breaker::CircuitBreaker;
 use crate::pipeline::connection_registry::ConnectionRegistry;
-use crate::pipeline::response_queue::ResponseQueue;
-use crate::request_flow;
+use crate::pipeline::control_channel::{ControlEvent, ControlSender};
+use crate::pipeline::inline::InlineFastPath;
+use crate::pipeline::response_queue::{ResponseQueue, ResponseReady};
+use crate::protocol::{
+    self, ConnectionDecoder, FixedPointResponse, LENGTH_PREFIX_BYTES, OVERLOAD_FRAME_BYTES,
+    OverloadReason, PARSE_ERROR_FRAME_BYTES, ParseError, REQUEST_ID_BYTES, ReplayStatus,
+    RequestDecoder, RequestFraming, ResponseSerializer, SEQ_PREFIX_BYTES,
+};
+use crate::request_flow::{self, ProcessRequestError, RequestOverflow, RingFullPolicy};
 use crate::ring_types::InferenceEvent;
+use crate::server::IoBackend;
+use crate::server::accounting::{self, ResponseAccounting};
+use crate::server::buf_ring::ReadBufferRing;
+use crate::server::control::{ConnectionInfo, IoThreadControl, IoThreadState};
+use crate::server::fixed_buf::FixedBuffers;
+use crate::server::reload::SoftLimits;
+use crate::server::replay::{Attach, ReplaySession, ReplaySessions};
+use crate::server::slots::ConnectionSlots;
+
+mod epoll;
 
 const OP_ACCEPT: u64 = 0;
 const OP_READ: u64 = 1;
 const OP_WRITE: u64 = 2;
 const OP_NOTIFY: u64 = 3;
+/// `OP_NOTIFY` data of wakeups the inference thread posts with `MSG_RING`, as opposed to the
+/// notify fd's poll.
+const NOTIFY_POSTED: u32 = 1;
+const OP_CONTROL: u64 = 4;
+const OP_CANCEL: u64 = 5;
+const OP_IDLE_SWEEP: u64 = 6;
+/// The accept ring has completions; see [`AcceptRing`].
+const OP_ACCEPT_RING: u64 = 7;
+/// Checks whether an op's completion was dropped from a full completion queue; see
+/// [`submit_probe`].
+const OP_PROBE: u64 = 8;
+
+/// Entries in each IO thread's accept ring: the accept, the control poll and a cancel.
+const ACCEPT_RING_ENTRIES: u32 = 8;
+
+/// Longest gap between idle-connection sweeps; shorter timeouts sweep every timeout.
+const IDLE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 const MAX_IOVECS_PER_WRITE: usize = 64;
+/// Written frames a connection keeps for reuse: enough to refill one full write without
+/// allocating.
+const MAX_SPARE_FRAMES: usize = MAX_IOVECS_PER_WRITE;
+/// A registered write buffer holds one full write of whole frames.
+const FIXED_WRITE_BUF_SIZE: usize = MAX_IOVECS_PER_WRITE * MAX_FRAME_BYTES;
 
 fn encode_user_data(op: u64, data: u32) -> u64 {
     (op << 32) | data as u64
@@ -36,26 +86,76 @@ fn decode_user_data(user_data: u64) -> (u64, u32) {
 
 struct IoUring {
     inner: io_uring::IoUring,
+    /// Registered with `inner`, which is declared first so it is dropped first.
+    read_buffers: Option<ReadBufferRing>,
+    /// Also registered with `inner`.
+    fixed_buffers: Option<FixedBuffers>,
     outstanding: usize,
+    /// SQEs pushed since the last submit.
+    unsubmitted: usize,
+    /// The kernel's count of dropped completions as of the last [`Self::take_dropped_cqes`].
+    dropped_cqes: u32,
+    /// The `user_data` of completions another thread posts with `MSG_RING`, which no SQE of
+    /// this ring is waiting for.
+    posted: Option<u64>,
 }
 
 impl IoUring {
     fn new(entries: u32) -> io::Result<Self> {
         Ok(Self {
             inner: io_uring::IoUring::new(entries)?,
+            read_buffers: None,
+            fixed_buffers: None,
             outstanding: 0,
+            unsubmitted: 0,
+            dropped_cqes: 0,
+            posted: None,
         })
     }
 
+    /// Have reads select from `entries` provided buffers; see [`ReadBufferRing`].
+    fn register_read_buffers(&mut self, entries: u16) -> io::Result<()> {
+        self.read_buffers = Some(ReadBufferRing::register(&self.inner.submitter(), entries)?);
+        Ok(())
+    }
+
+    /// Read and write through buffers registered per connection slot; see [`FixedBuffers`].
+    fn register_fixed_buffers(&mut self, slots: usize) -> io::Result<()> {
+        self.fixed_buffers = Some(FixedBuffers::register(
+            &self.inner.submitter(),
+            slots,
+            FIXED_WRITE_BUF_SIZE,
+        )?);
+        Ok(())
+    }
+
+    /// Whether `conn`, in slot `key`, reads and writes through registered buffers, registering
+    /// them if it has not tried yet.
+    fn uses_fixed_buffers(&mut self, conn: &mut Connection, key: u16) -> bool {
+        if !conn.fixed_buffers
+            && let Some(fixed) = self.fixed_buffers.as_mut().filter(|fixed| fixed.enabled())
+        {
+            match fixed.attach(&self.inner.submitter(), key, &mut conn.read_buf) {
+                Ok(()) => conn.fixed_buffers = true,
+                Err(e) => eprintln!(
+                    "disrust: cannot register fixed buffers ({e}), reading and writing new connections unregistered"
+                ),
+            }
+        }
+        conn.fixed_buffers
+    }
+
     fn push(&mut self, sqe: &Entry) {
         loop {
             match unsafe { self.inner.submission().push(sqe) } {
                 Ok(()) => {
                     self.outstanding += 1;
+                    self.unsubmitted += 1;
                     return;
                 }
                 Err(_) => {
                     self.inner.submit().expect("SQ flush failed");
+                    self.record_submitted();
                 }
             }
         }
@@ -65,42 +165,272 @@ impl IoUring {
         self.inner
             .submit_and_wait(n)
             .expect("submit_and_wait failed");
+        self.record_submitted();
     }
 
     fn submit(&mut self) {
         if self.outstanding > 0 {
             self.inner.submit().expect("io_uring submit failed");
+            self.record_submitted();
+        }
+    }
+
+    /// Submit only if SQEs were pushed since the last submit.
+    fn submit_pushed(&mut self) {
+        if self.unsubmitted > 0 {
+            self.inner.submit().expect("io_uring submit failed");
+            self.record_submitted();
+        }
+    }
+
+    fn fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+
+    fn record_submitted(&mut self) {
+        if self.unsubmitted > 0 {
+            metrics::record_io_sqes_submitted(self.unsubmitted as u64);
+            self.unsubmitted = 0;
         }
     }
 
-    fn drain_cqes_into(&mut self, buf: &mut Vec<(u64, i32)>) {
+    /// Collect `(user_data, result, flags)` per CQE. `cqueue::more(flags)` is set while a
+    /// multishot SQE stays armed and will complete again.
+    fn drain_cqes_into(&mut self, buf: &mut Vec<(u64, i32, u32)>) {
         for cqe in self.inner.completion() {
-            self.outstanding = self.outstanding.saturating_sub(1);
-            buf.push((cqe.user_data(), cqe.result()));
+            if !cqueue::more(cqe.flags()) && self.posted != Some(cqe.user_data()) {
+                self.outstanding = self.outstanding.saturating_sub(1);
+            }
+            buf.push((cqe.user_data(), cqe.result(), cqe.flags()));
+        }
+    }
+
+    /// Completions the kernel dropped since the last call because the completion queue was
+    /// full. Kernels since 5.5 hold such completions back instead, so this only happens when
+    /// they cannot allocate room for them.
+    fn take_dropped_cqes(&mut self) -> u32 {
+        let total = self.inner.completion().overflow();
+        let dropped = total.wrapping_sub(self.dropped_cqes);
+        self.dropped_cqes = total;
+        dropped
+    }
+}
+
+/// Ops with one SQE per ring that a probe is checking, as a bit per op; see [`submit_probe`].
+#[derive(Default)]
+struct Probes(u16);
+
+impl Probes {
+    fn start(&mut self, ring: &mut IoUring, op: u64) {
+        self.0 |= 1 << op;
+        submit_probe(ring, op, 0);
+    }
+
+    /// A completion for `op` arrived, so it was not lost.
+    fn complete(&mut self, op: u64) {
+        self.0 &= !(1 << op);
+    }
+
+    /// Whether `op` had no completion since its probe started, ending the probe.
+    fn take(&mut self, op: u64) -> bool {
+        let lost = self.0 & (1 << op) != 0;
+        self.complete(op);
+        lost
+    }
+}
+
+/// An IO thread's second, small ring, holding the listener's accept and the control poll.
+///
+/// Reads, writes and the response queue poll complete on the data ring, which can return
+/// thousands of CQEs per wake under load. Keeping accepts and control events on their own ring
+/// means they are handled every loop iteration, ahead of however many data completions are
+/// queued, and a burst of accepts never delays data completions past one iteration either. The
+/// data ring polls this ring's fd so a wait on the data ring still wakes for an accept.
+struct AcceptRing {
+    ring: IoUring,
+    cqes: Vec<(u64, i32, u32)>,
+    /// Whether new connections are still taken; cleared to cancel the accept.
+    accepting: bool,
+    /// Whether an accept SQE may still complete.
+    inflight: bool,
+    multishot: bool,
+    probes: Probes,
+}
+
+impl AcceptRing {
+    fn new() -> io::Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(ACCEPT_RING_ENTRIES)?,
+            cqes: Vec::new(),
+            accepting: true,
+            inflight: true,
+            multishot: true,
+            probes: Probes::default(),
+        })
+    }
+
+    /// Stop taking connections, cancelling the accept, if not already stopped.
+    fn stop_accepting(&mut self) {
+        if self.accepting {
+            self.accepting = false;
+            submit_cancel_accept(&mut self.ring);
+            self.ring.submit_pushed();
         }
     }
 }
 
+/// Remove and return the value noted for `request_seq`, or 0 if there is none.
+fn take_by_seq(noted: &mut VecDeque<(u64, u64)>, request_seq: u64) -> u64 {
+    match noted.iter().position(|&(seq, _)| seq == request_seq) {
+        Some(i) => noted.remove(i).map_or(0, |(_, value)| value),
+        None => 0,
+    }
+}
+
+/// Whether `request_seq` was noted, forgetting it if so.
+fn take_seq(noted: &mut VecDeque<u64>, request_seq: u64) -> bool {
+    match noted.iter().position(|&seq| seq == request_seq) {
+        Some(i) => noted.remove(i).is_some(),
+        None => false,
+    }
+}
+
+/// Optional fields written around every frame on a connection.
+#[derive(Debug, Clone, Copy, Default)]
+struct FramePrefixes {
+    /// The frame's length, so clients can skip frames they do not understand.
+    length: bool,
+    /// The `request_seq` the frame answers, so clients can verify ordering.
+    request_seq: bool,
+    /// A status byte per result after each response, so clients can tell which vectors failed.
+    vector_status: bool,
+    /// The `request_id` the client sent before the request the frame answers.
+    request_id: bool,
+}
+
+impl FramePrefixes {
+    /// How requests are framed on a connection writing these prefixes.
+    fn framing(self) -> RequestFraming {
+        if self.request_id {
+            RequestFraming::REQUEST_ID
+        } else {
+            RequestFraming::PLAIN
+        }
+    }
+}
+
+/// What a frame carries after its prefixes.
+#[derive(Clone, Copy)]
+enum FrameBody<'a> {
+    /// Scores, serialized in the connection's format and followed by their vector status.
+    Results(&'a [f32]),
+    /// A frame encoded the same in every format: an overload or parse error.
+    Encoded(&'a [u8]),
+}
+
+const MAX_PREFIX_BYTES: usize = LENGTH_PREFIX_BYTES + SEQ_PREFIX_BYTES + REQUEST_ID_BYTES;
+const MAX_FRAME_BYTES: usize =
+    MAX_PREFIX_BYTES + WRITE_BUF_SIZE + protocol::vector_status_size(MAX_VECTORS_PER_REQUEST);
+
 struct ResponseFrame {
+    /// When the read that completed the request it answers finished; `published_at_ns` for
+    /// frames answering no request.
+    received_at_ns: u64,
     published_at_ns: u64,
+    /// When the write carrying the frame was submitted.
+    write_submitted_at_ns: u64,
     len: usize,
     offset: usize,
-    data: [u8; WRITE_BUF_SIZE],
+    data: [u8; MAX_FRAME_BYTES],
 }
 
 impl ResponseFrame {
-    fn new(published_at_ns: u64, bytes: &[u8]) -> Self {
-        debug_assert!(bytes.len() <= WRITE_BUF_SIZE);
-        let mut data = [0u8; WRITE_BUF_SIZE];
-        data[..bytes.len()].copy_from_slice(bytes);
+    fn empty() -> Self {
         Self {
-            published_at_ns,
-            len: bytes.len(),
+            received_at_ns: 0,
+            published_at_ns: 0,
+            write_submitted_at_ns: 0,
+            len: 0,
             offset: 0,
-            data,
+            data: [0u8; MAX_FRAME_BYTES],
         }
     }
 
+    #[cfg(test)]
+    fn new(published_at_ns: u64, bytes: &[u8]) -> Self {
+        let mut frame = Self::empty();
+        frame.fill(
+            published_at_ns,
+            FramePrefixes::default(),
+            &protocol::NATIVE_RESPONSE,
+            0,
+            0,
+            FrameBody::Encoded(bytes),
+            0,
+        );
+        frame
+    }
+
+    /// Overwrite the frame with `body`, preceded by the `prefixes` for `request_seq` and
+    /// `request_id`. Results are written by `serializer` and, with `prefixes.vector_status`,
+    /// followed by their vector status with the `invalid_vectors` mask.
+    #[allow(clippy::too_many_arguments)]
+    fn fill(
+        &mut self,
+        published_at_ns: u64,
+        prefixes: FramePrefixes,
+        serializer: &dyn ResponseSerializer,
+        request_seq: u64,
+        request_id: u64,
+        body: FrameBody<'_>,
+        invalid_vectors: u64,
+    ) {
+        let (body_len, status_len) = match body {
+            FrameBody::Results(results) if prefixes.vector_status => (
+                serializer.response_size(results.len()),
+                protocol::vector_status_size(results.len()),
+            ),
+            FrameBody::Results(results) => (serializer.response_size(results.len()), 0),
+            FrameBody::Encoded(bytes) => (bytes.len(), 0),
+        };
+        debug_assert!(body_len <= WRITE_BUF_SIZE);
+        let mut start = if prefixes.length {
+            LENGTH_PREFIX_BYTES
+        } else {
+            0
+        };
+        if prefixes.request_seq {
+            protocol::encode_seq_prefix(request_seq, &mut self.data[start..]);
+            start += SEQ_PREFIX_BYTES;
+        }
+        if prefixes.request_id {
+            protocol::encode_request_id(request_id, &mut self.data[start..]);
+            start += REQUEST_ID_BYTES;
+        }
+        if prefixes.length {
+            protocol::encode_length_prefix(
+                start - LENGTH_PREFIX_BYTES + body_len + status_len,
+                &mut self.data,
+            );
+        }
+        let end = start + body_len;
+        match body {
+            FrameBody::Results(results) => {
+                serializer.encode(results, &mut self.data[start..end]);
+                protocol::encode_vector_status(
+                    results,
+                    invalid_vectors,
+                    &mut self.data[end..end + status_len],
+                );
+            }
+            FrameBody::Encoded(bytes) => self.data[start..end].copy_from_slice(bytes),
+        }
+        self.received_at_ns = published_at_ns;
+        self.published_at_ns = published_at_ns;
+        self.len = end + status_len;
+        self.offset = 0;
+    }
+
     fn remaining(&self) -> usize {
         self.len.saturating_sub(self.offset)
     }
@@ -108,24 +438,96 @@ impl ResponseFrame {
     fn remaining_ptr(&self) -> *const u8 {
         unsafe { self.data.as_ptr().add(self.offset) }
     }
+
+    fn remaining_bytes(&self) -> &[u8] {
+        &self.data[self.offset.min(self.len)..self.len]
+    }
 }
 
 struct Connection {
     fd: RawFd,
     conn: ConnectionRef,
-    read_buf: Box<[u8; READ_BUF_SIZE]>,
+    /// `READ_BUF_SIZE` bytes, or empty while a connection reading from provided buffers holds
+    /// nothing.
+    read_buf: Box<[u8]>,
     read_len: usize,
+    /// Request-stream bytes consumed before the start of `read_buf`.
+    stream_offset: u64,
+    /// Bytes of a skipped malformed request still to arrive, discarded as they are read.
+    skip_bytes: usize,
     next_request_seq: u64,
+    /// Sequence number of the next response to append to `queue`.
+    next_response_seq: u64,
     read_inflight: bool,
+    /// A probe is checking whether the pending read's completion was dropped.
+    read_probed: bool,
     read_closed: bool,
     parse_queued: bool,
     write_closed: bool,
     write_inflight: bool,
+    /// A probe is checking whether the pending write's completion was dropped.
+    write_probed: bool,
+    /// `read_buf` and the slot's write buffer are registered with the data ring; see
+    /// [`FixedBuffers`].
+    fixed_buffers: bool,
     ready_queued: bool,
     queue: VecDeque<Box<ResponseFrame>>,
+    /// Locally generated frames (overload rejections, inline scores) waiting for earlier responses, keyed by
+    /// request sequence number.
+    deferred: VecDeque<(u64, Box<ResponseFrame>)>,
     inflight: VecDeque<Box<ResponseFrame>>,
+    /// Written frames kept for reuse, at most `MAX_SPARE_FRAMES`. Boxed so they move back into
+    /// the queues without copying.
+    #[allow(clippy::vec_box)]
+    spare_frames: Vec<Box<ResponseFrame>>,
     inflight_iovecs: [libc::iovec; MAX_IOVECS_PER_WRITE],
     inflight_iov_count: usize,
+    /// Bytes queued but not yet written: `queue`, `deferred` and the unwritten tail of
+    /// `inflight`.
+    backlog_bytes: usize,
+    read_paused: bool,
+    evicted: bool,
+    /// Shutting down: no more reads, and the connection is closed once everything read so far
+    /// is answered.
+    closing: bool,
+    /// When request bytes were last read or response bytes written.
+    last_active_ns: u64,
+    /// When request bytes were last read.
+    received_at_ns: u64,
+    prefixes: FramePrefixes,
+    /// Reads this connection's requests, with any state it keeps for them.
+    decoder: ConnectionDecoder,
+    /// Writes this connection's scores.
+    serializer: &'static dyn ResponseSerializer,
+    /// Writes the scores of requests asking for fixed point, which requests may only do with
+    /// one.
+    fixed_point: Option<FixedPointResponse>,
+    /// Features per request vector.
+    feature_dim: usize,
+    /// Most vectors per request on the port this connection was accepted on.
+    max_vectors: usize,
+    /// Size reads by `bytes_per_read` rather than always filling the free buffer.
+    adaptive_reads: bool,
+    /// Recent bytes per read: an EWMA, raised to the whole read whenever one fills.
+    bytes_per_read: usize,
+    /// Bytes the read in flight asked for.
+    read_requested: usize,
+    /// Requests with NaN or infinite features and the mask of those vectors, until their frame
+    /// is built; only recorded with `prefixes.vector_status`.
+    invalid_vectors: VecDeque<(u64, u64)>,
+    /// `request_id`s of requests whose frame is not built yet, keyed by request sequence number;
+    /// only recorded with `prefixes.request_id`.
+    request_ids: VecDeque<(u64, u64)>,
+    /// Requests asking for fixed-point scores whose frame is not built yet, by request sequence
+    /// number; only recorded with `fixed_point`.
+    fixed_point_requests: VecDeque<u64>,
+    /// With `--replay-window`, nothing is parsed until the client's replay hello is answered.
+    awaiting_hello: bool,
+    /// The replay session this connection resumed, which keeps every frame it builds.
+    replay: Option<Arc<Mutex<ReplaySession>>>,
+    /// `request_seq` of this connection's first request; 0 unless it resumed a session.
+    first_request_seq: u64,
+    accounting: ResponseAccounting,
 }
 
 impl Connection {
@@ -133,37 +535,407 @@ impl Connection {
         Self {
             fd,
             conn,
-            read_buf: Box::new([0u8; READ_BUF_SIZE]),
+            read_buf: vec![0u8; READ_BUF_SIZE].into_boxed_slice(),
             read_len: 0,
+            stream_offset: 0,
+            skip_bytes: 0,
             next_request_seq: 0,
+            next_response_seq: 0,
             read_inflight: false,
+            read_probed: false,
             read_closed: false,
             parse_queued: false,
             write_closed: false,
             write_inflight: false,
+            write_probed: false,
+            fixed_buffers: false,
             ready_queued: false,
             queue: VecDeque::new(),
+            deferred: VecDeque::new(),
             inflight: VecDeque::new(),
+            spare_frames: Vec::new(),
             inflight_iovecs: [libc::iovec {
                 iov_base: std::ptr::null_mut(),
                 iov_len: 0,
             }; MAX_IOVECS_PER_WRITE],
             inflight_iov_count: 0,
+            backlog_bytes: 0,
+            read_paused: false,
+            evicted: false,
+            closing: false,
+            last_active_ns: monotonic_now_ns(),
+            received_at_ns: 0,
+            prefixes: FramePrefixes::default(),
+            decoder: ConnectionDecoder::native(),
+            serializer: &protocol::NATIVE_RESPONSE,
+            fixed_point: None,
+            feature_dim: FEATURE_DIM,
+            max_vectors: MAX_VECTORS_PER_REQUEST,
+            adaptive_reads: false,
+            bytes_per_read: 0,
+            read_requested: 0,
+            invalid_vectors: VecDeque::new(),
+            request_ids: VecDeque::new(),
+            fixed_point_requests: VecDeque::new(),
+            awaiting_hello: false,
+            replay: None,
+            first_request_seq: 0,
+            accounting: ResponseAccounting::default(),
+        }
+    }
+
+    /// Whether every request read so far is answered and every response written.
+    fn is_settled(&self, overflow: &RequestOverflow) -> bool {
+        !self.parse_queued
+            && !overflow.is_blocked(self.conn)
+            && self.next_response_seq == self.next_request_seq
+            && !self.write_inflight
+            && self.queue.is_empty()
+            && self.deferred.is_empty()
+            && self.inflight.is_empty()
+    }
+
+    /// How requests are framed on this connection.
+    fn framing(&self) -> RequestFraming {
+        let framing = self
+            .prefixes
+            .framing()
+            .with_feature_dim(self.feature_dim)
+            .with_max_vectors(self.max_vectors);
+        if self.fixed_point.is_some() {
+            framing.with_fixed_point_scores()
+        } else {
+            framing
+        }
+    }
+
+    /// Bytes the next read asks for. With `adaptive_reads`, twice the recent bytes per read,
+    /// rounded up to a power of two, so a connection sending a little at a time reads small and
+    /// one whose reads keep filling doubles its read until it fills the buffer.
+    fn read_size(&self) -> usize {
+        let free = READ_BUF_SIZE - self.read_len;
+        if !self.adaptive_reads {
+            return free;
+        }
+        (self.bytes_per_read * 2)
+            .next_power_of_two()
+            .max(MIN_READ_SIZE)
+            .min(free)
+    }
+
+    /// Note a completed read of `bytes`.
+    fn record_read(&mut self, bytes: usize) {
+        self.bytes_per_read = self.bytes_per_read - self.bytes_per_read / 8 + bytes / 8;
+        if bytes >= self.read_requested {
+            // The socket likely holds more.
+            self.bytes_per_read = self.bytes_per_read.max(bytes);
         }
     }
 
     fn read_buf_tail(&mut self) -> (*mut u8, u32) {
         (
             unsafe { self.read_buf.as_mut_ptr().add(self.read_len) },
-            (READ_BUF_SIZE - self.read_len) as u32,
+            self.read_size() as u32,
         )
     }
 
+    /// How the connection looks to the admin `connections` command.
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            conn_id: self.conn.conn_id,
+            read_size: self.read_size(),
+            bytes_per_read: self.bytes_per_read,
+            buffered: self.read_len,
+            requests: self.next_request_seq - self.first_request_seq,
+            in_flight: self.next_request_seq.saturating_sub(self.next_response_seq),
+        }
+    }
+
+    /// A frame holding `body`, reusing a spare one when there is one.
+    fn frame(
+        &mut self,
+        request_seq: u64,
+        published_at_ns: u64,
+        body: FrameBody<'_>,
+    ) -> Box<ResponseFrame> {
+        let mut frame = self
+            .spare_frames
+            .pop()
+            .unwrap_or_else(|| Box::new(ResponseFrame::empty()));
+        let invalid_vectors = take_by_seq(&mut self.invalid_vectors, request_seq);
+        let request_id = take_by_seq(&mut self.request_ids, request_seq);
+        let serializer: &dyn ResponseSerializer = match &self.fixed_point {
+            Some(fixed_point) if take_seq(&mut self.fixed_point_requests, request_seq) => {
+                fixed_point
+            }
+            _ => self.serializer,
+        };
+        frame.fill(
+            published_at_ns,
+            self.prefixes,
+            serializer,
+            request_seq,
+            request_id,
+            body,
+            invalid_vectors,
+        );
+        if let Some(session) = &self.replay {
+            session
+                .lock()
+                .unwrap()
+                .record(request_seq, &frame.data[..frame.len]);
+        }
+        frame
+    }
+
+    /// The frame answering `response` from the response queue: its scores, or a timeout frame
+    /// if it `timed_out`.
+    fn response_frame(&mut self, response: &ResponseReady, timed_out: bool) -> Box<ResponseFrame> {
+        metrics::record_response_queue_wait(elapsed_since_ns(response.processed_at_ns));
+        let mut timeout = [0u8; OVERLOAD_FRAME_BYTES];
+        let body = if timed_out {
+            protocol::encode_overload(OverloadReason::Timeout, 0, &mut timeout);
+            FrameBody::Encoded(&timeout)
+        } else {
+            FrameBody::Results(response.results())
+        };
+        let mut frame = self.frame(response.request_seq, response.published_at_ns, body);
+        frame.received_at_ns = response.received_at_ns;
+        frame
+    }
+
+    /// Queue `bytes` as sent, ahead of every response: a replay reply or a frame it resends.
+    fn push_unnumbered(&mut self, bytes: &[u8]) {
+        let mut frame = self
+            .spare_frames
+            .pop()
+            .unwrap_or_else(|| Box::new(ResponseFrame::empty()));
+        frame.data[..bytes.len()].copy_from_slice(bytes);
+        frame.published_at_ns = monotonic_now_ns();
+        frame.received_at_ns = frame.published_at_ns;
+        frame.len = bytes.len();
+        frame.offset = 0;
+        self.accounting.on_unnumbered_frame();
+        self.backlog_bytes += frame.len;
+        self.queue.push_back(frame);
+        self.ready_queued = true;
+    }
+
+    /// Note what the frames answering the requests in `read_buf[..consumed]`, numbered from
+    /// `first_seq`, need: their `request_id`s, whether they asked for fixed-point scores, and
+    /// with vector status on, which vectors carry NaN or infinite features or break `schema`,
+    /// which is checked whether or not vector status is on. A skipped malformed request only
+    /// needs its `request_id`.
+    fn note_requests(&mut self, consumed: usize, first_seq: u64, schema: Option<&FeatureSchema>) {
+        let framing = self.framing();
+        let decoder = &mut self.decoder;
+        let mut pos = 0;
+        let mut request_seq = first_seq;
+        while pos < consumed {
+            let (bytes_consumed, request_id) = match decoder
+                .parse(&self.read_buf[pos..consumed], framing)
+            {
+                protocol::ParseResult::Complete {
+                    bytes_consumed,
+                    request_id,
+                    ..
+                } => (bytes_consumed, request_id),
+                protocol::ParseResult::Error(error) => {
+                    let Some(len) = decoder.skip_len(&error, framing, MAX_SKIPPED_REQUEST_BYTES)
+                    else {
+                        break;
+                    };
+                    if framing.request_ids {
+                        let request_id = decoder.request_id(&self.read_buf[pos..]);
+                        self.request_ids.push_back((request_seq, request_id));
+                    }
+                    pos += len;
+                    request_seq += 1;
+                    continue;
+                }
+                protocol::ParseResult::Incomplete(_) => break,
+            };
+            if let Some(request_id) = request_id {
+                self.request_ids.push_back((request_seq, request_id));
+            }
+            let frame = &self.read_buf[pos..pos + bytes_consumed];
+            if framing.fixed_point_scores && decoder.wants_fixed_point(frame, framing) {
+                self.fixed_point_requests.push_back(request_seq);
+            }
+            let features = decoder.features(frame, framing);
+            let mut mask = schema.map_or(0, |schema| schema.check_vectors(features));
+            if self.prefixes.vector_status {
+                mask |= protocol::non_finite_vectors(features, framing.feature_dim);
+            }
+            if self.prefixes.vector_status && mask != 0 {
+                self.invalid_vectors.push_back((request_seq, mask));
+            }
+            pos += bytes_consumed;
+            request_seq += 1;
+        }
+    }
+
+    /// Move queued frames into `inflight` if it is empty, up to `MAX_IOVECS_PER_WRITE`, and point
+    /// `inflight_iovecs` at what is left of each. Returns the iovecs to write, 0 if none.
+    fn gather_write(&mut self) -> usize {
+        if self.inflight.is_empty() {
+            while self.inflight.len() < MAX_IOVECS_PER_WRITE {
+                let Some(mut frame) = self.queue.pop_front() else {
+                    break;
+                };
+                let now_ns = monotonic_now_ns();
+                metrics::record_publish_to_write_submit(Duration::from_nanos(
+                    now_ns.saturating_sub(frame.published_at_ns),
+                ));
+                frame.write_submitted_at_ns = now_ns;
+                self.inflight.push_back(frame);
+            }
+        }
+        for (iovec, frame) in self.inflight_iovecs.iter_mut().zip(&self.inflight) {
+            *iovec = libc::iovec {
+                iov_base: frame.remaining_ptr() as *mut libc::c_void,
+                iov_len: frame.remaining(),
+            };
+        }
+        self.inflight_iov_count = self.inflight.len();
+        self.inflight_iov_count
+    }
+
+    /// Copy the frames the last [`Self::gather_write`] gathered into `buf`, which has room for
+    /// `MAX_IOVECS_PER_WRITE` whole frames, returning the bytes copied.
+    fn copy_gathered(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for frame in self.inflight.iter().take(self.inflight_iov_count) {
+            let bytes = frame.remaining_bytes();
+            buf[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        }
+        len
+    }
+
+    /// Account for `written` bytes of the gathered frames, recycling every frame written whole.
+    fn complete_write(&mut self, written: usize) {
+        self.backlog_bytes = self.backlog_bytes.saturating_sub(written);
+        let mut remaining = written;
+        while remaining > 0 {
+            let Some(frame) = self.inflight.front_mut() else {
+                break;
+            };
+            let frame_remaining = frame.remaining();
+            if remaining >= frame_remaining {
+                remaining -= frame_remaining;
+                if let Some(frame) = self.inflight.pop_front() {
+                    let now_ns = monotonic_now_ns();
+                    metrics::record_write_submit_to_written(Duration::from_nanos(
+                        now_ns.saturating_sub(frame.write_submitted_at_ns),
+                    ));
+                    metrics::record_receive_to_written(Duration::from_nanos(
+                        now_ns.saturating_sub(frame.received_at_ns),
+                    ));
+                    self.recycle_frame(frame);
+                }
+                self.accounting.on_written(1);
+            } else {
+                frame.offset += remaining;
+                remaining = 0;
+            }
+        }
+        self.inflight_iov_count = 0;
+    }
+
+    fn recycle_frame(&mut self, frame: Box<ResponseFrame>) {
+        if self.spare_frames.len() < MAX_SPARE_FRAMES {
+            self.spare_frames.push(frame);
+        }
+    }
+
+    /// Append the response for `request_seq`, then any deferred frames it unblocks.
+    fn push_response(&mut self, request_seq: u64, frame: Box<ResponseFrame>) {
+        self.accounting
+            .on_response(request_seq, self.next_response_seq);
+        self.push_in_order(request_seq, frame);
+    }
+
+    fn push_in_order(&mut self, request_seq: u64, frame: Box<ResponseFrame>) {
+        self.backlog_bytes += frame.len;
+        self.queue.push_back(frame);
+        self.next_response_seq = request_seq + 1;
+        while self
+            .deferred
+            .front()
+            .is_some_and(|(seq, _)| *seq == self.next_response_seq)
+        {
+            let (_, frame) = self.deferred.pop_front().unwrap();
+            self.queue.push_back(frame);
+            self.next_response_seq += 1;
+        }
+        self.ready_queued = true;
+    }
+
+    /// Answer `request_seq` with an overload frame, keeping it behind earlier responses that
+    /// are still in flight.
+    fn push_overload(&mut self, request_seq: u64, reason: OverloadReason, retry_after_ms: u16) {
+        let mut bytes = [0u8; OVERLOAD_FRAME_BYTES];
+        protocol::encode_overload(reason, retry_after_ms, &mut bytes);
+        let frame = self.frame(request_seq, monotonic_now_ns(), FrameBody::Encoded(&bytes));
+        self.push_local(request_seq, frame);
+    }
+
+    /// Answer `request_seq` with a parse error frame, in order like [`Self::push_overload`].
+    fn push_parse_error(&mut self, request_seq: u64, error: &ParseError) {
+        let mut bytes = [0u8; PARSE_ERROR_FRAME_BYTES];
+        protocol::encode_parse_error(error, &mut bytes);
+        let frame = self.frame(request_seq, monotonic_now_ns(), FrameBody::Encoded(&bytes));
+        self.push_local(request_seq, frame);
+    }
+
+    /// Answer `request_seq` with scores computed on this thread, in order like
+    /// [`Self::push_overload`].
+    fn push_inline(&mut self, request_seq: u64, scores: &[f32]) {
+        let mut frame = self.frame(request_seq, monotonic_now_ns(), FrameBody::Results(scores));
+        frame.received_at_ns = self.received_at_ns;
+        self.push_local(request_seq, frame);
+    }
+
+    fn push_local(&mut self, request_seq: u64, frame: Box<ResponseFrame>) {
+        self.accounting.on_local_frame();
+        if request_seq == self.next_response_seq {
+            self.push_in_order(request_seq, frame);
+        } else {
+            self.backlog_bytes += frame.len;
+            self.deferred.push_back((request_seq, frame));
+        }
+    }
+
+    /// Apply the write-backlog `limit` after queuing frames: reads pause above it and the
+    /// connection is evicted above twice it. Returns `true` if the connection should be evicted.
+    fn check_write_backlog(&mut self, limit: usize) -> bool {
+        if self.backlog_bytes > limit.saturating_mul(2) {
+            return true;
+        }
+        if !self.read_paused && self.backlog_bytes > limit {
+            self.read_paused = true;
+            metrics::inc_write_backlog_paused();
+        }
+        false
+    }
+
+    /// Resume reads once the backlog has fallen to half of `limit`. Returns `true` on the
+    /// transition.
+    fn maybe_resume_reads(&mut self, limit: usize) -> bool {
+        if self.read_paused && self.backlog_bytes <= limit / 2 {
+            self.read_paused = false;
+            return true;
+        }
+        false
+    }
+
     fn should_reap(&self, registry: &ConnectionRegistry) -> bool {
         self.read_closed
             && self.write_closed
             && !self.write_inflight
             && self.queue.is_empty()
+            && self.deferred.is_empty()
             && self.inflight.is_empty()
             && registry.is_retired(self.conn)
     }
@@ -177,6 +949,30 @@ pub struct IngressThread<P> {
     response_queue: Arc<ResponseQueue>,
     publish_gate: Arc<Mutex<()>>,
     registry: Arc<ConnectionRegistry>,
+    max_connections: usize,
+    prefixes: FramePrefixes,
+    request_decoder: &'static dyn RequestDecoder,
+    response_serializer: &'static dyn ResponseSerializer,
+    fixed_point: Option<FixedPointResponse>,
+    feature_dim: usize,
+    max_vectors: usize,
+    inline: Option<InlineFastPath>,
+    schema: Option<Arc<FeatureSchema>>,
+    overflow: RequestOverflow,
+    admission: Option<Arc<LargeRequestAdmission>>,
+    circuit: Option<Arc<CircuitBreaker>>,
+    embeddings: Option<EmbeddingReader>,
+    replay: Option<Arc<ReplaySessions>>,
+    idle_timeout: Option<Duration>,
+    expiry: ResponseExpiry,
+    slot_quarantine: Option<Duration>,
+    read_buffer_ring: Option<u16>,
+    adaptive_reads: bool,
+    fixed_buffers: bool,
+    io_backend: IoBackend,
+    limits: Arc<SoftLimits>,
+    control: Arc<IoThreadControl>,
+    inference_control: Option<ControlSender>,
 }
 
 impl<P> IngressThread<P>
@@ -200,23 +996,371 @@ where
             response_queue,
             publish_gate,
             registry,
+            max_connections: SLAB_CAPACITY,
+            prefixes: FramePrefixes::default(),
+            request_decoder: &protocol::NATIVE_REQUEST,
+            response_serializer: &protocol::NATIVE_RESPONSE,
+            fixed_point: None,
+            feature_dim: FEATURE_DIM,
+            max_vectors: MAX_VECTORS_PER_REQUEST,
+            inline: None,
+            schema: None,
+            overflow: RequestOverflow::new(REQUEST_OVERFLOW_CAPACITY),
+            admission: None,
+            circuit: None,
+            embeddings: None,
+            replay: None,
+            idle_timeout: None,
+            expiry: ResponseExpiry::default(),
+            slot_quarantine: None,
+            read_buffer_ring: None,
+            adaptive_reads: false,
+            fixed_buffers: false,
+            io_backend: IoBackend::Auto,
+            limits: Arc::new(SoftLimits::default()),
+            control: Arc::new(IoThreadControl::new()),
+            inference_control: None,
         }
     }
 
+    /// Cap concurrent connections on this thread below `SLAB_CAPACITY`. Connections accepted
+    /// past the cap are closed immediately.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        assert!(
+            (1..=SLAB_CAPACITY).contains(&max_connections),
+            "max_connections must be in 1..={SLAB_CAPACITY}"
+        );
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Prefix every response and overload frame with the `request_seq` it answers, so clients
+    /// can verify ordering per connection.
+    pub fn with_request_seq_echo(mut self) -> Self {
+        self.prefixes.request_seq = true;
+        self
+    }
+
+    /// Prefix every frame with its length, so clients can skip frames they do not understand.
+    pub fn with_length_prefix(mut self) -> Self {
+        self.prefixes.length = true;
+        self
+    }
+
+    /// Follow every response with a status byte per result; see [`protocol::VectorStatus`].
+    pub fn with_vector_status(mut self) -> Self {
+        self.prefixes.vector_status = true;
+        self
+    }
+
+    /// Read requests with `decoder` instead of the native format. Connections do not negotiate
+    /// a format yet, so each takes its IO thread's when it is accepted.
+    pub fn with_request_decoder(mut self, decoder: &'static dyn RequestDecoder) -> Self {
+        self.request_decoder = decoder;
+        self
+    }
+
+    /// Write scores with `serializer` instead of the native format. Connections do not
+    /// negotiate a format yet, so each takes its IO thread's when it is accepted.
+    pub fn with_response_serializer(mut self, serializer: &'static dyn ResponseSerializer) -> Self {
+        self.response_serializer = serializer;
+        self
+    }
+
+    /// Let requests ask for their scores as fixed-point integers, `scale` times the score; see
+    /// [`FixedPointResponse`]. Other requests are still answered in the thread's format.
+    pub fn with_fixed_point_scale(mut self, scale: f32) -> Self {
+        assert!(
+            scale.is_finite() && scale > 0.0,
+            "fixed-point scale must be positive"
+        );
+        self.fixed_point = Some(FixedPointResponse { scale });
+        self
+    }
+
+    /// Read a client-chosen `request_id` before every request and echo it before the frame
+    /// answering it.
+    pub fn with_request_ids(mut self) -> Self {
+        self.prefixes.request_id = true;
+        self
+    }
+
+    /// Read requests of `feature_dim` features per vector instead of `FEATURE_DIM`.
+    pub fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+        assert!(
+            (1..=MAX_FEATURE_DIM).contains(&feature_dim),
+            "feature_dim must be in 1..={MAX_FEATURE_DIM}"
+        );
+        self.feature_dim = feature_dim;
+        self
+    }
+
+    /// Treat requests of more than `max_vectors` vectors as malformed, below the protocol's
+    /// `MAX_VECTORS_PER_REQUEST`.
+    pub fn with_max_vectors(mut self, max_vectors: usize) -> Self {
+        assert!(
+            (1..=MAX_VECTORS_PER_REQUEST).contains(&max_vectors),
+            "max_vectors must be in 1..={MAX_VECTORS_PER_REQUEST}"
+        );
+        self.max_vectors = max_vectors;
+        self
+    }
+
+    /// Score single-vector requests on this thread while the request ring is quiet; see
+    /// [`InlineFastPath`].
+    pub fn with_inline_fast_path(mut self, fast_path: InlineFastPath) -> Self {
+        self.inline = Some(fast_path);
+        self
+    }
+
+    /// Check every request vector against `schema`, counting violations per feature.
+    pub fn with_feature_schema(mut self, schema: Arc<FeatureSchema>) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Hold back large requests `admission` does not admit; see [`LargeRequestAdmission`].
+    pub fn with_large_request_admission(mut self, admission: Arc<LargeRequestAdmission>) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Answer requests with overload frames while `circuit` is open; see [`CircuitBreaker`].
+    pub fn with_circuit_breaker(mut self, circuit: Arc<CircuitBreaker>) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
+
+    /// Expand the ids at the end of each request vector to their rows of the current table in
+    /// `store`; see [`crate::embedding`].
+    pub fn with_embeddings(mut self, store: Arc<EmbeddingStore>) -> Self {
+        self.embeddings = Some(store.reader());
+        self
+    }
+
+    /// Open every connection with a replay hello and keep each session's recent frames in
+    /// `sessions`; see [`crate::server::replay`].
+    pub fn with_replay_sessions(mut self, sessions: Arc<ReplaySessions>) -> Self {
+        self.replay = Some(sessions);
+        self
+    }
+
+    /// Close connections that have read no request and written no response for `timeout`, once
+    /// everything they sent is answered, reclaiming their connection slot and read SQE. Checked at
+    /// least every second, so a connection may outlive `timeout` by up to that long.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero(), "idle timeout must be > 0");
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Answer with a timeout frame, instead of its scores, a response that arrives `timeout` or
+    /// more after its request was published.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero(), "request timeout must be > 0");
+        self.expiry.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Answer with a timeout frame, instead of its scores, a response that waited `ttl` or more
+    /// in the response queue, as it may during a stall of this thread: its client has likely
+    /// given up on it, so only the few bytes of the timeout frame are spent on it.
+    pub fn with_response_ttl(mut self, ttl: Duration) -> Self {
+        assert!(!ttl.is_zero(), "response TTL must be > 0");
+        self.expiry.response_ttl = Some(ttl);
+        self
+    }
+
+    /// Hold a closed connection's slot back for `quarantine` before a new connection reuses its
+    /// `conn_id`; see [`ConnectionSlots`].
+    pub fn with_slot_quarantine(mut self, quarantine: Duration) -> Self {
+        assert!(!quarantine.is_zero(), "slot quarantine must be > 0");
+        self.slot_quarantine = Some(quarantine);
+        self
+    }
+
+    /// Size each connection's reads by its recent bytes per read, from `MIN_READ_SIZE` up to
+    /// the free buffer, instead of always asking for the whole free buffer.
+    pub fn with_adaptive_reads(mut self) -> Self {
+        self.adaptive_reads = true;
+        self
+    }
+
+    /// Read from `entries` shared provided buffers instead of giving every connection its own
+    /// read buffer while it waits; see [`ReadBufferRing`].
+    pub fn with_read_buffer_ring(mut self, entries: u16) -> Self {
+        assert!(
+            entries.is_power_of_two() && entries <= 1 << 15,
+            "read buffer ring entries must be a power of two up to 32768"
+        );
+        self.read_buffer_ring = Some(entries);
+        self
+    }
+
+    /// Read into each connection's own buffer and write from a per-slot buffer, both registered
+    /// with the ring, instead of pinning pages on every read and write; see [`FixedBuffers`].
+    /// Ignored alongside `with_read_buffer_ring`, whose connections share their read buffers.
+    pub fn with_fixed_buffers(mut self) -> Self {
+        self.fixed_buffers = true;
+        self
+    }
+
+    /// Drive sockets with `backend`; see [`IoBackend`]. Provided and registered buffers are
+    /// io_uring only, so epoll ignores `with_read_buffer_ring` and `with_fixed_buffers`.
+    pub fn with_io_backend(mut self, backend: IoBackend) -> Self {
+        self.io_backend = backend;
+        self
+    }
+
+    /// Park up to `capacity` requests that find the request ring full, instead of the default
+    /// `REQUEST_OVERFLOW_CAPACITY`; `0` leaves them in their read buffers. See
+    /// [`RequestOverflow`].
+    pub fn with_request_overflow(mut self, capacity: usize) -> Self {
+        self.overflow = RequestOverflow::new(capacity);
+        self
+    }
+
+    /// Answer requests that find the request ring full with an overload frame suggesting a
+    /// `retry_after_ms` backoff, instead of holding them until a slot frees up.
+    pub fn with_overload_rejection(self, retry_after_ms: u16) -> Self {
+        self.limits
+            .set_overload_retry_after_ms(Some(retry_after_ms));
+        self
+    }
+
+    /// Bound per-connection response bytes queued behind a client that is not reading. Above
+    /// `bytes` the connection stops reading new requests; at twice `bytes` it is closed.
+    pub fn with_write_backlog_limit(self, bytes: usize) -> Self {
+        assert!(bytes > 0, "write backlog limit must be > 0");
+        self.limits.set_write_backlog_bytes(Some(bytes));
+        self
+    }
+
+    /// Read overload rejection and the write backlog limit from `limits` on every use, so a
+    /// config reload changes them on a running thread. Replaces the two builders above.
+    pub fn with_soft_limits(mut self, limits: Arc<SoftLimits>) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Tell the inference thread through `control` when connections close.
+    pub fn with_inference_control(mut self, control: ControlSender) -> Self {
+        self.inference_control = Some(control);
+        self
+    }
+
+    /// Share this thread's control handle so another thread can drain it.
+    pub fn with_control(mut self, control: Arc<IoThreadControl>) -> Self {
+        self.control = control;
+        self
+    }
+
+    /// Run the event loop. Returns only after a removal or shutdown requested through the
+    /// thread's `IoThreadControl` has drained every connection.
     pub fn run(mut self) {
+        if resolve_io_backend(self.io_backend) == IoBackend::Epoll {
+            return epoll::run(self);
+        }
+        // Until this thread exits, the inference thread waits for room in a full queue.
+        let _consumer = self.response_queue.attach_consumer();
         let mut ring = IoUring::new(4096).expect("io_uring creation failed");
-        let mut conns: Slab<Connection> = Slab::with_capacity(SLAB_CAPACITY);
-        let mut cqe_buf: Vec<(u64, i32)> = Vec::new();
+        let mut accept_ring = AcceptRing::new().expect("accept io_uring creation failed");
+        if let Some(entries) = self.read_buffer_ring
+            && let Err(e) = ring.register_read_buffers(entries)
+        {
+            eprintln!(
+                "disrust: io-{} cannot register a read buffer ring ({e}), reading into per-connection buffers",
+                self.thread_id
+            );
+        }
+        if self.fixed_buffers
+            && ring.read_buffers.is_none()
+            && let Err(e) = ring.register_fixed_buffers(SLAB_CAPACITY)
+        {
+            eprintln!(
+                "disrust: io-{} cannot register fixed buffers ({e}), reading and writing unregistered",
+                self.thread_id
+            );
+        }
+        let mut conns = ConnectionSlots::new(SLAB_CAPACITY);
+        if let Some(quarantine) = self.slot_quarantine {
+            conns = conns.with_quarantine(quarantine);
+        }
+        let mut cqe_buf: Vec<(u64, i32, u32)> = Vec::new();
         let mut parse_queue: VecDeque<u16> = VecDeque::new();
         let mut parse_submit_budget = 0u8;
-        submit_accept(&mut ring, self.listen_fd);
-        submit_notify(&mut ring, self.response_queue.notify_fd());
+        let mut poisoned = false;
+        let mut probes = Probes::default();
+        submit_accept(&mut accept_ring.ring, self.listen_fd, accept_ring.multishot);
+        submit_control(&mut accept_ring.ring, self.control.notify_fd());
+        accept_ring.ring.submit_pushed();
+        submit_accept_ring_poll(&mut ring, accept_ring.ring.fd());
+        // The inference thread posts wakeups to this ring where its queue was built for that,
+        // and otherwise they come from polling the queue's eventfd, which a posting queue
+        // still signals for a wakeup it could not post. The loop drains the queue before it
+        // first waits either way.
+        let notify_fd = self
+            .response_queue
+            .notify_fd()
+            .expect("IO thread response queue must have a pollable notifier");
+        submit_notify(&mut ring, notify_fd);
+        let posted_user_data = encode_user_data(OP_NOTIFY, NOTIFY_POSTED);
+        let ring_wakeups = self
+            .response_queue
+            .post_wakeups_to(ring.fd(), posted_user_data);
+        if ring_wakeups.is_some() {
+            ring.posted = Some(posted_user_data);
+        }
+        // Read by the kernel when each timeout SQE is submitted, so it must not move.
+        let idle_sweep = self
+            .idle_timeout
+            .map(|timeout| Timespec::from(timeout.min(IDLE_SWEEP_INTERVAL)));
+        if let Some(interval) = &idle_sweep {
+            submit_idle_sweep(&mut ring, interval);
+        }
 
         loop {
+            if self.control.remove_requested() && self.control.state() == IoThreadState::Drained {
+                return;
+            }
+            let iteration_start = monotonic_now_ns();
+
             let phase_start = monotonic_now_ns();
-            drain_response_queue(&mut conns, &self.response_queue);
+            drain_response_queue(
+                &mut conns,
+                &self.response_queue,
+                &self.registry,
+                self.limits.write_backlog_bytes(),
+                self.expiry,
+            );
             metrics::add_io_response_drain(monotonic_now_ns().saturating_sub(phase_start));
 
+            if self.response_queue.is_poisoned() {
+                if !poisoned {
+                    poisoned = true;
+                    eprintln!(
+                        "disrust: io-{} inference thread gone, closing {} connection(s)",
+                        self.thread_id,
+                        conns.len()
+                    );
+                    accept_ring.stop_accepting();
+                }
+                // Repeated so a connection accepted while the cancel was in flight fails too.
+                fail_connections(&mut conns, &self.registry);
+            }
+
+            if !self.overflow.is_empty() {
+                drain_request_overflow(
+                    &mut conns,
+                    &mut parse_queue,
+                    &mut self.overflow,
+                    &mut self.producer,
+                    &mut self.allocator,
+                    &self.publish_gate,
+                    self.inline.as_ref(),
+                );
+            }
+
             let phase_start = monotonic_now_ns();
             submit_ready_writes(&mut ring, &mut conns, &self.registry);
             metrics::add_io_write_submit(monotonic_now_ns().saturating_sub(phase_start));
@@ -234,9 +1378,23 @@ where
                     &mut self.allocator,
                     &self.publish_gate,
                     &self.registry,
+                    &self.limits,
+                    self.inline.as_ref(),
+                    self.schema.as_deref(),
+                    &mut self.overflow,
+                    self.admission.as_deref(),
+                    self.circuit.as_deref(),
+                    self.embeddings.as_mut().map(EmbeddingReader::current),
+                    self.replay.as_deref(),
                     key,
                 );
                 metrics::add_io_parse(monotonic_now_ns().saturating_sub(phase_start));
+                // Accepts and control events are not held up by a long run of buffered parses.
+                let accept_cqes = self.handle_accept_ring(&mut ring, &mut accept_ring, &mut conns);
+                metrics::record_io_ring_cqes(0, accept_cqes as u64);
+                if self.control.shutdown_requested() {
+                    close_answered_connections(&mut conns, &self.registry, &self.overflow);
+                }
                 // Queued parse work can enqueue follow-on reads and shard-local writes.
                 // Submit often enough to preserve progress, but batch a few parse iterations
                 // together so the hot path does not pay an `io_uring_enter` syscall on every
@@ -246,29 +1404,43 @@ where
                     ring.submit();
                     parse_submit_budget = 0;
                 }
-                reap_retired_connections(&mut conns, &self.registry);
+                reap_retired_connections(
+                    &mut conns,
+                    &self.registry,
+                    &self.control,
+                    self.inference_control.as_ref(),
+                );
+                publish_control_state(&self.control, &conns, accept_ring.inflight);
+                metrics::record_io_iteration(elapsed_since_ns(iteration_start));
                 continue;
             }
 
             parse_submit_budget = 0;
             let phase_start = monotonic_now_ns();
-            ring.wait(1);
-            metrics::add_io_wait(monotonic_now_ns().saturating_sub(phase_start));
+            if self.overflow.is_empty() {
+                ring.wait(1);
+            } else {
+                // Parked requests are retried every iteration, so only poll for completions.
+                ring.submit();
+            }
+            let wait_ns = monotonic_now_ns().saturating_sub(phase_start);
+            metrics::add_io_wait(wait_ns);
             cqe_buf.clear();
             ring.drain_cqes_into(&mut cqe_buf);
+            let dropped_cqes = ring.take_dropped_cqes();
 
             let phase_start = monotonic_now_ns();
-            for &(user_data, result) in &cqe_buf {
+            let accept_cqes = self.handle_accept_ring(&mut ring, &mut accept_ring, &mut conns);
+            metrics::record_io_wake(
+                wake_reasons(&cqe_buf) | wake_reasons(&accept_ring.cqes[..accept_cqes]),
+            );
+            metrics::record_io_ring_cqes(cqe_buf.len() as u64, accept_cqes as u64);
+            for &(user_data, result, flags) in &cqe_buf {
                 let (op, data) = decode_user_data(user_data);
                 match op {
-                    OP_ACCEPT => handle_accept(
-                        &mut ring,
-                        &mut conns,
-                        result,
-                        self.thread_id,
-                        self.listen_fd,
-                        &self.registry,
-                    ),
+                    OP_READ if take_cancelled_read(&mut conns, data as u16, result) => {
+                        submit_read(&mut ring, &mut conns, data as u16);
+                    }
                     OP_READ => handle_read(
                         &mut ring,
                         &mut conns,
@@ -277,107 +1449,634 @@ where
                         &mut self.allocator,
                         &self.publish_gate,
                         &self.registry,
+                        &self.limits,
+                        self.inline.as_ref(),
+                        self.schema.as_deref(),
+                        &mut self.overflow,
+                        self.admission.as_deref(),
+                        self.circuit.as_deref(),
+                        self.embeddings.as_mut().map(EmbeddingReader::current),
+                        self.replay.as_deref(),
+                        &self.control,
                         data as u16,
                         result,
+                        cqueue::buffer_select(flags),
                     ),
-                    OP_WRITE => handle_write(&mut conns, &self.registry, data as u16, result),
-                    OP_NOTIFY => handle_notify(&mut ring, self.response_queue.notify_fd(), result),
-                    _ => {}
+                    OP_WRITE => {
+                        let key = data as u16;
+                        let limit = self.limits.write_backlog_bytes();
+                        // A write a probe cancelled wrote nothing and is resubmitted.
+                        let result = if take_cancelled_write(&mut conns, key, result) {
+                            0
+                        } else {
+                            result
+                        };
+                        if handle_write(&mut conns, &self.registry, key, result, limit) {
+                            resume_reads(&mut ring, &mut conns, &mut parse_queue, key);
+                        }
+                    }
+                    OP_NOTIFY if data == NOTIFY_POSTED => {}
+                    OP_NOTIFY => {
+                        probes.complete(OP_NOTIFY);
+                        handle_notify(&mut ring, notify_fd, result);
+                    }
+                    OP_ACCEPT_RING => {
+                        probes.complete(OP_ACCEPT_RING);
+                        submit_accept_ring_poll(&mut ring, accept_ring.ring.fd());
+                    }
+                    OP_IDLE_SWEEP => {
+                        probes.complete(OP_IDLE_SWEEP);
+                        if let (Some(timeout), Some(interval)) = (self.idle_timeout, &idle_sweep) {
+                            close_idle_connections(
+                                &mut conns,
+                                &self.registry,
+                                &self.overflow,
+                                timeout,
+                                monotonic_now_ns(),
+                            );
+                            submit_idle_sweep(&mut ring, interval);
+                        }
+                    }
+                    OP_PROBE if result == -libc::ENOENT => {
+                        // Nothing was left to cancel: the probed op's completion was dropped.
+                        let (target, key) = ((data >> 16) as u64, data as u16);
+                        match target {
+                            OP_READ | OP_WRITE => lost_connection_completion(
+                                &mut conns,
+                                &self.registry,
+                                target,
+                                key,
+                                self.limits.write_backlog_bytes(),
+                            ),
+                            OP_NOTIFY if probes.take(OP_NOTIFY) => {
+                                submit_notify(&mut ring, notify_fd);
+                            }
+                            OP_ACCEPT_RING if probes.take(OP_ACCEPT_RING) => {
+                                submit_accept_ring_poll(&mut ring, accept_ring.ring.fd());
+                            }
+                            OP_IDLE_SWEEP if probes.take(OP_IDLE_SWEEP) => {
+                                if let Some(interval) = &idle_sweep {
+                                    submit_idle_sweep(&mut ring, interval);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    OP_CANCEL | OP_PROBE => {}
+                    _ => unknown_cqe(self.thread_id, user_data, result),
+                }
+            }
+            if dropped_cqes > 0 {
+                metrics::add_io_cqes_dropped(dropped_cqes as u64);
+                eprintln!(
+                    "disrust: io-{} data ring dropped {dropped_cqes} completion(s), probing pending ops",
+                    self.thread_id
+                );
+                probe_connections(&mut ring, &mut conns);
+                for op in [OP_NOTIFY, OP_ACCEPT_RING] {
+                    probes.start(&mut ring, op);
+                }
+                if idle_sweep.is_some() {
+                    probes.start(&mut ring, OP_IDLE_SWEEP);
                 }
             }
             metrics::add_io_cqe(monotonic_now_ns().saturating_sub(phase_start));
 
-            reap_retired_connections(&mut conns, &self.registry);
+            if self.control.shutdown_requested() {
+                close_answered_connections(&mut conns, &self.registry, &self.overflow);
+            }
+            reap_retired_connections(
+                &mut conns,
+                &self.registry,
+                &self.control,
+                self.inference_control.as_ref(),
+            );
+            if publish_control_state(&self.control, &conns, accept_ring.inflight) {
+                eprintln!("disrust: io-{} drained", self.thread_id);
+            }
+            metrics::record_io_iteration(
+                elapsed_since_ns(iteration_start).saturating_sub(Duration::from_nanos(wait_ns)),
+            );
+        }
+    }
+}
+
+impl<P> IngressThread<P>
+where
+    P: Producer<InferenceEvent>,
+{
+    /// Handle whatever the accept ring has completed, without waiting. Returns how many CQEs it
+    /// had; they stay in `accept_ring.cqes` until the next call.
+    fn handle_accept_ring(
+        &mut self,
+        ring: &mut IoUring,
+        accept_ring: &mut AcceptRing,
+        conns: &mut ConnectionSlots<Connection>,
+    ) -> usize {
+        let mut cqes = std::mem::take(&mut accept_ring.cqes);
+        cqes.clear();
+        accept_ring.ring.drain_cqes_into(&mut cqes);
+        let dropped_cqes = accept_ring.ring.take_dropped_cqes();
+        for &(user_data, result, flags) in &cqes {
+            let (op, data) = decode_user_data(user_data);
+            match op {
+                OP_ACCEPT => {
+                    handle_accept(
+                        ring,
+                        conns,
+                        result,
+                        self.thread_id,
+                        self.max_connections,
+                        self.prefixes,
+                        self.request_decoder,
+                        self.response_serializer,
+                        self.fixed_point,
+                        self.feature_dim,
+                        self.max_vectors,
+                        self.adaptive_reads,
+                        self.replay.is_some(),
+                        &self.registry,
+                    );
+                    let more = cqueue::more(flags);
+                    if !more {
+                        accept_ring.probes.complete(OP_ACCEPT);
+                    }
+                    self.accept_ended(accept_ring, result, more);
+                }
+                OP_CONTROL => {
+                    accept_ring.probes.complete(OP_CONTROL);
+                    handle_control(&mut accept_ring.ring, self.control.notify_fd(), result);
+                    if self.control.take_listing_request() {
+                        self.control
+                            .publish_connections(conns.iter().map(|(_, c)| c.info()).collect());
+                    }
+                    if accept_ring.accepting && self.control.state() == IoThreadState::Draining {
+                        if self.control.shutdown_requested() {
+                            eprintln!(
+                                "disrust: io-{} shutting down, answering {} connection(s)",
+                                self.thread_id,
+                                conns.len()
+                            );
+                        } else {
+                            eprintln!("disrust: io-{} draining", self.thread_id);
+                        }
+                        accept_ring.stop_accepting();
+                    }
+                }
+                OP_PROBE if result == -libc::ENOENT => match (data >> 16) as u64 {
+                    // The accept's final completion was dropped; finish it as a cancelled one.
+                    OP_ACCEPT if accept_ring.probes.take(OP_ACCEPT) => {
+                        self.accept_ended(accept_ring, -libc::ECANCELED, false);
+                    }
+                    OP_CONTROL if accept_ring.probes.take(OP_CONTROL) => {
+                        submit_control(&mut accept_ring.ring, self.control.notify_fd());
+                    }
+                    _ => {}
+                },
+                OP_CANCEL | OP_PROBE => {}
+                _ => unknown_cqe(self.thread_id, user_data, result),
+            }
+        }
+        if dropped_cqes > 0 {
+            metrics::add_io_cqes_dropped(dropped_cqes as u64);
+            eprintln!(
+                "disrust: io-{} accept ring dropped {dropped_cqes} completion(s), probing pending ops",
+                self.thread_id
+            );
+            if accept_ring.inflight {
+                accept_ring.probes.start(&mut accept_ring.ring, OP_ACCEPT);
+            }
+            accept_ring.probes.start(&mut accept_ring.ring, OP_CONTROL);
+        }
+        accept_ring.ring.submit_pushed();
+        let handled = cqes.len();
+        accept_ring.cqes = cqes;
+        handled
+    }
+
+    /// Rearm or stop accepting after an accept completion, `more` if it stays armed.
+    fn accept_ended(&self, accept_ring: &mut AcceptRing, result: i32, more: bool) {
+        match next_accept(accept_ring.multishot, result, more, accept_ring.accepting) {
+            AcceptNext::Armed => {}
+            AcceptNext::Submit { multishot } => {
+                if accept_ring.multishot && !multishot {
+                    eprintln!(
+                        "disrust: io-{} kernel lacks multishot accept, accepting one connection per SQE",
+                        self.thread_id
+                    );
+                }
+                accept_ring.multishot = multishot;
+                submit_accept(&mut accept_ring.ring, self.listen_fd, multishot);
+            }
+            AcceptNext::Stopped => {
+                accept_ring.inflight = false;
+                unsafe { libc::close(self.listen_fd) };
+            }
+        }
+    }
+}
+
+/// The backend `requested` comes to: `Auto` is io_uring if this process can create a ring, which
+/// old kernels and seccomp policies that block `io_uring_setup` prevent, and epoll otherwise.
+pub(crate) fn resolve_io_backend(requested: IoBackend) -> IoBackend {
+    match requested {
+        IoBackend::Auto => match IoUring::new(2) {
+            Ok(_) => IoBackend::IoUring,
+            Err(e) => {
+                eprintln!("disrust: io_uring unavailable ({e}), using epoll");
+                IoBackend::Epoll
+            }
+        },
+        backend => backend,
+    }
+}
+
+/// Mask of `metrics::wake` bits for the completion kinds in one wake's CQEs.
+fn wake_reasons(cqes: &[(u64, i32, u32)]) -> u8 {
+    cqes.iter().fold(0, |reasons, &(user_data, _, _)| {
+        reasons
+            | match decode_user_data(user_data).0 {
+                OP_ACCEPT => metrics::wake::ACCEPT,
+                OP_READ => metrics::wake::READ,
+                OP_WRITE => metrics::wake::WRITE,
+                OP_NOTIFY => metrics::wake::NOTIFY,
+                OP_CONTROL => metrics::wake::CONTROL,
+                _ => 0,
+            }
+    })
+}
+
+fn drain_response_queue(
+    conns: &mut ConnectionSlots<Connection>,
+    response_queue: &Arc<ResponseQueue>,
+    registry: &Arc<ConnectionRegistry>,
+    write_backlog_limit: usize,
+    expiry: ResponseExpiry,
+) {
+    // Responses are copied out of the queue slot straight into the frame the write will send.
+    while response_queue
+        .pop_with(|response| {
+            deliver_response(conns, registry, response, write_backlog_limit, expiry)
+        })
+        .is_some()
+    {}
+}
+
+/// How late a response may reach its IO thread before it is answered with a timeout frame.
+#[derive(Debug, Clone, Copy, Default)]
+struct ResponseExpiry {
+    /// Counted from the request's publish to the request ring.
+    request_timeout: Option<Duration>,
+    /// Counted from the inference thread queuing the response.
+    response_ttl: Option<Duration>,
+}
+
+/// Whether a response is answered with its scores or a timeout frame, and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freshness {
+    Fresh,
+    /// The inference thread gave up on the request without running it.
+    TimedOut,
+    /// Its scores arrived too late to be worth sending.
+    Stale,
+}
+
+impl ResponseExpiry {
+    fn check(&self, response: &ResponseReady) -> Freshness {
+        if response.timed_out {
+            return Freshness::TimedOut;
+        }
+        let outlived = |limit: Option<Duration>, since_ns: u64| {
+            limit.is_some_and(|limit| elapsed_since_ns(since_ns) >= limit)
+        };
+        if outlived(self.request_timeout, response.published_at_ns)
+            || outlived(self.response_ttl, response.processed_at_ns)
+        {
+            Freshness::Stale
+        } else {
+            Freshness::Fresh
+        }
+    }
+}
+
+fn deliver_response(
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    response: &ResponseReady,
+    write_backlog_limit: usize,
+    expiry: ResponseExpiry,
+) {
+    let Some(conn) = conns.get_mut(response.conn.conn_id as usize) else {
+        return;
+    };
+    if conn.conn != response.conn {
+        return;
+    }
+    if conn.write_closed || conn.evicted {
+        conn.accounting.on_discarded();
+        if conn.replay.is_some() {
+            // Kept for the client's next connection even though this one cannot send it.
+            let fresh = expiry.check(response) == Freshness::Fresh;
+            let frame = conn.response_frame(response, !fresh);
+            conn.recycle_frame(frame);
+            maybe_mark_read_closed(registry, conn);
+        }
+        return;
+    }
+    let freshness = expiry.check(response);
+    match freshness {
+        Freshness::Fresh => {}
+        Freshness::TimedOut => metrics::inc_overload_rejected(OverloadReason::Timeout),
+        // Counted apart from overload: the request was served, only too slowly.
+        Freshness::Stale => metrics::inc_responses_stale(),
+    }
+    let frame = conn.response_frame(response, freshness != Freshness::Fresh);
+    conn.push_response(response.request_seq, frame);
+    if conn.check_write_backlog(write_backlog_limit) {
+        evict_slow_consumer(registry, conn);
+    }
+}
+
+/// Close a connection whose client stopped reading. Queued frames are dropped and the socket is
+/// shut down, so an in-flight write fails and releases the frames it still borrows.
+fn evict_slow_consumer(registry: &Arc<ConnectionRegistry>, conn: &mut Connection) {
+    metrics::inc_slow_consumer_evicted();
+    eprintln!(
+        "io-{}: write backlog {} bytes over limit, evicting conn {}",
+        conn.conn.shard_id(),
+        conn.backlog_bytes,
+        conn.conn.conn_id
+    );
+    abort_connection(registry, conn);
+}
+
+/// Fail every connection once the response queue is poisoned: no response will ever arrive, so
+/// clients see their connection close instead of hanging.
+fn fail_connections(conns: &mut ConnectionSlots<Connection>, registry: &Arc<ConnectionRegistry>) {
+    for (_, conn) in conns.iter_mut() {
+        if !conn.evicted {
+            abort_connection(registry, conn);
         }
     }
 }
 
-fn drain_response_queue(conns: &mut Slab<Connection>, response_queue: &Arc<ResponseQueue>) {
-    while let Some(response) = response_queue.pop() {
-        let Some(conn) = conns.get_mut(response.conn.conn_id as usize) else {
+/// Shut the socket down and drop everything queued for it. An in-flight write fails and
+/// releases the frames it still borrows, and the pending read completes, closing the connection.
+fn abort_connection(registry: &Arc<ConnectionRegistry>, conn: &mut Connection) {
+    unsafe { libc::shutdown(conn.fd, libc::SHUT_RDWR) };
+    conn.evicted = true;
+    conn.read_closed = true;
+    conn.read_paused = false;
+    conn.accounting
+        .on_dropped((conn.queue.len() + conn.deferred.len()) as u64);
+    conn.queue.clear();
+    conn.deferred.clear();
+    if !conn.write_inflight {
+        conn.accounting.on_dropped(conn.inflight.len() as u64);
+        conn.inflight.clear();
+    }
+    conn.backlog_bytes = conn.inflight.iter().map(|frame| frame.remaining()).sum();
+    maybe_mark_read_closed(registry, conn);
+}
+
+/// Stop reading from every connection, and close each one once every request it sent has been
+/// answered and every response written. Bytes of a request the client had not finished sending
+/// are dropped.
+fn close_answered_connections(
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    overflow: &RequestOverflow,
+) {
+    for (_, conn) in conns.iter_mut() {
+        conn.closing = true;
+        if conn.read_closed || conn.evicted || !conn.is_settled(overflow) {
+            continue;
+        }
+        close_settled_connection(registry, conn);
+    }
+}
+
+/// Close every connection that has been quiet for `timeout`: nothing read or written, and
+/// nothing read left to answer or write.
+fn close_idle_connections(
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    overflow: &RequestOverflow,
+    timeout: Duration,
+    now_ns: u64,
+) {
+    let timeout_ns = timeout.as_nanos() as u64;
+    for (_, conn) in conns.iter_mut() {
+        if conn.read_closed
+            || conn.evicted
+            || conn.closing
+            || conn.read_paused
+            || now_ns.saturating_sub(conn.last_active_ns) < timeout_ns
+            || !conn.is_settled(overflow)
+        {
+            continue;
+        }
+        metrics::inc_idle_connections_closed();
+        close_settled_connection(registry, conn);
+    }
+}
+
+/// Shut down a connection with nothing left to answer or write.
+fn close_settled_connection(registry: &Arc<ConnectionRegistry>, conn: &mut Connection) {
+    // A pending read completes empty and closes the connection on the usual path.
+    unsafe { libc::shutdown(conn.fd, libc::SHUT_RDWR) };
+    if !conn.read_inflight {
+        conn.read_closed = true;
+        maybe_mark_read_closed(registry, conn);
+    }
+}
+
+/// Pick reads back up on a connection whose write backlog has drained.
+fn resume_reads(
+    ring: &mut impl SubmitReads,
+    conns: &mut ConnectionSlots<Connection>,
+    parse_queue: &mut VecDeque<u16>,
+    key: u16,
+) {
+    if conns[key as usize].read_len > 0 {
+        enqueue_parse(conns, parse_queue, key);
+    } else {
+        submit_read(ring, conns, key);
+    }
+}
+
+fn reap_retired_connections(
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    control: &IoThreadControl,
+    inference_control: Option<&ControlSender>,
+) {
+    let retired: Vec<u16> = conns
+        .iter()
+        .filter_map(|(k, c)| c.should_reap(registry).then_some(k as u16))
+        .collect();
+    for key in retired {
+        let Some(mut conn) = conns.try_remove(key as usize, monotonic_now_ns()) else {
             continue;
         };
-        if conn.conn != response.conn || conn.write_closed {
-            continue;
+        conn.decoder.close();
+        if accounting::ENABLED {
+            let check = conn
+                .accounting
+                .check(conn.next_request_seq - conn.first_request_seq);
+            if let Err(e) = &check {
+                eprintln!(
+                    "io-{}: response accounting violation on conn {}: {e}",
+                    conn.conn.shard_id(),
+                    conn.conn.conn_id
+                );
+            }
+            control.record_close_check(&check);
+        }
+        // Best effort: a full channel only leaves state the next generation's entries replace.
+        if let Some(inference_control) = inference_control {
+            inference_control.send(ControlEvent::ConnectionClosed(conn.conn));
         }
-        conn.queue.push_back(Box::new(ResponseFrame::new(
-            response.published_at_ns,
-            &response.data[..response.len],
-        )));
-        conn.ready_queued = true;
     }
 }
 
-fn reap_retired_connections(conns: &mut Slab<Connection>, registry: &Arc<ConnectionRegistry>) {
-    let retired: Vec<u16> = conns
-        .iter()
-        .filter_map(|(k, c)| c.should_reap(registry).then_some(k as u16))
-        .collect();
-    for key in retired {
-        conns.try_remove(key as usize);
+/// Publish the connection count and, once a drain has stopped accepts and every connection has
+/// been reaped, mark the thread drained. Returns `true` on the transition to drained.
+fn publish_control_state(
+    control: &IoThreadControl,
+    conns: &ConnectionSlots<Connection>,
+    accept_inflight: bool,
+) -> bool {
+    control.set_connections(conns.len());
+    if control.state() == IoThreadState::Draining && !accept_inflight && conns.is_empty() {
+        control.mark_drained();
+        return true;
     }
+    false
 }
 
 fn maybe_mark_read_closed(registry: &Arc<ConnectionRegistry>, conn: &mut Connection) {
     if conn.read_closed
         && !conn.write_inflight
         && conn.queue.is_empty()
+        && conn.deferred.is_empty()
         && conn.inflight.is_empty()
         && !conn.write_closed
+        && conn
+            .replay
+            .as_ref()
+            .is_none_or(|session| session.lock().unwrap().settle(conn.next_request_seq))
     {
         conn.write_closed = true;
         registry.mark_read_closed(conn.conn, conn.next_request_seq);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_accept(
-    ring: &mut IoUring,
-    conns: &mut Slab<Connection>,
+    ring: &mut impl SubmitReads,
+    conns: &mut ConnectionSlots<Connection>,
     result: i32,
     thread_id: u8,
-    listen_fd: RawFd,
+    max_connections: usize,
+    prefixes: FramePrefixes,
+    decoder: &'static dyn RequestDecoder,
+    serializer: &'static dyn ResponseSerializer,
+    fixed_point: Option<FixedPointResponse>,
+    feature_dim: usize,
+    max_vectors: usize,
+    adaptive_reads: bool,
+    replay: bool,
     registry: &Arc<ConnectionRegistry>,
 ) {
     if result >= 0 {
         let client_fd = result as RawFd;
-        if conns.len() >= SLAB_CAPACITY {
+        if conns.len() >= max_connections {
             unsafe { libc::close(client_fd) };
-        } else {
-            let entry = conns.vacant_entry();
-            let key = entry.key();
+            return;
+        }
+        let key = conns.insert_with(monotonic_now_ns(), |key| {
             let conn = registry.open(thread_id, key as u16, client_fd);
-            entry.insert(Connection::new(client_fd, conn));
-            submit_read(ring, conns, key as u16);
+            let mut connection = Connection::new(client_fd, conn);
+            connection.prefixes = prefixes;
+            connection.decoder = ConnectionDecoder::new(decoder);
+            connection.serializer = serializer;
+            connection.fixed_point = fixed_point;
+            connection.feature_dim = feature_dim;
+            connection.max_vectors = max_vectors;
+            connection.adaptive_reads = adaptive_reads;
+            connection.awaiting_hello = replay;
+            connection
+        });
+        match key {
+            Some(key) => submit_read(ring, conns, key as u16),
+            // Every free slot is still quarantined.
+            None => unsafe {
+                libc::close(client_fd);
+            },
         }
     }
-    submit_accept(ring, listen_fd);
 }
 
 #[allow(clippy::too_many_arguments)]
 fn handle_read(
-    ring: &mut IoUring,
-    conns: &mut Slab<Connection>,
+    ring: &mut impl SubmitReads,
+    conns: &mut ConnectionSlots<Connection>,
     parse_queue: &mut VecDeque<u16>,
     producer: &mut impl Producer<InferenceEvent>,
     allocator: &mut PoolAllocator,
     publish_gate: &Arc<Mutex<()>>,
     registry: &Arc<ConnectionRegistry>,
+    limits: &SoftLimits,
+    inline: Option<&InlineFastPath>,
+    schema: Option<&FeatureSchema>,
+    overflow: &mut RequestOverflow,
+    admission: Option<&LargeRequestAdmission>,
+    circuit: Option<&CircuitBreaker>,
+    embeddings: Option<EmbeddingLookup<'_>>,
+    replay: Option<&ReplaySessions>,
+    control: &IoThreadControl,
     key: u16,
     result: i32,
+    selected: Option<u16>,
 ) {
     let key_usize = key as usize;
     metrics::inc_read_cqes();
-    if result <= 0 {
-        if result < 0 {
-            metrics::inc_read_negative();
+    if let Some(bid) = selected {
+        // Copy the bytes out of the provided buffer and give it straight back.
+        let buffers = ring.read_buffers().expect("a provided buffer was selected");
+        if result > 0
+            && let Some(conn) = conns.get_mut(key_usize)
+        {
+            let len = result as usize;
+            if conn.read_buf.is_empty() {
+                conn.read_buf = buffers.take_read_buf();
+            }
+            conn.read_buf[conn.read_len..conn.read_len + len]
+                .copy_from_slice(buffers.buffer(bid, len));
         }
+        buffers.recycle(bid);
+    }
+    if result == -libc::ENOBUFS
+        && let Some(buffers) = ring.read_buffers()
+    {
+        // Every provided buffer is taken; nothing was read, so read into a buffer of its own.
+        metrics::inc_read_nobufs();
         if let Some(conn) = conns.get_mut(key_usize) {
             conn.read_inflight = false;
-            conn.read_closed = true;
-            maybe_mark_read_closed(registry, conn);
+            if conn.read_buf.is_empty() {
+                conn.read_buf = buffers.take_read_buf();
+            }
+            if !(conn.read_closed || conn.read_paused || conn.closing) {
+                ring.submit_direct_read(conn, key);
+            }
         }
         return;
     }
+    if result <= 0 {
+        handle_read_failure(ring, conns, registry, key, result);
+        return;
+    }
 
     let bytes_read = result as usize;
     metrics::add_read_bytes(bytes_read as u64);
@@ -385,7 +2084,16 @@ fn handle_read(
         return;
     };
     conn.read_inflight = false;
+    conn.record_read(bytes_read);
     conn.read_len += bytes_read;
+    conn.last_active_ns = monotonic_now_ns();
+    conn.received_at_ns = conn.last_active_ns;
+    if conn.skip_bytes > 0 {
+        let skipped = conn.skip_bytes.min(conn.read_len);
+        compact_read_buf(conn, skipped);
+        conn.skip_bytes -= skipped;
+    }
+    let seq_before = conn.next_request_seq;
     enqueue_parse(conns, parse_queue, key);
 
     parse_and_maybe_read(
@@ -396,52 +2104,218 @@ fn handle_read(
         allocator,
         publish_gate,
         registry,
+        limits,
+        inline,
+        schema,
+        overflow,
+        admission,
+        circuit,
+        embeddings,
+        replay,
         key,
     );
+    if cfg!(feature = "metrics")
+        && let Some(conn) = conns.get(key_usize)
+    {
+        record_read_frames(control, conn, seq_before);
+    }
+}
+
+/// Whether a read that failed with `errno` should be submitted again: the socket had nothing to
+/// read after all, or the read was interrupted. Any other error, a reset or a broken pipe among
+/// them, ends the connection's reads.
+fn read_error_is_transient(errno: i32) -> bool {
+    matches!(errno, libc::EAGAIN | libc::EINTR)
+}
+
+/// Handle a read that completed with end of stream (`0`) or an error: retry it if the error is
+/// transient, otherwise close the connection's reads.
+fn handle_read_failure(
+    ring: &mut impl SubmitReads,
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    key: u16,
+    result: i32,
+) {
+    if result < 0 {
+        metrics::inc_read_negative();
+    }
+    let Some(conn) = conns.get_mut(key as usize) else {
+        return;
+    };
+    conn.read_inflight = false;
+    if result < 0 && read_error_is_transient(-result) {
+        metrics::inc_read_retried();
+        if !(conn.read_closed || conn.read_paused || conn.closing) {
+            ring.submit_read(conn, key);
+        }
+        return;
+    }
+    conn.read_closed = true;
+    maybe_mark_read_closed(registry, conn);
+}
+
+/// Frames the parse pass after a read consumed, and whether the bytes left behind are the start
+/// of a frame still waiting on the socket (rather than a whole frame held back by a full ring).
+fn record_read_frames(control: &IoThreadControl, conn: &Connection, seq_before: u64) {
+    let leftover = &conn.read_buf[..conn.read_len];
+    let partial = !leftover.is_empty()
+        && matches!(
+            conn.decoder.parse(leftover, conn.framing()),
+            protocol::ParseResult::Incomplete(_)
+        );
+    control.record_read_frames(conn.next_request_seq - seq_before, partial);
 }
 
 #[allow(clippy::too_many_arguments)]
 fn parse_and_maybe_read(
-    ring: &mut IoUring,
-    conns: &mut Slab<Connection>,
+    ring: &mut impl SubmitReads,
+    conns: &mut ConnectionSlots<Connection>,
     parse_queue: &mut VecDeque<u16>,
     producer: &mut impl Producer<InferenceEvent>,
     allocator: &mut PoolAllocator,
     publish_gate: &Arc<Mutex<()>>,
     registry: &Arc<ConnectionRegistry>,
+    limits: &SoftLimits,
+    inline: Option<&InlineFastPath>,
+    schema: Option<&FeatureSchema>,
+    overflow: &mut RequestOverflow,
+    admission: Option<&LargeRequestAdmission>,
+    circuit: Option<&CircuitBreaker>,
+    embeddings: Option<EmbeddingLookup<'_>>,
+    replay: Option<&ReplaySessions>,
     key: u16,
 ) {
     let key_usize = key as usize;
     let Some(conn) = conns.get_mut(key_usize) else {
         return;
     };
+    if conn.read_paused {
+        return;
+    }
+    if conn.awaiting_hello {
+        if conn.read_len < protocol::REPLAY_HELLO_BYTES {
+            submit_read(ring, conns, key);
+            return;
+        }
+        let sessions = replay.expect("only replay connections await a hello");
+        let (token, resume_seq) = protocol::decode_replay_hello(&conn.read_buf[..]);
+        compact_read_buf(conn, protocol::REPLAY_HELLO_BYTES);
+        conn.awaiting_hello = false;
+        if !resume_session(sessions, registry, conn, token, resume_seq) {
+            conn.read_len = 0;
+            conn.read_closed = true;
+            maybe_mark_read_closed(registry, conn);
+            return;
+        }
+    }
     let buf = &conn.read_buf[..conn.read_len];
+    let overload_retry_after_ms = limits.overload_retry_after_ms();
+    let ring_full = if overload_retry_after_ms.is_some() {
+        RingFullPolicy::Reject
+    } else {
+        RingFullPolicy::Wait
+    };
+    let mut rejected = Vec::new();
+    let mut scored = Vec::new();
+    let mut scores = Vec::new();
+    let mut invalid = Vec::new();
+    let seq_before = conn.next_request_seq;
+    let framing = conn.framing();
 
     let publish_guard = publish_gate.lock().unwrap();
-    match request_flow::process_requests_from_buffer(
+    let result = request_flow::process_requests_with_inline(
         buf,
         producer,
         allocator,
         conn.conn,
         &mut conn.next_request_seq,
-    ) {
+        conn.received_at_ns,
+        ring_full,
+        limits.non_finite_features(),
+        limits.malformed_requests(),
+        framing,
+        &mut conn.decoder,
+        inline,
+        Some(&mut *overflow).filter(|overflow| overflow.capacity() > 0),
+        admission,
+        circuit,
+        embeddings,
+        |request_seq, reason| rejected.push((request_seq, reason)),
+        |request_seq, request_scores| {
+            scored.push((request_seq, request_scores.len()));
+            scores.extend_from_slice(request_scores);
+        },
+        |request_seq, error| invalid.push((request_seq, error)),
+    );
+    drop(publish_guard);
+    if conn.prefixes.vector_status
+        || conn.prefixes.request_id
+        || conn.fixed_point.is_some()
+        || schema.is_some()
+    {
+        let consumed = match &result {
+            Ok(outcome) => outcome.consumed,
+            Err(ProcessRequestError::Parse { consumed, .. }) => *consumed,
+        };
+        conn.note_requests(consumed, seq_before, schema);
+    }
+    // Requests before a parse error were processed too, so answer them either way.
+    let retry_after_ms = overload_retry_after_ms.unwrap_or_default();
+    for (request_seq, reason) in rejected {
+        let retry_after_ms = match (reason, circuit) {
+            (OverloadReason::CircuitOpen, Some(circuit)) => circuit.retry_after_ms(),
+            _ => retry_after_ms,
+        };
+        conn.push_overload(request_seq, reason, retry_after_ms);
+    }
+    let mut offset = 0;
+    for (request_seq, len) in scored {
+        conn.push_inline(request_seq, &scores[offset..offset + len]);
+        offset += len;
+    }
+    for (request_seq, error) in invalid {
+        conn.push_parse_error(request_seq, &error.at(conn.stream_offset));
+    }
+    match result {
         Ok(outcome) => {
-            drop(publish_guard);
             compact_read_buf(conn, outcome.consumed);
+            conn.skip_bytes += outcome.skip;
             metrics::add_bytes_consumed(outcome.consumed as u64);
             registry.update_published_seq_end(conn.conn, conn.next_request_seq);
+            if conn.check_write_backlog(limits.write_backlog_bytes()) {
+                evict_slow_consumer(registry, conn);
+                return;
+            }
+            if outcome.ring_full && overflow.capacity() > 0 {
+                // Wait for the overflow queue to drain instead of re-parsing every iteration.
+                overflow.block(conn.conn);
+                return;
+            }
             if outcome.needs_read {
                 submit_read(ring, conns, key);
             }
         }
-        Err(e) => {
-            drop(publish_guard);
+        Err(ProcessRequestError::Parse { error, consumed }) => {
+            let e = error.at(conn.stream_offset);
             eprintln!(
-                "io-{}: request parse error ({:?}), closing conn {}",
+                "io-{}: malformed request {} on conn {key} ({e}), closing it",
                 conn.conn.shard_id(),
-                e,
-                key
+                conn.next_request_seq,
             );
+            // The malformed request takes the next sequence and is answered with the error.
+            let request_seq = conn.next_request_seq;
+            conn.next_request_seq += 1;
+            if conn.prefixes.request_id {
+                // The id precedes the header that failed, so it was read whole.
+                let request_id = conn.decoder.request_id(&conn.read_buf[consumed..]);
+                conn.request_ids.push_back((request_seq, request_id));
+            }
+            conn.push_parse_error(request_seq, &e);
+            registry.update_published_seq_end(conn.conn, conn.next_request_seq);
+            // Drop the buffered bytes so a parse already queued for this connection finds
+            // nothing to publish again.
+            conn.read_len = 0;
             conn.read_closed = true;
             maybe_mark_read_closed(registry, conn);
             return;
@@ -449,6 +2323,9 @@ fn parse_and_maybe_read(
     }
 
     let c = &conns[key as usize];
+    if c.read_paused {
+        return;
+    }
     if !c.read_closed && c.read_len == 0 {
         submit_read(ring, conns, key);
     } else if !c.read_closed && !c.read_inflight && c.read_len > 0 {
@@ -456,28 +2333,149 @@ fn parse_and_maybe_read(
     }
 }
 
+/// Answer `conn`'s replay hello: resume the session named by `token` from `resume_seq`, or start
+/// one if `token` is 0, queuing the reply and the kept frames, or refuse it. Returns whether the
+/// connection goes on to read requests.
+fn resume_session(
+    sessions: &ReplaySessions,
+    registry: &ConnectionRegistry,
+    conn: &mut Connection,
+    token: u64,
+    resume_seq: u64,
+) -> bool {
+    let mut reply = [0u8; protocol::REPLAY_REPLY_BYTES];
+    match sessions.attach(token, resume_seq, conn.conn, registry) {
+        Attach::Resumed {
+            token,
+            session,
+            next_request_seq,
+            frames,
+        } => {
+            protocol::encode_replay_reply(
+                ReplayStatus::Resumed,
+                token,
+                next_request_seq,
+                &mut reply,
+            );
+            conn.push_unnumbered(&reply);
+            for frame in &frames {
+                conn.push_unnumbered(frame);
+            }
+            metrics::add_responses_replayed(frames.len() as u64);
+            conn.replay = Some(session);
+            conn.first_request_seq = next_request_seq;
+            conn.next_request_seq = next_request_seq;
+            conn.next_response_seq = next_request_seq;
+            true
+        }
+        Attach::Refused(status) => {
+            protocol::encode_replay_reply(status, 0, 0, &mut reply);
+            conn.push_unnumbered(&reply);
+            false
+        }
+    }
+}
+
+/// Publish parked requests, then resume parsing on connections the full overflow queue
+/// blocked once it has room.
+fn drain_request_overflow(
+    conns: &mut ConnectionSlots<Connection>,
+    parse_queue: &mut VecDeque<u16>,
+    overflow: &mut RequestOverflow,
+    producer: &mut impl Producer<InferenceEvent>,
+    allocator: &mut PoolAllocator,
+    publish_gate: &Arc<Mutex<()>>,
+    inline: Option<&InlineFastPath>,
+) {
+    {
+        let _publish_guard = publish_gate.lock().unwrap();
+        overflow.drain(producer, allocator, inline.map(InlineFastPath::occupancy));
+    }
+    for conn_ref in overflow.take_unblocked() {
+        let key = conn_ref.conn_id;
+        if conns
+            .get(key as usize)
+            .is_some_and(|conn| conn.conn == conn_ref)
+        {
+            enqueue_parse(conns, parse_queue, key);
+        }
+    }
+}
+
 fn compact_read_buf(conn: &mut Connection, consumed: usize) {
     if consumed > 0 {
         conn.read_buf.copy_within(consumed..conn.read_len, 0);
         conn.read_len -= consumed;
+        conn.stream_offset += consumed as u64;
     }
 }
 
-fn enqueue_parse(conns: &mut Slab<Connection>, parse_queue: &mut VecDeque<u16>, key: u16) {
+fn enqueue_parse(
+    conns: &mut ConnectionSlots<Connection>,
+    parse_queue: &mut VecDeque<u16>,
+    key: u16,
+) {
     let Some(conn) = conns.get_mut(key as usize) else {
         return;
     };
-    if conn.read_closed || conn.read_inflight || conn.read_len == 0 || conn.parse_queued {
+    if conn.read_closed
+        || conn.read_paused
+        || conn.read_inflight
+        || conn.read_len == 0
+        || conn.parse_queued
+    {
         return;
     }
     conn.parse_queued = true;
     parse_queue.push_back(key);
 }
 
-fn submit_accept(ring: &mut IoUring, listen_fd: RawFd) {
-    let sqe = opcode::Accept::new(Fd(listen_fd), ptr::null_mut(), ptr::null_mut())
+/// What the accept SQE needs after one of its completions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AcceptNext {
+    /// A multishot accept is still armed.
+    Armed,
+    /// Submit a new accept, multishot or one-shot.
+    Submit { multishot: bool },
+    /// Accepts were cancelled and the last completion is in; the listener can close.
+    Stopped,
+}
+
+fn next_accept(multishot: bool, result: i32, more: bool, accepting: bool) -> AcceptNext {
+    if more {
+        return AcceptNext::Armed;
+    }
+    if !accepting {
+        return AcceptNext::Stopped;
+    }
+    // Kernels before 5.19 reject the multishot flag outright; fall back to one-shot accepts.
+    // Any other end of a multishot (an accept error, a full CQ) just rearms it.
+    AcceptNext::Submit {
+        multishot: multishot && result != -libc::EINVAL,
+    }
+}
+
+fn submit_accept(ring: &mut IoUring, listen_fd: RawFd, multishot: bool) {
+    let sqe = if multishot {
+        opcode::AcceptMulti::new(Fd(listen_fd)).build()
+    } else {
+        opcode::Accept::new(Fd(listen_fd), ptr::null_mut(), ptr::null_mut()).build()
+    };
+    ring.push(&sqe.user_data(encode_user_data(OP_ACCEPT, 0)));
+}
+
+fn submit_cancel_accept(ring: &mut IoUring) {
+    let sqe = opcode::AsyncCancel::new(encode_user_data(OP_ACCEPT, 0))
+        .build()
+        .user_data(encode_user_data(OP_CANCEL, 0));
+    ring.push(&sqe);
+}
+
+/// Wake the data ring when the accept ring at `accept_ring_fd` has completions.
+fn submit_accept_ring_poll(ring: &mut IoUring, accept_ring_fd: RawFd) {
+    let sqe = opcode::PollAdd::new(Fd(accept_ring_fd), libc::POLLIN as _)
         .build()
-        .user_data(encode_user_data(OP_ACCEPT, 0));
+        .user_data(encode_user_data(OP_ACCEPT_RING, 0));
     ring.push(&sqe);
 }
 
@@ -488,51 +2486,179 @@ fn submit_notify(ring: &mut IoUring, notify_fd: RawFd) {
     ring.push(&sqe);
 }
 
+fn submit_control(ring: &mut IoUring, control_fd: RawFd) {
+    let sqe = opcode::PollAdd::new(Fd(control_fd), libc::POLLIN as _)
+        .build()
+        .user_data(encode_user_data(OP_CONTROL, 0));
+    ring.push(&sqe);
+}
+
 fn handle_notify(ring: &mut IoUring, notify_fd: RawFd, result: i32) {
     if result >= 0 {
-        loop {
-            let mut value = 0u64;
-            let rc = unsafe {
-                libc::read(
-                    notify_fd,
-                    (&mut value as *mut u64).cast::<libc::c_void>(),
-                    std::mem::size_of::<u64>(),
-                )
-            };
-            if rc < 0 {
-                let err = std::io::Error::last_os_error()
-                    .raw_os_error()
-                    .unwrap_or_default();
-                if err == libc::EAGAIN {
-                    break;
-                }
-                panic!("eventfd read failed: {err}");
-            }
-            if rc == 0 {
-                break;
-            }
-        }
+        notify::drain_fd(notify_fd);
     }
     submit_notify(ring, notify_fd);
 }
 
-fn submit_read(ring: &mut IoUring, conns: &mut Slab<Connection>, key: u16) {
-    let conn = &mut conns[key as usize];
-    if conn.read_inflight || conn.read_closed {
-        return;
+fn submit_idle_sweep(ring: &mut IoUring, interval: &Timespec) {
+    let sqe = opcode::Timeout::new(interval)
+        .build()
+        .user_data(encode_user_data(OP_IDLE_SWEEP, 0));
+    ring.push(&sqe);
+}
+
+fn handle_control(ring: &mut IoUring, control_fd: RawFd, result: i32) {
+    if result >= 0 {
+        notify::drain_fd(control_fd);
     }
-    conn.read_inflight = true;
-    let (buf_ptr, buf_len) = conn.read_buf_tail();
-    let sqe = opcode::Recv::new(Fd(conn.fd), buf_ptr, buf_len)
+    submit_control(ring, control_fd);
+}
+
+/// Cancel the pending `op` on `key`, to learn whether its completion was dropped after the
+/// completion queue overflowed. If the op is still pending it completes with `-ECANCELED` and
+/// is rearmed; if its completion was dropped there is nothing to cancel, and the probe
+/// completes with `-ENOENT` in its place.
+fn submit_probe(ring: &mut IoUring, op: u64, key: u16) {
+    let sqe = opcode::AsyncCancel::new(encode_user_data(op, key as u32))
         .build()
-        .user_data(encode_user_data(OP_READ, key as u32));
+        .user_data(encode_user_data(OP_PROBE, ((op as u32) << 16) | key as u32));
     ring.push(&sqe);
-    metrics::inc_read_submits();
+}
+
+/// Probe every connection's pending read and write.
+fn probe_connections(ring: &mut IoUring, conns: &mut ConnectionSlots<Connection>) {
+    for (key, conn) in conns.iter_mut() {
+        if conn.read_inflight {
+            conn.read_probed = true;
+            submit_probe(ring, OP_READ, key as u16);
+        }
+        if conn.write_inflight {
+            conn.write_probed = true;
+            submit_probe(ring, OP_WRITE, key as u16);
+        }
+    }
+}
+
+/// Whether a read completion is for a read a probe cancelled, which read nothing. Any read
+/// completion ends the connection's probe.
+fn take_cancelled_read(conns: &mut ConnectionSlots<Connection>, key: u16, result: i32) -> bool {
+    let Some(conn) = conns.get_mut(key as usize) else {
+        return false;
+    };
+    if std::mem::take(&mut conn.read_probed) && result == -libc::ECANCELED {
+        conn.read_inflight = false;
+        return true;
+    }
+    false
+}
+
+/// Like [`take_cancelled_read`], for a write completion.
+fn take_cancelled_write(conns: &mut ConnectionSlots<Connection>, key: u16, result: i32) -> bool {
+    conns
+        .get_mut(key as usize)
+        .is_some_and(|conn| std::mem::take(&mut conn.write_probed) && result == -libc::ECANCELED)
+}
+
+/// A probe found `key`'s pending `op`, a read or a write, had its completion dropped. How many
+/// bytes it moved is unknown, so the stream can't be resumed: close the connection as for a
+/// failed read or write.
+fn lost_connection_completion(
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    op: u64,
+    key: u16,
+    write_backlog_limit: usize,
+) {
+    let Some(conn) = conns.get_mut(key as usize) else {
+        return;
+    };
+    if op == OP_READ && std::mem::take(&mut conn.read_probed) {
+        conn.read_inflight = false;
+        conn.read_closed = true;
+        maybe_mark_read_closed(registry, conn);
+    } else if op == OP_WRITE && std::mem::take(&mut conn.write_probed) {
+        handle_write(conns, registry, key, -libc::ECANCELED, write_backlog_limit);
+    }
+}
+
+/// Count a completion whose user data names no op, logging the first in the process: it means
+/// an op was added without a handler, or user data was corrupted.
+fn unknown_cqe(thread_id: u8, user_data: u64, result: i32) {
+    static LOGGED: AtomicBool = AtomicBool::new(false);
+    metrics::inc_io_unknown_cqes();
+    if !LOGGED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "disrust: io-{thread_id} ignoring a completion with unknown user data {user_data:#x} \
+             (result {result})"
+        );
+    }
+}
+
+fn submit_read(ring: &mut impl SubmitReads, conns: &mut ConnectionSlots<Connection>, key: u16) {
+    let conn = &mut conns[key as usize];
+    if conn.read_inflight || conn.read_closed || conn.read_paused || conn.closing {
+        return;
+    }
+    ring.submit_read(conn, key);
+}
+
+/// Where an IO thread's socket reads are submitted: its data ring, or the [`epoll`] fallback.
+/// Either way a read completes through `handle_read`, with the bytes at the tail of the
+/// connection's read buffer or in the provided buffer it names.
+trait SubmitReads {
+    /// Read from `conn`'s socket, which has no read in flight.
+    fn submit_read(&mut self, conn: &mut Connection, key: u16);
+
+    /// Read into the free tail of `conn`'s own read buffer.
+    fn submit_direct_read(&mut self, conn: &mut Connection, key: u16);
+
+    /// Provided buffers reads may select from, if any are registered.
+    fn read_buffers(&mut self) -> Option<&mut ReadBufferRing>;
+}
+
+impl SubmitReads for IoUring {
+    fn submit_read(&mut self, conn: &mut Connection, key: u16) {
+        let Some(buffers) = self.read_buffers.as_mut().filter(|_| conn.read_len == 0) else {
+            self.submit_direct_read(conn, key);
+            return;
+        };
+        // Nothing buffered: hold no read buffer until bytes arrive, then take one.
+        buffers.put_read_buf(std::mem::take(&mut conn.read_buf));
+        conn.read_requested = conn.read_size().min(buffers.buf_size() as usize);
+        let sqe = opcode::Recv::new(Fd(conn.fd), ptr::null_mut(), conn.read_requested as u32)
+            .buf_group(buffers.group())
+            .build()
+            .flags(squeue::Flags::BUFFER_SELECT)
+            .user_data(encode_user_data(OP_READ, key as u32));
+        conn.read_inflight = true;
+        self.push(&sqe);
+        metrics::inc_read_submits();
+    }
+
+    fn submit_direct_read(&mut self, conn: &mut Connection, key: u16) {
+        let fixed = self.uses_fixed_buffers(conn, key);
+        conn.read_inflight = true;
+        let (buf_ptr, buf_len) = conn.read_buf_tail();
+        conn.read_requested = buf_len as usize;
+        let sqe = if fixed {
+            opcode::ReadFixed::new(Fd(conn.fd), buf_ptr, buf_len, FixedBuffers::read_index(key))
+                .build()
+        } else {
+            opcode::Recv::new(Fd(conn.fd), buf_ptr, buf_len).build()
+        };
+        let sqe = sqe.user_data(encode_user_data(OP_READ, key as u32));
+        self.push(&sqe);
+        metrics::inc_read_submits();
+    }
+
+    fn read_buffers(&mut self) -> Option<&mut ReadBufferRing> {
+        self.read_buffers.as_mut()
+    }
 }
 
 fn submit_ready_writes(
     ring: &mut IoUring,
-    conns: &mut Slab<Connection>,
+    conns: &mut ConnectionSlots<Connection>,
     registry: &Arc<ConnectionRegistry>,
 ) {
     let ready: Vec<u16> = conns
@@ -548,7 +2674,7 @@ fn submit_ready_writes(
 
 fn submit_write(
     ring: &mut IoUring,
-    conns: &mut Slab<Connection>,
+    conns: &mut ConnectionSlots<Connection>,
     registry: &Arc<ConnectionRegistry>,
     key: u16,
 ) {
@@ -558,48 +2684,42 @@ fn submit_write(
         return;
     }
 
-    if conn.inflight.is_empty() {
-        while conn.inflight.len() < MAX_IOVECS_PER_WRITE {
-            let Some(frame) = conn.queue.pop_front() else {
-                break;
-            };
-            metrics::record_publish_to_write_submit(elapsed_since_ns(frame.published_at_ns));
-            conn.inflight.push_back(frame);
-        }
-    }
-
-    if conn.inflight.is_empty() {
+    let iov_count = conn.gather_write();
+    if iov_count == 0 {
         maybe_mark_read_closed(registry, conn);
         return;
     }
-
-    let mut iov_count = 0usize;
-    for frame in conn.inflight.iter() {
-        conn.inflight_iovecs[iov_count] = libc::iovec {
-            iov_base: frame.remaining_ptr() as *mut libc::c_void,
-            iov_len: frame.remaining(),
-        };
-        iov_count += 1;
-    }
-    conn.inflight_iov_count = iov_count;
     conn.write_inflight = true;
 
-    let sqe = opcode::Writev::new(Fd(conn.fd), conn.inflight_iovecs.as_ptr(), iov_count as u32)
-        .build()
-        .user_data(encode_user_data(OP_WRITE, key as u32));
+    let fixed = conn
+        .fixed_buffers
+        .then(|| ring.fixed_buffers.as_mut()?.write_buf(key))
+        .flatten();
+    let sqe = match fixed {
+        Some((buf, index)) => {
+            let len = conn.copy_gathered(buf);
+            opcode::WriteFixed::new(Fd(conn.fd), buf.as_ptr(), len as u32, index).build()
+        }
+        None => opcode::Writev::new(Fd(conn.fd), conn.inflight_iovecs.as_ptr(), iov_count as u32)
+            .build(),
+    };
+    let sqe = sqe.user_data(encode_user_data(OP_WRITE, key as u32));
     ring.push(&sqe);
     metrics::inc_write_sqes();
 }
 
+/// Returns `true` if the completed write brought a paused connection's backlog low enough to
+/// resume reading.
 fn handle_write(
-    conns: &mut Slab<Connection>,
+    conns: &mut ConnectionSlots<Connection>,
     registry: &Arc<ConnectionRegistry>,
     key: u16,
     result: i32,
-) {
+    write_backlog_limit: usize,
+) -> bool {
     metrics::inc_write_cqes();
     let Some(conn) = conns.get_mut(key as usize) else {
-        return;
+        return false;
     };
     if result < 0 {
         metrics::inc_write_negative();
@@ -613,50 +2733,107 @@ fn handle_write(
             }
             _ => {}
         }
-        conn.write_closed = true;
+        // Torn down like an aborted connection, so it is still retired once nothing is owed.
+        conn.evicted = true;
         conn.write_inflight = false;
+        conn.accounting
+            .on_dropped((conn.inflight.len() + conn.queue.len() + conn.deferred.len()) as u64);
         conn.inflight.clear();
         conn.queue.clear();
+        conn.deferred.clear();
+        conn.backlog_bytes = 0;
         conn.inflight_iov_count = 0;
         conn.read_closed = true;
         maybe_mark_read_closed(registry, conn);
-        return;
-    }
-
-    let mut remaining = result as usize;
-    while remaining > 0 {
-        let Some(frame) = conn.inflight.front_mut() else {
-            break;
-        };
-        let frame_remaining = frame.remaining();
-        if remaining >= frame_remaining {
-            remaining -= frame_remaining;
-            conn.inflight.pop_front();
-        } else {
-            frame.offset += remaining;
-            remaining = 0;
-        }
+        return false;
     }
 
+    conn.complete_write(result as usize);
     conn.write_inflight = false;
-    conn.inflight_iov_count = 0;
+    conn.last_active_ns = monotonic_now_ns();
 
+    if conn.evicted {
+        conn.accounting.on_dropped(conn.inflight.len() as u64);
+        conn.inflight.clear();
+        conn.backlog_bytes = 0;
+    }
     if !conn.inflight.is_empty() || !conn.queue.is_empty() {
         conn.ready_queued = true;
     } else {
         maybe_mark_read_closed(registry, conn);
     }
+    conn.maybe_resume_reads(write_backlog_limit)
+}
+
+/// One connection's response write path with no socket or ring behind it, for benchmarks:
+/// responses are framed and queued as the IO thread does, gathered into the iovecs a write
+/// would submit, and completed as if the kernel took the bytes written.
+pub struct WritePath {
+    conn: Connection,
+}
+
+impl WritePath {
+    pub fn new(conn: ConnectionRef) -> Self {
+        Self {
+            conn: Connection::new(-1, conn),
+        }
+    }
+
+    pub fn with_request_seq_echo(mut self) -> Self {
+        self.conn.prefixes.request_seq = true;
+        self
+    }
+
+    pub fn with_length_prefix(mut self) -> Self {
+        self.conn.prefixes.length = true;
+        self
+    }
+
+    pub fn with_vector_status(mut self) -> Self {
+        self.conn.prefixes.vector_status = true;
+        self
+    }
+
+    pub fn with_response_serializer(mut self, serializer: &'static dyn ResponseSerializer) -> Self {
+        self.conn.serializer = serializer;
+        self
+    }
+
+    /// Frame and queue `response` as its delivery from the response queue would.
+    pub fn deliver(&mut self, response: &ResponseReady) {
+        let frame = self.conn.response_frame(response, response.timed_out);
+        self.conn.push_response(response.request_seq, frame);
+    }
+
+    /// The iovecs the next write would submit, empty once every queued frame is written.
+    pub fn gather(&mut self) -> &[libc::iovec] {
+        let iov_count = self.conn.gather_write();
+        &self.conn.inflight_iovecs[..iov_count]
+    }
+
+    /// Complete the gathered write with `written` bytes.
+    pub fn complete(&mut self, written: usize) {
+        self.conn.complete_write(written);
+    }
+
+    /// Bytes queued or gathered and not yet written.
+    pub fn backlog_bytes(&self) -> usize {
+        self.conn.backlog_bytes
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use slab::Slab;
-
     use super::*;
+    use crate::constants::FEATURE_DIM;
     use crate::pipeline::connection_registry::ConnectionRegistry;
+    use crate::pipeline::control_channel::control_channel;
     use crate::pipeline::response_queue::{ResponseQueue, ResponseReady};
+    use crate::protocol::VectorStatus;
+
+    const UNLIMITED: usize = usize::MAX;
 
     // ---------------------------------------------------------------------------
     // Helpers
@@ -665,13 +2842,12 @@ mod tests {
         Arc::new(ConnectionRegistry::new(1, 64))
     }
 
-    /// Insert one connection at slab key 0, registered at conn_id 0.
-    fn setup(registry: &Arc<ConnectionRegistry>) -> (Slab<Connection>, ConnectionRef) {
-        let mut conns = Slab::with_capacity(4);
-        let entry = conns.vacant_entry();
-        assert_eq!(entry.key(), 0, "first slab key must be 0");
+    /// Insert one connection at slot 0, registered at conn_id 0.
+    fn setup(registry: &Arc<ConnectionRegistry>) -> (ConnectionSlots<Connection>, ConnectionRef) {
+        let mut conns = ConnectionSlots::new(4);
         let conn_ref = registry.open(0, 0, -1);
-        entry.insert(Connection::new(-1, conn_ref));
+        let key = conns.insert_with(0, |_| Connection::new(-1, conn_ref));
+        assert_eq!(key, Some(0), "first slot must be 0");
         (conns, conn_ref)
     }
 
@@ -688,6 +2864,59 @@ mod tests {
         conn.inflight.iter().map(|f| f.offset).collect()
     }
 
+    /// A backend that records the reads submitted to it instead of performing them.
+    #[derive(Default)]
+    struct RecordedReads(Vec<u16>);
+
+    impl SubmitReads for RecordedReads {
+        fn submit_read(&mut self, conn: &mut Connection, key: u16) {
+            self.submit_direct_read(conn, key);
+        }
+
+        fn submit_direct_read(&mut self, conn: &mut Connection, key: u16) {
+            conn.read_inflight = true;
+            self.0.push(key);
+        }
+
+        fn read_buffers(&mut self) -> Option<&mut ReadBufferRing> {
+            None
+        }
+    }
+
+    // ---------------------------------------------------------------------------
+    // read_size
+
+    #[test]
+    fn adaptive_reads_shrink_for_trickles_and_double_while_reads_fill() {
+        let registry = make_registry();
+        let (mut conns, _) = setup(&registry);
+        let conn = &mut conns[0];
+        assert_eq!(
+            conn.read_size(),
+            READ_BUF_SIZE,
+            "whole free buffer by default"
+        );
+        conn.adaptive_reads = true;
+
+        // A firehose: every read fills, so each next read is twice as large.
+        let mut sizes = Vec::new();
+        for _ in 0..6 {
+            conn.read_requested = conn.read_size();
+            sizes.push(conn.read_requested);
+            conn.record_read(conn.read_requested);
+        }
+        assert_eq!(sizes, [4096, 8192, 16384, 32768, 65536, 65536]);
+
+        // The connection slows to a trickle; its reads shrink back to the minimum.
+        for _ in 0..64 {
+            conn.read_requested = conn.read_size();
+            conn.record_read(100);
+        }
+        assert_eq!(conn.read_size(), MIN_READ_SIZE);
+        conn.read_len = READ_BUF_SIZE - 1000;
+        assert_eq!(conn.read_size(), 1000, "never past the free buffer");
+    }
+
     // ---------------------------------------------------------------------------
     // compact_read_buf
 
@@ -726,6 +2955,59 @@ mod tests {
         assert_eq!(&conn.read_buf[..3], b"fgh");
     }
 
+    // ---------------------------------------------------------------------------
+    // handle_read_failure
+
+    #[test]
+    fn transient_read_errors_rearm_the_read() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let mut ring = RecordedReads::default();
+        for errno in [libc::EAGAIN, libc::EINTR] {
+            conns[0].read_inflight = true;
+            handle_read_failure(&mut ring, &mut conns, &registry, 0, -errno);
+            let conn = &conns[0];
+            assert!(conn.read_inflight && !conn.read_closed, "errno {errno}");
+        }
+        assert_eq!(ring.0, [0, 0]);
+        assert!(registry.is_open(conn_ref));
+    }
+
+    #[test]
+    fn a_transient_read_error_on_a_paused_connection_waits_for_it_to_resume() {
+        let registry = make_registry();
+        let (mut conns, _) = setup(&registry);
+        let mut ring = RecordedReads::default();
+        conns[0].read_inflight = true;
+        conns[0].read_paused = true;
+
+        handle_read_failure(&mut ring, &mut conns, &registry, 0, -libc::EAGAIN);
+
+        assert!(ring.0.is_empty());
+        let conn = &conns[0];
+        assert!(!conn.read_inflight && !conn.read_closed);
+    }
+
+    #[test]
+    fn end_of_stream_and_connection_errors_close_reads() {
+        for result in [0, -libc::ECONNRESET, -libc::EPIPE, -libc::EIO] {
+            let registry = make_registry();
+            let (mut conns, conn_ref) = setup(&registry);
+            let mut ring = RecordedReads::default();
+            conns[0].read_inflight = true;
+
+            handle_read_failure(&mut ring, &mut conns, &registry, 0, result);
+
+            assert!(ring.0.is_empty(), "result {result}");
+            let conn = &conns[0];
+            assert!(!conn.read_inflight && conn.read_closed, "result {result}");
+            assert!(
+                registry.is_retired(conn_ref),
+                "nothing owed, result {result}"
+            );
+        }
+    }
+
     // ---------------------------------------------------------------------------
     // handle_write
 
@@ -736,7 +3018,7 @@ mod tests {
         push_inflight(&mut conns[0], &[1u8; 10]);
         conns[0].write_inflight = true;
 
-        handle_write(&mut conns, &registry, 0, 10);
+        handle_write(&mut conns, &registry, 0, 10, UNLIMITED);
 
         let conn = &conns[0];
         assert!(conn.inflight.is_empty());
@@ -751,7 +3033,7 @@ mod tests {
         push_inflight(&mut conns[0], &[1u8; 20]);
         conns[0].write_inflight = true;
 
-        handle_write(&mut conns, &registry, 0, 7);
+        handle_write(&mut conns, &registry, 0, 7, UNLIMITED);
 
         let conn = &conns[0];
         assert_eq!(conn.inflight.len(), 1);
@@ -769,7 +3051,7 @@ mod tests {
         conns[0].write_inflight = true;
 
         // Completes first frame (10) and 3 bytes into second.
-        handle_write(&mut conns, &registry, 0, 13);
+        handle_write(&mut conns, &registry, 0, 13, UNLIMITED);
 
         let conn = &conns[0];
         assert_eq!(conn.inflight.len(), 1);
@@ -785,7 +3067,7 @@ mod tests {
         push_queued(&mut conns[0], &[2u8; 10]);
         conns[0].write_inflight = true;
 
-        handle_write(&mut conns, &registry, 0, 10);
+        handle_write(&mut conns, &registry, 0, 10, UNLIMITED);
 
         let conn = &conns[0];
         assert!(conn.inflight.is_empty());
@@ -799,12 +3081,12 @@ mod tests {
     #[test]
     fn write_error_tears_down_connection() {
         let registry = make_registry();
-        let (mut conns, _) = setup(&registry);
+        let (mut conns, conn_ref) = setup(&registry);
         push_inflight(&mut conns[0], &[1u8; 10]);
         push_queued(&mut conns[0], &[2u8; 10]);
         conns[0].write_inflight = true;
 
-        handle_write(&mut conns, &registry, 0, -libc::EPIPE);
+        handle_write(&mut conns, &registry, 0, -libc::EPIPE, UNLIMITED);
 
         let conn = &conns[0];
         assert!(conn.write_closed);
@@ -812,6 +3094,10 @@ mod tests {
         assert!(!conn.write_inflight);
         assert!(conn.inflight.is_empty());
         assert!(conn.queue.is_empty());
+        assert!(
+            registry.is_retired(conn_ref),
+            "nothing is owed, so it can be reaped"
+        );
     }
 
     #[test]
@@ -823,7 +3109,7 @@ mod tests {
         conn.write_inflight = true;
         push_inflight(conn, &[1u8; 10]);
 
-        handle_write(&mut conns, &registry, 0, 10);
+        handle_write(&mut conns, &registry, 0, 10, UNLIMITED);
 
         assert!(conns[0].write_closed);
         assert!(registry.is_retired(conn_ref));
@@ -837,12 +3123,197 @@ mod tests {
         let registry = make_registry();
         let (mut conns, conn_ref) = setup(&registry);
         let rq = Arc::new(ResponseQueue::new(8));
-        rq.push(ResponseReady::encode(conn_ref, 0, 1, &[1.0f32]));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        assert_eq!(conns[0].queue.len(), 1);
+        assert!(conns[0].ready_queued);
+    }
+
+    #[test]
+    fn drain_answers_timed_out_and_late_responses_with_timeout_frames() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let rq = Arc::new(ResponseQueue::new(8));
+        let now_ns = monotonic_now_ns();
+        let mut timed_out = ResponseReady::new(conn_ref, 0, now_ns, &[]);
+        timed_out.fill_timed_out(conn_ref, 0, now_ns);
+        rq.push(timed_out);
+        rq.push(ResponseReady::new(conn_ref, 1, now_ns, &[1.0f32]));
+        // The second response's timeout runs out before the third is published.
+        std::thread::sleep(Duration::from_millis(60));
+        rq.push(ResponseReady::new(
+            conn_ref,
+            2,
+            monotonic_now_ns(),
+            &[1.0f32],
+        ));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry {
+                request_timeout: Some(Duration::from_millis(50)),
+                response_ttl: None,
+            },
+        );
+
+        let frames: Vec<_> = conns[0]
+            .queue
+            .iter()
+            .map(|frame| &frame.data[..frame.len])
+            .collect();
+        assert_eq!(frames.len(), 3);
+        for frame in &frames[..2] {
+            assert_eq!(
+                protocol::decode_overload(frame),
+                Some((Some(OverloadReason::Timeout), 0))
+            );
+        }
+        assert_eq!(frames[2].len(), protocol::response_size(1));
+        assert_eq!(frames[2][0], 1, "in time, so scored");
+    }
+
+    #[test]
+    fn drain_answers_responses_queued_past_their_ttl_with_timeout_frames() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let rq = Arc::new(ResponseQueue::new(8));
+        // Both requests were published long ago; only the time in the queue counts.
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        std::thread::sleep(Duration::from_millis(60));
+        rq.push(ResponseReady::new(conn_ref, 1, 1, &[2.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry {
+                request_timeout: None,
+                response_ttl: Some(Duration::from_millis(50)),
+            },
+        );
+
+        let frames: Vec<_> = conns[0]
+            .queue
+            .iter()
+            .map(|frame| &frame.data[..frame.len])
+            .collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            protocol::decode_overload(frames[0]),
+            Some((Some(OverloadReason::Timeout), 0))
+        );
+        assert_eq!(protocol::decode_response(frames[1]), [2.0]);
+    }
+
+    #[test]
+    fn drain_releases_overload_frame_after_earlier_response() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let rq = Arc::new(ResponseQueue::new(8));
+
+        // Request 1 was rejected while request 0 is still being inferred.
+        conns[0].push_overload(1, OverloadReason::RingFull, 7);
+        assert!(conns[0].queue.is_empty());
+        assert_eq!(conns[0].deferred.len(), 1);
+
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let conn = &conns[0];
+        assert!(conn.deferred.is_empty());
+        assert_eq!(conn.queue.len(), 2);
+        assert_eq!(conn.queue[0].data[0], 1, "result frame first");
+        assert_eq!(
+            protocol::decode_overload(&conn.queue[1].data[..conn.queue[1].len]),
+            Some((Some(OverloadReason::RingFull), 7))
+        );
+        assert_eq!(conn.next_response_seq, 2);
+    }
+
+    #[test]
+    fn inline_score_waits_behind_published_response() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let rq = Arc::new(ResponseQueue::new(8));
+
+        // Request 0 went through the ring; request 1 was scored inline.
+        conns[0].push_inline(1, &[2.5]);
+        assert!(conns[0].queue.is_empty());
+
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let conn = &conns[0];
+        assert_eq!(conn.queue.len(), 2);
+        let inline = &conn.queue[1].data[..conn.queue[1].len];
+        assert_eq!(inline.len(), protocol::response_size(1));
+        assert_eq!(inline[0], 1);
+        assert_eq!(f32::from_le_bytes(inline[1..5].try_into().unwrap()), 2.5);
+        assert_eq!(conn.next_response_seq, 2);
+    }
+
+    #[test]
+    fn written_frame_is_reused_for_the_next_response() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let rq = Arc::new(ResponseQueue::new(8));
+        push_inflight(&mut conns[0], &[1u8; 10]);
+        let written: *const ResponseFrame = &*conns[0].inflight[0];
+        conns[0].write_inflight = true;
+
+        handle_write(&mut conns, &registry, 0, 10, UNLIMITED);
+        assert_eq!(conns[0].spare_frames.len(), 1);
+
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let conn = &conns[0];
+        assert!(conn.spare_frames.is_empty());
+        assert!(std::ptr::eq(&*conn.queue[0], written));
+        assert_eq!(conn.queue[0].len, protocol::response_size(1));
+        assert_eq!(conn.queue[0].offset, 0);
+    }
 
-        drain_response_queue(&mut conns, &rq);
+    #[test]
+    fn overload_with_no_earlier_responses_is_queued_immediately() {
+        let registry = make_registry();
+        let (mut conns, _) = setup(&registry);
+
+        conns[0].push_overload(0, OverloadReason::RingFull, 7);
 
         assert_eq!(conns[0].queue.len(), 1);
         assert!(conns[0].ready_queued);
+        assert_eq!(conns[0].next_response_seq, 1);
     }
 
     #[test]
@@ -855,9 +3326,15 @@ mod tests {
             conn_ref.conn_id,
             conn_ref.generation().wrapping_add(1),
         );
-        rq.push(ResponseReady::encode(stale, 0, 1, &[1.0f32]));
-
-        drain_response_queue(&mut conns, &rq);
+        rq.push(ResponseReady::new(stale, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
 
         assert!(conns[0].queue.is_empty());
         assert!(!conns[0].ready_queued);
@@ -869,9 +3346,15 @@ mod tests {
         let (mut conns, conn_ref) = setup(&registry);
         conns[0].write_closed = true;
         let rq = Arc::new(ResponseQueue::new(8));
-        rq.push(ResponseReady::encode(conn_ref, 0, 1, &[1.0f32]));
-
-        drain_response_queue(&mut conns, &rq);
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
 
         assert!(conns[0].queue.is_empty());
     }
@@ -881,11 +3364,17 @@ mod tests {
         let registry = make_registry();
         let (mut conns, _) = setup(&registry);
         let rq = Arc::new(ResponseQueue::new(8));
-        // conn_id=99 does not exist in the slab
+        // conn_id=99 does not exist in the slots
         let ghost = ConnectionRef::new(0, 99, 1);
-        rq.push(ResponseReady::encode(ghost, 0, 1, &[1.0f32]));
-
-        drain_response_queue(&mut conns, &rq);
+        rq.push(ResponseReady::new(ghost, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
 
         assert!(conns[0].queue.is_empty());
     }
@@ -896,7 +3385,7 @@ mod tests {
     fn retire(
         registry: &Arc<ConnectionRegistry>,
         conn_ref: ConnectionRef,
-        conns: &mut Slab<Connection>,
+        conns: &mut ConnectionSlots<Connection>,
     ) {
         let conn = &mut conns[conn_ref.conn_id as usize];
         conn.read_closed = true;
@@ -910,9 +3399,69 @@ mod tests {
         let (mut conns, conn_ref) = setup(&registry);
         retire(&registry, conn_ref, &mut conns);
 
-        reap_retired_connections(&mut conns, &registry);
+        reap_retired_connections(&mut conns, &registry, &IoThreadControl::new(), None);
+
+        assert!(conns.get(0).is_none());
+    }
+
+    #[test]
+    fn reap_tells_inference_thread_the_connection_closed() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        retire(&registry, conn_ref, &mut conns);
+        let (tx, rx) = control_channel(4);
+
+        reap_retired_connections(&mut conns, &registry, &IoThreadControl::new(), Some(&tx));
+
+        assert_eq!(
+            rx.try_recv(),
+            Some(ControlEvent::ConnectionClosed(conn_ref))
+        );
+        assert_eq!(rx.try_recv(), None);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn reap_records_response_accounting() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].next_request_seq = 2;
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+        let frames: Vec<_> = conns[0].queue.drain(..).collect();
+        conns[0].inflight.extend(frames);
+        conns[0].write_inflight = true;
+        handle_write(
+            &mut conns,
+            &registry,
+            0,
+            protocol::response_size(1) as i32,
+            UNLIMITED,
+        );
+        retire(&registry, conn_ref, &mut conns);
+        // The second response arrives after the client went away.
+        rq.push(ResponseReady::new(conn_ref, 1, 1, &[2.0f32]));
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let control = IoThreadControl::new();
+        reap_retired_connections(&mut conns, &registry, &control, None);
 
         assert!(conns.get(0).is_none());
+        assert_eq!(control.accounting().clean, 1);
+        assert_eq!(control.accounting().violations, 0);
     }
 
     #[test]
@@ -922,7 +3471,7 @@ mod tests {
         retire(&registry, conn_ref, &mut conns);
         conns[0].write_inflight = true;
 
-        reap_retired_connections(&mut conns, &registry);
+        reap_retired_connections(&mut conns, &registry, &IoThreadControl::new(), None);
 
         assert!(conns.get(0).is_some());
     }
@@ -934,7 +3483,7 @@ mod tests {
         retire(&registry, conn_ref, &mut conns);
         push_inflight(&mut conns[0], &[1u8; 5]);
 
-        reap_retired_connections(&mut conns, &registry);
+        reap_retired_connections(&mut conns, &registry, &IoThreadControl::new(), None);
 
         assert!(conns.get(0).is_some());
     }
@@ -947,8 +3496,534 @@ mod tests {
         conns[0].read_closed = true;
         conns[0].write_closed = true;
 
-        reap_retired_connections(&mut conns, &registry);
+        reap_retired_connections(&mut conns, &registry, &IoThreadControl::new(), None);
 
         assert!(conns.get(0).is_some());
     }
+
+    #[test]
+    fn echo_request_seq_prefixes_each_frame() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].prefixes.request_seq = true;
+        conns[0].next_request_seq = 2;
+        conns[0].push_overload(1, OverloadReason::RingFull, 5);
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let queue = &conns[0].queue;
+        assert_eq!(queue.len(), 2);
+        assert_eq!(protocol::decode_seq_prefix(&queue[0].data), 0);
+        assert_eq!(queue[0].len, SEQ_PREFIX_BYTES + protocol::response_size(1));
+        assert_eq!(protocol::decode_seq_prefix(&queue[1].data), 1);
+        assert!(protocol::decode_overload(&queue[1].data[SEQ_PREFIX_BYTES..]).is_some());
+    }
+
+    #[test]
+    fn length_prefix_covers_seq_prefix_and_frame() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].prefixes = FramePrefixes {
+            length: true,
+            request_seq: true,
+            vector_status: false,
+            request_id: false,
+        };
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32, 2.0]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let frame = &conns[0].queue[0];
+        let framed = SEQ_PREFIX_BYTES + protocol::response_size(2);
+        assert_eq!(frame.len, LENGTH_PREFIX_BYTES + framed);
+        assert_eq!(protocol::decode_length_prefix(&frame.data), framed);
+        assert_eq!(
+            protocol::decode_seq_prefix(&frame.data[LENGTH_PREFIX_BYTES..]),
+            0
+        );
+        assert_eq!(
+            protocol::decode_response(&frame.data[LENGTH_PREFIX_BYTES + SEQ_PREFIX_BYTES..]),
+            [1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn vector_status_follows_responses_only() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].prefixes = FramePrefixes {
+            length: true,
+            request_seq: false,
+            vector_status: true,
+            request_id: false,
+        };
+        let mut features = [1.0f32; 3 * FEATURE_DIM];
+        features[FEATURE_DIM - 1] = f32::NAN;
+        let mut request = 3u32.to_le_bytes().to_vec();
+        request.extend(features.iter().flat_map(|f| f.to_le_bytes()));
+        conns[0].read_buf[..request.len()].copy_from_slice(&request);
+        conns[0].note_requests(request.len(), 0, None);
+        conns[0].next_request_seq = 2;
+        conns[0].push_overload(1, OverloadReason::RingFull, 5);
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(
+            conn_ref,
+            0,
+            1,
+            &[f32::NAN, 2.0, f32::INFINITY],
+        ));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let queue = &conns[0].queue;
+        let framed = protocol::response_size(3) + protocol::vector_status_size(3);
+        assert_eq!(queue[0].len, LENGTH_PREFIX_BYTES + framed);
+        assert_eq!(protocol::decode_length_prefix(&queue[0].data), framed);
+        assert_eq!(
+            protocol::decode_vector_status(
+                &queue[0].data[LENGTH_PREFIX_BYTES + protocol::response_size(3)..queue[0].len]
+            ),
+            vec![
+                Some(VectorStatus::InvalidInput),
+                Some(VectorStatus::Ok),
+                Some(VectorStatus::ModelError)
+            ]
+        );
+        assert_eq!(queue[1].len, LENGTH_PREFIX_BYTES + OVERLOAD_FRAME_BYTES);
+        assert!(conns[0].invalid_vectors.is_empty());
+    }
+
+    /// Results as big-endian `f32`s after a `u16` count, to tell a connection's serializer
+    /// from the native one.
+    struct BigEndianResponse;
+
+    impl protocol::ResponseSerializer for BigEndianResponse {
+        fn name(&self) -> &'static str {
+            "big-endian"
+        }
+
+        fn response_size(&self, num_results: usize) -> usize {
+            2 + 4 * num_results
+        }
+
+        fn encode(&self, results: &[f32], dst: &mut [u8]) {
+            dst[..2].copy_from_slice(&(results.len() as u16).to_be_bytes());
+            for (result, out) in results.iter().zip(dst[2..].chunks_exact_mut(4)) {
+                out.copy_from_slice(&result.to_be_bytes());
+            }
+        }
+
+        fn decode(&self, frame: &[u8]) -> Vec<f32> {
+            let num_results = u16::from_be_bytes([frame[0], frame[1]]) as usize;
+            frame[2..2 + 4 * num_results]
+                .chunks_exact(4)
+                .map(|bytes| f32::from_be_bytes(bytes.try_into().unwrap()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn scores_are_written_by_the_connection_serializer() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].serializer = &BigEndianResponse;
+        conns[0].prefixes.length = true;
+        conns[0].next_request_seq = 3;
+        conns[0].push_inline(1, &[3.0]);
+        conns[0].push_overload(2, OverloadReason::RingFull, 5);
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32, 2.0]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let queue = &conns[0].queue;
+        let body = |i: usize| &queue[i].data[LENGTH_PREFIX_BYTES..queue[i].len];
+        assert_eq!(protocol::decode_length_prefix(&queue[0].data), 2 + 4 * 2);
+        assert_eq!(BigEndianResponse.decode(body(0)), [1.0, 2.0]);
+        assert_eq!(
+            BigEndianResponse.decode(body(1)),
+            [3.0],
+            "inline scores too"
+        );
+        assert!(
+            protocol::decode_overload(body(2)).is_some(),
+            "overload frames are the same in every format"
+        );
+    }
+
+    #[test]
+    fn write_path_gathers_and_completes_without_a_ring() {
+        let mut path = WritePath::new(ConnectionRef::new(0, 0, 1)).with_request_seq_echo();
+        for seq in 0..MAX_IOVECS_PER_WRITE as u64 + 1 {
+            path.deliver(&ResponseReady::new(
+                ConnectionRef::new(0, 0, 1),
+                seq,
+                0,
+                &[1.0],
+            ));
+        }
+        let frame_len = SEQ_PREFIX_BYTES + protocol::response_size(1);
+        assert_eq!(path.backlog_bytes(), frame_len * (MAX_IOVECS_PER_WRITE + 1));
+
+        let iovecs = path.gather();
+        assert_eq!(
+            iovecs.len(),
+            MAX_IOVECS_PER_WRITE,
+            "one write takes at most this many"
+        );
+        assert!(iovecs.iter().all(|iov| iov.iov_len == frame_len));
+        path.complete(frame_len + 1);
+        let iovecs = path.gather();
+        assert_eq!(
+            iovecs.len(),
+            MAX_IOVECS_PER_WRITE - 1,
+            "the rest of the first write"
+        );
+        assert_eq!(iovecs[0].iov_len, frame_len - 1, "resumes mid frame");
+        let written = iovecs.iter().map(|iov| iov.iov_len).sum();
+        path.complete(written);
+        assert_eq!(path.gather().len(), 1, "then the frame left queued");
+        path.complete(frame_len);
+        assert!(path.gather().is_empty());
+        assert_eq!(path.backlog_bytes(), 0);
+    }
+
+    #[test]
+    fn request_ids_are_echoed_after_seq_prefix() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        conns[0].prefixes = FramePrefixes {
+            length: true,
+            request_seq: true,
+            vector_status: false,
+            request_id: true,
+        };
+        let mut requests = Vec::new();
+        for request_id in [0xaa, 0xbb] {
+            requests.extend_from_slice(&u64::to_le_bytes(request_id));
+            requests.extend_from_slice(&1u32.to_le_bytes());
+            requests.extend_from_slice(&[0u8; FEATURE_DIM * 4]);
+        }
+        conns[0].read_buf[..requests.len()].copy_from_slice(&requests);
+        conns[0].note_requests(requests.len(), 0, None);
+        conns[0].next_request_seq = 2;
+        conns[0].push_overload(1, OverloadReason::RingFull, 5);
+        let rq = Arc::new(ResponseQueue::new(4));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+
+        drain_response_queue(
+            &mut conns,
+            &rq,
+            &registry,
+            UNLIMITED,
+            ResponseExpiry::default(),
+        );
+
+        let queue = &conns[0].queue;
+        let prefixes = LENGTH_PREFIX_BYTES + SEQ_PREFIX_BYTES;
+        assert_eq!(
+            protocol::decode_length_prefix(&queue[0].data),
+            SEQ_PREFIX_BYTES + REQUEST_ID_BYTES + protocol::response_size(1)
+        );
+        assert_eq!(
+            protocol::decode_request_id(&queue[0].data[prefixes..]),
+            0xaa
+        );
+        assert_eq!(
+            protocol::decode_response(&queue[0].data[prefixes + REQUEST_ID_BYTES..queue[0].len]),
+            [1.0]
+        );
+        assert_eq!(
+            protocol::decode_request_id(&queue[1].data[prefixes..]),
+            0xbb
+        );
+        assert!(protocol::decode_overload(&queue[1].data[prefixes + REQUEST_ID_BYTES..]).is_some());
+        assert!(conns[0].request_ids.is_empty());
+    }
+
+    // ---------------------------------------------------------------------------
+    // write backlog limit
+
+    #[test]
+    fn write_backlog_pauses_reads_until_client_catches_up() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let limit = protocol::response_size(1);
+        let rq = Arc::new(ResponseQueue::new(8));
+        rq.push(ResponseReady::new(conn_ref, 0, 1, &[1.0f32]));
+        rq.push(ResponseReady::new(conn_ref, 1, 1, &[2.0f32]));
+
+        drain_response_queue(&mut conns, &rq, &registry, limit, ResponseExpiry::default());
+
+        let conn = &mut conns[0];
+        assert_eq!(conn.backlog_bytes, 2 * protocol::response_size(1));
+        assert!(conn.read_paused);
+        assert!(!conn.evicted);
+
+        let frames: Vec<_> = conn.queue.drain(..).collect();
+        conn.inflight.extend(frames);
+        conn.write_inflight = true;
+        assert!(!handle_write(&mut conns, &registry, 0, 2, limit));
+        assert!(conns[0].read_paused, "still above half the limit");
+        let remaining: usize = conns[0].inflight.iter().map(|f| f.remaining()).sum();
+        conns[0].write_inflight = true;
+        assert!(handle_write(
+            &mut conns,
+            &registry,
+            0,
+            remaining as i32,
+            limit
+        ));
+        assert!(!conns[0].read_paused);
+        assert_eq!(conns[0].backlog_bytes, 0);
+    }
+
+    #[test]
+    fn write_backlog_past_twice_limit_evicts_connection() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        let limit = protocol::response_size(1);
+        let rq = Arc::new(ResponseQueue::new(8));
+        for seq in 0..4 {
+            rq.push(ResponseReady::new(conn_ref, seq, 1, &[1.0f32]));
+        }
+
+        drain_response_queue(&mut conns, &rq, &registry, limit, ResponseExpiry::default());
+
+        let conn = &conns[0];
+        assert!(conn.evicted);
+        assert!(conn.queue.is_empty());
+        assert_eq!(conn.backlog_bytes, 0);
+        assert!(conn.read_closed && conn.write_closed);
+        assert!(registry.is_retired(conn_ref));
+    }
+
+    #[test]
+    fn poisoned_queue_fails_every_connection() {
+        let registry = make_registry();
+        let (mut conns, conn_ref) = setup(&registry);
+        push_queued(&mut conns[0], &[1, 0, 0, 0, 0]);
+
+        fail_connections(&mut conns, &registry);
+
+        let conn = &conns[0];
+        assert!(conn.evicted);
+        assert!(conn.queue.is_empty());
+        assert!(conn.read_closed && conn.write_closed);
+        assert!(registry.is_retired(conn_ref));
+    }
+
+    #[test]
+    fn wake_reasons_collect_each_completion_kind_once() {
+        let cqes = [
+            (encode_user_data(OP_READ, 3), 16, 0),
+            (encode_user_data(OP_READ, 4), 16, 0),
+            (encode_user_data(OP_NOTIFY, 0), 1, 0),
+            (encode_user_data(OP_CANCEL, 0), 0, 0),
+        ];
+        assert_eq!(
+            wake_reasons(&cqes),
+            metrics::wake::READ | metrics::wake::NOTIFY
+        );
+        assert_eq!(wake_reasons(&[]), 0);
+    }
+
+    #[test]
+    fn multishot_accept_rearms_only_when_it_ends() {
+        let (fd, ecanceled, einval) = (7, -libc::ECANCELED, -libc::EINVAL);
+        assert_eq!(next_accept(true, fd, true, true), AcceptNext::Armed);
+        assert_eq!(
+            next_accept(true, -libc::EMFILE, false, true),
+            AcceptNext::Submit { multishot: true }
+        );
+        assert_eq!(
+            next_accept(true, einval, false, true),
+            AcceptNext::Submit { multishot: false },
+            "falls back on kernels without multishot accept"
+        );
+        assert_eq!(
+            next_accept(false, fd, false, true),
+            AcceptNext::Submit { multishot: false }
+        );
+        // A drain cancels the accept; the listener closes on its final completion only.
+        assert_eq!(next_accept(true, fd, true, false), AcceptNext::Armed);
+        assert_eq!(
+            next_accept(true, ecanceled, false, false),
+            AcceptNext::Stopped
+        );
+        assert_eq!(next_accept(false, fd, false, false), AcceptNext::Stopped);
+    }
+
+    #[test]
+    fn accept_ring_completions_wake_the_data_ring() {
+        let mut ring = IoUring::new(8).unwrap();
+        let mut accept_ring = AcceptRing::new().unwrap();
+        submit_accept_ring_poll(&mut ring, accept_ring.ring.fd());
+        ring.submit();
+
+        let nop = opcode::Nop::new()
+            .build()
+            .user_data(encode_user_data(OP_CANCEL, 0));
+        accept_ring.ring.push(&nop);
+        accept_ring.ring.submit_pushed();
+        ring.wait(1);
+        let mut cqes = Vec::new();
+        ring.drain_cqes_into(&mut cqes);
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(decode_user_data(cqes[0].0).0, OP_ACCEPT_RING);
+
+        // The accept ring still holds its completion for the next loop iteration.
+        accept_ring.ring.drain_cqes_into(&mut accept_ring.cqes);
+        assert_eq!(accept_ring.cqes.len(), 1);
+    }
+
+    /// Wait for and collect `n` completions from `ring`.
+    fn wait_cqes(ring: &mut IoUring, n: usize) -> Vec<(u64, i32, u32)> {
+        let mut cqes = Vec::new();
+        while cqes.len() < n {
+            ring.wait(1);
+            ring.drain_cqes_into(&mut cqes);
+        }
+        cqes
+    }
+
+    #[test]
+    fn probes_rearm_pending_reads_and_close_connections_whose_completion_was_dropped() {
+        let registry = make_registry();
+        let mut conns = ConnectionSlots::new(4);
+        let (sock, mut peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        let pending = conns
+            .insert_with(0, |key| {
+                Connection::new(sock.as_raw_fd(), registry.open(0, key as u16, -1))
+            })
+            .unwrap() as u16;
+        let lost_ref = registry.open(0, 1, -1);
+        let lost = conns
+            .insert_with(0, |_| Connection::new(-1, lost_ref))
+            .unwrap() as u16;
+        let mut ring = IoUring::new(8).unwrap();
+        submit_read(&mut ring, &mut conns, pending);
+        // The other connection's read completed, but the completion was dropped.
+        conns[lost as usize].read_inflight = true;
+
+        probe_connections(&mut ring, &mut conns);
+        for (user_data, result, _) in wait_cqes(&mut ring, 3) {
+            let (op, data) = decode_user_data(user_data);
+            match op {
+                OP_READ => {
+                    assert_eq!((data as u16, result), (pending, -libc::ECANCELED));
+                    assert!(take_cancelled_read(&mut conns, pending, result));
+                    submit_read(&mut ring, &mut conns, pending);
+                }
+                OP_PROBE if result == -libc::ENOENT => {
+                    assert_eq!(data, ((OP_READ as u32) << 16) | lost as u32);
+                    lost_connection_completion(&mut conns, &registry, OP_READ, lost, UNLIMITED);
+                }
+                OP_PROBE => assert_eq!(data, ((OP_READ as u32) << 16) | pending as u32),
+                _ => panic!("unexpected completion {user_data:#x}"),
+            }
+        }
+        assert!(conns[lost as usize].read_closed);
+        assert!(registry.is_retired(lost_ref));
+        let conn = &conns[pending as usize];
+        assert!(conn.read_inflight && !conn.read_probed && !conn.read_closed);
+
+        // The rearmed read takes the next bytes as usual.
+        std::io::Write::write_all(&mut peer, b"x").unwrap();
+        let cqes = wait_cqes(&mut ring, 1);
+        assert_eq!(cqes[0].0, encode_user_data(OP_READ, pending as u32));
+        assert_eq!(cqes[0].1, 1);
+        assert!(!take_cancelled_read(&mut conns, pending, cqes[0].1));
+    }
+
+    #[test]
+    fn a_dropped_singleton_completion_is_noticed_only_once() {
+        let mut ring = IoUring::new(8).unwrap();
+        let mut probes = Probes::default();
+        probes.start(&mut ring, OP_NOTIFY);
+        probes.start(&mut ring, OP_IDLE_SWEEP);
+        probes.complete(OP_IDLE_SWEEP);
+        assert!(!probes.take(OP_IDLE_SWEEP), "its completion arrived");
+        assert!(probes.take(OP_NOTIFY));
+        assert!(!probes.take(OP_NOTIFY), "already rearmed");
+
+        // Neither op was pending, so both probes find nothing to cancel.
+        let cqes = wait_cqes(&mut ring, 2);
+        assert!(cqes.iter().all(|&(user_data, result, _)| {
+            decode_user_data(user_data).0 == OP_PROBE && result == -libc::ENOENT
+        }));
+        assert_eq!(ring.take_dropped_cqes(), 0);
+    }
+
+    #[test]
+    fn fixed_buffers_read_into_the_connection_buffer_and_write_what_is_left_of_each_frame() {
+        let registry = make_registry();
+        let mut conns = ConnectionSlots::new(4);
+        let (sock, mut peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        let key = conns
+            .insert_with(0, |key| {
+                Connection::new(sock.as_raw_fd(), registry.open(0, key as u16, -1))
+            })
+            .unwrap() as u16;
+        let mut ring = IoUring::new(8).unwrap();
+        ring.register_fixed_buffers(4).unwrap();
+
+        submit_read(&mut ring, &mut conns, key);
+        assert!(conns[key as usize].fixed_buffers);
+        std::io::Write::write_all(&mut peer, b"ping").unwrap();
+        let cqes = wait_cqes(&mut ring, 1);
+        assert_eq!(cqes[0].0, encode_user_data(OP_READ, key as u32));
+        assert_eq!(cqes[0].1, 4);
+        assert_eq!(&conns[key as usize].read_buf[..4], b"ping");
+
+        // A frame already partly written, then two whole ones.
+        let conn = &mut conns[key as usize];
+        push_queued(conn, b"first");
+        push_queued(conn, b"second");
+        push_queued(conn, b"third");
+        conn.queue[0].offset = 3;
+        let expected: Vec<u8> = conn
+            .queue
+            .iter()
+            .flat_map(|frame| frame.remaining_bytes().to_vec())
+            .collect();
+        submit_write(&mut ring, &mut conns, &registry, key);
+        let cqes = wait_cqes(&mut ring, 1);
+        assert_eq!(cqes[0].0, encode_user_data(OP_WRITE, key as u32));
+        assert_eq!(cqes[0].1, expected.len() as i32);
+
+        let mut written = vec![0u8; expected.len()];
+        std::io::Read::read_exact(&mut peer, &mut written).unwrap();
+        assert_eq!(written, expected);
+        let conn = &mut conns[key as usize];
+        conn.complete_write(cqes[0].1 as usize);
+        assert!(conn.inflight.is_empty());
+    }
 }
@@ -0,0 +1,555 @@
+//! The IO thread on epoll, for kernels and containers without io_uring.
+//!
+//! Readiness stands in for completions. A submitted read is tried at once and, if the socket has
+//! nothing yet, again once epoll reports it readable; its result goes through the same
+//! `handle_read` a ring's read completion does. A write is a `writev` of the same gathered
+//! frames, retried once the socket is writable, and completes through `handle_write`. Parsing,
+//! publishing, response framing and connection lifetimes are the io_uring thread's code, so a
+//! client cannot tell the two apart. Provided read buffers need io_uring and are not used.
+
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use disruptor::Producer;
+
+use super::{
+    Connection, IDLE_SWEEP_INTERVAL, IngressThread, SubmitReads, abort_connection,
+    close_answered_connections, close_idle_connections, drain_request_overflow,
+    drain_response_queue, fail_connections, handle_accept, handle_read, handle_write,
+    maybe_mark_read_closed, parse_and_maybe_read, publish_control_state, reap_retired_connections,
+    resume_reads,
+};
+use crate::clock::{elapsed_since_ns, monotonic_now_ns};
+use crate::config::SLAB_CAPACITY;
+use crate::connection_id::ConnectionRef;
+use crate::embedding::EmbeddingReader;
+use crate::metrics;
+use crate::notify;
+use crate::pipeline::connection_registry::ConnectionRegistry;
+use crate::ring_types::InferenceEvent;
+use crate::server::buf_ring::ReadBufferRing;
+use crate::server::control::IoThreadState;
+use crate::server::slots::ConnectionSlots;
+
+/// Event tokens past any connection slot.
+const LISTEN_TOKEN: u64 = u64::MAX;
+const NOTIFY_TOKEN: u64 = u64::MAX - 1;
+const CONTROL_TOKEN: u64 = u64::MAX - 2;
+
+const MAX_EVENTS: usize = 256;
+
+struct Epoll {
+    fd: RawFd,
+    /// Connections with a read submitted, tried before the next wait.
+    reads: Vec<u16>,
+    /// Connections epoll could not watch, to be closed before their reads are tried.
+    unwatched: Vec<u16>,
+    /// The connection each slot's socket is registered for.
+    registered: Vec<Option<ConnectionRef>>,
+}
+
+impl Epoll {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd,
+            reads: Vec::new(),
+            unwatched: Vec::new(),
+            registered: vec![None; SLAB_CAPACITY],
+        })
+    }
+
+    fn ctl(&self, op: libc::c_int, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event { events, u64: token };
+        if unsafe { libc::epoll_ctl(self.fd, op, fd, &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, fd, libc::EPOLLIN as u32, token)
+    }
+
+    /// Watch `conn`'s socket under `key`, once per connection.
+    fn register(&mut self, conn: &Connection, key: u16) {
+        let registered = &mut self.registered[key as usize];
+        if *registered == Some(conn.conn) {
+            return;
+        }
+        // Edge-triggered: reads and writes are tried before waiting, so only a socket becoming
+        // ready again matters.
+        let events = (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET) as u32;
+        let added = self
+            .ctl(libc::EPOLL_CTL_ADD, conn.fd, events, key as u64)
+            .or_else(|e| match e.raw_os_error() {
+                // The fd number is still watched for the slot's previous connection.
+                Some(libc::EEXIST) => self.ctl(libc::EPOLL_CTL_MOD, conn.fd, events, key as u64),
+                _ => Err(e),
+            });
+        match added {
+            Ok(()) => self.registered[key as usize] = Some(conn.conn),
+            // Without events the connection would never be read or written again.
+            Err(e) => {
+                eprintln!(
+                    "disrust: io-{} cannot watch conn {key} with epoll, closing it: {e}",
+                    conn.conn.shard_id()
+                );
+                self.unwatched.push(key);
+            }
+        }
+    }
+
+    /// Wait up to `timeout_ms` (`-1` for no limit) for events, returning how many arrived.
+    fn wait(&self, events: &mut [libc::epoll_event], timeout_ms: i32) -> usize {
+        let n = unsafe {
+            libc::epoll_wait(
+                self.fd,
+                events.as_mut_ptr(),
+                events.len() as libc::c_int,
+                timeout_ms,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            assert_eq!(
+                e.kind(),
+                io::ErrorKind::Interrupted,
+                "epoll_wait failed: {e}"
+            );
+            return 0;
+        }
+        n as usize
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl SubmitReads for Epoll {
+    fn submit_read(&mut self, conn: &mut Connection, key: u16) {
+        self.submit_direct_read(conn, key);
+    }
+
+    fn submit_direct_read(&mut self, conn: &mut Connection, key: u16) {
+        self.register(conn, key);
+        conn.read_inflight = true;
+        conn.read_requested = conn.read_size();
+        self.reads.push(key);
+        metrics::inc_read_submits();
+    }
+
+    fn read_buffers(&mut self) -> Option<&mut ReadBufferRing> {
+        None
+    }
+}
+
+fn errno() -> i32 {
+    io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(libc::EIO)
+}
+
+/// Try `key`'s submitted read, returning the result a ring would have completed it with, or
+/// `None` if the socket has nothing yet.
+fn try_read(conns: &mut ConnectionSlots<Connection>, key: u16) -> Option<i32> {
+    let conn = conns.get_mut(key as usize)?;
+    if !conn.read_inflight {
+        return None;
+    }
+    let (buf_ptr, buf_len) = conn.read_buf_tail();
+    let n = unsafe { libc::recv(conn.fd, buf_ptr.cast(), buf_len as usize, 0) };
+    match n {
+        0.. => Some(n as i32),
+        _ => match errno() {
+            libc::EAGAIN | libc::EINTR => None,
+            e => Some(-e),
+        },
+    }
+}
+
+/// Write `key`'s queued frames until its socket would block or nothing is left. A write the
+/// socket cannot take yet stays in flight until epoll reports it writable.
+fn write_until_blocked(
+    epoll: &mut Epoll,
+    conns: &mut ConnectionSlots<Connection>,
+    registry: &Arc<ConnectionRegistry>,
+    parse_queue: &mut VecDeque<u16>,
+    key: u16,
+    write_backlog_limit: usize,
+) {
+    loop {
+        let Some(conn) = conns.get_mut(key as usize) else {
+            return;
+        };
+        if !conn.write_inflight {
+            conn.ready_queued = false;
+            if conn.write_closed {
+                return;
+            }
+            if conn.gather_write() == 0 {
+                maybe_mark_read_closed(registry, conn);
+                return;
+            }
+            conn.write_inflight = true;
+            metrics::inc_write_sqes();
+        }
+        let n = unsafe {
+            libc::writev(
+                conn.fd,
+                conn.inflight_iovecs.as_ptr(),
+                conn.inflight_iov_count as libc::c_int,
+            )
+        };
+        let result = match n {
+            0.. => n as i32,
+            _ => match errno() {
+                libc::EAGAIN => return,
+                libc::EINTR => continue,
+                e => -e,
+            },
+        };
+        if handle_write(conns, registry, key, result, write_backlog_limit) {
+            resume_reads(epoll, conns, parse_queue, key);
+        }
+        if !conns
+            .get(key as usize)
+            .is_some_and(|conn| conn.ready_queued && !conn.write_inflight)
+        {
+            return;
+        }
+    }
+}
+
+/// Close the listener; the thread accepts no more connections.
+fn stop_accepting(epoll: &Epoll, listen_fd: RawFd, accepting: &mut bool) {
+    if *accepting {
+        *accepting = false;
+        let _ = epoll.ctl(libc::EPOLL_CTL_DEL, listen_fd, 0, 0);
+        unsafe { libc::close(listen_fd) };
+    }
+}
+
+/// [`IngressThread::run`] with epoll in place of the data and accept rings.
+pub(super) fn run<P>(mut t: IngressThread<P>)
+where
+    P: Producer<InferenceEvent>,
+{
+    // Until this thread exits, the inference thread waits for room in a full queue.
+    let _consumer = t.response_queue.attach_consumer();
+    let mut epoll = Epoll::new().expect("epoll creation failed");
+    if t.read_buffer_ring.is_some() {
+        eprintln!(
+            "disrust: io-{} provided read buffers need io_uring, reading into per-connection buffers",
+            t.thread_id
+        );
+    }
+    if t.fixed_buffers {
+        eprintln!(
+            "disrust: io-{} fixed buffers need io_uring, reading and writing unregistered",
+            t.thread_id
+        );
+    }
+    let mut conns = ConnectionSlots::new(SLAB_CAPACITY);
+    if let Some(quarantine) = t.slot_quarantine {
+        conns = conns.with_quarantine(quarantine);
+    }
+    // Accepts are tried until the listener has none left, so it must not block.
+    unsafe {
+        let flags = libc::fcntl(t.listen_fd, libc::F_GETFL);
+        libc::fcntl(t.listen_fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    let notify_fd = t
+        .response_queue
+        .notify_fd()
+        .expect("IO thread response queue must have a pollable notifier");
+    epoll
+        .add(t.listen_fd, LISTEN_TOKEN)
+        .expect("epoll cannot watch the listener");
+    epoll
+        .add(notify_fd, NOTIFY_TOKEN)
+        .expect("epoll cannot watch the response queue");
+    epoll
+        .add(t.control.notify_fd(), CONTROL_TOKEN)
+        .expect("epoll cannot watch the control notifier");
+    let mut accepting = true;
+    let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+    let mut parse_queue: VecDeque<u16> = VecDeque::new();
+    let mut ready: Vec<u16> = Vec::new();
+    let mut poisoned = false;
+    let sweep_interval = t
+        .idle_timeout
+        .map(|timeout| timeout.min(IDLE_SWEEP_INTERVAL));
+    let mut next_sweep_ns =
+        sweep_interval.map(|interval| monotonic_now_ns() + interval.as_nanos() as u64);
+
+    loop {
+        let iteration_start = monotonic_now_ns();
+        let write_backlog_limit = t.limits.write_backlog_bytes();
+
+        let phase_start = monotonic_now_ns();
+        drain_response_queue(
+            &mut conns,
+            &t.response_queue,
+            &t.registry,
+            write_backlog_limit,
+            t.expiry,
+        );
+        metrics::add_io_response_drain(monotonic_now_ns().saturating_sub(phase_start));
+
+        if t.response_queue.is_poisoned() {
+            if !poisoned {
+                poisoned = true;
+                eprintln!(
+                    "disrust: io-{} inference thread gone, closing {} connection(s)",
+                    t.thread_id,
+                    conns.len()
+                );
+                stop_accepting(&epoll, t.listen_fd, &mut accepting);
+            }
+            fail_connections(&mut conns, &t.registry);
+        }
+
+        if !t.overflow.is_empty() {
+            drain_request_overflow(
+                &mut conns,
+                &mut parse_queue,
+                &mut t.overflow,
+                &mut t.producer,
+                &mut t.allocator,
+                &t.publish_gate,
+                t.inline.as_ref(),
+            );
+        }
+
+        if let Some(key) = parse_queue.pop_front() {
+            if let Some(conn) = conns.get_mut(key as usize) {
+                conn.parse_queued = false;
+            }
+            let phase_start = monotonic_now_ns();
+            parse_and_maybe_read(
+                &mut epoll,
+                &mut conns,
+                &mut parse_queue,
+                &mut t.producer,
+                &mut t.allocator,
+                &t.publish_gate,
+                &t.registry,
+                &t.limits,
+                t.inline.as_ref(),
+                t.schema.as_deref(),
+                &mut t.overflow,
+                t.admission.as_deref(),
+                t.circuit.as_deref(),
+                t.embeddings.as_mut().map(EmbeddingReader::current),
+                t.replay.as_deref(),
+                key,
+            );
+            metrics::add_io_parse(monotonic_now_ns().saturating_sub(phase_start));
+        }
+
+        for key in std::mem::take(&mut epoll.unwatched) {
+            if let Some(conn) = conns.get_mut(key as usize) {
+                abort_connection(&t.registry, conn);
+            }
+        }
+        for key in std::mem::take(&mut epoll.reads) {
+            let Some(result) = try_read(&mut conns, key) else {
+                continue;
+            };
+            handle_read(
+                &mut epoll,
+                &mut conns,
+                &mut parse_queue,
+                &mut t.producer,
+                &mut t.allocator,
+                &t.publish_gate,
+                &t.registry,
+                &t.limits,
+                t.inline.as_ref(),
+                t.schema.as_deref(),
+                &mut t.overflow,
+                t.admission.as_deref(),
+                t.circuit.as_deref(),
+                t.embeddings.as_mut().map(EmbeddingReader::current),
+                t.replay.as_deref(),
+                &t.control,
+                key,
+                result,
+                None,
+            );
+        }
+
+        // Writes go after parsing and reads, so the frames those queued are written in this
+        // iteration too: a connection is left with frames to write only while its socket is full.
+        let phase_start = monotonic_now_ns();
+        ready.extend(conns.iter().filter_map(|(key, conn)| {
+            (conn.ready_queued && !conn.write_closed && !conn.write_inflight).then_some(key as u16)
+        }));
+        for key in ready.drain(..) {
+            write_until_blocked(
+                &mut epoll,
+                &mut conns,
+                &t.registry,
+                &mut parse_queue,
+                key,
+                write_backlog_limit,
+            );
+        }
+        metrics::add_io_write_submit(monotonic_now_ns().saturating_sub(phase_start));
+
+        if t.control.shutdown_requested() {
+            close_answered_connections(&mut conns, &t.registry, &t.overflow);
+        }
+        reap_retired_connections(
+            &mut conns,
+            &t.registry,
+            &t.control,
+            t.inference_control.as_ref(),
+        );
+        if publish_control_state(&t.control, &conns, accepting) {
+            eprintln!("disrust: io-{} drained", t.thread_id);
+        }
+        if t.control.remove_requested() && t.control.state() == IoThreadState::Drained {
+            return;
+        }
+
+        // Wait only with nothing left to do before the next event.
+        let busy = !parse_queue.is_empty()
+            || !epoll.reads.is_empty()
+            || !epoll.unwatched.is_empty()
+            || !t.overflow.is_empty();
+        let timeout_ms = if busy {
+            0
+        } else {
+            next_sweep_ns.map_or(-1, |at_ns| {
+                at_ns.saturating_sub(monotonic_now_ns()).div_ceil(1_000_000) as i32
+            })
+        };
+        let phase_start = monotonic_now_ns();
+        let n = epoll.wait(&mut events, timeout_ms);
+        let wait_ns = monotonic_now_ns().saturating_sub(phase_start);
+        metrics::add_io_wait(wait_ns);
+
+        let phase_start = monotonic_now_ns();
+        let mut wake = 0;
+        for event in &events[..n] {
+            let (token, flags) = (event.u64, event.events);
+            match token {
+                LISTEN_TOKEN => {
+                    wake |= metrics::wake::ACCEPT;
+                    // A drain may have closed the listener earlier in this wake.
+                    if accepting {
+                        loop {
+                            let fd = unsafe {
+                                libc::accept4(
+                                    t.listen_fd,
+                                    ptr::null_mut(),
+                                    ptr::null_mut(),
+                                    libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                                )
+                            };
+                            let result = if fd >= 0 { fd } else { -errno() };
+                            if result == -libc::EAGAIN {
+                                break;
+                            }
+                            handle_accept(
+                                &mut epoll,
+                                &mut conns,
+                                result,
+                                t.thread_id,
+                                t.max_connections,
+                                t.prefixes,
+                                t.request_decoder,
+                                t.response_serializer,
+                                t.fixed_point,
+                                t.feature_dim,
+                                t.max_vectors,
+                                t.adaptive_reads,
+                                t.replay.is_some(),
+                                &t.registry,
+                            );
+                            if result < 0 && result != -libc::EINTR && result != -libc::ECONNABORTED
+                            {
+                                break;
+                            }
+                        }
+                    }
+                }
+                NOTIFY_TOKEN => {
+                    wake |= metrics::wake::NOTIFY;
+                    notify::drain_fd(notify_fd);
+                }
+                CONTROL_TOKEN => {
+                    wake |= metrics::wake::CONTROL;
+                    notify::drain_fd(t.control.notify_fd());
+                    if t.control.take_listing_request() {
+                        t.control
+                            .publish_connections(conns.iter().map(|(_, c)| c.info()).collect());
+                    }
+                    if accepting && t.control.state() == IoThreadState::Draining {
+                        if t.control.shutdown_requested() {
+                            eprintln!(
+                                "disrust: io-{} shutting down, answering {} connection(s)",
+                                t.thread_id,
+                                conns.len()
+                            );
+                        } else {
+                            eprintln!("disrust: io-{} draining", t.thread_id);
+                        }
+                        stop_accepting(&epoll, t.listen_fd, &mut accepting);
+                    }
+                }
+                key => {
+                    let key = key as u16;
+                    let Some(conn) = conns.get(key as usize) else {
+                        continue;
+                    };
+                    let failed = flags & (libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0;
+                    if conn.read_inflight
+                        && (failed || flags & (libc::EPOLLIN | libc::EPOLLRDHUP) as u32 != 0)
+                    {
+                        wake |= metrics::wake::READ;
+                        epoll.reads.push(key);
+                    }
+                    if conn.write_inflight && (failed || flags & libc::EPOLLOUT as u32 != 0) {
+                        wake |= metrics::wake::WRITE;
+                        write_until_blocked(
+                            &mut epoll,
+                            &mut conns,
+                            &t.registry,
+                            &mut parse_queue,
+                            key,
+                            write_backlog_limit,
+                        );
+                    }
+                }
+            }
+        }
+        metrics::record_io_wake(wake);
+
+        if let (Some(timeout), Some(interval), Some(at_ns)) =
+            (t.idle_timeout, sweep_interval, next_sweep_ns)
+        {
+            let now_ns = monotonic_now_ns();
+            if now_ns >= at_ns {
+                close_idle_connections(&mut conns, &t.registry, &t.overflow, timeout, now_ns);
+                next_sweep_ns = Some(now_ns + interval.as_nanos() as u64);
+            }
+        }
+        metrics::add_io_cqe(monotonic_now_ns().saturating_sub(phase_start));
+        metrics::record_io_iteration(
+            elapsed_since_ns(iteration_start).saturating_sub(Duration::from_nanos(wait_ns)),
+        );
+    }
+}
@@ -1,34 +1,43 @@
-use std::os::unix::io::IntoRawFd;
-use std::panic::{self, AssertUnwindSafe};
-use std::sync::Arc;
-use std::sync::mpsc;
-use std::thread;
+use std::fmt;
+use std::str::FromStr;
 
 use clap::Args;
-use disruptor::{BusySpin, build_multi_producer};
-use socket2::{Domain, Protocol, Socket, Type};
 
-use crate::affinity;
-use crate::buffer_pool::{BufferPool, set_factory_pool};
 use crate::config::{
-    DEFAULT_BATCH_COALESCE_US, GPU_BUFFER_POOL_BYTES, GPU_DISRUPTOR_SIZE, MAX_IO_THREADS,
-    MAX_SESSION_BATCH_SIZE, SESSION_POOL_SIZE, SLAB_CAPACITY,
+    DEFAULT_BATCH_COALESCE_US, DEFAULT_SAMPLE_RATE, MAX_SESSION_BATCH_SIZE, SLAB_CAPACITY,
 };
-use crate::metrics;
-use crate::pipeline::connection_registry::ConnectionRegistry;
-use crate::pipeline::inference::InferenceConsumer;
-use crate::pipeline::response_queue::ResponseQueue;
-use crate::pipeline::{InferenceBackend, OrtBackend};
-use crate::ring_types::InferenceEvent;
-
+use crate::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::pipeline::BackendKind;
+use crate::pipeline::inline::PlacementPolicy;
+use crate::request_flow::{MalformedPolicy, NonFinitePolicy};
+
+pub mod accounting;
+pub mod admin;
+#[cfg(target_os = "linux")]
+mod buf_ring;
+pub mod control;
+pub mod control_plane;
+#[cfg(target_os = "linux")]
+mod fixed_buf;
+#[cfg(target_os = "linux")]
 mod ingress;
-
-pub use ingress::IngressThread;
-
-enum WorkerExit {
-    Returned(&'static str),
-    Panicked(&'static str, String),
-}
+pub mod model_reload;
+pub mod reload;
+pub mod replay;
+#[cfg(target_os = "linux")]
+mod serve;
+pub mod shutdown;
+pub mod slots;
+#[cfg(target_os = "linux")]
+mod state_dump;
+
+pub use control::{AccountingCounts, IoThreadControl, IoThreadSet, IoThreadState};
+pub use control_plane::ControlPlane;
+#[cfg(target_os = "linux")]
+pub use ingress::{IngressThread, WritePath};
+pub use reload::{ConfigReloader, SoftLimits};
+#[cfg(target_os = "linux")]
+pub use serve::{Server, ServerBuilder, ServerError, run};
 
 #[derive(Args, Clone)]
 pub struct ServeArgs {
@@ -36,23 +45,137 @@ pub struct ServeArgs {
     #[arg(short, long, default_value_t = 9900)]
     pub port: u16,
 
-    /// Path to ONNX model file
+    /// Path to ONNX model file, or with `--backend gbdt` to an XGBoost text dump
     #[arg(short, long)]
     pub model: String,
 
-    /// Runtime cap on ring slots per GPU submission.
+    /// Backend that runs --model: `ort` (ONNX Runtime) or `gbdt` (native tree ensemble).
+    #[arg(long, default_value = "ort")]
+    pub backend: BackendKind,
+
+    /// Manifest (`version`, `sha256`, `dtype`, `input_dim`) to check --model against before
+    /// loading it.
+    #[arg(long)]
+    pub model_manifest: Option<std::path::PathBuf>,
+
+    /// Features per request vector. Requests, the model input, and every per-feature file
+    /// (manifest, canary, schema, inline model) must all use this width.
+    #[arg(long, default_value_t = FEATURE_DIM, value_parser = crate::protocol::parse_feature_dim)]
+    pub feature_dim: usize,
+
+    /// Most vectors a request may carry, up to MAX_VECTORS_PER_REQUEST; larger requests are
+    /// malformed.
+    #[arg(
+        long,
+        default_value_t = MAX_VECTORS_PER_REQUEST,
+        value_parser = crate::protocol::parse_max_vectors
+    )]
+    pub max_vectors: usize,
+
+    /// --max-vectors for single ports under --per-thread-ports, as `port:vectors[,...]`; ports
+    /// not listed use --max-vectors.
+    #[arg(long, requires = "per_thread_ports")]
+    pub port_max_vectors: Option<String>,
+
+    /// Canary file of `input` vectors and the `min`/`max` their outputs must fall within, run
+    /// against the model before any IO thread starts. A failing canary keeps the server from
+    /// accepting connections.
+    #[arg(long)]
+    pub canary: Option<std::path::PathBuf>,
+
+    /// Feature schema (`dim`, then `feature.<index> = <name> range|categorical ...` lines) to
+    /// check every request vector against, counting violations per feature.
+    #[arg(long)]
+    pub feature_schema: Option<std::path::PathBuf>,
+
+    /// Embedding table (`DREMBED1`, `rows` and `dim` as u32, then the rows as f32, all
+    /// little-endian) to look up the ids sent in the last --embedding-ids values of every
+    /// request vector. The model sees each id's row in its place. Re-mapped on SIGHUP with
+    /// --config.
+    #[arg(
+        long,
+        requires = "embedding_ids",
+        conflicts_with_all = ["inline_linear_model", "feature_schema"]
+    )]
+    pub embeddings: Option<std::path::PathBuf>,
+
+    /// How many values at the end of every request vector are `u32` ids into --embeddings.
+    #[arg(long, requires = "embeddings")]
+    pub embedding_ids: Option<usize>,
+
+    /// Calibration (`method = platt|isotonic` and its parameters) applied to every result on the
+    /// inference thread. Re-read on SIGHUP with --config, independently of the model.
+    #[arg(long, conflicts_with = "inline_linear_model")]
+    pub calibration: Option<std::path::PathBuf>,
+
+    /// Keep running per-feature mean, variance and range over every scored vector, reported as
+    /// a per-interval `drift` metrics line and by the admin `drift` command.
+    #[arg(long)]
+    pub drift_stats: bool,
+
+    /// Append a line of features and scores for a sampled fraction of requests to this file, or
+    /// to a Unix socket given as `unix:<path>`, for debugging model outputs.
+    #[arg(long)]
+    pub sample_sink: Option<String>,
+
+    /// Fraction of requests --sample-sink records. Re-read on SIGHUP with --config.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_SAMPLE_RATE,
+        value_parser = crate::pipeline::sampling::parse_sample_rate
+    )]
+    pub sample_rate: f64,
+
+    /// Runtime cap on ring slots per GPU submission: a batch is submitted once it holds this
+    /// many requests, without waiting out --batch-coalesce-us.
     #[arg(long, default_value_t = MAX_SESSION_BATCH_SIZE)]
     pub max_batch_slots: usize,
 
-    /// Coalescing window for a partial batch once a session is available, in microseconds.
+    /// Coalescing window for a partial batch once a session is available, in microseconds:
+    /// requests accumulate across ring polls until --max-batch-slots or this long after the
+    /// first, whichever comes first. 0 submits whatever is waiting.
     #[arg(long, default_value_t = DEFAULT_BATCH_COALESCE_US)]
     pub batch_coalesce_us: u64,
 
+    /// Stop holding a partial batch open for more requests once the oldest waiting request,
+    /// counted from its publish to the request ring, plus the expected batch time reaches this
+    /// many microseconds.
+    #[arg(long)]
+    pub request_deadline_us: Option<u64>,
+
+    /// Answer a request with a timeout frame once this many microseconds have passed since its
+    /// publish to the request ring: it is dropped unrun if it is still waiting for a batch, and
+    /// its scores are replaced if they reach its IO thread later.
+    #[arg(long)]
+    pub request_timeout_us: Option<u64>,
+
+    /// Answer a request with a timeout frame instead of its scores once they have waited this
+    /// many microseconds for their IO thread, as they may while it stalls, so no bandwidth goes
+    /// on answers clients have likely given up on. Counted as stale, apart from overload.
+    #[arg(long)]
+    pub response_ttl_us: Option<u64>,
+
+    /// Answer new requests with overload frames, without running them, for `open_ms` once at
+    /// least `percent` of a `window` of requests timed out, as `percent:window:open_ms`; then
+    /// let a few through and resume once they are answered in time. Needs --request-timeout-us.
+    #[arg(long)]
+    pub circuit_breaker: Option<String>,
+
+    /// Return bit-identical results for identical requests across runs: deterministic backend
+    /// kernels and one request per batch, at some cost in throughput.
+    #[arg(long, conflicts_with = "inline_linear_model")]
+    pub deterministic: bool,
+
     /// Metrics reporting interval in seconds.
     #[arg(long, default_value_t = 10)]
     pub metrics_interval_secs: u64,
 
-    /// Pin the metrics reporter thread to a specific CPU id.
+    /// Serve the metrics in Prometheus text format at `GET /metrics` on this port.
+    #[cfg(feature = "prometheus")]
+    #[arg(long)]
+    pub prometheus_port: Option<u16>,
+
+    /// Pin the control-plane thread (metrics reports, admin socket) to a specific CPU id.
     #[arg(long)]
     pub metrics_cpu: Option<usize>,
 
@@ -71,193 +194,244 @@ pub struct ServeArgs {
     /// Number of ingress IO threads to run via SO_REUSEPORT sharding.
     #[arg(long, default_value_t = 1)]
     pub io_threads: u8,
-}
 
-fn create_listener(port: u16) -> Socket {
-    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))
-        .expect("socket creation failed");
-    socket.set_reuse_address(true).unwrap();
-    socket.set_reuse_port(true).expect("SO_REUSEPORT failed");
-    socket.set_nonblocking(true).unwrap();
-    socket.set_nodelay(true).unwrap();
-    let addr = std::net::SocketAddrV4::new(std::net::Ipv4Addr::UNSPECIFIED, port);
-    socket.bind(&addr.into()).expect("bind failed");
-    socket.listen(1024).expect("listen failed");
-    socket
+    /// Bind each IO thread to its own port (port + thread index) instead of sharing one port
+    /// via SO_REUSEPORT, so an external load balancer can target and drain threads individually.
+    #[arg(long)]
+    pub per_thread_ports: bool,
+
+    /// Maximum concurrent connections per ingress IO thread.
+    #[arg(long, default_value_t = SLAB_CAPACITY)]
+    pub max_connections: usize,
+
+    /// Back the request feature pool with huge pages to cut TLB misses: reserved huge pages when
+    /// enough are free, else transparent huge pages, else ordinary pages. Ignored with CUDA,
+    /// whose pool is pinned host memory.
+    #[arg(long)]
+    pub huge_pages: bool,
+
+    /// Refuse to start if the worst-case allocation plan exceeds this many MiB.
+    #[arg(long)]
+    pub memory_budget_mb: Option<usize>,
+
+    /// Lower --max-connections to fit --memory-budget-mb instead of refusing to start.
+    #[arg(long, requires = "memory_budget_mb")]
+    pub memory_budget_shrink: bool,
+
+    /// Reject requests that find the request ring full with an overload frame carrying this
+    /// Retry-After hint, instead of holding them until a slot frees up.
+    #[arg(long)]
+    pub overload_retry_after_ms: Option<u16>,
+
+    /// Admit requests of more than `vectors` vectors to the request ring only while fewer than
+    /// `limit` of them are unanswered, as `vectors:limit`, so a burst of huge requests cannot
+    /// delay the small ones behind it. Others wait, or are rejected with
+    /// --overload-retry-after-ms.
+    #[arg(long)]
+    pub large_requests: Option<String>,
+
+    /// Prefix every response and overload frame with the `request_seq` it answers (`u32` LE), so
+    /// clients can detect lost, duplicated or reordered responses. Clients must opt in to match.
+    #[arg(long)]
+    pub echo_request_seq: bool,
+
+    /// Let clients resume a session after reconnecting: every connection opens with a replay
+    /// hello carrying the session token the server issued, `request_seq` runs on across a
+    /// session's connections, and the last this many frames of each session are resent from
+    /// where the client left off. Needs --echo-request-seq.
+    #[arg(long)]
+    pub replay_window: Option<usize>,
+
+    /// Prefix every frame with its length (`u32` LE, counting the `request_seq` prefix and the
+    /// frame after it), so clients can skip frames they do not understand. Clients must opt in
+    /// to match.
+    #[arg(long)]
+    pub length_prefix: bool,
+
+    /// Follow every response with a status byte per vector (`ok`, `invalid_input`,
+    /// `model_error`), so a client can use the good results of a partly failed request. Clients
+    /// must opt in to match.
+    #[arg(long)]
+    pub vector_status: bool,
+
+    /// Read a client-chosen `request_id` (`u64` LE) before every request and echo it before the
+    /// frame answering it, after the `request_seq` prefix, so a proxy multiplexing clients onto
+    /// one connection can route responses back. Clients must opt in to match.
+    #[arg(long)]
+    pub request_ids: bool,
+
+    /// Let a request set bit 30 of its `num_vectors` to have its scores sent back as `u16`s,
+    /// each score times this scale, rounded and saturated to 0..=65535, e.g. 10000 for basis
+    /// points. Without it, such requests are malformed, so a client learns the server cannot
+    /// answer them.
+    #[arg(long, value_parser = crate::protocol::parse_fixed_point_scale)]
+    pub fixed_point_scale: Option<f32>,
+
+    /// What to do with requests carrying NaN or infinite features: `pass` them to the model,
+    /// `clamp` them to finite values, or `reject` them with a parse error frame.
+    #[arg(long, default_value = "pass")]
+    pub non_finite_features: NonFinitePolicy,
+
+    /// What to do with a request whose `num_vectors` is out of range: answer it with a parse
+    /// error frame and `close` the connection, or `skip` the vectors its header declared (up to
+    /// 1 MiB) and keep the connection open.
+    #[arg(long, default_value = "close")]
+    pub malformed_requests: MalformedPolicy,
+
+    /// Score small requests on the IO thread with this linear model (`--feature-dim` weights,
+    /// then a bias), skipping the inference thread. The model must compute the same function as
+    /// --model.
+    #[arg(long)]
+    pub inline_linear_model: Option<std::path::PathBuf>,
+
+    /// Which requests --inline-linear-model scores, as `vectors:occupancy[,...]`: requests of up
+    /// to `vectors` vectors go inline while at most `occupancy` requests wait in the request ring.
+    #[arg(long, default_value = PlacementPolicy::DEFAULT)]
+    pub inline_policy: String,
+
+    /// Stop reading from a connection once this many KiB of responses are queued behind it, and
+    /// close it at twice that, so a client that stops reading cannot grow server memory.
+    #[arg(long)]
+    pub max_write_backlog_kb: Option<usize>,
+
+    /// Close a connection once it has sent no request and been written no response for this
+    /// many seconds, and everything it sent is answered, freeing its slot for a new one.
+    #[arg(long)]
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Hold a closed connection's slot back this many milliseconds before a new connection
+    /// reuses its `conn_id`.
+    #[arg(long)]
+    pub slot_quarantine_ms: Option<u64>,
+
+    /// Read from this many shared provided buffers per IO thread (a power of two up to 32768)
+    /// instead of holding a read buffer for every connection while it waits.
+    #[arg(long)]
+    pub read_buffer_ring: Option<u16>,
+
+    /// Size each connection's socket reads by its recent bytes per read (from 4 KiB, doubling
+    /// while reads fill) instead of always asking for its whole free read buffer.
+    #[arg(long)]
+    pub adaptive_reads: bool,
+
+    /// Register each connection's read buffer and a write buffer per connection slot with the
+    /// IO thread's io_uring, and read and write through them without pinning pages per
+    /// operation.
+    #[arg(long)]
+    pub fixed_buffers: bool,
+
+    /// How IO threads drive their sockets: `io-uring`, `epoll` (nonblocking reads and `writev`,
+    /// for kernels or containers without io_uring), or `auto`, io_uring when a ring can be
+    /// created and epoll otherwise.
+    #[arg(long, default_value = "auto")]
+    pub io_backend: IoBackend,
+
+    /// On SIGTERM or SIGINT, how long to wait for in-flight requests to be answered and written
+    /// before exiting anyway.
+    #[arg(long, default_value_t = 30)]
+    pub shutdown_grace_secs: u64,
+
+    /// Also write the state dump printed to stderr when a worker thread fails or shutdown times
+    /// out (pool cursors, ring occupancy, in-flight requests per connection) to this file.
+    #[arg(long)]
+    pub state_dump_file: Option<std::path::PathBuf>,
+
+    /// Unix socket path for admin commands (`health`, `status`, `drain <io-thread-id>`).
+    #[arg(long)]
+    pub admin_socket: Option<std::path::PathBuf>,
+
+    /// Config file of `key = value` settings that override these flags. Re-read on SIGHUP:
+    /// overload, write backlog, metrics interval, non-finite feature, batch coalesce, calibration
+    /// and sample rate changes apply live, others need a restart.
+    #[arg(long)]
+    pub config: Option<std::path::PathBuf>,
 }
 
-fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
-    match payload.downcast::<String>() {
-        Ok(msg) => *msg,
-        Err(payload) => match payload.downcast::<&'static str>() {
-            Ok(msg) => (*msg).to_string(),
-            Err(_) => "non-string panic payload".to_string(),
-        },
+impl ServeArgs {
+    /// `disrust serve --model <model>`: every setting but the model at its default.
+    pub fn for_model(model: impl Into<String>) -> Self {
+        use clap::FromArgMatches;
+
+        let matches = Self::augment_args(clap::Command::new("serve"))
+            .no_binary_name(true)
+            .get_matches_from([format!("--model={}", model.into())]);
+        Self::from_arg_matches(&matches).expect("serve defaults parse")
     }
 }
 
-pub fn run(args: ServeArgs) {
-    if args.metrics_interval_secs == 0 {
-        eprintln!("disrust: --metrics-interval-secs must be > 0");
-        std::process::exit(1);
-    }
+/// How an IO thread drives its sockets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoBackend {
+    /// io_uring if this process can create a ring, epoll otherwise.
+    #[default]
+    Auto,
+    /// A data io_uring and an accept io_uring per IO thread.
+    IoUring,
+    /// Readiness from epoll, with nonblocking reads and `writev`.
+    Epoll,
+}
 
-    metrics::spawn_reporter(args.metrics_interval_secs, args.metrics_cpu);
-    let port = args.port;
-    let max_batch_slots = args.max_batch_slots;
-    let batch_coalesce = std::time::Duration::from_micros(args.batch_coalesce_us);
-    let io_threads = args.io_threads as usize;
-
-    if max_batch_slots == 0 || max_batch_slots > MAX_SESSION_BATCH_SIZE {
-        eprintln!(
-            "disrust: --max-batch-slots must be in 1..={}",
-            MAX_SESSION_BATCH_SIZE
-        );
-        std::process::exit(1);
-    }
-    if io_threads == 0 || io_threads > MAX_IO_THREADS {
-        eprintln!("disrust: --io-threads must be in 1..={MAX_IO_THREADS}");
-        std::process::exit(1);
+impl IoBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IoBackend::Auto => "auto",
+            IoBackend::IoUring => "io-uring",
+            IoBackend::Epoll => "epoll",
+        }
     }
+}
 
-    eprintln!("disrust: starting on port {}", port);
-    eprintln!(
-        "disrust: max_batch_slots={} (compile-time max={})",
-        max_batch_slots, MAX_SESSION_BATCH_SIZE
-    );
-    eprintln!("disrust: batch_coalesce_us={}", args.batch_coalesce_us);
-    if let Some(cpu) = args.metrics_cpu {
-        eprintln!("disrust: metrics_cpu={cpu}");
-    }
-    if let Some(cpu) = args.submission_cpu {
-        eprintln!("disrust: submission_cpu={cpu}");
-    }
-    if let Some(cpu) = args.completion_cpu {
-        eprintln!("disrust: completion_cpu={cpu}");
-    }
-    if let Some(cpu) = args.io_cpu {
-        eprintln!("disrust: io_cpu_base={cpu}");
+impl FromStr for IoBackend {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "auto" => Ok(IoBackend::Auto),
+            "io-uring" => Ok(IoBackend::IoUring),
+            "epoll" => Ok(IoBackend::Epoll),
+            other => Err(format!(
+                "unknown IO backend '{other}', expected auto, io-uring or epoll"
+            )),
+        }
     }
-    eprintln!("disrust: io_threads={io_threads}");
-
-    OrtBackend::init();
-    set_factory_pool(BufferPool::new_boxed(1));
-
-    let model_bytes = std::fs::read(&args.model).unwrap_or_else(|e| {
-        eprintln!("Failed to read model '{}': {}", args.model, e);
-        std::process::exit(1);
-    });
-
-    eprintln!("disrust: loading {} session(s)", SESSION_POOL_SIZE);
-    let backend = OrtBackend::new(&model_bytes, SESSION_POOL_SIZE);
-
-    let pool = OrtBackend::make_pool();
-    let allocator = pool.allocator();
-
-    eprintln!(
-        "disrust: buffer pool {} MB",
-        GPU_BUFFER_POOL_BYTES / 1_000_000,
-    );
-
-    let builder = build_multi_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
-    let (submission_poller, builder) = builder.event_poller();
-    let (completion_poller, builder) = builder.and_then().event_poller();
-    let producer = builder.build();
-
-    let response_queues = (0..io_threads)
-        .map(|_| Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2)))
-        .collect::<Vec<_>>();
-    let publish_gate = Arc::new(std::sync::Mutex::new(()));
-    let registry = Arc::new(ConnectionRegistry::new(io_threads, SLAB_CAPACITY));
-    let (worker_exit_tx, worker_exit_rx) = mpsc::channel::<WorkerExit>();
-
-    if let (Some(submission_cpu), Some(completion_cpu)) = (args.submission_cpu, args.completion_cpu)
-        && submission_cpu != completion_cpu
-    {
-        eprintln!(
-            "disrust: --submission-cpu and --completion-cpu must match when submission and completion share one inference thread"
-        );
-        std::process::exit(1);
+}
+
+impl fmt::Display for IoBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
+}
 
-    let inference_consumer = InferenceConsumer::new(
-        submission_poller,
-        completion_poller,
-        backend,
-        response_queues.clone(),
-        Arc::clone(&registry),
-        max_batch_slots,
-        batch_coalesce,
-    );
-    let inference_cpu = args.submission_cpu.or(args.completion_cpu);
-    thread::Builder::new()
-        .name("inference".into())
-        .spawn({
-            let worker_exit_tx = worker_exit_tx.clone();
-            move || {
-                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
-                    if let Some(cpu) = inference_cpu {
-                        affinity::pin_current_thread(cpu, "inference")
-                            .unwrap_or_else(|e| panic!("{e}"));
-                    }
-                    inference_consumer.run()
-                }));
-                let _ = match outcome {
-                    Ok(()) => worker_exit_tx.send(WorkerExit::Returned("inference")),
-                    Err(payload) => worker_exit_tx
-                        .send(WorkerExit::Panicked("inference", panic_message(payload))),
-                };
-            }
-        })
-        .expect("failed to spawn inference consumer");
-
-    eprintln!("disrust: ready");
-
-    for (thread_id, response_queue) in response_queues.iter().enumerate() {
-        let listen_socket = create_listener(port);
-        let ingress = IngressThread::new(
-            thread_id as u8,
-            listen_socket.into_raw_fd(),
-            producer.clone(),
-            allocator,
-            Arc::clone(response_queue),
-            Arc::clone(&publish_gate),
-            Arc::clone(&registry),
-        );
-        let io_cpu = args.io_cpu.map(|base| base + thread_id);
-        let thread_name = format!("io-{thread_id}");
-        thread::Builder::new()
-            .name(thread_name.clone())
-            .spawn({
-                let worker_exit_tx = worker_exit_tx.clone();
-                move || {
-                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
-                        if let Some(cpu) = io_cpu {
-                            affinity::pin_current_thread(cpu, &thread_name)
-                                .unwrap_or_else(|e| panic!("{e}"));
-                        }
-                        ingress.run()
-                    }));
-                    let _ = match outcome {
-                        Ok(()) => worker_exit_tx.send(WorkerExit::Returned("ingress")),
-                        Err(payload) => worker_exit_tx
-                            .send(WorkerExit::Panicked("ingress", panic_message(payload))),
-                    };
-                }
-            })
-            .expect("failed to spawn IO thread");
+#[cfg(test)]
+mod tests {
+    use super::ServeArgs;
+
+    #[test]
+    fn for_model_takes_every_flag_default() {
+        let args = ServeArgs::for_model("model.onnx");
+        assert_eq!(args.model, "model.onnx");
+        assert_eq!(args.port, 9900);
+        assert_eq!(args.shutdown_grace_secs, 30);
+        assert!(args.admin_socket.is_none());
+        assert!(!args.huge_pages);
     }
 
-    drop(worker_exit_tx);
-    match worker_exit_rx
-        .recv()
-        .expect("worker exit channel closed unexpectedly")
-    {
-        WorkerExit::Returned(name) => {
-            panic!("disrust: worker thread '{name}' exited unexpectedly");
-        }
-        WorkerExit::Panicked(name, message) => {
-            panic!("disrust: worker thread '{name}' panicked: {message}");
-        }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn builder_rejects_invalid_settings_before_starting() {
+        use super::ServerBuilder;
+
+        let err = ServerBuilder::new("model.onnx")
+            .with_io_threads(0)
+            .start()
+            .err()
+            .expect("zero IO threads");
+        assert!(err.contains("--io-threads"), "{err}");
+        let err = ServerBuilder::new("model.onnx")
+            .with_max_connections(0)
+            .start()
+            .err()
+            .expect("zero connections");
+        assert!(err.contains("--max-connections"), "{err}");
     }
 }
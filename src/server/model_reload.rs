@@ -0,0 +1,235 @@
+//! Model hot reload.
+//!
+//! The admin `reload-model` command reads `--model` again, checked against `--model-manifest`
+//! when given, and builds a new backend for it on a `model-loader` thread, so the control plane
+//! and the data plane never wait on a slow load. With `--canary`, the new backend must pass the
+//! canary too. Only then is it handed to the inference thread, which stops submitting batches to
+//! the current backend, swaps the new one in once the running batches complete, and reports its
+//! version in metrics. Connections stay open throughout; their requests wait in the ring for the
+//! few batch times the swap takes. A model that fails to load or fails its canary is logged and
+//! counted, and the current one keeps serving.
+//!
+//! Both backends are held while the new one is built and checked, so a reload needs memory for
+//! two models. A file replaced by rename is picked up at the same path.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+
+use crate::buffer_pool::BufferPool;
+use crate::canary::Canary;
+use crate::config::SESSION_POOL_SIZE;
+use crate::metrics;
+use crate::model_artifact::ModelArtifact;
+use crate::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use crate::pipeline::inference::ModelSwap;
+use crate::pipeline::{BackendKind, ModelBackend, Numerics, OrtBackend};
+
+/// Where the model comes from and the backend that runs it.
+#[derive(Debug, Clone)]
+pub struct ModelSource {
+    model: PathBuf,
+    manifest: Option<PathBuf>,
+    backend: BackendKind,
+    numerics: Numerics,
+    feature_dim: usize,
+}
+
+impl ModelSource {
+    pub fn new(
+        model: &Path,
+        manifest: Option<&Path>,
+        backend: BackendKind,
+        numerics: Numerics,
+        feature_dim: usize,
+    ) -> Self {
+        Self {
+            model: model.to_path_buf(),
+            manifest: manifest.map(Path::to_path_buf),
+            backend,
+            numerics,
+            feature_dim,
+        }
+    }
+
+    pub fn model(&self) -> &Path {
+        &self.model
+    }
+
+    /// Read the model, check it against its manifest, and build a backend taking vectors of
+    /// `feature_dim` features for it.
+    pub fn load(&self) -> Result<(ModelBackend, ModelArtifact), String> {
+        let model = self.model.display();
+        let artifact = ModelArtifact::load(&self.model, self.manifest.as_deref(), self.feature_dim)
+            .map_err(|e| format!("model {model}: {e}"))?;
+        match &artifact.manifest {
+            Some(manifest) => eprintln!("disrust: model {model} {manifest}"),
+            None => eprintln!("disrust: model {model} (no manifest, unverified)"),
+        }
+        let backend = match self.backend {
+            BackendKind::Ort => {
+                eprintln!("disrust: loading {} session(s)", SESSION_POOL_SIZE);
+                ModelBackend::Ort(
+                    OrtBackend::with_numerics(&artifact.bytes, SESSION_POOL_SIZE, self.numerics)
+                        .with_feature_dim(self.feature_dim),
+                )
+            }
+            BackendKind::Gbdt => {
+                let gbdt = std::str::from_utf8(&artifact.bytes)
+                    .map_err(|_| "not a text dump".to_string())
+                    .and_then(|text| GbdtModel::parse(text, self.feature_dim))
+                    .map_err(|e| format!("model {model}: {e}"))?;
+                eprintln!(
+                    "disrust: gbdt model, {} trees, {:?} objective",
+                    gbdt.num_trees(),
+                    gbdt.objective()
+                );
+                ModelBackend::Gbdt(GbdtBackend::new(gbdt))
+            }
+        };
+        Ok((backend, artifact))
+    }
+}
+
+/// Loads the model again on request and hands it to the inference thread.
+pub struct ModelReloader {
+    source: Arc<ModelSource>,
+    /// The canary every new model must pass, with a pool of its own to score it from: the
+    /// server's pool belongs to the IO threads.
+    canary: Option<(Arc<Canary>, &'static BufferPool)>,
+    swaps: SyncSender<ModelSwap<ModelBackend>>,
+    loading: Arc<AtomicBool>,
+}
+
+impl ModelReloader {
+    /// Send each reloaded backend to the inference thread on `swaps`.
+    pub fn new(source: ModelSource, swaps: SyncSender<ModelSwap<ModelBackend>>) -> Self {
+        Self {
+            source: Arc::new(source),
+            canary: None,
+            swaps,
+            loading: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Swap in only models that pass `canary`.
+    pub fn with_canary(mut self, canary: Canary) -> Self {
+        // Twice the inputs, so the pool never has to wrap a canary batch.
+        let pool = BufferPool::leak_new(2 * canary.input_len());
+        self.canary = Some((Arc::new(canary), pool));
+        self
+    }
+
+    pub fn model(&self) -> &Path {
+        self.source.model()
+    }
+
+    /// Start loading the model on a thread of its own. Fails if a reload is already under way.
+    pub fn start(&self) -> Result<(), String> {
+        if self.loading.swap(true, Ordering::AcqRel) {
+            return Err("a model reload is already in progress".to_string());
+        }
+        let source = Arc::clone(&self.source);
+        let canary = self.canary.clone();
+        let swaps = self.swaps.clone();
+        let loading = Arc::clone(&self.loading);
+        let spawned = thread::Builder::new()
+            .name("model-loader".into())
+            .spawn(move || {
+                match load_checked(&source, canary) {
+                    Ok(swap) => {
+                        let version = swap.version.clone();
+                        if swaps.send(swap).is_ok() {
+                            eprintln!("disrust: model version {version} loaded, swapping it in");
+                        }
+                    }
+                    Err(e) => {
+                        metrics::inc_model_reload_failed();
+                        eprintln!("disrust: model reload failed, keeping the current model: {e}");
+                    }
+                }
+                loading.store(false, Ordering::Release);
+            });
+        if let Err(e) = spawned {
+            self.loading.store(false, Ordering::Release);
+            return Err(format!("failed to spawn model loader: {e}"));
+        }
+        Ok(())
+    }
+}
+
+fn load_checked(
+    source: &ModelSource,
+    canary: Option<(Arc<Canary>, &'static BufferPool)>,
+) -> Result<ModelSwap<ModelBackend>, String> {
+    let (mut backend, artifact) = source.load()?;
+    if let Some((canary, pool)) = canary {
+        let outcome = canary.run(&mut backend, &mut pool.allocator());
+        eprintln!("disrust: canary {outcome}");
+        if !outcome.passed() {
+            return Err(format!("canary {outcome}"));
+        }
+    }
+    Ok(ModelSwap {
+        backend,
+        version: artifact.version().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::{ModelReloader, ModelSource};
+    use crate::canary::Canary;
+    use crate::constants::FEATURE_DIM;
+    use crate::pipeline::{BackendKind, Numerics};
+
+    const MODEL: &str = "\
+booster[0]:
+0:[f0<0.5] yes=1,no=2,missing=1
+\t1:leaf=0.25
+\t2:leaf=0.75
+";
+
+    fn canary(min: f32, max: f32) -> Canary {
+        let input = vec!["1.0"; FEATURE_DIM].join(", ");
+        Canary::parse(
+            &format!("input = {input}\nmin = {min}\nmax = {max}"),
+            FEATURE_DIM,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reloads_the_model_off_thread_and_swaps_in_only_what_passes_the_canary() {
+        let path = std::env::temp_dir().join(format!("disrust-reload-{}.txt", std::process::id()));
+        std::fs::write(&path, MODEL).unwrap();
+        let source = ModelSource::new(
+            &path,
+            None,
+            BackendKind::Gbdt,
+            Numerics::default(),
+            FEATURE_DIM,
+        );
+
+        let (swaps, swapped) = mpsc::sync_channel(1);
+        let reloader = ModelReloader::new(source.clone(), swaps).with_canary(canary(0.5, 1.0));
+        reloader.start().unwrap();
+        let swap = swapped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(swap.version, "unversioned");
+
+        let (swaps, swapped) = mpsc::sync_channel(1);
+        let reloader = ModelReloader::new(source, swaps).with_canary(canary(0.9, 1.0));
+        reloader.start().unwrap();
+        assert!(swapped.recv_timeout(Duration::from_millis(200)).is_err());
+        // The failed reload finished, so another may start.
+        while reloader.start().is_err() {
+            std::thread::yield_now();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,707 @@
+//! Config file and live reload on SIGHUP.
+//!
+//! The config file holds one `key = value` per line, using the `serve` flag names with
+//! underscores (`overload_retry_after_ms = 50`); `#` starts a comment and `off` clears an
+//! optional value. At startup the file overrides the command line. On SIGHUP the control plane
+//! re-reads it: soft limits in [`SoftLimits`] take effect immediately, `batch_coalesce_us`, the
+//! `calibration` file (re-read even when its path is unchanged) and, with a sample sink open,
+//! `sample_rate` are sent to the inference thread as control events, the `embeddings` table is
+//! mapped again and swapped in for the IO threads, and keys that size allocations or threads are
+//! only logged as needing a restart.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::calibration::Calibration;
+use crate::embedding::{EmbeddingStore, EmbeddingTable};
+use crate::pipeline::control_channel::{ControlEvent, ControlSender};
+use crate::pipeline::sampling;
+use crate::protocol;
+use crate::request_flow::{MalformedPolicy, NonFinitePolicy};
+use crate::server::ServeArgs;
+
+const RETRY_AFTER_OFF: u32 = u32::MAX;
+
+/// Limits the data plane reads on every use, so a reload changes them without a restart.
+#[derive(Debug)]
+pub struct SoftLimits {
+    overload_retry_after_ms: AtomicU32,
+    write_backlog_bytes: AtomicUsize,
+    metrics_interval_secs: AtomicU64,
+    non_finite_features: AtomicU8,
+    malformed_requests: AtomicBool,
+}
+
+impl SoftLimits {
+    pub fn from_args(args: &ServeArgs) -> Self {
+        let limits = Self::default();
+        limits.store(args);
+        limits
+    }
+
+    fn store(&self, args: &ServeArgs) {
+        self.set_overload_retry_after_ms(args.overload_retry_after_ms);
+        self.set_write_backlog_bytes(args.max_write_backlog_kb.map(|kb| kb * 1024));
+        self.metrics_interval_secs
+            .store(args.metrics_interval_secs, Ordering::Relaxed);
+        self.set_non_finite_features(args.non_finite_features);
+        self.set_malformed_requests(args.malformed_requests);
+    }
+
+    /// Retry-After hint for overload frames, or `None` to hold requests while the ring is full.
+    pub fn overload_retry_after_ms(&self) -> Option<u16> {
+        match self.overload_retry_after_ms.load(Ordering::Relaxed) {
+            RETRY_AFTER_OFF => None,
+            ms => Some(ms as u16),
+        }
+    }
+
+    pub fn set_overload_retry_after_ms(&self, retry_after_ms: Option<u16>) {
+        self.overload_retry_after_ms.store(
+            retry_after_ms.map_or(RETRY_AFTER_OFF, u32::from),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Per-connection write backlog above which reads pause; `usize::MAX` when unlimited.
+    pub fn write_backlog_bytes(&self) -> usize {
+        self.write_backlog_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_write_backlog_bytes(&self, bytes: Option<usize>) {
+        self.write_backlog_bytes
+            .store(bytes.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    pub fn metrics_interval_secs(&self) -> u64 {
+        self.metrics_interval_secs.load(Ordering::Relaxed)
+    }
+
+    /// What the IO threads do with requests carrying NaN or infinite features.
+    pub fn non_finite_features(&self) -> NonFinitePolicy {
+        match self.non_finite_features.load(Ordering::Relaxed) {
+            1 => NonFinitePolicy::Clamp,
+            2 => NonFinitePolicy::Reject,
+            _ => NonFinitePolicy::PassThrough,
+        }
+    }
+
+    pub fn set_non_finite_features(&self, policy: NonFinitePolicy) {
+        let value = match policy {
+            NonFinitePolicy::PassThrough => 0,
+            NonFinitePolicy::Clamp => 1,
+            NonFinitePolicy::Reject => 2,
+        };
+        self.non_finite_features.store(value, Ordering::Relaxed);
+    }
+
+    /// What the IO threads do with requests whose `num_vectors` is out of range.
+    pub fn malformed_requests(&self) -> MalformedPolicy {
+        if self.malformed_requests.load(Ordering::Relaxed) {
+            MalformedPolicy::Skip
+        } else {
+            MalformedPolicy::Close
+        }
+    }
+
+    pub fn set_malformed_requests(&self, policy: MalformedPolicy) {
+        self.malformed_requests
+            .store(policy == MalformedPolicy::Skip, Ordering::Relaxed);
+    }
+}
+
+impl Default for SoftLimits {
+    fn default() -> Self {
+        Self {
+            overload_retry_after_ms: AtomicU32::new(RETRY_AFTER_OFF),
+            write_backlog_bytes: AtomicUsize::new(usize::MAX),
+            metrics_interval_secs: AtomicU64::new(10),
+            non_finite_features: AtomicU8::new(0),
+            malformed_requests: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Keys applied live on reload.
+const LIVE_KEYS: &[&str] = &[
+    "overload_retry_after_ms",
+    "max_write_backlog_kb",
+    "metrics_interval_secs",
+    "non_finite_features",
+    "malformed_requests",
+];
+
+/// Apply every `key = value` line of `text` to `args`.
+pub fn apply_config(text: &str, args: &mut ServeArgs) -> Result<(), String> {
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", line_no + 1))?;
+        let (key, value) = (key.trim(), value.trim());
+        apply_key(key, value, args).map_err(|e| format!("line {}: {key}: {e}", line_no + 1))?;
+    }
+    Ok(())
+}
+
+fn apply_key(key: &str, value: &str, args: &mut ServeArgs) -> Result<(), String> {
+    match key {
+        "port" => args.port = parse(value)?,
+        "io_threads" => args.io_threads = parse(value)?,
+        "per_thread_ports" => args.per_thread_ports = parse(value)?,
+        "echo_request_seq" => args.echo_request_seq = parse(value)?,
+        "replay_window" => args.replay_window = parse_optional(value)?,
+        "length_prefix" => args.length_prefix = parse(value)?,
+        "vector_status" => args.vector_status = parse(value)?,
+        "request_ids" => args.request_ids = parse(value)?,
+        "fixed_point_scale" => {
+            args.fixed_point_scale = match value {
+                "off" => None,
+                _ => Some(protocol::parse_fixed_point_scale(value)?),
+            }
+        }
+        "backend" => args.backend = parse(value)?,
+        "feature_dim" => args.feature_dim = protocol::parse_feature_dim(value)?,
+        "max_vectors" => args.max_vectors = protocol::parse_max_vectors(value)?,
+        "port_max_vectors" => args.port_max_vectors = parse_optional(value)?,
+        "inline_linear_model" => args.inline_linear_model = parse_optional(value)?,
+        "inline_policy" => args.inline_policy = value.to_string(),
+        "canary" => args.canary = parse_optional(value)?,
+        "feature_schema" => args.feature_schema = parse_optional(value)?,
+        "embeddings" => args.embeddings = parse_optional(value)?,
+        "embedding_ids" => args.embedding_ids = parse_optional(value)?,
+        "calibration" => args.calibration = parse_optional(value)?,
+        "drift_stats" => args.drift_stats = parse(value)?,
+        "sample_sink" => args.sample_sink = parse_optional(value)?,
+        "sample_rate" => args.sample_rate = sampling::parse_sample_rate(value)?,
+        "max_connections" => args.max_connections = parse(value)?,
+        "max_batch_slots" => args.max_batch_slots = parse(value)?,
+        "batch_coalesce_us" => args.batch_coalesce_us = parse(value)?,
+        "request_deadline_us" => args.request_deadline_us = parse_optional(value)?,
+        "request_timeout_us" => args.request_timeout_us = parse_optional(value)?,
+        "response_ttl_us" => args.response_ttl_us = parse_optional(value)?,
+        "circuit_breaker" => args.circuit_breaker = parse_optional(value)?,
+        "deterministic" => args.deterministic = parse(value)?,
+        "huge_pages" => args.huge_pages = parse(value)?,
+        "memory_budget_mb" => args.memory_budget_mb = parse_optional(value)?,
+        "overload_retry_after_ms" => args.overload_retry_after_ms = parse_optional(value)?,
+        "large_requests" => args.large_requests = parse_optional(value)?,
+        "max_write_backlog_kb" => args.max_write_backlog_kb = parse_optional(value)?,
+        "idle_timeout_secs" => args.idle_timeout_secs = parse_optional(value)?,
+        "slot_quarantine_ms" => args.slot_quarantine_ms = parse_optional(value)?,
+        "read_buffer_ring" => args.read_buffer_ring = parse_optional(value)?,
+        "adaptive_reads" => args.adaptive_reads = parse(value)?,
+        "fixed_buffers" => args.fixed_buffers = parse(value)?,
+        "io_backend" => args.io_backend = parse(value)?,
+        "metrics_interval_secs" => args.metrics_interval_secs = parse(value)?,
+        #[cfg(feature = "prometheus")]
+        "prometheus_port" => args.prometheus_port = parse_optional(value)?,
+        "shutdown_grace_secs" => args.shutdown_grace_secs = parse(value)?,
+        "state_dump_file" => args.state_dump_file = parse_optional(value)?,
+        "non_finite_features" => args.non_finite_features = parse(value)?,
+        "malformed_requests" => args.malformed_requests = parse(value)?,
+        _ => return Err("unknown key".to_string()),
+    }
+    Ok(())
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}'"))
+}
+
+fn parse_optional<T: FromStr>(value: &str) -> Result<Option<T>, String> {
+    if value == "off" {
+        return Ok(None);
+    }
+    parse(value).map(Some)
+}
+
+/// Restart-only keys whose value in `next` differs from `running`.
+fn restart_required(running: &ServeArgs, next: &ServeArgs) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let mut check = |key, differs| {
+        if differs {
+            changed.push(key);
+        }
+    };
+    check("port", running.port != next.port);
+    check("io_threads", running.io_threads != next.io_threads);
+    check(
+        "per_thread_ports",
+        running.per_thread_ports != next.per_thread_ports,
+    );
+    check(
+        "echo_request_seq",
+        running.echo_request_seq != next.echo_request_seq,
+    );
+    check("replay_window", running.replay_window != next.replay_window);
+    check("length_prefix", running.length_prefix != next.length_prefix);
+    check("vector_status", running.vector_status != next.vector_status);
+    check("request_ids", running.request_ids != next.request_ids);
+    check(
+        "fixed_point_scale",
+        running.fixed_point_scale != next.fixed_point_scale,
+    );
+    check("backend", running.backend != next.backend);
+    check("feature_dim", running.feature_dim != next.feature_dim);
+    check("max_vectors", running.max_vectors != next.max_vectors);
+    check(
+        "port_max_vectors",
+        running.port_max_vectors != next.port_max_vectors,
+    );
+    check(
+        "inline_linear_model",
+        running.inline_linear_model != next.inline_linear_model,
+    );
+    check("inline_policy", running.inline_policy != next.inline_policy);
+    check("canary", running.canary != next.canary);
+    check(
+        "feature_schema",
+        running.feature_schema != next.feature_schema,
+    );
+    check("embeddings", running.embeddings != next.embeddings);
+    check("embedding_ids", running.embedding_ids != next.embedding_ids);
+    check("calibration", running.calibration != next.calibration);
+    check("drift_stats", running.drift_stats != next.drift_stats);
+    check("sample_sink", running.sample_sink != next.sample_sink);
+    check("sample_rate", running.sample_rate != next.sample_rate);
+    check(
+        "max_connections",
+        running.max_connections != next.max_connections,
+    );
+    check(
+        "max_batch_slots",
+        running.max_batch_slots != next.max_batch_slots,
+    );
+    check(
+        "batch_coalesce_us",
+        running.batch_coalesce_us != next.batch_coalesce_us,
+    );
+    check(
+        "request_deadline_us",
+        running.request_deadline_us != next.request_deadline_us,
+    );
+    check(
+        "request_timeout_us",
+        running.request_timeout_us != next.request_timeout_us,
+    );
+    check(
+        "response_ttl_us",
+        running.response_ttl_us != next.response_ttl_us,
+    );
+    check(
+        "circuit_breaker",
+        running.circuit_breaker != next.circuit_breaker,
+    );
+    check("deterministic", running.deterministic != next.deterministic);
+    check("huge_pages", running.huge_pages != next.huge_pages);
+    check(
+        "memory_budget_mb",
+        running.memory_budget_mb != next.memory_budget_mb,
+    );
+    check(
+        "large_requests",
+        running.large_requests != next.large_requests,
+    );
+    check(
+        "idle_timeout_secs",
+        running.idle_timeout_secs != next.idle_timeout_secs,
+    );
+    check(
+        "slot_quarantine_ms",
+        running.slot_quarantine_ms != next.slot_quarantine_ms,
+    );
+    check(
+        "read_buffer_ring",
+        running.read_buffer_ring != next.read_buffer_ring,
+    );
+    check(
+        "adaptive_reads",
+        running.adaptive_reads != next.adaptive_reads,
+    );
+    check("fixed_buffers", running.fixed_buffers != next.fixed_buffers);
+    check("io_backend", running.io_backend != next.io_backend);
+    #[cfg(feature = "prometheus")]
+    check(
+        "prometheus_port",
+        running.prometheus_port != next.prometheus_port,
+    );
+    check(
+        "shutdown_grace_secs",
+        running.shutdown_grace_secs != next.shutdown_grace_secs,
+    );
+    check(
+        "state_dump_file",
+        running.state_dump_file != next.state_dump_file,
+    );
+    changed
+}
+
+/// Outcome of one reload.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Live keys now in effect, as `key=value`.
+    pub applied: Vec<String>,
+    /// Keys that changed but only take effect after a restart.
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Re-reads the config file and pushes soft limits into the running server.
+///
+/// Each reload starts from the startup configuration, so a key deleted from the file falls back
+/// to its command-line value.
+pub struct ConfigReloader {
+    path: PathBuf,
+    running: ServeArgs,
+    inference_control: Option<ControlSender>,
+    embeddings: Option<Arc<EmbeddingStore>>,
+}
+
+impl ConfigReloader {
+    /// `running` is the configuration the server started with, after the file was applied.
+    pub fn new(path: &Path, running: ServeArgs) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            running,
+            inference_control: None,
+            embeddings: None,
+        }
+    }
+
+    /// Apply `batch_coalesce_us`, `calibration` and, if sampling, `sample_rate` live by sending
+    /// them to the inference thread through `control`.
+    pub fn with_inference_control(mut self, control: ControlSender) -> Self {
+        self.inference_control = Some(control);
+        self
+    }
+
+    /// Map the `embeddings` file again on every reload and swap it into `store`. The table must
+    /// keep its row width; a file replaced by rename is picked up at the same path.
+    pub fn with_embeddings(mut self, store: Arc<EmbeddingStore>) -> Self {
+        self.embeddings = Some(store);
+        self
+    }
+
+    pub fn reload(&self, limits: &SoftLimits) -> Result<ReloadReport, String> {
+        let text = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("read {}: {e}", self.path.display()))?;
+        self.reload_from(&text, limits)
+    }
+
+    fn reload_from(&self, text: &str, limits: &SoftLimits) -> Result<ReloadReport, String> {
+        let mut next = self.running.clone();
+        apply_config(text, &mut next)?;
+        if next.metrics_interval_secs == 0 {
+            return Err("metrics_interval_secs must be > 0".to_string());
+        }
+        if next.max_write_backlog_kb == Some(0) {
+            return Err("max_write_backlog_kb must be > 0".to_string());
+        }
+
+        let mut restart_required = restart_required(&self.running, &next);
+        let mut applied: Vec<String> = LIVE_KEYS
+            .iter()
+            .map(|key| format!("{key}={}", live_value(&next, key)))
+            .collect();
+        if let (Some(store), Some(path)) = (&self.embeddings, &next.embeddings) {
+            let table = EmbeddingTable::load(path)?;
+            store.replace(table)?;
+            restart_required.retain(|&key| key != "embeddings");
+            applied.push(format!("embeddings={}", path.display()));
+        }
+        if let Some(control) = &self.inference_control {
+            let calibration = next
+                .calibration
+                .as_deref()
+                .map(Calibration::load)
+                .transpose()?
+                .map(Arc::new);
+            let coalesce = Duration::from_micros(next.batch_coalesce_us);
+            if !control.send(ControlEvent::SetBatchCoalesce(coalesce))
+                || !control.send(ControlEvent::SetCalibration(calibration))
+            {
+                return Err("inference control channel is full".to_string());
+            }
+            restart_required.retain(|&key| key != "batch_coalesce_us" && key != "calibration");
+            if self.running.sample_sink.is_some() {
+                if !control.send(ControlEvent::SetSampleRate(next.sample_rate)) {
+                    return Err("inference control channel is full".to_string());
+                }
+                restart_required.retain(|&key| key != "sample_rate");
+                applied.push(format!("sample_rate={}", next.sample_rate));
+            }
+            applied.push(format!("batch_coalesce_us={}", next.batch_coalesce_us));
+            applied.push(format!(
+                "calibration={}",
+                next.calibration
+                    .as_ref()
+                    .map_or_else(|| "off".to_string(), |path| path.display().to_string())
+            ));
+        }
+        limits.store(&next);
+        Ok(ReloadReport {
+            applied,
+            restart_required,
+        })
+    }
+}
+
+fn live_value(args: &ServeArgs, key: &str) -> String {
+    fn optional<T: ToString>(value: Option<T>) -> String {
+        value.map_or_else(|| "off".to_string(), |v| v.to_string())
+    }
+    match key {
+        "overload_retry_after_ms" => optional(args.overload_retry_after_ms),
+        "max_write_backlog_kb" => optional(args.max_write_backlog_kb),
+        "metrics_interval_secs" => args.metrics_interval_secs.to_string(),
+        "non_finite_features" => args.non_finite_features.to_string(),
+        "malformed_requests" => args.malformed_requests.to_string(),
+        _ => unreachable!("{key} is not a live key"),
+    }
+}
+
+static SIGHUP_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    SIGHUP_RECEIVED.store(true, Ordering::Relaxed);
+}
+
+/// Record SIGHUP for [`take_sighup`] instead of terminating the process.
+pub fn install_sighup_handler() {
+    let handler: extern "C" fn(libc::c_int) = on_sighup;
+    unsafe { libc::signal(libc::SIGHUP, handler as libc::sighandler_t) };
+}
+
+/// `true` once per SIGHUP received since the last call.
+pub fn take_sighup() -> bool {
+    SIGHUP_RECEIVED.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{ConfigReloader, SoftLimits, apply_config};
+    use crate::calibration::Calibration;
+    use crate::pipeline::control_channel::{ControlEvent, control_channel};
+    use crate::request_flow::{MalformedPolicy, NonFinitePolicy};
+    use crate::server::ServeArgs;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        serve: ServeArgs,
+    }
+
+    fn args() -> ServeArgs {
+        Cli::parse_from(["disrust", "--model", "m.onnx"]).serve
+    }
+
+    #[test]
+    fn config_file_overrides_flags() {
+        let mut args = args();
+        apply_config(
+            "# soft limits\nmax_write_backlog_kb = 64\n\nio_threads=2 # two shards\n",
+            &mut args,
+        )
+        .unwrap();
+        assert_eq!(args.max_write_backlog_kb, Some(64));
+        assert_eq!(args.io_threads, 2);
+        assert!(apply_config("nope = 1", &mut args).is_err());
+        assert!(apply_config("io_threads", &mut args).is_err());
+        assert!(apply_config("io_threads = many", &mut args).is_err());
+    }
+
+    #[test]
+    fn deterministic_needs_a_restart_and_excludes_inline_scoring() {
+        let running = args();
+        let limits = SoftLimits::from_args(&running);
+        let reloader = ConfigReloader::new("unused".as_ref(), running);
+        let report = reloader
+            .reload_from("deterministic = true\n", &limits)
+            .unwrap();
+        assert_eq!(report.restart_required, vec!["deterministic"]);
+
+        let inline_and_deterministic = Cli::try_parse_from([
+            "disrust",
+            "--model",
+            "m.onnx",
+            "--deterministic",
+            "--inline-linear-model",
+            "linear.txt",
+        ]);
+        assert!(inline_and_deterministic.is_err());
+    }
+
+    #[test]
+    fn reload_applies_soft_limits_and_reports_restart_keys() {
+        let running = args();
+        let limits = SoftLimits::from_args(&running);
+        let reloader = ConfigReloader::new("unused".as_ref(), running);
+
+        let report = reloader
+            .reload_from(
+                "overload_retry_after_ms = 25\nmax_write_backlog_kb = 8\nport = 1234\n",
+                &limits,
+            )
+            .unwrap();
+        assert_eq!(limits.overload_retry_after_ms(), Some(25));
+        assert_eq!(limits.write_backlog_bytes(), 8 * 1024);
+        assert_eq!(report.restart_required, vec!["port"]);
+        assert!(
+            report
+                .applied
+                .contains(&"overload_retry_after_ms=25".to_string())
+        );
+
+        reloader
+            .reload_from("overload_retry_after_ms = off\n", &limits)
+            .unwrap();
+        assert_eq!(limits.overload_retry_after_ms(), None);
+        assert_eq!(limits.write_backlog_bytes(), usize::MAX);
+
+        reloader
+            .reload_from("non_finite_features = reject\n", &limits)
+            .unwrap();
+        assert_eq!(limits.non_finite_features(), NonFinitePolicy::Reject);
+        assert!(
+            reloader
+                .reload_from("non_finite_features = drop\n", &limits)
+                .is_err()
+        );
+        reloader
+            .reload_from("malformed_requests = skip\n", &limits)
+            .unwrap();
+        assert_eq!(limits.malformed_requests(), MalformedPolicy::Skip);
+
+        assert!(
+            reloader
+                .reload_from("metrics_interval_secs = 0\n", &limits)
+                .is_err()
+        );
+        assert_eq!(limits.metrics_interval_secs(), 10);
+    }
+
+    #[test]
+    fn reload_sends_batch_coalesce_to_the_inference_thread() {
+        let running = args();
+        let limits = SoftLimits::from_args(&running);
+        let (tx, rx) = control_channel(4);
+
+        let without_control = ConfigReloader::new("unused".as_ref(), running.clone());
+        let report = without_control
+            .reload_from("batch_coalesce_us = 75\n", &limits)
+            .unwrap();
+        assert_eq!(report.restart_required, vec!["batch_coalesce_us"]);
+
+        let reloader = ConfigReloader::new("unused".as_ref(), running).with_inference_control(tx);
+        let report = reloader
+            .reload_from("batch_coalesce_us = 75\n", &limits)
+            .unwrap();
+        assert!(report.restart_required.is_empty());
+        assert!(report.applied.contains(&"batch_coalesce_us=75".to_string()));
+        assert_eq!(
+            rx.try_recv(),
+            Some(ControlEvent::SetBatchCoalesce(Duration::from_micros(75)))
+        );
+        assert_eq!(rx.try_recv(), Some(ControlEvent::SetCalibration(None)));
+    }
+
+    #[test]
+    fn reload_sends_the_sample_rate_only_while_sampling() {
+        let running = args();
+        let limits = SoftLimits::from_args(&running);
+        let (tx, _rx) = control_channel(4);
+        let reloader =
+            ConfigReloader::new("unused".as_ref(), running.clone()).with_inference_control(tx);
+        let report = reloader
+            .reload_from(
+                "sample_rate = 0.5
+",
+                &limits,
+            )
+            .unwrap();
+        assert_eq!(report.restart_required, vec!["sample_rate"]);
+        assert!(
+            reloader
+                .reload_from(
+                    "sample_rate = 2
+",
+                    &limits
+                )
+                .is_err()
+        );
+
+        let sampling = ServeArgs {
+            sample_sink: Some("samples.log".to_string()),
+            ..running
+        };
+        let (tx, rx_sampling) = control_channel(4);
+        let reloader = ConfigReloader::new("unused".as_ref(), sampling).with_inference_control(tx);
+        let report = reloader
+            .reload_from(
+                "sample_rate = 0.5
+",
+                &limits,
+            )
+            .unwrap();
+        assert!(report.restart_required.is_empty());
+        assert!(report.applied.contains(&"sample_rate=0.5".to_string()));
+        let events: Vec<_> = std::iter::from_fn(|| rx_sampling.try_recv()).collect();
+        assert!(events.contains(&ControlEvent::SetSampleRate(0.5)));
+    }
+
+    #[test]
+    fn reload_rereads_the_calibration_file() {
+        let path =
+            std::env::temp_dir().join(format!("disrust-calibration-{}.txt", std::process::id()));
+        let running = args();
+        let limits = SoftLimits::from_args(&running);
+        let (tx, rx) = control_channel(4);
+        let reloader = ConfigReloader::new("unused".as_ref(), running).with_inference_control(tx);
+        let config = format!("calibration = {}\n", path.display());
+
+        std::fs::write(&path, "method = platt\na = -1\nb = 0\n").unwrap();
+        let report = reloader.reload_from(&config, &limits).unwrap();
+        assert!(report.restart_required.is_empty());
+        assert!(
+            report
+                .applied
+                .contains(&format!("calibration={}", path.display()))
+        );
+        rx.try_recv();
+        assert_eq!(
+            rx.try_recv(),
+            Some(ControlEvent::SetCalibration(Some(Arc::new(
+                Calibration::Platt { a: -1.0, b: 0.0 }
+            ))))
+        );
+
+        // Same path, new contents: recalibrating does not need a config change.
+        std::fs::write(&path, "method = platt\na = -2\nb = 0\n").unwrap();
+        reloader.reload_from(&config, &limits).unwrap();
+        rx.try_recv();
+        assert_eq!(
+            rx.try_recv(),
+            Some(ControlEvent::SetCalibration(Some(Arc::new(
+                Calibration::Platt { a: -2.0, b: 0.0 }
+            ))))
+        );
+
+        std::fs::write(&path, "method = platt\n").unwrap();
+        assert!(reloader.reload_from(&config, &limits).is_err());
+        assert_eq!(rx.try_recv(), None, "nothing sent for a bad file");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
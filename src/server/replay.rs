@@ -0,0 +1,322 @@
+//! Response replay across reconnects.
+//!
+//! With `--replay-window N`, every connection opens with a hello naming its session and the
+//! `request_seq` of the first response the client has not received. Request sequence
+//! numbers run on across a session's connections, and the session keeps its last `N` frames
+//! exactly as they were sent. A reconnecting client is sent the kept frames from its
+//! `resume_seq` before anything else, so it learns the outcome of every request it sent before
+//! the connection dropped and only has to retry requests the server never numbered, that is,
+//! bytes it never finished sending.
+//!
+//! A session is named by an opaque token the server issues in the reply to a hello that starts
+//! one (token 0). Tokens are keyed per process, so a client cannot guess its way into another
+//! client's session, and a token from before a restart is simply `gone`.
+//!
+//! Frames are kept as they are built, before they are written, so a response is kept even when
+//! the write that would have carried it fails. A connection that resumed a session is not
+//! retired until every request it numbered has its frame kept, and a new connection only takes
+//! the session over once the previous one is retired; until then its hello is answered `busy`
+//! and the previous connection is shut down, so the client can retry right away.
+//!
+//! Sessions are shared by every IO thread, since a reconnect can land on any of them. At most
+//! [`REPLAY_MAX_SESSIONS`] are held; past that, starting a session evicts the one whose
+//! connection retired longest ago.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::monotonic_now_ns;
+use crate::config::REPLAY_MAX_SESSIONS;
+use crate::connection_id::ConnectionRef;
+use crate::pipeline::connection_registry::ConnectionRegistry;
+use crate::protocol::ReplayStatus;
+
+/// One session's numbering and its most recent frames.
+#[derive(Debug)]
+pub struct ReplaySession {
+    /// The connection that last resumed the session.
+    owner: Option<ConnectionRef>,
+    /// `request_seq` the next connection's first request takes.
+    next_request_seq: u64,
+    /// `request_seq` answered by the first frame in `frames`.
+    window_start: u64,
+    frames: VecDeque<Box<[u8]>>,
+    /// Frames built before the frame of an earlier request, by `request_seq`.
+    ahead: BTreeMap<u64, Box<[u8]>>,
+    window: usize,
+    /// When the session was last resumed or its connection settled.
+    last_active_ns: u64,
+}
+
+impl ReplaySession {
+    fn new(window: usize) -> Self {
+        Self {
+            owner: None,
+            next_request_seq: 0,
+            window_start: 0,
+            frames: VecDeque::new(),
+            ahead: BTreeMap::new(),
+            window,
+            last_active_ns: monotonic_now_ns(),
+        }
+    }
+
+    /// `request_seq` the next kept frame must answer.
+    fn window_end(&self) -> u64 {
+        self.window_start + self.frames.len() as u64
+    }
+
+    /// Keep `frame`, the bytes answering `request_seq`, in sequence order.
+    pub fn record(&mut self, request_seq: u64, frame: &[u8]) {
+        if request_seq != self.window_end() {
+            debug_assert!(
+                request_seq > self.window_end(),
+                "frame {request_seq} kept twice"
+            );
+            self.ahead.insert(request_seq, frame.into());
+            return;
+        }
+        self.frames.push_back(frame.into());
+        while let Some(frame) = self.ahead.remove(&self.window_end()) {
+            self.frames.push_back(frame);
+        }
+        while self.frames.len() > self.window {
+            self.frames.pop_front();
+            self.window_start += 1;
+        }
+    }
+
+    /// Whether every request numbered before `next_request_seq` has its frame kept, so the
+    /// connection holding the session may retire. Once it has, the next connection to resume
+    /// the session numbers its requests from there.
+    pub fn settle(&mut self, next_request_seq: u64) -> bool {
+        if self.window_end() < next_request_seq {
+            return false;
+        }
+        self.next_request_seq = next_request_seq;
+        self.last_active_ns = monotonic_now_ns();
+        true
+    }
+
+    fn owner_open(&self, registry: &ConnectionRegistry) -> bool {
+        self.owner.is_some_and(|owner| registry.is_open(owner))
+    }
+}
+
+/// Outcome of a replay hello.
+#[derive(Debug)]
+pub enum Attach {
+    /// Send `frames`, then number the connection's requests from `next_request_seq`.
+    Resumed {
+        token: u64,
+        session: Arc<Mutex<ReplaySession>>,
+        next_request_seq: u64,
+        frames: Vec<Box<[u8]>>,
+    },
+    /// Answer with this status and close the connection.
+    Refused(ReplayStatus),
+}
+
+/// Every replay session, shared by the IO threads.
+#[derive(Debug)]
+pub struct ReplaySessions {
+    window: usize,
+    /// By token.
+    sessions: Mutex<HashMap<u64, Arc<Mutex<ReplaySession>>>>,
+    /// Random per-process keys tokens are derived with.
+    token_keys: RandomState,
+    tokens_issued: AtomicU64,
+}
+
+impl ReplaySessions {
+    /// Sessions keeping their last `window` frames.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "replay window must be > 0");
+        Self {
+            window,
+            sessions: Mutex::new(HashMap::new()),
+            token_keys: RandomState::new(),
+            tokens_issued: AtomicU64::new(0),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Resume the session named by `token` on `conn` from `resume_seq`, or start a new one if
+    /// `token` and `resume_seq` are both 0.
+    pub fn attach(
+        &self,
+        token: u64,
+        resume_seq: u64,
+        conn: ConnectionRef,
+        registry: &ConnectionRegistry,
+    ) -> Attach {
+        let mut sessions = self.sessions.lock().unwrap();
+        let token = if token == 0 {
+            if resume_seq != 0 {
+                return Attach::Refused(ReplayStatus::Gone);
+            }
+            if sessions.len() >= REPLAY_MAX_SESSIONS && !evict_oldest(&mut sessions, registry) {
+                return Attach::Refused(ReplayStatus::Busy);
+            }
+            let token = self.issue_token(&sessions);
+            sessions.insert(token, Arc::new(Mutex::new(ReplaySession::new(self.window))));
+            token
+        } else if sessions.contains_key(&token) {
+            token
+        } else {
+            return Attach::Refused(ReplayStatus::Gone);
+        };
+        let session = Arc::clone(&sessions[&token]);
+        drop(sessions);
+
+        let mut state = session.lock().unwrap();
+        if state.owner_open(registry) {
+            // Most likely a connection the client gave up on; close it so a retry can resume.
+            registry.shutdown(state.owner.expect("an open owner"));
+            return Attach::Refused(ReplayStatus::Busy);
+        }
+        if resume_seq < state.window_start || resume_seq > state.window_end() {
+            return Attach::Refused(ReplayStatus::Gone);
+        }
+        debug_assert_eq!(state.next_request_seq, state.window_end());
+        state.owner = Some(conn);
+        state.last_active_ns = monotonic_now_ns();
+        let skip = (resume_seq - state.window_start) as usize;
+        let frames = state.frames.iter().skip(skip).cloned().collect();
+        let next_request_seq = state.next_request_seq;
+        drop(state);
+        Attach::Resumed {
+            token,
+            session,
+            next_request_seq,
+            frames,
+        }
+    }
+
+    /// A fresh nonzero token no held session has.
+    fn issue_token(&self, sessions: &HashMap<u64, Arc<Mutex<ReplaySession>>>) -> u64 {
+        loop {
+            let nonce = self.tokens_issued.fetch_add(1, Ordering::Relaxed);
+            let token = self.token_keys.hash_one(nonce);
+            if token != 0 && !sessions.contains_key(&token) {
+                return token;
+            }
+        }
+    }
+}
+
+/// Drop the session whose connection retired longest ago. Returns `false` if every session
+/// still has an open connection.
+fn evict_oldest(
+    sessions: &mut HashMap<u64, Arc<Mutex<ReplaySession>>>,
+    registry: &ConnectionRegistry,
+) -> bool {
+    let oldest = sessions
+        .iter()
+        .filter_map(|(&id, session)| {
+            let session = session.lock().unwrap();
+            (!session.owner_open(registry)).then_some((session.last_active_ns, id))
+        })
+        .min();
+    match oldest {
+        Some((_, id)) => sessions.remove(&id).is_some(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attach, ReplaySessions};
+    use crate::pipeline::connection_registry::ConnectionRegistry;
+    use crate::protocol::ReplayStatus;
+
+    fn resumed(attach: Attach) -> (u64, Vec<Vec<u8>>) {
+        match attach {
+            Attach::Resumed {
+                next_request_seq,
+                frames,
+                ..
+            } => (
+                next_request_seq,
+                frames.into_iter().map(Vec::from).collect(),
+            ),
+            Attach::Refused(status) => panic!("refused: {status:?}"),
+        }
+    }
+
+    fn refused(attach: Attach) -> ReplayStatus {
+        match attach {
+            Attach::Refused(status) => status,
+            Attach::Resumed { .. } => panic!("resumed"),
+        }
+    }
+
+    #[test]
+    fn sessions_resume_from_the_first_unreceived_frame() {
+        let registry = ConnectionRegistry::new(1, 4);
+        let sessions = ReplaySessions::new(3);
+        let first = registry.open(0, 0, -1);
+        let Attach::Resumed { token, session, .. } = sessions.attach(0, 0, first, &registry) else {
+            panic!("a new session starts");
+        };
+        assert_ne!(token, 0);
+
+        // Frame 1 is built before frame 0 and kept behind it; only the last three are held.
+        session.lock().unwrap().record(1, b"one");
+        session.lock().unwrap().record(0, b"zero");
+        assert!(
+            !session.lock().unwrap().settle(4),
+            "frames 2 and 3 are owed"
+        );
+        session.lock().unwrap().record(2, b"two");
+        session.lock().unwrap().record(3, b"three");
+
+        let second = registry.open(0, 1, -1);
+        assert_eq!(
+            refused(sessions.attach(token, 2, second, &registry)),
+            ReplayStatus::Busy,
+            "the first connection is still open"
+        );
+        assert!(session.lock().unwrap().settle(4));
+        registry.mark_read_closed(first, 4);
+
+        assert_eq!(
+            refused(sessions.attach(token, 0, second, &registry)),
+            ReplayStatus::Gone,
+            "frame 0 fell out of the window"
+        );
+        assert_eq!(
+            refused(sessions.attach(token, 5, second, &registry)),
+            ReplayStatus::Gone,
+            "frame 4 was never sent"
+        );
+        assert_eq!(
+            refused(sessions.attach(token ^ 1, 2, second, &registry)),
+            ReplayStatus::Gone,
+            "only issued tokens resume"
+        );
+        assert_eq!(
+            refused(sessions.attach(0, 2, second, &registry)),
+            ReplayStatus::Gone,
+            "new sessions start from 0"
+        );
+        let (next_request_seq, frames) = resumed(sessions.attach(token, 2, second, &registry));
+        assert_eq!(next_request_seq, 4);
+        assert_eq!(frames, vec![b"two".to_vec(), b"three".to_vec()]);
+        assert_eq!(sessions.len(), 1);
+    }
+}
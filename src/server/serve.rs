@@ -0,0 +1,1240 @@
+//! The io_uring server: IO threads, the inference thread and the control plane, wired up from
+//! [`ServeArgs`]. Linux-only.
+
+use std::os::unix::io::IntoRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, Producer, build_multi_producer};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::affinity;
+use crate::buffer_pool::{BufferPool, PoolAllocator, set_factory_pool};
+use crate::calibration::Calibration;
+use crate::canary::Canary;
+use crate::clock;
+use crate::config::{
+    CONTROL_CHANNEL_CAPACITY, GPU_DISRUPTOR_SIZE, MAX_IO_THREADS, MAX_SESSION_BATCH_SIZE,
+    MIN_READ_SIZE, PROVIDED_READ_BUF_SIZE, REPLAY_MAX_SESSIONS, RESPONSE_QUEUE_SIZE,
+    SAMPLE_QUEUE_CAPACITY, SLAB_CAPACITY, gpu_buffer_pool_bytes, gpu_buffer_pool_capacity,
+};
+use crate::constants::{MAX_FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use crate::embedding::{EmbeddingStore, EmbeddingTable};
+use crate::feature_schema::FeatureSchema;
+use crate::memory_plan::AllocationPlan;
+use crate::metrics;
+use crate::notify;
+use crate::pipeline::admission::LargeRequestAdmission;
+use crate::pipeline::circuit_breaker::CircuitBreaker;
+use crate::pipeline::connection_registry::ConnectionRegistry;
+use crate::pipeline::control_channel::{ControlEvent, ControlSender, control_channel};
+use crate::pipeline::drift::DriftMonitor;
+use crate::pipeline::inference::InferenceConsumer;
+use crate::pipeline::inline::{InlineFastPath, LinearModel, PlacementPolicy, RingOccupancy};
+use crate::pipeline::response_queue::ResponseRouter;
+use crate::pipeline::sampling;
+use crate::pipeline::{BackendKind, InferenceBackend, ModelBackend, Numerics, OrtBackend};
+use crate::protocol;
+use crate::ring_types::InferenceEvent;
+use crate::server::model_reload::{ModelReloader, ModelSource};
+use crate::server::replay::ReplaySessions;
+use crate::server::state_dump::StateDump;
+use crate::server::{
+    ConfigReloader, ControlPlane, IngressThread, IoBackend, IoThreadControl, IoThreadSet,
+    IoThreadState, ServeArgs, SoftLimits, admin, ingress, reload, shutdown,
+};
+
+/// Exit status when a worker thread dies (`EX_SOFTWARE`).
+const EXIT_WORKER_FAILED: i32 = 70;
+/// How long IO threads get to close their connections after a worker dies.
+const FATAL_EXIT_GRACE: Duration = Duration::from_secs(1);
+/// Exit status when a shutdown did not finish within `--shutdown-grace-secs`.
+const EXIT_SHUTDOWN_TIMEOUT: i32 = 1;
+/// How often the main thread checks for a shutdown signal while it waits on the workers.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum WorkerExit {
+    Returned(&'static str),
+    Panicked(&'static str, String),
+}
+
+fn create_listener(port: u16, reuse_port: bool) -> std::io::Result<Socket> {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.set_nodelay(true)?;
+    let addr = std::net::SocketAddrV4::new(std::net::Ipv4Addr::UNSPECIFIED, port);
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket)
+}
+
+/// Everything needed to start one more IO thread after startup.
+struct IoThreadSpawner<P> {
+    port: u16,
+    per_thread_ports: bool,
+    io_cpu: Option<usize>,
+    max_connections: usize,
+    feature_dim: usize,
+    max_vectors: usize,
+    /// `--max-vectors` overrides by port, under per-thread ports.
+    port_max_vectors: Vec<(u16, usize)>,
+    echo_request_seq: bool,
+    length_prefix: bool,
+    vector_status: bool,
+    request_ids: bool,
+    fixed_point_scale: Option<f32>,
+    inline: Option<InlineFastPath>,
+    schema: Option<Arc<FeatureSchema>>,
+    admission: Option<Arc<LargeRequestAdmission>>,
+    circuit: Option<Arc<CircuitBreaker>>,
+    embeddings: Option<Arc<EmbeddingStore>>,
+    replay: Option<Arc<ReplaySessions>>,
+    idle_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    response_ttl: Option<Duration>,
+    slot_quarantine: Option<Duration>,
+    read_buffer_ring: Option<u16>,
+    adaptive_reads: bool,
+    fixed_buffers: bool,
+    io_backend: IoBackend,
+    limits: Arc<SoftLimits>,
+    producer: P,
+    allocator: PoolAllocator,
+    response_queues: Arc<ResponseRouter>,
+    publish_gate: Arc<std::sync::Mutex<()>>,
+    registry: Arc<ConnectionRegistry>,
+    inference_control: ControlSender,
+    worker_exit_tx: mpsc::Sender<WorkerExit>,
+}
+
+impl<P> IoThreadSpawner<P>
+where
+    P: Producer<InferenceEvent> + Clone + Send + 'static,
+{
+    fn spawn(&self, thread_id: u8, control: Arc<IoThreadControl>) -> Result<(), String> {
+        let (listen_socket, max_vectors) = if self.per_thread_ports {
+            let port = self
+                .port
+                .checked_add(thread_id as u16)
+                .ok_or_else(|| format!("port {} + {thread_id} out of range", self.port))?;
+            let max_vectors = self
+                .port_max_vectors
+                .iter()
+                .find(|&&(listed, _)| listed == port)
+                .map_or(self.max_vectors, |&(_, max_vectors)| max_vectors);
+            let socket = create_listener(port, false)
+                .map_err(|e| format!("listener on port {port} failed: {e}"))?;
+            (socket, max_vectors)
+        } else {
+            let socket = create_listener(self.port, true)
+                .map_err(|e| format!("listener on port {} failed: {e}", self.port))?;
+            (socket, self.max_vectors)
+        };
+        let ingress = IngressThread::new(
+            thread_id,
+            listen_socket.into_raw_fd(),
+            self.producer.clone(),
+            self.allocator,
+            self.response_queues
+                .get_or_register(thread_id, RESPONSE_QUEUE_SIZE),
+            Arc::clone(&self.publish_gate),
+            Arc::clone(&self.registry),
+        )
+        .with_max_connections(self.max_connections)
+        .with_feature_dim(self.feature_dim)
+        .with_max_vectors(max_vectors)
+        .with_soft_limits(Arc::clone(&self.limits))
+        .with_control(Arc::clone(&control))
+        .with_inference_control(self.inference_control.clone());
+        let ingress = if self.echo_request_seq {
+            ingress.with_request_seq_echo()
+        } else {
+            ingress
+        };
+        let ingress = if self.length_prefix {
+            ingress.with_length_prefix()
+        } else {
+            ingress
+        };
+        let ingress = if self.vector_status {
+            ingress.with_vector_status()
+        } else {
+            ingress
+        };
+        let ingress = if self.request_ids {
+            ingress.with_request_ids()
+        } else {
+            ingress
+        };
+        let ingress = match self.fixed_point_scale {
+            Some(scale) => ingress.with_fixed_point_scale(scale),
+            None => ingress,
+        };
+        let ingress = match &self.inline {
+            Some(fast_path) => ingress.with_inline_fast_path(fast_path.clone()),
+            None => ingress,
+        };
+        let ingress = match &self.schema {
+            Some(schema) => ingress.with_feature_schema(Arc::clone(schema)),
+            None => ingress,
+        };
+        let ingress = match &self.admission {
+            Some(admission) => ingress.with_large_request_admission(Arc::clone(admission)),
+            None => ingress,
+        };
+        let ingress = match &self.circuit {
+            Some(circuit) => ingress.with_circuit_breaker(Arc::clone(circuit)),
+            None => ingress,
+        };
+        let ingress = match &self.embeddings {
+            Some(store) => ingress.with_embeddings(Arc::clone(store)),
+            None => ingress,
+        };
+        let ingress = match &self.replay {
+            Some(sessions) => ingress.with_replay_sessions(Arc::clone(sessions)),
+            None => ingress,
+        };
+        let ingress = match self.idle_timeout {
+            Some(timeout) => ingress.with_idle_timeout(timeout),
+            None => ingress,
+        };
+        let ingress = match self.request_timeout {
+            Some(timeout) => ingress.with_request_timeout(timeout),
+            None => ingress,
+        };
+        let ingress = match self.response_ttl {
+            Some(ttl) => ingress.with_response_ttl(ttl),
+            None => ingress,
+        };
+        let ingress = match self.slot_quarantine {
+            Some(quarantine) => ingress.with_slot_quarantine(quarantine),
+            None => ingress,
+        };
+        let ingress = match self.read_buffer_ring {
+            Some(entries) => ingress.with_read_buffer_ring(entries),
+            None => ingress,
+        };
+        let ingress = if self.adaptive_reads {
+            ingress.with_adaptive_reads()
+        } else {
+            ingress
+        };
+        let ingress = if self.fixed_buffers {
+            ingress.with_fixed_buffers()
+        } else {
+            ingress
+        };
+        let ingress = ingress.with_io_backend(self.io_backend);
+        let io_cpu = self.io_cpu.map(|base| base + thread_id as usize);
+        let thread_name = format!("io-{thread_id}");
+        thread::Builder::new()
+            .name(thread_name.clone())
+            .spawn({
+                let worker_exit_tx = self.worker_exit_tx.clone();
+                move || {
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                        if let Some(cpu) = io_cpu {
+                            affinity::pin_current_thread(cpu, &thread_name)
+                                .unwrap_or_else(|e| panic!("{e}"));
+                        }
+                        ingress.run()
+                    }));
+                    let _ = match outcome {
+                        Ok(()) if control.remove_requested() => {
+                            control.mark_stopped();
+                            eprintln!("disrust: {thread_name} removed");
+                            Ok(())
+                        }
+                        Ok(()) => worker_exit_tx.send(WorkerExit::Returned("ingress")),
+                        Err(payload) => worker_exit_tx
+                            .send(WorkerExit::Panicked("ingress", panic_message(payload))),
+                    };
+                }
+            })
+            .map_err(|e| format!("failed to spawn IO thread: {e}"))?;
+        Ok(())
+    }
+}
+
+/// Parse `port:vectors[,...]`, each port at most once and none below `base_port`.
+fn parse_port_max_vectors(text: &str, base_port: u16) -> Result<Vec<(u16, usize)>, String> {
+    let mut limits: Vec<(u16, usize)> = Vec::new();
+    for entry in text.split(',') {
+        let (port, vectors) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("'{entry}' is not port:vectors"))?;
+        let port = port
+            .trim()
+            .parse::<u16>()
+            .map_err(|e| format!("'{entry}': bad port: {e}"))?;
+        if port < base_port {
+            return Err(format!("port {port} is below --port {base_port}"));
+        }
+        if limits.iter().any(|&(listed, _)| listed == port) {
+            return Err(format!("port {port} is listed twice"));
+        }
+        limits.push((port, protocol::parse_max_vectors(vectors)?));
+    }
+    Ok(limits)
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(msg) => (*msg).to_string(),
+            Err(_) => "non-string panic payload".to_string(),
+        },
+    }
+}
+
+fn start(mut args: ServeArgs) -> Result<Server, String> {
+    if let Some(path) = args.config.clone() {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read config {}: {e}", path.display()))?;
+        reload::apply_config(&text, &mut args)
+            .map_err(|e| format!("config {}: {e}", path.display()))?;
+    }
+    if args.metrics_interval_secs == 0 {
+        return Err("--metrics-interval-secs must be > 0".to_string());
+    }
+    if cfg!(feature = "metrics") {
+        clock::calibrate_ticks();
+    }
+
+    let port = args.port;
+    // One request per batch, so a request's results never depend on what it was batched with.
+    let max_batch_slots = if args.deterministic {
+        1
+    } else {
+        args.max_batch_slots
+    };
+    let batch_coalesce = Duration::from_micros(args.batch_coalesce_us);
+    let io_threads = args.io_threads as usize;
+
+    if args.max_batch_slots == 0 || args.max_batch_slots > MAX_SESSION_BATCH_SIZE {
+        return Err(format!(
+            "--max-batch-slots must be in 1..={MAX_SESSION_BATCH_SIZE}"
+        ));
+    }
+    if io_threads == 0 || io_threads > MAX_IO_THREADS {
+        return Err(format!("--io-threads must be in 1..={MAX_IO_THREADS}"));
+    }
+    if args.per_thread_ports && port as usize + io_threads - 1 > u16::MAX as usize {
+        return Err(format!(
+            "--port + --io-threads - 1 must not exceed {}",
+            u16::MAX
+        ));
+    }
+    if args.max_connections == 0 || args.max_connections > SLAB_CAPACITY {
+        return Err(format!("--max-connections must be in 1..={SLAB_CAPACITY}"));
+    }
+    if args.max_write_backlog_kb == Some(0) {
+        return Err("--max-write-backlog-kb must be > 0".to_string());
+    }
+    if args.request_timeout_us == Some(0) {
+        return Err("--request-timeout-us must be > 0".to_string());
+    }
+    if args.response_ttl_us == Some(0) {
+        return Err("--response-ttl-us must be > 0".to_string());
+    }
+    if args.circuit_breaker.is_some() && args.request_timeout_us.is_none() {
+        return Err("--circuit-breaker needs --request-timeout-us".to_string());
+    }
+    if args.idle_timeout_secs == Some(0) {
+        return Err("--idle-timeout-secs must be > 0".to_string());
+    }
+    if args.slot_quarantine_ms == Some(0) {
+        return Err("--slot-quarantine-ms must be > 0".to_string());
+    }
+    if let Some(entries) = args.read_buffer_ring
+        && !(entries.is_power_of_two() && entries <= 1 << 15)
+    {
+        return Err("--read-buffer-ring must be a power of two up to 32768".to_string());
+    }
+    if args.read_buffer_ring.is_some() && args.io_backend == IoBackend::Epoll {
+        return Err("--read-buffer-ring needs --io-backend io-uring".to_string());
+    }
+    if args.fixed_buffers && args.io_backend == IoBackend::Epoll {
+        return Err("--fixed-buffers needs --io-backend io-uring".to_string());
+    }
+    if args.fixed_buffers && args.read_buffer_ring.is_some() {
+        return Err("--fixed-buffers and --read-buffer-ring are mutually exclusive".to_string());
+    }
+    if args.replay_window == Some(0) {
+        return Err("--replay-window must be > 0".to_string());
+    }
+    if args.replay_window.is_some() && !args.echo_request_seq {
+        return Err("--replay-window needs --echo-request-seq".to_string());
+    }
+    let port_max_vectors = match &args.port_max_vectors {
+        Some(_) if !args.per_thread_ports => {
+            return Err("--port-max-vectors needs --per-thread-ports".to_string());
+        }
+        Some(text) => {
+            parse_port_max_vectors(text, port).map_err(|e| format!("--port-max-vectors: {e}"))?
+        }
+        None => Vec::new(),
+    };
+
+    // The inline model and the schema run on the IO thread against the vectors as clients send
+    // them, ids and all, so they never see the rows embeddings look up. Flags conflict already;
+    // this catches a config file or a builder asking for both.
+    if args.embeddings.is_some()
+        && (args.inline_linear_model.is_some() || args.feature_schema.is_some())
+    {
+        return Err(
+            "--embeddings cannot be combined with --inline-linear-model or --feature-schema"
+                .to_string(),
+        );
+    }
+    // With embeddings, the model's input is wider than the vectors clients send.
+    let wire_dim = args.feature_dim;
+    let embeddings = args
+        .embeddings
+        .as_ref()
+        .map(|path| {
+            let ids = args
+                .embedding_ids
+                .ok_or("--embeddings needs --embedding-ids")?;
+            if ids == 0 || ids > wire_dim {
+                return Err(format!("--embedding-ids must be in 1..={wire_dim}"));
+            }
+            let table = EmbeddingTable::load(path).map_err(|e| format!("--embeddings: {e}"))?;
+            eprintln!(
+                "disrust: embeddings {}, {} rows of {}, last {ids} features are ids",
+                path.display(),
+                table.rows(),
+                table.dim()
+            );
+            Ok::<_, String>(Arc::new(EmbeddingStore::new(table, ids)))
+        })
+        .transpose()?;
+    let feature_dim = embeddings
+        .as_ref()
+        .map_or(wire_dim, |store| store.model_dim(wire_dim));
+    if feature_dim > MAX_FEATURE_DIM {
+        return Err(format!(
+            "--embeddings widens vectors to {feature_dim} features, over {MAX_FEATURE_DIM}"
+        ));
+    }
+    let budget_bytes = args
+        .memory_budget_mb
+        .map(|budget_mb| {
+            budget_mb
+                .checked_mul(1024 * 1024)
+                .ok_or_else(|| format!("--memory-budget-mb {budget_mb} is too large"))
+        })
+        .transpose()?;
+    let mut plan = AllocationPlan::for_server(io_threads, args.max_connections, feature_dim);
+    if let (Some(budget_mb), Some(budget_bytes)) = (args.memory_budget_mb, budget_bytes) {
+        let requested_connections = plan.max_connections;
+        plan = plan
+            .fit_to_budget(budget_bytes, args.memory_budget_shrink)
+            .map_err(|e| format!("memory budget exceeded: {e}\n{}", e.plan()))?;
+        if plan.max_connections < requested_connections {
+            eprintln!(
+                "disrust: warning: shrinking max_connections {} -> {} to fit {} MiB budget",
+                requested_connections, plan.max_connections, budget_mb
+            );
+        }
+    }
+    let max_connections = plan.max_connections;
+    // Threads added at runtime through the admin socket must still fit the budget.
+    let max_io_threads = match budget_bytes {
+        Some(budget_bytes) => (io_threads..=MAX_IO_THREADS)
+            .take_while(|&threads| {
+                AllocationPlan::for_server(threads, max_connections, feature_dim).total_bytes()
+                    <= budget_bytes
+            })
+            .last()
+            .unwrap_or(io_threads),
+        None => MAX_IO_THREADS,
+    };
+
+    if args.per_thread_ports {
+        eprintln!(
+            "disrust: starting on ports {}..={} (one per IO thread)",
+            port,
+            port as usize + io_threads - 1
+        );
+    } else {
+        eprintln!("disrust: starting on port {}", port);
+    }
+    eprintln!(
+        "disrust: max_batch_slots={} (compile-time max={})",
+        max_batch_slots, MAX_SESSION_BATCH_SIZE
+    );
+    eprintln!("disrust: batch_coalesce_us={}", args.batch_coalesce_us);
+    if let Some(deadline_us) = args.request_deadline_us {
+        eprintln!("disrust: request_deadline_us={deadline_us}, coalescing stops short of it");
+    }
+    if let Some(timeout_us) = args.request_timeout_us {
+        eprintln!("disrust: answering requests unanswered after {timeout_us}us with timeouts");
+    }
+    if let Some(ttl_us) = args.response_ttl_us {
+        eprintln!("disrust: replacing responses queued over {ttl_us}us with timeouts");
+    }
+    if embeddings.is_some() {
+        eprintln!("disrust: feature_dim={wire_dim} on the wire, {feature_dim} to the model");
+    } else {
+        eprintln!("disrust: feature_dim={feature_dim}");
+    }
+    if args.max_vectors != MAX_VECTORS_PER_REQUEST || !port_max_vectors.is_empty() {
+        let ports: Vec<String> = port_max_vectors
+            .iter()
+            .map(|(port, vectors)| format!(" port {port}={vectors}"))
+            .collect();
+        eprintln!(
+            "disrust: max_vectors={}{}",
+            args.max_vectors,
+            ports.concat()
+        );
+    }
+    if args.deterministic {
+        eprintln!("disrust: deterministic numerics, one request per batch");
+    }
+    if let Some(retry_after_ms) = args.overload_retry_after_ms {
+        eprintln!("disrust: overload rejection on, retry_after_ms={retry_after_ms}");
+    }
+    if args.echo_request_seq {
+        eprintln!("disrust: echoing request_seq before every response");
+    }
+    let replay = args.replay_window.map(|window| {
+        eprintln!(
+            "disrust: replay sessions on, last {window} frames kept for each of up to {REPLAY_MAX_SESSIONS}"
+        );
+        Arc::new(ReplaySessions::new(window))
+    });
+    if args.length_prefix {
+        eprintln!("disrust: length-prefixing every response");
+    }
+    if args.vector_status {
+        eprintln!("disrust: vector status after every response");
+    }
+    if args.request_ids {
+        eprintln!("disrust: reading a request_id before every request and echoing it");
+    }
+    if let Some(scale) = args.fixed_point_scale {
+        eprintln!("disrust: fixed-point scores on request, scaled by {scale}");
+    }
+    let placement =
+        PlacementPolicy::parse(&args.inline_policy).map_err(|e| format!("--inline-policy: {e}"))?;
+    let inline_model = args
+        .inline_linear_model
+        .as_ref()
+        .map(|path| {
+            let model = LinearModel::load(path, feature_dim)
+                .map_err(|e| format!("--inline-linear-model: {e}"))?;
+            eprintln!("disrust: inline scoring on, policy {}", args.inline_policy);
+            Ok::<_, String>(model)
+        })
+        .transpose()?;
+    let canary = args
+        .canary
+        .as_ref()
+        .map(|path| Canary::load(path, feature_dim).map_err(|e| format!("--canary: {e}")))
+        .transpose()?;
+    let schema = args.feature_schema.as_ref().map(|path| {
+        let schema =
+            FeatureSchema::load(path, feature_dim).map_err(|e| format!("--feature-schema: {e}"))?;
+        let features = schema.feature_names();
+        eprintln!(
+            "disrust: feature schema {}, {} constrained features",
+            path.display(),
+            features.len()
+        );
+        metrics::set_schema_features(features);
+        Ok::<_, String>(Arc::new(schema))
+    });
+    let schema = schema.transpose()?;
+    let calibration = args
+        .calibration
+        .as_ref()
+        .map(|path| {
+            let calibration = Calibration::load(path).map_err(|e| format!("--calibration: {e}"))?;
+            eprintln!("disrust: calibration {} ({calibration})", path.display());
+            Ok::<_, String>(Arc::new(calibration))
+        })
+        .transpose()?;
+    if let Some(kb) = args.max_write_backlog_kb {
+        eprintln!("disrust: max_write_backlog_kb={kb} (evict at {})", kb * 2);
+    }
+    if let Some(secs) = args.idle_timeout_secs {
+        eprintln!("disrust: closing connections idle for {secs}s");
+    }
+    if let Some(ms) = args.slot_quarantine_ms {
+        eprintln!("disrust: quarantining freed connection slots for {ms}ms");
+    }
+    let io_backend = ingress::resolve_io_backend(args.io_backend);
+    if io_backend == IoBackend::Epoll {
+        eprintln!("disrust: IO threads on epoll");
+    }
+    if let Some(entries) = args.read_buffer_ring
+        && io_backend == IoBackend::IoUring
+    {
+        eprintln!(
+            "disrust: reading into {entries} provided buffers of {} KiB per IO thread",
+            PROVIDED_READ_BUF_SIZE / 1024
+        );
+    }
+    if args.fixed_buffers && io_backend == IoBackend::IoUring {
+        eprintln!("disrust: reading and writing through registered buffers");
+    }
+    if args.adaptive_reads {
+        eprintln!(
+            "disrust: sizing reads by connection throughput, from {} KiB",
+            MIN_READ_SIZE / 1024
+        );
+    }
+    if let Some(cpu) = args.metrics_cpu {
+        eprintln!("disrust: metrics_cpu={cpu}");
+    }
+    if let Some(cpu) = args.submission_cpu {
+        eprintln!("disrust: submission_cpu={cpu}");
+    }
+    if let Some(cpu) = args.completion_cpu {
+        eprintln!("disrust: completion_cpu={cpu}");
+    }
+    if let Some(cpu) = args.io_cpu {
+        eprintln!("disrust: io_cpu_base={cpu}");
+    }
+    eprintln!("disrust: io_threads={io_threads} (runtime max={max_io_threads})");
+    eprintln!("disrust: {plan}");
+
+    let admin_listener = args
+        .admin_socket
+        .as_ref()
+        .map(|path| {
+            let listener = admin::bind(path)
+                .map_err(|e| format!("failed to bind admin socket {}: {e}", path.display()))?;
+            eprintln!("disrust: admin socket {}", path.display());
+            Ok::<_, String>(listener)
+        })
+        .transpose()?;
+    #[cfg(feature = "prometheus")]
+    let exporter = args
+        .prometheus_port
+        .map(|port| {
+            let addr = std::net::SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, port));
+            let exporter = crate::prometheus::Exporter::bind(addr)
+                .map_err(|e| format!("failed to bind prometheus port {port}: {e}"))?;
+            eprintln!("disrust: prometheus metrics on port {port}");
+            Ok::<_, String>(exporter)
+        })
+        .transpose()?;
+
+    if args.backend == BackendKind::Ort {
+        OrtBackend::init();
+    }
+    set_factory_pool(BufferPool::new_boxed(1));
+
+    let numerics = if args.deterministic {
+        Numerics::Deterministic
+    } else {
+        Numerics::Fast
+    };
+    let model_source = ModelSource::new(
+        args.model.as_ref(),
+        args.model_manifest.as_deref(),
+        args.backend,
+        numerics,
+        feature_dim,
+    );
+    let (mut backend, model) = model_source.load()?;
+    metrics::set_model_version(model.version());
+    drop(model);
+
+    // Pinned CUDA host memory cannot be asked for huge pages, so it keeps the backend's pool.
+    let pool = if args.huge_pages && !cfg!(feature = "cuda") {
+        BufferPool::leak_new_huge(gpu_buffer_pool_capacity(feature_dim))
+    } else {
+        if args.huge_pages {
+            eprintln!("disrust: --huge-pages ignored, the buffer pool is pinned CUDA host memory");
+        }
+        ModelBackend::make_pool(feature_dim)
+    };
+    let mut allocator = pool.allocator();
+
+    let canary_outcome = canary.as_ref().map(|canary| {
+        let outcome = canary.run(&mut backend, &mut allocator);
+        eprintln!("disrust: canary {outcome}");
+        outcome
+    });
+    if canary_outcome
+        .as_ref()
+        .is_some_and(|outcome| !outcome.passed())
+        && args.admin_socket.is_none()
+    {
+        return Err("canary failed and there is no admin socket to report it".to_string());
+    }
+    // Without a passing canary, no IO thread starts, so the server never takes traffic; the admin
+    // socket stays up to report why.
+    let canary_failure = canary_outcome
+        .as_ref()
+        .filter(|outcome| !outcome.passed())
+        .map(ToString::to_string);
+    let ready = canary_failure.is_none();
+
+    eprintln!(
+        "disrust: buffer pool {} MB, {} pages",
+        gpu_buffer_pool_bytes(feature_dim) / 1_000_000,
+        pool.pages().as_str(),
+    );
+
+    let builder = build_multi_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (submission_poller, builder) = builder.event_poller();
+    let (completion_poller, builder) = builder.and_then().event_poller();
+    let producer = builder.build();
+
+    let response_queues = ResponseRouter::new(max_io_threads);
+    let response_queues = if io_backend == IoBackend::IoUring && notify::msg_ring_supported() {
+        eprintln!("disrust: waking IO threads with MSG_RING");
+        Arc::new(response_queues.with_msg_ring_wakeups())
+    } else {
+        Arc::new(response_queues)
+    };
+    let publish_gate = Arc::new(std::sync::Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(max_io_threads, SLAB_CAPACITY));
+    let (worker_exit_tx, worker_exit_rx) = mpsc::channel::<WorkerExit>();
+
+    if let (Some(submission_cpu), Some(completion_cpu)) = (args.submission_cpu, args.completion_cpu)
+        && submission_cpu != completion_cpu
+    {
+        return Err(
+            "--submission-cpu and --completion-cpu must match when submission and completion share one inference thread"
+                .to_string(),
+        );
+    }
+
+    let ring_occupancy = inline_model
+        .is_some()
+        .then(|| Arc::new(RingOccupancy::default()));
+    let (control_tx, control_rx) = control_channel(CONTROL_CHANNEL_CAPACITY);
+    let mut inference_consumer = InferenceConsumer::new(
+        submission_poller,
+        completion_poller,
+        backend,
+        Arc::clone(&response_queues),
+        Arc::clone(&registry),
+        max_batch_slots,
+        batch_coalesce,
+    )
+    .with_control(control_rx);
+    // Reloads are requested on the admin socket, so without one there is nothing to swap in.
+    let mut model_reloader = None;
+    if args.admin_socket.is_some() {
+        let (swaps, swapped) = mpsc::sync_channel(1);
+        inference_consumer = inference_consumer.with_model_swaps(swapped);
+        let reloader = ModelReloader::new(model_source, swaps);
+        model_reloader = Some(match canary {
+            Some(canary) => reloader.with_canary(canary),
+            None => reloader,
+        });
+    }
+    if let Some(deadline_us) = args.request_deadline_us {
+        inference_consumer =
+            inference_consumer.with_request_deadline(Duration::from_micros(deadline_us));
+    }
+    if let Some(timeout_us) = args.request_timeout_us {
+        inference_consumer =
+            inference_consumer.with_request_timeout(Duration::from_micros(timeout_us));
+    }
+    if let Some(calibration) = calibration {
+        inference_consumer = inference_consumer.with_calibration(calibration);
+    }
+    let drift = args
+        .drift_stats
+        .then(|| Arc::new(DriftMonitor::new(feature_dim)));
+    if let Some(monitor) = &drift {
+        eprintln!("disrust: collecting per-feature drift statistics");
+        inference_consumer = inference_consumer.with_drift_monitor(Arc::clone(monitor));
+    }
+    if let Some(spec) = &args.sample_sink {
+        let sink = sampling::open_sink(spec).map_err(|e| format!("--sample-sink {spec}: {e}"))?;
+        let (sampler, samples) = sampling::sample_channel(args.sample_rate, SAMPLE_QUEUE_CAPACITY);
+        thread::Builder::new()
+            .name("sampler".into())
+            .spawn(move || {
+                if let Err(e) = sampling::write_samples(samples, sink) {
+                    eprintln!("disrust: sample sink failed, sampling stopped: {e}");
+                }
+            })
+            .expect("failed to spawn sample writer");
+        eprintln!(
+            "disrust: sampling requests to {spec}, rate={}",
+            args.sample_rate
+        );
+        inference_consumer = inference_consumer.with_sampler(sampler);
+    }
+    let admission = args.large_requests.as_deref().map(|spec| {
+        let admission =
+            LargeRequestAdmission::parse(spec).map_err(|e| format!("--large-requests: {e}"))?;
+        eprintln!(
+            "disrust: admitting at most {} requests over {} vectors at once",
+            admission.limit(),
+            admission.threshold()
+        );
+        Ok::<_, String>(Arc::new(admission))
+    });
+    let admission = admission.transpose()?;
+    if let Some(admission) = &admission {
+        inference_consumer = inference_consumer.with_large_request_admission(Arc::clone(admission));
+    }
+    let circuit = args.circuit_breaker.as_deref().map(|spec| {
+        let circuit = CircuitBreaker::parse(spec).map_err(|e| format!("--circuit-breaker: {e}"))?;
+        eprintln!(
+            "disrust: failing requests fast for {}ms once {}% of {} requests time out",
+            circuit.open_for().as_millis(),
+            circuit.percent(),
+            circuit.window()
+        );
+        Ok::<_, String>(Arc::new(circuit))
+    });
+    let circuit = circuit.transpose()?;
+    if let Some(circuit) = &circuit {
+        inference_consumer = inference_consumer.with_circuit_breaker(Arc::clone(circuit));
+    }
+    if let Some(occupancy) = &ring_occupancy {
+        inference_consumer = inference_consumer.with_ring_occupancy(Arc::clone(occupancy));
+    }
+    let state_dump = StateDump::new(pool, GPU_DISRUPTOR_SIZE, Arc::clone(&response_queues));
+    let state_dump = match &ring_occupancy {
+        Some(occupancy) => state_dump.with_ring_occupancy(Arc::clone(occupancy)),
+        None => state_dump,
+    };
+    let state_dump = match &args.state_dump_file {
+        Some(path) => state_dump.with_path(path.clone()),
+        None => state_dump,
+    };
+    let inline = inline_model
+        .zip(ring_occupancy)
+        .map(|(model, occupancy)| InlineFastPath::new(Arc::new(model), placement, occupancy));
+    let inference_cpu = args.submission_cpu.or(args.completion_cpu);
+    let inference = thread::Builder::new()
+        .name("inference".into())
+        .spawn({
+            let worker_exit_tx = worker_exit_tx.clone();
+            move || {
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if let Some(cpu) = inference_cpu {
+                        affinity::pin_current_thread(cpu, "inference")
+                            .unwrap_or_else(|e| panic!("{e}"));
+                    }
+                    inference_consumer.run()
+                }));
+                let _ = match outcome {
+                    Ok(()) => worker_exit_tx.send(WorkerExit::Returned("inference")),
+                    Err(payload) => worker_exit_tx
+                        .send(WorkerExit::Panicked("inference", panic_message(payload))),
+                };
+            }
+        })
+        .expect("failed to spawn inference consumer");
+
+    let limits = Arc::new(SoftLimits::from_args(&args));
+    let spawner = std::sync::Mutex::new(IoThreadSpawner {
+        port,
+        per_thread_ports: args.per_thread_ports,
+        io_cpu: args.io_cpu,
+        max_connections,
+        feature_dim: wire_dim,
+        max_vectors: args.max_vectors,
+        port_max_vectors,
+        echo_request_seq: args.echo_request_seq,
+        length_prefix: args.length_prefix,
+        vector_status: args.vector_status,
+        request_ids: args.request_ids,
+        fixed_point_scale: args.fixed_point_scale,
+        inline,
+        schema,
+        admission,
+        circuit,
+        embeddings: embeddings.clone(),
+        replay,
+        idle_timeout: args.idle_timeout_secs.map(Duration::from_secs),
+        request_timeout: args.request_timeout_us.map(Duration::from_micros),
+        response_ttl: args.response_ttl_us.map(Duration::from_micros),
+        slot_quarantine: args.slot_quarantine_ms.map(Duration::from_millis),
+        read_buffer_ring: args.read_buffer_ring,
+        io_backend,
+        adaptive_reads: args.adaptive_reads,
+        fixed_buffers: args.fixed_buffers,
+        limits: Arc::clone(&limits),
+        producer,
+        allocator,
+        response_queues: Arc::clone(&response_queues),
+        publish_gate,
+        registry,
+        inference_control: control_tx.clone(),
+        worker_exit_tx: worker_exit_tx.clone(),
+    });
+    let io_thread_set = Arc::new(IoThreadSet::new(
+        max_io_threads,
+        Box::new(move |thread_id, control| match &canary_failure {
+            Some(failure) => Err(format!("not starting io-{thread_id}: canary {failure}")),
+            None => spawner.lock().unwrap().spawn(thread_id, control),
+        }),
+    ));
+    let mut control_plane =
+        ControlPlane::new(Arc::clone(&io_thread_set), args.metrics_interval_secs)
+            .with_response_queues(Arc::clone(&response_queues));
+    if let Some(listener) = admin_listener {
+        control_plane = control_plane.with_admin_socket(listener);
+    }
+    #[cfg(feature = "prometheus")]
+    if let Some(exporter) = exporter {
+        control_plane = control_plane.with_prometheus(exporter);
+    }
+    if let Some(cpu) = args.metrics_cpu {
+        control_plane = control_plane.with_cpu(cpu);
+    }
+    if let Some(outcome) = canary_outcome {
+        control_plane = control_plane.with_canary(outcome);
+    }
+    if let Some(reloader) = model_reloader {
+        control_plane = control_plane.with_model_reload(reloader);
+    }
+    if let Some(monitor) = drift {
+        control_plane = control_plane.with_drift_monitor(monitor);
+    }
+    if let Some(path) = &args.config {
+        eprintln!("disrust: config {} (reloaded on SIGHUP)", path.display());
+        let reloader =
+            ConfigReloader::new(path, args.clone()).with_inference_control(control_tx.clone());
+        let reloader = match &embeddings {
+            Some(store) => reloader.with_embeddings(Arc::clone(store)),
+            None => reloader,
+        };
+        control_plane = control_plane.with_config_reload(reloader, limits);
+    }
+    let control_plane_stop = Arc::new(AtomicBool::new(false));
+    let control_plane = control_plane
+        .spawn_until(Arc::clone(&control_plane_stop))
+        .expect("failed to spawn control-plane thread");
+
+    drop(worker_exit_tx);
+    let server = Server {
+        io_threads: io_thread_set,
+        response_queues,
+        inference_control: control_tx,
+        worker_exit_rx,
+        inference,
+        control_plane,
+        control_plane_stop,
+        shutdown_grace: Duration::from_secs(args.shutdown_grace_secs),
+        state_dump,
+        ready,
+    };
+    let initial_io_threads = if ready { io_threads } else { 0 };
+    for _ in 0..initial_io_threads {
+        if let Err(e) = server.io_threads.add() {
+            let _ = server.stop();
+            return Err(e);
+        }
+    }
+
+    if ready {
+        eprintln!("disrust: ready");
+    } else {
+        eprintln!("disrust: not ready, the canary failed; not accepting connections");
+    }
+    Ok(server)
+}
+
+/// Why a [`Server`] stopped other than cleanly.
+#[derive(Debug)]
+pub enum ServerError {
+    /// A worker thread returned or panicked. Every connection has been closed, and the IO
+    /// threads left will not take new ones.
+    WorkerFailed(String),
+    /// The shutdown did not finish within its grace period; `stage` is what it was waiting on.
+    ShutdownTimedOut {
+        grace: Duration,
+        stage: &'static str,
+    },
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::WorkerFailed(message) => f.write_str(message),
+            ServerError::ShutdownTimedOut { grace, stage } => {
+                write!(f, "shutdown timed out after {grace:?} {stage}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// Configures and starts a server from the library, the way `disrust serve` does from flags.
+///
+/// [`ServerBuilder::new`] starts from `disrust serve`'s defaults; [`ServerBuilder::from_args`]
+/// takes any [`ServeArgs`], for settings without a builder method of their own.
+pub struct ServerBuilder {
+    args: ServeArgs,
+}
+
+impl ServerBuilder {
+    /// A server for the ONNX model at `model`, with every other setting at its default.
+    pub fn new(model: impl Into<String>) -> Self {
+        Self::from_args(ServeArgs::for_model(model))
+    }
+
+    pub fn from_args(args: ServeArgs) -> Self {
+        Self { args }
+    }
+
+    /// Port to listen on; with per-thread ports, IO thread `i` listens on `port + i`.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.args.port = port;
+        self
+    }
+
+    /// IO threads to start with. More can be added at runtime through [`Server::io_threads`].
+    pub fn with_io_threads(mut self, io_threads: u8) -> Self {
+        self.args.io_threads = io_threads;
+        self
+    }
+
+    /// Open connections per IO thread, which sizes the per-connection read buffers.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.args.max_connections = max_connections;
+        self
+    }
+
+    /// Features per request vector, which sizes the request feature pool.
+    pub fn with_feature_dim(mut self, feature_dim: usize) -> Self {
+        self.args.feature_dim = feature_dim;
+        self
+    }
+
+    /// Runtime cap on ring slots per backend submission.
+    pub fn with_max_batch_slots(mut self, max_batch_slots: usize) -> Self {
+        self.args.max_batch_slots = max_batch_slots;
+        self
+    }
+
+    /// Refuse to start if the allocation plan, pools and buffers included, needs more than
+    /// `budget_mb` MiB; with `shrink`, lower the connection limit to fit instead.
+    pub fn with_memory_budget_mb(mut self, budget_mb: usize, shrink: bool) -> Self {
+        self.args.memory_budget_mb = Some(budget_mb);
+        self.args.memory_budget_shrink = shrink;
+        self
+    }
+
+    /// Back the request feature pool with huge pages where the system has them.
+    pub fn with_huge_pages(mut self) -> Self {
+        self.args.huge_pages = true;
+        self
+    }
+
+    /// How long [`Server::stop`] may take, in whole seconds.
+    pub fn with_shutdown_grace(mut self, grace: Duration) -> Self {
+        self.args.shutdown_grace_secs = grace.as_secs();
+        self
+    }
+
+    /// Load the model, start the inference thread, the control plane and the IO threads, and
+    /// return once they accept connections. Errors name the setting at fault, as `disrust
+    /// serve` reports them.
+    pub fn start(self) -> Result<Server, String> {
+        start(self.args)
+    }
+}
+
+/// A running server, from [`ServerBuilder::start`].
+///
+/// Dropping it leaves its threads serving; call [`Server::stop`] or [`Server::run_until`] to
+/// shut it down.
+pub struct Server {
+    io_threads: Arc<IoThreadSet>,
+    response_queues: Arc<ResponseRouter>,
+    inference_control: ControlSender,
+    worker_exit_rx: mpsc::Receiver<WorkerExit>,
+    inference: thread::JoinHandle<()>,
+    control_plane: thread::JoinHandle<()>,
+    control_plane_stop: Arc<AtomicBool>,
+    shutdown_grace: Duration,
+    state_dump: StateDump,
+    ready: bool,
+}
+
+impl Server {
+    /// The IO threads, to add, drain or remove them or read their status, as the admin socket
+    /// does.
+    pub fn io_threads(&self) -> &Arc<IoThreadSet> {
+        &self.io_threads
+    }
+
+    /// Whether the server accepts connections; `false` after a failed canary.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Serve until `stop_requested` returns `true`, checked every 50 ms, then [`Self::stop`]. Returns early if a worker thread exits.
+    pub fn run_until(self, stop_requested: impl Fn() -> bool) -> Result<(), ServerError> {
+        loop {
+            match self.worker_exit_rx.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok(exit) => return Err(self.fail(exit)),
+                Err(mpsc::RecvTimeoutError::Timeout) if stop_requested() => return self.stop(),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    panic!("worker exit channel closed unexpectedly")
+                }
+            }
+        }
+    }
+
+    /// Stop accepting and reading, answer and write every request already read, then let the
+    /// inference thread finish its in-flight batches and join it and the control plane, all
+    /// within the shutdown grace period.
+    pub fn stop(self) -> Result<(), ServerError> {
+        match drain_and_stop(
+            &self.io_threads,
+            &self.inference_control,
+            &self.worker_exit_rx,
+            self.shutdown_grace,
+        ) {
+            Ok(()) => {
+                let _ = self.inference.join();
+                stop_control_plane(&self.control_plane_stop, self.control_plane);
+                Ok(())
+            }
+            Err(ShutdownError::Worker(exit)) => Err(self.fail(exit)),
+            Err(ShutdownError::TimedOut(stage)) => {
+                let reason = format!("shutdown timed out after {:?} {stage}", self.shutdown_grace);
+                self.state_dump.write(&reason, &self.io_threads);
+                stop_control_plane(&self.control_plane_stop, self.control_plane);
+                Err(ServerError::ShutdownTimedOut {
+                    grace: self.shutdown_grace,
+                    stage,
+                })
+            }
+        }
+    }
+
+    /// Dump the server's state, then close every connection after a worker exited, and stop
+    /// the control plane.
+    fn fail(self, exit: WorkerExit) -> ServerError {
+        let reason = match exit {
+            WorkerExit::Returned(name) => format!("worker thread '{name}' exited unexpectedly"),
+            WorkerExit::Panicked(name, message) => {
+                format!("worker thread '{name}' panicked: {message}")
+            }
+        };
+        // Before the connections close, so the dump shows what they were waiting for.
+        self.state_dump.write(&reason, &self.io_threads);
+        // A panicking inference thread has already poisoned the queues; this covers every other
+        // worker exit, so IO threads close their connections.
+        self.response_queues.poison_all();
+        wait_for_connections_closed(&self.io_threads, FATAL_EXIT_GRACE);
+        stop_control_plane(&self.control_plane_stop, self.control_plane);
+        ServerError::WorkerFailed(reason)
+    }
+}
+
+fn stop_control_plane(stop: &AtomicBool, control_plane: thread::JoinHandle<()>) {
+    stop.store(true, Ordering::Relaxed);
+    let _ = control_plane.join();
+}
+
+/// Run the server configured by `args` until SIGTERM or SIGINT, then drain it and exit the
+/// process.
+pub fn run(args: ServeArgs) {
+    let server = ServerBuilder::from_args(args).start().unwrap_or_else(|e| {
+        eprintln!("disrust: {e}");
+        std::process::exit(1);
+    });
+    shutdown::install_handler();
+    eprintln!(
+        "disrust: SIGTERM/SIGINT drain in-flight requests for up to {}s",
+        server.shutdown_grace.as_secs()
+    );
+    match server.run_until(shutdown::requested) {
+        Ok(()) => {
+            eprintln!("disrust: shut down cleanly");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("disrust: {e}");
+            std::process::exit(match e {
+                ServerError::WorkerFailed(_) => EXIT_WORKER_FAILED,
+                ServerError::ShutdownTimedOut { .. } => EXIT_SHUTDOWN_TIMEOUT,
+            });
+        }
+    }
+}
+
+enum ShutdownError {
+    /// A worker exited some other way while the server was shutting down.
+    Worker(WorkerExit),
+    /// The grace period ran out; the stage that was still waiting.
+    TimedOut(&'static str),
+}
+
+/// Shut down every IO thread, answering and writing what their connections already sent, then
+/// let the inference thread finish its in-flight batches and return, all within `grace`.
+fn drain_and_stop(
+    io_thread_set: &IoThreadSet,
+    inference_control: &ControlSender,
+    worker_exit_rx: &mpsc::Receiver<WorkerExit>,
+    grace: Duration,
+) -> Result<(), ShutdownError> {
+    eprintln!("disrust: shutting down, draining in-flight requests");
+    let deadline = Instant::now() + grace;
+    io_thread_set.shutdown_all();
+    while !io_thread_set
+        .status()
+        .iter()
+        .all(|&(_, state, _)| state == IoThreadState::Stopped)
+    {
+        if let Ok(exit) = worker_exit_rx.try_recv() {
+            return Err(ShutdownError::Worker(exit));
+        }
+        if Instant::now() >= deadline {
+            return Err(ShutdownError::TimedOut("waiting for IO threads"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    // Every publisher is gone, so whatever is in the ring now is all the inference thread has
+    // left to answer.
+    while !inference_control.send(ControlEvent::Shutdown) {
+        if Instant::now() >= deadline {
+            return Err(ShutdownError::TimedOut("signalling the inference thread"));
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match worker_exit_rx.recv_timeout(remaining) {
+        Ok(WorkerExit::Returned("inference")) => Ok(()),
+        Ok(exit) => Err(ShutdownError::Worker(exit)),
+        Err(_) => Err(ShutdownError::TimedOut("waiting for the inference thread")),
+    }
+}
+
+/// Wait up to `grace` for every IO thread to report no open connections.
+fn wait_for_connections_closed(io_thread_set: &IoThreadSet, grace: Duration) {
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        let open: usize = io_thread_set
+            .status()
+            .iter()
+            .map(|&(_, _, connections)| connections)
+            .sum();
+        if open == 0 {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    eprintln!("disrust: connections still open after {grace:?}, exiting anyway");
+}
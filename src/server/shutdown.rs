@@ -0,0 +1,35 @@
+//! Graceful shutdown on SIGTERM or SIGINT.
+//!
+//! The signal handler only records the request; `serve` polls [`requested`] while it waits on
+//! its worker threads. It then shuts every IO thread down through its `IoThreadControl`: accepts
+//! and reads stop, and each connection is closed once every request it sent has been answered
+//! and written. Only when no IO thread is left to publish does the inference thread get
+//! `ControlEvent::Shutdown`, and it returns once the ring and its in-flight batches are empty.
+//!
+//! A second signal exits at once, for when the drain itself hangs.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit status on a second signal, as if the default handler had run for SIGINT.
+const EXIT_FORCED: libc::c_int = 130;
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    if SHUTDOWN_REQUESTED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(EXIT_FORCED) };
+    }
+}
+
+/// Record SIGTERM and SIGINT for [`requested`] instead of terminating the process.
+pub fn install_handler() {
+    let handler: extern "C" fn(libc::c_int) = on_signal;
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        unsafe { libc::signal(signal, handler as libc::sighandler_t) };
+    }
+}
+
+/// `true` once SIGTERM or SIGINT has been received.
+pub fn requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,176 @@
+//! Connection slots: the `conn_id`s an IO thread hands out.
+//!
+//! A slot freed by a closed connection is normally taken by the next accept. With
+//! `--slot-quarantine-ms`, it is held back that long first, so a response still on its way to
+//! the old connection finds the slot empty instead of taken by a new one. The generation in
+//! every `ConnectionRef` already stops such a response from being delivered; quarantine also
+//! covers anything keyed on `conn_id` alone. Size it above the longest a response waits in the
+//! response queue.
+
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut};
+use std::time::Duration;
+
+use crate::metrics;
+
+/// Slots for up to `capacity` values, keyed `0..capacity`.
+pub struct ConnectionSlots<T> {
+    entries: Vec<Option<T>>,
+    len: usize,
+    capacity: usize,
+    /// Reusable slots, most recently freed last.
+    free: Vec<usize>,
+    /// Freed slots and when each becomes reusable, oldest first.
+    quarantine: VecDeque<(usize, u64)>,
+    quarantine_ns: u64,
+}
+
+impl<T> ConnectionSlots<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            len: 0,
+            capacity,
+            free: Vec::new(),
+            quarantine: VecDeque::new(),
+            quarantine_ns: 0,
+        }
+    }
+
+    /// Hold each freed slot back for `quarantine` before it is reused.
+    pub fn with_quarantine(mut self, quarantine: Duration) -> Self {
+        self.quarantine_ns = quarantine.as_nanos() as u64;
+        self
+    }
+
+    /// Occupied slots.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Freed slots not yet reusable.
+    pub fn quarantined(&self) -> usize {
+        self.quarantine.len()
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        self.entries.get(key)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        self.entries.get_mut(key)?.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(key, entry)| Some((key, entry.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(key, entry)| Some((key, entry.as_mut()?)))
+    }
+
+    /// Fill a free slot with `make(key)`, returning its key, or `None` if every slot is
+    /// occupied or quarantined at `now_ns`.
+    pub fn insert_with(&mut self, now_ns: u64, make: impl FnOnce(usize) -> T) -> Option<usize> {
+        let released = self
+            .quarantine
+            .iter()
+            .take_while(|&&(_, until_ns)| until_ns <= now_ns)
+            .count();
+        if released > 0 {
+            self.free
+                .extend(self.quarantine.drain(..released).map(|(key, _)| key));
+            metrics::sub_slots_quarantined(released);
+        }
+        let key = match self.free.pop() {
+            Some(key) => key,
+            None if self.entries.len() < self.capacity => {
+                self.entries.push(None);
+                self.entries.len() - 1
+            }
+            None => return None,
+        };
+        self.entries[key] = Some(make(key));
+        self.len += 1;
+        Some(key)
+    }
+
+    /// Empty slot `key` at `now_ns`, quarantining it if configured.
+    pub fn try_remove(&mut self, key: usize, now_ns: u64) -> Option<T> {
+        let value = self.entries.get_mut(key)?.take()?;
+        self.len -= 1;
+        if self.quarantine_ns > 0 {
+            self.quarantine
+                .push_back((key, now_ns + self.quarantine_ns));
+            metrics::add_slots_quarantined(1);
+        } else {
+            self.free.push(key);
+        }
+        Some(value)
+    }
+}
+
+impl<T> Drop for ConnectionSlots<T> {
+    fn drop(&mut self) {
+        metrics::sub_slots_quarantined(self.quarantine.len());
+    }
+}
+
+impl<T> Index<usize> for ConnectionSlots<T> {
+    type Output = T;
+
+    fn index(&self, key: usize) -> &T {
+        self.get(key).expect("no value in slot")
+    }
+}
+
+impl<T> IndexMut<usize> for ConnectionSlots<T> {
+    fn index_mut(&mut self, key: usize) -> &mut T {
+        self.get_mut(key).expect("no value in slot")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ConnectionSlots;
+
+    #[test]
+    fn freed_slots_are_reused_only_after_quarantine() {
+        let mut slots = ConnectionSlots::new(3).with_quarantine(Duration::from_nanos(100));
+        assert_eq!(slots.insert_with(0, |key| key * 10), Some(0));
+        assert_eq!(slots.insert_with(0, |key| key * 10), Some(1));
+        assert_eq!(slots.try_remove(0, 10), Some(0));
+        assert_eq!(slots.try_remove(0, 10), None);
+        assert_eq!((slots.len(), slots.quarantined()), (1, 1));
+
+        // Slot 0 is quarantined until 110, so a fresh slot is used, then none is left.
+        assert_eq!(slots.insert_with(50, |key| key * 10), Some(2));
+        assert_eq!(slots.insert_with(109, |key| key * 10), None);
+        assert_eq!(slots.insert_with(110, |key| key * 10), Some(0));
+        assert_eq!(slots.quarantined(), 0);
+        assert_eq!(
+            slots
+                .iter()
+                .map(|(key, &value)| (key, value))
+                .collect::<Vec<_>>(),
+            [(0, 0), (1, 10), (2, 20)]
+        );
+
+        let mut plain = ConnectionSlots::new(2);
+        assert_eq!(plain.insert_with(0, |_| ()), Some(0));
+        plain.try_remove(0, 0);
+        assert_eq!(plain.insert_with(0, |_| ()), Some(0), "reused at once");
+    }
+}
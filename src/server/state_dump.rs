@@ -0,0 +1,219 @@
+//! Postmortem state dump, written when a worker thread fails or shutdown runs out of time.
+//!
+//! Both usually follow an exhaustion the server could not get out of: a pool whose read cursor
+//! stopped moving, a response queue nobody drains, connections waiting on answers that never
+//! come. The dump records that state in a few lines on stderr, and in `--state-dump-file` if
+//! given, so a postmortem has it without a debugger attached:
+//!
+//! ```text
+//! disrust: state dump (worker thread 'inference' panicked: ...)
+//!   pool: read=<n> write=<n> in_use=<n> capacity=<n>
+//!   request_ring: occupied=<n> size=<n>
+//!   response_queue io-<id>: len=<n> capacity=<n> max=<n>
+//!   io-<id> <state> connections=<n>
+//!   io-<id> conn=<conn_id> in_flight=<n> requests=<n> buffered=<bytes>
+//!   io-<id> no answer
+//! ```
+//!
+//! Pool cursors count f32 values. `request_ring` appears only with `--inline-linear-model`,
+//! the one mode that counts the ring's occupancy. Connections are listed by their IO threads,
+//! so a thread that is stuck or gone shows as `no answer`.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::buffer_pool::{BufferPool, PoolCursors};
+use crate::pipeline::inline::RingOccupancy;
+use crate::pipeline::response_queue::{QueueOccupancy, ResponseRouter};
+use crate::server::control::{ConnectionInfo, IoThreadSet, IoThreadState};
+
+/// How long the dump waits for each IO thread to list its connections.
+const LISTING_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What a fatal-error dump reads the server's state from.
+pub(crate) struct StateDump {
+    pool: &'static BufferPool,
+    ring_size: usize,
+    ring_occupancy: Option<Arc<RingOccupancy>>,
+    response_queues: Arc<ResponseRouter>,
+    path: Option<PathBuf>,
+}
+
+/// The state at one moment, as [`write_state`] prints it.
+struct State {
+    pool: PoolCursors,
+    /// Requests in the request ring and its size, where counted.
+    request_ring: Option<(u64, usize)>,
+    response_queues: Vec<(u8, QueueOccupancy)>,
+    io_threads: Vec<(u8, IoThreadState, usize)>,
+    connections: Vec<(u8, Option<Vec<ConnectionInfo>>)>,
+}
+
+impl StateDump {
+    pub(crate) fn new(
+        pool: &'static BufferPool,
+        ring_size: usize,
+        response_queues: Arc<ResponseRouter>,
+    ) -> Self {
+        Self {
+            pool,
+            ring_size,
+            ring_occupancy: None,
+            response_queues,
+            path: None,
+        }
+    }
+
+    /// Report the request ring's occupancy from `occupancy`.
+    pub(crate) fn with_ring_occupancy(mut self, occupancy: Arc<RingOccupancy>) -> Self {
+        self.ring_occupancy = Some(occupancy);
+        self
+    }
+
+    /// Also write each dump to `path`, replacing what it held.
+    pub(crate) fn with_path(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Dump the state to stderr and the dump file, if any, headed by `reason`.
+    pub(crate) fn write(&self, reason: &str, io_threads: &IoThreadSet) {
+        let state = State {
+            pool: self.pool.cursors(),
+            request_ring: self
+                .ring_occupancy
+                .as_ref()
+                .map(|occupancy| (occupancy.len(), self.ring_size)),
+            response_queues: self
+                .response_queues
+                .registered()
+                .map(|(shard_id, queue)| (shard_id, queue.take_occupancy()))
+                .collect(),
+            io_threads: io_threads.status(),
+            connections: io_threads.connections(LISTING_TIMEOUT),
+        };
+        let mut dump = Vec::new();
+        write_state(&mut dump, reason, &state).expect("writing to a Vec cannot fail");
+        let _ = io::stderr().write_all(&dump);
+        if let Some(path) = &self.path
+            && let Err(e) = File::create(path).and_then(|mut file| file.write_all(&dump))
+        {
+            eprintln!(
+                "disrust: cannot write the state dump to {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+fn write_state(out: &mut impl Write, reason: &str, state: &State) -> io::Result<()> {
+    writeln!(out, "disrust: state dump ({reason})")?;
+    let pool = state.pool;
+    writeln!(
+        out,
+        "  pool: read={} write={} in_use={} capacity={}",
+        pool.read,
+        pool.write,
+        pool.in_use(),
+        pool.capacity
+    )?;
+    if let Some((occupied, size)) = state.request_ring {
+        writeln!(out, "  request_ring: occupied={occupied} size={size}")?;
+    }
+    for (shard_id, queue) in &state.response_queues {
+        writeln!(
+            out,
+            "  response_queue io-{shard_id}: len={} capacity={} max={}",
+            queue.len, queue.capacity, queue.max_occupancy
+        )?;
+    }
+    for (thread_id, thread_state, connections) in &state.io_threads {
+        writeln!(
+            out,
+            "  io-{thread_id} {} connections={connections}",
+            thread_state.as_str()
+        )?;
+    }
+    for (thread_id, listing) in &state.connections {
+        let Some(connections) = listing else {
+            writeln!(out, "  io-{thread_id} no answer")?;
+            continue;
+        };
+        for conn in connections {
+            writeln!(
+                out,
+                "  io-{thread_id} conn={} in_flight={} requests={} buffered={}",
+                conn.conn_id, conn.in_flight, conn.requests, conn.buffered
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{State, write_state};
+    use crate::buffer_pool::PoolCursors;
+    use crate::pipeline::response_queue::QueueOccupancy;
+    use crate::server::control::{ConnectionInfo, IoThreadState};
+
+    #[test]
+    fn dump_lists_pool_rings_and_connections() {
+        let state = State {
+            pool: PoolCursors {
+                read: 4096,
+                write: 4096 + 960,
+                capacity: 1024,
+            },
+            request_ring: Some((30, 4096)),
+            response_queues: vec![(
+                0,
+                QueueOccupancy {
+                    capacity: 8,
+                    len: 8,
+                    max_occupancy: 8,
+                    ..QueueOccupancy::default()
+                },
+            )],
+            io_threads: vec![
+                (0, IoThreadState::Running, 1),
+                (1, IoThreadState::Draining, 2),
+            ],
+            connections: vec![
+                (
+                    0,
+                    Some(vec![ConnectionInfo {
+                        conn_id: 5,
+                        read_size: 4096,
+                        bytes_per_read: 0,
+                        buffered: 12,
+                        requests: 40,
+                        in_flight: 32,
+                    }]),
+                ),
+                (1, None),
+            ],
+        };
+        let mut out = Vec::new();
+        write_state(
+            &mut out,
+            "worker thread 'inference' exited unexpectedly",
+            &state,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "disrust: state dump (worker thread 'inference' exited unexpectedly)\n\
+             \x20 pool: read=4096 write=5056 in_use=960 capacity=1024\n\
+             \x20 request_ring: occupied=30 size=4096\n\
+             \x20 response_queue io-0: len=8 capacity=8 max=8\n\
+             \x20 io-0 running connections=1\n\
+             \x20 io-1 draining connections=2\n\
+             \x20 io-0 conn=5 in_flight=32 requests=40 buffered=12\n\
+             \x20 io-1 no answer\n"
+        );
+    }
+}
@@ -105,6 +105,15 @@ impl TimerSnapshot {
         self.value_us(99.99)
     }
 
+    /// Raw recorded value at `percentile`, for histograms of counts rather than durations.
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        self.hist.value_at_quantile(percentile / 100.0)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.hist.max()
+    }
+
     pub fn max_us(&self) -> f64 {
         self.hist.max() as f64 / 1_000.0
     }
@@ -0,0 +1,534 @@
+//! Declarative wire-frame layouts.
+//!
+//! Every frame on the wire is described here as a list of fields with a fixed offset, scalar
+//! type and byte order. `protocol` derives its sizes from these descriptions and reads and writes
+//! header fields through them, and [`render_spec`] turns the same descriptions into the
+//! byte-level layout table checked in as `tests/golden/wire_layout.md`. The parser and the spec
+//! therefore cannot drift apart without a test failing.
+//!
+//! Layout rules, enforced by the unit tests below: fields are listed in offset order, fixed
+//! fields are contiguous from offset 0, and at most one repeated field closes the frame.
+
+use std::fmt::Write;
+
+use crate::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+
+/// Scalar wire type of one field element. All multi-byte scalars are little-endian on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    U8,
+    U16Le,
+    U32Le,
+    U64Le,
+    F16Le,
+    F32Le,
+}
+
+impl Scalar {
+    pub const fn width(self) -> usize {
+        match self {
+            Scalar::U8 => 1,
+            Scalar::U16Le | Scalar::F16Le => 2,
+            Scalar::U32Le | Scalar::F32Le => 4,
+            Scalar::U64Le => 8,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Scalar::U8 => "u8",
+            Scalar::U16Le => "u16 LE",
+            Scalar::U32Le => "u32 LE",
+            Scalar::U64Le => "u64 LE",
+            Scalar::F16Le => "f16 LE",
+            Scalar::F32Le => "f32 LE",
+        }
+    }
+}
+
+/// How many scalars a field holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    /// `num_vectors × per_vector` scalars, where `num_vectors` comes from the frame header.
+    PerVector(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: usize,
+    pub scalar: Scalar,
+    pub repeat: Repeat,
+    pub doc: &'static str,
+}
+
+impl Field {
+    const fn once(name: &'static str, offset: usize, scalar: Scalar, doc: &'static str) -> Self {
+        Self {
+            name,
+            offset,
+            scalar,
+            repeat: Repeat::Once,
+            doc,
+        }
+    }
+
+    const fn per_vector(
+        name: &'static str,
+        offset: usize,
+        scalar: Scalar,
+        per_vector: usize,
+        doc: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            offset,
+            scalar,
+            repeat: Repeat::PerVector(per_vector),
+            doc,
+        }
+    }
+
+    /// Byte length of this field in a frame carrying `num_vectors` vectors.
+    pub const fn len(&self, num_vectors: usize) -> usize {
+        match self.repeat {
+            Repeat::Once => self.scalar.width(),
+            Repeat::PerVector(per_vector) => self.scalar.width() * per_vector * num_vectors,
+        }
+    }
+
+    /// Byte range of a `Once` field.
+    fn range(&self) -> std::ops::Range<usize> {
+        debug_assert_eq!(self.repeat, Repeat::Once, "{} is repeated", self.name);
+        self.offset..self.offset + self.scalar.width()
+    }
+
+    pub fn read_u8(&self, frame: &[u8]) -> u8 {
+        debug_assert_eq!(self.scalar, Scalar::U8, "{} is not u8", self.name);
+        frame[self.offset]
+    }
+
+    pub fn read_u16(&self, frame: &[u8]) -> u16 {
+        debug_assert_eq!(self.scalar, Scalar::U16Le, "{} is not u16", self.name);
+        u16::from_le_bytes(frame[self.range()].try_into().unwrap())
+    }
+
+    pub fn read_u32(&self, frame: &[u8]) -> u32 {
+        debug_assert_eq!(self.scalar, Scalar::U32Le, "{} is not u32", self.name);
+        u32::from_le_bytes(frame[self.range()].try_into().unwrap())
+    }
+
+    pub fn read_u64(&self, frame: &[u8]) -> u64 {
+        debug_assert_eq!(self.scalar, Scalar::U64Le, "{} is not u64", self.name);
+        u64::from_le_bytes(frame[self.range()].try_into().unwrap())
+    }
+
+    pub fn write_u8(&self, frame: &mut [u8], value: u8) {
+        debug_assert_eq!(self.scalar, Scalar::U8, "{} is not u8", self.name);
+        frame[self.offset] = value;
+    }
+
+    pub fn write_u16(&self, frame: &mut [u8], value: u16) {
+        debug_assert_eq!(self.scalar, Scalar::U16Le, "{} is not u16", self.name);
+        frame[self.range()].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&self, frame: &mut [u8], value: u32) {
+        debug_assert_eq!(self.scalar, Scalar::U32Le, "{} is not u32", self.name);
+        frame[self.range()].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&self, frame: &mut [u8], value: u64) {
+        debug_assert_eq!(self.scalar, Scalar::U64Le, "{} is not u64", self.name);
+        frame[self.range()].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn render_len(&self) -> String {
+        match self.repeat {
+            Repeat::Once => self.scalar.width().to_string(),
+            Repeat::PerVector(1) => format!("{} × num_vectors", self.scalar.width()),
+            Repeat::PerVector(per_vector) => {
+                format!("{} × num_vectors × {per_vector}", self.scalar.width())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLayout {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub fields: &'static [Field],
+}
+
+impl FrameLayout {
+    /// Bytes before the first repeated field; the whole frame if nothing repeats.
+    pub const fn header_bytes(&self) -> usize {
+        let mut bytes = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            if let Repeat::Once = self.fields[i].repeat {
+                bytes += self.fields[i].scalar.width();
+            }
+            i += 1;
+        }
+        bytes
+    }
+
+    /// Total frame length when carrying `num_vectors` vectors.
+    pub const fn size(&self, num_vectors: usize) -> usize {
+        let mut bytes = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            bytes += self.fields[i].len(num_vectors);
+            i += 1;
+        }
+        bytes
+    }
+
+    /// Markdown table of the byte-level layout.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "## {}", self.name).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{}", self.doc).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| offset | bytes | type | field | description |").unwrap();
+        writeln!(out, "|---|---|---|---|---|").unwrap();
+        for field in self.fields {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                field.offset,
+                field.render_len(),
+                field.scalar.name(),
+                field.name,
+                field.doc
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+pub const REQUEST_NUM_VECTORS: Field = Field::once(
+    "num_vectors",
+    0,
+    Scalar::U32Le,
+    "vectors in this request, 1..=MAX_VECTORS_PER_REQUEST; bit 31 set marks a half request, and bit 30 set asks for a fixed-point response",
+);
+pub const REQUEST_FEATURES: Field = Field::per_vector(
+    "features",
+    4,
+    Scalar::F32Le,
+    FEATURE_DIM,
+    "`--feature-dim` features per vector (FEATURE_DIM by default), vector-major",
+);
+pub const REQUEST: FrameLayout = FrameLayout {
+    name: "request",
+    doc: "Client to server. Requests may be pipelined back to back on one connection.",
+    fields: &[REQUEST_NUM_VECTORS, REQUEST_FEATURES],
+};
+
+pub const HALF_REQUEST_NUM_VECTORS: Field = Field::once(
+    "num_vectors",
+    0,
+    Scalar::U32Le,
+    "vectors in this request, 1..=MAX_VECTORS_PER_REQUEST, with bit 31 (0x8000_0000) set",
+);
+pub const HALF_REQUEST_FEATURES: Field = Field::per_vector(
+    "features",
+    4,
+    Scalar::F16Le,
+    FEATURE_DIM,
+    "`--feature-dim` IEEE half-precision features per vector, vector-major",
+);
+pub const HALF_REQUEST: FrameLayout = FrameLayout {
+    name: "half request",
+    doc: "Client to server, in place of a request: the same request with half-precision features, for half the bytes. The server widens them to f32 as it reads them, so everything after parsing, error offsets aside, sees the request as if it had been sent in f32. May be mixed with requests on one connection.",
+    fields: &[HALF_REQUEST_NUM_VECTORS, HALF_REQUEST_FEATURES],
+};
+
+pub const RESPONSE_NUM_VECTORS: Field = Field::once(
+    "num_vectors",
+    0,
+    Scalar::U8,
+    "vectors in the matching request; never 0",
+);
+pub const RESPONSE_RESULTS: Field = Field::per_vector(
+    "results",
+    1,
+    Scalar::F32Le,
+    1,
+    "one score per request vector, in request order",
+);
+pub const RESPONSE: FrameLayout = FrameLayout {
+    name: "response",
+    doc: "Server to client. Exactly one response or overload frame per request, in order.",
+    fields: &[RESPONSE_NUM_VECTORS, RESPONSE_RESULTS],
+};
+
+pub const FIXED_POINT_RESPONSE_NUM_VECTORS: Field = Field::once(
+    "num_vectors",
+    0,
+    Scalar::U8,
+    "vectors in the matching request; never 0",
+);
+pub const FIXED_POINT_RESPONSE_RESULTS: Field = Field::per_vector(
+    "results",
+    1,
+    Scalar::U16Le,
+    1,
+    "one score per request vector, in request order, as round(score × `--fixed-point-scale`) saturated to 0..=65535; NaN is 0",
+);
+pub const FIXED_POINT_RESPONSE: FrameLayout = FrameLayout {
+    name: "fixed-point response",
+    doc: "Server to client, only with `serve --fixed-point-scale`: answers a request whose `num_vectors` has bit 30 (0x4000_0000) set, in place of a response. Other requests on the connection are still answered with responses.",
+    fields: &[
+        FIXED_POINT_RESPONSE_NUM_VECTORS,
+        FIXED_POINT_RESPONSE_RESULTS,
+    ],
+};
+
+pub const VECTOR_STATUS_STATUS: Field = Field::per_vector(
+    "status",
+    0,
+    Scalar::U8,
+    1,
+    "one per result, in order: 0 = ok, 1 = invalid_input (NaN or infinite features, or outside the feature schema), 2 = model_error (NaN or infinite result)",
+);
+pub const VECTOR_STATUS: FrameLayout = FrameLayout {
+    name: "vector status",
+    doc: "Server to client, only with `serve --vector-status`: follows every response or fixed-point response frame, not overload or parse error frames, and is counted in its length prefix.",
+    fields: &[VECTOR_STATUS_STATUS],
+};
+
+pub const OVERLOAD_MARKER: Field = Field::once(
+    "marker",
+    0,
+    Scalar::U8,
+    "always 0; distinguishes an overload frame from a response",
+);
+pub const OVERLOAD_REASON: Field = Field::once(
+    "reason",
+    1,
+    Scalar::U8,
+    "why the request was rejected; 1 = ring full, 2 = large requests over `serve --large-requests`",
+);
+pub const OVERLOAD_RETRY_AFTER_MS: Field = Field::once(
+    "retry_after_ms",
+    2,
+    Scalar::U16Le,
+    "suggested client backoff before sending more",
+);
+pub const OVERLOAD: FrameLayout = FrameLayout {
+    name: "overload",
+    doc: "Server to client, in place of a response, for a request rejected without running.",
+    fields: &[OVERLOAD_MARKER, OVERLOAD_REASON, OVERLOAD_RETRY_AFTER_MS],
+};
+
+pub const PARSE_ERROR_MARKER: Field =
+    Field::once("marker", 0, Scalar::U8, "always 0, as in an overload frame");
+pub const PARSE_ERROR_KIND: Field = Field::once(
+    "kind",
+    1,
+    Scalar::U8,
+    "always 255; distinguishes a parse error from an overload reason",
+);
+pub const PARSE_ERROR_FIELD: Field = Field::once(
+    "field",
+    2,
+    Scalar::U16Le,
+    "request field that failed to parse; 1 = num_vectors, 2 = features",
+);
+pub const PARSE_ERROR_VALUE: Field = Field::once(
+    "value",
+    4,
+    Scalar::U32Le,
+    "value the server read for that field; for features, the f32 bits",
+);
+pub const PARSE_ERROR_OFFSET: Field = Field::once(
+    "offset",
+    8,
+    Scalar::U32Le,
+    "low 32 bits of the field's byte offset in the connection's request stream",
+);
+pub const PARSE_ERROR: FrameLayout = FrameLayout {
+    name: "parse error",
+    doc: "Server to client, in place of a response, for a request that failed to parse. After a num_vectors error the server closes the connection, unless it runs with `--malformed-requests skip` and reads past the vectors the header declared; a features error, under `--non-finite-features reject`, answers only that request.",
+    fields: &[
+        PARSE_ERROR_MARKER,
+        PARSE_ERROR_KIND,
+        PARSE_ERROR_FIELD,
+        PARSE_ERROR_VALUE,
+        PARSE_ERROR_OFFSET,
+    ],
+};
+
+pub const LENGTH_PREFIX_FRAME_LEN: Field = Field::once(
+    "frame_len",
+    0,
+    Scalar::U32Le,
+    "bytes after this field up to the next length prefix: the sequence and request id prefixes, if any, and the frame",
+);
+pub const LENGTH_PREFIX: FrameLayout = FrameLayout {
+    name: "length prefix",
+    doc: "Server to client, only with `serve --length-prefix`: precedes every frame, before the sequence prefix, so a client can skip frames it does not understand.",
+    fields: &[LENGTH_PREFIX_FRAME_LEN],
+};
+
+pub const SEQ_PREFIX_REQUEST_SEQ: Field = Field::once(
+    "request_seq",
+    0,
+    Scalar::U32Le,
+    "low 32 bits of the answered request's 0-based position on its connection, or in its session with `serve --replay-window`",
+);
+pub const SEQ_PREFIX: FrameLayout = FrameLayout {
+    name: "sequence prefix",
+    doc: "Server to client, only with `serve --echo-request-seq`: precedes every response, overload and parse error frame.",
+    fields: &[SEQ_PREFIX_REQUEST_SEQ],
+};
+
+pub const REQUEST_ID_REQUEST_ID: Field = Field::once(
+    "request_id",
+    0,
+    Scalar::U64Le,
+    "opaque client-chosen id, echoed unchanged; the server never interprets it",
+);
+pub const REQUEST_ID: FrameLayout = FrameLayout {
+    name: "request id prefix",
+    doc: "Both directions, only with `serve --request-ids`: precedes every request, and is echoed before the response, overload or parse error frame answering it, after the sequence prefix.",
+    fields: &[REQUEST_ID_REQUEST_ID],
+};
+
+pub const REPLAY_HELLO_SESSION_TOKEN: Field = Field::once(
+    "session_token",
+    0,
+    Scalar::U64Le,
+    "0 to start a session, otherwise the token a previous reply issued for it",
+);
+pub const REPLAY_HELLO_RESUME_SEQ: Field = Field::once(
+    "resume_seq",
+    8,
+    Scalar::U64Le,
+    "request_seq of the first response the client has not received; 0 for a new session",
+);
+pub const REPLAY_HELLO: FrameLayout = FrameLayout {
+    name: "replay hello",
+    doc: "Client to server, only with `serve --replay-window`: the first bytes on every connection, before any request.",
+    fields: &[REPLAY_HELLO_SESSION_TOKEN, REPLAY_HELLO_RESUME_SEQ],
+};
+
+pub const REPLAY_REPLY_STATUS: Field = Field::once(
+    "status",
+    0,
+    Scalar::U32Le,
+    "0 = resumed; 1 = busy, the session's previous connection is still open or owed responses, so retry shortly; 2 = gone, the server no longer holds the session or resume_seq, so start a new one",
+);
+pub const REPLAY_REPLY_SESSION_TOKEN: Field = Field::once(
+    "session_token",
+    4,
+    Scalar::U64Le,
+    "opaque token naming the session, issued when it starts and the same on every connection that resumes it; 0 unless resumed",
+);
+pub const REPLAY_REPLY_NEXT_REQUEST_SEQ: Field = Field::once(
+    "next_request_seq",
+    12,
+    Scalar::U64Le,
+    "request_seq the connection's first request will take; 0 unless resumed",
+);
+pub const REPLAY_REPLY: FrameLayout = FrameLayout {
+    name: "replay reply",
+    doc: "Server to client, only with `serve --replay-window`: answers the hello, without prefixes. When resumed, the frames kept from resume_seq follow exactly as first sent, then responses to new requests; otherwise the server closes the connection.",
+    fields: &[
+        REPLAY_REPLY_STATUS,
+        REPLAY_REPLY_SESSION_TOKEN,
+        REPLAY_REPLY_NEXT_REQUEST_SEQ,
+    ],
+};
+
+/// Every frame on the wire, in spec order.
+pub const FRAMES: &[FrameLayout] = &[
+    REQUEST,
+    HALF_REQUEST,
+    RESPONSE,
+    FIXED_POINT_RESPONSE,
+    VECTOR_STATUS,
+    OVERLOAD,
+    PARSE_ERROR,
+    LENGTH_PREFIX,
+    SEQ_PREFIX,
+    REQUEST_ID,
+    REPLAY_HELLO,
+    REPLAY_REPLY,
+];
+
+/// The full wire spec rendered from [`FRAMES`].
+pub fn render_spec() -> String {
+    let mut out = String::new();
+    writeln!(out, "# disrust wire format").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "Generated from `src/wire_layout.rs`. FEATURE_DIM = {FEATURE_DIM}, MAX_VECTORS_PER_REQUEST = {MAX_VECTORS_PER_REQUEST}."
+    )
+    .unwrap();
+    for frame in FRAMES {
+        writeln!(out).unwrap();
+        out.push_str(&frame.render());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FRAMES, Repeat};
+
+    #[test]
+    fn fields_are_contiguous_with_trailing_repeat_only() {
+        for frame in FRAMES {
+            let mut next_offset = 0;
+            for (i, field) in frame.fields.iter().enumerate() {
+                assert_eq!(
+                    field.offset, next_offset,
+                    "{}.{} is not contiguous",
+                    frame.name, field.name
+                );
+                if let Repeat::PerVector(_) = field.repeat {
+                    assert_eq!(
+                        i,
+                        frame.fields.len() - 1,
+                        "{}.{} repeats but is not last",
+                        frame.name,
+                        field.name
+                    );
+                }
+                next_offset += field.len(0);
+            }
+            assert_eq!(frame.header_bytes(), next_offset);
+        }
+    }
+
+    #[test]
+    fn field_accessors_round_trip() {
+        let mut frame = [0u8; 4];
+        super::OVERLOAD_REASON.write_u8(&mut frame, 1);
+        super::OVERLOAD_RETRY_AFTER_MS.write_u16(&mut frame, 0x0102);
+        assert_eq!(frame, [0, 1, 0x02, 0x01]);
+        assert_eq!(super::OVERLOAD_RETRY_AFTER_MS.read_u16(&frame), 0x0102);
+
+        let mut header = [0u8; 4];
+        super::REQUEST_NUM_VECTORS.write_u32(&mut header, 7);
+        assert_eq!(header, [7, 0, 0, 0]);
+        assert_eq!(super::REQUEST_NUM_VECTORS.read_u32(&header), 7);
+
+        let mut prefix = [0u8; 8];
+        super::REQUEST_ID_REQUEST_ID.write_u64(&mut prefix, 0x0102_0304_0506_0708);
+        assert_eq!(prefix, [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(
+            super::REQUEST_ID_REQUEST_ID.read_u64(&prefix),
+            0x0102_0304_0506_0708
+        );
+    }
+}
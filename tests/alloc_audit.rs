@@ -0,0 +1,225 @@
+//! Hot-path allocation audit, enforced when built with `--features alloc-audit`.
+//!
+//! Installs the counting allocator and runs each steady-state stage of the pipeline without
+//! io_uring, after a warm-up that lets buffers reach their working size: request parsing into
+//! the request ring, response framing and iovec gathering, and both ends together around an
+//! inference thread running the native tree backend, whose batch loop is audited too. Fails,
+//! listing every stage, when one allocates at all:
+//!
+//! ```text
+//! cargo test --features alloc-audit --test alloc_audit
+//! ```
+#![cfg(all(feature = "alloc-audit", target_os = "linux"))]
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use disruptor::{BusySpin, build_multi_producer, build_single_producer};
+
+use disrust::alloc_audit::{self, AllocCount, CountingAllocator};
+use disrust::buffer_pool::BufferPool;
+use disrust::config::SLAB_CAPACITY;
+use disrust::connection_id::ConnectionRef;
+use disrust::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use disrust::pipeline::connection_registry::ConnectionRegistry;
+use disrust::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use disrust::pipeline::inference::InferenceConsumer;
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseReady, ResponseRouter};
+use disrust::request_flow;
+use disrust::ring_types::InferenceEvent;
+use disrust::server::WritePath;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Requests parsed from one buffer.
+const BATCH: usize = 256;
+/// Responses queued on a connection between writes: one full write, which is what a
+/// connection keeps spare frames for. A connection whose writes fall further behind allocates
+/// frames for the excess.
+const WRITE_BATCH: usize = 64;
+/// Batches run before counting, for buffers to grow to their working size.
+const WARMUP_BATCHES: usize = 50;
+/// Batches counted; any allocation in them fails the stage.
+const BATCHES: usize = 500;
+
+const MODEL: &str = "\
+booster[0]:
+0:[f0<0.5] yes=1,no=2,missing=1
+\t1:[f3<0.25] yes=3,no=4,missing=3
+\t\t3:leaf=0.1
+\t\t4:leaf=0.2
+\t2:[f7<0.75] yes=5,no=6,missing=5
+\t\t5:leaf=-0.1
+\t\t6:leaf=0.3
+";
+
+/// What `batch` allocates over [`BATCHES`] runs after [`WARMUP_BATCHES`], on this thread and
+/// any audited worker.
+fn allocated_by(mut batch: impl FnMut()) -> AllocCount {
+    for _ in 0..WARMUP_BATCHES {
+        batch();
+    }
+    let before = alloc_audit::count();
+    for _ in 0..BATCHES {
+        alloc_audit::audited(&mut batch);
+    }
+    alloc_audit::count().since(before)
+}
+
+fn request_batch() -> Vec<u8> {
+    common::one_request_bytes(1, &[0.5; FEATURE_DIM]).repeat(BATCH)
+}
+
+/// Parsing single-vector requests and publishing them to the request ring.
+fn parse_allocations() -> AllocCount {
+    const RING_SIZE: usize = 4096;
+    let builder = build_single_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(RING_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+    let buf = request_batch();
+    let conn = ConnectionRef::new(0, 0, 1);
+    let mut request_seq = 0;
+
+    allocated_by(|| {
+        let outcome = request_flow::process_requests_from_buffer(
+            &buf,
+            &mut producer,
+            &mut allocator,
+            conn,
+            &mut request_seq,
+        )
+        .expect("well-formed requests");
+        assert_eq!(outcome.num_published, BATCH);
+        while let Ok(mut guard) = poller.poll() {
+            for _ in &mut guard {}
+        }
+    })
+}
+
+/// Framing single-vector responses, gathering them into writes and completing them.
+fn write_allocations() -> AllocCount {
+    let conn = ConnectionRef::new(0, 0, 1);
+    let mut path = WritePath::new(conn);
+    let mut batch = vec![ResponseReady::new(conn, 0, 0, &[0.5]); WRITE_BATCH];
+    let mut request_seq = 0;
+
+    allocated_by(|| {
+        for response in &mut batch {
+            response.request_seq = request_seq;
+            request_seq += 1;
+            path.deliver(response);
+        }
+        drain_writes(&mut path);
+    })
+}
+
+/// Write everything queued on `path`, the kernel taking every byte offered.
+fn drain_writes(path: &mut WritePath) {
+    loop {
+        let iovecs = path.gather();
+        if iovecs.is_empty() {
+            return;
+        }
+        let written = iovecs.iter().map(|iov| iov.iov_len).sum();
+        path.complete(written);
+    }
+}
+
+/// Request bytes to written response bytes, through an audited inference thread scoring them
+/// with the native tree backend.
+fn pipeline_allocations() -> AllocCount {
+    const RING_SIZE: usize = 4096;
+    let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (submission_poller, builder) = builder.event_poller();
+    let (completion_poller, builder) = builder.and_then().event_poller();
+    let mut producer = builder.build();
+
+    let model = GbdtModel::parse(MODEL, FEATURE_DIM).expect("valid model");
+    let response_queue = Arc::new(ResponseQueue::new(RING_SIZE));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let conn = registry.open(0, 0, -1);
+    let stop = Arc::new(AtomicBool::new(false));
+    let inference = InferenceConsumer::new(
+        submission_poller,
+        completion_poller,
+        GbdtBackend::new(model),
+        Arc::new(ResponseRouter::from(vec![Arc::clone(&response_queue)])),
+        Arc::clone(&registry),
+        BATCH,
+        Duration::from_micros(50),
+    );
+    let handle = thread::Builder::new()
+        .name("audit-inference".into())
+        .spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                alloc_audit::audit_this_thread(true);
+                inference.run_until(stop);
+            }
+        })
+        .expect("failed to spawn inference thread");
+
+    let pool = BufferPool::leak_new(RING_SIZE * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+    let buf = request_batch();
+    let mut path = WritePath::new(conn);
+    let mut request_seq = 0;
+
+    let allocated = allocated_by(|| {
+        request_flow::process_requests_from_buffer(
+            &buf,
+            &mut producer,
+            &mut allocator,
+            conn,
+            &mut request_seq,
+        )
+        .expect("well-formed requests");
+        let mut answered = 0;
+        while answered < BATCH {
+            match response_queue.pop() {
+                Some(response) => {
+                    path.deliver(&response);
+                    answered += 1;
+                    if answered % WRITE_BATCH == 0 {
+                        drain_writes(&mut path);
+                    }
+                }
+                None => std::hint::spin_loop(),
+            }
+        }
+    });
+
+    registry.mark_read_closed(conn, request_seq);
+    stop.store(true, Ordering::Relaxed);
+    handle.join().expect("inference thread panicked");
+    allocated
+}
+
+#[test]
+fn steady_state_hot_path_does_not_allocate() {
+    common::init_factory_pool();
+
+    let failures: Vec<String> = [
+        ("parse", parse_allocations()),
+        ("write", write_allocations()),
+        ("pipeline", pipeline_allocations()),
+    ]
+    .into_iter()
+    .filter_map(|(stage, allocated)| {
+        eprintln!("  {stage}: {allocated} over {BATCHES} batches");
+        (allocated != AllocCount::default()).then(|| format!("{stage} made {allocated}"))
+    })
+    .collect();
+    assert!(
+        failures.is_empty(),
+        "hot path allocated in steady state:\n  {}",
+        failures.join("\n  ")
+    );
+}
@@ -1,16 +1,15 @@
 #![allow(dead_code)]
 
-use std::os::unix::io::RawFd;
-
 use disrust::buffer_pool::{BufferPool, set_factory_pool};
 use disrust::constants::FEATURE_DIM;
+use disrust::notify::NotifyFd;
 
 pub fn init_factory_pool() {
     let _ = set_factory_pool(BufferPool::new_boxed(1));
 }
 
-pub fn create_eventfd() -> RawFd {
-    unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) }
+pub fn create_notify_fd() -> NotifyFd {
+    NotifyFd::new().expect("notify fd creation failed")
 }
 
 /// Build a byte buffer for one request: [u32 num_vectors][f32 * num_vectors * FEATURE_DIM].
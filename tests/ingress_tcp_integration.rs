@@ -1,5 +1,7 @@
 //! Integration test: TCP -> ingress io thread -> request ring.
 
+#![cfg(target_os = "linux")]
+
 mod common;
 
 use std::io::Write;
@@ -16,11 +18,14 @@ use socket2::{Domain, Protocol, Socket, Type};
 use disrust::buffer_pool::BufferPool;
 use disrust::config::{GPU_DISRUPTOR_SIZE, SLAB_CAPACITY};
 use disrust::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use disrust::notify::{self, MsgRingNotifier};
 use disrust::pipeline::connection_registry::ConnectionRegistry;
 use disrust::pipeline::response_queue::{ResponseQueue, ResponseReady};
 use disrust::protocol;
+use disrust::request_flow::MalformedPolicy;
 use disrust::ring_types::InferenceEvent;
-use disrust::server::IngressThread;
+use disrust::server::replay::ReplaySessions;
+use disrust::server::{IngressThread, IoBackend, IoThreadControl, IoThreadState, SoftLimits};
 
 fn create_listener() -> (std::os::fd::RawFd, SocketAddr) {
     let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))
@@ -158,7 +163,7 @@ fn ingress_reuses_retired_slot_after_queued_parse_error() {
         listen_fd,
         producer,
         allocator,
-        response_queue,
+        Arc::clone(&response_queue),
         publish_gate,
         Arc::clone(&registry),
     );
@@ -195,6 +200,26 @@ fn ingress_reuses_retired_slot_after_queued_parse_error() {
     assert_eq!(events_a[0].2, 0);
     assert_eq!(events_a[1].2, 1);
 
+    // The malformed request is answered with a parse error frame after the two responses.
+    let sum = req_features.iter().copied().sum::<f32>();
+    for request_seq in 0..2 {
+        response_queue.push(ResponseReady::new(conn_a, request_seq, 1, &[sum]));
+    }
+    stream_a
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut frames = Vec::new();
+    stream_a
+        .read_to_end(&mut frames)
+        .expect("read responses before close failed");
+    let error_at = 2 * protocol::response_size(1);
+    assert_eq!(frames.len(), error_at + protocol::PARSE_ERROR_FRAME_BYTES);
+    assert_eq!(protocol::decode_response(&frames[..error_at]), [sum]);
+    let error = protocol::decode_parse_error(&frames[error_at..]).expect("parse error frame");
+    assert_eq!(error.field, protocol::RequestField::NumVectors);
+    assert_eq!(error.value, 0);
+    assert_eq!(error.offset, 2 * req.len() as u64);
+
     drop(stream_a);
 
     let mut stream_b = TcpStream::connect(addr).expect("second connect failed");
@@ -222,6 +247,220 @@ fn ingress_reuses_retired_slot_after_queued_parse_error() {
     );
 }
 
+#[test]
+fn ingress_skips_malformed_requests_and_keeps_the_connection() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool_capacity = GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+    let pool = BufferPool::leak_new(pool_capacity);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+    let limits = Arc::new(SoftLimits::default());
+    limits.set_malformed_requests(MalformedPolicy::Skip);
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_soft_limits(limits);
+    thread::Builder::new()
+        .name("ingress-skip-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    // A request, an empty one, an oversized one sent in two writes, then another request.
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32 + 1.0).collect();
+    let req = common::one_request_bytes(1, &features);
+    let oversized_vectors = MAX_VECTORS_PER_REQUEST as u32 * 4;
+    let mut oversized = oversized_vectors.to_le_bytes().to_vec();
+    oversized.resize(protocol::request_size(oversized_vectors as usize), 0x3f);
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream.set_nodelay(true).unwrap();
+    stream.write_all(&req).unwrap();
+    stream.write_all(&0u32.to_le_bytes()).unwrap();
+    stream.write_all(&oversized[..oversized.len() / 2]).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(&oversized[oversized.len() / 2..]).unwrap();
+    stream.write_all(&req).unwrap();
+
+    let events = collect_events(&mut event_poller, 2);
+    assert_eq!(events.len(), 2, "both well-formed requests are published");
+    assert_eq!((events[0].2, events[1].2), (0, 3));
+    assert_eq!(events[1].3, features, "the stream resynced after the skip");
+
+    let sum = features.iter().copied().sum::<f32>();
+    for (conn, _, request_seq, _) in &events {
+        response_queue.push(ResponseReady::new(*conn, *request_seq, 1, &[sum]));
+    }
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let response_len = protocol::response_size(1);
+    let mut frames = vec![0u8; 2 * response_len + 2 * protocol::PARSE_ERROR_FRAME_BYTES];
+    stream
+        .read_exact(&mut frames)
+        .expect("read all four frames");
+    let (first, rest) = frames.split_at(response_len);
+    let (errors, last) = rest.split_at(2 * protocol::PARSE_ERROR_FRAME_BYTES);
+    assert_eq!(protocol::decode_response(first), [sum]);
+    assert_eq!(protocol::decode_response(last), [sum]);
+    let empty = protocol::decode_parse_error(errors).expect("parse error frame");
+    assert_eq!((empty.value, empty.offset), (0, req.len() as u64));
+    let too_many = protocol::decode_parse_error(&errors[protocol::PARSE_ERROR_FRAME_BYTES..])
+        .expect("parse error frame");
+    assert_eq!(too_many.field, protocol::RequestField::NumVectors);
+    assert_eq!(too_many.value, oversized_vectors);
+    assert_eq!(too_many.offset, req.len() as u64 + 4);
+
+    // The connection is still open and serving.
+    stream.write_all(&req).unwrap();
+    let events = collect_events(&mut event_poller, 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].2, 4);
+}
+
+#[test]
+fn ingress_rejects_requests_over_its_max_vectors() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool_capacity = GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+    let pool = BufferPool::leak_new(pool_capacity);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+    let limits = Arc::new(SoftLimits::default());
+    limits.set_malformed_requests(MalformedPolicy::Skip);
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_soft_limits(limits)
+    .with_max_vectors(2);
+    thread::Builder::new()
+        .name("ingress-max-vectors-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    // Two vectors are within the limit, three are not, however far below the protocol's.
+    let features: Vec<f32> = (0..3 * FEATURE_DIM).map(|i| i as f32).collect();
+    let two = common::one_request_bytes(2, &features);
+    let three = common::one_request_bytes(3, &features);
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream.write_all(&two).unwrap();
+    stream.write_all(&three).unwrap();
+    stream.write_all(&two).unwrap();
+
+    let events = collect_events(&mut event_poller, 2);
+    assert_eq!(events.len(), 2);
+    assert_eq!((events[0].2, events[1].2), (0, 2));
+    for (conn, num_vectors, request_seq, _) in &events {
+        assert_eq!(*num_vectors, 2);
+        response_queue.push(ResponseReady::new(*conn, *request_seq, 2, &[1.0, 2.0]));
+    }
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let response_len = protocol::response_size(2);
+    let mut frames = vec![0u8; 2 * response_len + protocol::PARSE_ERROR_FRAME_BYTES];
+    stream
+        .read_exact(&mut frames)
+        .expect("read all three frames");
+    let error = protocol::decode_parse_error(&frames[response_len..]).expect("parse error frame");
+    assert_eq!(error.field, protocol::RequestField::NumVectors);
+    assert_eq!((error.value, error.offset), (3, two.len() as u64));
+    assert_eq!(
+        protocol::decode_response(&frames[response_len + protocol::PARSE_ERROR_FRAME_BYTES..]),
+        [1.0, 2.0]
+    );
+}
+
+#[test]
+fn ingress_answers_fixed_point_requests_with_fixed_point_scores() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_fixed_point_scale(protocol::BASIS_POINTS);
+    thread::Builder::new()
+        .name("ingress-fixed-point-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    // Fixed-point and plain requests mixed on one connection.
+    let features = vec![0.5f32; 2 * FEATURE_DIM];
+    let mut fixed_point = common::one_request_bytes(2, &features);
+    fixed_point[..4].copy_from_slice(&(2 | protocol::FIXED_POINT_REQUEST_FLAG).to_le_bytes());
+    let plain = common::one_request_bytes(1, &features);
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream.write_all(&fixed_point).unwrap();
+    stream.write_all(&plain).unwrap();
+    stream.write_all(&fixed_point).unwrap();
+
+    let events = collect_events(&mut event_poller, 3);
+    assert_eq!(events.len(), 3);
+    let scores: [&[f32]; 3] = [&[0.25, 1.5], &[0.75], &[f32::NAN, 7.0]];
+    for ((conn, num_vectors, request_seq, _), scores) in events.iter().zip(scores) {
+        assert_eq!(*num_vectors as usize, scores.len());
+        response_queue.push(ResponseReady::new(*conn, *request_seq, 2, scores));
+    }
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let fixed_point_len = protocol::fixed_point_response_size(2);
+    let mut frames = vec![0u8; 2 * fixed_point_len + protocol::response_size(1)];
+    stream
+        .read_exact(&mut frames)
+        .expect("read all three frames");
+    let (first, rest) = frames.split_at(fixed_point_len);
+    let (second, third) = rest.split_at(protocol::response_size(1));
+    assert_eq!(first, [2, 0xc4, 0x09, 0x98, 0x3a]);
+    assert_eq!(protocol::decode_response(second), [0.75]);
+    // NaN is sent as 0, and scores past the largest u16 saturate.
+    assert_eq!(third, [2, 0, 0, 0xff, 0xff]);
+}
+
 #[test]
 fn ingress_submits_writes_while_queued_parse_work_remains() {
     common::init_factory_pool();
@@ -278,7 +517,7 @@ fn ingress_submits_writes_while_queued_parse_work_remains() {
     let expected_sum = req1_features.iter().copied().sum::<f32>();
     let mut expected = [0u8; 5];
     protocol::encode_response(&[expected_sum], &mut expected);
-    response_queue.push(ResponseReady::encode(conn, 0, 1, &[expected_sum]));
+    response_queue.push(ResponseReady::new(conn, 0, 1, &[expected_sum]));
 
     let mut response = [0u8; 5];
     match stream.read_exact(&mut response) {
@@ -361,3 +600,641 @@ fn ingress_accepts_many_simultaneous_connections() {
         assert_eq!(feats, &features);
     }
 }
+
+#[test]
+fn ingress_reads_requests_through_a_provided_buffer_ring() {
+    // One provided buffer for eight connections, so most reads find the ring empty and fall
+    // back to a buffer of their own; each request also arrives split across two writes.
+    const N: usize = 8;
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool_capacity = GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+    let pool = BufferPool::leak_new(pool_capacity);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        response_queue,
+        publish_gate,
+        registry,
+    )
+    .with_read_buffer_ring(1);
+    thread::Builder::new()
+        .name("ingress-buffer-ring-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let features: Vec<f32> = (0..2 * FEATURE_DIM).map(|i| i as f32).collect();
+    let req = common::one_request_bytes(2, &features);
+    let (head, tail) = req.split_at(req.len() / 2);
+    let mut streams: Vec<_> = (0..N)
+        .map(|_| {
+            let mut stream = TcpStream::connect(addr).expect("connect failed");
+            stream.set_nodelay(true).unwrap();
+            stream.write_all(head).expect("write failed");
+            stream
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(50));
+    for stream in &mut streams {
+        stream.write_all(tail).expect("write failed");
+        stream.write_all(&req).expect("write failed");
+    }
+
+    let events = collect_events(&mut event_poller, 2 * N);
+    assert_eq!(events.len(), 2 * N, "every request must be published");
+    for (_, num_vectors, _, feats) in &events {
+        assert_eq!(*num_vectors, 2);
+        assert_eq!(feats, &features);
+    }
+    let mut per_conn: Vec<_> = events
+        .iter()
+        .map(|(conn, _, request_seq, _)| (conn.conn_id, *request_seq))
+        .collect();
+    per_conn.sort_unstable();
+    per_conn.dedup();
+    assert_eq!(per_conn.len(), 2 * N, "two requests on each connection");
+}
+
+#[test]
+fn ingress_drain_stops_accepts_and_waits_for_connections() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool_capacity = GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+    let pool = BufferPool::leak_new(pool_capacity);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let control = Arc::new(IoThreadControl::new());
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        response_queue,
+        publish_gate,
+        registry,
+    )
+    .with_control(Arc::clone(&control));
+    thread::Builder::new()
+        .name("ingress-drain-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let req = common::one_request_bytes(1, &features);
+
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream.write_all(&req).expect("write failed");
+    assert_eq!(collect_events(&mut event_poller, 1).len(), 1);
+
+    assert!(control.request_drain());
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while TcpStream::connect(addr).is_ok() {
+        assert!(
+            Instant::now() < deadline,
+            "listener still accepting after drain"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(control.state(), IoThreadState::Draining);
+    assert_eq!(control.connections(), 1);
+
+    // The existing connection keeps being served while draining.
+    stream.write_all(&req).expect("write while draining failed");
+    assert_eq!(collect_events(&mut event_poller, 1).len(), 1);
+    drop(stream);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while control.state() != IoThreadState::Drained {
+        assert!(
+            Instant::now() < deadline,
+            "io thread never reported drained"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(control.connections(), 0);
+}
+
+#[test]
+fn ingress_run_returns_after_remove_drains() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (_event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(MAX_VECTORS_PER_REQUEST * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let control = Arc::new(IoThreadControl::new());
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        response_queue,
+        publish_gate,
+        registry,
+    )
+    .with_control(Arc::clone(&control));
+    let handle = thread::Builder::new()
+        .name("ingress-remove-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let stream = TcpStream::connect(addr).expect("connect failed");
+    assert!(control.request_remove());
+    thread::sleep(Duration::from_millis(50));
+    assert!(!handle.is_finished(), "run returned with a live connection");
+
+    drop(stream);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !handle.is_finished() {
+        assert!(
+            Instant::now() < deadline,
+            "run did not return after removal"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    handle.join().expect("ingress thread panicked");
+    assert_eq!(control.state(), IoThreadState::Drained);
+}
+
+#[test]
+fn ingress_shutdown_answers_in_flight_requests_then_closes() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let control = Arc::new(IoThreadControl::new());
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_control(Arc::clone(&control));
+    let handle = thread::Builder::new()
+        .name("ingress-shutdown-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    // Connected first, so it is accepted by the time the request below is published.
+    let mut idle = TcpStream::connect(addr).expect("connect failed");
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream
+        .write_all(&common::one_request_bytes(1, &features))
+        .expect("write failed");
+    let events = collect_events(&mut event_poller, 1);
+    assert_eq!(events.len(), 1);
+
+    assert!(control.request_shutdown());
+    thread::sleep(Duration::from_millis(50));
+    assert!(
+        !handle.is_finished(),
+        "run returned with a request unanswered"
+    );
+    // The idle connection had nothing in flight, so it is closed right away.
+    idle.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    assert_eq!(idle.read(&mut [0u8; 1]).expect("idle read failed"), 0);
+
+    let (conn, _, request_seq, _) = events[0];
+    response_queue.push(ResponseReady::new(conn, request_seq, 1, &[7.0]));
+
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut received = Vec::new();
+    stream
+        .read_to_end(&mut received)
+        .expect("read until close failed");
+    assert_eq!(protocol::decode_response(&received), [7.0]);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !handle.is_finished() {
+        assert!(
+            Instant::now() < deadline,
+            "run did not return after shutdown"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    handle.join().expect("ingress thread panicked");
+    assert_eq!(control.state(), IoThreadState::Drained);
+}
+
+#[test]
+fn ingress_closes_idle_connections_but_not_ones_awaiting_responses() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool_capacity = GPU_DISRUPTOR_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM;
+    let pool = BufferPool::leak_new(pool_capacity);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let control = Arc::new(IoThreadControl::new());
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        response_queue,
+        publish_gate,
+        registry,
+    )
+    .with_control(Arc::clone(&control))
+    .with_idle_timeout(Duration::from_millis(200));
+    thread::Builder::new()
+        .name("ingress-idle-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let mut waiting = TcpStream::connect(addr).expect("connect failed");
+    waiting
+        .write_all(&common::one_request_bytes(1, &features))
+        .expect("write failed");
+    assert_eq!(collect_events(&mut event_poller, 1).len(), 1);
+    let mut idle = TcpStream::connect(addr).expect("connect failed");
+
+    idle.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let started = Instant::now();
+    let mut byte = [0u8; 1];
+    assert_eq!(idle.read(&mut byte).expect("idle read failed"), 0);
+    assert!(started.elapsed() >= Duration::from_millis(150));
+
+    // The request published above is never answered, so its connection stays open.
+    waiting
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    let err = waiting
+        .read(&mut byte)
+        .expect_err("awaiting connection closed");
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+    assert_eq!(control.connections(), 1);
+}
+
+/// Send a replay hello and read the reply, retrying while the session is busy. Returns the
+/// session token and the `request_seq` the connection's first request takes.
+fn resume_replay_session(addr: SocketAddr, token: u64, resume_seq: u64) -> (TcpStream, u64, u64) {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let mut stream = TcpStream::connect(addr).expect("connect failed");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut hello = [0u8; protocol::REPLAY_HELLO_BYTES];
+        protocol::encode_replay_hello(token, resume_seq, &mut hello);
+        stream.write_all(&hello).expect("hello write failed");
+        let mut reply = [0u8; protocol::REPLAY_REPLY_BYTES];
+        stream.read_exact(&mut reply).expect("reply read failed");
+        match protocol::decode_replay_reply(&reply) {
+            Some((protocol::ReplayStatus::Resumed, token, next_request_seq)) => {
+                return (stream, token, next_request_seq);
+            }
+            Some((protocol::ReplayStatus::Busy, ..)) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            other => panic!("hello refused: {other:?}"),
+        }
+    }
+}
+
+/// Read one seq-prefixed single-vector response, returning its `request_seq` and results.
+fn read_seq_prefixed_response(stream: &mut TcpStream) -> (u32, Vec<f32>) {
+    let mut frame = vec![0u8; protocol::SEQ_PREFIX_BYTES + protocol::response_size(1)];
+    stream.read_exact(&mut frame).expect("response read failed");
+    (
+        protocol::decode_seq_prefix(&frame),
+        protocol::decode_response(&frame[protocol::SEQ_PREFIX_BYTES..]),
+    )
+}
+
+#[test]
+fn ingress_replays_responses_missed_across_a_reconnect() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_request_seq_echo()
+    .with_replay_sessions(Arc::new(ReplaySessions::new(4)));
+    thread::Builder::new()
+        .name("ingress-replay-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let (mut first, token, next_request_seq) = resume_replay_session(addr, 0, 0);
+    assert_ne!(token, 0, "starting a session issues its token");
+    assert_eq!(next_request_seq, 0);
+    let mut requests = common::one_request_bytes(1, &features);
+    requests.extend(common::one_request_bytes(1, &features));
+    first.write_all(&requests).expect("write failed");
+    let events = collect_events(&mut event_poller, 2);
+    assert_eq!(events.len(), 2);
+    assert_eq!((events[0].2, events[1].2), (0, 1));
+
+    response_queue.push(ResponseReady::new(events[0].0, 0, 1, &[1.0]));
+    assert_eq!(read_seq_prefixed_response(&mut first), (0, vec![1.0]));
+
+    // The client drops the connection before the second response goes out.
+    drop(first);
+    thread::sleep(Duration::from_millis(50));
+    response_queue.push(ResponseReady::new(events[1].0, 1, 1, &[2.0]));
+
+    let (mut second, resumed_token, next_request_seq) = resume_replay_session(addr, token, 1);
+    assert_eq!(resumed_token, token);
+    assert_eq!(next_request_seq, 2, "numbering runs on across the session");
+    assert_eq!(read_seq_prefixed_response(&mut second), (1, vec![2.0]));
+
+    second
+        .write_all(&common::one_request_bytes(1, &features))
+        .expect("write failed");
+    let events = collect_events(&mut event_poller, 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].2, 2);
+    response_queue.push(ResponseReady::new(events[0].0, 2, 1, &[3.0]));
+    assert_eq!(read_seq_prefixed_response(&mut second), (2, vec![3.0]));
+
+    // Another hello while the session's connection is open is turned away.
+    let mut hello = [0u8; protocol::REPLAY_HELLO_BYTES];
+    protocol::encode_replay_hello(token, 9, &mut hello);
+    let mut late = TcpStream::connect(addr).expect("connect failed");
+    late.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    late.write_all(&hello).expect("hello write failed");
+    let mut reply = [0u8; protocol::REPLAY_REPLY_BYTES];
+    late.read_exact(&mut reply).expect("reply read failed");
+    assert_eq!(
+        protocol::decode_replay_reply(&reply),
+        Some((protocol::ReplayStatus::Busy, 0, 0)),
+        "the second connection still holds the session"
+    );
+}
+
+#[test]
+fn ingress_on_epoll_answers_pipelined_requests_and_shuts_down() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let control = Arc::new(IoThreadControl::new());
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_request_seq_echo()
+    .with_control(Arc::clone(&control))
+    .with_io_backend(IoBackend::Epoll);
+    let handle = thread::Builder::new()
+        .name("ingress-epoll-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    const REQUESTS: usize = 300;
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let requests = common::one_request_bytes(1, &features).repeat(REQUESTS);
+    // Split mid-request, so one request spans two reads.
+    stream.write_all(&requests[..7]).expect("write failed");
+    thread::sleep(Duration::from_millis(20));
+    stream.write_all(&requests[7..]).expect("write failed");
+    let events = collect_events(&mut event_poller, REQUESTS);
+    assert_eq!(events.len(), REQUESTS);
+
+    // Queued before the client reads any, so several frames share each write.
+    for (conn, _, request_seq, _) in &events {
+        response_queue.push(ResponseReady::new(
+            *conn,
+            *request_seq,
+            1,
+            &[*request_seq as f32],
+        ));
+    }
+    for request_seq in 0..REQUESTS as u32 {
+        assert_eq!(
+            read_seq_prefixed_response(&mut stream),
+            (request_seq, vec![request_seq as f32])
+        );
+    }
+
+    assert!(control.request_shutdown());
+    assert_eq!(stream.read(&mut [0u8; 1]).expect("read failed"), 0);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !handle.is_finished() {
+        assert!(
+            Instant::now() < deadline,
+            "run did not return after shutdown"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    handle.join().expect("ingress thread panicked");
+    assert_eq!(control.state(), IoThreadState::Drained);
+}
+
+#[test]
+fn ingress_reads_and_writes_through_fixed_buffers() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let response_queue = Arc::new(ResponseQueue::new(SLAB_CAPACITY * 2));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_request_seq_echo()
+    .with_fixed_buffers();
+    thread::Builder::new()
+        .name("ingress-fixed-buffers-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    // More responses than one write holds, so writes continue from the registered buffer.
+    const REQUESTS: usize = 300;
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let requests = common::one_request_bytes(1, &features).repeat(REQUESTS);
+    stream.write_all(&requests[..7]).expect("write failed");
+    thread::sleep(Duration::from_millis(20));
+    stream.write_all(&requests[7..]).expect("write failed");
+    let events = collect_events(&mut event_poller, REQUESTS);
+    assert_eq!(events.len(), REQUESTS);
+    for (_, _, _, feats) in &events {
+        assert_eq!(feats, &features);
+    }
+
+    for (conn, _, request_seq, _) in &events {
+        response_queue.push(ResponseReady::new(
+            *conn,
+            *request_seq,
+            1,
+            &[*request_seq as f32],
+        ));
+    }
+    for request_seq in 0..REQUESTS as u32 {
+        assert_eq!(
+            read_seq_prefixed_response(&mut stream),
+            (request_seq, vec![request_seq as f32])
+        );
+    }
+}
+
+#[test]
+fn ingress_wakes_on_responses_posted_to_its_ring() {
+    if !notify::msg_ring_supported() {
+        return;
+    }
+    common::init_factory_pool();
+
+    let builder = build_single_producer(GPU_DISRUPTOR_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut event_poller, builder) = builder.event_poller();
+    let producer = builder.build();
+
+    let pool = BufferPool::leak_new(GPU_DISRUPTOR_SIZE * FEATURE_DIM);
+    let allocator = pool.allocator();
+    let notifier = MsgRingNotifier::new().expect("MSG_RING sender");
+    let response_queue = Arc::new(ResponseQueue::with_notifier(
+        SLAB_CAPACITY * 2,
+        Arc::new(notifier),
+    ));
+    let publish_gate = Arc::new(Mutex::new(()));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let (listen_fd, addr) = create_listener();
+
+    let ingress = IngressThread::new(
+        0,
+        listen_fd,
+        producer,
+        allocator,
+        Arc::clone(&response_queue),
+        publish_gate,
+        registry,
+    )
+    .with_request_seq_echo();
+    thread::Builder::new()
+        .name("ingress-msg-ring-test".into())
+        .spawn(move || ingress.run())
+        .expect("failed to spawn ingress thread");
+
+    const REQUESTS: usize = 5;
+    let features = vec![0.5; FEATURE_DIM];
+    let mut stream = TcpStream::connect(addr).expect("connect failed");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let requests = common::one_request_bytes(1, &features).repeat(REQUESTS);
+    stream.write_all(&requests).expect("write failed");
+    let events = collect_events(&mut event_poller, REQUESTS);
+    assert_eq!(events.len(), REQUESTS);
+
+    // One at a time, each onto an empty queue, so every response needs its own wakeup.
+    for (conn, _, request_seq, _) in &events {
+        thread::sleep(Duration::from_millis(10));
+        response_queue.push(ResponseReady::new(
+            *conn,
+            *request_seq,
+            1,
+            &[*request_seq as f32],
+        ));
+        assert_eq!(
+            read_seq_prefixed_response(&mut stream),
+            (*request_seq as u32, vec![*request_seq as f32])
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Swapping the inference thread's backend while it serves: requests answered before the swap
+//! are scored by the old model, requests after it by the new one, on the same connection.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, build_multi_producer};
+
+use disrust::buffer_pool::BufferPool;
+use disrust::config::SLAB_CAPACITY;
+use disrust::constants::FEATURE_DIM;
+use disrust::pipeline::connection_registry::ConnectionRegistry;
+use disrust::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use disrust::pipeline::inference::{InferenceConsumer, ModelSwap};
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseRouter};
+use disrust::request_flow;
+use disrust::ring_types::InferenceEvent;
+
+const RING_SIZE: usize = 64;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn model(leaf: f32) -> GbdtBackend {
+    let text =
+        format!("booster[0]:\n0:[f0<0.5] yes=1,no=2,missing=1\n\t1:leaf=0\n\t2:leaf={leaf}\n");
+    GbdtBackend::new(GbdtModel::parse(&text, FEATURE_DIM).expect("valid model"))
+}
+
+#[test]
+fn requests_after_a_swap_are_scored_by_the_new_model() {
+    common::init_factory_pool();
+    let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (submission_poller, builder) = builder.event_poller();
+    let (completion_poller, builder) = builder.and_then().event_poller();
+    let mut producer = builder.build();
+
+    let queue = Arc::new(ResponseQueue::new(RING_SIZE));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let conn = registry.open(0, 0, -1);
+    // A rendezvous channel: once a send returns, the inference thread holds the swap.
+    let (swaps, swapped) = mpsc::sync_channel(0);
+    let stop = Arc::new(AtomicBool::new(false));
+    let consumer = InferenceConsumer::new(
+        submission_poller,
+        completion_poller,
+        model(0.25),
+        Arc::new(ResponseRouter::from(vec![Arc::clone(&queue)])),
+        registry,
+        RING_SIZE,
+        Duration::from_micros(50),
+    )
+    .with_model_swaps(swapped);
+    let inference = thread::spawn({
+        let stop = Arc::clone(&stop);
+        move || consumer.run_until(stop)
+    });
+
+    let mut allocator = BufferPool::leak_new(RING_SIZE * FEATURE_DIM).allocator();
+    let mut request_seq = 0u64;
+    let mut round_trip = |count: usize| {
+        let buf = common::one_request_bytes(1, &[1.0; FEATURE_DIM]).repeat(count);
+        request_flow::process_requests_from_buffer(
+            &buf,
+            &mut producer,
+            &mut allocator,
+            conn,
+            &mut request_seq,
+        )
+        .expect("well-formed requests");
+        let deadline = Instant::now() + WAIT;
+        let mut scores = Vec::new();
+        while scores.len() < count {
+            match queue.pop() {
+                Some(response) => scores.extend_from_slice(response.results()),
+                None if Instant::now() < deadline => thread::yield_now(),
+                None => panic!("{} of {count} responses", scores.len()),
+            }
+        }
+        scores
+    };
+
+    assert_eq!(round_trip(8), [0.25; 8]);
+    swaps
+        .send(ModelSwap {
+            backend: model(0.5),
+            version: "v2".to_string(),
+        })
+        .expect("inference thread is running");
+    assert_eq!(round_trip(8), [0.5; 8]);
+    swaps
+        .send(ModelSwap {
+            backend: model(0.75),
+            version: "v3".to_string(),
+        })
+        .expect("inference thread is running");
+    assert_eq!(round_trip(RING_SIZE / 2), [0.75; RING_SIZE / 2]);
+
+    stop.store(true, Ordering::Relaxed);
+    inference.join().expect("inference thread panicked");
+}
@@ -0,0 +1,298 @@
+//! Hot-path performance budgets, enforced when built with `--features perf-budget`.
+//!
+//! Times each stage of the pipeline without io_uring: request parsing into the request ring,
+//! response framing and iovec gathering, and both ends together around an inference thread
+//! running the native tree backend. Fails, listing every stage, when one is more than
+//! `tolerance_pct` worse than its baseline. Baselines are read from `tests/perf_budgets.conf`,
+//! or from the file `DISRUST_PERF_BUDGETS` names, so a nightly job can keep ones measured on its
+//! own runner. They are release-build numbers:
+//!
+//! ```text
+//! cargo test --release --features perf-budget --test perf_budget
+//! ```
+#![cfg(all(feature = "perf-budget", target_os = "linux"))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, build_multi_producer, build_single_producer};
+
+use disrust::buffer_pool::BufferPool;
+use disrust::config::SLAB_CAPACITY;
+use disrust::connection_id::ConnectionRef;
+use disrust::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use disrust::pipeline::connection_registry::ConnectionRegistry;
+use disrust::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use disrust::pipeline::inference::InferenceConsumer;
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseReady, ResponseRouter};
+use disrust::request_flow;
+use disrust::ring_types::InferenceEvent;
+use disrust::server::WritePath;
+
+/// Requests parsed from one buffer, and responses written in one batch.
+const BATCH: usize = 256;
+/// Batches per timed run; each stage reports its best of `RUNS` runs.
+const BATCHES_PER_RUN: usize = 2_000;
+/// Fewer for the whole pipeline, whose batches wait on the inference thread.
+const PIPELINE_BATCHES_PER_RUN: usize = 200;
+const RUNS: usize = 5;
+
+const MODEL: &str = "\
+booster[0]:
+0:[f0<0.5] yes=1,no=2,missing=1
+\t1:[f3<0.25] yes=3,no=4,missing=3
+\t\t3:leaf=0.1
+\t\t4:leaf=0.2
+\t2:[f7<0.75] yes=5,no=6,missing=5
+\t\t5:leaf=-0.1
+\t\t6:leaf=0.3
+booster[1]:
+0:[f12<0.5] yes=1,no=2,missing=2
+\t1:leaf=0.05
+\t2:[f15<0.5] yes=3,no=4,missing=3
+\t\t3:leaf=-0.2
+\t\t4:leaf=0.4
+";
+
+/// Baselines and how far past them a measurement may fall.
+struct Budgets {
+    path: PathBuf,
+    tolerance_pct: f64,
+    baselines: HashMap<String, f64>,
+}
+
+impl Budgets {
+    fn load() -> Self {
+        let path = std::env::var_os("DISRUST_PERF_BUDGETS").map_or_else(
+            || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/perf_budgets.conf"),
+            PathBuf::from,
+        );
+        let text =
+            std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        let mut baselines = HashMap::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{}: expected `key = value`: {line}", path.display()));
+            let value: f64 = value
+                .trim()
+                .parse()
+                .unwrap_or_else(|e| panic!("{}: {key}: {e}", path.display()));
+            baselines.insert(key.trim().to_string(), value);
+        }
+        let tolerance_pct = baselines
+            .remove("tolerance_pct")
+            .unwrap_or_else(|| panic!("{}: no tolerance_pct", path.display()));
+        Self {
+            path,
+            tolerance_pct,
+            baselines,
+        }
+    }
+
+    /// Compare `measured` with the baseline for `name`, returning the regression if it is past
+    /// the tolerance. Rates are better higher, everything else lower.
+    fn check(&self, name: &str, measured: f64, higher_is_better: bool) -> Option<String> {
+        let baseline = *self
+            .baselines
+            .get(name)
+            .unwrap_or_else(|| panic!("{}: no baseline for {name}", self.path.display()));
+        let worse_pct = if higher_is_better {
+            (baseline - measured) / baseline * 100.0
+        } else {
+            (measured - baseline) / baseline * 100.0
+        };
+        eprintln!("  {name}: {measured:.1} (baseline {baseline:.1}, regression {worse_pct:+.1}%)");
+        (worse_pct > self.tolerance_pct).then(|| {
+            format!(
+                "{name} regressed {worse_pct:.1}% (measured {measured:.1}, baseline {baseline:.1}, \
+                 tolerance {}%)",
+                self.tolerance_pct
+            )
+        })
+    }
+}
+
+/// The fastest of `RUNS` runs of `run`.
+fn best_of(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..RUNS).map(|_| run()).min().unwrap()
+}
+
+fn request_batch() -> Vec<u8> {
+    common::one_request_bytes(1, &[0.5; FEATURE_DIM]).repeat(BATCH)
+}
+
+/// Nanoseconds to parse one single-vector request and publish it to the request ring.
+fn parse_ns_per_request() -> f64 {
+    const RING_SIZE: usize = 65536;
+    let builder = build_single_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(RING_SIZE * MAX_VECTORS_PER_REQUEST * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+    let buf = request_batch();
+    let conn = ConnectionRef::new(0, 0, 1);
+    let mut request_seq = 0;
+
+    let elapsed = best_of(|| {
+        let start = Instant::now();
+        for _ in 0..BATCHES_PER_RUN {
+            let outcome = request_flow::process_requests_from_buffer(
+                black_box(&buf),
+                &mut producer,
+                &mut allocator,
+                conn,
+                &mut request_seq,
+            )
+            .expect("well-formed requests");
+            assert_eq!(outcome.num_published, BATCH);
+            while let Ok(mut guard) = poller.poll() {
+                for _ in &mut guard {}
+            }
+        }
+        start.elapsed()
+    });
+    elapsed.as_nanos() as f64 / (BATCHES_PER_RUN * BATCH) as f64
+}
+
+/// Nanoseconds to frame one single-vector response, gather it into a write, and complete it.
+fn write_ns_per_response() -> f64 {
+    let conn = ConnectionRef::new(0, 0, 1);
+    let mut path = WritePath::new(conn);
+    let mut batch = vec![ResponseReady::new(conn, 0, 0, &[0.5]); BATCH];
+    let mut request_seq = 0;
+
+    let elapsed = best_of(|| {
+        let start = Instant::now();
+        for _ in 0..BATCHES_PER_RUN {
+            for response in &mut batch {
+                response.request_seq = request_seq;
+                request_seq += 1;
+                path.deliver(response);
+            }
+            drain_writes(&mut path);
+        }
+        start.elapsed()
+    });
+    elapsed.as_nanos() as f64 / (BATCHES_PER_RUN * BATCH) as f64
+}
+
+/// Write everything queued on `path`, the kernel taking every byte offered.
+fn drain_writes(path: &mut WritePath) {
+    loop {
+        let iovecs = black_box(path.gather());
+        if iovecs.is_empty() {
+            return;
+        }
+        let written = iovecs.iter().map(|iov| iov.iov_len).sum();
+        path.complete(written);
+    }
+}
+
+/// Requests per second from request bytes to written response bytes, through an inference
+/// thread scoring them with the native tree backend.
+fn pipeline_requests_per_sec() -> f64 {
+    const RING_SIZE: usize = 4096;
+    let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (submission_poller, builder) = builder.event_poller();
+    let (completion_poller, builder) = builder.and_then().event_poller();
+    let mut producer = builder.build();
+
+    let model = GbdtModel::parse(MODEL, FEATURE_DIM).expect("valid model");
+    let response_queue = Arc::new(ResponseQueue::new(RING_SIZE));
+    let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+    let conn = registry.open(0, 0, -1);
+    let stop = Arc::new(AtomicBool::new(false));
+    let inference = InferenceConsumer::new(
+        submission_poller,
+        completion_poller,
+        GbdtBackend::new(model),
+        Arc::new(ResponseRouter::from(vec![Arc::clone(&response_queue)])),
+        Arc::clone(&registry),
+        BATCH,
+        Duration::from_micros(50),
+    );
+    let handle = thread::Builder::new()
+        .name("perf-inference".into())
+        .spawn({
+            let stop = Arc::clone(&stop);
+            move || inference.run_until(stop)
+        })
+        .expect("failed to spawn inference thread");
+
+    let pool = BufferPool::leak_new(RING_SIZE * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+    let buf = request_batch();
+    let mut path = WritePath::new(conn);
+    let mut request_seq = 0;
+
+    let elapsed = best_of(|| {
+        let start = Instant::now();
+        for _ in 0..PIPELINE_BATCHES_PER_RUN {
+            request_flow::process_requests_from_buffer(
+                black_box(&buf),
+                &mut producer,
+                &mut allocator,
+                conn,
+                &mut request_seq,
+            )
+            .expect("well-formed requests");
+            let mut answered = 0;
+            while answered < BATCH {
+                match response_queue.pop() {
+                    Some(response) => {
+                        path.deliver(&response);
+                        answered += 1;
+                    }
+                    None => std::hint::spin_loop(),
+                }
+            }
+            drain_writes(&mut path);
+        }
+        start.elapsed()
+    });
+
+    registry.mark_read_closed(conn, request_seq);
+    stop.store(true, Ordering::Relaxed);
+    handle.join().expect("inference thread panicked");
+    (PIPELINE_BATCHES_PER_RUN * BATCH) as f64 / elapsed.as_secs_f64()
+}
+
+#[test]
+fn hot_path_stays_within_budget() {
+    if cfg!(debug_assertions) {
+        panic!("perf budgets are release-build numbers; run with --release");
+    }
+    common::init_factory_pool();
+    let budgets = Budgets::load();
+    eprintln!("perf budgets from {}:", budgets.path.display());
+
+    let failures: Vec<String> = [
+        budgets.check("parse_ns_per_request", parse_ns_per_request(), false),
+        budgets.check("write_ns_per_response", write_ns_per_response(), false),
+        budgets.check(
+            "pipeline_requests_per_sec",
+            pipeline_requests_per_sec(),
+            true,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    assert!(
+        failures.is_empty(),
+        "hot path over budget:\n  {}",
+        failures.join("\n  ")
+    );
+}
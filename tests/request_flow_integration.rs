@@ -2,12 +2,25 @@
 
 mod common;
 
-use disruptor::{BusySpin, build_single_producer};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use disruptor::{BusySpin, build_multi_producer, build_single_producer};
 
 use disrust::buffer_pool::BufferPool;
 use disrust::connection_id::ConnectionRef;
 use disrust::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
-use disrust::request_flow;
+use disrust::embedding::{EmbeddingLookup, EmbeddingTable};
+use disrust::pipeline::admission::LargeRequestAdmission;
+use disrust::pipeline::circuit_breaker::{CircuitBreaker, CircuitState, HALF_OPEN_PROBES};
+use disrust::pipeline::inline::{InlineFastPath, LinearModel, PlacementPolicy, RingOccupancy};
+use disrust::protocol::{
+    self, ConnectionDecoder, DecoderState, OverloadReason, ParseError, ParseResult, RequestDecoder,
+    RequestField, RequestFraming,
+};
+use disrust::request_flow::{self, MalformedPolicy, NonFinitePolicy, RequestOverflow};
 use disrust::ring_types::InferenceEvent;
 
 #[test]
@@ -173,8 +186,10 @@ fn request_flow_parse_error_returns_err() {
     let mut allocator = pool.allocator();
 
     let mut request_seq = 0u64;
-    // num_vectors = 0 is invalid
-    let buf = [0u8, 0, 0, 0];
+    // num_vectors = 0 is invalid, after one valid request
+    let mut buf = vec![0u8; protocol::request_size(1)];
+    buf[..4].copy_from_slice(&1u32.to_le_bytes());
+    buf.extend_from_slice(&[0u8, 0, 0, 0]);
 
     let result = request_flow::process_requests_from_buffer(
         &buf,
@@ -185,8 +200,1237 @@ fn request_flow_parse_error_returns_err() {
     );
 
     assert!(result.is_err());
-    if let Err(request_flow::ProcessRequestError::Parse(_)) = result {
+    if let Err(request_flow::ProcessRequestError::Parse { error: e, consumed }) = result {
+        assert_eq!(consumed, protocol::request_size(1));
+        assert_eq!(e.field, protocol::RequestField::NumVectors);
+        assert_eq!(e.value, 0);
+        assert_eq!(e.offset, protocol::request_size(1) as u64);
     } else {
         panic!("expected Parse error");
     }
+    assert_eq!(request_seq, 1, "the request before the error was published");
+}
+
+/// Feed a pipelined stream in uneven reads, compacting the buffer by `consumed` the way an IO
+/// thread does, with a malformed header late in the stream. Every frame before it is processed
+/// exactly once and the error lands on its first byte; debug builds also run the shadow parser
+/// on every pass.
+#[test]
+fn request_flow_consumption_stays_on_frame_boundaries_across_reads() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(64, InferenceEvent::factory, BusySpin);
+    let (_poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(64 * MAX_VECTORS_PER_REQUEST * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let mut rng = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move |bound: u64| {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng % bound
+    };
+    let mut stream = Vec::new();
+    for _ in 0..200 {
+        let num_vectors = next(4) as u32 + 1;
+        stream.extend_from_slice(&num_vectors.to_le_bytes());
+        stream.resize(stream.len() + num_vectors as usize * FEATURE_DIM * 4, 0x3f);
+    }
+    let bad_at = stream.len();
+    stream.extend_from_slice(&(MAX_VECTORS_PER_REQUEST as u32 + 1).to_le_bytes());
+    stream.extend_from_slice(&[0u8; 32]);
+
+    let mut buf = Vec::new();
+    let mut offset = 0usize;
+    let mut read = 0usize;
+    let mut request_seq = 0u64;
+    let mut rejected = 0u64;
+    let error = loop {
+        assert!(read < stream.len(), "stream ended without the parse error");
+        let len = (next(700) as usize + 1).min(stream.len() - read);
+        buf.extend_from_slice(&stream[read..read + len]);
+        read += len;
+        let result = request_flow::process_requests_with_policy(
+            &buf,
+            &mut producer,
+            &mut allocator,
+            ConnectionRef::new(0, 3, 1),
+            &mut request_seq,
+            request_flow::RingFullPolicy::Reject,
+            |_, _| rejected += 1,
+        );
+        match result {
+            Ok(outcome) => {
+                assert!(outcome.consumed <= buf.len());
+                buf.drain(..outcome.consumed);
+                offset += outcome.consumed;
+            }
+            Err(request_flow::ProcessRequestError::Parse { error, consumed }) => {
+                let error = error.at(offset as u64);
+                offset += consumed;
+                break error;
+            }
+        }
+    };
+
+    assert_eq!(
+        offset, bad_at,
+        "consumed exactly the frames before the bad one"
+    );
+    assert_eq!(error.offset, bad_at as u64);
+    assert_eq!(request_seq, 200);
+    assert_eq!(
+        rejected,
+        200 - 64,
+        "ring of 64 with no consumer rejects the rest"
+    );
+}
+
+#[test]
+fn request_flow_rejects_requests_when_ring_full() {
+    common::init_factory_pool();
+
+    // Ring of 2 with no consumer progress: the third and fourth requests find it full.
+    let builder = build_single_producer(2, InferenceEvent::factory, BusySpin);
+    let (_poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let features: Vec<f32> = (0..FEATURE_DIM).map(|i| i as f32).collect();
+    let one = common::one_request_bytes(1, &features);
+    let buf = one.repeat(4);
+
+    let mut request_seq = 0u64;
+    let mut rejected = Vec::new();
+    let outcome = request_flow::process_requests_with_policy(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 0, 1),
+        &mut request_seq,
+        request_flow::RingFullPolicy::Reject,
+        |seq, reason| rejected.push((seq, reason)),
+    )
+    .expect("reject policy should not fail");
+
+    assert_eq!(
+        outcome.consumed,
+        buf.len(),
+        "rejected requests are consumed"
+    );
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(
+        request_seq, 4,
+        "rejected requests still take a sequence number"
+    );
+    assert_eq!(
+        rejected,
+        vec![(2, OverloadReason::RingFull), (3, OverloadReason::RingFull)]
+    );
+
+    let mut frame = [0u8; protocol::OVERLOAD_FRAME_BYTES];
+    protocol::encode_overload(OverloadReason::RingFull, 250, &mut frame);
+    assert_eq!(
+        protocol::decode_overload(&frame),
+        Some((Some(OverloadReason::RingFull), 250))
+    );
+    assert_eq!(protocol::decode_overload(&[1u8, 0, 0, 0, 0]), None);
+}
+
+#[test]
+fn request_flow_scores_single_vector_requests_inline_while_ring_is_quiet() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (_poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let occupancy = Arc::new(RingOccupancy::default());
+    let inline = InlineFastPath::new(
+        Arc::new(LinearModel::new(vec![1.0; FEATURE_DIM], 0.5)),
+        PlacementPolicy::default(),
+        Arc::clone(&occupancy),
+    );
+
+    let one: Vec<f32> = vec![1.0; FEATURE_DIM];
+    let two: Vec<f32> = vec![1.0; FEATURE_DIM * 2];
+    // Inline, published (two vectors), then published again because the ring is no longer empty.
+    let mut buf = common::one_request_bytes(1, &one);
+    buf.extend(common::one_request_bytes(2, &two));
+    buf.extend(common::one_request_bytes(1, &one));
+
+    let mut request_seq = 0u64;
+    let mut scored = Vec::new();
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 0, 1),
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::PassThrough,
+        MalformedPolicy::Close,
+        RequestFraming::PLAIN,
+        &mut ConnectionDecoder::native(),
+        Some(&inline),
+        None,
+        None,
+        None,
+        None,
+        |_, _| panic!("nothing should be rejected"),
+        |seq, scores| scored.push((seq, scores.to_vec())),
+        |_, _| panic!("every feature is finite"),
+    )
+    .expect("valid requests");
+
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(request_seq, 3);
+    assert_eq!(scored, vec![(0, vec![FEATURE_DIM as f32 + 0.5])]);
+    assert_eq!(
+        occupancy.len(),
+        2,
+        "published requests count toward occupancy"
+    );
+}
+
+#[test]
+fn request_flow_rejects_or_clamps_non_finite_features() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let finite = vec![1.0; FEATURE_DIM];
+    let mut nan = finite.clone();
+    nan[3] = f32::NAN;
+    let mut inf = finite.clone();
+    inf[0] = f32::NEG_INFINITY;
+    let mut buf = common::one_request_bytes(1, &finite);
+    buf.extend(common::one_request_bytes(1, &nan));
+    buf.extend(common::one_request_bytes(1, &inf));
+    let request_len = buf.len() / 3;
+
+    let process = |buf: &[u8], producer: &mut _, allocator: &mut _, policy, framing| {
+        let mut request_seq = 0u64;
+        let mut invalid = Vec::new();
+        let outcome = request_flow::process_requests_with_inline(
+            buf,
+            producer,
+            allocator,
+            ConnectionRef::new(0, 0, 1),
+            &mut request_seq,
+            0,
+            request_flow::RingFullPolicy::Wait,
+            policy,
+            MalformedPolicy::Close,
+            framing,
+            &mut ConnectionDecoder::native(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            |_, _| panic!("nothing should be rejected"),
+            |_, _| panic!("nothing is scored inline"),
+            |seq, error| invalid.push((seq, error)),
+        )
+        .expect("non-finite features are not a framing error");
+        assert_eq!(outcome.consumed, buf.len());
+        assert_eq!(request_seq, 3);
+        (outcome.num_published, invalid)
+    };
+
+    let (published, invalid) = process(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        NonFinitePolicy::Reject,
+        RequestFraming::PLAIN,
+    );
+    assert_eq!(published, 1, "only the finite request is published");
+    assert_eq!(
+        invalid,
+        vec![
+            (
+                1,
+                ParseError {
+                    field: RequestField::Features,
+                    value: f32::NAN.to_bits(),
+                    offset: (request_len + 4 + 3 * 4) as u64,
+                }
+            ),
+            (
+                2,
+                ParseError {
+                    field: RequestField::Features,
+                    value: f32::NEG_INFINITY.to_bits(),
+                    offset: (2 * request_len + 4) as u64,
+                }
+            ),
+        ]
+    );
+    let mut frame = [0u8; protocol::PARSE_ERROR_FRAME_BYTES];
+    protocol::encode_parse_error(&invalid[1].1, &mut frame);
+    assert_eq!(protocol::decode_parse_error(&frame), Some(invalid[1].1));
+
+    let (published, invalid) = process(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        NonFinitePolicy::Clamp,
+        RequestFraming::PLAIN,
+    );
+    assert_eq!(published, 3);
+    assert!(invalid.is_empty());
+    let firsts: Vec<(u64, f32, f32)> = match poller.poll() {
+        Ok(mut guard) => (&mut guard)
+            .map(|event| (event.request_seq, event.vector(0)[0], event.vector(0)[3]))
+            .collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(
+        firsts,
+        vec![
+            (0, 1.0, 1.0),
+            (0, 1.0, 1.0),
+            (1, 1.0, 0.0),
+            (2, f32::MIN, 1.0)
+        ]
+    );
+
+    // Offsets count the request id prefixes too.
+    let framed: Vec<u8> = buf
+        .chunks(request_len)
+        .zip(0u64..)
+        .flat_map(|(request, id)| [&id.to_le_bytes()[..], request].concat())
+        .collect();
+    let (published, invalid) = process(
+        &framed,
+        &mut producer,
+        &mut allocator,
+        NonFinitePolicy::Reject,
+        RequestFraming::REQUEST_ID,
+    );
+    assert_eq!(published, 1);
+    let framed_len = protocol::REQUEST_ID_BYTES + request_len;
+    assert_eq!(
+        invalid
+            .iter()
+            .map(|(_, error)| error.offset)
+            .collect::<Vec<_>>(),
+        vec![
+            (framed_len + protocol::REQUEST_ID_BYTES + 4 + 3 * 4) as u64,
+            (2 * framed_len + protocol::REQUEST_ID_BYTES + 4) as u64,
+        ]
+    );
+}
+
+#[test]
+fn request_flow_reads_vectors_of_the_configured_feature_dim() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    const DIM: usize = 4;
+    let framing = RequestFraming::PLAIN.with_feature_dim(DIM);
+    let mut buf = Vec::new();
+    for (num_vectors, first) in [(2u32, 0.0f32), (1, 8.0)] {
+        buf.extend_from_slice(&num_vectors.to_le_bytes());
+        for i in 0..num_vectors as usize * DIM {
+            buf.extend_from_slice(&(first + i as f32).to_le_bytes());
+        }
+    }
+    assert_eq!(buf.len(), framing.request_size(2) + framing.request_size(1));
+
+    let mut request_seq = 0u64;
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 0, 1),
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::Reject,
+        MalformedPolicy::Close,
+        framing,
+        &mut ConnectionDecoder::native(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        |_, _| panic!("nothing should be rejected"),
+        |_, _| panic!("nothing is scored inline"),
+        |_, _| panic!("all features are finite"),
+    )
+    .expect("requests frame at the configured dim");
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+
+    let vectors: Vec<Vec<f32>> = match poller.poll() {
+        Ok(mut guard) => (&mut guard)
+            .flat_map(|event| {
+                (0..event.num_vectors as usize)
+                    .map(|v| event.vector(v).to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(
+        vectors,
+        vec![
+            vec![0.0, 1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0, 7.0],
+            vec![8.0, 9.0, 10.0, 11.0]
+        ]
+    );
+}
+
+#[test]
+fn request_flow_widens_half_precision_requests() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    const DIM: usize = 2;
+    let framing = RequestFraming::PLAIN.with_feature_dim(DIM);
+    let half_request = |halves: &[u16]| {
+        let num_vectors = (halves.len() / DIM) as u32 | protocol::HALF_REQUEST_FLAG;
+        let mut request = num_vectors.to_le_bytes().to_vec();
+        request.extend(halves.iter().flat_map(|half| half.to_le_bytes()));
+        request
+    };
+    // 1.0, -2.0, 0.5, 65504.0; then a NaN; then a full-precision request between them.
+    let mut buf = half_request(&[0x3c00, 0xc000, 0x3800, 0x7bff]);
+    assert_eq!(buf.len(), framing.request_size_as(2, true));
+    buf.extend(half_request(&[0x3c00, 0x7e00]));
+    let nan_at = buf.len() - 2;
+    let mut full = 1u32.to_le_bytes().to_vec();
+    full.extend([3.0f32, 4.0].iter().flat_map(|f| f.to_le_bytes()));
+    buf.extend(full);
+
+    let mut request_seq = 0u64;
+    let mut invalid = Vec::new();
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 0, 1),
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::Reject,
+        MalformedPolicy::Close,
+        framing,
+        &mut ConnectionDecoder::native(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        |_, _| panic!("nothing should be rejected"),
+        |_, _| panic!("nothing is scored inline"),
+        |request_seq, error| invalid.push((request_seq, error.offset)),
+    )
+    .expect("half requests frame");
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(
+        invalid,
+        [(1, nan_at as u64)],
+        "offsets count half-width features"
+    );
+
+    let vectors: Vec<Vec<f32>> = match poller.poll() {
+        Ok(mut guard) => (&mut guard)
+            .flat_map(|event| {
+                (0..event.num_vectors as usize)
+                    .map(|v| event.vector(v).to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(
+        vectors,
+        vec![vec![1.0, -2.0], vec![0.5, 65504.0], vec![3.0, 4.0]]
+    );
+}
+
+/// Requests as a `u8` vector count and big-endian features, to tell a connection's decoder
+/// from the native one. Each connection's state follows how far into the stream it is.
+struct BigEndianRequests;
+
+/// Connections whose [`StreamPosition`] was closed.
+static STREAMS_CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct StreamPosition {
+    consumed: usize,
+    passes: usize,
+}
+
+impl DecoderState for StreamPosition {
+    fn on_consumed(&mut self, bytes: &[u8]) {
+        self.consumed += bytes.len();
+        self.passes += 1;
+    }
+
+    fn on_close(&mut self) {
+        STREAMS_CLOSED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl RequestDecoder for BigEndianRequests {
+    fn name(&self) -> &'static str {
+        "big-endian"
+    }
+
+    fn open(&self) -> Option<Box<dyn DecoderState>> {
+        Some(Box::new(StreamPosition::default()))
+    }
+
+    fn parse(
+        &self,
+        _state: Option<&dyn DecoderState>,
+        buf: &[u8],
+        framing: RequestFraming,
+    ) -> ParseResult {
+        let Some(&num_vectors) = buf.first() else {
+            return ParseResult::Incomplete(1);
+        };
+        if num_vectors == 0 || num_vectors as usize > framing.max_vectors {
+            return ParseResult::Error(ParseError {
+                field: RequestField::NumVectors,
+                value: num_vectors as u32,
+                offset: 0,
+            });
+        }
+        let len = 1 + num_vectors as usize * framing.vector_bytes();
+        if buf.len() < len {
+            return ParseResult::Incomplete(len - buf.len());
+        }
+        ParseResult::Complete {
+            num_vectors,
+            bytes_consumed: len,
+            request_id: None,
+        }
+    }
+
+    fn features<'a>(
+        &self,
+        _state: Option<&dyn DecoderState>,
+        frame: &'a [u8],
+        _framing: RequestFraming,
+        scratch: &'a mut Vec<u8>,
+    ) -> &'a [u8] {
+        scratch.clear();
+        scratch.extend(
+            frame[1..]
+                .chunks_exact(4)
+                .flat_map(|bytes| f32::from_be_bytes(bytes.try_into().unwrap()).to_le_bytes()),
+        );
+        scratch
+    }
+
+    fn feature_offset(&self, _frame: &[u8], _framing: RequestFraming, index: usize) -> usize {
+        1 + 4 * index
+    }
+
+    fn skip_len(
+        &self,
+        _error: &ParseError,
+        _framing: RequestFraming,
+        _max_bytes: usize,
+    ) -> Option<usize> {
+        None
+    }
+
+    fn request_id(&self, _buf: &[u8]) -> u64 {
+        0
+    }
+}
+
+#[test]
+fn request_flow_reads_requests_through_the_connection_decoder() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    const DIM: usize = 2;
+    let framing = RequestFraming::PLAIN.with_feature_dim(DIM);
+    let requests: [&[f32]; 3] = [&[1.0, 2.0, 3.0, 4.0], &[5.0, f32::NAN], &[7.0, 8.0]];
+    let mut buf = Vec::new();
+    for features in requests {
+        buf.push((features.len() / DIM) as u8);
+        buf.extend(features.iter().flat_map(|f| f.to_be_bytes()));
+    }
+
+    let mut request_seq = 0u64;
+    let mut invalid = Vec::new();
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 0, 1),
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::Reject,
+        MalformedPolicy::Close,
+        framing,
+        &mut ConnectionDecoder::new(&BigEndianRequests),
+        None,
+        None,
+        None,
+        None,
+        None,
+        |_, _| panic!("nothing should be rejected"),
+        |_, _| panic!("nothing is scored inline"),
+        |request_seq, error| invalid.push((request_seq, error)),
+    )
+    .expect("every request frames");
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    let second_nan_at = 1 + 4 * DIM * 2 + 1 + 4;
+    assert_eq!(
+        invalid
+            .iter()
+            .map(|(seq, error)| (*seq, error.offset))
+            .collect::<Vec<_>>(),
+        [(1, second_nan_at as u64)],
+        "the offset is the NaN's in the stream"
+    );
+
+    let vectors: Vec<Vec<f32>> = match poller.poll() {
+        Ok(mut guard) => (&mut guard)
+            .flat_map(|event| {
+                (0..event.num_vectors as usize)
+                    .map(|v| event.vector(v).to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(
+        vectors,
+        vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![7.0, 8.0]]
+    );
+}
+
+#[test]
+fn request_flow_tells_decoder_state_what_each_pass_consumed() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (_poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    const DIM: usize = 2;
+    let framing = RequestFraming::PLAIN.with_feature_dim(DIM);
+    let request_len = 1 + DIM * 4;
+    let buf: Vec<u8> = [1.0f32, 2.0, 3.0]
+        .iter()
+        .flat_map(|&first| {
+            let mut request = vec![1u8];
+            request.extend([first, first].iter().flat_map(|f| f.to_be_bytes()));
+            request
+        })
+        .collect();
+
+    let mut decoder = ConnectionDecoder::new(&BigEndianRequests);
+    let mut request_seq = 0u64;
+    let mut pass = |bytes: &[u8], decoder: &mut ConnectionDecoder| {
+        request_flow::process_requests_with_inline(
+            bytes,
+            &mut producer,
+            &mut allocator,
+            ConnectionRef::new(0, 0, 1),
+            &mut request_seq,
+            0,
+            request_flow::RingFullPolicy::Wait,
+            NonFinitePolicy::PassThrough,
+            MalformedPolicy::Close,
+            framing,
+            decoder,
+            None,
+            None,
+            None,
+            None,
+            None,
+            |_, _| panic!("nothing should be rejected"),
+            |_, _| panic!("nothing is scored inline"),
+            |_, _| panic!("every request is well formed"),
+        )
+        .expect("every request frames")
+    };
+    let position = |decoder: &ConnectionDecoder| {
+        let state = decoder.state().expect("the decoder keeps state");
+        let position = state.downcast_ref::<StreamPosition>().unwrap();
+        (position.consumed, position.passes)
+    };
+
+    // The first read ends inside the last request, which the state does not hear about.
+    let first = pass(&buf[..buf.len() - 3], &mut decoder);
+    assert_eq!(first.consumed, 2 * request_len);
+    assert_eq!(position(&decoder), (2 * request_len, 1));
+    let second = pass(&buf[first.consumed..], &mut decoder);
+    assert_eq!(second.consumed, request_len);
+    assert_eq!(position(&decoder), (buf.len(), 2));
+
+    let closed = STREAMS_CLOSED.load(Ordering::Relaxed);
+    decoder.close();
+    assert!(decoder.state().is_none());
+    assert!(STREAMS_CLOSED.load(Ordering::Relaxed) > closed);
+}
+
+#[test]
+fn request_flow_parks_requests_when_ring_full_and_publishes_them_in_order() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(2, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let buf: Vec<u8> = (0..5)
+        .flat_map(|i| common::one_request_bytes(1, &[i as f32; FEATURE_DIM]))
+        .collect();
+    let request_len = buf.len() / 5;
+    let conn = ConnectionRef::new(0, 3, 1);
+    let mut overflow = RequestOverflow::new(2);
+    let mut request_seq = 0u64;
+
+    // Two requests fill the ring, two are parked, the fifth stays in the buffer.
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        conn,
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::PassThrough,
+        MalformedPolicy::Close,
+        RequestFraming::PLAIN,
+        &mut ConnectionDecoder::native(),
+        None,
+        Some(&mut overflow),
+        None,
+        None,
+        None,
+        |_, _| panic!("nothing should be rejected"),
+        |_, _| panic!("nothing is scored inline"),
+        |_, _| panic!("every feature is finite"),
+    )
+    .expect("valid requests");
+    assert_eq!(outcome.consumed, 4 * request_len);
+    assert_eq!(outcome.num_published, 2);
+    assert!(outcome.ring_full);
+    assert_eq!(request_seq, 4);
+    assert_eq!(overflow.len(), 2);
+    overflow.block(conn);
+    assert!(overflow.take_unblocked().is_empty(), "queue is still full");
+
+    let drained: Vec<u64> = match poller.poll() {
+        Ok(mut guard) => (&mut guard).map(|event| event.request_seq).collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(drained, vec![0, 1]);
+
+    assert_eq!(overflow.drain(&mut producer, &mut allocator, None), 2);
+    assert!(overflow.is_empty());
+    assert_eq!(overflow.take_unblocked(), vec![conn]);
+    match poller.poll() {
+        Ok(mut guard) => {
+            let events: Vec<_> = (&mut guard)
+                .map(|event| (event.request_seq, event.vector(0)[0]))
+                .collect();
+            assert_eq!(events, vec![(2, 2.0), (3, 3.0)]);
+        }
+        Err(_) => panic!("expected parked events"),
+    }
+}
+
+#[test]
+fn request_flow_holds_or_rejects_large_requests_over_the_admission_limit() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(16, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    // Large, small, large, small: only one request over one vector may be in the ring.
+    let large = common::one_request_bytes(2, &[1.0; 2 * FEATURE_DIM]);
+    let small = common::one_request_bytes(1, &[1.0; FEATURE_DIM]);
+    let buf = [&large[..], &small, &large, &small].concat();
+    let admission = LargeRequestAdmission::parse("1:1").unwrap();
+    let mut request_seq = 0u64;
+    let mut process = |buf: &[u8], policy, request_seq: &mut u64, rejected: &mut Vec<_>| {
+        request_flow::process_requests_with_inline(
+            buf,
+            &mut producer,
+            &mut allocator,
+            ConnectionRef::new(0, 0, 1),
+            request_seq,
+            0,
+            policy,
+            NonFinitePolicy::PassThrough,
+            MalformedPolicy::Close,
+            RequestFraming::PLAIN,
+            &mut ConnectionDecoder::native(),
+            None,
+            None,
+            Some(&admission),
+            None,
+            None,
+            |seq, reason| rejected.push((seq, reason)),
+            |_, _| panic!("nothing is scored inline"),
+            |_, _| panic!("every feature is finite"),
+        )
+        .expect("valid requests")
+    };
+
+    // Waiting: the second large request stays in the buffer until the first is answered.
+    let mut rejected = Vec::new();
+    let outcome = process(
+        &buf,
+        request_flow::RingFullPolicy::Wait,
+        &mut request_seq,
+        &mut rejected,
+    );
+    assert!(outcome.large_request_held);
+    assert_eq!(outcome.consumed, large.len() + small.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(request_seq, 2);
+    assert_eq!(admission.in_flight(), 1);
+    let outcome = process(
+        &buf[outcome.consumed..],
+        request_flow::RingFullPolicy::Wait,
+        &mut request_seq,
+        &mut rejected,
+    );
+    assert!(outcome.large_request_held);
+    assert_eq!(outcome.consumed, 0);
+
+    admission.complete(2);
+    let rest = &buf[large.len() + small.len()..];
+    let outcome = process(
+        rest,
+        request_flow::RingFullPolicy::Wait,
+        &mut request_seq,
+        &mut rejected,
+    );
+    assert!(!outcome.large_request_held);
+    assert_eq!(outcome.consumed, rest.len());
+    assert_eq!(outcome.num_published, 2);
+    let published: Vec<u64> = match poller.poll() {
+        Ok(mut guard) => (&mut guard).map(|event| event.request_seq).collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(published, vec![0, 1, 2, 3]);
+
+    // Rejecting: the large request is answered with an overload frame and consumed.
+    let outcome = process(
+        &buf,
+        request_flow::RingFullPolicy::Reject,
+        &mut request_seq,
+        &mut rejected,
+    );
+    assert!(!outcome.large_request_held);
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(
+        rejected,
+        vec![
+            (4, OverloadReason::LargeRequests),
+            (6, OverloadReason::LargeRequests)
+        ]
+    );
+    assert_eq!(admission.in_flight(), 1);
+}
+
+#[test]
+fn request_flow_skips_malformed_requests_that_still_frame() {
+    common::init_factory_pool();
+
+    let builder = build_single_producer(64, InferenceEvent::factory, BusySpin);
+    let (_poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+
+    let req = common::one_request_bytes(1, &[1.0; FEATURE_DIM]);
+    let oversized_vectors = MAX_VECTORS_PER_REQUEST + 1;
+    let mut buf = req.clone();
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&req);
+    buf.extend_from_slice(&(oversized_vectors as u32).to_le_bytes());
+    buf.resize(buf.len() + 10, 0);
+
+    let mut process = |buf: &[u8], malformed, request_seq: &mut u64| {
+        let mut invalid = Vec::new();
+        let result = request_flow::process_requests_with_inline(
+            buf,
+            &mut producer,
+            &mut allocator,
+            ConnectionRef::new(0, 0, 1),
+            request_seq,
+            0,
+            request_flow::RingFullPolicy::Wait,
+            NonFinitePolicy::PassThrough,
+            malformed,
+            RequestFraming::PLAIN,
+            &mut ConnectionDecoder::native(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            |_, _| panic!("nothing should be rejected"),
+            |_, _| panic!("nothing is scored inline"),
+            |seq, error| invalid.push((seq, error)),
+        );
+        (result, invalid)
+    };
+
+    let mut request_seq = 0;
+    let (result, invalid) = process(&buf, MalformedPolicy::Skip, &mut request_seq);
+    let outcome = result.expect("both malformed requests still frame");
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(
+        outcome.skip,
+        protocol::request_size(oversized_vectors) - protocol::REQUEST_HEADER_BYTES - 10
+    );
+    assert_eq!(request_seq, 4);
+    let at = |offset: usize, value: usize| ParseError {
+        field: RequestField::NumVectors,
+        value: value as u32,
+        offset: offset as u64,
+    };
+    assert_eq!(
+        invalid,
+        vec![
+            (1, at(req.len(), 0)),
+            (3, at(2 * req.len() + 4, oversized_vectors))
+        ]
+    );
+
+    // A declared length past the skip limit, or the close policy, still ends the parse.
+    let mut garbage = req.clone();
+    garbage.extend_from_slice(&u32::MAX.to_le_bytes());
+    let mut request_seq = 0;
+    let (result, invalid) = process(&garbage, MalformedPolicy::Skip, &mut request_seq);
+    assert!(matches!(
+        result,
+        Err(request_flow::ProcessRequestError::Parse { consumed, .. }) if consumed == req.len()
+    ));
+    assert!(invalid.is_empty());
+    let mut request_seq = 0;
+    let (result, _) = process(&buf, MalformedPolicy::Close, &mut request_seq);
+    assert!(result.is_err());
+    assert_eq!(request_seq, 1);
+}
+
+#[test]
+fn request_flow_expands_embedding_ids_into_published_and_parked_vectors() {
+    common::init_factory_pool();
+
+    // Two rows of width 2; the last wire value of every vector is an id.
+    let path = std::env::temp_dir().join(format!("disrust-flow-embeddings-{}", std::process::id()));
+    let mut table = b"DREMBED1".to_vec();
+    table.extend(2u32.to_le_bytes());
+    table.extend(2u32.to_le_bytes());
+    for value in [10.0f32, 11.0, 20.0, 21.0] {
+        table.extend(value.to_le_bytes());
+    }
+    std::fs::write(&path, table).unwrap();
+    let table = EmbeddingTable::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let embeddings = EmbeddingLookup::new(&table, 1);
+    let model_dim = embeddings.model_dim(FEATURE_DIM);
+    assert_eq!(model_dim, FEATURE_DIM + 1);
+
+    let builder = build_single_producer(2, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(256 * model_dim);
+    let mut allocator = pool.allocator();
+
+    let vector = |dense: f32, id: u32| {
+        let mut vector = vec![dense; FEATURE_DIM - 1];
+        vector.push(f32::from_bits(id));
+        vector
+    };
+    let buf: Vec<u8> = [(1.0, 1), (2.0, 0), (3.0, 9)]
+        .into_iter()
+        .flat_map(|(dense, id)| common::one_request_bytes(1, &vector(dense, id)))
+        .collect();
+    let mut overflow = RequestOverflow::new(1);
+    let mut request_seq = 0u64;
+
+    // Two requests fill the ring and the third, whose id is past the table, is parked.
+    let outcome = request_flow::process_requests_with_inline(
+        &buf,
+        &mut producer,
+        &mut allocator,
+        ConnectionRef::new(0, 4, 1),
+        &mut request_seq,
+        0,
+        request_flow::RingFullPolicy::Wait,
+        NonFinitePolicy::PassThrough,
+        MalformedPolicy::Close,
+        RequestFraming::PLAIN,
+        &mut ConnectionDecoder::native(),
+        None,
+        Some(&mut overflow),
+        None,
+        None,
+        Some(embeddings),
+        |_, _| panic!("nothing should be rejected"),
+        |_, _| panic!("nothing is scored inline"),
+        |_, _| panic!("every feature is finite"),
+    )
+    .expect("valid requests");
+    assert_eq!(outcome.consumed, buf.len());
+    assert_eq!(outcome.num_published, 2);
+    assert_eq!(overflow.len(), 1);
+
+    let expanded = |dense: f32, row: [f32; 2]| {
+        let mut vector = vec![dense; FEATURE_DIM - 1];
+        vector.extend(row);
+        vector
+    };
+    let published: Vec<Vec<f32>> = match poller.poll() {
+        Ok(mut guard) => (&mut guard).map(|event| event.vector(0).to_vec()).collect(),
+        Err(_) => panic!("expected published events"),
+    };
+    assert_eq!(
+        published,
+        vec![expanded(1.0, [20.0, 21.0]), expanded(2.0, [10.0, 11.0])]
+    );
+
+    assert_eq!(overflow.drain(&mut producer, &mut allocator, None), 1);
+    match poller.poll() {
+        Ok(mut guard) => {
+            let parked: Vec<Vec<f32>> =
+                (&mut guard).map(|event| event.vector(0).to_vec()).collect();
+            assert_eq!(
+                parked,
+                vec![expanded(3.0, [0.0, 0.0])],
+                "unknown ids expand to zeros"
+            );
+        }
+        Err(_) => panic!("expected the parked event"),
+    }
+}
+
+#[test]
+fn request_flow_hands_back_half_open_probes_that_find_the_ring_full() {
+    common::init_factory_pool();
+
+    const RING_SIZE: usize = 4;
+    let builder = build_single_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let mut producer = builder.build();
+    let pool = BufferPool::leak_new(256 * FEATURE_DIM);
+    let mut allocator = pool.allocator();
+    let conn = ConnectionRef::new(0, 5, 1);
+
+    let circuit = CircuitBreaker::new(100, 1, Duration::from_millis(1));
+    assert!(circuit.record(0, 1));
+    thread::sleep(Duration::from_millis(2));
+
+    let mut request_seq = 0u64;
+    let mut offer = |count: usize| {
+        let buf = common::one_request_bytes(1, &[0.5; FEATURE_DIM]).repeat(count);
+        let mut rejected = Vec::new();
+        let outcome = request_flow::process_requests_with_inline(
+            &buf,
+            &mut producer,
+            &mut allocator,
+            conn,
+            &mut request_seq,
+            0,
+            request_flow::RingFullPolicy::Reject,
+            NonFinitePolicy::PassThrough,
+            MalformedPolicy::Close,
+            RequestFraming::PLAIN,
+            &mut ConnectionDecoder::native(),
+            None,
+            None,
+            None,
+            Some(&circuit),
+            None,
+            |_, reason| rejected.push(reason),
+            |_, _| panic!("nothing is scored inline"),
+            |_, _| panic!("every feature is finite"),
+        )
+        .expect("valid requests");
+        (outcome.num_published, rejected)
+    };
+
+    // Ten probes taken, four published; the six that found the ring full give theirs back.
+    let (published, rejected) = offer(HALF_OPEN_PROBES as usize + 2);
+    assert_eq!(published, RING_SIZE);
+    assert_eq!(
+        rejected,
+        vec![OverloadReason::RingFull; HALF_OPEN_PROBES as usize + 2 - RING_SIZE]
+    );
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+
+    match poller.poll() {
+        Ok(mut guard) => assert_eq!((&mut guard).count(), RING_SIZE),
+        Err(_) => panic!("expected published events"),
+    }
+    let (published, rejected) = offer(HALF_OPEN_PROBES as usize - RING_SIZE + 1);
+    assert_eq!(published, HALF_OPEN_PROBES as usize - RING_SIZE);
+    assert_eq!(rejected, vec![OverloadReason::CircuitOpen]);
+}
+
+/// The inline model and the schema read wire features, so a server with embeddings refuses
+/// them, including when a config file asks for what the flags would have refused.
+#[cfg(target_os = "linux")]
+#[test]
+fn serve_refuses_embeddings_with_wire_feature_readers() {
+    use disrust::server::{ServeArgs, ServerBuilder};
+
+    let dir = std::env::temp_dir();
+    let config = dir.join(format!(
+        "disrust-flow-embedding-config-{}",
+        std::process::id()
+    ));
+    std::fs::write(&config, "feature_schema = schema.conf\n").unwrap();
+    let with_embeddings = || {
+        let mut args = ServeArgs::for_model("model.onnx");
+        args.embeddings = Some(dir.join("embeddings.bin"));
+        args.embedding_ids = Some(1);
+        args
+    };
+
+    let mut args = with_embeddings();
+    args.config = Some(config.clone());
+    let err = ServerBuilder::from_args(args).start().err();
+    std::fs::remove_file(&config).unwrap();
+    let err = err.expect("schema from the config file");
+    assert!(err.contains("--feature-schema"), "{err}");
+
+    let mut args = with_embeddings();
+    args.inline_linear_model = Some(dir.join("inline.model"));
+    let err = ServerBuilder::from_args(args)
+        .start()
+        .err()
+        .expect("inline model with embeddings");
+    assert!(err.contains("--inline-linear-model"), "{err}");
+}
+
+#[test]
+fn request_flow_producers_on_several_threads_share_the_ring_through_the_publish_gate() {
+    common::init_factory_pool();
+
+    const PRODUCERS: usize = 4;
+    const BATCHES_PER_PRODUCER: usize = 50;
+    const REQUESTS_PER_BATCH: usize = 8;
+    const RING_SIZE: usize = 64;
+    let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+    let (mut poller, builder) = builder.event_poller();
+    let producer = builder.build();
+    // Small enough that every producer waits on both the ring and the pool.
+    let pool = BufferPool::leak_new(RING_SIZE / 2 * FEATURE_DIM);
+    let publish_gate = Arc::new(Mutex::new(()));
+
+    let handles: Vec<_> = (0..PRODUCERS)
+        .map(|id| {
+            let mut producer = producer.clone();
+            let mut allocator = pool.allocator();
+            let publish_gate = Arc::clone(&publish_gate);
+            thread::spawn(move || {
+                let conn = ConnectionRef::new(0, id as u16, 1);
+                let buf = common::one_request_bytes(1, &[id as f32; FEATURE_DIM])
+                    .repeat(REQUESTS_PER_BATCH);
+                let mut request_seq = 0u64;
+                for _ in 0..BATCHES_PER_PRODUCER {
+                    let _publish = publish_gate.lock().unwrap();
+                    let outcome = request_flow::process_requests_from_buffer(
+                        &buf,
+                        &mut producer,
+                        &mut allocator,
+                        conn,
+                        &mut request_seq,
+                    )
+                    .expect("well-formed requests");
+                    assert_eq!(outcome.num_published, REQUESTS_PER_BATCH);
+                }
+            })
+        })
+        .collect();
+    drop(producer);
+
+    // Each connection's requests arrive in order, carrying its own features, and releasing
+    // every slice in ring order hands the whole pool back.
+    let mut next_seq = [0u64; PRODUCERS];
+    let total = PRODUCERS * BATCHES_PER_PRODUCER * REQUESTS_PER_BATCH;
+    let mut seen = 0;
+    while seen < total {
+        if let Ok(mut guard) = poller.poll() {
+            for event in &mut guard {
+                let id = event.conn_id() as usize;
+                assert_eq!(
+                    event.request_seq, next_seq[id],
+                    "connection {id} out of order"
+                );
+                next_seq[id] += 1;
+                assert_eq!(event.vector(0), &[id as f32; FEATURE_DIM]);
+                event.features.release();
+                seen += 1;
+            }
+        }
+    }
+    for handle in handles {
+        handle.join().expect("producer thread panicked");
+    }
+    assert_eq!(
+        next_seq,
+        [(BATCHES_PER_PRODUCER * REQUESTS_PER_BATCH) as u64; PRODUCERS]
+    );
+    assert_eq!(pool.utilization().0, 0, "every slice returned to the pool");
 }
@@ -0,0 +1,220 @@
+//! The inference thread's request timeout: requests still waiting for a batch once it runs out
+//! are answered with timeouts instead of being run, in order, and their ring slots and pool
+//! space are released for the requests after them. With a circuit breaker, enough of them open
+//! the circuit and new requests are rejected before they reach the ring.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, MultiProducer, SingleConsumerBarrier, build_multi_producer};
+
+use disrust::buffer_pool::{BufferPool, PoolAllocator};
+use disrust::config::SLAB_CAPACITY;
+use disrust::connection_id::ConnectionRef;
+use disrust::constants::FEATURE_DIM;
+use disrust::pipeline::circuit_breaker::{CircuitBreaker, CircuitState};
+use disrust::pipeline::connection_registry::ConnectionRegistry;
+use disrust::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use disrust::pipeline::inference::InferenceConsumer;
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseReady, ResponseRouter};
+use disrust::protocol::{ConnectionDecoder, OverloadReason, RequestFraming};
+use disrust::request_flow::{self, MalformedPolicy, NonFinitePolicy, RingFullPolicy};
+use disrust::ring_types::InferenceEvent;
+
+const RING_SIZE: usize = 64;
+const WAIT: Duration = Duration::from_secs(5);
+
+const MODEL: &str = "\
+booster[0]:
+0:[f0<0.5] yes=1,no=2,missing=1
+\t1:leaf=0.25
+\t2:leaf=0.75
+";
+
+type Producer = MultiProducer<InferenceEvent, SingleConsumerBarrier>;
+
+/// An inference thread answering one IO thread's response queue, with a pool that holds only
+/// a ring's worth of single-vector requests.
+struct Pipeline {
+    producer: Producer,
+    allocator: PoolAllocator,
+    queue: Arc<ResponseQueue>,
+    conn: ConnectionRef,
+    request_seq: u64,
+    stop: Arc<AtomicBool>,
+    inference: JoinHandle<()>,
+}
+
+impl Pipeline {
+    fn start(request_timeout: Duration, circuit: Option<Arc<CircuitBreaker>>) -> Self {
+        common::init_factory_pool();
+        let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+        let (submission_poller, builder) = builder.event_poller();
+        let (completion_poller, builder) = builder.and_then().event_poller();
+        let producer = builder.build();
+
+        let queue = Arc::new(ResponseQueue::new(RING_SIZE));
+        let registry = Arc::new(ConnectionRegistry::new(1, SLAB_CAPACITY));
+        let conn = registry.open(0, 0, -1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let model = GbdtModel::parse(MODEL, FEATURE_DIM).expect("valid model");
+        let consumer = InferenceConsumer::new(
+            submission_poller,
+            completion_poller,
+            GbdtBackend::new(model),
+            Arc::new(ResponseRouter::from(vec![Arc::clone(&queue)])),
+            registry,
+            RING_SIZE,
+            Duration::from_micros(50),
+        )
+        .with_request_timeout(request_timeout);
+        let consumer = match circuit {
+            Some(circuit) => consumer.with_circuit_breaker(circuit),
+            None => consumer,
+        };
+        let inference = thread::Builder::new()
+            .name("test-inference".into())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || consumer.run_until(stop)
+            })
+            .expect("failed to spawn inference thread");
+
+        Self {
+            producer,
+            allocator: BufferPool::leak_new(RING_SIZE * FEATURE_DIM).allocator(),
+            queue,
+            conn,
+            request_seq: 0,
+            stop,
+            inference,
+        }
+    }
+
+    /// Publish `count` single-vector requests scoring 0.75, then wait for their responses.
+    fn round_trip(&mut self, count: usize) -> Vec<ResponseReady> {
+        let buf = common::one_request_bytes(1, &[1.0; FEATURE_DIM]).repeat(count);
+        let outcome = request_flow::process_requests_from_buffer(
+            &buf,
+            &mut self.producer,
+            &mut self.allocator,
+            self.conn,
+            &mut self.request_seq,
+        )
+        .expect("well-formed requests");
+        assert_eq!(
+            outcome.num_published, count,
+            "ring or pool space was not released"
+        );
+
+        let deadline = Instant::now() + WAIT;
+        let mut responses = Vec::new();
+        while responses.len() < count {
+            match self.queue.pop() {
+                Some(response) => responses.push(response),
+                None if Instant::now() < deadline => thread::yield_now(),
+                None => panic!("{} of {count} responses", responses.len()),
+            }
+        }
+        responses
+    }
+
+    /// Offer `count` requests through `circuit`, returning those it rejected; none may be
+    /// published.
+    fn offer_through(
+        &mut self,
+        circuit: &CircuitBreaker,
+        count: usize,
+    ) -> Vec<(u64, OverloadReason)> {
+        let buf = common::one_request_bytes(1, &[1.0; FEATURE_DIM]).repeat(count);
+        let mut rejected = Vec::new();
+        let outcome = request_flow::process_requests_with_inline(
+            &buf,
+            &mut self.producer,
+            &mut self.allocator,
+            self.conn,
+            &mut self.request_seq,
+            0,
+            RingFullPolicy::Wait,
+            NonFinitePolicy::PassThrough,
+            MalformedPolicy::Close,
+            RequestFraming::PLAIN,
+            &mut ConnectionDecoder::native(),
+            None,
+            None,
+            None,
+            Some(circuit),
+            None,
+            |request_seq, reason| rejected.push((request_seq, reason)),
+            |_, _| panic!("nothing is scored inline"),
+            |_, _| panic!("every feature is finite"),
+        )
+        .expect("well-formed requests");
+        assert_eq!(outcome.consumed, buf.len());
+        assert_eq!(outcome.num_published, 0);
+        rejected
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.inference.join().expect("inference thread panicked");
+    }
+}
+
+#[test]
+fn requests_that_wait_out_the_timeout_are_answered_unrun_and_release_their_slots() {
+    // Every request has waited longer than this by the time a batch could take it.
+    let mut pipeline = Pipeline::start(Duration::from_nanos(1), None);
+
+    // Three ring's worth in all, so the ring and pool only keep taking requests if timed out
+    // ones give their space back.
+    let mut request_seq = 0u64;
+    for _ in 0..6 {
+        for response in pipeline.round_trip(RING_SIZE / 2) {
+            assert_eq!(response.conn, pipeline.conn);
+            assert_eq!(response.request_seq, request_seq, "answered in order");
+            assert!(response.timed_out);
+            assert!(response.results().is_empty());
+            request_seq += 1;
+        }
+    }
+    pipeline.stop();
+}
+
+#[test]
+fn requests_batched_within_the_timeout_are_scored() {
+    let mut pipeline = Pipeline::start(WAIT, None);
+    for (request_seq, response) in pipeline.round_trip(RING_SIZE / 2).iter().enumerate() {
+        assert_eq!(response.request_seq, request_seq as u64);
+        assert!(!response.timed_out);
+        assert_eq!(response.results(), &[0.75]);
+    }
+    pipeline.stop();
+}
+
+#[test]
+fn timeouts_that_spend_the_error_budget_open_the_circuit_to_new_requests() {
+    let window = RING_SIZE as u64 / 2;
+    let circuit = Arc::new(CircuitBreaker::new(100, window, Duration::from_secs(60)));
+    let mut pipeline = Pipeline::start(Duration::from_nanos(1), Some(Arc::clone(&circuit)));
+    assert!(
+        pipeline
+            .round_trip(window as usize)
+            .iter()
+            .all(|response| response.timed_out)
+    );
+    assert_eq!(circuit.state(), CircuitState::Open);
+
+    // Rejected in order without a ring slot, even though a full ring would hold them.
+    let rejected = pipeline.offer_through(&circuit, 4);
+    let expected: Vec<_> = (window..window + 4)
+        .map(|request_seq| (request_seq, OverloadReason::CircuitOpen))
+        .collect();
+    assert_eq!(rejected, expected);
+    assert!(circuit.retry_after_ms() > 0);
+    pipeline.stop();
+}
@@ -0,0 +1,222 @@
+//! The inference thread's response fan-out across IO threads: each response lands on its
+//! connection's shard queue, only queues that received responses wake their consumer, and a
+//! response for a shard with no queue closes its connection instead of reaching another thread.
+
+mod common;
+
+use std::io::Read;
+use std::os::fd::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use disruptor::{BusySpin, MultiProducer, SingleConsumerBarrier, build_multi_producer};
+
+use disrust::buffer_pool::{BufferPool, PoolAllocator};
+use disrust::config::SLAB_CAPACITY;
+use disrust::connection_id::ConnectionRef;
+use disrust::constants::FEATURE_DIM;
+use disrust::notify::CondvarNotifier;
+use disrust::pipeline::connection_registry::ConnectionRegistry;
+use disrust::pipeline::gbdt::{GbdtBackend, GbdtModel};
+use disrust::pipeline::inference::InferenceConsumer;
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseReady, ResponseRouter};
+use disrust::request_flow;
+use disrust::ring_types::InferenceEvent;
+
+const RING_SIZE: usize = 64;
+const IO_THREADS: usize = 3;
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Scores 0.25 below `f0 = 0.5` and 0.75 above it.
+const MODEL: &str = "\
+booster[0]:
+0:[f0<0.5] yes=1,no=2,missing=1
+\t1:leaf=0.25
+\t2:leaf=0.75
+";
+
+type Producer = MultiProducer<InferenceEvent, SingleConsumerBarrier>;
+
+/// An inference thread answering `IO_THREADS` response queues, each with its own notifier.
+struct FanOut {
+    producer: Producer,
+    allocator: PoolAllocator,
+    queues: Vec<Arc<ResponseQueue>>,
+    notifiers: Vec<Arc<CondvarNotifier>>,
+    registry: Arc<ConnectionRegistry>,
+    stop: Arc<AtomicBool>,
+    inference: JoinHandle<()>,
+}
+
+impl FanOut {
+    /// The registry covers `registry_shards` IO threads, which may be more than have queues.
+    fn start(registry_shards: usize) -> Self {
+        common::init_factory_pool();
+        let builder = build_multi_producer(RING_SIZE, InferenceEvent::factory, BusySpin);
+        let (submission_poller, builder) = builder.event_poller();
+        let (completion_poller, builder) = builder.and_then().event_poller();
+        let producer = builder.build();
+
+        let notifiers: Vec<_> = (0..IO_THREADS)
+            .map(|_| Arc::new(CondvarNotifier::new()))
+            .collect();
+        let queues: Vec<_> = notifiers
+            .iter()
+            .map(|notifier| Arc::new(ResponseQueue::with_notifier(RING_SIZE, notifier.clone())))
+            .collect();
+        let registry = Arc::new(ConnectionRegistry::new(registry_shards, SLAB_CAPACITY));
+        let stop = Arc::new(AtomicBool::new(false));
+        let model = GbdtModel::parse(MODEL, FEATURE_DIM).expect("valid model");
+        let consumer = InferenceConsumer::new(
+            submission_poller,
+            completion_poller,
+            GbdtBackend::new(model),
+            Arc::new(ResponseRouter::from(queues.clone())),
+            Arc::clone(&registry),
+            RING_SIZE,
+            Duration::from_micros(50),
+        );
+        let inference = thread::Builder::new()
+            .name("test-inference".into())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || consumer.run_until(stop)
+            })
+            .expect("failed to spawn inference thread");
+
+        Self {
+            producer,
+            allocator: BufferPool::leak_new(RING_SIZE * FEATURE_DIM).allocator(),
+            queues,
+            notifiers,
+            registry,
+            stop,
+            inference,
+        }
+    }
+
+    /// Publish `count` single-vector requests for `conn`, every feature `fill`.
+    fn publish(&mut self, conn: ConnectionRef, request_seq: &mut u64, count: usize, fill: f32) {
+        let buf = common::one_request_bytes(1, &[fill; FEATURE_DIM]).repeat(count);
+        let outcome = request_flow::process_requests_from_buffer(
+            &buf,
+            &mut self.producer,
+            &mut self.allocator,
+            conn,
+            request_seq,
+        )
+        .expect("well-formed requests");
+        assert_eq!(outcome.num_published, count);
+    }
+
+    /// Pop `count` responses from `shard`'s queue, waiting up to `WAIT` for them.
+    fn take(&self, shard: usize, count: usize) -> Vec<ResponseReady> {
+        let deadline = Instant::now() + WAIT;
+        let mut responses = Vec::new();
+        while responses.len() < count {
+            match self.queues[shard].pop() {
+                Some(response) => responses.push(response),
+                None if Instant::now() < deadline => thread::yield_now(),
+                None => panic!("shard {shard}: {} of {count} responses", responses.len()),
+            }
+        }
+        responses
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.inference.join().expect("inference thread panicked");
+    }
+}
+
+#[test]
+fn responses_reach_only_their_own_io_threads_queue() {
+    let mut fan_out = FanOut::start(IO_THREADS);
+    let conns: Vec<_> = (0..IO_THREADS as u8)
+        .map(|shard| fan_out.registry.open(shard, 7, -1))
+        .collect();
+    let mut seqs = [0u64; IO_THREADS];
+
+    // Interleave shards 0 and 2, leaving shard 1 idle.
+    for _ in 0..4 {
+        fan_out.publish(conns[0], &mut seqs[0], 3, 0.0);
+        fan_out.publish(conns[2], &mut seqs[2], 2, 1.0);
+    }
+    for shard in [0, 2] {
+        assert!(
+            fan_out.notifiers[shard].wait_timeout(WAIT),
+            "shard {shard} was never woken"
+        );
+    }
+    let shard0 = fan_out.take(0, 12);
+    let shard2 = fan_out.take(2, 8);
+    for (responses, conn, score) in [(&shard0, conns[0], 0.25), (&shard2, conns[2], 0.75)] {
+        for (request_seq, response) in responses.iter().enumerate() {
+            assert_eq!(response.conn, conn);
+            assert_eq!(response.request_seq, request_seq as u64);
+            assert_eq!(response.results(), &[score]);
+        }
+    }
+    assert!(
+        !fan_out.notifiers[1].wait_timeout(Duration::from_millis(50)),
+        "shard 1 was woken with nothing to answer"
+    );
+    assert!(fan_out.queues[1].pop().is_none());
+    for shard in [0, 2] {
+        assert!(
+            fan_out.queues[shard].pop().is_none(),
+            "shard {shard} overfilled"
+        );
+    }
+
+    // Shard 1's first response wakes it, and only it. Batches that found shards 0 and 2 drained
+    // may have left them a wakeup, so consume those first.
+    for shard in [0, 2] {
+        fan_out.notifiers[shard].wait_timeout(Duration::ZERO);
+    }
+    fan_out.publish(conns[1], &mut seqs[1], 1, 0.0);
+    assert!(fan_out.notifiers[1].wait_timeout(WAIT));
+    let shard1 = fan_out.take(1, 1);
+    assert_eq!(shard1[0].conn, conns[1]);
+    for shard in [0, 2] {
+        assert!(!fan_out.notifiers[shard].wait_timeout(Duration::from_millis(10)));
+    }
+
+    fan_out.stop();
+}
+
+#[test]
+fn a_response_for_a_shard_without_a_queue_closes_its_connection() {
+    // A fourth IO thread's connection that never registered a queue, and a reference from
+    // past every thread, which no registry slot covers.
+    let mut fan_out = FanOut::start(IO_THREADS + 1);
+    let (sock, mut peer) = UnixStream::pair().expect("unix pair");
+    let unrouted = fan_out
+        .registry
+        .open(IO_THREADS as u8, 0, sock.into_raw_fd());
+    let unregistered = ConnectionRef::new(9, 0, 1);
+    let answered = fan_out.registry.open(0, 0, -1);
+
+    fan_out.publish(unregistered, &mut 0, 2, 0.0);
+    fan_out.publish(unrouted, &mut 0, 2, 0.0);
+    fan_out.publish(answered, &mut 0, 2, 1.0);
+
+    // The connection is shut down, so its client sees the stream end rather than hang.
+    peer.set_read_timeout(Some(WAIT)).unwrap();
+    let mut byte = [0u8; 1];
+    assert_eq!(peer.read(&mut byte).expect("shutdown, not a timeout"), 0);
+
+    // The inference thread carries on for connections it can answer.
+    let responses = fan_out.take(0, 2);
+    assert!(responses.iter().all(|response| response.conn == answered));
+    for shard in 0..IO_THREADS {
+        assert!(
+            fan_out.queues[shard].pop().is_none(),
+            "shard {shard} got a stray response"
+        );
+    }
+    fan_out.stop();
+}
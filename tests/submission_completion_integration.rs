@@ -16,8 +16,9 @@ use disrust::constants::FEATURE_DIM;
 use disrust::metrics;
 use disrust::pipeline::connection_registry::ConnectionRegistry;
 use disrust::pipeline::inference::InferenceConsumer;
-use disrust::pipeline::response_queue::ResponseQueue;
+use disrust::pipeline::response_queue::{ResponseQueue, ResponseRouter};
 use disrust::pipeline::{InferenceBackend, OrtBackend};
+use disrust::protocol;
 use disrust::ring_types::InferenceEvent;
 
 #[cfg(feature = "cuda")]
@@ -82,7 +83,7 @@ fn run_pipeline_order_test() {
         submission_poller,
         completion_poller,
         backend,
-        vec![Arc::clone(&response_queue)],
+        Arc::new(ResponseRouter::from(vec![Arc::clone(&response_queue)])),
         Arc::clone(&registry),
         256,
         Duration::from_micros(500),
@@ -160,7 +161,7 @@ fn run_pipeline_order_test() {
                 .get_mut(&response.conn)
                 .expect("unexpected connection in observed map");
             let mut frame = [0u8; 5];
-            frame.copy_from_slice(&response.data[..response.len]);
+            protocol::encode_response(response.results(), &mut frame);
             entry.push(frame);
         } else {
             assert!(
@@ -201,7 +202,7 @@ fn run_sustained_response_queue_test() {
         submission_poller,
         completion_poller,
         backend,
-        vec![Arc::clone(&response_queue)],
+        Arc::new(ResponseRouter::from(vec![Arc::clone(&response_queue)])),
         Arc::clone(&registry),
         256,
         Duration::from_micros(500),
@@ -271,7 +272,7 @@ fn run_sustained_response_queue_test() {
                 .get_mut(&response.conn)
                 .expect("unexpected connection in observed map");
             let mut frame = [0u8; 5];
-            frame.copy_from_slice(&response.data[..response.len]);
+            protocol::encode_response(response.results(), &mut frame);
             frames.push(frame);
         } else {
             assert!(
@@ -0,0 +1,202 @@
+//! Golden test: the wire spec rendered from `wire_layout` must match the checked-in copy, and the
+//! protocol encoders must put bytes where the layouts say they go.
+//!
+//! After an intentional wire change, regenerate with
+//! `UPDATE_GOLDEN=1 cargo test --test wire_layout_golden`.
+
+use disrust::constants::{FEATURE_DIM, MAX_VECTORS_PER_REQUEST};
+use disrust::protocol::{
+    self, FixedPointResponse, OverloadReason, ParseError, RequestField, RequestFraming,
+    ResponseSerializer, SequenceCheck, SequenceError,
+};
+use disrust::wire_layout;
+
+const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/wire_layout.md");
+
+#[test]
+fn rendered_wire_spec_matches_golden() {
+    let rendered = wire_layout::render_spec();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(GOLDEN_PATH, &rendered).unwrap();
+        return;
+    }
+    let golden = std::fs::read_to_string(GOLDEN_PATH).unwrap();
+    assert_eq!(
+        rendered, golden,
+        "wire layout changed; rerun with UPDATE_GOLDEN=1 if intentional"
+    );
+}
+
+#[test]
+fn protocol_encoders_follow_layouts() {
+    let mut request = vec![0u8; protocol::request_size(2)];
+    request[..4].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(
+        wire_layout::REQUEST_FEATURES.offset + wire_layout::REQUEST_FEATURES.len(2),
+        request.len()
+    );
+    assert_eq!(
+        wire_layout::REQUEST_FEATURES.len(2),
+        2 * FEATURE_DIM * protocol::BYTES_PER_F32
+    );
+    match protocol::try_parse_request(&request, RequestFraming::PLAIN) {
+        protocol::ParseResult::Complete {
+            num_vectors,
+            bytes_consumed,
+            request_id,
+        } => {
+            assert_eq!(num_vectors, 2);
+            assert_eq!(bytes_consumed, request.len());
+            assert_eq!(request_id, None);
+        }
+        _ => panic!("expected a complete request"),
+    }
+
+    let mut framed = vec![0u8; protocol::REQUEST_ID_BYTES];
+    protocol::encode_request_id(0xfeed_0000_0000_0001, &mut framed);
+    assert_eq!(framed, [1, 0, 0, 0, 0, 0, 0xed, 0xfe]);
+    framed.extend_from_slice(&request);
+    assert_eq!(
+        RequestFraming::REQUEST_ID.header_bytes(),
+        protocol::REQUEST_ID_BYTES + protocol::REQUEST_HEADER_BYTES
+    );
+    match protocol::try_parse_request(&framed, RequestFraming::REQUEST_ID) {
+        protocol::ParseResult::Complete {
+            num_vectors,
+            bytes_consumed,
+            request_id,
+        } => {
+            assert_eq!(num_vectors, 2);
+            assert_eq!(bytes_consumed, framed.len());
+            assert_eq!(request_id, Some(0xfeed_0000_0000_0001));
+        }
+        _ => panic!("expected a complete request"),
+    }
+    let protocol::ParseResult::Error(error) =
+        protocol::try_parse_request(&[0u8; 12], RequestFraming::REQUEST_ID)
+    else {
+        panic!("expected a parse error");
+    };
+    assert_eq!(error.offset, protocol::REQUEST_ID_BYTES as u64);
+
+    let results = [1.5f32, -2.0];
+    let mut response = vec![0u8; protocol::response_size(results.len())];
+    protocol::encode_response(&results, &mut response);
+    assert_eq!(wire_layout::RESPONSE_NUM_VECTORS.read_u8(&response), 2);
+    let offset = wire_layout::RESPONSE_RESULTS.offset;
+    assert_eq!(&response[offset..offset + 4], &1.5f32.to_le_bytes());
+    assert_eq!(&response[offset + 4..offset + 8], &(-2.0f32).to_le_bytes());
+
+    // Bit 30 asks for a fixed-point response, where the framing allows it.
+    let mut fixed_point_request = request.clone();
+    fixed_point_request[..4]
+        .copy_from_slice(&(2 | protocol::FIXED_POINT_REQUEST_FLAG).to_le_bytes());
+    assert!(matches!(
+        protocol::try_parse_request(&fixed_point_request, RequestFraming::PLAIN),
+        protocol::ParseResult::Error(ParseError {
+            field: RequestField::NumVectors,
+            ..
+        })
+    ));
+    assert!(matches!(
+        protocol::try_parse_request(
+            &fixed_point_request,
+            RequestFraming::PLAIN.with_fixed_point_scores()
+        ),
+        protocol::ParseResult::Complete { num_vectors: 2, bytes_consumed, .. }
+            if bytes_consumed == request.len()
+    ));
+    let fixed_point = FixedPointResponse::default();
+    let results = [0.25f32, 1.5, 0.000_06];
+    let mut response = vec![0u8; fixed_point.response_size(results.len())];
+    assert_eq!(response.len(), protocol::fixed_point_response_size(3));
+    fixed_point.encode(&results, &mut response);
+    assert_eq!(
+        wire_layout::FIXED_POINT_RESPONSE_NUM_VECTORS.read_u8(&response),
+        3
+    );
+    let offset = wire_layout::FIXED_POINT_RESPONSE_RESULTS.offset;
+    assert_eq!(&response[offset..], &[0xc4, 0x09, 0x98, 0x3a, 0x01, 0x00]);
+    assert_eq!(fixed_point.decode(&response), [0.25, 1.5, 0.0001]);
+
+    let mut overload = [0xffu8; protocol::OVERLOAD_FRAME_BYTES];
+    protocol::encode_overload(OverloadReason::RingFull, 300, &mut overload);
+    assert_eq!(overload, [0, 1, 0x2c, 0x01]);
+    assert_eq!(
+        protocol::decode_overload(&overload),
+        Some((Some(OverloadReason::RingFull), 300))
+    );
+
+    let bad_header = 300u32.to_le_bytes();
+    let protocol::ParseResult::Error(error) =
+        protocol::try_parse_request(&bad_header, RequestFraming::PLAIN)
+    else {
+        panic!("expected a parse error");
+    };
+    let error = error.at(0x1_0000_0010);
+    assert_eq!(
+        error,
+        ParseError {
+            field: RequestField::NumVectors,
+            value: 300,
+            offset: 0x1_0000_0010,
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "num_vectors = 300 at byte 4294967312: expected 1..={MAX_VECTORS_PER_REQUEST} or the port's --max-vectors"
+        )
+    );
+    let mut frame = [0u8; protocol::PARSE_ERROR_FRAME_BYTES];
+    protocol::encode_parse_error(&error, &mut frame);
+    assert_eq!(frame, [0, 0xff, 1, 0, 0x2c, 0x01, 0, 0, 0x10, 0, 0, 0]);
+    assert!(protocol::is_parse_error(&frame[..2]));
+    assert_eq!(protocol::decode_overload(&frame), None);
+    assert_eq!(
+        protocol::decode_parse_error(&frame),
+        Some(ParseError {
+            offset: 0x10,
+            ..error
+        })
+    );
+    assert_eq!(protocol::decode_parse_error(&overload), None);
+}
+
+#[test]
+fn length_prefix_round_trips() {
+    let mut prefix = [0u8; protocol::LENGTH_PREFIX_BYTES];
+    protocol::encode_length_prefix(
+        protocol::SEQ_PREFIX_BYTES + protocol::response_size(3),
+        &mut prefix,
+    );
+    assert_eq!(prefix, [17, 0, 0, 0]);
+    assert_eq!(protocol::decode_length_prefix(&prefix), 17);
+}
+
+#[test]
+fn seq_prefix_round_trips_and_check_flags_gaps_and_repeats() {
+    let mut prefix = [0u8; protocol::SEQ_PREFIX_BYTES];
+    protocol::encode_seq_prefix((1u64 << 32) + 7, &mut prefix);
+    assert_eq!(prefix, [7, 0, 0, 0]);
+    assert_eq!(protocol::decode_seq_prefix(&prefix), 7);
+
+    let mut check = SequenceCheck::default();
+    assert_eq!(check.check(0), Ok(()));
+    assert_eq!(check.check(1), Ok(()));
+    assert_eq!(
+        check.check(1),
+        Err(SequenceError::Repeated {
+            expected: 2,
+            got: 1
+        })
+    );
+    assert_eq!(
+        check.check(5),
+        Err(SequenceError::Skipped {
+            expected: 2,
+            got: 5
+        })
+    );
+    assert_eq!(check.check(2), Ok(()));
+}